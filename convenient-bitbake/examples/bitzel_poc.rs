@@ -176,6 +176,7 @@ echo "Stamp file created successfully"
         timeout: Some(Duration::from_secs(30)),
         network_policy: NetworkPolicy::LoopbackOnly,
             resource_limits: ResourceLimits::default(),
+            flags: Default::default(),
     };
 
     executor.execute_task(spec)
@@ -197,6 +198,7 @@ echo "Sources unpacked" > "$D/unpack.stamp"
         timeout: Some(Duration::from_secs(30)),
         network_policy: NetworkPolicy::Isolated,
             resource_limits: ResourceLimits::default(),
+            flags: Default::default(),
     };
 
     executor.execute_task(spec)
@@ -225,6 +227,7 @@ fi
         timeout: Some(Duration::from_secs(60)),
         network_policy: NetworkPolicy::Isolated,
             resource_limits: ResourceLimits::default(),
+            flags: Default::default(),
     };
 
     executor.execute_task(spec)
@@ -249,6 +252,7 @@ ls -R "$D"
         timeout: Some(Duration::from_secs(30)),
         network_policy: NetworkPolicy::Isolated,
             resource_limits: ResourceLimits::default(),
+            flags: Default::default(),
     };
 
     executor.execute_task(spec)
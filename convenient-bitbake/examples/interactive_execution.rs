@@ -388,6 +388,7 @@ echo "Done" > "$D/{}.stamp"
             timeout: Some(Duration::from_secs(30)),
             network_policy,
             resource_limits: ResourceLimits::default(),
+            flags: Default::default(),
         };
 
         specs.insert(task_key, spec);
@@ -364,6 +364,7 @@ echo "Task completed" > "$D/{}.stamp"
             timeout: Some(Duration::from_secs(30)),
             network_policy,
             resource_limits: ResourceLimits::default(),
+            flags: Default::default(),
         };
 
         specs.insert(task_key, spec);
@@ -1,6 +1,7 @@
 // Test garbage collection: mark-and-sweep + LRU eviction
 use convenient_bitbake::executor::cache::{ContentAddressableStore, ActionCache, CacheConfig};
 use convenient_bitbake::executor::types::{ContentHash, TaskOutput};
+use convenient_bitbake::compression::CompressionAlgorithm;
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::thread;
@@ -19,6 +20,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         max_size_bytes: Some(10 * 1024 * 1024), // 10 MB max
         gc_threshold_bytes: 1 * 1024 * 1024,    // Trigger at 1 MB
         gc_target_bytes: 512 * 1024,            // Clean down to 512 KB
+        pin_last_builds: 0,
+        compression: CompressionAlgorithm::None,
+        chunking_threshold_bytes: None,
     };
 
     let mut cas = ContentAddressableStore::with_config(tmp1.path().join("cas"), config)?;
@@ -78,6 +82,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         max_size_bytes: Some(1 * 1024 * 1024),  // 1 MB max
         gc_threshold_bytes: 800 * 1024,         // Trigger at 800 KB
         gc_target_bytes: 400 * 1024,            // Clean down to 400 KB
+        pin_last_builds: 0,
+        compression: CompressionAlgorithm::None,
+        chunking_threshold_bytes: None,
     };
 
     let mut cas2 = ContentAddressableStore::with_config(tmp2.path().join("cas"), config2.clone())?;
@@ -133,6 +140,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         max_size_bytes: Some(5 * 1024 * 1024),  // 5 MB max
         gc_threshold_bytes: 2 * 1024 * 1024,    // Trigger at 2 MB
         gc_target_bytes: 1 * 1024 * 1024,       // Clean down to 1 MB
+        pin_last_builds: 0,
+        compression: CompressionAlgorithm::None,
+        chunking_threshold_bytes: None,
     };
 
     let mut cas3 = ContentAddressableStore::with_config(tmp3.path(), config3)?;
@@ -168,6 +178,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         max_size_bytes: Some(10 * 1024 * 1024), // 10 MB
         gc_threshold_bytes: 3 * 1024 * 1024,    // Trigger at 3 MB
         gc_target_bytes: 2 * 1024 * 1024,       // Clean to 2 MB
+        pin_last_builds: 0,
+        compression: CompressionAlgorithm::None,
+        chunking_threshold_bytes: None,
     };
 
     let mut cas4 = ContentAddressableStore::with_config(tmp4.path().join("cas"), config4)?;
@@ -0,0 +1,24 @@
+//! BitBake language server
+//!
+//! Speaks LSP over stdin/stdout; warnings are logged to stderr.
+//!
+//! Usage:
+//!   bitbake-lsp
+//!
+//! Editors pass the build directory as `initializationOptions.buildDir`;
+//! otherwise `<workspace>/build` is used when it has conf/bblayers.conf.
+
+use lsp_server::Connection;
+
+fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_max_level(tracing::Level::WARN)
+        .init();
+
+    let (connection, io_threads) = Connection::stdio();
+    convenient_bitbake::lsp::run(&connection)?;
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
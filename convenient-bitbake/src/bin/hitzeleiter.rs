@@ -316,7 +316,7 @@ fn discover_layers(
         let mut layers = Vec::new();
 
         // Add explicitly configured layers
-        for (layer_name, _layer_config) in &config.layers {
+        for (layer_name, _layer_config) in config.enabled_layers() {
             let layer_path = if layer_name.is_empty() || layer_name == "." {
                 repo_path.clone()
             } else {
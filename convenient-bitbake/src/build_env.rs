@@ -3,7 +3,7 @@
 //! Represents a complete BitBake build environment loaded from a build directory
 //! containing conf/bblayers.conf and conf/local.conf.
 
-use crate::{BbLayersConfig, BuildContext, LayerConfig, LocalConfig, VariableExpander};
+use crate::{BbLayersConfig, BuildContext, DataStore, LayerConfig, LocalConfig, VariableExpander};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use tracing::{debug, info};
@@ -255,6 +255,67 @@ impl BuildEnvironment {
         Ok(build_context)
     }
 
+    /// Parse the configuration into a datastore: bblayers.conf, every layer.conf,
+    /// bitbake.conf (local.conf when no layer has one) and the INHERIT classes
+    pub fn parse_configuration(&self, context: &BuildContext) -> Result<DataStore, String> {
+        let mut d = DataStore::new();
+        d.set_var("TOPDIR", self.topdir.to_string_lossy());
+        d.add_search_path(&self.topdir);
+
+        let bblayers = self.confdir.join("bblayers.conf");
+        if bblayers.exists() {
+            d.parse_file(&bblayers)?;
+        }
+
+        // ${LAYERDIR} is bound while each layer.conf is parsed, as BitBake does
+        for layer in &context.layers {
+            let layer_conf = layer.layer_dir.join("conf/layer.conf");
+            let content = std::fs::read_to_string(&layer_conf)
+                .map_err(|e| format!("Failed to read {}: {}", layer_conf.display(), e))?;
+            let content = content.replace("${LAYERDIR}", &layer.layer_dir.to_string_lossy());
+            d.parse_content(&content, &layer_conf)?;
+            d.add_search_path(&layer.layer_dir);
+        }
+
+        match d
+            .bbpath()
+            .into_iter()
+            .map(|dir| dir.join("conf/bitbake.conf"))
+            .find(|p| p.exists())
+        {
+            Some(bitbake_conf) => d.parse_file(&bitbake_conf)?,
+            None => d.parse_file(&self.confdir.join("local.conf"))?,
+        }
+
+        d.inherit_global_classes()?;
+        Ok(d)
+    }
+
+    /// Multiconfig names from BBMULTICONFIG in local.conf
+    pub fn multiconfigs(&self) -> Vec<String> {
+        self.get_var("BBMULTICONFIG")
+            .map(|value| crate::multiconfig::parse_bbmulticonfig(&value))
+            .unwrap_or_default()
+    }
+
+    /// Create the BuildContext of a multiconfig ("" is the default configuration)
+    pub fn create_multiconfig_context(&self, name: &str) -> Result<BuildContext, String> {
+        let build_context = self.create_build_context()?;
+        if name.is_empty() {
+            return Ok(build_context);
+        }
+
+        if !self.multiconfigs().iter().any(|mc| mc == name) {
+            return Err(format!(
+                "Multiconfig '{}' is not listed in BBMULTICONFIG ({})",
+                name,
+                self.multiconfigs().join(" ")
+            ));
+        }
+
+        build_context.for_multiconfig(name, &self.confdir)
+    }
+
     /// Get MACHINE setting
     pub fn get_machine(&self) -> Option<&str> {
         self.local_config.machine.as_deref()
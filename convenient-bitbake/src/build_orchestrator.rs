@@ -4,11 +4,14 @@
 //! layer discovery through task graph generation.
 
 use crate::{
-    BuildContext, ExtractionConfig, LayerConfig, Pipeline, PipelineConfig,
-    RecipeExtractor, RecipeGraph, SignatureCache, TaskExtractor, TaskGraph,
-    TaskGraphBuilder, TaskImplementation, TaskSpec,
+    BuildContext, DataStore, ExecutableTask, ExtractionConfig, LayerConfig, ParsedRecipe, Pipeline,
+    PipelineConfig, RecipeExtractor, RecipeFile, RecipeGraph, SignatureCache, SignatureGenerator,
+    TaskExtractor, TaskGraph, TaskGraphBuilder, TaskId, TaskImplementation, TaskSpec,
+    VariableExpander,
 };
-use crate::executor::types::{NetworkPolicy, ResourceLimits};
+use crate::parse_lifecycle;
+use crate::siggen;
+use crate::executor::types::{NetworkPolicy, ResourceLimits, TaskFlags};
 use crate::executor::ScriptPreprocessor;
 use std::collections::HashMap;
 use std::fs;
@@ -109,25 +112,73 @@ impl IncrementalStats {
 /// High-level build orchestrator
 pub struct BuildOrchestrator {
     config: OrchestratorConfig,
+    /// Multiconfig name and its configuration variables (None for the default configuration)
+    multiconfig: Option<(String, HashMap<String, String>)>,
+    /// Parsed configuration the recipes are parsed on top of
+    configuration: Option<DataStore>,
 }
 
 impl BuildOrchestrator {
     /// Create a new build orchestrator
     pub fn new(config: OrchestratorConfig) -> Self {
-        Self { config }
+        Self { config, multiconfig: None, configuration: None }
     }
 
-    /// Build a complete build plan from layer paths
-    pub async fn build_plan(
-        &self,
-        layer_paths: HashMap<String, Vec<PathBuf>>,
-    ) -> Result<BuildPlan, Box<dyn std::error::Error + Send + Sync>> {
+    /// Parse recipes on top of a parsed configuration (see
+    /// `BuildEnvironment::parse_configuration`), so task hashes cover the
+    /// configuration variables the tasks use, as BitBake's do
+    pub fn with_configuration(mut self, configuration: DataStore) -> Self {
+        self.configuration = Some(configuration);
+        self
+    }
 
-        let build_start = Instant::now();
+    /// Plan for a multiconfig instead of the default configuration
+    ///
+    /// `variables` are the multiconfig's configuration variables (see
+    /// `BuildContext::for_multiconfig`); pipeline and signature caches are kept
+    /// per multiconfig so configurations do not invalidate each other.
+    pub fn with_multiconfig(mut self, name: impl Into<String>, variables: HashMap<String, String>) -> Self {
+        let name = name.into();
+        self.multiconfig = if name.is_empty() { None } else { Some((name, variables)) };
+        self
+    }
 
-        // Step 1: Build layer context
-        let stage_start = Instant::now();
-        info!("Building layer context with priorities");
+    /// Cache directory of the planned configuration
+    fn cache_dir(&self) -> PathBuf {
+        let cache_dir = self.config.build_dir.join("hitzeleiter-cache");
+        match &self.multiconfig {
+            Some((name, _)) => cache_dir.join("mc").join(name),
+            None => cache_dir,
+        }
+    }
+
+    /// TMPDIR of the planned configuration
+    ///
+    /// A multiconfig's TMPDIR is taken from its variables, expanded with TOPDIR
+    /// as the build directory; "tmp-<mc>" is only used when it sets none.
+    pub fn tmp_dir(&self) -> PathBuf {
+        let build_dir = &self.config.build_dir;
+        match &self.multiconfig {
+            Some((name, variables)) => match variables.get("TMPDIR") {
+                Some(tmpdir) => {
+                    let mut expander = VariableExpander::new();
+                    expander.set("TOPDIR".to_string(), build_dir.to_string_lossy().to_string());
+                    for (key, value) in variables {
+                        expander.set(key.clone(), value.clone());
+                    }
+                    build_dir.join(expander.expand(tmpdir))
+                }
+                None => build_dir.join(format!("tmp-{name}")),
+            },
+            None => build_dir.join("tmp"),
+        }
+    }
+
+    /// Fresh layer context for the planned configuration
+    fn create_build_context(
+        &self,
+        layer_paths: &HashMap<String, Vec<PathBuf>>,
+    ) -> Result<BuildContext, Box<dyn std::error::Error + Send + Sync>> {
         let mut build_context = BuildContext::new();
 
         if let Some(machine) = &self.config.machine {
@@ -138,10 +189,33 @@ impl BuildOrchestrator {
             build_context.set_distro(distro.clone());
         }
 
+        if let Some((name, variables)) = &self.multiconfig {
+            build_context.global_variables.extend(variables.clone());
+            build_context
+                .global_variables
+                .insert("BB_CURRENT_MC".to_string(), name.clone());
+        }
+
         // Add layers
-        for layer_conf in self.find_layer_confs(&layer_paths)? {
+        for layer_conf in self.find_layer_confs(layer_paths)? {
             build_context.add_layer_from_conf(&layer_conf)?;
         }
+
+        Ok(build_context)
+    }
+
+    /// Build a complete build plan from layer paths
+    pub async fn build_plan(
+        &self,
+        layer_paths: HashMap<String, Vec<PathBuf>>,
+    ) -> Result<BuildPlan, Box<dyn std::error::Error + Send + Sync>> {
+
+        let build_start = Instant::now();
+
+        // Step 1: Build layer context
+        let stage_start = Instant::now();
+        info!("Building layer context with priorities");
+        let build_context = self.create_build_context(&layer_paths)?;
         info!("✓ Step 1 completed in {:?}", stage_start.elapsed());
 
         // Step 2: Parse recipes with parallel pipeline
@@ -151,7 +225,7 @@ impl BuildOrchestrator {
             max_io_parallelism: self.config.max_io_parallelism,
             max_cpu_parallelism: self.config.max_cpu_parallelism,
             enable_cache: true,
-            cache_dir: self.config.build_dir.join("hitzeleiter-cache/pipeline"),
+            cache_dir: self.cache_dir().join("pipeline"),
         };
 
         let pipeline = Pipeline::new(pipeline_config, build_context);
@@ -161,16 +235,7 @@ impl BuildOrchestrator {
         info!("✓ Step 2 completed in {:?} ({} recipes parsed)", stage_start.elapsed(), parsed_recipes.len());
 
         // Rebuild build_context for return value
-        let mut build_context = BuildContext::new();
-        if let Some(machine) = &self.config.machine {
-            build_context.set_machine(machine.clone());
-        }
-        if let Some(distro) = &self.config.distro {
-            build_context.set_distro(distro.clone());
-        }
-        for layer_conf in self.find_layer_confs(&layer_paths)? {
-            build_context.add_layer_from_conf(&layer_conf)?;
-        }
+        let build_context = self.create_build_context(&layer_paths)?;
 
         // Extract task implementations and helper functions
         let mut task_implementations = HashMap::new();
@@ -217,6 +282,8 @@ impl BuildOrchestrator {
             resolve_includes: true,   // Enable .inc file processing for variables (CRITICAL for DEPENDS)
             resolve_inherit: true,    // Enable .bbclass processing for standard task ordering
             class_search_paths,       // Provide paths to find base.bbclass and other classes
+            configuration: self.configuration.clone(),  // INHERIT and DISTRO_FEATURES from the build's configuration
+            run_anonymous_python: true,  // Recipe and class anonymous python: SkipRecipe, setVarFlag, addtask
             ..Default::default()
         });
         let (recipe_graph, _) = pipeline.build_recipe_graph(&parsed_recipes, &extractor)?;
@@ -238,11 +305,11 @@ impl BuildOrchestrator {
         }
 
         let mut sig_cache = SignatureCache::new(
-            self.config.build_dir.join("hitzeleiter-cache/signatures")
+            self.cache_dir().join("signatures")
         );
 
         // Load previous signatures
-        let cache_path = self.config.build_dir.join("hitzeleiter-cache/signatures/signatures.json");
+        let cache_path = self.cache_dir().join("signatures/signatures.json");
         let previous_signatures = if cache_path.exists() {
             let json = tokio::fs::read_to_string(&cache_path).await?;
             serde_json::from_str(&json).unwrap_or_default()
@@ -258,6 +325,9 @@ impl BuildOrchestrator {
             self.config.machine.as_deref(),
             self.config.distro.as_deref(),
         ).await?;
+
+        // BitBake's own hashes name the sstate archives shared with BitBake
+        let task_env = self.bitbake_task_env(&task_graph, &parsed_recipes, &build_context);
         info!("✓ Step 5 completed in {:?}", stage_start.elapsed());
 
         // Step 6: Analyze incremental build requirements
@@ -281,7 +351,7 @@ impl BuildOrchestrator {
             &task_implementations,
             &helper_implementations,
             &recipe_variables,
-            &self.config.build_dir,
+            &task_env,
         )?;
         info!("✓ Step 7 completed in {:?} ({} task specs created)", stage_start.elapsed(), task_specs.len());
 
@@ -318,6 +388,92 @@ impl BuildOrchestrator {
         Ok(confs)
     }
 
+    /// BitBake hashes of every task (BB_BASEHASH, BB_TASKHASH, BB_UNIHASH)
+    /// and the variables naming its sstate archive
+    fn bitbake_task_env(
+        &self,
+        task_graph: &TaskGraph,
+        parsed_recipes: &[ParsedRecipe],
+        context: &BuildContext,
+    ) -> HashMap<TaskId, HashMap<String, String>> {
+        let mut tasks_by_recipe: HashMap<&str, Vec<&ExecutableTask>> = HashMap::new();
+        for task in task_graph.tasks.values() {
+            tasks_by_recipe.entry(task.recipe_name.as_str()).or_default().push(task);
+        }
+
+        let mut basehashes = HashMap::new();
+        let mut name_vars = HashMap::new();
+        for parsed in parsed_recipes {
+            let Some(tasks) = tasks_by_recipe.remove(parsed.file.name.as_str()) else {
+                continue;
+            };
+            let d = self.recipe_datastore(context, &parsed.file);
+            let mut generator = SignatureGenerator::new(&d);
+            for task in tasks {
+                basehashes.insert(task.task_id, generator.basehash(&task.task_name));
+            }
+            name_vars.insert(parsed.file.name.as_str(), siggen::sstate_name_vars(&d));
+        }
+
+        siggen::task_hashes(task_graph, &basehashes)
+            .into_iter()
+            .map(|(task_id, hashes)| {
+                let mut env = task_graph
+                    .tasks
+                    .get(&task_id)
+                    .and_then(|task| name_vars.get(task.recipe_name.as_str()))
+                    .cloned()
+                    .unwrap_or_default();
+                env.extend(hashes.env_vars());
+                (task_id, env)
+            })
+            .collect()
+    }
+
+    /// A recipe parsed on top of the configuration, with its bbappends, and
+    /// finalised through the parse lifecycle (anonymous python, event handlers)
+    ///
+    /// The context's variables (MACHINE, DISTRO, multiconfig) are set on top
+    /// of the configuration; without one, only they and the layers' classes
+    /// are known. Parse errors are logged; whatever was parsed before them is
+    /// kept.
+    fn recipe_datastore(&self, context: &BuildContext, recipe: &RecipeFile) -> DataStore {
+        let mut d = self.configuration.clone().unwrap_or_else(|| {
+            let mut d = DataStore::new();
+            for layer in &context.layers {
+                d.add_search_path(&layer.layer_dir);
+            }
+            d
+        });
+        for (name, value) in &context.global_variables {
+            d.set_var(name, value.as_str());
+        }
+
+        let stem = recipe
+            .path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or(&recipe.name);
+        let (pn, pv) = stem.split_once('_').unwrap_or((stem, "1.0"));
+        d.set_var("FILE", recipe.path.to_string_lossy());
+        d.set_var("PN", pn);
+        d.set_var("PV", pv);
+
+        if let Err(e) = Self::parse_recipe(&mut d, context, &recipe.path) {
+            warn!("{}: {}", recipe.path.display(), e);
+        }
+        d
+    }
+
+    fn parse_recipe(d: &mut DataStore, context: &BuildContext, recipe: &Path) -> Result<(), String> {
+        d.inherit("base")?;
+        d.parse_file(recipe)?;
+        for bbappend in context.bbappends_for_recipe(recipe) {
+            d.parse_file(&bbappend)?;
+        }
+        parse_lifecycle::finalize_recipe(d).map(|_| ())
+    }
+
     /// Analyze incremental build requirements
     fn analyze_incremental_build(
         &self,
@@ -364,10 +520,10 @@ impl BuildOrchestrator {
         task_implementations: &HashMap<String, HashMap<String, TaskImplementation>>,
         helper_implementations: &HashMap<String, HashMap<String, TaskImplementation>>,
         recipe_variables: &HashMap<String, HashMap<String, String>>,
-        build_dir: &Path,
+        task_env: &HashMap<TaskId, HashMap<String, String>>,
     ) -> Result<HashMap<String, TaskSpec>, Box<dyn std::error::Error + Send + Sync>> {
         let mut specs = HashMap::new();
-        let tmp_dir = build_dir.join("tmp");
+        let tmp_dir = self.tmp_dir();
         fs::create_dir_all(&tmp_dir)?;
 
         info!("  Processing {} tasks...", task_graph.tasks.len());
@@ -414,21 +570,23 @@ impl BuildOrchestrator {
                 self.create_placeholder_script(&task.recipe_name, &task.task_name)
             };
 
+            // Get recipe variables from parsed recipes, or use defaults
+            let mut recipe_vars = recipe_variables
+                .get(&task.recipe_name)
+                .cloned()
+                .unwrap_or_else(HashMap::new);
+
+            // Add runtime variables that may not be in recipe
+            recipe_vars.entry("PN".to_string()).or_insert_with(|| task.recipe_name.clone());
+            let workdir = tmp_dir.join(&task.recipe_name);
+            recipe_vars.entry("WORKDIR".to_string()).or_insert_with(|| workdir.to_string_lossy().to_string());
+
+            let flags = Self::task_flags(task, &recipe_vars);
+
             // NEW: Preprocess script to handle BitBake syntax (${@python_expr}, ${VAR[flag]}, etc.)
             let script = {
                 let preprocess_start = Instant::now();
 
-                // Get recipe variables from parsed recipes, or use defaults
-                let mut recipe_vars = recipe_variables
-                    .get(&task.recipe_name)
-                    .cloned()
-                    .unwrap_or_else(HashMap::new);
-
-                // Add runtime variables that may not be in recipe
-                recipe_vars.entry("PN".to_string()).or_insert_with(|| task.recipe_name.clone());
-                let workdir = build_dir.join("tmp").join(&task.recipe_name);
-                recipe_vars.entry("WORKDIR".to_string()).or_insert_with(|| workdir.to_string_lossy().to_string());
-
                 let preprocessor = ScriptPreprocessor::new(recipe_vars);
 
                 let result = match preprocessor.preprocess(&raw_script) {
@@ -475,12 +633,13 @@ impl BuildOrchestrator {
                 recipe: task.recipe_name.clone(),
                 script,
                 workdir: task_workdir,
-                env: HashMap::new(),
+                env: task_env.get(&task.task_id).cloned().unwrap_or_default(),
                 outputs: vec![PathBuf::from(&output_file)],
                 timeout: Some(Duration::from_secs(300)),
                 execution_mode,
                 network_policy,
                 resource_limits: ResourceLimits::default(),
+                flags,
             };
 
             specs.insert(task_key, spec);
@@ -497,8 +656,39 @@ impl BuildOrchestrator {
         Ok(specs)
     }
 
+    /// Task flags for execution, expanded with the recipe's variables
+    ///
+    /// Paths that still reference unknown variables are dropped rather than
+    /// created literally.
+    fn task_flags(task: &ExecutableTask, recipe_vars: &HashMap<String, String>) -> TaskFlags {
+        let mut d = DataStore::new();
+        for (name, value) in recipe_vars {
+            d.set_var(name, value.as_str());
+        }
+
+        let mut flags = TaskFlags::from_task_flags(&task.flags);
+        for paths in [&mut flags.dirs, &mut flags.cleandirs, &mut flags.lockfiles] {
+            *paths = std::mem::take(paths)
+                .into_iter()
+                .filter_map(|path| {
+                    let expanded = d.expand(&path.to_string_lossy());
+                    if expanded.contains("${") {
+                        warn!(
+                            "{}:{}: ignoring unexpanded path {}",
+                            task.recipe_name, task.task_name, expanded
+                        );
+                        None
+                    } else {
+                        Some(PathBuf::from(expanded))
+                    }
+                })
+                .collect();
+        }
+        flags
+    }
+
     /// Collect recipe variables for preprocessing
-    fn collect_recipe_vars(&self, recipe_name: &str, build_dir: &Path) -> HashMap<String, String> {
+    fn collect_recipe_vars(&self, recipe_name: &str) -> HashMap<String, String> {
         let mut vars = HashMap::new();
 
         // Recipe metadata
@@ -507,14 +697,15 @@ impl BuildOrchestrator {
         vars.insert("PR".to_string(), "r0".to_string());
 
         // Directory paths (will be overridden by executor with actual paths)
-        let workdir = build_dir.join("tmp").join(recipe_name);
+        let tmp_dir = self.tmp_dir();
+        let workdir = tmp_dir.join(recipe_name);
         vars.insert("WORKDIR".to_string(), workdir.to_string_lossy().to_string());
         vars.insert("S".to_string(), workdir.join("src").to_string_lossy().to_string());
         vars.insert("B".to_string(), workdir.join("build").to_string_lossy().to_string());
         vars.insert("D".to_string(), workdir.join("image").to_string_lossy().to_string());
 
         // Other common variables (matching prelude.sh defaults)
-        vars.insert("TMPDIR".to_string(), build_dir.join("tmp").to_string_lossy().to_string());
+        vars.insert("TMPDIR".to_string(), tmp_dir.to_string_lossy().to_string());
         vars.insert("PTEST_PATH".to_string(), "/usr/lib/ptest".to_string());
         vars.insert("TESTDIR".to_string(), workdir.join("tests").to_string_lossy().to_string());
 
@@ -1,165 +1,37 @@
-// BitBake class dependency mappings
-// Maps class names to the build/runtime dependencies they add
+// BitBake class evaluation
+// Inherited classes are parsed through the DataStore like BitBake does, so the
+// DEPENDS, tasks and task flags they contribute always match the layer's classes.
+// With the parse lifecycle, the recipe is parsed into the same DataStore so its
+// own anonymous python and event handlers run too.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
-use std::fs;
-use crate::simple_python_eval::SimplePythonEvaluator;
-
-/// Get build dependencies added by a class
-pub fn get_class_build_deps(class_name: &str, distro_features: &str) -> Vec<String> {
-    match class_name {
-        // Build system classes
-        "autotools" | "autotools-brokensep" => vec![
-            "autoconf-native".to_string(),
-            "automake-native".to_string(),
-            "libtool-native".to_string(),
-            "gnu-config-native".to_string(),
-        ],
-
-        "cmake" => vec![
-            "cmake-native".to_string(),
-            "ninja-native".to_string(),
-        ],
-
-        "meson" => vec![
-            "meson-native".to_string(),
-            "ninja-native".to_string(),
-        ],
-
-        "pkgconfig" => vec![
-            "pkgconfig-native".to_string(),
-        ],
-
-        // Localization
-        "gettext" => vec![
-            "gettext-native".to_string(),
-        ],
-
-        // Systemd (conditional on DISTRO_FEATURES)
-        "systemd" => {
-            if distro_features.split_whitespace().any(|f| f == "systemd") {
-                vec!["systemd".to_string()]
-            } else {
-                vec![]
-            }
-        },
-
-        // Init scripts
-        "update-rc.d" => vec![
-            "update-rc.d-native".to_string(),
-        ],
-
-        // Python
-        "python3-dir" | "python3native" | "python3targetconfig" => vec![
-            "python3-native".to_string(),
-        ],
-
-        "setuptools3" | "setuptools3-base" | "python_setuptools_build_meta" => vec![
-            "python3-setuptools-native".to_string(),
-            "python3-wheel-native".to_string(),
-        ],
-
-        "python_pep517" => vec![
-            "python3-build-native".to_string(),
-            "python3-installer-native".to_string(),
-        ],
-
-        // Documentation
-        "gtk-doc" => vec![
-            "gtk-doc-native".to_string(),
-        ],
-
-        "texinfo" => vec![
-            "texinfo-native".to_string(),
-        ],
-
-        "manpages" => vec![
-            "groff-native".to_string(),
-        ],
-
-        // Kernel
-        "kernel" | "module-base" => vec![
-            "bc-native".to_string(),
-            "bison-native".to_string(),
-            "flex-native".to_string(),
-        ],
-
-        // Cargo/Rust
-        "cargo" => vec![
-            "cargo-native".to_string(),
-        ],
-
-        "rust-common" | "rust" => vec![
-            "rust-native".to_string(),
-        ],
-
-        // Go
-        "go" => vec![
-            "go-native".to_string(),
-        ],
-
-        // Others
-        "qemu" => vec![
-            "qemu-native".to_string(),
-        ],
-
-        "cross-canadian" => vec![
-            "nativesdk-gcc-cross-canadian-${TRANSLATED_TARGET_ARCH}".to_string(),
-        ],
-
-        // Classes that don't add build dependencies
-        "allarch" | "native" | "packagegroup" | "nopackages" |
-        "features_check" | "update-alternatives" | "useradd" |
-        "systemd-boot" | "image" | "core-image" | "populate_sdk" |
-        "deploy" | "devupstream" | "mirrors" | "sanity" => vec![],
-
-        // Unknown class - no deps
-        _ => vec![],
-    }
-}
-
-/// Get runtime dependencies added by a class
-pub fn get_class_runtime_deps(class_name: &str, distro_features: &str) -> Vec<String> {
-    match class_name {
-        "systemd" => {
-            if distro_features.split_whitespace().any(|f| f == "systemd") {
-                vec!["systemd".to_string()]
-            } else {
-                vec![]
-            }
-        },
-
-        "update-rc.d" => vec![
-            "update-rc.d".to_string(),
-        ],
-
-        "update-alternatives" => vec![
-            "update-alternatives-opkg".to_string(),
-        ],
+use tracing::debug;
+use crate::datastore::DataStore;
+use crate::parse_lifecycle::{self, ParseOutcome};
 
-        // Most classes don't add runtime deps
-        _ => vec![],
-    }
-}
+/// Flags that describe functions rather than tasks
+const FUNCTION_FLAGS: &[&str] = &["func", "python", "export_func", "export"];
 
 /// Parse inherit statement and return class names
 pub fn parse_inherit_statement(line: &str) -> Option<Vec<String>> {
     let trimmed = line.trim();
 
-    // Handle "inherit class1 class2 class3"
-    if let Some(rest) = trimmed.strip_prefix("inherit ") {
-        let classes: Vec<String> = rest
-            .split_whitespace()
-            .map(|s| s.to_string())
-            .collect();
+    // Handle "inherit class1 class2" and "inherit_defer class1"
+    let rest = trimmed
+        .strip_prefix("inherit ")
+        .or_else(|| trimmed.strip_prefix("inherit_defer "))?;
 
-        if !classes.is_empty() {
-            return Some(classes);
-        }
-    }
+    let classes: Vec<String> = rest
+        .split_whitespace()
+        .map(|s| s.to_string())
+        .collect();
 
-    None
+    if classes.is_empty() {
+        None
+    } else {
+        Some(classes)
+    }
 }
 
 /// Extract all inherited classes from content
@@ -175,252 +47,175 @@ pub fn extract_inherited_classes(content: &str) -> Vec<String> {
     classes
 }
 
-/// Parsed dependencies from a .bbclass file
+/// What a set of inherited classes contributes to a recipe
 #[derive(Debug, Clone, Default)]
-pub struct ClassDependencies {
+pub struct ClassContributions {
+    /// Classes inherited, including nested, deferred and INHERIT ones, in order
+    pub classes: Vec<String>,
+    /// Classes that could not be found or parsed, with the error
+    pub failed: Vec<(String, String)>,
+    /// DEPENDS added by the classes
     pub build_deps: Vec<String>,
+    /// RDEPENDS / RDEPENDS:${PN} added by the classes
     pub runtime_deps: Vec<String>,
+    /// addtask/deltask statements from the classes, in parse order
+    pub task_statements: Vec<String>,
+    /// Task flags (do_compile -> dirs -> "${B}"), expanded
+    pub task_flags: BTreeMap<String, BTreeMap<String, String>>,
+    /// Tasks implemented by EXPORT_FUNCTIONS (do_configure -> autotools_do_configure)
+    pub exported_functions: BTreeMap<String, String>,
+    /// Reason given by bb.parse.SkipRecipe in the recipe's or its classes' anonymous python
+    pub skip_reason: Option<String>,
 }
 
-/// Parse a .bbclass file and extract dependencies
-/// Phase 7f: Now supports Python expression evaluation in .bbclass files
-pub fn parse_class_file(
-    class_path: &Path,
+/// Parse `classes` on top of the configuration with the recipe's `variables`
+/// and collect what they contribute
+///
+/// As in BitBake, base and the classes listed in INHERIT are applied before
+/// the recipe's own classes. `configuration` is the parsed configuration
+/// (bitbake.conf, local.conf, distro conf), so INHERIT and DISTRO_FEATURES
+/// come from the build; without it the classes start from an empty DataStore.
+/// `search_paths` are layer directories; classes are looked up in their
+/// classes-recipe, classes-global and classes subdirectories. DEPENDS and
+/// RDEPENDS from `variables` are left out so only class contributions remain.
+pub fn evaluate_classes(
+    classes: &[String],
+    configuration: Option<&DataStore>,
+    search_paths: &[PathBuf],
     variables: &HashMap<String, String>,
-) -> Option<ClassDependencies> {
-    let content = fs::read_to_string(class_path).ok()?;
-
-    let mut deps = ClassDependencies::default();
-
-    // Create Python evaluator with recipe context
-    let evaluator = SimplePythonEvaluator::new(variables.clone());
-
-    for line in content.lines() {
-        let trimmed = line.trim();
-
-        // Skip comments and empty lines
-        if trimmed.is_empty() || trimmed.starts_with('#') {
-            continue;
-        }
-
-        // Parse DEPENDS lines (Phase 7f: with Python evaluation)
-        if let Some(extracted) = extract_depends_from_line(trimmed, "DEPENDS", &evaluator) {
-            deps.build_deps.extend(extracted);
+) -> ClassContributions {
+    let mut d = base_datastore(configuration, search_paths);
+    for (name, value) in variables {
+        if !name.starts_with("DEPENDS") && !name.starts_with("RDEPENDS") {
+            d.set_var(name, value.as_str());
         }
+    }
 
-        // Parse RDEPENDS lines (Phase 7f: with Python evaluation)
-        if let Some(extracted) = extract_depends_from_line(trimmed, "RDEPENDS", &evaluator) {
-            deps.runtime_deps.extend(extracted);
+    let mut contributions = ClassContributions::default();
+    inherit_global(&mut d, &mut contributions);
+    for class in classes {
+        if let Err(e) = d.inherit(class) {
+            debug!("Failed to inherit {}: {}", class, e);
+            contributions.failed.push((class.clone(), e));
         }
     }
+    if let Err(e) = d.finalize() {
+        contributions.failed.push(("inherit_defer".to_string(), e));
+    }
 
-    Some(deps)
+    collect(&d, variables.get("PN").map(String::as_str), contributions)
 }
 
-/// Extract dependencies from a single line
-/// Handles: DEPENDS = "foo", DEPENDS += "foo", DEPENDS:append = "foo", etc.
-/// Phase 7f: Now evaluates Python expressions using SimplePythonEvaluator
-fn extract_depends_from_line(
-    line: &str,
-    var_name: &str,
-    evaluator: &SimplePythonEvaluator,
-) -> Option<Vec<String>> {
-    // Handle various forms:
-    // DEPENDS = "foo bar"
-    // DEPENDS += "foo"
-    // DEPENDS:append = " foo"
-    // DEPENDS:prepend = "foo "
-    // Phase 7f: DEPENDS:append = "${@bb.utils.contains('DISTRO_FEATURES', 'systemd', 'libsystemd', '', d)}"
-
-    let trimmed = line.trim();
-
-    // Check if line starts with DEPENDS (or DEPENDS:something)
-    if !trimmed.starts_with(var_name) {
-        return None;
+/// Parse a recipe together with its classes into one DataStore and run
+/// BitBake's parse lifecycle on it
+///
+/// `content` is parsed as the recipe at `recipe_path` on top of base and
+/// INHERIT, then `parse_lifecycle::finalize_recipe` runs the event handlers
+/// and the anonymous python of the recipe and its classes. `variables` are
+/// set before parsing (PN, CLASSOVERRIDE, ...). The contributions hold the
+/// recipe's final DEPENDS, task statements and task flags, including what its
+/// anonymous python added with setVarFlag or bb.build.addtask.
+pub fn evaluate_recipe(
+    content: &str,
+    recipe_path: &Path,
+    configuration: Option<&DataStore>,
+    search_paths: &[PathBuf],
+    variables: &HashMap<String, String>,
+) -> ClassContributions {
+    let mut d = base_datastore(configuration, search_paths);
+    for (name, value) in variables {
+        d.set_var(name, value.as_str());
     }
+    d.set_var("FILE", recipe_path.to_string_lossy());
 
-    // Find the assignment operator
-    let after_var = if let Some(colon_pos) = trimmed.find(':') {
-        // Handle DEPENDS:append, DEPENDS:prepend, etc.
-        if let Some(eq_pos) = trimmed[colon_pos..].find('=') {
-            &trimmed[colon_pos + eq_pos + 1..]
-        } else {
-            return None;
-        }
-    } else if let Some(eq_pos) = trimmed.find('=') {
-        // Handle DEPENDS =, DEPENDS +=, etc.
-        &trimmed[eq_pos + 1..]
-    } else {
-        return None;
-    };
-
-    // Extract value from quotes or direct assignment
-    let mut value = clean_value(after_var);
-
-    if value.is_empty() {
-        return None;
+    let mut contributions = ClassContributions::default();
+    inherit_global(&mut d, &mut contributions);
+    if let Err(e) = d.parse_content(content, recipe_path) {
+        debug!("Failed to parse {}: {}", recipe_path.display(), e);
+        contributions.failed.push((recipe_path.display().to_string(), e));
     }
-
-    // Phase 7f: Evaluate Python expressions if present
-    if value.contains("${@") {
-        // Try to evaluate the Python expression
-        value = eval_python_in_value(&value, evaluator);
-
-        // If evaluation resulted in empty string, no dependencies
-        if value.is_empty() {
-            return None;
-        }
+    match parse_lifecycle::finalize_recipe(&mut d) {
+        Ok(ParseOutcome::Skipped(reason)) => contributions.skip_reason = Some(reason),
+        Ok(ParseOutcome::Parsed) => {}
+        Err(e) => contributions.failed.push(("anonymous python".to_string(), e)),
     }
 
-    // Split by whitespace and filter out empty strings
-    let deps: Vec<String> = value
-        .split_whitespace()
-        .filter(|s| !s.is_empty())
-        .map(|s| s.to_string())
-        .collect();
+    let pn = d.get_var("PN");
+    collect(&d, pn.as_deref(), contributions)
+}
 
-    if deps.is_empty() {
-        None
-    } else {
-        Some(deps)
+/// The configuration, or an empty DataStore, with the layers to search classes in
+fn base_datastore(configuration: Option<&DataStore>, search_paths: &[PathBuf]) -> DataStore {
+    let mut d = configuration.cloned().unwrap_or_default();
+    for path in search_paths {
+        d.add_search_path(path);
     }
+    d
 }
 
-/// Evaluate Python expressions in a value string (Phase 7f)
-/// Finds all ${@...} expressions and evaluates them using SimplePythonEvaluator
-fn eval_python_in_value(value: &str, evaluator: &SimplePythonEvaluator) -> String {
-    let mut result = String::new();
-    let mut start = 0;
-
-    while let Some(expr_start) = value[start..].find("${@") {
-        let abs_start = start + expr_start;
-
-        // Add text before the expression
-        result.push_str(&value[start..abs_start]);
-
-        // Find the closing brace
-        if let Some(expr_end) = find_matching_brace(&value[abs_start + 2..]) {
-            let expr = &value[abs_start..abs_start + 3 + expr_end]; // Include ${@ and }
-
-            // Try to evaluate the expression
-            if let Some(evaluated) = evaluator.evaluate(expr) {
-                result.push_str(&evaluated);
-            } else {
-                // Evaluation failed - keep original expression
-                // But return empty to signal we can't process this
-                return String::new();
-            }
-
-            start = abs_start + 3 + expr_end;
-        } else {
-            // No closing brace - keep original and continue
-            result.push_str(&value[abs_start..]);
-            break;
-        }
+/// base and INHERIT, which BitBake applies ahead of the recipe
+fn inherit_global(d: &mut DataStore, contributions: &mut ClassContributions) {
+    if let Err(e) = d.inherit("base") {
+        contributions.failed.push(("base".to_string(), e));
+    }
+    if let Err(e) = d.inherit_global_classes() {
+        contributions.failed.push(("INHERIT".to_string(), e));
     }
-
-    // Add remaining text
-    result.push_str(&value[start..]);
-
-    result
 }
 
-/// Find the matching closing brace for a ${...} expression
-/// Returns the position relative to the start (after ${@)
-fn find_matching_brace(s: &str) -> Option<usize> {
-    let mut depth = 1;
-    let mut in_single_quote = false;
-    let mut in_double_quote = false;
-
-    for (i, ch) in s.chars().enumerate() {
-        match ch {
-            '\'' if !in_double_quote => in_single_quote = !in_single_quote,
-            '"' if !in_single_quote => in_double_quote = !in_double_quote,
-            '{' if !in_single_quote && !in_double_quote => depth += 1,
-            '}' if !in_single_quote && !in_double_quote => {
-                depth -= 1;
-                if depth == 0 {
-                    return Some(i);
-                }
+/// Collect dependencies, task statements and task flags from a finalised DataStore
+fn collect(d: &DataStore, pn: Option<&str>, mut contributions: ClassContributions) -> ClassContributions {
+    contributions.classes = d.inherits().to_vec();
+    contributions.build_deps = split_deps(d.get_var("DEPENDS"));
+    contributions.runtime_deps = split_deps(d.get_var("RDEPENDS"));
+    if let Some(pn) = pn {
+        for dep in split_deps(d.get_var(&format!("RDEPENDS:{}", pn))) {
+            if !contributions.runtime_deps.contains(&dep) {
+                contributions.runtime_deps.push(dep);
             }
-            _ => {}
         }
     }
+    contributions.task_statements = d.task_statements().to_vec();
 
-    None
-}
-
-/// Clean a value by removing quotes and extra whitespace
-fn clean_value(value: &str) -> String {
-    let trimmed = value.trim();
-
-    // Remove surrounding quotes
-    let unquoted = if (trimmed.starts_with('"') && trimmed.ends_with('"'))
-        || (trimmed.starts_with('\'') && trimmed.ends_with('\''))
-    {
-        &trimmed[1..trimmed.len() - 1]
-    } else {
-        trimmed
-    };
-
-    unquoted.trim().to_string()
-}
-
-/// Find .bbclass file in search paths
-pub fn find_class_file(class_name: &str, search_paths: &[PathBuf]) -> Option<PathBuf> {
-    let class_filename = format!("{}.bbclass", class_name);
-
-    for base_path in search_paths {
-        // Try classes-recipe/ subdirectory (modern Yocto layout)
-        let recipe_class_path = base_path.join("classes-recipe").join(&class_filename);
-        if recipe_class_path.exists() {
-            return Some(recipe_class_path);
+    for key in d.keys() {
+        if !key.starts_with("do_") || key.contains(':') {
+            continue;
         }
 
-        // Try classes/ subdirectory (older layout)
-        let class_path = base_path.join("classes").join(&class_filename);
-        if class_path.exists() {
-            return Some(class_path);
+        let flags: BTreeMap<String, String> = d
+            .get_var_flags(&key)
+            .into_keys()
+            .filter(|flag| !FUNCTION_FLAGS.contains(&flag.as_str()))
+            .filter_map(|flag| d.get_var_flag(&key, &flag).map(|value| (flag, value)))
+            .collect();
+        if !flags.is_empty() {
+            contributions.task_flags.insert(key.clone(), flags);
         }
 
-        // Try direct path
-        let direct_path = base_path.join(&class_filename);
-        if direct_path.exists() {
-            return Some(direct_path);
+        if let Some(function) = d.exported_function(&key) {
+            contributions.exported_functions.insert(key, function);
         }
     }
 
-    None
+    contributions
 }
 
-/// Get dependencies for a class, trying to parse .bbclass file first,
-/// falling back to hardcoded mappings
-/// Phase 7f: Now accepts variables for Python expression evaluation
-pub fn get_class_deps_dynamic(
-    class_name: &str,
-    distro_features: &str,
-    search_paths: &[PathBuf],
-    variables: &HashMap<String, String>,
-) -> (Vec<String>, Vec<String>) {
-    // Try to parse .bbclass file first (Phase 7f: with Python evaluation)
-    if let Some(class_path) = find_class_file(class_name, search_paths)
-        && let Some(parsed) = parse_class_file(&class_path, variables) {
-            // Successfully parsed - use those dependencies
-            if !parsed.build_deps.is_empty() || !parsed.runtime_deps.is_empty() {
-                return (parsed.build_deps, parsed.runtime_deps);
-            }
+/// Split a dependency list, dropping references that could not be expanded
+fn split_deps(value: Option<String>) -> Vec<String> {
+    let mut deps: Vec<String> = Vec::new();
+    for dep in value.unwrap_or_default().split_whitespace() {
+        if !dep.contains("${") && !deps.iter().any(|d| d == dep) {
+            deps.push(dep.to_string());
         }
-
-    // Fall back to hardcoded mappings
-    let build_deps = get_class_build_deps(class_name, distro_features);
-    let runtime_deps = get_class_runtime_deps(class_name, distro_features);
-
-    (build_deps, runtime_deps)
+    }
+    deps
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::fs;
+    use tempfile::TempDir;
 
     #[test]
     fn test_parse_inherit_single() {
@@ -435,194 +230,180 @@ mod tests {
     }
 
     #[test]
-    fn test_autotools_deps() {
-        let deps = get_class_build_deps("autotools", "");
-        assert!(deps.contains(&"autoconf-native".to_string()));
-        assert!(deps.contains(&"automake-native".to_string()));
-        assert!(deps.contains(&"libtool-native".to_string()));
-    }
-
-    #[test]
-    fn test_cmake_deps() {
-        let deps = get_class_build_deps("cmake", "");
-        assert!(deps.contains(&"cmake-native".to_string()));
-        assert!(deps.contains(&"ninja-native".to_string()));
-    }
-
-    #[test]
-    fn test_systemd_conditional() {
-        // With systemd in DISTRO_FEATURES
-        let deps = get_class_build_deps("systemd", "systemd pam ipv6");
-        assert_eq!(deps, vec!["systemd"]);
-
-        // Without systemd in DISTRO_FEATURES
-        let deps = get_class_build_deps("systemd", "pam ipv6");
-        assert_eq!(deps, Vec::<String>::new());
-    }
-
-    #[test]
-    fn test_clean_value() {
-        assert_eq!(clean_value("\"foo bar\""), "foo bar");
-        assert_eq!(clean_value("'foo bar'"), "foo bar");
-        assert_eq!(clean_value("  foo bar  "), "foo bar");
-        assert_eq!(clean_value("\" foo bar \""), "foo bar");
-    }
-
-    #[test]
-    fn test_extract_depends_simple() {
-        let vars = HashMap::new();
-        let evaluator = SimplePythonEvaluator::new(vars);
-        let line = "DEPENDS = \"cmake-native ninja-native\"";
-        let deps = extract_depends_from_line(line, "DEPENDS", &evaluator).unwrap();
-        assert_eq!(deps, vec!["cmake-native", "ninja-native"]);
-    }
-
-    #[test]
-    fn test_extract_depends_append() {
-        let vars = HashMap::new();
-        let evaluator = SimplePythonEvaluator::new(vars);
-        let line = "DEPENDS:append = \" meson-native ninja-native\"";
-        let deps = extract_depends_from_line(line, "DEPENDS", &evaluator).unwrap();
-        assert_eq!(deps, vec!["meson-native", "ninja-native"]);
+    fn test_parse_inherit_defer() {
+        let classes = parse_inherit_statement("inherit_defer native").unwrap();
+        assert_eq!(classes, vec!["native"]);
+        assert_eq!(parse_inherit_statement("inherited = 1"), None);
     }
 
-    #[test]
-    fn test_extract_depends_prepend() {
-        let vars = HashMap::new();
-        let evaluator = SimplePythonEvaluator::new(vars);
-        let line = "DEPENDS:prepend = \"cmake-native \"";
-        let deps = extract_depends_from_line(line, "DEPENDS", &evaluator).unwrap();
-        assert_eq!(deps, vec!["cmake-native"]);
+    fn write_classes(layer: &std::path::Path) {
+        let classes = layer.join("classes-recipe");
+        fs::create_dir_all(&classes).unwrap();
+        fs::write(
+            classes.join("base.bbclass"),
+            r#"addtask fetch
+addtask configure after do_unpack
+addtask compile after do_configure
+base_do_configure() {
+	:
+}
+base_do_compile() {
+	oe_runmake
+}
+EXPORT_FUNCTIONS do_configure do_compile
+"#,
+        )
+        .unwrap();
+        fs::write(
+            classes.join("autotools.bbclass"),
+            r#"inherit siteinfo
+DEPENDS:prepend = "autoconf-native automake-native libtool-native "
+autotools_do_configure() {
+	oe_runconf
+}
+do_configure[dirs] = "${B}"
+EXPORT_FUNCTIONS do_configure
+"#,
+        )
+        .unwrap();
+        fs::write(
+            classes.join("siteinfo.bbclass"),
+            "DEPENDS:append = \" ${@bb.utils.contains('DISTRO_FEATURES', 'systemd', 'systemd', '', d)}\"\n",
+        )
+        .unwrap();
+        fs::write(
+            classes.join("update-rc.d.bbclass"),
+            "RDEPENDS:${PN}:append = \" update-rc.d\"\naddtask populate_initscripts after do_install\n",
+        )
+        .unwrap();
     }
 
     #[test]
-    fn test_extract_depends_with_python_no_context() {
-        // Without proper context, Python expression can't be evaluated
-        let vars = HashMap::new();
-        let evaluator = SimplePythonEvaluator::new(vars);
-        let line = "DEPENDS:append = \"${@'qemu-native' if d.getVar('FOO') else ''}\"";
-        let deps = extract_depends_from_line(line, "DEPENDS", &evaluator);
-        assert_eq!(deps, None); // Can't evaluate without FOO variable
-    }
+    fn test_evaluate_classes() {
+        let temp = TempDir::new().unwrap();
+        let layer = temp.path().join("meta");
+        write_classes(&layer);
 
-    #[test]
-    fn test_extract_depends_with_python_evaluated() {
-        // Phase 7f: With proper context, Python expression IS evaluated
         let mut vars = HashMap::new();
+        vars.insert("PN".to_string(), "foo".to_string());
+        vars.insert("B".to_string(), "/work/build".to_string());
         vars.insert("DISTRO_FEATURES".to_string(), "systemd pam".to_string());
-        let evaluator = SimplePythonEvaluator::new(vars);
-
-        let line = "DEPENDS:append = \" ${@bb.utils.contains('DISTRO_FEATURES', 'systemd', 'libsystemd', '', d)}\"";
-        let deps = extract_depends_from_line(line, "DEPENDS", &evaluator).unwrap();
-        assert_eq!(deps, vec!["libsystemd"]);
+        vars.insert("DEPENDS".to_string(), "zlib".to_string());
+
+        let classes = vec!["autotools".to_string(), "update-rc.d".to_string()];
+        let c = evaluate_classes(&classes, None, std::slice::from_ref(&layer), &vars);
+
+        assert!(c.failed.is_empty());
+        assert_eq!(c.classes, vec!["base", "autotools", "siteinfo", "update-rc.d"]);
+        assert_eq!(
+            c.build_deps,
+            vec!["autoconf-native", "automake-native", "libtool-native", "systemd"]
+        );
+        assert_eq!(c.runtime_deps, vec!["update-rc.d"]);
+        assert_eq!(c.task_statements.len(), 4);
+        assert_eq!(c.task_flags["do_configure"]["dirs"], "/work/build");
+        assert_eq!(c.exported_functions["do_configure"], "autotools_do_configure");
+        assert_eq!(c.exported_functions["do_compile"], "base_do_compile");
+
+        // Without systemd the python condition contributes nothing
+        vars.insert("DISTRO_FEATURES".to_string(), "pam".to_string());
+        let c = evaluate_classes(&classes, None, &[layer], &vars);
+        assert_eq!(c.build_deps.len(), 3);
     }
 
     #[test]
-    fn test_extract_depends_with_python_empty_result() {
-        // Python expression evaluates to empty string
-        let mut vars = HashMap::new();
-        vars.insert("DISTRO_FEATURES".to_string(), "pam ipv6".to_string());
-        let evaluator = SimplePythonEvaluator::new(vars);
-
-        let line = "DEPENDS:append = \"${@bb.utils.contains('DISTRO_FEATURES', 'systemd', 'libsystemd', '', d)}\"";
-        let deps = extract_depends_from_line(line, "DEPENDS", &evaluator);
-        assert_eq!(deps, None); // Empty result = no dependencies
-    }
-
-    #[test]
-    fn test_parse_class_content() {
-        let content = r#"
-# This is a test class
-DEPENDS:prepend = "cmake-native "
-DEPENDS:append = " ninja-native"
-RDEPENDS:${PN} = "bash"
-"#;
-
-        // Create a temporary file
-        let temp_dir = std::env::temp_dir();
-        let temp_file = temp_dir.join("test.bbclass");
-        std::fs::write(&temp_file, content).unwrap();
+    fn test_evaluate_missing_class() {
+        let temp = TempDir::new().unwrap();
+        let layer = temp.path().join("meta");
+        write_classes(&layer);
 
-        let vars = HashMap::new();
-        let parsed = parse_class_file(&temp_file, &vars).unwrap();
-
-        assert_eq!(parsed.build_deps.len(), 2);
-        assert!(parsed.build_deps.contains(&"cmake-native".to_string()));
-        assert!(parsed.build_deps.contains(&"ninja-native".to_string()));
-        assert_eq!(parsed.runtime_deps, vec!["bash"]);
+        let mut vars = HashMap::new();
+        vars.insert("INHERIT".to_string(), "update-rc.d".to_string());
+        let classes = vec!["nonexistent".to_string()];
+        let c = evaluate_classes(&classes, None, &[layer], &vars);
 
-        // Clean up
-        std::fs::remove_file(&temp_file).ok();
+        assert_eq!(c.classes, vec!["base", "update-rc.d"]);
+        assert_eq!(c.failed.len(), 1);
+        assert_eq!(c.failed[0].0, "nonexistent");
     }
 
     #[test]
-    fn test_parse_class_with_python_expressions() {
-        // Phase 7f: Test Python expression evaluation in .bbclass files
-        let content = r#"
-# Conditional dependencies based on DISTRO_FEATURES
-DEPENDS:append = " ${@bb.utils.contains('DISTRO_FEATURES', 'systemd', 'libsystemd', '', d)}"
-DEPENDS:append = " ${@bb.utils.contains('DISTRO_FEATURES', 'pam', 'libpam', '', d)}"
-RDEPENDS:${PN} = "${@bb.utils.filter('DISTRO_FEATURES', 'systemd pam wayland', d)}"
-"#;
+    fn test_evaluate_classes_on_configuration() {
+        let temp = TempDir::new().unwrap();
+        let layer = temp.path().join("meta");
+        write_classes(&layer);
 
-        // Create a temporary file
-        let temp_dir = std::env::temp_dir();
-        let temp_file = temp_dir.join("test_python.bbclass");
-        std::fs::write(&temp_file, content).unwrap();
+        // INHERIT and DISTRO_FEATURES come from local.conf / the distro conf
+        let mut configuration = DataStore::new();
+        configuration.add_search_path(&layer);
+        configuration.set_var("INHERIT", "update-rc.d");
+        configuration.set_var("DISTRO_FEATURES", "systemd");
 
-        // Test with systemd in DISTRO_FEATURES
         let mut vars = HashMap::new();
-        vars.insert("DISTRO_FEATURES".to_string(), "systemd pam ipv6".to_string());
-        let parsed = parse_class_file(&temp_file, &vars).unwrap();
-
-        // Should include libsystemd and libpam
-        assert!(parsed.build_deps.contains(&"libsystemd".to_string()));
-        assert!(parsed.build_deps.contains(&"libpam".to_string()));
-
-        // Runtime deps should be filtered list
-        assert!(parsed.runtime_deps.contains(&"systemd".to_string()));
-        assert!(parsed.runtime_deps.contains(&"pam".to_string()));
-
-        // Test without systemd in DISTRO_FEATURES
-        let mut vars2 = HashMap::new();
-        vars2.insert("DISTRO_FEATURES".to_string(), "ipv6 bluetooth".to_string());
-        let parsed2 = parse_class_file(&temp_file, &vars2).unwrap();
-
-        // Should NOT include systemd deps
-        assert!(!parsed2.build_deps.contains(&"libsystemd".to_string()));
-        assert!(!parsed2.build_deps.contains(&"libpam".to_string()));
-
-        // Clean up
-        std::fs::remove_file(&temp_file).ok();
+        vars.insert("PN".to_string(), "foo".to_string());
+        let classes = vec!["autotools".to_string()];
+        let c = evaluate_classes(&classes, Some(&configuration), &[], &vars);
+
+        assert!(c.failed.is_empty(), "{:?}", c.failed);
+        assert_eq!(c.classes, vec!["base", "update-rc.d", "autotools", "siteinfo"]);
+        assert!(c.build_deps.contains(&"systemd".to_string()));
+        assert_eq!(c.runtime_deps, vec!["update-rc.d"]);
     }
 
     #[test]
-    fn test_find_matching_brace() {
-        assert_eq!(find_matching_brace("test}"), Some(4));
-        assert_eq!(find_matching_brace("test{nested}end}"), Some(15));
-        assert_eq!(find_matching_brace("'}'} more"), Some(3));
-        assert_eq!(find_matching_brace("test"), None);
-    }
+    fn test_evaluate_recipe_anonymous_python() {
+        let temp = TempDir::new().unwrap();
+        let layer = temp.path().join("meta");
+        write_classes(&layer);
+        fs::write(
+            layer.join("classes-recipe/features_check.bbclass"),
+            r#"python () {
+    required = (d.getVar("REQUIRED_DISTRO_FEATURES") or "").split()
+    missing = [f for f in required if f not in d.getVar("DISTRO_FEATURES").split()]
+    if missing:
+        raise bb.parse.SkipRecipe("missing required distro features %s" % missing)
+    d.appendVar("DEPENDS", " features-native")
+}
+"#,
+        )
+        .unwrap();
+        let recipe_path = layer.join("recipes-test/foo/foo_1.0.bb");
 
-    #[test]
-    fn test_eval_python_in_value() {
         let mut vars = HashMap::new();
-        vars.insert("DISTRO_FEATURES".to_string(), "systemd pam".to_string());
-        let evaluator = SimplePythonEvaluator::new(vars);
-
-        // Test single expression
-        let value = "${@bb.utils.contains('DISTRO_FEATURES', 'systemd', 'yes', 'no', d)}";
-        let result = eval_python_in_value(value, &evaluator);
-        assert_eq!(result, "yes");
-
-        // Test with surrounding text
-        let value = "before ${@bb.utils.contains('DISTRO_FEATURES', 'pam', 'middle', 'skip', d)} after";
-        let result = eval_python_in_value(value, &evaluator);
-        assert_eq!(result, "before middle after");
-
-        // Test multiple expressions
-        let value = "${@bb.utils.contains('DISTRO_FEATURES', 'systemd', 'sys', '', d)} ${@bb.utils.contains('DISTRO_FEATURES', 'pam', 'auth', '', d)}";
-        let result = eval_python_in_value(value, &evaluator);
-        assert_eq!(result, "sys auth");
+        vars.insert("PN".to_string(), "foo".to_string());
+        vars.insert("DISTRO_FEATURES".to_string(), "pam".to_string());
+
+        // The recipe's own anonymous python runs in the same DataStore as its classes'
+        let recipe = r#"
+REQUIRED_DISTRO_FEATURES = "pam"
+DEPENDS = "zlib"
+inherit features_check
+python () {
+    d.setVarFlag("do_compile", "network", "1")
+    bb.build.addtask("do_deploy", "do_build", "do_compile", d)
+}
+"#;
+        let c = evaluate_recipe(recipe, &recipe_path, None, std::slice::from_ref(&layer), &vars);
+        assert!(c.failed.is_empty(), "{:?}", c.failed);
+        assert_eq!(c.skip_reason, None);
+        assert_eq!(c.build_deps, vec!["zlib", "features-native"]);
+        assert_eq!(c.task_flags["do_compile"]["network"], "1");
+        assert!(c.task_statements.contains(&"addtask do_deploy after do_compile before do_build".to_string()));
+
+        let c = evaluate_recipe(
+            &recipe.replace("= \"pam\"", "= \"x11\""),
+            &recipe_path,
+            None,
+            std::slice::from_ref(&layer),
+            &vars,
+        );
+        assert_eq!(
+            c.skip_reason.as_deref(),
+            Some("missing required distro features ['x11']")
+        );
+
+        let skipped = "python () {\n    raise bb.parse.SkipRecipe(\"not for this machine\")\n}\n";
+        let c = evaluate_recipe(skipped, &recipe_path, None, &[layer], &vars);
+        assert_eq!(c.skip_reason.as_deref(), Some("not for this machine"));
     }
 }
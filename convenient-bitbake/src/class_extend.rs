@@ -0,0 +1,237 @@
+// BBCLASSEXTEND variant expansion
+// Mirrors the virtclass handlers in native.bbclass, nativesdk.bbclass,
+// cross.bbclass and multilib.bbclass: each variant gets its own PN,
+// its own class override and a remapped DEPENDS/PROVIDES namespace.
+
+use crate::recipe_extractor::BuildContext;
+use std::fmt;
+
+/// One entry of a recipe's BBCLASSEXTEND list
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ClassExtension {
+    /// `native` - runs on the build host, PN becomes `${BPN}-native`
+    Native,
+    /// `nativesdk` - runs on the SDK host, PN becomes `nativesdk-${BPN}`
+    NativeSdk,
+    /// `cross` - build host tool targeting TARGET_ARCH, PN becomes `${BPN}-cross-${TARGET_ARCH}`
+    Cross,
+    /// `multilib:<variant>` - target build for an alternate ABI, PN becomes `<variant>-${BPN}`
+    Multilib(String),
+}
+
+impl ClassExtension {
+    /// Parse a single BBCLASSEXTEND entry ("native", "multilib:lib32", ...)
+    pub fn parse(entry: &str) -> Option<Self> {
+        match entry.trim() {
+            "native" => Some(Self::Native),
+            "nativesdk" => Some(Self::NativeSdk),
+            "cross" => Some(Self::Cross),
+            other => other
+                .strip_prefix("multilib:")
+                .filter(|variant| !variant.is_empty())
+                .map(|variant| Self::Multilib(variant.to_string())),
+        }
+    }
+
+    /// Parse a whole BBCLASSEXTEND value, skipping unknown and duplicate entries
+    pub fn parse_list(value: &str) -> Vec<Self> {
+        let mut extensions = Vec::new();
+        for entry in value.split_whitespace() {
+            if let Some(ext) = Self::parse(entry)
+                && !extensions.contains(&ext)
+            {
+                extensions.push(ext);
+            }
+        }
+        extensions
+    }
+
+    /// Class context used for override resolution ("native", "target", ...)
+    pub fn class(&self) -> &'static str {
+        match self {
+            Self::Native => "native",
+            Self::NativeSdk => "nativesdk",
+            Self::Cross => "cross",
+            Self::Multilib(_) => "target",
+        }
+    }
+
+    /// CLASSOVERRIDE for this variant (e.g. "class-native")
+    pub fn class_override(&self) -> String {
+        format!("class-{}", self.class())
+    }
+
+    /// Variant-specific override (e.g. "virtclass-native", "virtclass-multilib-lib32")
+    pub fn virtclass_override(&self) -> String {
+        match self {
+            Self::Multilib(variant) => format!("virtclass-multilib-{}", variant),
+            _ => format!("virtclass-{}", self.class()),
+        }
+    }
+
+    /// BBEXTENDCURR as BitBake sets it ("native", "multilib", ...)
+    pub fn extend_curr(&self) -> &'static str {
+        match self {
+            Self::Native => "native",
+            Self::NativeSdk => "nativesdk",
+            Self::Cross => "cross",
+            Self::Multilib(_) => "multilib",
+        }
+    }
+
+    /// BBEXTENDVARIANT as BitBake sets it (only non-empty for multilib)
+    pub fn extend_variant(&self) -> &str {
+        match self {
+            Self::Multilib(variant) => variant,
+            _ => "",
+        }
+    }
+
+    /// Multilib prefix (MLPREFIX) for this variant
+    pub fn mlprefix(&self) -> String {
+        match self {
+            Self::Multilib(variant) => format!("{}-", variant),
+            _ => String::new(),
+        }
+    }
+
+    /// Build context for extracting this variant, derived from the base context
+    pub fn build_context(&self, base: &BuildContext) -> BuildContext {
+        let mut ctx = base.clone();
+        ctx.class = self.class().to_string();
+        ctx.overrides.push(self.virtclass_override());
+        ctx
+    }
+
+    /// PN of this variant for a recipe with base name `bpn`
+    pub fn variant_pn(&self, bpn: &str, target_arch: &str) -> String {
+        match self {
+            Self::Native => format!("{}-native", bpn),
+            Self::NativeSdk => format!("nativesdk-{}", bpn),
+            Self::Cross => format!("{}-cross-{}", bpn, target_arch),
+            Self::Multilib(variant) => format!("{}-{}", variant, bpn),
+        }
+    }
+
+    /// Map a DEPENDS/PROVIDES entry into this variant's namespace
+    ///
+    /// `pn` is the variant PN and `bpn` the base recipe name; references to the
+    /// recipe itself are kept so that `${PN}-foo` maps to `${BPN}-foo-native`
+    /// rather than `${BPN}-native-foo-native`.
+    pub fn map_name(&self, name: &str, pn: &str, bpn: &str) -> String {
+        if name == pn {
+            return name.to_string();
+        }
+
+        match self {
+            Self::Native | Self::Cross => {
+                if name.ends_with("-native") || name.contains("-cross-") {
+                    name.to_string()
+                } else {
+                    format!("{}-native", name.replace(pn, bpn))
+                }
+            }
+            Self::NativeSdk => {
+                if name.ends_with("-native")
+                    || name.contains("-cross")
+                    || name.contains("nativesdk-")
+                {
+                    name.to_string()
+                } else if let Some(virt) = name.strip_prefix("virtual/") {
+                    format!("virtual/nativesdk-{}", virt)
+                } else {
+                    format!("nativesdk-{}", name.replace(pn, bpn))
+                }
+            }
+            Self::Multilib(variant) => {
+                let prefix = format!("{}-", variant);
+                if name.ends_with("-native")
+                    || name.contains("-cross")
+                    || name.starts_with("nativesdk-")
+                    || name.starts_with(&prefix)
+                    || name.contains(&format!("/{}", prefix))
+                {
+                    name.to_string()
+                } else if let Some(virt) = name.strip_prefix("virtual/") {
+                    format!("virtual/{}{}", prefix, virt)
+                } else {
+                    format!("{}{}", prefix, name.replace(pn, bpn))
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for ClassExtension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Multilib(variant) => write!(f, "multilib:{}", variant),
+            other => write!(f, "{}", other.class()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_list() {
+        let exts = ClassExtension::parse_list("native nativesdk multilib:lib32 bogus native");
+        assert_eq!(
+            exts,
+            vec![
+                ClassExtension::Native,
+                ClassExtension::NativeSdk,
+                ClassExtension::Multilib("lib32".to_string()),
+            ]
+        );
+        assert_eq!(exts[2].to_string(), "multilib:lib32");
+    }
+
+    #[test]
+    fn test_variant_pn() {
+        assert_eq!(ClassExtension::Native.variant_pn("zlib", "x86_64"), "zlib-native");
+        assert_eq!(ClassExtension::NativeSdk.variant_pn("zlib", "x86_64"), "nativesdk-zlib");
+        assert_eq!(ClassExtension::Cross.variant_pn("binutils", "aarch64"), "binutils-cross-aarch64");
+        assert_eq!(
+            ClassExtension::Multilib("lib32".to_string()).variant_pn("zlib", "x86_64"),
+            "lib32-zlib"
+        );
+    }
+
+    #[test]
+    fn test_map_name_native() {
+        let ext = ClassExtension::Native;
+        assert_eq!(ext.map_name("openssl", "curl-native", "curl"), "openssl-native");
+        assert_eq!(ext.map_name("pkgconfig-native", "curl-native", "curl"), "pkgconfig-native");
+        assert_eq!(ext.map_name("virtual/libiconv", "curl-native", "curl"), "virtual/libiconv-native");
+        assert_eq!(ext.map_name("curl-native", "curl-native", "curl"), "curl-native");
+    }
+
+    #[test]
+    fn test_map_name_nativesdk_and_multilib() {
+        let sdk = ClassExtension::NativeSdk;
+        assert_eq!(sdk.map_name("zlib", "nativesdk-curl", "curl"), "nativesdk-zlib");
+        assert_eq!(sdk.map_name("virtual/libc", "nativesdk-curl", "curl"), "virtual/nativesdk-libc");
+        assert_eq!(sdk.map_name("perl-native", "nativesdk-curl", "curl"), "perl-native");
+
+        let ml = ClassExtension::Multilib("lib32".to_string());
+        assert_eq!(ml.map_name("zlib", "lib32-curl", "curl"), "lib32-zlib");
+        assert_eq!(ml.map_name("virtual/libc", "lib32-curl", "curl"), "virtual/lib32-libc");
+        assert_eq!(ml.map_name("lib32-zlib", "lib32-curl", "curl"), "lib32-zlib");
+        assert_eq!(ml.map_name("cmake-native", "lib32-curl", "curl"), "cmake-native");
+    }
+
+    #[test]
+    fn test_build_context() {
+        let base = BuildContext::default();
+        let native = ClassExtension::Native.build_context(&base);
+        assert_eq!(native.class, "native");
+        assert!(native.overrides.contains(&"virtclass-native".to_string()));
+
+        let ml = ClassExtension::Multilib("lib32".to_string()).build_context(&base);
+        assert_eq!(ml.class, "target");
+        assert!(ml.overrides.contains(&"virtclass-multilib-lib32".to_string()));
+    }
+}
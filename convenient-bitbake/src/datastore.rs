@@ -0,0 +1,1567 @@
+// BitBake datastore with variable history
+// Mirrors bb.data_smart: every assignment is kept as an operation with the
+// file and line it came from, overrides and :append/:prepend/:remove are
+// applied when a variable is read, and ${VAR} / ${@...} are expanded lazily.
+// Classes go through the same parser: nested inherit, inherit_defer (applied
+// by finalize()) and EXPORT_FUNCTIONS behave as in bb.parse. Anonymous python,
+// def functions, addhandler and addpylib are recorded for parse_lifecycle.
+// emit_var() renders the annotated output of `bitbake -e`.
+
+use crate::simple_python_eval::SimplePythonEvaluator;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+/// Nesting limit for ${VAR} expansion and include/inherit chains
+const MAX_DEPTH: usize = 64;
+
+/// Assignment operator of a recorded operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssignOp {
+    /// `=`
+    Set,
+    /// `:=` - the value is expanded when the line is parsed
+    Immediate,
+    /// `?=` - only if the variable has no value yet
+    WeakDefault,
+    /// `??=` - only if nothing else ever sets the variable
+    DefaultValue,
+    /// `+=` - append with a space
+    Append,
+    /// `=+` - prepend with a space
+    Prepend,
+    /// `.=` - append without a space
+    DotAppend,
+    /// `=.` - prepend without a space
+    DotPrepend,
+    /// `:append` - applied when the variable is read
+    OverrideAppend,
+    /// `:prepend` - applied when the variable is read
+    OverridePrepend,
+    /// `:remove` - applied to the expanded value
+    OverrideRemove,
+    /// `unset`
+    Unset,
+}
+
+impl AssignOp {
+    /// Parse an assignment operator ("=", "?=", "+=", ...)
+    pub fn from_operator(op: &str) -> Option<Self> {
+        match op {
+            "=" => Some(Self::Set),
+            ":=" => Some(Self::Immediate),
+            "?=" => Some(Self::WeakDefault),
+            "??=" => Some(Self::DefaultValue),
+            "+=" => Some(Self::Append),
+            "=+" => Some(Self::Prepend),
+            ".=" => Some(Self::DotAppend),
+            "=." => Some(Self::DotPrepend),
+            _ => None,
+        }
+    }
+
+    /// Operation name as shown in the `bitbake -e` history
+    pub fn history_name(self) -> &'static str {
+        match self {
+            Self::Set => "set",
+            Self::Immediate => "immediate",
+            Self::WeakDefault => "set?",
+            Self::DefaultValue => "set??",
+            Self::Append => "append",
+            Self::Prepend => "prepend",
+            Self::DotAppend => "postdot",
+            Self::DotPrepend => "predot",
+            Self::OverrideAppend => ":append",
+            Self::OverridePrepend => ":prepend",
+            Self::OverrideRemove => ":remove",
+            Self::Unset => "del",
+        }
+    }
+}
+
+/// One recorded operation on a variable or variable flag
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VarOperation {
+    /// Variable the operation applies to, without :append/:prepend/:remove
+    /// (e.g. "DEPENDS" or "DEPENDS:class-native")
+    pub key: String,
+    /// Flag name for `VAR[flag] = value`
+    pub flag: Option<String>,
+    /// Operator
+    pub op: AssignOp,
+    /// Overrides that must be active for :append/:prepend/:remove to apply
+    pub conditions: Vec<String>,
+    /// Value as written (`:=` records the expanded value)
+    pub value: String,
+    /// File the operation came from (None when set programmatically)
+    pub file: Option<PathBuf>,
+    /// 1-based line number (0 when not from a file)
+    pub line: usize,
+    /// Global ordering of operations
+    pub seq: usize,
+}
+
+impl VarOperation {
+    /// History label relative to variable `base`, e.g. "set",
+    /// ":append[class-target]" or "override[qemuarm]:set"
+    pub fn label(&self, base: &str) -> String {
+        let mut label = self.op.history_name().to_string();
+        if !self.conditions.is_empty() {
+            label = format!("{}[{}]", label, self.conditions.join(":"));
+        }
+        match self
+            .key
+            .strip_prefix(base)
+            .and_then(|s| s.strip_prefix(':'))
+        {
+            Some(suffix) => format!("override[{}]:{}", suffix, label),
+            None => label,
+        }
+    }
+
+    /// "file:line" of the operation, if it came from a file
+    pub fn location(&self) -> Option<String> {
+        self.file
+            .as_ref()
+            .map(|file| format!("{}:{}", file.display(), self.line))
+    }
+}
+
+/// Variable store with per-operation history, like BitBake's data_smart
+#[derive(Debug, Clone, Default)]
+pub struct DataStore {
+    /// Operations per variable key, in the order they were recorded
+    ops: HashMap<String, Vec<VarOperation>>,
+    /// Override-qualified keys by prefix ("DEPENDS" -> ["DEPENDS:class-native"])
+    override_keys: HashMap<String, Vec<String>>,
+    /// Extra search paths for include/require/inherit (BBPATH is used as well)
+    search_paths: Vec<PathBuf>,
+    /// Classes inherited so far, in order
+    inherits: Vec<String>,
+    /// Classes whose inherit is deferred until finalize()
+    deferred_inherits: Vec<String>,
+    /// Classes currently being parsed (for EXPORT_FUNCTIONS)
+    class_stack: Vec<String>,
+    /// addtask/deltask statements, expanded, in parse order
+    task_statements: Vec<String>,
+    /// Anonymous python functions (`python () {}`), by variable name, in parse order
+    anonymous_functions: Vec<String>,
+    /// Event handlers registered with addhandler, in registration order
+    event_handlers: Vec<String>,
+    /// Python library functions (`def ...:`) as source, in parse order
+    python_methods: Vec<String>,
+    /// addpylib directories and the namespace they provide
+    python_libs: Vec<(PathBuf, String)>,
+    /// Files currently being parsed (include recursion guard)
+    file_stack: Vec<PathBuf>,
+    /// Next operation sequence number
+    seq: usize,
+    /// Active overrides, computed from OVERRIDES on first use
+    overrides_cache: RefCell<Option<Vec<String>>>,
+    /// Expanded values already computed
+    expand_cache: RefCell<HashMap<String, Option<String>>>,
+    /// Set while OVERRIDES itself is being computed
+    computing_overrides: Cell<bool>,
+}
+
+impl DataStore {
+    /// Create an empty datastore
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a directory searched by include/require/inherit (in addition to BBPATH)
+    pub fn add_search_path(&mut self, path: impl Into<PathBuf>) {
+        let path = path.into();
+        if !self.search_paths.contains(&path) {
+            self.search_paths.push(path);
+        }
+    }
+
+    /// Classes inherited so far, in inheritance order
+    pub fn inherits(&self) -> &[String] {
+        &self.inherits
+    }
+
+    /// addtask/deltask statements seen so far (variables expanded), in parse order
+    pub fn task_statements(&self) -> &[String] {
+        &self.task_statements
+    }
+
+    /// Variable names of the anonymous python functions, in parse order
+    pub fn anonymous_functions(&self) -> &[String] {
+        &self.anonymous_functions
+    }
+
+    /// Functions registered with addhandler, in registration order
+    pub fn event_handlers(&self) -> &[String] {
+        &self.event_handlers
+    }
+
+    /// Source of the python library functions (`def ...:`), in parse order
+    pub fn python_methods(&self) -> &[String] {
+        &self.python_methods
+    }
+
+    /// addpylib entries: library directory and namespace
+    pub fn python_libs(&self) -> &[(PathBuf, String)] {
+        &self.python_libs
+    }
+
+    // === Setting ===
+
+    /// Set a variable programmatically (`name` may carry :append/:remove and overrides)
+    pub fn set_var(&mut self, name: &str, value: impl Into<String>) {
+        self.record(name, None, AssignOp::Set, value.into(), None, 0);
+    }
+
+    /// Set a variable flag programmatically
+    pub fn set_var_flag(&mut self, name: &str, flag: &str, value: impl Into<String>) {
+        self.record(name, Some(flag), AssignOp::Set, value.into(), None, 0);
+    }
+
+    /// Remove a variable and its flags
+    pub fn del_var(&mut self, name: &str) {
+        self.record(name, None, AssignOp::Unset, String::new(), None, 0);
+    }
+
+    /// Append to a variable without a space (`d.appendVar`)
+    pub fn append_var(&mut self, name: &str, value: impl Into<String>) {
+        self.record(name, None, AssignOp::DotAppend, value.into(), None, 0);
+    }
+
+    /// Prepend to a variable without a space (`d.prependVar`)
+    pub fn prepend_var(&mut self, name: &str, value: impl Into<String>) {
+        self.record(name, None, AssignOp::DotPrepend, value.into(), None, 0);
+    }
+
+    /// Append to a variable flag without a space (`d.appendVarFlag`)
+    pub fn append_var_flag(&mut self, name: &str, flag: &str, value: impl Into<String>) {
+        self.record(name, Some(flag), AssignOp::DotAppend, value.into(), None, 0);
+    }
+
+    /// Prepend to a variable flag without a space (`d.prependVarFlag`)
+    pub fn prepend_var_flag(&mut self, name: &str, flag: &str, value: impl Into<String>) {
+        self.record(name, Some(flag), AssignOp::DotPrepend, value.into(), None, 0);
+    }
+
+    /// Remove a single variable flag
+    pub fn del_var_flag(&mut self, name: &str, flag: &str) {
+        self.record(name, Some(flag), AssignOp::Unset, String::new(), None, 0);
+    }
+
+    /// Record an addtask/deltask statement made at runtime (`bb.build.addtask`)
+    pub fn add_task_statement(&mut self, statement: impl Into<String>) {
+        self.task_statements.push(statement.into());
+    }
+
+    /// Record one operation; the variable name is split into key, operation and conditions
+    fn record(
+        &mut self,
+        name: &str,
+        flag: Option<&str>,
+        op: AssignOp,
+        value: String,
+        file: Option<&Path>,
+        line: usize,
+    ) {
+        let name = if name.contains("${") {
+            self.expand(name)
+        } else {
+            name.to_string()
+        };
+
+        let parts: Vec<&str> = name.split(':').collect();
+        let (key, op, conditions) = match parts
+            .iter()
+            .position(|p| matches!(*p, "append" | "prepend" | "remove"))
+        {
+            Some(idx) if idx > 0 && flag.is_none() => {
+                let op = match parts[idx] {
+                    "append" => AssignOp::OverrideAppend,
+                    "prepend" => AssignOp::OverridePrepend,
+                    _ => AssignOp::OverrideRemove,
+                };
+                let conditions = parts[idx + 1..].iter().map(|s| (*s).to_string()).collect();
+                (parts[..idx].join(":"), op, conditions)
+            }
+            _ => (name.clone(), op, Vec::new()),
+        };
+
+        let value = if op == AssignOp::Immediate {
+            self.expand(&value)
+        } else {
+            value
+        };
+
+        // Register override-qualified keys under every prefix
+        let key_parts: Vec<&str> = key.split(':').collect();
+        for i in 1..key_parts.len() {
+            let prefix = key_parts[..i].join(":");
+            let keys = self.override_keys.entry(prefix).or_default();
+            if !keys.contains(&key) {
+                keys.push(key.clone());
+            }
+        }
+
+        self.seq += 1;
+        self.ops.entry(key.clone()).or_default().push(VarOperation {
+            key,
+            flag: flag.map(str::to_string),
+            op,
+            conditions,
+            value,
+            file: file.map(Path::to_path_buf),
+            line,
+            seq: self.seq,
+        });
+
+        *self.overrides_cache.borrow_mut() = None;
+        self.expand_cache.borrow_mut().clear();
+    }
+
+    // === Parsing ===
+
+    /// Parse a .bb, .bbappend, .bbclass, .inc or .conf file into the datastore
+    pub fn parse_file(&mut self, path: &Path) -> Result<(), String> {
+        if self.file_stack.iter().any(|p| p == path) {
+            return Err(format!("Recursive include of {}", path.display()));
+        }
+        if self.file_stack.len() >= MAX_DEPTH {
+            return Err(format!("Include depth exceeded at {}", path.display()));
+        }
+
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+        self.file_stack.push(path.to_path_buf());
+        let result = self.parse_content(&content, path);
+        self.file_stack.pop();
+        result
+    }
+
+    /// Parse BitBake content, recording operations against `file`
+    pub fn parse_content(&mut self, content: &str, file: &Path) -> Result<(), String> {
+        let lines: Vec<&str> = content.lines().collect();
+        let mut i = 0;
+
+        while i < lines.len() {
+            let start = i;
+            let trimmed = lines[i].trim();
+            i += 1;
+
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+
+            // Shell and python functions: body runs until a closing "}" line
+            if let Some((name, python)) = parse_function_header(trimmed) {
+                let body_start = i;
+                while i < lines.len() && lines[i].trim_end() != "}" {
+                    i += 1;
+                }
+                let body = lines[body_start..i].join("\n");
+                i += 1;
+
+                // Anonymous python is stored like BitBake does, as __anon_<line>_<file>
+                let name = name.or_else(|| {
+                    python.then(|| {
+                        let path: String = file
+                            .display()
+                            .to_string()
+                            .chars()
+                            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+                            .collect();
+                        let name = format!("__anon_{}_{}", start + 1, path);
+                        self.anonymous_functions.push(name.clone());
+                        name
+                    })
+                });
+
+                if let Some(name) = name {
+                    self.record(&name, None, AssignOp::Set, body, Some(file), start + 1);
+                    self.record(
+                        &name,
+                        Some("func"),
+                        AssignOp::Set,
+                        "1".to_string(),
+                        Some(file),
+                        start + 1,
+                    );
+                    if python {
+                        self.record(
+                            &name,
+                            Some("python"),
+                            AssignOp::Set,
+                            "1".to_string(),
+                            Some(file),
+                            start + 1,
+                        );
+                    }
+                    // A real definition replaces an exported class function
+                    if self.flag_value(&name, "export_func").is_some() {
+                        self.record(
+                            &name,
+                            Some("export_func"),
+                            AssignOp::Unset,
+                            String::new(),
+                            Some(file),
+                            start + 1,
+                        );
+                    }
+                }
+                continue;
+            }
+
+            // Python library functions (def ...:) extend until the next unindented line
+            if trimmed.starts_with("def ") {
+                while i < lines.len()
+                    && (lines[i].trim().is_empty() || lines[i].starts_with(char::is_whitespace))
+                {
+                    i += 1;
+                }
+                let source = lines[start..i].join("\n");
+                self.python_methods.push(source.trim_end().to_string());
+                continue;
+            }
+
+            let mut statement = trimmed.to_string();
+            while statement.ends_with('\\') && i < lines.len() {
+                statement.pop();
+                statement.push_str(lines[i]);
+                i += 1;
+            }
+
+            self.parse_statement(statement.trim(), file, start + 1)?;
+        }
+
+        Ok(())
+    }
+
+    fn parse_statement(&mut self, statement: &str, file: &Path, line: usize) -> Result<(), String> {
+        let (keyword, rest) = statement
+            .split_once(char::is_whitespace)
+            .map_or((statement, ""), |(k, r)| (k, r.trim()));
+
+        match keyword {
+            "include" | "include_all" => return self.include(rest, false, file),
+            "require" => return self.include(rest, true, file),
+            "inherit" => {
+                for class in self.expand(rest).split_whitespace() {
+                    if class.contains("${") {
+                        debug!(
+                            "{}:{}: skipping unexpanded inherit {}",
+                            file.display(),
+                            line,
+                            class
+                        );
+                        continue;
+                    }
+                    self.inherit(class)?;
+                }
+                return Ok(());
+            }
+            "inherit_defer" => {
+                let classes = self.expand(rest);
+                self.deferred_inherits.extend(
+                    classes
+                        .split_whitespace()
+                        .filter(|c| !c.contains("${"))
+                        .map(str::to_string),
+                );
+                return Ok(());
+            }
+            "addtask" | "deltask" => {
+                let statement = format!("{} {}", keyword, self.expand(rest));
+                self.task_statements.push(statement);
+                return Ok(());
+            }
+            "EXPORT_FUNCTIONS" => {
+                self.export_functions(rest, file, line);
+                return Ok(());
+            }
+            "unset" => {
+                let (name, flag) = split_flag(rest);
+                self.record(name, flag, AssignOp::Unset, String::new(), Some(file), line);
+                return Ok(());
+            }
+            "export" => {
+                if let Some((name, flag, op, value)) = parse_assignment(rest) {
+                    self.record(name, flag, op, value, Some(file), line);
+                    self.record(
+                        name,
+                        Some("export"),
+                        AssignOp::Set,
+                        "1".to_string(),
+                        Some(file),
+                        line,
+                    );
+                } else if !rest.is_empty() {
+                    self.record(
+                        rest,
+                        Some("export"),
+                        AssignOp::Set,
+                        "1".to_string(),
+                        Some(file),
+                        line,
+                    );
+                }
+                return Ok(());
+            }
+            "addhandler" => {
+                for handler in self.expand(rest).split_whitespace() {
+                    if !self.event_handlers.iter().any(|h| h == handler) {
+                        self.event_handlers.push(handler.to_string());
+                    }
+                }
+                return Ok(());
+            }
+            "addpylib" => {
+                let expanded = self.expand(rest);
+                let mut parts = expanded.split_whitespace();
+                if let (Some(dir), Some(namespace)) = (parts.next(), parts.next()) {
+                    let entry = (PathBuf::from(dir), namespace.to_string());
+                    if !self.python_libs.contains(&entry) {
+                        self.python_libs.push(entry);
+                    }
+                }
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        if let Some((name, flag, op, value)) = parse_assignment(statement) {
+            self.record(name, flag, op, value, Some(file), line);
+        }
+
+        Ok(())
+    }
+
+    /// include/require: relative paths are tried next to the current file, then in BBPATH
+    fn include(&mut self, target: &str, required: bool, current: &Path) -> Result<(), String> {
+        let target = self.expand(target);
+        let target = target.trim();
+        if target.is_empty() {
+            return Ok(());
+        }
+
+        let candidate = Path::new(target);
+        let found = if candidate.is_absolute() {
+            candidate.exists().then(|| candidate.to_path_buf())
+        } else {
+            current
+                .parent()
+                .into_iter()
+                .map(Path::to_path_buf)
+                .chain(self.bbpath())
+                .map(|dir| dir.join(candidate))
+                .find(|path| path.exists())
+        };
+
+        match found {
+            Some(path) => self.parse_file(&path),
+            None if required => Err(format!(
+                "{}: Could not include required file {}",
+                current.display(),
+                target
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// inherit: classes are looked up in classes-recipe, classes-global and classes of BBPATH
+    pub fn inherit(&mut self, class: &str) -> Result<(), String> {
+        let name = class.trim_end_matches(".bbclass");
+        let name = name.rsplit('/').next().unwrap_or(name);
+        if self.inherits.iter().any(|c| c == name) {
+            return Ok(());
+        }
+
+        let file_name = format!("{}.bbclass", name);
+        let path = self
+            .bbpath()
+            .into_iter()
+            .flat_map(|dir| {
+                ["classes-recipe", "classes-global", "classes"]
+                    .map(|sub| dir.join(sub).join(&file_name))
+            })
+            .find(|path| path.exists())
+            .ok_or_else(|| format!("Could not inherit file classes/{}", file_name))?;
+
+        self.inherits.push(name.to_string());
+        self.class_stack.push(name.to_string());
+        let result = self.parse_file(&path);
+        self.class_stack.pop();
+        result
+    }
+
+    /// Inherit the classes listed in INHERIT, as BitBake does for every recipe
+    pub fn inherit_global_classes(&mut self) -> Result<(), String> {
+        let classes = self.get_var("INHERIT").unwrap_or_default();
+        for class in classes.split_whitespace().filter(|c| !c.contains("${")) {
+            self.inherit(class)?;
+        }
+        Ok(())
+    }
+
+    /// Apply deferred inherits (inherit_defer); call once the recipe and its
+    /// bbappends are parsed
+    pub fn finalize(&mut self) -> Result<(), String> {
+        while !self.deferred_inherits.is_empty() {
+            for class in std::mem::take(&mut self.deferred_inherits) {
+                self.inherit(&class)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// EXPORT_FUNCTIONS: `do_configure` becomes a call to `<class>_do_configure`
+    /// unless something other than an exported function already defines it
+    fn export_functions(&mut self, functions: &str, file: &Path, line: usize) {
+        let Some(class) = self.class_stack.last().cloned() else {
+            debug!(
+                "{}:{}: EXPORT_FUNCTIONS outside a class",
+                file.display(),
+                line
+            );
+            return;
+        };
+
+        for function in functions.split_whitespace() {
+            let called = format!("{}_{}", class, function);
+            if self.raw_value(function).is_some()
+                && self.flag_value(function, "export_func").is_none()
+            {
+                continue;
+            }
+
+            let python = self.flag_value(&called, "python").is_some_and(|v| v == "1");
+            let body = if python {
+                format!("    bb.build.exec_func('{}', d)", called)
+            } else {
+                format!("\t{}", called)
+            };
+
+            self.record(function, None, AssignOp::Set, body, Some(file), line);
+            self.record(
+                function,
+                Some("func"),
+                AssignOp::Set,
+                "1".to_string(),
+                Some(file),
+                line,
+            );
+            if python {
+                self.record(
+                    function,
+                    Some("python"),
+                    AssignOp::Set,
+                    "1".to_string(),
+                    Some(file),
+                    line,
+                );
+            } else if self.flag_value(function, "python").is_some() {
+                self.record(
+                    function,
+                    Some("python"),
+                    AssignOp::Unset,
+                    String::new(),
+                    Some(file),
+                    line,
+                );
+            }
+            self.record(
+                function,
+                Some("export_func"),
+                AssignOp::Set,
+                "1".to_string(),
+                Some(file),
+                line,
+            );
+        }
+    }
+
+    /// Class function an exported task calls (`do_configure` -> `autotools_do_configure`)
+    pub fn exported_function(&self, function: &str) -> Option<String> {
+        self.flag_value(function, "export_func")?;
+        let body = self.raw_value(function)?;
+        let body = body.trim();
+        match body.strip_prefix("bb.build.exec_func('") {
+            Some(rest) => rest.split('\'').next().map(str::to_string),
+            None => Some(body.to_string()),
+        }
+    }
+
+    /// Search path: explicit search paths followed by the entries of BBPATH
+    pub fn bbpath(&self) -> Vec<PathBuf> {
+        let mut paths = self.search_paths.clone();
+        if let Some(bbpath) = self.get_var("BBPATH") {
+            for entry in bbpath
+                .split(':')
+                .filter(|e| !e.is_empty() && !e.contains("${"))
+            {
+                let entry = PathBuf::from(entry);
+                if !paths.contains(&entry) {
+                    paths.push(entry);
+                }
+            }
+        }
+        paths
+    }
+
+    // === Reading ===
+
+    /// All variable keys, sorted
+    pub fn keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self.ops.keys().cloned().collect();
+        keys.sort();
+        keys
+    }
+
+    /// Whether the variable has a value
+    pub fn contains(&self, name: &str) -> bool {
+        self.get_var_unexpanded(name).is_some()
+    }
+
+    /// Final value of a variable with overrides, :append/:prepend, :remove and expansion applied
+    pub fn get_var(&self, name: &str) -> Option<String> {
+        let mut stack = Vec::new();
+        self.get_var_inner(name, &mut stack)
+    }
+
+    /// Value after overrides and :append/:prepend, before expansion and :remove
+    pub fn get_var_unexpanded(&self, name: &str) -> Option<String> {
+        let mut value = self.raw_value(name);
+
+        if !self.computing_overrides.get()
+            && let Some(override_key) = self.select_override(name)
+            && let Some(override_value) = self.get_var_unexpanded(&override_key)
+        {
+            value = Some(override_value);
+        }
+
+        for op in self.ops.get(name).into_iter().flatten() {
+            if op.flag.is_some() || !self.conditions_active(&op.conditions) {
+                continue;
+            }
+            match op.op {
+                AssignOp::OverrideAppend => {
+                    value = Some(format!("{}{}", value.unwrap_or_default(), op.value));
+                }
+                AssignOp::OverridePrepend => {
+                    value = Some(format!("{}{}", op.value, value.unwrap_or_default()));
+                }
+                AssignOp::Unset => value = None,
+                _ => {}
+            }
+        }
+
+        value
+    }
+
+    /// Expanded value of a variable flag
+    pub fn get_var_flag(&self, name: &str, flag: &str) -> Option<String> {
+        self.flag_value(name, flag).map(|v| self.expand(&v))
+    }
+
+    /// All flags of a variable, unexpanded
+    pub fn get_var_flags(&self, name: &str) -> BTreeMap<String, String> {
+        let mut names: Vec<&str> = self
+            .ops
+            .get(name)
+            .into_iter()
+            .flatten()
+            .filter_map(|op| op.flag.as_deref())
+            .collect();
+        names.dedup();
+
+        names
+            .into_iter()
+            .filter_map(|flag| self.flag_value(name, flag).map(|v| (flag.to_string(), v)))
+            .collect()
+    }
+
+    /// Values of the :remove operations that apply to a variable, unexpanded
+    pub fn removes(&self, name: &str) -> Vec<String> {
+        self.ops
+            .get(name)
+            .into_iter()
+            .flatten()
+            .filter(|op| {
+                op.op == AssignOp::OverrideRemove
+                    && op.flag.is_none()
+                    && self.conditions_active(&op.conditions)
+            })
+            .map(|op| op.value.clone())
+            .collect()
+    }
+
+    /// Expand ${VAR} references and ${@...} python expressions
+    pub fn expand(&self, input: &str) -> String {
+        let mut stack = Vec::new();
+        self.expand_inner(input, &mut stack)
+    }
+
+    /// All operations recorded for a variable, including override-qualified
+    /// variants and flags, in the order they happened
+    pub fn history(&self, name: &str) -> Vec<&VarOperation> {
+        let mut history: Vec<&VarOperation> = self.ops.get(name).into_iter().flatten().collect();
+        for key in self.override_keys.get(name).into_iter().flatten() {
+            history.extend(
+                self.ops
+                    .get(key)
+                    .into_iter()
+                    .flatten()
+                    .filter(|op| op.flag.is_none()),
+            );
+        }
+        history.sort_by_key(|op| op.seq);
+        history
+    }
+
+    /// Overrides currently active, from OVERRIDES (later entries take precedence)
+    pub fn active_overrides(&self) -> Vec<String> {
+        if let Some(cached) = self.overrides_cache.borrow().as_ref() {
+            return cached.clone();
+        }
+        if self.computing_overrides.get() {
+            return Vec::new();
+        }
+
+        self.computing_overrides.set(true);
+        let overrides: Vec<String> = self
+            .get_var("OVERRIDES")
+            .unwrap_or_default()
+            .split(':')
+            .map(str::trim)
+            .filter(|o| !o.is_empty() && !o.contains("${"))
+            .map(str::to_string)
+            .collect();
+        self.computing_overrides.set(false);
+        self.expand_cache.borrow_mut().clear();
+
+        *self.overrides_cache.borrow_mut() = Some(overrides.clone());
+        overrides
+    }
+
+    fn get_var_inner(&self, name: &str, stack: &mut Vec<String>) -> Option<String> {
+        let cacheable = !self.computing_overrides.get();
+        if cacheable && let Some(cached) = self.expand_cache.borrow().get(name) {
+            return cached.clone();
+        }
+
+        let raw = self.get_var_unexpanded(name)?;
+        stack.push(name.to_string());
+        let mut value = self.expand_inner(&raw, stack);
+        stack.pop();
+
+        let removes: Vec<String> = self
+            .ops
+            .get(name)
+            .into_iter()
+            .flatten()
+            .filter(|op| {
+                op.op == AssignOp::OverrideRemove
+                    && op.flag.is_none()
+                    && self.conditions_active(&op.conditions)
+            })
+            .flat_map(|op| {
+                self.expand_inner(&op.value, stack)
+                    .split_whitespace()
+                    .map(str::to_string)
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        if !removes.is_empty() {
+            value = value
+                .split_whitespace()
+                .filter(|item| !removes.iter().any(|r| r == item))
+                .collect::<Vec<_>>()
+                .join(" ");
+        }
+
+        if cacheable {
+            self.expand_cache
+                .borrow_mut()
+                .insert(name.to_string(), Some(value.clone()));
+        }
+        Some(value)
+    }
+
+    /// Value from plain assignments only (=, ?=, ??=, +=, .=, ...)
+    fn raw_value(&self, key: &str) -> Option<String> {
+        let mut value: Option<String> = None;
+        let mut default: Option<String> = None;
+
+        for op in self.ops.get(key).into_iter().flatten() {
+            if op.flag.is_some() {
+                continue;
+            }
+            apply_assignment(op, &mut value, &mut default);
+        }
+
+        value.or(default)
+    }
+
+    fn flag_value(&self, key: &str, flag: &str) -> Option<String> {
+        let mut value: Option<String> = None;
+        let mut default: Option<String> = None;
+
+        for op in self.ops.get(key).into_iter().flatten() {
+            match op.flag.as_deref() {
+                Some(f) if f == flag => apply_assignment(op, &mut value, &mut default),
+                None if op.op == AssignOp::Unset => {
+                    value = None;
+                    default = None;
+                }
+                _ => {}
+            }
+        }
+
+        value.or(default)
+    }
+
+    /// Pick the active override-qualified variant with the highest precedence
+    fn select_override(&self, name: &str) -> Option<String> {
+        let candidates = self.override_keys.get(name)?;
+        let active = self.active_overrides();
+
+        let mut best: Option<((usize, usize), &String)> = None;
+        for candidate in candidates {
+            let suffix: Vec<&str> = candidate[name.len() + 1..].split(':').collect();
+            let positions: Option<Vec<usize>> = suffix
+                .iter()
+                .map(|o| active.iter().position(|a| a == o))
+                .collect();
+            let Some(positions) = positions else {
+                continue;
+            };
+
+            let rank = (positions.iter().copied().max().unwrap_or(0), suffix.len());
+            if best
+                .as_ref()
+                .is_none_or(|(best_rank, _)| rank >= *best_rank)
+            {
+                best = Some((rank, candidate));
+            }
+        }
+
+        best.map(|(_, key)| key.clone())
+    }
+
+    fn conditions_active(&self, conditions: &[String]) -> bool {
+        if conditions.is_empty() {
+            return true;
+        }
+        if self.computing_overrides.get() {
+            return false;
+        }
+        let active = self.active_overrides();
+        conditions.iter().all(|c| active.contains(c))
+    }
+
+    fn expand_inner(&self, input: &str, stack: &mut Vec<String>) -> String {
+        let mut out = String::with_capacity(input.len());
+        let mut rest = input;
+
+        while let Some(pos) = rest.find("${") {
+            out.push_str(&rest[..pos]);
+            let after = &rest[pos + 2..];
+            let Some(end) = find_closing_brace(after) else {
+                out.push_str(&rest[pos..]);
+                return out;
+            };
+
+            let inner = &after[..end];
+            let replacement = if stack.len() >= MAX_DEPTH {
+                None
+            } else if let Some(code) = inner.strip_prefix('@') {
+                self.eval_python(code, stack)
+            } else {
+                let name = self.expand_inner(inner, stack);
+                if name.is_empty() || name.contains(char::is_whitespace) || stack.contains(&name) {
+                    None
+                } else {
+                    self.get_var_inner(&name, stack)
+                }
+            };
+
+            match replacement {
+                Some(value) => out.push_str(&value),
+                None => out.push_str(&rest[pos..pos + 2 + end + 1]),
+            }
+            rest = &after[end + 1..];
+        }
+
+        out.push_str(rest);
+        out
+    }
+
+    /// Evaluate ${@...} with the variables it names
+    fn eval_python(&self, code: &str, stack: &mut Vec<String>) -> Option<String> {
+        let code = self.expand_inner(code, stack);
+
+        let mut variables = HashMap::new();
+        for name in quoted_words(&code) {
+            if !variables.contains_key(&name)
+                && !stack.contains(&name)
+                && let Some(value) = self.get_var_inner(&name, stack)
+            {
+                variables.insert(name, value);
+            }
+        }
+
+        SimplePythonEvaluator::new(variables).evaluate(&code)
+    }
+
+    // === bitbake -e output ===
+
+    /// Render one variable like `bitbake -e`: annotated history, the
+    /// pre-expansion value and the final assignment
+    pub fn emit_var(&self, name: &str) -> String {
+        let mut out = String::new();
+        let history = self.history(name);
+
+        let _ = writeln!(out, "#");
+        let _ = writeln!(out, "# ${} [{} operations]", name, history.len());
+        for op in &history {
+            match op.location() {
+                Some(location) => {
+                    let _ = writeln!(out, "#   {} {}", op.label(name), location);
+                }
+                None => {
+                    let _ = writeln!(out, "#   {}", op.label(name));
+                }
+            }
+            let value = op.value.replace('\n', "\n#     ");
+            match &op.flag {
+                Some(flag) => {
+                    let _ = writeln!(out, "#     [{}] \"{}\"", flag, value);
+                }
+                None => {
+                    let _ = writeln!(out, "#     \"{}\"", value);
+                }
+            }
+        }
+
+        let raw = self.get_var_unexpanded(name);
+        let _ = writeln!(out, "# pre-expansion value:");
+        match &raw {
+            Some(raw) => {
+                let _ = writeln!(out, "#   \"{}\"", raw.replace('\n', "\n#   "));
+            }
+            None => {
+                let _ = writeln!(out, "#   None");
+            }
+        }
+
+        if raw.is_none() {
+            return out;
+        }
+
+        let flags = self.get_var_flags(name);
+        let value = self.get_var(name).unwrap_or_default();
+        if flags.get("func").is_some_and(|f| f == "1") {
+            let python = flags.get("python").is_some_and(|f| f == "1");
+            let _ = writeln!(out, "{}{}() {{", if python { "python " } else { "" }, name);
+            let _ = writeln!(out, "{}", value);
+            let _ = writeln!(out, "}}");
+        } else {
+            let export = flags.get("export").is_some_and(|f| f == "1");
+            let _ = writeln!(
+                out,
+                "{}{}=\"{}\"",
+                if export { "export " } else { "" },
+                name,
+                value.replace('"', "\\\"")
+            );
+        }
+
+        out
+    }
+
+    /// Render all variables like `bitbake -e` (functions last, internal `__` keys hidden)
+    pub fn emit(&self) -> String {
+        let keys: Vec<String> = self
+            .keys()
+            .into_iter()
+            .filter(|k| !k.starts_with("__"))
+            .collect();
+        let is_func = |key: &String| self.flag_value(key, "func").is_some_and(|f| f == "1");
+
+        let mut out = String::new();
+        for key in keys.iter().filter(|k| !is_func(k)) {
+            out.push_str(&self.emit_var(key));
+        }
+        for key in keys.iter().filter(|k| is_func(k)) {
+            out.push_str(&self.emit_var(key));
+        }
+        out
+    }
+}
+
+/// Apply a plain assignment in parse order
+///
+/// The `??=` default only applies when nothing else sets the variable, so
+/// appends and prepends build on the set value alone, as in BitBake.
+fn apply_assignment(op: &VarOperation, value: &mut Option<String>, default: &mut Option<String>) {
+    let current = || value.clone().unwrap_or_default();
+    match op.op {
+        AssignOp::Set | AssignOp::Immediate => *value = Some(op.value.clone()),
+        AssignOp::WeakDefault => {
+            if value.is_none() {
+                *value = Some(op.value.clone());
+            }
+        }
+        AssignOp::DefaultValue => *default = Some(op.value.clone()),
+        AssignOp::Append => *value = Some(format!("{} {}", current(), op.value)),
+        AssignOp::Prepend => *value = Some(format!("{} {}", op.value, current())),
+        AssignOp::DotAppend => *value = Some(format!("{}{}", current(), op.value)),
+        AssignOp::DotPrepend => *value = Some(format!("{}{}", op.value, current())),
+        AssignOp::Unset => {
+            *value = None;
+            *default = None;
+        }
+        AssignOp::OverrideAppend | AssignOp::OverridePrepend | AssignOp::OverrideRemove => {}
+    }
+}
+
+/// Parse `NAME[flag] <op> "value"` into (name, flag, operator, unquoted value)
+fn parse_assignment(line: &str) -> Option<(&str, Option<&str>, AssignOp, String)> {
+    let bytes = line.as_bytes();
+    let mut depth = 0usize;
+    let mut eq = None;
+    for (i, &b) in bytes.iter().enumerate() {
+        match b {
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' => depth = depth.saturating_sub(1),
+            b'"' | b'\'' if depth == 0 => return None,
+            b'=' if depth == 0 => {
+                eq = Some(i);
+                break;
+            }
+            _ => {}
+        }
+    }
+    let eq = eq?;
+
+    let (op_start, op_end) = if line[..eq].ends_with("??") {
+        (eq - 2, eq + 1)
+    } else if line[..eq].ends_with(['?', ':', '+', '.']) {
+        (eq - 1, eq + 1)
+    } else if line[eq + 1..].starts_with(['+', '.']) {
+        (eq, eq + 2)
+    } else {
+        (eq, eq + 1)
+    };
+
+    let op = AssignOp::from_operator(&line[op_start..op_end])?;
+    let target = line[..op_start].trim();
+    if target.is_empty() || target.contains(char::is_whitespace) {
+        return None;
+    }
+
+    let (name, flag) = split_flag(target);
+    Some((name, flag, op, unquote(line[op_end..].trim()).to_string()))
+}
+
+/// Split `NAME[flag]` into its parts
+fn split_flag(target: &str) -> (&str, Option<&str>) {
+    match target.split_once('[') {
+        Some((name, flag)) if flag.ends_with(']') => (name, Some(&flag[..flag.len() - 1])),
+        _ => (target, None),
+    }
+}
+
+fn unquote(value: &str) -> &str {
+    for quote in ['"', '\''] {
+        if value.len() >= 2 && value.starts_with(quote) && value.ends_with(quote) {
+            return &value[1..value.len() - 1];
+        }
+    }
+    value
+}
+
+/// Recognise `[fakeroot] [python] NAME() {`; returns the name (None for anonymous python)
+fn parse_function_header(line: &str) -> Option<(Option<String>, bool)> {
+    let rest = line.strip_prefix("fakeroot ").unwrap_or(line).trim_start();
+    let (python, rest) = match rest.strip_prefix("python") {
+        Some(after) if after.starts_with([' ', '(']) => (true, after.trim_start()),
+        _ => (false, rest),
+    };
+
+    let (name, tail) = rest.split_once('(')?;
+    let tail = tail.trim_start().strip_prefix(')')?.trim();
+    if tail != "{" {
+        return None;
+    }
+
+    let name = name.trim();
+    if name.contains(char::is_whitespace) || (!python && name.is_empty()) {
+        return None;
+    }
+    if name.is_empty() || name == "__anonymous" {
+        return Some((None, python));
+    }
+    Some((Some(name.to_string()), python))
+}
+
+/// Index of the `}` closing an expansion, allowing nested braces
+fn find_closing_brace(s: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, c) in s.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' if depth == 0 => return Some(i),
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Quoted words that look like variable names ('DISTRO_FEATURES', "PN")
+fn quoted_words(code: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    for quote in ['\'', '"'] {
+        for (i, part) in code.split(quote).enumerate() {
+            if i % 2 == 1
+                && !part.is_empty()
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | ':'))
+                && part
+                    .chars()
+                    .next()
+                    .is_some_and(|c| c.is_ascii_uppercase() || c == '_')
+            {
+                words.push(part.to_string());
+            }
+        }
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn parse(content: &str) -> DataStore {
+        let mut d = DataStore::new();
+        d.parse_content(content, Path::new("/layer/recipe.bb"))
+            .unwrap();
+        d
+    }
+
+    #[test]
+    fn test_operators() {
+        let d = parse(
+            r#"
+A = "a"
+A += "b"
+A =+ "z"
+A .= "c"
+A =. "y"
+B ?= "first"
+B ?= "second"
+C ??= "default"
+D ??= "default"
+D = "strong"
+E ??= "x"
+E += "y"
+"#,
+        );
+        assert_eq!(d.get_var("A").as_deref(), Some("yz a bc"));
+        assert_eq!(d.get_var("B").as_deref(), Some("first"));
+        assert_eq!(d.get_var("C").as_deref(), Some("default"));
+        assert_eq!(d.get_var("D").as_deref(), Some("strong"));
+        assert_eq!(d.get_var("E").as_deref(), Some(" y"));
+        assert_eq!(d.get_var("MISSING"), None);
+    }
+
+    #[test]
+    fn test_overrides_append_remove() {
+        let d = parse(
+            r#"
+OVERRIDES = "linux:${MACHINE}:class-target"
+MACHINE = "qemuarm"
+DEPENDS = "zlib openssl"
+DEPENDS:append = " curl"
+DEPENDS:append:class-native = " native-only"
+DEPENDS:prepend:qemuarm = "arm-tool "
+DEPENDS:remove = "openssl"
+CFLAGS = "-O2"
+CFLAGS:qemuarm = "-O1"
+CFLAGS:linux = "-Os"
+"#,
+        );
+        assert_eq!(
+            d.active_overrides(),
+            vec!["linux", "qemuarm", "class-target"]
+        );
+        assert_eq!(d.get_var("DEPENDS").as_deref(), Some("arm-tool zlib curl"));
+        // qemuarm comes later in OVERRIDES than linux, so it wins
+        assert_eq!(d.get_var("CFLAGS").as_deref(), Some("-O1"));
+        assert_eq!(
+            d.get_var_unexpanded("DEPENDS").as_deref(),
+            Some("arm-tool zlib openssl curl")
+        );
+    }
+
+    #[test]
+    fn test_expansion_and_immediate() {
+        let d = parse(
+            r#"
+PN = "foo"
+BPN = "${PN}"
+PV = "0.9"
+LATE = "${PV}"
+NOW := "${PV}"
+PV = "1.0"
+S = "${WORKDIR}/${BPN}-${PV}"
+WORKDIR = "/work"
+DISTRO_FEATURES = "systemd x11"
+INIT = "${@bb.utils.contains('DISTRO_FEATURES', 'systemd', 'systemd', 'sysvinit', d)}"
+SELF = "${SELF} x"
+"#,
+        );
+        assert_eq!(d.get_var("S").as_deref(), Some("/work/foo-1.0"));
+        assert_eq!(d.get_var("LATE").as_deref(), Some("1.0"));
+        assert_eq!(d.get_var("NOW").as_deref(), Some("0.9"));
+        assert_eq!(d.get_var("INIT").as_deref(), Some("systemd"));
+        assert_eq!(d.get_var("SELF").as_deref(), Some("${SELF} x"));
+        assert_eq!(d.expand("${UNDEFINED}/${PN}"), "${UNDEFINED}/foo");
+    }
+
+    #[test]
+    fn test_flags_functions_and_export() {
+        let d = parse(
+            r#"
+do_compile[depends] = "virtual/kernel:do_shared_workdir"
+do_compile[depends] += "zlib:do_populate_sysroot"
+SRC_URI[sha256sum] = "abc"
+export CC = "gcc"
+do_install() {
+    install -d ${D}${bindir}
+}
+python do_greet() {
+    bb.note("hi")
+}
+python () {
+    d.setVar("IGNORED", "1")
+}
+unset SRC_URI[sha256sum]
+"#,
+        );
+        assert_eq!(
+            d.get_var_flag("do_compile", "depends").as_deref(),
+            Some("virtual/kernel:do_shared_workdir zlib:do_populate_sysroot")
+        );
+        assert_eq!(d.get_var_flag("SRC_URI", "sha256sum"), None);
+        assert_eq!(d.get_var_flag("CC", "export").as_deref(), Some("1"));
+        assert_eq!(d.get_var_flag("do_install", "func").as_deref(), Some("1"));
+        assert_eq!(d.get_var_flag("do_greet", "python").as_deref(), Some("1"));
+        assert_eq!(
+            d.get_var_unexpanded("do_install").as_deref(),
+            Some("    install -d ${D}${bindir}")
+        );
+        assert!(!d.contains("IGNORED"));
+        assert!(d.emit_var("CC").ends_with("export CC=\"gcc\"\n"));
+    }
+
+    #[test]
+    fn test_anonymous_python_handlers_and_pylib() {
+        let mut d = parse(
+            r#"
+LAYERDIR = "/layer"
+addpylib ${LAYERDIR}/lib oe
+python () {
+    d.setVar("FOO", "1")
+}
+python __anonymous () {
+    pass
+}
+def helper(d):
+    return d.getVar("PN")
+
+python check_handler() {
+    pass
+}
+check_handler[eventmask] = "bb.event.RecipePreFinalise"
+addhandler check_handler
+"#,
+        );
+        assert_eq!(
+            d.anonymous_functions(),
+            &["__anon_4__layer_recipe_bb", "__anon_7__layer_recipe_bb"]
+        );
+        assert_eq!(
+            d.get_var_flag("__anon_4__layer_recipe_bb", "python").as_deref(),
+            Some("1")
+        );
+        assert_eq!(
+            d.python_methods(),
+            &["def helper(d):\n    return d.getVar(\"PN\")"]
+        );
+        assert_eq!(d.event_handlers(), &["check_handler"]);
+        assert_eq!(
+            d.python_libs(),
+            &[(PathBuf::from("/layer/lib"), "oe".to_string())]
+        );
+        assert!(!d.emit().contains("__anon"));
+
+        d.set_var("CFLAGS", "-O2");
+        d.append_var("CFLAGS", " -g");
+        d.prepend_var("CFLAGS", "-pipe ");
+        assert_eq!(d.get_var("CFLAGS").as_deref(), Some("-pipe -O2 -g"));
+        d.set_var_flag("do_compile", "depends", "a:do_x");
+        d.append_var_flag("do_compile", "depends", " b:do_y");
+        assert_eq!(
+            d.get_var_flag("do_compile", "depends").as_deref(),
+            Some("a:do_x b:do_y")
+        );
+        d.del_var_flag("do_compile", "depends");
+        assert_eq!(d.get_var_flag("do_compile", "depends"), None);
+    }
+
+    #[test]
+    fn test_history_and_emit() {
+        let d = parse(
+            "DEPENDS = \"zlib\"\nDEPENDS:append:class-target = \" openssl\"\nDEPENDS:class-native = \"\"\nOVERRIDES = \"class-target\"\n",
+        );
+        let history = d.history("DEPENDS");
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[0].label("DEPENDS"), "set");
+        assert_eq!(history[0].location().as_deref(), Some("/layer/recipe.bb:1"));
+        assert_eq!(history[1].label("DEPENDS"), ":append[class-target]");
+        assert_eq!(history[2].label("DEPENDS"), "override[class-native]:set");
+
+        let emitted = d.emit_var("DEPENDS");
+        assert!(emitted.contains("# $DEPENDS [3 operations]"));
+        assert!(
+            emitted.contains("#   :append[class-target] /layer/recipe.bb:2\n#     \" openssl\"")
+        );
+        assert!(emitted.contains("# pre-expansion value:\n#   \"zlib openssl\""));
+        assert!(emitted.ends_with("DEPENDS=\"zlib openssl\"\n"));
+    }
+
+    #[test]
+    fn test_include_require_inherit() {
+        let temp = TempDir::new().unwrap();
+        let layer = temp.path().join("meta-test");
+        fs::create_dir_all(layer.join("classes")).unwrap();
+        fs::create_dir_all(layer.join("recipes/foo")).unwrap();
+        fs::write(
+            layer.join("classes/base.bbclass"),
+            "DEPENDS ?= \"\"\ninherit helper\n",
+        )
+        .unwrap();
+        fs::write(
+            layer.join("classes/helper.bbclass"),
+            "DEPENDS:append = \" helper-native\"\n",
+        )
+        .unwrap();
+        fs::write(layer.join("recipes/foo/foo.inc"), "SUMMARY = \"Foo\"\n").unwrap();
+        let recipe = layer.join("recipes/foo/foo_1.0.bb");
+        fs::write(
+            &recipe,
+            "require foo.inc\ninclude missing.inc\ninherit base\nDEPENDS += \"zlib\"\n",
+        )
+        .unwrap();
+
+        let mut d = DataStore::new();
+        d.set_var("BBPATH", layer.to_string_lossy());
+        d.parse_file(&recipe).unwrap();
+
+        assert_eq!(d.get_var("SUMMARY").as_deref(), Some("Foo"));
+        assert_eq!(d.inherits(), &["base".to_string(), "helper".to_string()]);
+        assert_eq!(d.get_var("DEPENDS").as_deref(), Some(" zlib helper-native"));
+        let history = d.history("DEPENDS");
+        assert!(
+            history[0]
+                .file
+                .as_ref()
+                .unwrap()
+                .ends_with("classes/base.bbclass")
+        );
+
+        let mut d = DataStore::new();
+        assert!(d.parse_content("require nowhere.inc\n", &recipe).is_err());
+        assert!(d.parse_content("inherit nonexistent\n", &recipe).is_err());
+    }
+
+    #[test]
+    fn test_class_evaluation() {
+        let temp = TempDir::new().unwrap();
+        let layer = temp.path().join("meta-test");
+        let classes = layer.join("classes-recipe");
+        fs::create_dir_all(&classes).unwrap();
+        fs::write(
+            classes.join("base.bbclass"),
+            "base_do_compile() {\n    oe_runmake\n}\naddtask compile after do_configure\nEXPORT_FUNCTIONS do_compile do_install\nbase_do_install() {\n    :\n}\n",
+        )
+        .unwrap();
+        fs::write(
+            classes.join("cmake.bbclass"),
+            "inherit ninja\nDEPENDS:prepend = \"cmake-native \"\ncmake_do_compile() {\n    cmake --build ${B}\n}\nEXPORT_FUNCTIONS do_compile\n",
+        )
+        .unwrap();
+        fs::write(
+            classes.join("ninja.bbclass"),
+            "DEPENDS:append = \" ninja-native\"\n",
+        )
+        .unwrap();
+        fs::write(
+            classes.join("late.bbclass"),
+            "addtask deploy after do_install before do_build\ndo_deploy[dirs] = \"${B}\"\n",
+        )
+        .unwrap();
+
+        let mut d = DataStore::new();
+        d.add_search_path(&layer);
+        d.set_var("B", "/build");
+        d.set_var("INHERIT", "late");
+        d.inherit("base").unwrap();
+        d.parse_content(
+            "DEPENDS = \"zlib\"\ninherit_defer cmake\ndo_install() {\n    install -d ${D}\n}\n",
+            &layer.join("recipes/foo/foo_1.0.bb"),
+        )
+        .unwrap();
+        assert_eq!(d.inherits(), &["base".to_string()]);
+
+        d.finalize().unwrap();
+        d.inherit_global_classes().unwrap();
+        assert_eq!(d.inherits(), &["base", "cmake", "ninja", "late"]);
+        assert_eq!(
+            d.get_var("DEPENDS").as_deref(),
+            Some("cmake-native zlib ninja-native")
+        );
+
+        // The recipe's own do_install wins over base's export; cmake's do_compile replaces base's
+        assert_eq!(
+            d.exported_function("do_compile").as_deref(),
+            Some("cmake_do_compile")
+        );
+        assert_eq!(d.exported_function("do_install"), None);
+        assert_eq!(
+            d.get_var("do_install").as_deref(),
+            Some("    install -d ${D}")
+        );
+
+        assert_eq!(
+            d.task_statements(),
+            &[
+                "addtask compile after do_configure",
+                "addtask deploy after do_install before do_build"
+            ]
+        );
+        assert_eq!(
+            d.get_var_flag("do_deploy", "dirs").as_deref(),
+            Some("/build")
+        );
+    }
+}
@@ -299,6 +299,7 @@ mod tests {
             execution_mode: ExecutionMode::Shell,
             network_policy: NetworkPolicy::Isolated,
             resource_limits: ResourceLimits::default(),
+            flags: Default::default(),
         };
 
         let result = async_executor.execute_task(spec).await;
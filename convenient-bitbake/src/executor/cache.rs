@@ -1,12 +1,15 @@
 //! Content-addressable storage and action cache
 
 use super::types::{ContentHash, TaskOutput, ExecutionError, ExecutionResult};
+use crate::compression::{self, CompressionAlgorithm};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
 use std::fs::{self, File, OpenOptions};
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::os::unix::fs::OpenOptionsExt;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 /// Write data to a file atomically with fsync for durability
 ///
@@ -59,7 +62,7 @@ fn atomic_write(path: &Path, data: &[u8]) -> ExecutionResult<()> {
 ///
 /// Returns a file handle that holds the lock. The lock is released when the file is dropped.
 #[cfg(unix)]
-fn acquire_lock(path: &Path) -> ExecutionResult<File> {
+pub(crate) fn acquire_lock(path: &Path) -> ExecutionResult<File> {
     use std::os::unix::io::AsRawFd;
     use nix::fcntl::{flock, FlockArg};
 
@@ -81,7 +84,15 @@ fn acquire_lock(path: &Path) -> ExecutionResult<File> {
     Ok(lock_file)
 }
 
-/// Cache configuration for garbage collection
+/// FastCDC chunk size bounds (min / average / max)
+const CHUNK_MIN_SIZE: u32 = 256 * 1024;
+const CHUNK_AVG_SIZE: u32 = 1024 * 1024;
+const CHUNK_MAX_SIZE: u32 = 4 * 1024 * 1024;
+
+/// Number of most recent successful builds whose outputs are pinned by default
+pub const DEFAULT_KEEP_BUILDS: usize = 3;
+
+/// Cache configuration for storage and garbage collection
 #[derive(Debug, Clone)]
 pub struct CacheConfig {
     /// Maximum total cache size in bytes (None = unlimited)
@@ -90,6 +101,12 @@ pub struct CacheConfig {
     pub gc_threshold_bytes: u64,
     /// Target size after GC (should be < gc_threshold)
     pub gc_target_bytes: u64,
+    /// Never evict blobs referenced by the outputs of this many most recent successful builds
+    pub pin_last_builds: usize,
+    /// Compression for newly stored blobs (existing uncompressed blobs stay readable)
+    pub compression: CompressionAlgorithm,
+    /// Split blobs at least this large into content-defined chunks (None = never chunk)
+    pub chunking_threshold_bytes: Option<u64>,
 }
 
 impl Default for CacheConfig {
@@ -98,6 +115,9 @@ impl Default for CacheConfig {
             max_size_bytes: Some(10 * 1024 * 1024 * 1024), // 10 GB default
             gc_threshold_bytes: 8 * 1024 * 1024 * 1024,     // Trigger at 8 GB
             gc_target_bytes: 6 * 1024 * 1024 * 1024,        // Clean down to 6 GB
+            pin_last_builds: DEFAULT_KEEP_BUILDS,
+            compression: CompressionAlgorithm::Zstd,
+            chunking_threshold_bytes: Some(8 * 1024 * 1024), // Chunk sstate-like tarballs and images
         }
     }
 }
@@ -108,15 +128,82 @@ struct ObjectMetadata {
     path: PathBuf,
     size_bytes: u64,
     last_access_time: SystemTime,
+    /// Chunks making up the blob (empty unless the object is a chunk manifest)
+    chunks: Vec<ContentHash>,
+}
+
+/// Manifest of a blob stored as FastCDC chunks
+///
+/// Stored under `chunked/` in place of the blob; each chunk is an ordinary
+/// (possibly compressed) CAS object under `sha256/`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ChunkManifest {
+    /// Size of the reassembled blob
+    size: u64,
+    /// Chunks in blob order
+    chunks: Vec<ChunkRef>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ChunkRef {
+    hash: ContentHash,
+    length: u64,
+}
+
+/// On-disk index entry, persisted so access times survive restarts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IndexEntry {
+    size_bytes: u64,
+    /// Last access time (seconds since the Unix epoch)
+    last_access_secs: u64,
+}
+
+/// An object removed from the CAS by garbage collection
+#[derive(Debug, Clone)]
+pub struct EvictedObject {
+    pub hash: ContentHash,
+    pub size_bytes: u64,
+    pub last_access_time: SystemTime,
+}
+
+/// Outcome of a size-bounded eviction pass
+#[derive(Debug, Default)]
+pub struct EvictionReport {
+    /// Objects removed, oldest access first
+    pub evicted: Vec<EvictedObject>,
+    /// Total bytes freed
+    pub bytes_freed: u64,
+    /// Pinned objects present in the CAS (never evicted)
+    pub pinned_objects: usize,
+    /// CAS size after eviction
+    pub remaining_bytes: u64,
+}
+
+/// Outcome of re-hashing every object in the CAS
+#[derive(Debug, Default)]
+pub struct IntegrityReport {
+    /// Objects checked
+    pub objects_checked: usize,
+    /// Objects whose content didn't match their hash (moved to quarantine)
+    pub corrupt: Vec<ContentHash>,
 }
 
 /// Content-Addressable Store - stores files by their SHA-256 hash
+///
+/// Blobs are zstd-compressed when that makes them smaller; reads detect the
+/// format from magic bytes, so entries written uncompressed keep working.
+/// Blobs above `chunking_threshold_bytes` are split with FastCDC so nearly
+/// identical artifacts share most of their chunks.
 pub struct ContentAddressableStore {
     root: PathBuf,
     /// In-memory index with metadata for GC/LRU
     index: HashMap<ContentHash, ObjectMetadata>,
     /// Cache configuration
     config: CacheConfig,
+    /// Objects that must survive eviction (outputs of recent builds)
+    pinned: HashSet<ContentHash>,
+    /// Access times changed since the index was last written
+    index_dirty: bool,
 }
 
 impl ContentAddressableStore {
@@ -134,6 +221,8 @@ impl ContentAddressableStore {
             root,
             index: HashMap::new(),
             config,
+            pinned: HashSet::new(),
+            index_dirty: false,
         };
 
         // Rebuild index by scanning filesystem
@@ -142,19 +231,56 @@ impl ContentAddressableStore {
         Ok(cas)
     }
 
+    /// Get the cache configuration
+    pub fn config(&self) -> &CacheConfig {
+        &self.config
+    }
+
+    /// Pin objects so that LRU eviction and GC never remove them
+    pub fn pin(&mut self, hashes: impl IntoIterator<Item = ContentHash>) {
+        self.pinned.extend(hashes);
+    }
+
+    /// Check whether an object is pinned
+    pub fn is_pinned(&self, hash: &ContentHash) -> bool {
+        self.pinned.contains(hash)
+    }
+
     /// Store content and return its hash
     pub fn put(&mut self, content: &[u8]) -> ExecutionResult<ContentHash> {
         let hash = ContentHash::from_bytes(content);
-        let path = self.hash_to_path(&hash);
 
         // Skip if already exists (update access time)
-        if path.exists() {
-            if let Some(metadata) = self.index.get_mut(&hash) {
-                metadata.last_access_time = SystemTime::now();
-            }
+        if self.contains(&hash) {
+            self.touch(&hash);
             return Ok(hash);
         }
 
+        let chunked = self
+            .config
+            .chunking_threshold_bytes
+            .is_some_and(|threshold| content.len() as u64 >= threshold);
+
+        if chunked {
+            self.put_chunked(&hash, content)?;
+        } else {
+            self.put_blob(&hash, content)?;
+        }
+
+        // Trigger GC if cache is too large
+        self.gc_if_needed()?;
+
+        Ok(hash)
+    }
+
+    /// Store a single blob under `sha256/`, compressed if that pays off
+    fn put_blob(&mut self, hash: &ContentHash, content: &[u8]) -> ExecutionResult<()> {
+        let path = self.hash_to_path(hash);
+        if path.exists() {
+            self.touch(hash);
+            return Ok(());
+        }
+
         // Acquire lock to prevent concurrent writes to same hash
         #[cfg(unix)]
         let lock_path = path.with_extension("lock");
@@ -163,43 +289,161 @@ impl ContentAddressableStore {
 
         // Double-check after acquiring lock (another process might have written it)
         if path.exists() {
-            return Ok(hash);
+            return Ok(());
         }
 
         // Write atomically with fsync for durability
-        atomic_write(&path, content)?;
+        let stored = self.encode(content)?;
+        atomic_write(&path, &stored)?;
 
         // Update index with metadata
         let metadata = ObjectMetadata {
-            path: path.clone(),
-            size_bytes: content.len() as u64,
+            path,
+            size_bytes: stored.len() as u64,
             last_access_time: SystemTime::now(),
+            chunks: Vec::new(),
         };
         self.index.insert(hash.clone(), metadata);
+        self.index_dirty = true;
 
-        // Trigger GC if cache is too large
-        self.gc_if_needed()?;
+        Ok(())
+    }
 
-        Ok(hash)
+    /// Split content with FastCDC, store each chunk, then write the manifest
+    fn put_chunked(&mut self, hash: &ContentHash, content: &[u8]) -> ExecutionResult<()> {
+        let mut chunks = Vec::new();
+        for chunk in fastcdc::v2020::FastCDC::new(content, CHUNK_MIN_SIZE, CHUNK_AVG_SIZE, CHUNK_MAX_SIZE) {
+            let data = &content[chunk.offset..chunk.offset + chunk.length];
+            let chunk_hash = ContentHash::from_bytes(data);
+            self.put_blob(&chunk_hash, data)?;
+            chunks.push(ChunkRef { hash: chunk_hash, length: chunk.length as u64 });
+        }
+
+        // A single chunk is the blob itself - no manifest needed
+        if chunks.len() <= 1 {
+            return self.put_blob(hash, content);
+        }
+
+        let path = self.chunked_path(hash);
+
+        #[cfg(unix)]
+        let lock_path = path.with_extension("lock");
+        #[cfg(unix)]
+        let _lock = acquire_lock(&lock_path)?;
+
+        if !path.exists() {
+            let manifest = ChunkManifest { size: content.len() as u64, chunks };
+            let json = serde_json::to_vec(&manifest)?;
+            atomic_write(&path, &json)?;
+
+            let metadata = ObjectMetadata {
+                path,
+                size_bytes: json.len() as u64,
+                last_access_time: SystemTime::now(),
+                chunks: manifest.chunks.into_iter().map(|c| c.hash).collect(),
+            };
+            self.index.insert(hash.clone(), metadata);
+            self.index_dirty = true;
+        }
+
+        Ok(())
     }
 
-    /// Retrieve content by hash
-    pub fn get(&mut self, hash: &ContentHash) -> ExecutionResult<Vec<u8>> {
-        let path = self.hash_to_path(hash);
+    /// Encode a blob for storage
+    ///
+    /// Content is kept raw when compression doesn't shrink it, unless it starts
+    /// with a compression magic number - then it is always compressed so reads
+    /// can't mistake it for a compressed blob.
+    fn encode<'a>(&self, content: &'a [u8]) -> ExecutionResult<Cow<'a, [u8]>> {
+        let looks_compressed = CompressionAlgorithm::detect(content) != CompressionAlgorithm::None;
+        let algorithm = match self.config.compression {
+            CompressionAlgorithm::None if looks_compressed => CompressionAlgorithm::Zstd,
+            CompressionAlgorithm::None => return Ok(Cow::Borrowed(content)),
+            algorithm => algorithm,
+        };
 
-        // Update access time for LRU tracking
-        if let Some(metadata) = self.index.get_mut(hash) {
-            metadata.last_access_time = SystemTime::now();
+        let compressed = compression::compress(content, algorithm, algorithm.default_level())
+            .map_err(|e| ExecutionError::CacheError(format!("Compression failed: {}", e)))?;
+
+        if compressed.len() < content.len() || looks_compressed {
+            Ok(Cow::Owned(compressed))
+        } else {
+            Ok(Cow::Borrowed(content))
         }
+    }
 
-        fs::read(&path).map_err(|e| {
+    /// Decode a stored blob, verifying it against its hash
+    ///
+    /// Blobs written before compression was enabled may legitimately start
+    /// with a magic number; if decompressing doesn't reproduce the hash but
+    /// the raw bytes do, the raw bytes are the content.
+    fn decode(hash: &ContentHash, stored: Vec<u8>) -> ExecutionResult<Vec<u8>> {
+        if CompressionAlgorithm::detect(&stored) == CompressionAlgorithm::None {
+            return Ok(stored);
+        }
+
+        match compression::decompress(&stored) {
+            Ok(content) if ContentHash::from_bytes(&content) == *hash => Ok(content),
+            _ if ContentHash::from_bytes(&stored) == *hash => Ok(stored),
+            Ok(_) => Err(ExecutionError::CacheError(format!("Corrupt CAS object {}: hash mismatch", hash))),
+            Err(e) => Err(ExecutionError::CacheError(format!("Failed to decompress {}: {}", hash, e))),
+        }
+    }
+
+    /// Read and decode a single blob from `sha256/`
+    fn read_blob(&self, hash: &ContentHash) -> ExecutionResult<Vec<u8>> {
+        let stored = fs::read(self.hash_to_path(hash)).map_err(|e| {
             ExecutionError::CacheError(format!("Failed to read {}: {}", hash, e))
-        })
+        })?;
+        Self::decode(hash, stored)
+    }
+
+    /// Reassemble a chunked blob from its manifest
+    fn read_chunked(&self, hash: &ContentHash) -> ExecutionResult<Vec<u8>> {
+        let manifest: ChunkManifest = serde_json::from_slice(&fs::read(self.chunked_path(hash))?)?;
+
+        let mut content = Vec::with_capacity(manifest.size as usize);
+        for chunk in &manifest.chunks {
+            content.extend_from_slice(&self.read_blob(&chunk.hash)?);
+        }
+
+        if ContentHash::from_bytes(&content) != *hash {
+            return Err(ExecutionError::CacheError(format!(
+                "Corrupt chunked CAS object {}: hash mismatch",
+                hash
+            )));
+        }
+
+        Ok(content)
+    }
+
+    /// Retrieve content by hash
+    pub fn get(&mut self, hash: &ContentHash) -> ExecutionResult<Vec<u8>> {
+        // Update access time for LRU tracking
+        self.touch(hash);
+
+        if self.chunked_path(hash).exists() {
+            self.read_chunked(hash)
+        } else {
+            self.read_blob(hash)
+        }
     }
 
     /// Check if hash exists in store
     pub fn contains(&self, hash: &ContentHash) -> bool {
-        self.hash_to_path(hash).exists()
+        self.hash_to_path(hash).exists() || self.chunked_path(hash).exists()
+    }
+
+    /// Whether the object is stored as a plain uncompressed file (hard-linkable)
+    fn is_stored_raw(&self, hash: &ContentHash) -> bool {
+        use std::io::Read;
+
+        let mut magic = [0u8; 4];
+        let read = File::open(self.hash_to_path(hash)).and_then(|mut file| file.read(&mut magic));
+        match read {
+            Ok(n) => CompressionAlgorithm::detect(&magic[..n]) == CompressionAlgorithm::None,
+            Err(_) => false,
+        }
     }
 
     /// Store a file and return its hash
@@ -209,20 +453,31 @@ impl ContentAddressableStore {
     }
 
     /// Restore file from hash to destination
-    pub fn get_file(&self, hash: &ContentHash, dest: &Path) -> ExecutionResult<()> {
-        let source = self.hash_to_path(hash);
-
+    pub fn get_file(&mut self, hash: &ContentHash, dest: &Path) -> ExecutionResult<()> {
         if let Some(parent) = dest.parent() {
             fs::create_dir_all(parent)?;
         }
 
-        fs::copy(&source, dest)?;
+        if self.is_stored_raw(hash) {
+            self.touch(hash);
+            fs::copy(self.hash_to_path(hash), dest)?;
+        } else {
+            let content = self.get(hash)?;
+            fs::write(dest, content)?;
+        }
         Ok(())
     }
 
     /// Hard-link file from CAS to destination (zero-copy)
-    pub fn link_file(&self, hash: &ContentHash, dest: &Path) -> ExecutionResult<()> {
+    ///
+    /// Compressed and chunked objects can't be linked; they are decoded instead.
+    pub fn link_file(&mut self, hash: &ContentHash, dest: &Path) -> ExecutionResult<()> {
+        if !self.is_stored_raw(hash) {
+            return self.get_file(hash, dest);
+        }
+
         let source = self.hash_to_path(hash);
+        self.touch(hash);
 
         if let Some(parent) = dest.parent() {
             fs::create_dir_all(parent)?;
@@ -238,26 +493,120 @@ impl ContentAddressableStore {
         }
     }
 
+    /// Record an access for LRU tracking
+    ///
+    /// Touching a chunk manifest also touches its chunks.
+    fn touch(&mut self, hash: &ContentHash) {
+        let now = SystemTime::now();
+        let chunks = match self.index.get_mut(hash) {
+            Some(metadata) => {
+                metadata.last_access_time = now;
+                self.index_dirty = true;
+                metadata.chunks.clone()
+            }
+            None => return,
+        };
+
+        for chunk in &chunks {
+            if let Some(metadata) = self.index.get_mut(chunk) {
+                metadata.last_access_time = now;
+            }
+        }
+    }
+
+    /// The given objects plus the chunks of any chunk manifests among them
+    fn with_chunks<'a>(&self, hashes: impl IntoIterator<Item = &'a ContentHash>) -> HashSet<ContentHash> {
+        let mut closure = HashSet::new();
+        for hash in hashes {
+            if let Some(metadata) = self.index.get(hash) {
+                closure.extend(metadata.chunks.iter().cloned());
+            }
+            closure.insert(hash.clone());
+        }
+        closure
+    }
+
+    /// Path of the persisted access-time index
+    fn index_path(&self) -> PathBuf {
+        self.root.join("index.json")
+    }
+
+    /// Persist the access-time index if it changed
+    pub fn flush_index(&mut self) -> ExecutionResult<()> {
+        if !self.index_dirty {
+            return Ok(());
+        }
+
+        let entries: HashMap<String, IndexEntry> = self
+            .index
+            .iter()
+            .map(|(hash, metadata)| {
+                let last_access_secs = metadata
+                    .last_access_time
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0);
+                (hash.to_hex(), IndexEntry { size_bytes: metadata.size_bytes, last_access_secs })
+            })
+            .collect();
+
+        let json = serde_json::to_vec(&entries)?;
+        atomic_write(&self.index_path(), &json)?;
+        self.index_dirty = false;
+
+        Ok(())
+    }
+
+    /// Load persisted access times (missing or corrupt index is not an error)
+    fn load_index(&self) -> HashMap<String, IndexEntry> {
+        fs::read(self.index_path())
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default()
+    }
+
     /// Map content hash to filesystem path
     fn hash_to_path(&self, hash: &ContentHash) -> PathBuf {
+        self.sharded_path("sha256", hash)
+    }
+
+    /// Map content hash to the path of its chunk manifest
+    fn chunked_path(&self, hash: &ContentHash) -> PathBuf {
+        self.sharded_path("chunked", hash)
+    }
+
+    fn sharded_path(&self, dir: &str, hash: &ContentHash) -> PathBuf {
         let hex = hash.to_hex();
         // Use first 2 bytes for directory sharding (00-ff)
         self.root
-            .join("sha256")
+            .join(dir)
             .join(&hex[0..2])
             .join(&hex[2..4])
             .join(&hex)
     }
 
     /// Rebuild index by scanning filesystem
+    ///
+    /// Access times come from the persisted index when available, falling back
+    /// to the file's mtime for objects the index doesn't know about.
     fn rebuild_index(&mut self) -> ExecutionResult<()> {
-        let sha256_dir = self.root.join("sha256");
-        if !sha256_dir.exists() {
-            return Ok(());
+        let persisted = self.load_index();
+
+        self.scan_objects("sha256", &persisted);
+        self.scan_objects("chunked", &persisted);
+
+        Ok(())
+    }
+
+    /// Add every object below `root/<dir>` to the index
+    fn scan_objects(&mut self, dir: &str, persisted: &HashMap<String, IndexEntry>) {
+        let objects_dir = self.root.join(dir);
+        if !objects_dir.exists() {
+            return;
         }
 
         // Walk directory tree
-        for entry in walkdir::WalkDir::new(&sha256_dir)
+        for entry in walkdir::WalkDir::new(&objects_dir)
             .follow_links(false)
             .into_iter()
             .filter_map(|e| e.ok())
@@ -272,22 +621,37 @@ impl ContentAddressableStore {
                         continue;
                     }
 
+                    // Chunk manifests list the chunks they depend on
+                    let chunks = if dir == "chunked" {
+                        match fs::read(path).ok().and_then(|data| serde_json::from_slice::<ChunkManifest>(&data).ok()) {
+                            Some(manifest) => manifest.chunks.into_iter().map(|c| c.hash).collect(),
+                            None => {
+                                eprintln!("Warning: Ignoring unreadable chunk manifest {}", path.display());
+                                continue;
+                            }
+                        }
+                    } else {
+                        Vec::new()
+                    };
+
                     // Get file metadata
                     if let Ok(file_metadata) = fs::metadata(path) {
                         let hash = ContentHash::from_hex(filename);
+                        let last_access_time = match persisted.get(filename) {
+                            Some(entry) => UNIX_EPOCH + Duration::from_secs(entry.last_access_secs),
+                            None => file_metadata.modified().unwrap_or_else(|_| SystemTime::now()),
+                        };
                         let obj_metadata = ObjectMetadata {
                             path: path.to_path_buf(),
                             size_bytes: file_metadata.len(),
-                            last_access_time: file_metadata.modified()
-                                .unwrap_or_else(|_| SystemTime::now()),
+                            last_access_time,
+                            chunks,
                         };
                         self.index.insert(hash, obj_metadata);
                     }
                 }
             }
         }
-
-        Ok(())
     }
 
     /// Get statistics
@@ -312,8 +676,9 @@ impl ContentAddressableStore {
     /// For proper garbage collection, use gc_with_action_cache() explicitly.
     fn gc_if_needed(&mut self) -> ExecutionResult<()> {
         let total_size = self.compute_total_size();
+        let over_max = self.config.max_size_bytes.is_some_and(|max| total_size > max);
 
-        if total_size > self.config.gc_threshold_bytes {
+        if total_size > self.config.gc_threshold_bytes || over_max {
             let bytes_to_free = total_size.saturating_sub(self.config.gc_target_bytes);
             eprintln!(
                 "Cache size {} bytes exceeds threshold {} bytes, performing LRU eviction",
@@ -323,7 +688,7 @@ impl ContentAddressableStore {
 
             // Only do LRU eviction in automatic GC (no mark-and-sweep)
             let evicted = self.evict_lru(bytes_to_free)?;
-            eprintln!("Automatic GC: evicted {} oldest objects", evicted);
+            eprintln!("Automatic GC: evicted {} oldest objects", evicted.len());
         }
 
         Ok(())
@@ -341,13 +706,42 @@ impl ContentAddressableStore {
         let initial_count = self.index.len();
         let initial_size = self.compute_total_size();
 
+        let mut deleted = self.sweep(keep)?;
+        let size_after_sweep = self.compute_total_size();
+
+        // LRU eviction: if still over target, evict oldest objects
+        if size_after_sweep > self.config.gc_target_bytes {
+            let evicted = self.evict_lru(size_after_sweep - self.config.gc_target_bytes)?;
+            eprintln!("LRU eviction: removed {} objects", evicted.len());
+            deleted += evicted.len();
+        }
+
+        self.flush_index()?;
+
+        let final_size = self.compute_total_size();
+        eprintln!(
+            "GC complete: {} → {} objects, {} → {} bytes",
+            initial_count,
+            self.index.len(),
+            initial_size,
+            final_size
+        );
+
+        Ok(deleted)
+    }
+
+    /// Mark-and-sweep only: delete every object not reachable from `keep`
+    /// (or pinned), without evicting anything by size
+    pub fn sweep(&mut self, keep: &[ContentHash]) -> ExecutionResult<usize> {
         eprintln!(
             "Starting GC: {} objects, {} bytes",
-            initial_count, initial_size
+            self.index.len(),
+            self.compute_total_size()
         );
 
-        // Mark phase: identify objects to keep
-        let mut reachable: HashSet<ContentHash> = keep.iter().cloned().collect();
+        // Mark phase: identify objects to keep (pinned objects are always kept),
+        // including the chunks of kept chunk manifests
+        let reachable = self.with_chunks(keep.iter().chain(&self.pinned));
 
         // Sweep phase: delete unreachable objects
         let mut deleted = 0;
@@ -370,59 +764,76 @@ impl ContentAddressableStore {
             self.index.remove(hash);
         }
 
-        let size_after_sweep = self.compute_total_size();
+        self.flush_index()?;
 
         eprintln!(
             "Sweep complete: deleted {} unreachable objects, size now {} bytes",
-            deleted, size_after_sweep
-        );
-
-        // LRU eviction: if still over target, evict oldest objects
-        if size_after_sweep > self.config.gc_target_bytes {
-            let evicted = self.evict_lru(size_after_sweep - self.config.gc_target_bytes)?;
-            eprintln!("LRU eviction: removed {} objects", evicted);
-            deleted += evicted;
-        }
-
-        let final_size = self.compute_total_size();
-        eprintln!(
-            "GC complete: {} → {} objects, {} → {} bytes",
-            initial_count,
-            self.index.len(),
-            initial_size,
-            final_size
+            deleted,
+            self.compute_total_size()
         );
 
         Ok(deleted)
     }
 
+    /// Evict least recently used objects until the CAS fits in `max_bytes`
+    ///
+    /// Pinned objects are never evicted, so the CAS may stay above the limit
+    /// when pins alone exceed it.
+    pub fn enforce_max_size(&mut self, max_bytes: u64) -> ExecutionResult<EvictionReport> {
+        let total_size = self.compute_total_size();
+        let evicted = self.evict_lru(total_size.saturating_sub(max_bytes))?;
+        self.flush_index()?;
+
+        Ok(EvictionReport {
+            bytes_freed: evicted.iter().map(|obj| obj.size_bytes).sum(),
+            evicted,
+            pinned_objects: self.index.keys().filter(|hash| self.pinned.contains(*hash)).count(),
+            remaining_bytes: self.compute_total_size(),
+        })
+    }
+
     /// Evict least recently used objects to free up space
-    fn evict_lru(&mut self, bytes_to_free: u64) -> ExecutionResult<usize> {
-        // Collect all objects with access times
-        let mut objects: Vec<(ContentHash, SystemTime, u64)> = self
+    ///
+    /// Evicting a chunk also evicts the manifests that need it, so no
+    /// half-present chunked blob is left behind.
+    fn evict_lru(&mut self, bytes_to_free: u64) -> ExecutionResult<Vec<EvictedObject>> {
+        if bytes_to_free == 0 {
+            return Ok(Vec::new());
+        }
+
+        let protected = self.with_chunks(&self.pinned);
+
+        // Collect all unpinned objects with access times
+        let mut objects: Vec<(ContentHash, SystemTime, bool)> = self
             .index
             .iter()
-            .map(|(hash, metadata)| (hash.clone(), metadata.last_access_time, metadata.size_bytes))
+            .filter(|(hash, _)| !protected.contains(*hash))
+            .map(|(hash, metadata)| (hash.clone(), metadata.last_access_time, metadata.chunks.is_empty()))
             .collect();
 
-        // Sort by access time (oldest first)
-        objects.sort_by_key(|(_, access_time, _)| *access_time);
+        // Sort by access time (oldest first); manifests before their equally old chunks
+        objects.sort_by_key(|(_, access_time, is_blob)| (*access_time, *is_blob));
+
+        let mut dependents: HashMap<ContentHash, Vec<ContentHash>> = HashMap::new();
+        for (hash, metadata) in &self.index {
+            for chunk in &metadata.chunks {
+                dependents.entry(chunk.clone()).or_default().push(hash.clone());
+            }
+        }
 
         let mut freed = 0u64;
-        let mut evicted = 0;
+        let mut evicted = Vec::new();
 
-        for (hash, _, size) in objects {
+        for (hash, _, _) in objects {
             if freed >= bytes_to_free {
                 break;
             }
 
-            // Remove object
-            if let Some(metadata) = self.index.remove(&hash) {
-                if let Err(e) = fs::remove_file(&metadata.path) {
-                    eprintln!("Warning: Failed to evict {}: {}", hash, e);
-                } else {
-                    freed += size;
-                    evicted += 1;
+            let manifests = dependents.remove(&hash).unwrap_or_default();
+            for victim in std::iter::once(hash).chain(manifests) {
+                if let Some(object) = self.remove_object(&victim) {
+                    freed += object.size_bytes;
+                    evicted.push(object);
                 }
             }
         }
@@ -430,6 +841,75 @@ impl ContentAddressableStore {
         Ok(evicted)
     }
 
+    /// Remove an object from disk and the index
+    fn remove_object(&mut self, hash: &ContentHash) -> Option<EvictedObject> {
+        let metadata = self.index.remove(hash)?;
+        self.index_dirty = true;
+
+        if let Err(e) = fs::remove_file(&metadata.path) {
+            eprintln!("Warning: Failed to evict {}: {}", hash, e);
+            return None;
+        }
+
+        Some(EvictedObject {
+            hash: hash.clone(),
+            size_bytes: metadata.size_bytes,
+            last_access_time: metadata.last_access_time,
+        })
+    }
+
+    /// Re-hash every object and move corrupt ones into `quarantine_dir`
+    ///
+    /// Plain blobs are checked before chunk manifests, so a manifest whose
+    /// chunk was quarantined is quarantined as well. Quarantined files keep
+    /// their path relative to the CAS root.
+    pub fn verify(&mut self, quarantine_dir: &Path) -> ExecutionResult<IntegrityReport> {
+        let mut hashes: Vec<(bool, ContentHash)> = self
+            .index
+            .iter()
+            .map(|(hash, metadata)| (!metadata.chunks.is_empty(), hash.clone()))
+            .collect();
+        hashes.sort_by_key(|(chunked, _)| *chunked);
+
+        let mut report = IntegrityReport::default();
+        for (chunked, hash) in hashes {
+            report.objects_checked += 1;
+
+            let content = if chunked { self.read_chunked(&hash) } else { self.read_blob(&hash) };
+            let intact = matches!(content, Ok(ref data) if ContentHash::from_bytes(data) == hash);
+
+            if !intact {
+                self.quarantine(&hash, quarantine_dir)?;
+                report.corrupt.push(hash);
+            }
+        }
+
+        self.flush_index()?;
+        Ok(report)
+    }
+
+    /// Move an object out of the CAS into `quarantine_dir`
+    fn quarantine(&mut self, hash: &ContentHash, quarantine_dir: &Path) -> ExecutionResult<()> {
+        let Some(metadata) = self.index.remove(hash) else {
+            return Ok(());
+        };
+        self.index_dirty = true;
+
+        let relative = metadata.path.strip_prefix(&self.root).unwrap_or(&metadata.path);
+        let dest = quarantine_dir.join(relative);
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // Fall back to copy + remove when the quarantine is on another filesystem
+        if fs::rename(&metadata.path, &dest).is_err() {
+            fs::copy(&metadata.path, &dest)?;
+            fs::remove_file(&metadata.path)?;
+        }
+
+        Ok(())
+    }
+
     /// Perform garbage collection with mark-and-sweep using ActionCache references
     ///
     /// This is the recommended way to run GC: it walks the ActionCache to find
@@ -446,6 +926,19 @@ impl ContentAddressableStore {
         // Run GC with the reachable set
         self.gc(&reachable.into_iter().collect::<Vec<_>>())
     }
+
+    /// Mark-and-sweep against the ActionCache, without size-based eviction
+    pub fn sweep_with_action_cache(&mut self, action_cache: &ActionCache) -> ExecutionResult<usize> {
+        let reachable = action_cache.get_referenced_content_hashes();
+        self.sweep(&reachable.into_iter().collect::<Vec<_>>())
+    }
+}
+
+impl Drop for ContentAddressableStore {
+    fn drop(&mut self) {
+        // Best effort: losing access times only degrades LRU ordering
+        let _ = self.flush_index();
+    }
 }
 
 /// Cache statistics
@@ -559,6 +1052,11 @@ impl ActionCache {
         Ok(())
     }
 
+    /// Iterate over all cached task outputs by signature
+    pub fn entries(&self) -> impl Iterator<Item = (&ContentHash, &TaskOutput)> {
+        self.cache.iter()
+    }
+
     pub fn stats(&self) -> ActionCacheStats {
         ActionCacheStats {
             entry_count: self.cache.len(),
@@ -587,6 +1085,103 @@ pub struct ActionCacheStats {
     pub entry_count: usize,
 }
 
+/// Record of the outputs produced by one successful build
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BuildRecord {
+    /// Build target (e.g., "busybox")
+    pub target: String,
+    /// Completion time (seconds since the Unix epoch)
+    pub timestamp: u64,
+    /// CAS objects referenced by the outputs of every task in the build
+    pub outputs: Vec<ContentHash>,
+}
+
+/// History of successful builds, used to pin their outputs in the CAS
+pub struct BuildHistory {
+    root: PathBuf,
+}
+
+impl BuildHistory {
+    /// Create or open a build history directory
+    pub fn new(root: impl Into<PathBuf>) -> ExecutionResult<Self> {
+        let root = root.into();
+        fs::create_dir_all(&root)?;
+        Ok(Self { root })
+    }
+
+    /// Record a successful build
+    pub fn record(
+        &self,
+        target: &str,
+        outputs: impl IntoIterator<Item = ContentHash>,
+    ) -> ExecutionResult<()> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut outputs: Vec<ContentHash> = outputs.into_iter().collect();
+        outputs.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        outputs.dedup();
+
+        let record = BuildRecord {
+            target: target.to_string(),
+            timestamp: now.as_secs(),
+            outputs,
+        };
+
+        // Nanosecond file names keep records ordered and unique
+        let path = self.root.join(format!("{:020}.json", now.as_nanos()));
+        let json = serde_json::to_string_pretty(&record)?;
+        atomic_write(&path, json.as_bytes())
+    }
+
+    /// The `n` most recent builds, newest first
+    pub fn recent(&self, n: usize) -> ExecutionResult<Vec<BuildRecord>> {
+        let paths = self.record_paths()?;
+
+        let mut records = Vec::new();
+        for path in paths.iter().rev() {
+            if records.len() >= n {
+                break;
+            }
+            let json = fs::read_to_string(path)?;
+            if let Ok(record) = serde_json::from_str::<BuildRecord>(&json) {
+                records.push(record);
+            }
+        }
+
+        Ok(records)
+    }
+
+    /// Delete all but the `keep` most recent build records, returning how
+    /// many were deleted
+    pub fn prune(&self, keep: usize) -> ExecutionResult<usize> {
+        let mut paths = self.record_paths()?;
+        let old = paths.len().saturating_sub(keep);
+        for path in paths.drain(..old) {
+            fs::remove_file(path)?;
+        }
+        Ok(old)
+    }
+
+    /// Record files, oldest first
+    fn record_paths(&self) -> ExecutionResult<Vec<PathBuf>> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(&self.root)?
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().and_then(|s| s.to_str()) == Some("json"))
+            .collect();
+        paths.sort();
+        Ok(paths)
+    }
+
+    /// All CAS objects referenced by the `n` most recent builds
+    pub fn pinned_hashes(&self, n: usize) -> ExecutionResult<HashSet<ContentHash>> {
+        Ok(self
+            .recent(n)?
+            .into_iter()
+            .flat_map(|record| record.outputs)
+            .collect())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -665,4 +1260,237 @@ mod tests {
             assert_eq!(retrieved.stdout, "persistent");
         }
     }
+
+    /// Config storing blobs verbatim, so on-disk sizes equal content sizes
+    fn uncompressed_config() -> CacheConfig {
+        CacheConfig {
+            compression: CompressionAlgorithm::None,
+            chunking_threshold_bytes: None,
+            ..CacheConfig::default()
+        }
+    }
+
+    /// Deterministic incompressible data
+    fn noise(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed | 1;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_lru_eviction_respects_max_size_and_pins() {
+        let tmp = TempDir::new().unwrap();
+        let mut cas = ContentAddressableStore::with_config(tmp.path(), uncompressed_config()).unwrap();
+
+        let old = cas.put(&[0u8; 100]).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        let pinned = cas.put(&[1u8; 100]).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        let recent = cas.put(&[2u8; 100]).unwrap();
+
+        cas.pin([pinned.clone()]);
+        let report = cas.enforce_max_size(150).unwrap();
+
+        // Oldest unpinned objects go first; the pinned one survives even though it is older
+        assert_eq!(report.bytes_freed, 200);
+        assert_eq!(report.evicted.len(), 2);
+        assert_eq!(report.evicted[0].hash, old);
+        assert_eq!(report.evicted[1].hash, recent);
+        assert_eq!(report.pinned_objects, 1);
+        assert!(cas.contains(&pinned));
+        assert!(!cas.contains(&old));
+    }
+
+    #[test]
+    fn test_access_times_persist_across_reopen() {
+        let tmp = TempDir::new().unwrap();
+
+        let (first, second) = {
+            let mut cas = ContentAddressableStore::new(tmp.path()).unwrap();
+            let first = cas.put(b"first").unwrap();
+            let second = cas.put(b"second").unwrap();
+
+            // Make "first" the most recently used by backdating "second" in the index
+            cas.index.get_mut(&second).unwrap().last_access_time = UNIX_EPOCH + Duration::from_secs(1);
+            cas.index.get_mut(&first).unwrap().last_access_time = UNIX_EPOCH + Duration::from_secs(2);
+            cas.index_dirty = true;
+            (first, second)
+        };
+
+        let mut cas = ContentAddressableStore::new(tmp.path()).unwrap();
+        let report = cas.enforce_max_size(6).unwrap();
+
+        assert_eq!(report.evicted.len(), 1);
+        assert_eq!(report.evicted[0].hash, second);
+        assert!(cas.contains(&first));
+    }
+
+    #[test]
+    fn test_cas_compresses_blobs() {
+        let tmp = TempDir::new().unwrap();
+        let mut cas = ContentAddressableStore::new(tmp.path()).unwrap();
+
+        let content = b"compressible ".repeat(1000);
+        let hash = cas.put(&content).unwrap();
+
+        let stored = fs::read(cas.hash_to_path(&hash)).unwrap();
+        assert_eq!(CompressionAlgorithm::detect(&stored), CompressionAlgorithm::Zstd);
+        assert!(stored.len() < content.len());
+        assert_eq!(cas.get(&hash).unwrap(), content);
+
+        let dest = tmp.path().join("restored");
+        cas.link_file(&hash, &dest).unwrap();
+        assert_eq!(fs::read(&dest).unwrap(), content);
+    }
+
+    #[test]
+    fn test_cas_reads_uncompressed_entries() {
+        let tmp = TempDir::new().unwrap();
+        let mut cas = ContentAddressableStore::new(tmp.path()).unwrap();
+
+        // Entries written before compression, including one that happens to be a zstd stream
+        let plain = b"legacy blob".to_vec();
+        let zst = compression::compress(b"payload", CompressionAlgorithm::Zstd, 3).unwrap();
+        for content in [&plain, &zst] {
+            let hash = ContentHash::from_bytes(content);
+            let path = cas.hash_to_path(&hash);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, content).unwrap();
+
+            assert_eq!(&cas.get(&hash).unwrap(), content);
+        }
+
+        // Storing a zstd stream again round-trips to the stream, not its payload
+        let hash = cas.put(&zst).unwrap();
+        assert_eq!(cas.get(&hash).unwrap(), zst);
+    }
+
+    #[test]
+    fn test_cas_chunks_large_blobs() {
+        let tmp = TempDir::new().unwrap();
+        let config = CacheConfig {
+            chunking_threshold_bytes: Some(1024 * 1024),
+            ..CacheConfig::default()
+        };
+        let mut cas = ContentAddressableStore::with_config(tmp.path(), config).unwrap();
+
+        let original = noise(12 * 1024 * 1024, 42);
+        let mut modified = original.clone();
+        modified[6 * 1024 * 1024..6 * 1024 * 1024 + 16].copy_from_slice(b"patched in place");
+
+        let a = cas.put(&original).unwrap();
+        let size_after_first = cas.stats().total_size_bytes;
+        let b = cas.put(&modified).unwrap();
+        let size_after_second = cas.stats().total_size_bytes;
+
+        assert!(cas.chunked_path(&a).exists());
+        assert_eq!(cas.get(&a).unwrap(), original);
+        assert_eq!(cas.get(&b).unwrap(), modified);
+
+        // The near-identical blob only adds the chunks around the edit
+        let added = size_after_second - size_after_first;
+        assert!(added < size_after_first / 2, "added {} of {}", added, size_after_first);
+
+        // Reopening picks the manifests back up
+        drop(cas);
+        let mut cas = ContentAddressableStore::new(tmp.path()).unwrap();
+        assert_eq!(cas.get(&b).unwrap(), modified);
+    }
+
+    #[test]
+    fn test_evicting_chunk_evicts_manifest() {
+        let tmp = TempDir::new().unwrap();
+        let config = CacheConfig {
+            chunking_threshold_bytes: Some(1024 * 1024),
+            ..uncompressed_config()
+        };
+        let mut cas = ContentAddressableStore::with_config(tmp.path(), config).unwrap();
+
+        let content = noise(8 * 1024 * 1024, 7);
+        let hash = cas.put(&content).unwrap();
+        let chunks = cas.index[&hash].chunks.clone();
+        assert!(chunks.len() > 1);
+
+        // Pinning the blob protects its chunks
+        cas.pin([hash.clone()]);
+        cas.enforce_max_size(0).unwrap();
+        assert_eq!(cas.get(&hash).unwrap(), content);
+
+        cas.pinned.clear();
+        let report = cas.enforce_max_size(1).unwrap();
+        assert!(report.evicted.iter().any(|obj| obj.hash == hash));
+        assert!(!cas.contains(&hash));
+    }
+
+    #[test]
+    fn test_verify_quarantines_corrupt_objects() {
+        let tmp = TempDir::new().unwrap();
+        let cas_dir = tmp.path().join("cas");
+        let quarantine = tmp.path().join("quarantine");
+        let mut cas = ContentAddressableStore::with_config(&cas_dir, uncompressed_config()).unwrap();
+
+        let good = cas.put(b"intact").unwrap();
+        let bad = cas.put(b"bit rot").unwrap();
+        fs::write(cas.hash_to_path(&bad), b"bit r0t").unwrap();
+
+        let report = cas.verify(&quarantine).unwrap();
+
+        assert_eq!(report.objects_checked, 2);
+        assert_eq!(report.corrupt, vec![bad.clone()]);
+        assert!(cas.contains(&good));
+        assert!(!cas.contains(&bad));
+
+        let hex = bad.to_hex();
+        assert!(quarantine.join("sha256").join(&hex[0..2]).join(&hex[2..4]).join(&hex).exists());
+    }
+
+    #[test]
+    fn test_sweep_does_not_evict_by_size() {
+        let tmp = TempDir::new().unwrap();
+        let config = CacheConfig {
+            gc_target_bytes: 0,
+            ..CacheConfig::default()
+        };
+        let mut cas = ContentAddressableStore::with_config(tmp.path(), config).unwrap();
+
+        let kept = cas.put(b"referenced").unwrap();
+        let dropped = cas.put(b"unreferenced").unwrap();
+
+        assert_eq!(cas.sweep(std::slice::from_ref(&kept)).unwrap(), 1);
+        assert!(cas.contains(&kept));
+        assert!(!cas.contains(&dropped));
+    }
+
+    #[test]
+    fn test_build_history_pins_recent_builds() {
+        let tmp = TempDir::new().unwrap();
+        let history = BuildHistory::new(tmp.path()).unwrap();
+
+        let a = ContentHash::from_bytes(b"a");
+        let b = ContentHash::from_bytes(b"b");
+        let c = ContentHash::from_bytes(b"c");
+
+        history.record("busybox", [a.clone()]).unwrap();
+        history.record("busybox", [b.clone()]).unwrap();
+        history.record("zlib", [c.clone(), b.clone()]).unwrap();
+
+        let recent = history.recent(2).unwrap();
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[0].target, "zlib");
+
+        let pinned = history.pinned_hashes(2).unwrap();
+        assert!(pinned.contains(&b));
+        assert!(pinned.contains(&c));
+        assert!(!pinned.contains(&a));
+
+        assert_eq!(history.prune(2).unwrap(), 1);
+        assert_eq!(history.recent(10).unwrap().len(), 2);
+        assert_eq!(history.prune(2).unwrap(), 0);
+    }
 }
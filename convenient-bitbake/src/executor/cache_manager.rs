@@ -1,9 +1,9 @@
 //! Cache management commands - Bazel-inspired CLI for Bitzel cache
 
-#[allow(unused_imports)]
-use super::cache::{ActionCache, ContentAddressableStore};
-use super::types::ExecutionResult;
-use std::path::Path;
+use super::cache::{ActionCache, BuildHistory, ContentAddressableStore, EvictedObject, DEFAULT_KEEP_BUILDS};
+use super::types::{ContentHash, ExecutionResult, TaskOutput};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 
 /// Cache management operations
 pub struct CacheManager {
@@ -90,19 +90,174 @@ impl CacheManager {
         Ok(query)
     }
 
-    /// GC - Remove unreferenced CAS objects
-    /// (placeholder for future implementation)
-    pub fn gc(&self) -> ExecutionResult<GcStats> {
-        // TODO: Implement mark-and-sweep GC
-        // 1. Load all action cache entries
-        // 2. Mark all referenced CAS objects
-        // 3. Sweep unreferenced objects
+    /// GC - Remove unreferenced CAS objects, then evict LRU objects down to a size limit
+    ///
+    /// Objects referenced by the outputs of the last `keep_builds` successful
+    /// builds are pinned and never removed; older build records are pruned.
+    /// Without `max_size_bytes` nothing is evicted by size.
+    pub fn gc(&self, options: &GcOptions) -> ExecutionResult<GcStats> {
+        let cas_dir = self.cache_dir.join("cas");
+        let action_cache_dir = self.cache_dir.join("action-cache");
+        let builds_dir = self.cache_dir.join("builds");
+
+        if !cas_dir.exists() {
+            return Ok(GcStats::default());
+        }
+
+        let mut cas = ContentAddressableStore::new(&cas_dir)?;
+        let history = BuildHistory::new(&builds_dir)?;
+        cas.pin(history.pinned_hashes(options.keep_builds)?);
+        let builds_pruned = history.prune(options.keep_builds)?;
+
+        let before = cas.stats();
+
+        // Mark-and-sweep: drop objects no action cache entry references
+        if options.sweep_unreferenced {
+            let action_cache = ActionCache::new(&action_cache_dir)?;
+            cas.sweep_with_action_cache(&action_cache)?;
+        }
+        let after_sweep = cas.stats();
+
+        // Size bound: evict least recently used objects
+        let report = match options.max_size_bytes {
+            Some(max_bytes) => cas.enforce_max_size(max_bytes)?,
+            None => Default::default(),
+        };
+
+        let after = cas.stats();
 
         Ok(GcStats {
-            objects_removed: 0,
-            bytes_freed: 0,
+            objects_removed: before.object_count - after.object_count,
+            bytes_freed: before.total_size_bytes.saturating_sub(after.total_size_bytes),
+            unreferenced_removed: before.object_count - after_sweep.object_count,
+            evicted: report.evicted,
+            pinned_objects: report.pinned_objects,
+            remaining_bytes: after.total_size_bytes,
+            builds_pruned,
         })
     }
+
+    /// Verify - re-hash the CAS and cross-check the action cache against it
+    ///
+    /// Corrupt blobs, unreadable action cache entries and `.tmp` leftovers from
+    /// interrupted atomic writes are moved into `quarantine/`. Run this while
+    /// no build is using the cache, since in-flight writes also use `.tmp`.
+    pub fn verify(&self) -> ExecutionResult<VerifyReport> {
+        let cas_dir = self.cache_dir.join("cas");
+        let action_cache_dir = self.cache_dir.join("action-cache");
+        let quarantine_dir = self.cache_dir.join("quarantine");
+
+        let mut report = VerifyReport::default();
+
+        // Collect temp files before opening the CAS, which silently deletes them
+        for dir in [&cas_dir, &action_cache_dir] {
+            for path in files_matching(dir, |name| name.ends_with(".tmp")) {
+                report.temp_files.push(self.quarantine_file(&path, &quarantine_dir)?);
+            }
+        }
+
+        // Action cache entries that no longer parse would otherwise be skipped silently
+        for path in files_matching(&action_cache_dir, |name| name.ends_with(".json")) {
+            let parses = std::fs::read(&path)
+                .ok()
+                .is_some_and(|json| serde_json::from_slice::<TaskOutput>(&json).is_ok());
+            if !parses {
+                report.corrupt_actions.push(self.quarantine_file(&path, &quarantine_dir)?);
+            }
+        }
+
+        let mut cas = ContentAddressableStore::new(&cas_dir)?;
+        let integrity = cas.verify(&quarantine_dir.join("cas"))?;
+        report.objects_checked = integrity.objects_checked;
+        report.corrupt_objects = integrity.corrupt;
+
+        // Every output an action claims must still be in the CAS
+        let action_cache = ActionCache::new(&action_cache_dir)?;
+        for (signature, output) in action_cache.entries() {
+            report.actions_checked += 1;
+
+            let mut missing: Vec<(PathBuf, ContentHash)> = output
+                .output_files
+                .iter()
+                .filter(|(_, hash)| !cas.contains(hash))
+                .map(|(path, hash)| (path.clone(), hash.clone()))
+                .collect();
+
+            if !missing.is_empty() {
+                missing.sort_by(|a, b| a.0.cmp(&b.0));
+                report.broken_actions.push(BrokenAction { signature: signature.clone(), missing });
+            }
+        }
+        report.broken_actions.sort_by(|a, b| a.signature.as_str().cmp(b.signature.as_str()));
+
+        Ok(report)
+    }
+
+    /// Repair after [`verify`](Self::verify)
+    ///
+    /// `fetched` holds blobs re-downloaded (e.g. from a remote cache) for
+    /// [`VerifyReport::missing_blobs`]. Blobs matching their hash are restored
+    /// into the CAS; actions whose outputs are still missing afterwards are
+    /// invalidated so the next build re-runs them.
+    pub fn repair(
+        &self,
+        report: &VerifyReport,
+        fetched: HashMap<ContentHash, Vec<u8>>,
+    ) -> ExecutionResult<RepairStats> {
+        let mut stats = RepairStats::default();
+
+        let mut cas = ContentAddressableStore::new(self.cache_dir.join("cas"))?;
+        for (hash, data) in fetched {
+            if ContentHash::from_bytes(&data) == hash {
+                cas.put(&data)?;
+                stats.restored += 1;
+            } else {
+                stats.rejected += 1;
+            }
+        }
+
+        let mut action_cache = ActionCache::new(self.cache_dir.join("action-cache"))?;
+        for action in &report.broken_actions {
+            if action.missing.iter().any(|(_, hash)| !cas.contains(hash)) {
+                action_cache.invalidate(&action.signature)?;
+                stats.invalidated.push(action.signature.clone());
+            }
+        }
+
+        Ok(stats)
+    }
+
+    /// Move a file into the quarantine, keeping its path relative to the cache dir
+    fn quarantine_file(&self, path: &Path, quarantine_dir: &Path) -> ExecutionResult<PathBuf> {
+        let relative = path.strip_prefix(&self.cache_dir).unwrap_or(path);
+        let dest = quarantine_dir.join(relative);
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::rename(path, &dest)?;
+        Ok(dest)
+    }
+}
+
+/// Options for [`CacheManager::gc`]
+#[derive(Debug, Clone)]
+pub struct GcOptions {
+    /// Evict least recently used objects until the CAS fits in this many bytes
+    pub max_size_bytes: Option<u64>,
+    /// Pin objects referenced by this many most recent successful builds
+    pub keep_builds: usize,
+    /// Remove objects not referenced by any action cache entry
+    pub sweep_unreferenced: bool,
+}
+
+impl Default for GcOptions {
+    fn default() -> Self {
+        Self {
+            max_size_bytes: None,
+            keep_builds: DEFAULT_KEEP_BUILDS,
+            sweep_unreferenced: true,
+        }
+    }
 }
 
 #[derive(Debug, Default)]
@@ -131,10 +286,86 @@ pub struct CacheQuery {
 pub struct GcStats {
     pub objects_removed: usize,
     pub bytes_freed: u64,
+    /// Objects removed because no action cache entry referenced them
+    pub unreferenced_removed: usize,
+    /// Objects evicted to satisfy the size limit, oldest access first
+    pub evicted: Vec<EvictedObject>,
+    /// Pinned objects that were kept regardless of age
+    pub pinned_objects: usize,
+    /// CAS size after GC
+    pub remaining_bytes: u64,
+    /// Build records older than the last `keep_builds` that were deleted
+    pub builds_pruned: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    /// CAS objects re-hashed
+    pub objects_checked: usize,
+    /// CAS objects whose content didn't match their hash (quarantined)
+    pub corrupt_objects: Vec<ContentHash>,
+    /// Leftover `.tmp` files from interrupted writes (quarantined, new locations)
+    pub temp_files: Vec<PathBuf>,
+    /// Action cache entries checked
+    pub actions_checked: usize,
+    /// Action cache entries that failed to parse (quarantined, new locations)
+    pub corrupt_actions: Vec<PathBuf>,
+    /// Action cache entries referencing outputs missing from the CAS
+    pub broken_actions: Vec<BrokenAction>,
+}
+
+impl VerifyReport {
+    /// True if nothing was wrong with the cache
+    pub fn is_clean(&self) -> bool {
+        self.corrupt_objects.is_empty()
+            && self.temp_files.is_empty()
+            && self.corrupt_actions.is_empty()
+            && self.broken_actions.is_empty()
+    }
+
+    /// Blobs a repair should try to re-fetch: quarantined objects and missing outputs
+    pub fn missing_blobs(&self) -> Vec<ContentHash> {
+        let mut seen = HashSet::new();
+        self.corrupt_objects
+            .iter()
+            .chain(self.broken_actions.iter().flat_map(|a| a.missing.iter().map(|(_, hash)| hash)))
+            .filter(|hash| seen.insert((*hash).clone()))
+            .cloned()
+            .collect()
+    }
+}
+
+/// An action cache entry whose outputs are not all in the CAS
+#[derive(Debug, Clone)]
+pub struct BrokenAction {
+    pub signature: ContentHash,
+    /// Output paths and the blobs they need
+    pub missing: Vec<(PathBuf, ContentHash)>,
+}
+
+#[derive(Debug, Default)]
+pub struct RepairStats {
+    /// Blobs restored into the CAS
+    pub restored: usize,
+    /// Fetched blobs discarded because they didn't match their hash
+    pub rejected: usize,
+    /// Actions invalidated because their outputs couldn't be restored
+    pub invalidated: Vec<ContentHash>,
 }
 
 // Helper functions
 
+fn files_matching(dir: &Path, matches: impl Fn(&str) -> bool) -> Vec<PathBuf> {
+    walkdir::WalkDir::new(dir)
+        .follow_links(false)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter(|e| e.file_name().to_str().is_some_and(&matches))
+        .map(|e| e.into_path())
+        .collect()
+}
+
 fn count_files(dir: &Path) -> usize {
     walkdir::WalkDir::new(dir)
         .follow_links(false)
@@ -169,6 +400,8 @@ fn dir_size(dir: &Path) -> u64 {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::compression::CompressionAlgorithm;
+    use crate::executor::cache::CacheConfig;
     use tempfile::TempDir;
 
     #[test]
@@ -212,4 +445,150 @@ mod tests {
         assert_eq!(query.cas_objects, 1);
         assert!(query.cas_bytes > 0);
     }
+
+    #[test]
+    fn test_gc_max_size_keeps_pinned_builds() {
+        let tmp = TempDir::new().unwrap();
+        let cache_dir = tmp.path().join("cache");
+
+        let (old, kept) = {
+            let config = CacheConfig {
+                compression: CompressionAlgorithm::None,
+                ..CacheConfig::default()
+            };
+            let mut cas = ContentAddressableStore::with_config(cache_dir.join("cas"), config).unwrap();
+            let old = cas.put(&[0u8; 100]).unwrap();
+            let kept = cas.put(&[1u8; 100]).unwrap();
+            (old, kept)
+        };
+
+        // Only "kept" belongs to a successful build
+        BuildHistory::new(cache_dir.join("builds"))
+            .unwrap()
+            .record("busybox", [kept.clone()])
+            .unwrap();
+
+        let manager = CacheManager::new(&cache_dir);
+        let options = GcOptions {
+            max_size_bytes: Some(0),
+            keep_builds: 1,
+            sweep_unreferenced: false,
+        };
+        let stats = manager.gc(&options).unwrap();
+
+        assert_eq!(stats.objects_removed, 1);
+        assert_eq!(stats.evicted.len(), 1);
+        assert_eq!(stats.evicted[0].hash, old);
+        assert_eq!(stats.pinned_objects, 1);
+        assert_eq!(stats.remaining_bytes, 100);
+    }
+
+    #[test]
+    fn test_gc_prunes_build_history() {
+        let tmp = TempDir::new().unwrap();
+        let cache_dir = tmp.path().join("cache");
+
+        let (old, kept) = {
+            let mut cas = ContentAddressableStore::new(cache_dir.join("cas")).unwrap();
+            (cas.put(b"old build").unwrap(), cas.put(b"new build").unwrap())
+        };
+        let history = BuildHistory::new(cache_dir.join("builds")).unwrap();
+        history.record("busybox", [old.clone()]).unwrap();
+        history.record("busybox", [kept.clone()]).unwrap();
+
+        let manager = CacheManager::new(&cache_dir);
+        let options = GcOptions {
+            keep_builds: 1,
+            sweep_unreferenced: false,
+            ..GcOptions::default()
+        };
+        let stats = manager.gc(&options).unwrap();
+
+        // No size limit: nothing is evicted, only the old record goes
+        assert_eq!(stats.builds_pruned, 1);
+        assert_eq!(stats.objects_removed, 0);
+        assert!(stats.evicted.is_empty());
+        let recent = history.recent(10).unwrap();
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].outputs, vec![kept]);
+    }
+
+    #[test]
+    fn test_verify_and_repair() {
+        let tmp = TempDir::new().unwrap();
+        let cache_dir = tmp.path().join("cache");
+
+        let (intact, rotten) = {
+            let mut cas = ContentAddressableStore::new(cache_dir.join("cas")).unwrap();
+            (cas.put(b"intact output").unwrap(), cas.put(b"rotten output").unwrap())
+        };
+
+        // Simulate bit rot, an interrupted write and an unreadable action entry
+        let hex = rotten.to_hex();
+        let rotten_path = cache_dir.join("cas/sha256").join(&hex[0..2]).join(&hex[2..4]).join(&hex);
+        std::fs::write(&rotten_path, b"garbage").unwrap();
+        std::fs::write(cache_dir.join("cas/sha256/leftover.tmp"), b"partial").unwrap();
+        std::fs::create_dir_all(cache_dir.join("action-cache/00")).unwrap();
+        std::fs::write(cache_dir.join("action-cache/00/broken.json"), b"{").unwrap();
+
+        let mut action_cache = ActionCache::new(cache_dir.join("action-cache")).unwrap();
+        let mut actions = Vec::new();
+        for (name, hash) in [("good", &intact), ("bad", &rotten)] {
+            let signature = ContentHash::from_bytes(name.as_bytes());
+            let mut output_files = HashMap::new();
+            output_files.insert(PathBuf::from(name), hash.clone());
+            let output = TaskOutput {
+                signature: signature.clone(),
+                output_files,
+                stdout: String::new(),
+                stderr: String::new(),
+                exit_code: 0,
+                duration_ms: 0,
+            };
+            action_cache.put(signature.clone(), output).unwrap();
+            actions.push(signature);
+        }
+
+        let manager = CacheManager::new(&cache_dir);
+        let report = manager.verify().unwrap();
+
+        assert!(!report.is_clean());
+        assert_eq!(report.objects_checked, 2);
+        assert_eq!(report.corrupt_objects, vec![rotten.clone()]);
+        assert_eq!(report.temp_files.len(), 1);
+        assert_eq!(report.corrupt_actions.len(), 1);
+        assert_eq!(report.actions_checked, 2);
+        assert_eq!(report.broken_actions.len(), 1);
+        assert_eq!(report.broken_actions[0].signature, actions[1]);
+        assert_eq!(report.missing_blobs(), vec![rotten.clone()]);
+        assert!(cache_dir.join("quarantine/cas/sha256/leftover.tmp").exists());
+
+        // Nothing fetched: the broken action is invalidated
+        let stats = manager.repair(&report, HashMap::new()).unwrap();
+        assert_eq!(stats.restored, 0);
+        assert_eq!(stats.invalidated, vec![actions[1].clone()]);
+        assert!(!ActionCache::new(cache_dir.join("action-cache")).unwrap().contains(&actions[1]));
+
+        // A later verify finds a clean cache
+        assert!(manager.verify().unwrap().is_clean());
+    }
+
+    #[test]
+    fn test_repair_restores_fetched_blobs() {
+        let tmp = TempDir::new().unwrap();
+        let cache_dir = tmp.path().join("cache");
+        let manager = CacheManager::new(&cache_dir);
+
+        let hash = ContentHash::from_bytes(b"from remote");
+        let report = VerifyReport { corrupt_objects: vec![hash.clone()], ..Default::default() };
+
+        let mut fetched = HashMap::new();
+        fetched.insert(hash.clone(), b"from remote".to_vec());
+        fetched.insert(ContentHash::from_bytes(b"other"), b"tampered".to_vec());
+
+        let stats = manager.repair(&report, fetched).unwrap();
+        assert_eq!(stats.restored, 1);
+        assert_eq!(stats.rejected, 1);
+        assert!(ContentAddressableStore::new(cache_dir.join("cas")).unwrap().contains(&hash));
+    }
 }
@@ -0,0 +1,237 @@
+//! Interactive debug shell inside a task's sandbox
+//!
+//! Reconstructs the environment a task runs in - the same `SandboxSpec`
+//! mounts, the executor's shared sysroot, `TaskSpec.env` plus the BitBake
+//! helper prelude, and the same namespaces as `native_sandbox` - and drops
+//! the user into an interactive bash there. Optionally the task script is
+//! re-run with `set -x` before the prompt appears.
+
+use super::bbhelpers::get_bb_helpers;
+use super::executor::{apply_sandbox_paths, sandbox_spec_for_task, sysroot_dir};
+use super::native_sandbox::spawn_interactive_in_namespace;
+use super::sandbox::SandboxManager;
+use super::types::{ExecutionResult, SandboxSpec, TaskSpec};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// Options for spawning a devshell
+#[derive(Debug, Clone)]
+pub struct DevShellOptions {
+    /// Re-run the task script with `set -x` before handing over the prompt
+    pub rerun_task: bool,
+
+    /// Shell binary to launch
+    pub shell: PathBuf,
+}
+
+impl Default for DevShellOptions {
+    fn default() -> Self {
+        Self {
+            rerun_task: false,
+            shell: PathBuf::from("/bin/bash"),
+        }
+    }
+}
+
+/// A prepared devshell sandbox for a single task
+pub struct DevShell {
+    /// Task being debugged
+    spec: TaskSpec,
+
+    /// Sandbox specification (mounts and environment)
+    sandbox_spec: SandboxSpec,
+
+    /// Root directory of the sandbox on the host
+    sandbox_root: PathBuf,
+
+    /// Launch options
+    options: DevShellOptions,
+}
+
+impl DevShell {
+    /// Build the sandbox for `spec` in the `TaskExecutor` cache at `cache_dir`
+    ///
+    /// The sandbox lives next to the executor's and sees the same environment,
+    /// RECIPE_SYSROOT being the shared sysroot the executor stages the
+    /// `do_populate_sysroot` outputs of built recipes into.
+    pub fn prepare(spec: &TaskSpec, cache_dir: &Path, options: DevShellOptions) -> ExecutionResult<Self> {
+        let manager = SandboxManager::new(cache_dir.join("sandboxes"))?;
+        let mut sandbox_spec = sandbox_spec_for_task(spec, manager.sandbox_dir())?;
+        let sandbox = manager.create_sandbox(sandbox_spec.clone())?;
+        let sandbox_root = sandbox.root().to_path_buf();
+
+        let sysroot = sysroot_dir(cache_dir);
+        fs::create_dir_all(&sysroot)?;
+        apply_sandbox_paths(&mut sandbox_spec.env, &sandbox_root, &sysroot);
+
+        info!("Prepared devshell for {}:{} in {}", spec.recipe, spec.name, sandbox_root.display());
+
+        Ok(Self {
+            spec: spec.clone(),
+            sandbox_spec,
+            sandbox_root,
+            options,
+        })
+    }
+
+    /// Root directory of the sandbox on the host
+    pub fn sandbox_root(&self) -> &Path {
+        &self.sandbox_root
+    }
+
+    /// Environment the shell will start with
+    pub fn env(&self) -> &std::collections::HashMap<String, String> {
+        &self.sandbox_spec.env
+    }
+
+    /// Contents of the bash rcfile that sets up the shell
+    ///
+    /// Sources the task prelude, adds the BitBake helpers, relaxes the
+    /// prelude's `set -euo pipefail` so typos don't end the session, and
+    /// optionally re-runs the task script in a traced subshell.
+    pub fn rcfile(&self) -> String {
+        let mut rc = String::new();
+
+        rc.push_str("# hitzeleiter devshell\n");
+        rc.push_str("[ -f /hitzeleiter/prelude.sh ] && . /hitzeleiter/prelude.sh\n");
+        rc.push_str(get_bb_helpers());
+        rc.push_str("\nset +e +u +o pipefail\n");
+        rc.push_str(&format!(
+            "export PS1='[devshell {}:{}] \\w \\$ '\n",
+            self.spec.recipe, self.spec.name
+        ));
+
+        if self.options.rerun_task {
+            rc.push_str(&format!(
+                "echo '>>> Re-running {} with set -x'\n",
+                self.spec.name
+            ));
+            rc.push_str("( set -x; . \"$WORKDIR/temp/run.");
+            rc.push_str(&self.spec.name);
+            rc.push_str("\" )\n");
+            rc.push_str("echo \">>> Task exited with status $?\"\n");
+        }
+
+        rc
+    }
+
+    /// Launch the interactive shell and wait for it to exit
+    ///
+    /// Returns the shell's exit code.
+    pub fn spawn(&self) -> ExecutionResult<i32> {
+        let work_dir = self.sandbox_root.join("work");
+        let temp_dir = work_dir.join("temp");
+        fs::create_dir_all(&temp_dir)?;
+
+        // Keep the task script next to the logs, as BitBake does with run.do_*
+        fs::write(temp_dir.join(format!("run.{}", self.spec.name)), &self.spec.script)?;
+
+        let rcfile = temp_dir.join("devshell.rc");
+        fs::write(&rcfile, self.rcfile())?;
+
+        let argv = vec![
+            self.options.shell.to_string_lossy().to_string(),
+            "--rcfile".to_string(),
+            rcfile.to_string_lossy().to_string(),
+            "-i".to_string(),
+        ];
+
+        let work_dir = work_dir.canonicalize().unwrap_or(work_dir);
+        let exit_code = spawn_interactive_in_namespace(
+            &argv,
+            &work_dir,
+            &self.sandbox_spec.env,
+            self.sandbox_spec.network_policy,
+        )?;
+
+        if exit_code != 0 {
+            warn!("Devshell exited with status {}", exit_code);
+        }
+
+        Ok(exit_code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::types::{ExecutionMode, NetworkPolicy, ResourceLimits};
+    use crate::executor::TaskExecutor;
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+
+    fn task_spec(workdir: PathBuf) -> TaskSpec {
+        let mut env = HashMap::new();
+        env.insert("PN".to_string(), "busybox".to_string());
+
+        TaskSpec {
+            name: "do_compile".to_string(),
+            recipe: "busybox".to_string(),
+            script: "oe_runmake".to_string(),
+            workdir,
+            env,
+            outputs: vec![],
+            timeout: None,
+            execution_mode: ExecutionMode::Shell,
+            network_policy: NetworkPolicy::Isolated,
+            resource_limits: ResourceLimits::default(),
+            flags: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_prepare_reconstructs_environment() {
+        let tmp = TempDir::new().unwrap();
+        let spec = task_spec(tmp.path().join("workdir"));
+
+        let shell = DevShell::prepare(&spec, &tmp.path().join("cache"), DevShellOptions::default()).unwrap();
+
+        assert_eq!(shell.env().get("PN").map(String::as_str), Some("busybox"));
+        assert!(shell.env()["WORKDIR"].ends_with("work"));
+        assert!(Path::new(&shell.env()["RECIPE_SYSROOT"]).is_dir());
+        assert_eq!(shell.env()["STAGING_DIR_HOST"], shell.env()["RECIPE_SYSROOT"]);
+    }
+
+    #[test]
+    fn test_prepare_sees_built_dependency_sysroot() {
+        let tmp = TempDir::new().unwrap();
+        let cache_dir = tmp.path().join("cache");
+
+        // Build zlib's sysroot with the executor, as `hitzeleiter build` does
+        let mut zlib = task_spec(tmp.path().join("zlib"));
+        zlib.name = "do_populate_sysroot".to_string();
+        zlib.recipe = "zlib".to_string();
+        zlib.script = "mkdir -p \"$SYSROOT_DESTDIR/usr/include\"\n\
+                       echo '#define ZLIB_VERSION' | tee \"$SYSROOT_DESTDIR/usr/include/zlib.h\""
+            .to_string();
+        fs::create_dir_all(&zlib.workdir).unwrap();
+        let mut executor = TaskExecutor::new(&cache_dir).unwrap();
+        assert_eq!(executor.execute_task(zlib.clone()).unwrap().exit_code, 0);
+
+        let spec = task_spec(tmp.path().join("workdir"));
+        let shell = DevShell::prepare(&spec, &cache_dir, DevShellOptions::default()).unwrap();
+        let header = Path::new(&shell.env()["RECIPE_SYSROOT"]).join("usr/include/zlib.h");
+        assert!(fs::read_to_string(&header).unwrap().contains("ZLIB_VERSION"));
+
+        // A cache hit stages the sysroot again from the CAS
+        fs::remove_dir_all(executor.sysroot()).unwrap();
+        executor.execute_task(zlib).unwrap();
+        assert_eq!(executor.stats().cache_hits, 1);
+        assert!(header.is_file());
+    }
+
+    #[test]
+    fn test_rcfile_rerun() {
+        let tmp = TempDir::new().unwrap();
+        let spec = task_spec(tmp.path().join("workdir"));
+
+        let options = DevShellOptions { rerun_task: true, ..DevShellOptions::default() };
+        let shell = DevShell::prepare(&spec, &tmp.path().join("cache"), options).unwrap();
+
+        let rc = shell.rcfile();
+        assert!(rc.contains("oe_runmake()"));
+        assert!(rc.contains("set +e +u +o pipefail"));
+        assert!(rc.contains("( set -x; . \"$WORKDIR/temp/run.do_compile\" )"));
+    }
+}
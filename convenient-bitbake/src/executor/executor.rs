@@ -1,18 +1,25 @@
 //! Main task executor - brings together caching, sandboxing, and execution
 
-use super::cache::{ActionCache, ContentAddressableStore};
+use super::cache::{ActionCache, BuildHistory, ContentAddressableStore};
 use super::direct_executor;
 use super::sandbox::SandboxManager;
 use super::script_analyzer;
+use super::sstate::{self, SigInfo, SstateArchiveName, SstateMirror};
 use super::types::{
     ContentHash, ExecutionError, ExecutionMode, ExecutionResult, NetworkPolicy, ResourceLimits,
     SandboxSpec, TaskOutput, TaskSignature, TaskSpec,
 };
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::time::Instant;
 use tracing::{debug, info, warn};
 
+/// Task whose SYSROOT_DESTDIR is staged into the shared sysroot
+const SYSROOT_TASK: &str = "populate_sysroot";
+
+/// SYSROOT_DESTDIR within a sandbox's WORKDIR, as in BitBake
+const SYSROOT_DESTDIR: &str = "sysroot-destdir";
+
 /// Main task executor with caching and sandboxing
 pub struct TaskExecutor {
     /// Content-addressable store for caching files
@@ -21,6 +28,16 @@ pub struct TaskExecutor {
     action_cache: ActionCache,
     /// Sandbox manager
     sandbox_manager: SandboxManager,
+    /// Shared sysroot the `do_populate_sysroot` outputs are staged into
+    sysroot: PathBuf,
+    /// History of successful builds (pins their outputs in the CAS)
+    build_history: BuildHistory,
+    /// CAS objects referenced by successful tasks in the current build
+    build_outputs: HashSet<ContentHash>,
+    /// BitBake sstate cache tier (SSTATE_DIR + SSTATE_MIRRORS)
+    sstate: Option<SstateMirror>,
+    /// Export executed tasks into SSTATE_DIR
+    export_sstate: bool,
     /// Statistics
     stats: ExecutionStats,
 }
@@ -32,20 +49,40 @@ impl TaskExecutor {
         let cas_dir = cache_dir.join("cas");
         let action_cache_dir = cache_dir.join("action-cache");
         let sandbox_dir = cache_dir.join("sandboxes");
+        let sysroot = sysroot_dir(cache_dir);
+        std::fs::create_dir_all(&sysroot)?;
 
         info!("Initializing task executor");
         debug!("CAS directory: {}", cas_dir.display());
         debug!("Action cache directory: {}", action_cache_dir.display());
         debug!("Sandbox directory: {}", sandbox_dir.display());
 
+        let mut cas = ContentAddressableStore::new(cas_dir)?;
+        let build_history = BuildHistory::new(cache_dir.join("builds"))?;
+        cas.pin(build_history.pinned_hashes(cas.config().pin_last_builds)?);
+
         Ok(Self {
-            cas: ContentAddressableStore::new(cas_dir)?,
+            cas,
             action_cache: ActionCache::new(action_cache_dir)?,
             sandbox_manager: SandboxManager::new(sandbox_dir)?,
+            sysroot,
+            build_history,
+            build_outputs: HashSet::new(),
+            sstate: None,
+            export_sstate: false,
             stats: ExecutionStats::default(),
         })
     }
 
+    /// Use a BitBake sstate cache as a tier behind the action cache
+    ///
+    /// With `export`, successfully executed tasks are also written to the
+    /// mirror's SSTATE_DIR in sstate layout.
+    pub fn set_sstate(&mut self, mirror: SstateMirror, export: bool) {
+        self.sstate = Some(mirror);
+        self.export_sstate = export;
+    }
+
     /// Execute a task with caching
     pub fn execute_task(&mut self, spec: TaskSpec) -> ExecutionResult<TaskOutput> {
         info!(
@@ -53,23 +90,64 @@ impl TaskExecutor {
             spec.recipe, spec.name, spec.execution_mode
         );
 
+        // [noexec] tasks only order their dependencies
+        if spec.flags.noexec {
+            debug!("{}:{} is noexec, nothing to run", spec.recipe, spec.name);
+            self.stats.noexec_tasks += 1;
+            return Ok(TaskOutput {
+                signature: ContentHash::from_bytes(format!("{}:{}", spec.recipe, spec.name).as_bytes()),
+                output_files: HashMap::new(),
+                stdout: String::new(),
+                stderr: String::new(),
+                exit_code: 0,
+                duration_ms: 0,
+            });
+        }
+
         // 1. Compute signature
         let mut signature = self.compute_signature(&spec)?;
         let sig_hash = signature.compute();
 
         debug!("Task signature: {}", sig_hash);
 
-        // 2. Check cache
-        if let Some(cached) = self.action_cache.get(&sig_hash) {
-            info!("Cache HIT for {}:{}", spec.recipe, spec.name);
-            self.stats.cache_hits += 1;
-            return Ok(cached.clone());
+        // [nostamp] tasks are never up to date
+        if spec.flags.nostamp {
+            info!("{}:{} is nostamp, not using the cache", spec.recipe, spec.name);
+        } else {
+            // 2. Check cache
+            if let Some(cached) = self.action_cache.get(&sig_hash).cloned() {
+                info!("Cache HIT for {}:{}", spec.recipe, spec.name);
+                self.stats.cache_hits += 1;
+                if cached.success() {
+                    self.build_outputs.extend(cached.output_files.values().cloned());
+                    self.stage_sysroot(&spec, &cached)?;
+                }
+                return Ok(cached);
+            }
+
+            // 3. Check the sstate tier
+            if let Some(restored) = self.restore_from_sstate(&spec, &sig_hash)? {
+                self.stats.cache_hits += 1;
+                self.stats.sstate_hits += 1;
+                self.build_outputs.extend(restored.output_files.values().cloned());
+                self.stage_sysroot(&spec, &restored)?;
+                return Ok(restored);
+            }
+
+            info!("Cache MISS for {}:{}", spec.recipe, spec.name);
+            self.stats.cache_misses += 1;
         }
 
-        info!("Cache MISS for {}:{}", spec.recipe, spec.name);
-        self.stats.cache_misses += 1;
+        // [cleandirs], [dirs] and [lockfiles], held until the task is done;
+        // sandboxed tasks apply them inside their sandbox (see execute_sandboxed)
+        let sandboxed = matches!(spec.execution_mode, ExecutionMode::Shell | ExecutionMode::Python);
+        let (_locks, spec) = if sandboxed {
+            (Vec::new(), spec)
+        } else {
+            (prepare_task_dirs(&spec)?, with_task_cwd(spec))
+        };
 
-        // 3. Execute based on execution mode
+        // 4. Execute based on execution mode
         let (result_stdout, result_stderr, result_exit_code, output_files, duration) =
             match spec.execution_mode {
                 ExecutionMode::DirectRust => {
@@ -98,8 +176,20 @@ impl TaskExecutor {
             duration_ms: duration,
         };
 
-        // 4. Store in cache
-        self.action_cache.put(sig_hash, task_output.clone())?;
+        // 5. Store in cache
+        if !spec.flags.nostamp {
+            self.action_cache.put(sig_hash, task_output.clone())?;
+        }
+        if task_output.success() {
+            self.build_outputs.extend(task_output.output_files.values().cloned());
+            self.stage_sysroot(&spec, &task_output)?;
+
+            if self.export_sstate
+                && let Err(e) = self.export_to_sstate(&spec, &task_output)
+            {
+                warn!("sstate export failed for {}:{}: {}", spec.recipe, spec.name, e);
+            }
+        }
 
         info!("Task completed in {}ms", task_output.duration_ms);
         self.stats.tasks_executed += 1;
@@ -107,6 +197,70 @@ impl TaskExecutor {
         Ok(task_output)
     }
 
+    /// Stage the SYSROOT_DESTDIR of a successful `do_populate_sysroot` into the shared sysroot
+    ///
+    /// The files are restored from the CAS, so tasks served from a cache stage
+    /// them as well. Every later task sees them as RECIPE_SYSROOT.
+    fn stage_sysroot(&mut self, spec: &TaskSpec, output: &TaskOutput) -> ExecutionResult<()> {
+        if !is_sysroot_task(spec) {
+            return Ok(());
+        }
+
+        let destdir = Path::new("/work").join(SYSROOT_DESTDIR);
+        let mut staged = 0;
+        for (path, hash) in &output.output_files {
+            if let Ok(rel_path) = path.strip_prefix(&destdir) {
+                self.cas.get_file(hash, &self.sysroot.join(rel_path))?;
+                staged += 1;
+            }
+        }
+        debug!("Staged {} sysroot files of {}", staged, spec.recipe);
+        Ok(())
+    }
+
+    /// Import the task's sstate archive, if the sstate tier has one
+    fn restore_from_sstate(
+        &mut self,
+        spec: &TaskSpec,
+        sig_hash: &ContentHash,
+    ) -> ExecutionResult<Option<TaskOutput>> {
+        let Some(mirror) = &self.sstate else {
+            return Ok(None);
+        };
+
+        let unihash = sstate::unihash_for_task(spec, sig_hash);
+        let Some(archive) = mirror.find(&unihash, &spec.name) else {
+            return Ok(None);
+        };
+
+        match sstate::import_archive(&archive, &mut self.cas, sig_hash.clone()) {
+            Ok(output) => {
+                info!("sstate HIT for {}:{} ({})", spec.recipe, spec.name, archive.display());
+                self.action_cache.put(sig_hash.clone(), output.clone())?;
+                Ok(Some(output))
+            }
+            Err(e) => {
+                warn!("Ignoring unreadable sstate archive {}: {}", archive.display(), e);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Write a task's outputs to SSTATE_DIR so plain BitBake can consume them
+    fn export_to_sstate(&mut self, spec: &TaskSpec, output: &TaskOutput) -> ExecutionResult<()> {
+        let Some(mirror) = &self.sstate else {
+            return Ok(());
+        };
+
+        let unihash = sstate::unihash_for_task(spec, &output.signature);
+        let name = SstateArchiveName::for_task(spec, &unihash);
+        let siginfo = SigInfo::for_task(spec, &output.signature, &unihash);
+
+        let archive = sstate::export_output(output, &mut self.cas, &name, &siginfo, &mirror.sstate_dir)?;
+        debug!("Exported {}", archive.display());
+        Ok(())
+    }
+
     /// Execute task using direct Rust calls (no sandbox)
     fn execute_direct_rust(
         &mut self,
@@ -271,26 +425,13 @@ impl TaskExecutor {
         // IMPORTANT: Since mount namespaces are currently disabled in native_sandbox.rs,
         // we must use actual host absolute paths to the sandbox directories, not namespace-relative paths.
         // When mount namespaces are enabled in the future, these should be changed back to /work, /work/src, etc.
-        // Canonicalize to get absolute path (sandbox_root might be relative like "build/hitzeleiter-cache/sandboxes/XXX")
-        let sandbox_root_abs = sandbox_root.canonicalize()
-            .unwrap_or_else(|_| std::env::current_dir().unwrap_or_default().join(&sandbox_root));
-        let work_dir = sandbox_root_abs.join("work");
-        sandbox_spec.env.insert(
-            "WORKDIR".to_string(),
-            work_dir.to_string_lossy().to_string(),
-        );
-        sandbox_spec.env.insert(
-            "S".to_string(),
-            work_dir.join("src").to_string_lossy().to_string(),
-        );
-        sandbox_spec.env.insert(
-            "B".to_string(),
-            work_dir.join("build").to_string_lossy().to_string(),
-        );
-        sandbox_spec.env.insert(
-            "D".to_string(),
-            work_dir.join("outputs").to_string_lossy().to_string(),
-        );
+        apply_sandbox_paths(&mut sandbox_spec.env, &sandbox_root, &self.sysroot);
+
+        // [dirs], [cleandirs] and [lockfiles] name WORKDIR/S/B/D paths, which now
+        // live in the sandbox; the task's cd has to go there as well
+        let sandbox_task = sandbox_task_spec(spec, &sandbox_spec.env);
+        let _locks = prepare_task_dirs(&sandbox_task)?;
+        sandbox.update_command(vec![with_task_cwd(sandbox_task).script]);
         sandbox.update_env(sandbox_spec.env);
 
         info!("Executing in sandbox: {}", sandbox_root.display());
@@ -376,53 +517,22 @@ impl TaskExecutor {
 
     /// Prepare sandbox spec from task spec
     fn prepare_sandbox(&self, spec: &TaskSpec) -> ExecutionResult<SandboxSpec> {
-        let mut sandbox_spec = SandboxSpec::new(vec![spec.script.clone()]);
-
-        // Mount workdir as read-write
-        sandbox_spec.rw_dirs.push(PathBuf::from("/work"));
-        sandbox_spec.rw_dirs.push(PathBuf::from("/work/outputs"));
-        sandbox_spec.rw_dirs.push(PathBuf::from("/work/build"));
-        sandbox_spec.rw_dirs.push(PathBuf::from("/work/temp"));
-        sandbox_spec.cwd = PathBuf::from("/work");
-
-        // Create prelude.sh for sandboxed execution
-        const PRELUDE_CONTENT: &str = include_str!("prelude.sh");
-        let prelude_path = self.sandbox_manager.sandbox_dir().join("prelude.sh");
-        std::fs::write(&prelude_path, PRELUDE_CONTENT)?;
-        sandbox_spec.ro_inputs.push((
-            prelude_path,
-            PathBuf::from("/hitzeleiter/prelude.sh"),
-        ));
-
-        // If workdir exists, mount it
-        if spec.workdir.exists() {
-            sandbox_spec.ro_inputs.push((
-                spec.workdir.clone(),
-                PathBuf::from("/work/src"),
-            ));
-        }
-
-        // Add declared outputs
-        for output in &spec.outputs {
-            // If output is already an absolute path, use it as-is
-            if output.is_absolute() {
-                sandbox_spec.outputs.push(output.clone());
-            } else {
-                sandbox_spec.outputs.push(PathBuf::from("/work/outputs").join(output));
-            }
-        }
-
-        // Copy environment
-        sandbox_spec.env = spec.env.clone();
-
-        // BitBake environment variables will be set after sandbox creation with actual paths
+        sandbox_spec_for_task(spec, self.sandbox_manager.sandbox_dir())
+    }
 
-        Ok(sandbox_spec)
+    /// Record the current build as successful
+    ///
+    /// The outputs of the most recent successful builds are pinned in the CAS
+    /// and survive size-bounded LRU eviction.
+    pub fn record_successful_build(&mut self, target: &str) -> ExecutionResult<()> {
+        let outputs = std::mem::take(&mut self.build_outputs);
+        self.cas.pin(outputs.iter().cloned());
+        self.build_history.record(target, outputs)
     }
 
     /// Restore task outputs to a directory
     pub fn restore_outputs(
-        &self,
+        &mut self,
         output: &TaskOutput,
         dest_dir: &Path,
     ) -> ExecutionResult<()> {
@@ -436,6 +546,11 @@ impl TaskExecutor {
         Ok(())
     }
 
+    /// Shared sysroot the `do_populate_sysroot` outputs are staged into
+    pub fn sysroot(&self) -> &Path {
+        &self.sysroot
+    }
+
     /// Get executor statistics
     pub fn stats(&self) -> &ExecutionStats {
         &self.stats
@@ -457,12 +572,229 @@ impl TaskExecutor {
     }
 }
 
+/// Build the sandbox spec used to execute a task
+///
+/// Shared by [`TaskExecutor`] and the devshell so that an interactive
+/// session sees exactly the mounts a real task execution would.
+pub(crate) fn sandbox_spec_for_task(spec: &TaskSpec, sandbox_dir: &Path) -> ExecutionResult<SandboxSpec> {
+    let mut sandbox_spec = SandboxSpec::new(vec![spec.script.clone()]);
+
+    // Mount workdir as read-write
+    sandbox_spec.rw_dirs.push(PathBuf::from("/work"));
+    sandbox_spec.rw_dirs.push(PathBuf::from("/work/outputs"));
+    sandbox_spec.rw_dirs.push(PathBuf::from("/work/build"));
+    sandbox_spec.rw_dirs.push(PathBuf::from("/work/temp"));
+    sandbox_spec.cwd = PathBuf::from("/work");
+
+    // Create prelude.sh for sandboxed execution
+    const PRELUDE_CONTENT: &str = include_str!("prelude.sh");
+    let prelude_path = sandbox_dir.join("prelude.sh");
+    std::fs::write(&prelude_path, PRELUDE_CONTENT)?;
+    sandbox_spec.ro_inputs.push((
+        prelude_path,
+        PathBuf::from("/hitzeleiter/prelude.sh"),
+    ));
+
+    // If workdir exists, mount it
+    if spec.workdir.exists() {
+        sandbox_spec.ro_inputs.push((
+            spec.workdir.clone(),
+            PathBuf::from("/work/src"),
+        ));
+    }
+
+    // do_populate_sysroot hands its SYSROOT_DESTDIR over to the shared sysroot
+    if is_sysroot_task(spec) {
+        let destdir = PathBuf::from("/work").join(SYSROOT_DESTDIR);
+        sandbox_spec.rw_dirs.push(destdir.clone());
+        sandbox_spec.outputs.push(destdir);
+    }
+
+    // Add declared outputs
+    for output in &spec.outputs {
+        // If output is already an absolute path, use it as-is
+        if output.is_absolute() {
+            sandbox_spec.outputs.push(output.clone());
+        } else {
+            sandbox_spec.outputs.push(PathBuf::from("/work/outputs").join(output));
+        }
+    }
+
+    // Copy environment
+    sandbox_spec.env = spec.env.clone();
+
+    // BitBake environment variables will be set after sandbox creation with actual paths
+
+    Ok(sandbox_spec)
+}
+
+/// Apply a task's `[cleandirs]`, `[dirs]` and `[lockfiles]` flags like BitBake
+///
+/// Relative paths are taken from the task's workdir. The returned lock files
+/// keep the `[lockfiles]` locks until they are dropped.
+fn prepare_task_dirs(spec: &TaskSpec) -> ExecutionResult<Vec<std::fs::File>> {
+    let resolve = |path: &PathBuf| spec.workdir.join(path);
+
+    for dir in spec.flags.cleandirs.iter().map(resolve) {
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir)?;
+        }
+        std::fs::create_dir_all(&dir)?;
+    }
+    for dir in spec.flags.dirs.iter().map(resolve) {
+        std::fs::create_dir_all(&dir)?;
+    }
+
+    // Always lock in the same order so two tasks sharing lockfiles cannot deadlock
+    let mut lockfiles: Vec<PathBuf> = spec.flags.lockfiles.iter().map(resolve).collect();
+    lockfiles.sort();
+    lockfiles.dedup();
+
+    let mut locks = Vec::new();
+    for lockfile in lockfiles {
+        debug!("{}:{} taking lock {}", spec.recipe, spec.name, lockfile.display());
+        #[cfg(unix)]
+        locks.push(super::cache::acquire_lock(&lockfile)?);
+        #[cfg(not(unix))]
+        warn!("Lockfile {} not supported on this platform", lockfile.display());
+    }
+
+    Ok(locks)
+}
+
+/// Run the task from its last `[dirs]` entry, as BitBake does
+///
+/// DirectRust tasks are interpreted without a shell and keep their own workdir.
+fn with_task_cwd(mut spec: TaskSpec) -> TaskSpec {
+    if spec.execution_mode != ExecutionMode::DirectRust
+        && let Some(cwd) = spec.flags.cwd()
+    {
+        let cd = format!("cd '{}'\n", spec.workdir.join(cwd).display());
+        let body_start = if spec.script.starts_with("#!") {
+            if !spec.script.contains('\n') {
+                spec.script.push('\n');
+            }
+            spec.script.find('\n').map_or(0, |pos| pos + 1)
+        } else {
+            0
+        };
+        spec.script.insert_str(body_start, &cd);
+    }
+    spec
+}
+
+/// The task as seen inside a created sandbox
+///
+/// `[dirs]`, `[cleandirs]` and `[lockfiles]` are mapped with [`sandbox_path`]
+/// and the workdir becomes the sandbox's WORKDIR. `sandbox_env` is the task
+/// environment after [`apply_sandbox_paths`].
+fn sandbox_task_spec(spec: &TaskSpec, sandbox_env: &HashMap<String, String>) -> TaskSpec {
+    let map = |paths: &[PathBuf]| -> Vec<PathBuf> {
+        paths.iter().map(|path| sandbox_path(path, spec, sandbox_env)).collect()
+    };
+
+    let mut sandbox_task = spec.clone();
+    sandbox_task.flags.dirs = map(&spec.flags.dirs);
+    sandbox_task.flags.cleandirs = map(&spec.flags.cleandirs);
+    sandbox_task.flags.lockfiles = map(&spec.flags.lockfiles);
+    if let Some(workdir) = sandbox_env.get("WORKDIR") {
+        sandbox_task.workdir = PathBuf::from(workdir);
+    }
+    sandbox_task
+}
+
+/// Map a host path of the task into its sandbox
+///
+/// Paths under the task's WORKDIR, S, B or D move to where
+/// [`apply_sandbox_paths`] points those variables, the most specific variable
+/// winning (S usually lives under WORKDIR). Relative paths are taken from
+/// WORKDIR. Anything else, such as a lock shared under TMPDIR, is left alone.
+fn sandbox_path(path: &Path, spec: &TaskSpec, sandbox_env: &HashMap<String, String>) -> PathBuf {
+    if path.is_relative() {
+        return match sandbox_env.get("WORKDIR") {
+            Some(workdir) => Path::new(workdir).join(path),
+            None => spec.workdir.join(path),
+        };
+    }
+
+    let mut best: Option<(usize, PathBuf)> = None;
+    for var in ["WORKDIR", "S", "B", "D"] {
+        let host = match spec.env.get(var) {
+            Some(value) => PathBuf::from(value),
+            None if var == "WORKDIR" => spec.workdir.clone(),
+            None => continue,
+        };
+        let (Some(sandbox), Ok(rest)) = (sandbox_env.get(var), path.strip_prefix(&host)) else {
+            continue;
+        };
+        let depth = host.components().count();
+        if best.as_ref().is_none_or(|(best_depth, _)| depth > *best_depth) {
+            let mapped = if rest.as_os_str().is_empty() {
+                PathBuf::from(sandbox)
+            } else {
+                Path::new(sandbox).join(rest)
+            };
+            best = Some((depth, mapped));
+        }
+    }
+
+    best.map_or_else(|| path.to_path_buf(), |(_, mapped)| mapped)
+}
+
+/// Shared sysroot of the executor using `cache_dir`
+pub(crate) fn sysroot_dir(cache_dir: &Path) -> PathBuf {
+    cache_dir.join("sysroot")
+}
+
+/// Whether the task is `do_populate_sysroot` (with or without the `do_` prefix)
+fn is_sysroot_task(spec: &TaskSpec) -> bool {
+    spec.name.strip_prefix("do_").unwrap_or(&spec.name) == SYSROOT_TASK
+}
+
+/// Point WORKDIR/S/B/D and SYSROOT_DESTDIR at the work directories of a
+/// created sandbox, and RECIPE_SYSROOT/STAGING_DIR_HOST at the shared sysroot
+pub(crate) fn apply_sandbox_paths(env: &mut HashMap<String, String>, sandbox_root: &Path, sysroot: &Path) {
+    // Canonicalize to get absolute path (sandbox_root might be relative like "build/hitzeleiter-cache/sandboxes/XXX")
+    let sandbox_root_abs = sandbox_root.canonicalize()
+        .unwrap_or_else(|_| std::env::current_dir().unwrap_or_default().join(sandbox_root));
+    let work_dir = sandbox_root_abs.join("work");
+    env.insert(
+        "WORKDIR".to_string(),
+        work_dir.to_string_lossy().to_string(),
+    );
+    env.insert(
+        "S".to_string(),
+        work_dir.join("src").to_string_lossy().to_string(),
+    );
+    env.insert(
+        "B".to_string(),
+        work_dir.join("build").to_string_lossy().to_string(),
+    );
+    env.insert(
+        "D".to_string(),
+        work_dir.join("outputs").to_string_lossy().to_string(),
+    );
+    env.insert(
+        "SYSROOT_DESTDIR".to_string(),
+        work_dir.join(SYSROOT_DESTDIR).to_string_lossy().to_string(),
+    );
+
+    let sysroot = sysroot.canonicalize()
+        .unwrap_or_else(|_| std::env::current_dir().unwrap_or_default().join(sysroot));
+    env.insert("RECIPE_SYSROOT".to_string(), sysroot.to_string_lossy().to_string());
+    env.insert("STAGING_DIR_HOST".to_string(), sysroot.to_string_lossy().to_string());
+}
+
 /// Execution statistics
 #[derive(Debug, Default, Clone)]
 pub struct ExecutionStats {
     pub tasks_executed: usize,
     pub cache_hits: usize,
     pub cache_misses: usize,
+    /// Cache hits served from the sstate tier (included in `cache_hits`)
+    pub sstate_hits: usize,
+    /// `[noexec]` tasks passed through without running anything
+    pub noexec_tasks: usize,
 }
 
 impl ExecutionStats {
@@ -479,6 +811,7 @@ impl ExecutionStats {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::executor::types::TaskFlags;
     use tempfile::TempDir;
 
     #[test]
@@ -497,6 +830,7 @@ mod tests {
             execution_mode: ExecutionMode::Shell,
             network_policy: NetworkPolicy::Isolated,
             resource_limits: ResourceLimits::default(),
+            flags: Default::default(),
         };
 
         std::fs::create_dir_all(&spec.workdir).unwrap();
@@ -527,6 +861,7 @@ mod tests {
             execution_mode: ExecutionMode::Shell,
             network_policy: NetworkPolicy::Isolated,
             resource_limits: ResourceLimits::default(),
+            flags: Default::default(),
         };
 
         // First execution - cache miss
@@ -559,6 +894,7 @@ mod tests {
             execution_mode: ExecutionMode::Shell,
             network_policy: NetworkPolicy::Isolated,
             resource_limits: ResourceLimits::default(),
+            flags: Default::default(),
         };
 
         let result = executor.execute_task(spec);
@@ -596,6 +932,7 @@ echo "Hello from Rust!" > "$D/output.txt"
             execution_mode: ExecutionMode::DirectRust,
             network_policy: NetworkPolicy::Isolated,
             resource_limits: ResourceLimits::default(),
+            flags: Default::default(),
         };
 
         std::fs::create_dir_all(&spec.workdir).unwrap();
@@ -613,6 +950,128 @@ echo "Hello from Rust!" > "$D/output.txt"
         assert_eq!(executor.stats().cache_misses, 1);
     }
 
+    fn flagged_spec(workdir: PathBuf, flags: TaskFlags) -> TaskSpec {
+        TaskSpec {
+            name: "do_flagged".to_string(),
+            recipe: "flags-test".to_string(),
+            script: "#!/bin/bash\necho 'run' > /work/outputs/out.txt".to_string(),
+            workdir,
+            env: HashMap::new(),
+            outputs: vec![PathBuf::from("out.txt")],
+            timeout: None,
+            execution_mode: ExecutionMode::Shell,
+            network_policy: NetworkPolicy::Isolated,
+            resource_limits: ResourceLimits::default(),
+            flags,
+        }
+    }
+
+    #[test]
+    fn test_noexec_task() {
+        let tmp = TempDir::new().unwrap();
+        let mut executor = TaskExecutor::new(tmp.path()).unwrap();
+
+        let flags = TaskFlags { noexec: true, ..Default::default() };
+        let spec = flagged_spec(tmp.path().join("workdir"), flags);
+        let output = executor.execute_task(spec).unwrap();
+
+        assert!(output.success());
+        assert!(output.output_files.is_empty());
+        assert_eq!(executor.stats().noexec_tasks, 1);
+        assert_eq!(executor.stats().tasks_executed, 0);
+        assert_eq!(executor.stats().cache_misses, 0);
+    }
+
+    #[test]
+    fn test_nostamp_task() {
+        let tmp = TempDir::new().unwrap();
+        let mut executor = TaskExecutor::new(tmp.path()).unwrap();
+        let workdir = tmp.path().join("workdir");
+        std::fs::create_dir_all(&workdir).unwrap();
+
+        let flags = TaskFlags { nostamp: true, ..Default::default() };
+        let spec = flagged_spec(workdir, flags);
+        executor.execute_task(spec.clone()).unwrap();
+        executor.execute_task(spec).unwrap();
+
+        assert_eq!(executor.stats().tasks_executed, 2);
+        assert_eq!(executor.stats().cache_hits, 0);
+    }
+
+    #[test]
+    fn test_task_dirs_flags() {
+        let tmp = TempDir::new().unwrap();
+        let workdir = tmp.path().join("workdir");
+        std::fs::create_dir_all(workdir.join("image/stale")).unwrap();
+
+        let flags = TaskFlags {
+            dirs: vec![PathBuf::from("temp"), PathBuf::from("build")],
+            cleandirs: vec![PathBuf::from("image")],
+            lockfiles: vec![tmp.path().join("locks/sysroot.lock")],
+            ..Default::default()
+        };
+        let spec = flagged_spec(workdir.clone(), flags);
+
+        let locks = prepare_task_dirs(&spec).unwrap();
+        assert!(workdir.join("temp").is_dir());
+        assert!(workdir.join("build").is_dir());
+        assert!(workdir.join("image").is_dir());
+        assert!(!workdir.join("image/stale").exists());
+        assert!(tmp.path().join("locks/sysroot.lock").exists());
+        assert_eq!(locks.len(), 1);
+
+        // The last [dirs] entry becomes the working directory, after the shebang
+        let spec = with_task_cwd(spec);
+        let expected = format!("#!/bin/bash\ncd '{}'\necho", workdir.join("build").display());
+        assert!(spec.script.starts_with(&expected), "{}", spec.script);
+    }
+
+    #[test]
+    fn test_task_dirs_in_sandbox() {
+        let tmp = TempDir::new().unwrap();
+        let host = tmp.path().join("tmp/work/foo/1.0");
+        let sandbox_root = tmp.path().join("sandbox");
+        std::fs::create_dir_all(&sandbox_root).unwrap();
+
+        let flags = TaskFlags {
+            dirs: vec![PathBuf::from("temp"), host.join("foo-1.0/src"), host.join("build")],
+            cleandirs: vec![host.join("image")],
+            lockfiles: vec![tmp.path().join("tmp/locks/sysroot.lock")],
+            ..Default::default()
+        };
+        let mut spec = flagged_spec(host.clone(), flags);
+        spec.env.insert("WORKDIR".to_string(), host.display().to_string());
+        spec.env.insert("S".to_string(), host.join("foo-1.0").display().to_string());
+        spec.env.insert("B".to_string(), host.join("build").display().to_string());
+        spec.env.insert("D".to_string(), host.join("image").display().to_string());
+
+        let mut sandbox_env = spec.env.clone();
+        apply_sandbox_paths(&mut sandbox_env, &sandbox_root, &tmp.path().join("cache/sysroot"));
+        let work = sandbox_root.canonicalize().unwrap().join("work");
+
+        // S wins over WORKDIR for paths under both; shared locks stay put
+        let sandbox_task = sandbox_task_spec(&spec, &sandbox_env);
+        assert_eq!(
+            sandbox_task.flags.dirs,
+            vec![work.join("temp"), work.join("src/src"), work.join("build")]
+        );
+        assert_eq!(sandbox_task.flags.cleandirs, vec![work.join("outputs")]);
+        assert_eq!(sandbox_task.flags.lockfiles, spec.flags.lockfiles);
+
+        let sandbox_task = with_task_cwd(sandbox_task);
+        let expected = format!("#!/bin/bash\ncd '{}'\n", work.join("build").display());
+        assert!(sandbox_task.script.starts_with(&expected), "{}", sandbox_task.script);
+
+        // A sandboxed task runs in its sandbox's B and leaves the host WORKDIR alone
+        let mut executor = TaskExecutor::new(tmp.path().join("cache")).unwrap();
+        spec.script = "#!/bin/bash\npwd".to_string();
+        spec.outputs.clear();
+        let output = executor.execute_task(spec).unwrap();
+        assert!(output.success(), "{}", output.stderr);
+        assert!(output.stdout.trim_end().ends_with("work/build"), "{}", output.stdout);
+        assert!(!host.exists());
+    }
+
     #[test]
     fn test_auto_detect_execution_mode() {
         // Simple script should be DirectRust
@@ -24,6 +24,8 @@ pub mod fetch_handler;
 pub mod retry;
 pub mod bbhelpers;
 pub mod rust_shell_executor;
+pub mod devshell;
+pub mod sstate;
 
 // External executor abstraction
 pub mod external;
@@ -32,15 +34,15 @@ pub mod wasm_executor;
 pub mod executor_pool;
 
 pub use types::{
-    TaskSignature, TaskOutput, TaskSpec, SandboxSpec,
+    TaskSignature, TaskOutput, TaskSpec, TaskFlags, SandboxSpec,
     ContentHash, ExecutionResult, ExecutionMode, NetworkPolicy, ResourceLimits,
 };
-pub use cache::{ContentAddressableStore, ActionCache};
+pub use cache::{ContentAddressableStore, ActionCache, BuildHistory, BuildRecord, EvictedObject, EvictionReport, IntegrityReport, DEFAULT_KEEP_BUILDS};
 pub use sandbox::SandboxManager;
 pub use sandbox_backend::SandboxBackend;
 pub use executor::TaskExecutor;
 pub use execution_log::{ExecutionLog, ExecutionOutcome, ExecutionError, ErrorCategory, ExecutionMetrics};
-pub use cache_manager::{CacheManager, CacheQuery, CleanStats, ExpungeStats};
+pub use cache_manager::{CacheManager, CacheQuery, CleanStats, ExpungeStats, GcOptions, GcStats, VerifyReport, BrokenAction, RepairStats};
 pub use async_executor::{AsyncTaskExecutor, ExecutionProgress, ExecutionSummary};
 pub use monitor::{TaskMonitor, TaskInfo, TaskState, BuildStats};
 pub use interactive::{InteractiveExecutor, InteractiveOptions, ExecutionControlHandle};
@@ -51,6 +53,8 @@ pub use direct_executor::{execute_direct, DirectExecutionResult};
 pub use fetch_handler::{fetch_source, FetchError, FetchResult};
 pub use retry::{RetryPolicy, execute_with_retry, execute_with_retry_sync};
 pub use rust_shell_executor::{RustShellExecutor, RustShellResult, execute_with_bitbake_env, create_bitbake_prelude};
+pub use devshell::{DevShell, DevShellOptions};
+pub use sstate::{SstateArchiveName, SstateMirror, SigInfo};
 
 // External executor types
 pub use external::{
@@ -64,3 +68,4 @@ pub use executor_pool::{ExecutorPool, AggregateStats};
 
 #[cfg(target_os = "linux")]
 pub use native_sandbox::execute_in_namespace;
+pub use native_sandbox::spawn_interactive_in_namespace;
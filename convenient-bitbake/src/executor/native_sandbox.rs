@@ -253,6 +253,83 @@ pub fn execute_in_namespace(
     result
 }
 
+/// Run an interactive command in the task namespaces with the terminal attached
+///
+/// Used by the devshell: the child enters the same namespaces as
+/// [`execute_in_namespace`] for the given network policy, but stdin/stdout/stderr
+/// are inherited instead of captured. Returns the command's exit code.
+#[cfg(target_os = "linux")]
+pub fn spawn_interactive_in_namespace(
+    argv: &[String],
+    work_dir: &Path,
+    env: &std::collections::HashMap<String, String>,
+    network_policy: NetworkPolicy,
+) -> Result<i32, ExecutionError> {
+    let (program, args) = argv.split_first()
+        .ok_or_else(|| ExecutionError::SandboxError("Empty devshell command".to_string()))?;
+
+    info!("Spawning interactive shell in native Linux namespace sandbox: {:?}", network_policy);
+
+    match unsafe { fork() }
+        .map_err(|e| ExecutionError::SandboxError(format!("Fork failed: {}", e)))?
+    {
+        ForkResult::Parent { child } => {
+            match waitpid(child, None)
+                .map_err(|e| ExecutionError::SandboxError(format!("waitpid failed: {}", e)))?
+            {
+                WaitStatus::Exited(_pid, code) => Ok(code),
+                WaitStatus::Signaled(_pid, signal, _) => Err(ExecutionError::SandboxError(format!(
+                    "Devshell killed by signal: {:?}",
+                    signal
+                ))),
+                status => Err(ExecutionError::SandboxError(format!(
+                    "Devshell ended unexpectedly: {:?}",
+                    status
+                ))),
+            }
+        }
+        ForkResult::Child => {
+            let result = (|| -> Result<i32, ExecutionError> {
+                enter_task_namespaces(network_policy)?;
+
+                install_prelude_script()
+                    .map_err(|e| ExecutionError::SandboxError(format!("Failed to install prelude: {}", e)))?;
+
+                chdir(work_dir)
+                    .map_err(|e| ExecutionError::SandboxError(format!("chdir failed: {}", e)))?;
+
+                let mut cmd = Command::new(program);
+                cmd.args(args).current_dir(work_dir);
+
+                cmd.env_clear();
+                for (key, value) in env {
+                    cmd.env(key, value);
+                }
+
+                // Add essential environment
+                cmd.env("HOME", "/tmp");
+                cmd.env("PATH", "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin");
+                cmd.env("SHELL", "/bin/bash");
+                if let Ok(term) = std::env::var("TERM") {
+                    cmd.env("TERM", term);
+                }
+
+                let status = cmd.status()
+                    .map_err(|e| ExecutionError::SandboxError(format!("Failed to spawn {}: {}", program, e)))?;
+                Ok(status.code().unwrap_or_else(|| 128 + status.signal().unwrap_or(0)))
+            })();
+
+            match result {
+                Ok(exit_code) => std::process::exit(exit_code),
+                Err(e) => {
+                    eprintln!("Devshell failed: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+}
+
 /// Setup UID/GID mapping for child process
 #[cfg(target_os = "linux")]
 fn setup_uid_gid_mapping(child: Pid, write_fd: OwnedFd) -> Result<(), ExecutionError> {
@@ -584,23 +661,12 @@ fn execute_with_bash(
     Ok(status.code().unwrap_or(1))
 }
 
-/// Child process: create mount+PID+network namespaces without user namespace
-/// (requires CAP_SYS_ADMIN or running in privileged mode)
+/// Create the namespaces used for task execution and configure networking
+///
+/// Shared by task execution and the interactive devshell so both see the
+/// same isolation for a given network policy.
 #[cfg(target_os = "linux")]
-fn execute_child_without_userns(
-    script: &str,
-    work_dir: &Path,
-    env: &std::collections::HashMap<String, String>,
-    network_policy: NetworkPolicy,
-    cgroup_path: Option<&Path>,
-) -> Result<i32, ExecutionError> {
-    use std::fs::File;
-
-    // Move to cgroup BEFORE creating namespaces (so child inherits cgroup)
-    if let Some(path) = cgroup_path {
-        move_to_cgroup(path)?;
-    }
-
+fn enter_task_namespaces(network_policy: NetworkPolicy) -> Result<(), ExecutionError> {
     // Determine which namespaces to create based on network policy
     // NOTE: Temporarily disabling mount+PID namespaces to debug execution
     // We'll use network isolation and process isolation without filesystem isolation for now
@@ -646,6 +712,28 @@ fn execute_child_without_userns(
         }
     }
 
+    Ok(())
+}
+
+/// Child process: create mount+PID+network namespaces without user namespace
+/// (requires CAP_SYS_ADMIN or running in privileged mode)
+#[cfg(target_os = "linux")]
+fn execute_child_without_userns(
+    script: &str,
+    work_dir: &Path,
+    env: &std::collections::HashMap<String, String>,
+    network_policy: NetworkPolicy,
+    cgroup_path: Option<&Path>,
+) -> Result<i32, ExecutionError> {
+    use std::fs::File;
+
+    // Move to cgroup BEFORE creating namespaces (so child inherits cgroup)
+    if let Some(path) = cgroup_path {
+        move_to_cgroup(path)?;
+    }
+
+    enter_task_namespaces(network_policy)?;
+
     // NOTE: Mount operations disabled since we're not using mount namespaces
     // When we add proper mount namespace support, we'll need to properly set up the root
     // filesystem before bind mounting system directories
@@ -774,6 +862,19 @@ pub fn execute_in_namespace(
     ))
 }
 
+/// Fallback for non-Linux platforms
+#[cfg(not(target_os = "linux"))]
+pub fn spawn_interactive_in_namespace(
+    _argv: &[String],
+    _work_dir: &Path,
+    _env: &std::collections::HashMap<String, String>,
+    _network_policy: NetworkPolicy,
+) -> Result<i32, ExecutionError> {
+    Err(ExecutionError::SandboxError(
+        "Native namespace sandbox only available on Linux".to_string()
+    ))
+}
+
 #[cfg(test)]
 #[cfg(target_os = "linux")]
 mod tests {
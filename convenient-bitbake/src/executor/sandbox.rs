@@ -123,6 +123,11 @@ impl Sandbox {
         self.spec.env = env;
     }
 
+    /// Replace the command, e.g. once it can refer to the sandbox's own paths
+    pub fn update_command(&mut self, command: Vec<String>) {
+        self.spec.command = command;
+    }
+
     /// Execute command in sandbox using the configured backend
     pub fn execute(&self) -> ExecutionResult<SandboxResult> {
         self.backend.execute(&self.spec, &self.root)
@@ -0,0 +1,590 @@
+//! BitBake shared-state (sstate) compatibility
+//!
+//! Reads and writes sstate-cache archives so hitzeleiter can share artifacts
+//! with stock BitBake:
+//! - `sstate:<PN>:<PACKAGE_ARCH>:<PV>:<PR>:<SSTATE_PKGARCH>:<SSTATE_VERSION>:<unihash>_<task>.tar.zst`
+//!   archives under `<unihash[0..2]>/<unihash[2..4]>/`
+//! - `.siginfo` files next to them (zstd-compressed JSON, BitBake 2.0+)
+//!
+//! Archive entries map 1:1 to `TaskOutput::output_files`. Archives are named
+//! after the task's `BB_UNIHASH`, which the build orchestrator computes the way
+//! BitBake does (see `crate::siggen`): archives from BitBake builds are
+//! restored, and exported ones are found by BitBake. Specs without one fall
+//! back to the hitzeleiter task signature, which only other hitzeleiter builds
+//! sharing the SSTATE_MIRRORS directory hit.
+
+use super::cache::ContentAddressableStore;
+use super::types::{ContentHash, ExecutionError, ExecutionResult, TaskOutput, TaskSpec};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+/// SSTATE_VERSION written into exported archive names
+pub const SSTATE_VERSION: &str = "14";
+
+/// Archive extension used by current BitBake
+const ARCHIVE_SUFFIX: &str = ".tar.zst";
+
+/// Parsed sstate archive file name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SstateArchiveName {
+    pub pn: String,
+    pub package_arch: String,
+    pub pv: String,
+    pub pr: String,
+    pub sstate_pkgarch: String,
+    pub version: String,
+    pub unihash: String,
+    /// Task name without the `do_` prefix (e.g. `populate_sysroot`)
+    pub task: String,
+}
+
+impl SstateArchiveName {
+    /// Parse an archive file name (without directories)
+    pub fn parse(file_name: &str) -> Option<Self> {
+        let stem = file_name.strip_suffix(ARCHIVE_SUFFIX)?;
+        let fields: Vec<&str> = stem.split(':').collect();
+        if fields.len() != 8 || fields[0] != "sstate" {
+            return None;
+        }
+
+        let (unihash, task) = fields[7].split_once('_')?;
+        if unihash.len() < 4 || task.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            pn: fields[1].to_string(),
+            package_arch: fields[2].to_string(),
+            pv: fields[3].to_string(),
+            pr: fields[4].to_string(),
+            sstate_pkgarch: fields[5].to_string(),
+            version: fields[6].to_string(),
+            unihash: unihash.to_string(),
+            task: task.to_string(),
+        })
+    }
+
+    /// Name the archive for a task, using the BitBake variables in its environment
+    ///
+    /// SSTATE_PKGSPEC is used as is when present; otherwise the name is made
+    /// of PN, PACKAGE_ARCH, PV, PR and SSTATE_PKGARCH.
+    pub fn for_task(spec: &TaskSpec, unihash: &str) -> Self {
+        let var = |name: &str| spec.env.get(name).filter(|v| !v.is_empty()).cloned();
+        let task = spec.name.strip_prefix("do_").unwrap_or(&spec.name).to_string();
+
+        if let Some(pkgspec) = var("SSTATE_PKGSPEC")
+            && let ["sstate", pn, package_arch, pv, pr, sstate_pkgarch, version, ""] =
+                pkgspec.split(':').collect::<Vec<_>>()[..]
+        {
+            return Self {
+                pn: pn.to_string(),
+                package_arch: package_arch.to_string(),
+                pv: pv.to_string(),
+                pr: pr.to_string(),
+                sstate_pkgarch: sstate_pkgarch.to_string(),
+                version: version.to_string(),
+                unihash: unihash.to_string(),
+                task,
+            };
+        }
+
+        let package_arch = var("PACKAGE_ARCH")
+            .or_else(|| var("MACHINE"))
+            .unwrap_or_else(|| "allarch".to_string());
+
+        Self {
+            pn: var("PN").unwrap_or_else(|| spec.recipe.clone()),
+            sstate_pkgarch: var("SSTATE_PKGARCH").unwrap_or_else(|| package_arch.clone()),
+            package_arch,
+            pv: var("PV").unwrap_or_else(|| "0".to_string()),
+            pr: var("PR").unwrap_or_else(|| "r0".to_string()),
+            version: SSTATE_VERSION.to_string(),
+            unihash: unihash.to_string(),
+            task,
+        }
+    }
+
+    /// Archive file name
+    pub fn file_name(&self) -> String {
+        format!(
+            "sstate:{}:{}:{}:{}:{}:{}:{}_{}{}",
+            self.pn,
+            self.package_arch,
+            self.pv,
+            self.pr,
+            self.sstate_pkgarch,
+            self.version,
+            self.unihash,
+            self.task,
+            ARCHIVE_SUFFIX
+        )
+    }
+
+    /// Path relative to SSTATE_DIR (`<hash[0..2]>/<hash[2..4]>/<file name>`)
+    pub fn relative_path(&self) -> PathBuf {
+        PathBuf::from(&self.unihash[0..2])
+            .join(&self.unihash[2..4])
+            .join(self.file_name())
+    }
+}
+
+/// Hash an archive for `spec` is named after: the BitBake `BB_UNIHASH` if the
+/// task carries one, otherwise the hitzeleiter signature
+pub fn unihash_for_task(spec: &TaskSpec, signature: &ContentHash) -> String {
+    spec.env
+        .get("BB_UNIHASH")
+        .filter(|h| h.len() >= 4)
+        .cloned()
+        .unwrap_or_else(|| signature.to_hex())
+}
+
+/// Path of the `.siginfo` file belonging to an archive
+pub fn siginfo_path(archive: &Path) -> PathBuf {
+    let mut name = archive.as_os_str().to_owned();
+    name.push(".siginfo");
+    PathBuf::from(name)
+}
+
+/// Task signature data written by BitBake next to each archive
+///
+/// Only the fields hitzeleiter uses are typed; everything else is kept
+/// verbatim so a read-modify-write round trip doesn't lose data.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SigInfo {
+    #[serde(default)]
+    pub task: String,
+    #[serde(default)]
+    pub basehash: String,
+    #[serde(default)]
+    pub taskhash: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unihash: Option<String>,
+    #[serde(default)]
+    pub runtaskdeps: Vec<String>,
+    #[serde(default)]
+    pub varvals: BTreeMap<String, Option<String>>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl SigInfo {
+    /// Read a siginfo file (zstd-compressed or plain JSON)
+    ///
+    /// Pickled siginfo from BitBake before 2.0 is not supported.
+    pub fn read(path: &Path) -> ExecutionResult<Self> {
+        let data = fs::read(path)?;
+        let json = zstd::decode_all(data.as_slice()).unwrap_or(data);
+
+        serde_json::from_slice(&json).map_err(|e| {
+            ExecutionError::CacheError(format!(
+                "Unsupported siginfo {} (pre-2.0 pickle?): {}",
+                path.display(),
+                e
+            ))
+        })
+    }
+
+    /// Write as zstd-compressed JSON, like BitBake 2.0+
+    pub fn write(&self, path: &Path) -> ExecutionResult<()> {
+        let json = serde_json::to_vec(self)?;
+        let compressed = zstd::encode_all(json.as_slice(), 3)?;
+        fs::write(path, compressed)?;
+        Ok(())
+    }
+
+    /// Signature data describing a task, with the BitBake hashes its spec
+    /// carries (BB_BASEHASH, BB_TASKHASH)
+    pub fn for_task(spec: &TaskSpec, signature: &ContentHash, unihash: &str) -> Self {
+        let hash = |name: &str| spec.env.get(name).filter(|h| !h.is_empty()).cloned();
+
+        let mut extra = serde_json::Map::new();
+        // Keys bitbake-diffsigs expects to be present
+        for key in ["basehash_ignore_vars", "taskdeps", "file_checksum_values"] {
+            extra.insert(key.to_string(), serde_json::Value::Array(Vec::new()));
+        }
+        for key in ["gendeps", "runtaskhashes"] {
+            extra.insert(key.to_string(), serde_json::Value::Object(serde_json::Map::new()));
+        }
+
+        Self {
+            task: spec.name.clone(),
+            basehash: hash("BB_BASEHASH").unwrap_or_else(|| signature.to_hex()),
+            taskhash: hash("BB_TASKHASH").unwrap_or_else(|| unihash.to_string()),
+            unihash: Some(unihash.to_string()),
+            runtaskdeps: Vec::new(),
+            varvals: spec.env.iter().map(|(k, v)| (k.clone(), Some(v.clone()))).collect(),
+            extra,
+        }
+    }
+}
+
+/// SSTATE_DIR plus read-only SSTATE_MIRRORS, searched in that order
+#[derive(Debug, Clone)]
+pub struct SstateMirror {
+    /// Local sstate cache; exported archives go here
+    pub sstate_dir: PathBuf,
+    /// Mirror directories from SSTATE_MIRRORS
+    pub mirrors: Vec<PathBuf>,
+}
+
+impl SstateMirror {
+    /// Create from SSTATE_DIR and an optional SSTATE_MIRRORS value
+    ///
+    /// SSTATE_MIRRORS is a list of `<regex> <url>` pairs; only `file://`
+    /// mirrors can be used as a cache tier, other schemes are skipped.
+    pub fn new(sstate_dir: impl Into<PathBuf>, sstate_mirrors: Option<&str>) -> Self {
+        let mut mirrors = Vec::new();
+
+        let value = sstate_mirrors.unwrap_or("").replace("\\n", " ");
+        let tokens: Vec<&str> = value.split_whitespace().collect();
+        for pair in tokens.chunks(2) {
+            let [_, url] = pair else { continue };
+            match url.strip_prefix("file://") {
+                Some(path) => {
+                    let path = path.trim_end_matches("PATH").trim_end_matches('/');
+                    mirrors.push(PathBuf::from(path));
+                }
+                None => warn!("Skipping non-local sstate mirror {}", url),
+            }
+        }
+
+        Self {
+            sstate_dir: sstate_dir.into(),
+            mirrors,
+        }
+    }
+
+    /// Find the archive for `unihash` and `task` (with or without `do_`)
+    pub fn find(&self, unihash: &str, task: &str) -> Option<PathBuf> {
+        if unihash.len() < 4 {
+            return None;
+        }
+        let task = task.strip_prefix("do_").unwrap_or(task);
+        let suffix = format!(":{}_{}{}", unihash, task, ARCHIVE_SUFFIX);
+
+        for root in std::iter::once(&self.sstate_dir).chain(&self.mirrors) {
+            // Native archives live below an extra path component (SSTATE_EXTRAPATH)
+            let mut shard_dirs = vec![root.join(&unihash[0..2]).join(&unihash[2..4])];
+            if let Ok(entries) = fs::read_dir(root) {
+                for entry in entries.filter_map(|e| e.ok()) {
+                    let dir = entry.path().join(&unihash[0..2]).join(&unihash[2..4]);
+                    if dir.is_dir() {
+                        shard_dirs.push(dir);
+                    }
+                }
+            }
+
+            for dir in shard_dirs {
+                let Ok(entries) = fs::read_dir(&dir) else { continue };
+                for entry in entries.filter_map(|e| e.ok()) {
+                    let name = entry.file_name();
+                    if name.to_str().is_some_and(|n| n.starts_with("sstate:") && n.ends_with(&suffix)) {
+                        return Some(entry.path());
+                    }
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// Import an sstate archive into the CAS as the output of `signature`
+///
+/// Regular files become output files; directories and symlinks are not
+/// representable in a `TaskOutput` and are skipped.
+pub fn import_archive(
+    archive: &Path,
+    cas: &mut ContentAddressableStore,
+    signature: ContentHash,
+) -> ExecutionResult<TaskOutput> {
+    let name = archive
+        .file_name()
+        .and_then(|n| n.to_str())
+        .and_then(SstateArchiveName::parse)
+        .ok_or_else(|| {
+            ExecutionError::CacheError(format!("Not an sstate archive: {}", archive.display()))
+        })?;
+
+    let siginfo = siginfo_path(archive);
+    if siginfo.exists() {
+        match SigInfo::read(&siginfo) {
+            Ok(sig) if sig.unihash.as_deref().is_some_and(|h| h != name.unihash) => {
+                warn!("{}: siginfo unihash doesn't match archive name", archive.display());
+            }
+            Ok(_) => {}
+            Err(e) => debug!("Ignoring siginfo: {}", e),
+        }
+    }
+
+    let decoder = zstd::Decoder::new(BufReader::new(File::open(archive)?))?;
+    let mut tar = tar::Archive::new(decoder);
+
+    let mut output_files = HashMap::new();
+    let mut skipped = 0;
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            if !entry.header().entry_type().is_dir() {
+                skipped += 1;
+            }
+            continue;
+        }
+
+        let path = entry.path()?.into_owned();
+        let path = path.strip_prefix(".").unwrap_or(&path).to_path_buf();
+
+        let mut content = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut content)?;
+        output_files.insert(path, cas.put(&content)?);
+    }
+
+    if skipped > 0 {
+        debug!("{}: skipped {} non-regular entries", archive.display(), skipped);
+    }
+
+    Ok(TaskOutput {
+        signature,
+        output_files,
+        stdout: format!("Restored from sstate {}", name.file_name()),
+        stderr: String::new(),
+        exit_code: 0,
+        duration_ms: 0,
+    })
+}
+
+/// Export a task output into `sstate_dir` in sstate layout, with its siginfo
+///
+/// Entries are written in sorted order with zeroed mtimes so the archive is
+/// reproducible. Returns the archive path.
+pub fn export_output(
+    output: &TaskOutput,
+    cas: &mut ContentAddressableStore,
+    name: &SstateArchiveName,
+    siginfo: &SigInfo,
+    sstate_dir: &Path,
+) -> ExecutionResult<PathBuf> {
+    let archive = sstate_dir.join(name.relative_path());
+    if let Some(parent) = archive.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut paths: Vec<&PathBuf> = output.output_files.keys().collect();
+    paths.sort();
+
+    // Write next to the destination and rename, so readers never see a partial archive
+    let temp = archive.with_extension("tmp");
+    {
+        let encoder = zstd::Encoder::new(File::create(&temp)?, 3)?;
+        let mut tar = tar::Builder::new(encoder);
+
+        for path in paths {
+            let content = cas.get(&output.output_files[path])?;
+
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(file_mode(&content));
+            header.set_mtime(0);
+            header.set_cksum();
+
+            tar.append_data(&mut header, Path::new(".").join(path), content.as_slice())?;
+        }
+
+        let mut file = tar.into_inner()?.finish()?;
+        file.flush()?;
+        file.sync_all()?;
+    }
+    fs::rename(&temp, &archive)?;
+
+    siginfo.write(&siginfo_path(&archive))?;
+
+    Ok(archive)
+}
+
+/// Outputs don't record permissions; keep executables executable
+fn file_mode(content: &[u8]) -> u32 {
+    if content.starts_with(b"\x7fELF") || content.starts_with(b"#!") {
+        0o755
+    } else {
+        0o644
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datastore::DataStore;
+    use crate::executor::types::{ExecutionMode, NetworkPolicy, ResourceLimits};
+    use crate::siggen::{self, SignatureGenerator, TaskHashes};
+    use tempfile::TempDir;
+
+    fn task_spec() -> TaskSpec {
+        let mut env = HashMap::new();
+        env.insert("PN".to_string(), "zlib".to_string());
+        env.insert("PV".to_string(), "1.3.1".to_string());
+        env.insert("MACHINE".to_string(), "qemux86-64".to_string());
+
+        TaskSpec {
+            name: "do_populate_sysroot".to_string(),
+            recipe: "zlib".to_string(),
+            script: String::new(),
+            workdir: PathBuf::from("/tmp"),
+            env,
+            outputs: vec![],
+            timeout: None,
+            execution_mode: ExecutionMode::Shell,
+            network_policy: NetworkPolicy::Isolated,
+            resource_limits: ResourceLimits::default(),
+            flags: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_archive_name_round_trip() {
+        let file_name = "sstate:zlib:core2-64-poky-linux:1.3.1:r0:core2-64:14:0a1b2c3d4e5f_populate_sysroot.tar.zst";
+        let name = SstateArchiveName::parse(file_name).unwrap();
+
+        assert_eq!(name.pn, "zlib");
+        assert_eq!(name.package_arch, "core2-64-poky-linux");
+        assert_eq!(name.unihash, "0a1b2c3d4e5f");
+        assert_eq!(name.task, "populate_sysroot");
+        assert_eq!(name.file_name(), file_name);
+        assert_eq!(name.relative_path(), PathBuf::from("0a/1b").join(file_name));
+
+        assert!(SstateArchiveName::parse("zlib.tar.zst").is_none());
+        assert!(SstateArchiveName::parse(&format!("{}.siginfo", file_name)).is_none());
+    }
+
+    #[test]
+    fn test_mirrors_parse_file_urls() {
+        let mirror = SstateMirror::new(
+            "/build/sstate-cache",
+            Some("file://.* file:///srv/sstate/PATH \\n file://.* https://sstate.example.com/PATH;downloadfilename=PATH"),
+        );
+
+        assert_eq!(mirror.mirrors, vec![PathBuf::from("/srv/sstate")]);
+    }
+
+    #[test]
+    fn test_export_then_import_via_mirror() {
+        let tmp = TempDir::new().unwrap();
+        let mut cas = ContentAddressableStore::new(tmp.path().join("cas")).unwrap();
+
+        let mut output_files = HashMap::new();
+        output_files.insert(PathBuf::from("usr/lib/libz.so.1"), cas.put(b"\x7fELF libz").unwrap());
+        output_files.insert(PathBuf::from("usr/include/zlib.h"), cas.put(b"/* zlib */").unwrap());
+        let signature = ContentHash::from_bytes(b"zlib:populate_sysroot");
+        let output = TaskOutput {
+            signature: signature.clone(),
+            output_files,
+            stdout: String::new(),
+            stderr: String::new(),
+            exit_code: 0,
+            duration_ms: 10,
+        };
+
+        let spec = task_spec();
+        let unihash = signature.to_hex();
+        let name = SstateArchiveName::for_task(&spec, &unihash);
+        let siginfo = SigInfo::for_task(&spec, &signature, &unihash);
+
+        let exported = tmp.path().join("exported");
+        let archive = export_output(&output, &mut cas, &name, &siginfo, &exported).unwrap();
+        assert!(archive.starts_with(exported.join(&unihash[0..2]).join(&unihash[2..4])));
+        assert_eq!(SigInfo::read(&siginfo_path(&archive)).unwrap().unihash.as_deref(), Some(unihash.as_str()));
+
+        // A fresh SSTATE_DIR finds the archive through SSTATE_MIRRORS
+        let mirror = SstateMirror::new(
+            tmp.path().join("sstate-cache"),
+            Some(&format!("file://.* file://{}/PATH", exported.display())),
+        );
+        let found = mirror.find(&unihash, "do_populate_sysroot").unwrap();
+        assert_eq!(found, archive);
+        assert!(mirror.find(&unihash, "do_install").is_none());
+
+        let mut other_cas = ContentAddressableStore::new(tmp.path().join("other-cas")).unwrap();
+        let imported = import_archive(&found, &mut other_cas, signature.clone()).unwrap();
+
+        assert_eq!(imported.output_files, output.output_files);
+        assert_eq!(
+            other_cas.get(&imported.output_files[Path::new("usr/include/zlib.h")]).unwrap(),
+            b"/* zlib */"
+        );
+    }
+
+    #[test]
+    fn test_restore_archive_named_by_bitbake() {
+        let mut d = DataStore::new();
+        d.parse_content(
+            r#"
+PN = "zlib"
+PV = "1.3.1"
+PR = "r0"
+PACKAGE_ARCH = "core2-64"
+TARGET_VENDOR = "-poky"
+TARGET_OS = "linux"
+SSTATE_PKGARCH = "${PACKAGE_ARCH}"
+SSTATE_VERSION = "14"
+SSTATE_PKGSPEC = "sstate:${PN}:${PACKAGE_ARCH}${TARGET_VENDOR}-${TARGET_OS}:${PV}:${PR}:${SSTATE_PKGARCH}:${SSTATE_VERSION}:"
+do_populate_sysroot() {
+	sysroot_stage_all
+}
+"#,
+            Path::new("/layer/zlib_1.3.1.bb"),
+        )
+        .unwrap();
+
+        // Hashes as BitBake computes them for a task without dependencies
+        let basehash = SignatureGenerator::new(&d).basehash("do_populate_sysroot");
+        let taskhash = siggen::taskhash(&basehash, &[], None);
+        let hashes = TaskHashes { basehash, taskhash: taskhash.clone(), unihash: taskhash.clone() };
+
+        // The archive under the name sstate.bbclass gives it
+        let tmp = TempDir::new().unwrap();
+        let mirror_dir = tmp.path().join("mirror");
+        let archive = mirror_dir.join(&taskhash[0..2]).join(&taskhash[2..4]).join(format!(
+            "sstate:zlib:core2-64-poky-linux:1.3.1:r0:core2-64:14:{}_populate_sysroot.tar.zst",
+            taskhash
+        ));
+        fs::create_dir_all(archive.parent().unwrap()).unwrap();
+        {
+            let encoder = zstd::Encoder::new(File::create(&archive).unwrap(), 3).unwrap();
+            let mut tar = tar::Builder::new(encoder);
+            let mut header = tar::Header::new_gnu();
+            header.set_size(10);
+            header.set_mode(0o644);
+            header.set_cksum();
+            tar.append_data(&mut header, "./usr/include/zlib.h", &b"/* zlib */"[..]).unwrap();
+            tar.into_inner().unwrap().finish().unwrap();
+        }
+
+        let mut spec = task_spec();
+        spec.env = siggen::sstate_name_vars(&d);
+        spec.env.extend(hashes.env_vars());
+        let signature = ContentHash::from_bytes(b"zlib:populate_sysroot");
+        let unihash = unihash_for_task(&spec, &signature);
+        assert_eq!(unihash, taskhash);
+        assert_eq!(
+            SstateArchiveName::for_task(&spec, &unihash).relative_path(),
+            archive.strip_prefix(&mirror_dir).unwrap()
+        );
+
+        let mirror = SstateMirror::new(
+            tmp.path().join("sstate-cache"),
+            Some(&format!("file://.* file://{}/PATH", mirror_dir.display())),
+        );
+        let found = mirror.find(&unihash, &spec.name).unwrap();
+        assert_eq!(found, archive);
+
+        let mut cas = ContentAddressableStore::new(tmp.path().join("cas")).unwrap();
+        let restored = import_archive(&found, &mut cas, signature).unwrap();
+        assert_eq!(
+            cas.get(&restored.output_files[Path::new("usr/include/zlib.h")]).unwrap(),
+            b"/* zlib */"
+        );
+    }
+}
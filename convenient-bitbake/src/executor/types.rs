@@ -283,6 +283,55 @@ pub struct TaskSpec {
 
     /// Resource limits for this task (only used if execution_mode requires sandbox)
     pub resource_limits: ResourceLimits,
+
+    /// BitBake task flags applied around execution
+    #[serde(default)]
+    pub flags: TaskFlags,
+}
+
+/// BitBake task flags that change how a task is run
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskFlags {
+    /// `[noexec]`: nothing to run, the task succeeds immediately
+    pub noexec: bool,
+
+    /// `[nostamp]`: never served from a cache, always re-run
+    pub nostamp: bool,
+
+    /// `[dirs]`: created before the task runs, the last one is its working directory
+    pub dirs: Vec<PathBuf>,
+
+    /// `[cleandirs]`: emptied and recreated before the task runs
+    pub cleandirs: Vec<PathBuf>,
+
+    /// `[lockfiles]`: held exclusively while the task runs
+    pub lockfiles: Vec<PathBuf>,
+}
+
+impl TaskFlags {
+    /// Read the flags from a task's (already expanded) flag values
+    pub fn from_task_flags(flags: &HashMap<String, String>) -> Self {
+        let is_set = |name: &str| flags.get(name).is_some_and(|v| !matches!(v.trim(), "" | "0"));
+        let paths = |name: &str| -> Vec<PathBuf> {
+            flags
+                .get(name)
+                .map(|v| v.split_whitespace().map(PathBuf::from).collect())
+                .unwrap_or_default()
+        };
+
+        Self {
+            noexec: is_set("noexec"),
+            nostamp: is_set("nostamp"),
+            dirs: paths("dirs"),
+            cleandirs: paths("cleandirs"),
+            lockfiles: paths("lockfiles"),
+        }
+    }
+
+    /// Directory the task runs in (the last `[dirs]` entry), if any
+    pub fn cwd(&self) -> Option<&PathBuf> {
+        self.dirs.last()
+    }
 }
 
 /// Sandbox specification
@@ -0,0 +1,647 @@
+// Lossless formatter for BitBake metadata (.bb, .bbappend, .bbclass, .inc)
+//
+// Files are split into statements the way BitBake's parser reads them: logical
+// lines joined by backslash continuations, and function bodies up to their
+// closing brace (or, for `def`, the end of the indented block). Assignments,
+// inherit and include lines are re-emitted from their CST; everything else,
+// comments and function bodies included, is kept verbatim. A statement whose
+// CST does not cover its whole text is kept verbatim as well.
+//
+// Formatting:
+// - one space around assignment operators, single spaces in inherit/include
+// - continuation lines indented, with " \" before each line break
+// - single-quoted values converted to double quotes where that is safe
+// - runs of blank lines collapsed, trailing whitespace removed
+// - in recipes, plain assignments between barriers (inherit, include,
+//   functions, :=, export, ...) sorted into the OE style guide order
+
+use crate::parser;
+use crate::syntax_kind::{SyntaxKind, SyntaxNode};
+use regex::Regex;
+use std::path::Path;
+
+/// Variables in the order of the OE style guide, in groups that the formatter
+/// separates by a blank line. `inherit` sits between `S` and the build class
+/// variables, which are every variable not listed here
+pub const STYLE_ORDER: &[&[&str]] = &[
+    &[
+        "SUMMARY",
+        "DESCRIPTION",
+        "AUTHOR",
+        "HOMEPAGE",
+        "BUGTRACKER",
+        "SECTION",
+        "LICENSE",
+        "LIC_FILES_CHKSUM",
+    ],
+    &["DEPENDS", "PROVIDES", "PV"],
+    &["SRC_URI", "SRCREV", "S"],
+    &["inherit"],
+    &[],
+    &[
+        "PACKAGE_ARCH",
+        "PACKAGES",
+        "FILES",
+        "RDEPENDS",
+        "RRECOMMENDS",
+        "RSUGGESTS",
+        "RPROVIDES",
+        "RCONFLICTS",
+        "RREPLACES",
+    ],
+    &["BBCLASSEXTEND"],
+];
+
+/// Position of a variable in [`STYLE_ORDER`] as (group, index); old-style
+/// `FILES_${PN}` names rank with their variable, unlisted variables with the
+/// build class group
+#[must_use]
+pub fn style_rank(var: &str) -> (usize, usize) {
+    for (group, names) in STYLE_ORDER.iter().enumerate() {
+        for (index, name) in names.iter().enumerate() {
+            let old_override = name.len() > 2
+                && var.strip_prefix(name).is_some_and(|rest| rest.starts_with('_'));
+            if var == *name || old_override {
+                return (group, index);
+            }
+        }
+    }
+    let build = STYLE_ORDER.iter().position(|names| names.is_empty()).unwrap_or(0);
+    (build, 0)
+}
+
+/// A variable assignment, as written
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assignment {
+    /// Preceded by `export`
+    pub export: bool,
+    /// Full name with overrides and flag, e.g. `RDEPENDS:${PN}-dev` or `SRC_URI[sha256sum]`
+    pub name: String,
+    /// Operator (`=`, `+=`, `?=`, ...)
+    pub op: String,
+    /// Value text including quotes and line continuations
+    pub value: String,
+}
+
+impl Assignment {
+    /// The variable without overrides and flag
+    #[must_use]
+    pub fn var(&self) -> &str {
+        let end = self.name.find([':', '[']).unwrap_or(self.name.len());
+        &self.name[..end]
+    }
+
+    /// The `:override` parts of the name (`append`, `${PN}`, ...)
+    #[must_use]
+    pub fn overrides(&self) -> Vec<&str> {
+        let end = self.name.find('[').unwrap_or(self.name.len());
+        self.name[..end].split(':').skip(1).collect()
+    }
+
+    /// The flag of a `VAR[flag]` assignment
+    #[must_use]
+    pub fn flag(&self) -> Option<&str> {
+        let start = self.name.find('[')?;
+        self.name[start + 1..].strip_suffix(']')
+    }
+
+    /// The value as BitBake sees it: continuations joined, quotes removed
+    #[must_use]
+    pub fn unquoted(&self) -> String {
+        let mut joined = String::new();
+        for line in self.value.split('\n') {
+            let line = line.trim_end();
+            match line.strip_suffix('\\') {
+                Some(line) => joined.push_str(line),
+                None => joined.push_str(line),
+            }
+        }
+        let joined = joined.trim();
+        match joined.chars().next() {
+            Some(quote @ ('"' | '\'')) if joined.len() > 1 && joined.ends_with(quote) => {
+                joined[1..joined.len() - 1].to_string()
+            }
+            _ => joined.to_string(),
+        }
+    }
+}
+
+/// What a statement is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StatementKind {
+    /// `VAR op "value"`
+    Assignment(Assignment),
+    /// `inherit class...`
+    Inherit(Vec<String>),
+    /// `include`/`require` with the file it names
+    Include {
+        /// `include` or `require`
+        keyword: String,
+        /// The file, unexpanded
+        path: String,
+    },
+    /// Shell or python function, including `def` blocks
+    Function {
+        /// Function name (empty for anonymous python)
+        name: String,
+    },
+    /// Comment line
+    Comment,
+    /// Empty line
+    Blank,
+    /// Anything else (addtask, EXPORT_FUNCTIONS, unparsable lines), kept verbatim
+    Other,
+}
+
+/// A statement of a BitBake file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Statement {
+    /// What the statement is
+    pub kind: StatementKind,
+    /// Source text, without the final newline
+    pub text: String,
+    /// First line, 1-based
+    pub line: usize,
+}
+
+/// Split a file into statements, following BitBake's line handling
+#[must_use]
+pub fn statements(source: &str) -> Vec<Statement> {
+    // BitBake's __func_start_regexp__ and __def_regexp__
+    let func_start =
+        Regex::new(r"^((python|fakeroot)\s+)*(?P<func>[\w.\-+{}$:]+)?\s*\(\s*\)\s*\{\s*$").unwrap();
+    let def_start = Regex::new(r"^def\s+(?P<func>\w+)\s*\(.*\)\s*:").unwrap();
+
+    let lines: Vec<&str> = source.lines().collect();
+    let mut statements = Vec::new();
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i];
+        let trimmed = line.trim();
+        let start = i;
+
+        let kind = if trimmed.is_empty() {
+            i += 1;
+            StatementKind::Blank
+        } else if trimmed.starts_with('#') {
+            i += 1;
+            StatementKind::Comment
+        } else if let Some(caps) = func_start.captures(line.trim_end()) {
+            // Body runs up to a line that is just "}"
+            i += 1;
+            while i < lines.len() && lines[i].trim_end() != "}" {
+                i += 1;
+            }
+            i = (i + 1).min(lines.len());
+            StatementKind::Function {
+                name: caps.name("func").map_or("", |m| m.as_str()).to_string(),
+            }
+        } else if let Some(caps) = def_start.captures(line) {
+            // Indented lines, comments and blank lines continue a def block
+            i += 1;
+            while i < lines.len()
+                && (lines[i].is_empty()
+                    || lines[i].starts_with([' ', '\t'])
+                    || lines[i].starts_with('#'))
+            {
+                i += 1;
+            }
+            while i > start + 1 && lines[i - 1].trim().is_empty() {
+                i -= 1;
+            }
+            StatementKind::Function {
+                name: caps["func"].to_string(),
+            }
+        } else {
+            while i + 1 < lines.len() && lines[i].trim_end().ends_with('\\') {
+                i += 1;
+            }
+            i += 1;
+            classify(&lines[start..i].join("\n"))
+        };
+
+        statements.push(Statement {
+            kind,
+            text: lines[start..i].join("\n"),
+            line: start + 1,
+        });
+    }
+
+    statements
+}
+
+/// Classify a logical line through its CST
+fn classify(text: &str) -> StatementKind {
+    let (export, rest) = match text.strip_prefix("export") {
+        Some(rest) if rest.starts_with([' ', '\t']) && rest.contains(['=']) => {
+            (true, rest.trim_start())
+        }
+        _ => (false, text),
+    };
+
+    let parse = parser::parse(rest);
+    let root = parse.syntax();
+    let mut nodes = root.children();
+    let (Some(node), None) = (nodes.next(), nodes.next()) else {
+        return StatementKind::Other;
+    };
+    // The node must cover the statement, or formatting would drop text
+    if node.text().to_string().trim_end() != rest.trim_end() {
+        return StatementKind::Other;
+    }
+
+    match node.kind() {
+        SyntaxKind::VARIABLE_ASSIGNMENT => assignment(&node, export).map_or(StatementKind::Other, StatementKind::Assignment),
+        SyntaxKind::INHERIT_STMT => StatementKind::Inherit(
+            rest.split_whitespace().skip(1).map(str::to_string).collect(),
+        ),
+        SyntaxKind::INCLUDE_STMT | SyntaxKind::REQUIRE_STMT => {
+            let (keyword, path) = rest.trim().split_once(char::is_whitespace).unwrap_or((rest.trim(), ""));
+            StatementKind::Include {
+                keyword: keyword.to_string(),
+                path: path.trim().to_string(),
+            }
+        }
+        _ => StatementKind::Other,
+    }
+}
+
+fn assignment(node: &SyntaxNode, export: bool) -> Option<Assignment> {
+    let mut name = None;
+    let mut op = None;
+    let mut value = String::new();
+    for element in node.children_with_tokens() {
+        match element {
+            rowan::NodeOrToken::Node(child) if child.kind() == SyntaxKind::VARIABLE_NAME => {
+                name = Some(child.text().to_string());
+            }
+            rowan::NodeOrToken::Node(child) if child.kind() == SyntaxKind::VARIABLE_VALUE => {
+                value = child.text().to_string();
+            }
+            rowan::NodeOrToken::Token(token) if token.kind().is_assignment_op() => {
+                op = Some(token.text().to_string());
+            }
+            _ => {}
+        }
+    }
+    Some(Assignment {
+        export,
+        name: name?,
+        op: op?,
+        value: value.trim_end().to_string(),
+    })
+}
+
+/// Formatter settings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatOptions {
+    /// Sort assignments into the OE style guide order
+    pub reorder: bool,
+    /// Indentation of continuation lines
+    pub indent: usize,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            reorder: true,
+            indent: 4,
+        }
+    }
+}
+
+impl FormatOptions {
+    /// Defaults for a file: the style guide order only applies to recipes
+    #[must_use]
+    pub fn for_path(path: &Path) -> Self {
+        let recipe = path
+            .extension()
+            .is_some_and(|ext| ext == "bb" || ext == "bbappend");
+        Self {
+            reorder: recipe,
+            ..Self::default()
+        }
+    }
+}
+
+/// Format a BitBake file
+#[must_use]
+pub fn format(source: &str, options: &FormatOptions) -> String {
+    let statements = statements(source);
+    let mut blocks = blocks(&statements);
+    if options.reorder {
+        reorder(&mut blocks);
+    }
+
+    let mut out = String::new();
+    let mut pending_blank = false;
+    for block in &blocks {
+        if block.is_empty() {
+            pending_blank = !out.is_empty();
+            continue;
+        }
+        if pending_blank {
+            out.push('\n');
+            pending_blank = false;
+        }
+        for statement in block {
+            out.push_str(&format_statement(statement, options));
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Comments directly above a statement travel with it; a blank line is an empty block
+fn blocks(statements: &[Statement]) -> Vec<Vec<&Statement>> {
+    let mut blocks: Vec<Vec<&Statement>> = Vec::new();
+    let mut comments: Vec<&Statement> = Vec::new();
+    for statement in statements {
+        match statement.kind {
+            StatementKind::Comment => comments.push(statement),
+            StatementKind::Blank => {
+                if !comments.is_empty() {
+                    blocks.push(std::mem::take(&mut comments));
+                }
+                blocks.push(Vec::new());
+            }
+            _ => {
+                comments.push(statement);
+                blocks.push(std::mem::take(&mut comments));
+            }
+        }
+    }
+    if !comments.is_empty() {
+        blocks.push(comments);
+    }
+    blocks
+}
+
+/// The style rank of a block that may move, `None` for barriers
+fn movable_rank(block: &[&Statement]) -> Option<(usize, usize)> {
+    match &block.last()?.kind {
+        StatementKind::Assignment(a) if !a.export && a.op != ":=" => Some(style_rank(a.var())),
+        _ => None,
+    }
+}
+
+/// Sort each run of movable blocks that is out of order, keeping the relative
+/// order of equal ranks (so operations on one variable never swap)
+fn reorder(blocks: &mut Vec<Vec<&Statement>>) {
+    let mut result = Vec::with_capacity(blocks.len());
+    let mut run = Vec::new();
+    let mut blanks = Vec::new();
+
+    for block in blocks.drain(..) {
+        if block.is_empty() {
+            if run.is_empty() {
+                result.push(block);
+            } else {
+                blanks.push(block);
+            }
+        } else if movable_rank(&block).is_some() {
+            run.append(&mut blanks);
+            run.push(block);
+        } else {
+            flush_run(&mut run, &mut result);
+            result.append(&mut blanks);
+            result.push(block);
+        }
+    }
+    flush_run(&mut run, &mut result);
+    result.append(&mut blanks);
+    *blocks = result;
+}
+
+fn flush_run<'a>(run: &mut Vec<Vec<&'a Statement>>, result: &mut Vec<Vec<&'a Statement>>) {
+    let ranks: Vec<(usize, usize)> = run.iter().filter_map(|b| movable_rank(b)).collect();
+    if ranks.is_sorted() {
+        result.append(run);
+        return;
+    }
+
+    let mut sorted: Vec<Vec<&Statement>> = run.drain(..).filter(|b| !b.is_empty()).collect();
+    sorted.sort_by_key(|b| movable_rank(b));
+    let mut previous = None;
+    for block in sorted {
+        let group = movable_rank(&block).map(|(group, _)| group);
+        if previous.is_some() && previous != group {
+            result.push(Vec::new());
+        }
+        previous = group;
+        result.push(block);
+    }
+}
+
+fn format_statement(statement: &Statement, options: &FormatOptions) -> String {
+    match &statement.kind {
+        StatementKind::Assignment(a) => {
+            let value = format_value(&a.value, options.indent);
+            let export = if a.export { "export " } else { "" };
+            format!("{}{} {} {}", export, a.name.trim(), a.op, value)
+                .trim_end()
+                .to_string()
+        }
+        StatementKind::Inherit(classes) => format!("inherit {}", classes.join(" ")),
+        StatementKind::Include { keyword, path } => format!("{} {}", keyword, path),
+        StatementKind::Comment => statement.text.trim_end().to_string(),
+        StatementKind::Blank => String::new(),
+        StatementKind::Function { .. } | StatementKind::Other => statement.text.clone(),
+    }
+}
+
+/// Normalise quoting and continuation lines without changing the words of the value
+fn format_value(value: &str, indent: usize) -> String {
+    let value = value.trim();
+    let lines: Vec<&str> = value.split('\n').collect();
+
+    if lines.len() == 1 {
+        if let Some(inner) = value.strip_prefix('\'').and_then(|v| v.strip_suffix('\''))
+            && !inner.contains(['"', '\'', '\\'])
+        {
+            return format!("\"{}\"", inner);
+        }
+        return value.to_string();
+    }
+
+    let pad = " ".repeat(indent);
+    let mut out = Vec::with_capacity(lines.len());
+    for (i, line) in lines.iter().enumerate() {
+        let last = i + 1 == lines.len();
+        let content = if last {
+            *line
+        } else {
+            line.trim_end().strip_suffix('\\').unwrap_or(line)
+        };
+
+        // Indenting is only safe where whitespace already separates the words
+        let mut formatted = if i > 0 && content.trim() != "\"" && content.starts_with([' ', '\t']) {
+            pad.clone()
+        } else {
+            String::new()
+        };
+        formatted.push_str(if i == 0 { content.trim_end() } else { content.trim() });
+
+        if !last {
+            let next_spaced = lines[i + 1].starts_with([' ', '\t']);
+            let spaced = content.ends_with([' ', '\t']) || next_spaced;
+            if spaced && !formatted.trim().is_empty() && formatted.trim() != "\"" {
+                formatted.push(' ');
+            }
+            formatted.push('\\');
+        }
+        out.push(formatted);
+    }
+    out.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fmt(source: &str) -> String {
+        format(source, &FormatOptions::default())
+    }
+
+    #[test]
+    fn test_statements() {
+        let source = r#"SUMMARY = "x"
+RDEPENDS:${PN}-dev += "foo \
+    bar"
+
+do_install() {
+    install -d ${D}${bindir}
+}
+def helper(d):
+    return "1"
+
+inherit cmake pkgconfig
+require foo-${PV}.inc
+addtask foo after do_compile
+"#;
+        let statements = statements(source);
+        let kinds: Vec<_> = statements.iter().map(|s| (&s.kind, s.line)).collect();
+        assert!(matches!(kinds[0], (StatementKind::Assignment(a), 1) if a.var() == "SUMMARY"));
+        let StatementKind::Assignment(rdepends) = &statements[1].kind else {
+            panic!("{:?}", statements[1]);
+        };
+        assert_eq!(rdepends.var(), "RDEPENDS");
+        assert_eq!(rdepends.overrides(), ["${PN}-dev"]);
+        assert_eq!(rdepends.unquoted(), "foo     bar");
+        assert_eq!(kinds[2], (&StatementKind::Blank, 4));
+        assert_eq!(
+            kinds[3],
+            (&StatementKind::Function { name: "do_install".to_string() }, 5)
+        );
+        assert_eq!(
+            kinds[4],
+            (&StatementKind::Function { name: "helper".to_string() }, 8)
+        );
+        assert_eq!(
+            kinds[6],
+            (&StatementKind::Inherit(vec!["cmake".to_string(), "pkgconfig".to_string()]), 11)
+        );
+        assert!(matches!(kinds[7].0, StatementKind::Include { keyword, path } if keyword == "require" && path == "foo-${PV}.inc"));
+        assert_eq!(kinds[8].0, &StatementKind::Other);
+    }
+
+    #[test]
+    fn test_flag_and_export_assignments() {
+        let statements = statements("SRC_URI[archive.sha256sum] = \"abc\"\nexport CC = 'gcc'\n");
+        let StatementKind::Assignment(flag) = &statements[0].kind else {
+            panic!();
+        };
+        assert_eq!(flag.var(), "SRC_URI");
+        assert_eq!(flag.flag(), Some("archive.sha256sum"));
+        let StatementKind::Assignment(export) = &statements[1].kind else {
+            panic!();
+        };
+        assert!(export.export);
+        assert_eq!(export.unquoted(), "gcc");
+    }
+
+    #[test]
+    fn test_spacing_and_quoting() {
+        assert_eq!(
+            fmt("FOO='bar'\nBAR  ?=   \"x\"   \ninherit   a    b\n\n\n\n# done   \n"),
+            "FOO = \"bar\"\nBAR ?= \"x\"\ninherit a b\n\n# done\n"
+        );
+        // Single quotes stay when the value needs them
+        assert_eq!(fmt("FOO = 'say \"hi\"'\n"), "FOO = 'say \"hi\"'\n");
+    }
+
+    #[test]
+    fn test_continuations() {
+        let source = "SRC_URI = \"git://x;branch=main \\\n  file://a.patch\\\n\t\tfile://b.patch \\\n        \"\n";
+        assert_eq!(
+            fmt(source),
+            "SRC_URI = \"git://x;branch=main \\\n    file://a.patch \\\n    file://b.patch \\\n\"\n"
+        );
+
+        // A continuation inside a word must not gain whitespace
+        let joined = "FOO = \"abc\\\ndef\"\n";
+        assert_eq!(fmt(joined), joined);
+    }
+
+    #[test]
+    fn test_functions_are_verbatim() {
+        let source = "do_install() {\n\tinstall -m 0644 foo   ${D}  \n}\n\npython __anonymous () {\n    d.setVar('A',  '1')\n}\n";
+        assert_eq!(fmt(source), source);
+    }
+
+    #[test]
+    fn test_style_guide_order() {
+        let source = r#"# Source
+SRC_URI = "https://example.com/foo.tar.gz"
+LICENSE = "MIT"
+SUMMARY = "Foo"
+DEPENDS = "zlib"
+DEPENDS:append = " openssl"
+
+inherit autotools
+
+RDEPENDS:${PN} = "bash"
+FILES:${PN} += "/opt"
+EXTRA_OECONF = "--disable-x"
+"#;
+        let formatted = fmt(source);
+        assert_eq!(
+            formatted,
+            r#"SUMMARY = "Foo"
+LICENSE = "MIT"
+
+DEPENDS = "zlib"
+DEPENDS:append = " openssl"
+
+# Source
+SRC_URI = "https://example.com/foo.tar.gz"
+
+inherit autotools
+
+EXTRA_OECONF = "--disable-x"
+
+FILES:${PN} += "/opt"
+RDEPENDS:${PN} = "bash"
+"#
+        );
+        assert_eq!(fmt(&formatted), formatted);
+
+        let classes = FormatOptions::for_path(Path::new("foo.bbclass"));
+        assert_eq!(format(source, &classes).lines().nth(1), Some("SRC_URI = \"https://example.com/foo.tar.gz\""));
+    }
+
+    #[test]
+    fn test_barriers_keep_order() {
+        let source = "require foo.inc\nPV = \"1\"\nA := \"${PV}\"\nLICENSE = \"MIT\"\n";
+        assert_eq!(fmt(source), source);
+    }
+
+    #[test]
+    fn test_idempotent_on_unparsable_lines() {
+        let source = "FOO = \"a\" junk \"b\"\n@@@\nBAR = unquoted\n";
+        let formatted = fmt(source);
+        assert_eq!(fmt(&formatted), formatted);
+        assert!(formatted.contains("@@@"));
+    }
+}
@@ -1,10 +1,82 @@
 // Layer context management for BitBake builds
 // Handles layer configuration, priorities, and global variable context
 
-use crate::{BitbakeRecipe, IncludeResolver, SimpleResolver};
+use crate::provider_selection::vercmp;
+use crate::{BitbakeRecipe, IncludeResolver, ProviderPreferences, SimpleResolver};
+use regex::Regex;
+use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
+
+/// A LAYERDEPENDS / LAYERRECOMMENDS entry such as `core (>= 12)`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayerDependency {
+    /// Collection name of the required layer
+    pub collection: String,
+    /// Optional version constraint as (operator, version), e.g. (">=", "12")
+    pub constraint: Option<(String, String)>,
+}
+
+impl LayerDependency {
+    /// Parse a dependency list the way bb.utils.explode_dep_versions2 does
+    /// e.g. "core (>= 12) openembedded-layer meta-python(=1)"
+    pub fn parse_list(value: &str) -> Vec<Self> {
+        let spaced = value.replace('\\', " ").replace('(', " ( ").replace(')', " ) ");
+        let mut deps: Vec<Self> = Vec::new();
+        let mut tokens = spaced.split_whitespace();
+
+        while let Some(token) = tokens.next() {
+            if token == "(" {
+                let constraint: Vec<&str> = tokens.by_ref().take_while(|t| *t != ")").collect();
+                let constraint = constraint.join("");
+                let split = constraint
+                    .find(|c: char| !matches!(c, '<' | '>' | '=' | '!'))
+                    .unwrap_or(constraint.len());
+                let (op, version) = constraint.split_at(split);
+                let op = if op.is_empty() { "=" } else { op };
+                if let Some(last) = deps.last_mut() {
+                    last.constraint = Some((op.to_string(), version.trim().to_string()));
+                }
+            } else {
+                deps.push(Self {
+                    collection: token.to_string(),
+                    constraint: None,
+                });
+            }
+        }
+
+        deps
+    }
+
+    /// Check the constraint against the LAYERVERSION of the provided layer
+    pub fn is_satisfied_by(&self, version: &str) -> bool {
+        let Some((op, wanted)) = &self.constraint else {
+            return true;
+        };
+        let ordering = vercmp(version, wanted);
+        match op.as_str() {
+            "=" | "==" => ordering == Ordering::Equal,
+            "!=" => ordering != Ordering::Equal,
+            ">=" => ordering != Ordering::Less,
+            "<=" => ordering != Ordering::Greater,
+            ">" | ">>" => ordering == Ordering::Greater,
+            "<" | "<<" => ordering == Ordering::Less,
+            _ => false,
+        }
+    }
+}
+
+/// A BBFILES_DYNAMIC entry: files parsed only if a collection is (or, with `!`, is not) present
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DynamicBbFiles {
+    /// Collection that gates this pattern
+    pub collection: String,
+    /// True for `!collection:pattern` entries (active when the collection is absent)
+    pub negated: bool,
+    /// Glob pattern with ${LAYERDIR} expanded
+    pub pattern: String,
+}
 
 /// Layer configuration from layer.conf
 #[derive(Debug, Clone)]
@@ -63,8 +135,9 @@ impl LayerConfig {
             .variables
             .get(&depends_key)
             .map(|s| {
-                s.split_whitespace()
-                    .map(|d| d.to_string())
+                LayerDependency::parse_list(s)
+                    .into_iter()
+                    .map(|d| d.collection)
                     .collect::<Vec<_>>()
             })
             .unwrap_or_default();
@@ -92,13 +165,62 @@ impl LayerConfig {
         })
     }
 
-    /// Get BBFILES pattern for this layer
+    /// Get BBFILES patterns for this layer, with ${LAYERDIR} expanded
     pub fn get_bbfiles(&self) -> Vec<String> {
         self.variables
             .get("BBFILES")
-            .map(|s| vec![s.clone()])
+            .map(|s| {
+                s.split_whitespace()
+                    .filter(|p| *p != "\\")
+                    .map(|p| self.expand_layerdir(p))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Get BBFILES_DYNAMIC entries for this layer
+    pub fn get_bbfiles_dynamic(&self) -> Vec<DynamicBbFiles> {
+        self.variables
+            .get("BBFILES_DYNAMIC")
+            .map(|s| {
+                s.split_whitespace()
+                    .filter_map(|entry| entry.split_once(':'))
+                    .map(|(collection, pattern)| {
+                        let (negated, collection) = match collection.strip_prefix('!') {
+                            Some(c) => (true, c),
+                            None => (false, collection),
+                        };
+                        DynamicBbFiles {
+                            collection: collection.to_string(),
+                            negated,
+                            pattern: self.expand_layerdir(pattern),
+                        }
+                    })
+                    .collect()
+            })
             .unwrap_or_default()
     }
+
+    /// LAYERDEPENDS_<collection> with version constraints
+    pub fn layer_depends(&self) -> Vec<LayerDependency> {
+        self.collection_list("LAYERDEPENDS")
+    }
+
+    /// LAYERRECOMMENDS_<collection> with version constraints
+    pub fn layer_recommends(&self) -> Vec<LayerDependency> {
+        self.collection_list("LAYERRECOMMENDS")
+    }
+
+    fn collection_list(&self, prefix: &str) -> Vec<LayerDependency> {
+        self.variables
+            .get(&format!("{}_{}", prefix, self.collection))
+            .map(|s| LayerDependency::parse_list(s))
+            .unwrap_or_default()
+    }
+
+    fn expand_layerdir(&self, value: &str) -> String {
+        value.replace("${LAYERDIR}", &self.layer_dir.to_string_lossy())
+    }
 }
 
 /// Build context with all layers and configuration
@@ -202,10 +324,97 @@ impl BuildContext {
     }
 
     /// Find all recipes in all layers
+    ///
+    /// Expands each layer's BBFILES plus the BBFILES_DYNAMIC entries whose
+    /// collection condition holds, then drops everything matched by BBMASK.
     pub fn find_recipes(&self) -> Vec<PathBuf> {
         let mut recipes = Vec::new();
+        let mut seen = HashSet::new();
+
+        for pattern in self.bbfile_patterns() {
+            if let Ok(files) = glob::glob(&pattern) {
+                for file in files.flatten() {
+                    if seen.insert(file.clone()) {
+                        recipes.push(file);
+                    }
+                }
+            }
+        }
+
+        // Layers without BBFILES fall back to the conventional recipes-* layout
+        for file in self.find_recipes_by_layout() {
+            if seen.insert(file.clone()) {
+                recipes.push(file);
+            }
+        }
+
+        let bbmask = self.bbmask();
+        if let Some(mask) = &bbmask {
+            let before = recipes.len();
+            recipes.retain(|f| !mask.is_match(&f.to_string_lossy()));
+            debug!("BBMASK masked {} files", before - recipes.len());
+        }
+
+        recipes
+    }
+
+    /// BBFILES globs of all layers plus the active BBFILES_DYNAMIC entries
+    pub fn bbfile_patterns(&self) -> Vec<String> {
+        let collections: HashSet<&str> = self.layers.iter().map(|l| l.collection.as_str()).collect();
+        let mut patterns = Vec::new();
 
         for layer in &self.layers {
+            patterns.extend(layer.get_bbfiles());
+
+            for dynamic in layer.get_bbfiles_dynamic() {
+                if collections.contains(dynamic.collection.as_str()) != dynamic.negated {
+                    debug!("Activating BBFILES_DYNAMIC entry for {}: {}", dynamic.collection, dynamic.pattern);
+                    patterns.push(dynamic.pattern);
+                }
+            }
+        }
+
+        patterns
+    }
+
+    /// Compile BBMASK like BitBake's collect_bbfiles: each whitespace-separated
+    /// entry is a regex searched anywhere in the file path; invalid entries are skipped
+    pub fn bbmask(&self) -> Option<Regex> {
+        let mut values: Vec<&str> = self.layers.iter().filter_map(|l| l.variables.get("BBMASK")).map(String::as_str).collect();
+        if let Some(global) = self.global_variables.get("BBMASK") {
+            values.push(global);
+        }
+
+        let mut masks = Vec::new();
+        for mask in values.iter().flat_map(|v| v.split_whitespace()) {
+            if mask == "\\" {
+                continue;
+            }
+            // An old-style single regex may end up starting with '|', which would mask everything
+            let mask = match mask.strip_prefix('|') {
+                Some(fixed) => {
+                    warn!("BBMASK contains regular expression beginning with '|', fixing: {}", mask);
+                    fixed
+                }
+                None => mask,
+            };
+            match Regex::new(mask) {
+                Ok(_) => masks.push(mask),
+                Err(_) => warn!("BBMASK contains an invalid regular expression, ignoring: {}", mask),
+            }
+        }
+
+        if masks.is_empty() {
+            return None;
+        }
+        Regex::new(&masks.join("|")).ok()
+    }
+
+    /// Find recipes in layers that do not declare BBFILES
+    fn find_recipes_by_layout(&self) -> Vec<PathBuf> {
+        let mut recipes = Vec::new();
+
+        for layer in self.layers.iter().filter(|l| l.get_bbfiles().is_empty()) {
             let recipes_dir = layer.layer_dir.join("recipes-*");
             debug!("Searching for recipes in: {:?}", recipes_dir);
 
@@ -242,6 +451,26 @@ impl BuildContext {
             }
         }
 
+        // Collection-conditional bbappends from BBFILES_DYNAMIC
+        for pattern in self.bbfile_patterns() {
+            if !pattern.ends_with(".bbappend") {
+                continue;
+            }
+            if let Ok(entries) = glob::glob(&pattern) {
+                for entry in entries.flatten() {
+                    if entry.file_stem().and_then(|s| s.to_str()) == Some(recipe_name)
+                        && !bbappends.contains(&entry)
+                    {
+                        bbappends.push(entry);
+                    }
+                }
+            }
+        }
+
+        if let Some(mask) = self.bbmask() {
+            bbappends.retain(|f| !mask.is_match(&f.to_string_lossy()));
+        }
+
         // Sort by layer priority
         bbappends.sort_by(|a, b| {
             let layer_a = self.get_layer_for_path(a);
@@ -255,6 +484,21 @@ impl BuildContext {
         bbappends
     }
 
+    /// bbappends of a recipe file, by exact name and with a `%` version wildcard
+    pub fn bbappends_for_recipe(&self, recipe: &Path) -> Vec<PathBuf> {
+        let Some(stem) = recipe.file_stem().and_then(|s| s.to_str()) else {
+            return Vec::new();
+        };
+        let pn = stem.split_once('_').map_or(stem, |(pn, _)| pn);
+        let mut bbappends = self.find_bbappends_for(stem);
+        for bbappend in self.find_bbappends_for(&format!("{}_%", pn)) {
+            if !bbappends.contains(&bbappend) {
+                bbappends.push(bbappend);
+            }
+        }
+        bbappends
+    }
+
     /// Get the layer that contains a given path
     fn get_layer_for_path(&self, path: &Path) -> Option<&LayerConfig> {
         self.layers
@@ -373,6 +617,65 @@ impl BuildContext {
         override_resolver
     }
 
+    /// Provider selection settings: PREFERRED_* from configuration and layer priorities
+    pub fn provider_preferences(&self) -> ProviderPreferences {
+        let mut prefs = ProviderPreferences::new();
+
+        // Lowest priority layer first so higher priority layer.conf settings win
+        for layer in self.layers.iter().rev() {
+            prefs.merge_variables(&layer.variables);
+            prefs.set_layer_priority(layer.collection.clone(), layer.priority);
+        }
+        prefs.merge_variables(&self.global_variables);
+
+        prefs
+    }
+
+    /// Multiconfig names listed in BBMULTICONFIG
+    pub fn multiconfigs(&self) -> Vec<String> {
+        self.global_variables
+            .get("BBMULTICONFIG")
+            .map(|value| crate::multiconfig::parse_bbmulticonfig(value))
+            .unwrap_or_default()
+    }
+
+    /// Derive the build context of a multiconfig
+    ///
+    /// Looks for conf/multiconfig/<name>.conf in `confdir` and then in each layer
+    /// (as BitBake does via BBPATH) and overlays its assignments, typically
+    /// MACHINE, DISTRO and TMPDIR, on top of this configuration.
+    pub fn for_multiconfig(&self, name: &str, confdir: &Path) -> Result<BuildContext, String> {
+        let conf_name = format!("multiconfig/{}.conf", name);
+        let conf_file = std::iter::once(confdir.join(&conf_name))
+            .chain(self.layers.iter().map(|l| l.layer_dir.join("conf").join(&conf_name)))
+            .find(|path| path.exists())
+            .ok_or_else(|| {
+                format!("Multiconfig '{}' not found: no conf/{} in the build directory or any layer", name, conf_name)
+            })?;
+
+        info!("Loading multiconfig '{}' from {:?}", name, conf_file);
+
+        let mut context = BuildContext::new();
+        context.layers = self.layers.clone();
+        context.global_variables = self.global_variables.clone();
+        context.machine = self.machine.clone();
+        context.distro = self.distro.clone();
+        context.load_conf_file(&conf_file)?;
+
+        context.machine = context.global_variables.get("MACHINE").cloned();
+        context.distro = context.global_variables.get("DISTRO").cloned();
+        context
+            .global_variables
+            .insert("BB_CURRENT_MC".to_string(), name.to_string());
+
+        Ok(context)
+    }
+
+    /// Get the collection name of the layer containing a path
+    pub fn layer_collection_for_path(&self, path: &Path) -> Option<&str> {
+        self.get_layer_for_path(path).map(|l| l.collection.as_str())
+    }
+
     /// Get layer information
     pub fn get_layers_info(&self) -> Vec<(String, i32, PathBuf)> {
         self.layers
@@ -382,22 +685,102 @@ impl BuildContext {
     }
 
     /// Verify layer dependencies
+    ///
+    /// LAYERDEPENDS must be present and satisfy any version constraint against
+    /// LAYERVERSION; unmet LAYERRECOMMENDS and LAYERSERIES_COMPAT mismatches are warnings.
     pub fn verify_dependencies(&self) -> Result<(), String> {
-        let available: HashSet<_> = self.layers.iter().map(|l| l.collection.as_str()).collect();
+        let versions: HashMap<&str, Option<&str>> = self
+            .layers
+            .iter()
+            .map(|l| (l.collection.as_str(), l.version.as_deref()))
+            .collect();
 
         for layer in &self.layers {
-            for dep in &layer.depends {
-                if !available.contains(dep.as_str()) {
+            for dep in layer.layer_depends() {
+                let Some(version) = versions.get(dep.collection.as_str()) else {
                     return Err(format!(
                         "Layer '{}' depends on '{}' which is not available",
-                        layer.collection, dep
+                        layer.collection, dep.collection
                     ));
+                };
+                let Some((_, wanted)) = &dep.constraint else {
+                    continue;
+                };
+                match version {
+                    None => {
+                        return Err(format!(
+                            "Layer '{}' depends on version {} of layer '{}', which exists in your configuration but does not specify a version",
+                            layer.collection, wanted, dep.collection
+                        ));
+                    }
+                    Some(version) if !dep.is_satisfied_by(version) => {
+                        return Err(format!(
+                            "Layer '{}' depends on version {} of layer '{}', but version {} is currently enabled",
+                            layer.collection, wanted, dep.collection, version
+                        ));
+                    }
+                    Some(_) => {}
+                }
+            }
+
+            for rec in layer.layer_recommends() {
+                let Some(version) = versions.get(rec.collection.as_str()) else {
+                    debug!("Layer '{}' recommends layer '{}', but it is not enabled", layer.collection, rec.collection);
+                    continue;
+                };
+                if let Some((op, wanted)) = &rec.constraint
+                    && !version.is_some_and(|v| rec.is_satisfied_by(v))
+                {
+                    warn!(
+                        "Layer '{}' recommends version {} {} of layer '{}', but version {} is enabled",
+                        layer.collection,
+                        op,
+                        wanted,
+                        rec.collection,
+                        version.unwrap_or("(unset)")
+                    );
                 }
             }
         }
 
+        for warning in self.check_series_compat() {
+            warn!("{}", warning);
+        }
+
         Ok(())
     }
+
+    /// Compare each layer's LAYERSERIES_COMPAT against LAYERSERIES_CORENAMES
+    /// Returns one message per incompatible or undeclared layer
+    pub fn check_series_compat(&self) -> Vec<String> {
+        let corenames = self
+            .global_variables
+            .get("LAYERSERIES_CORENAMES")
+            .or_else(|| self.layers.iter().find_map(|l| l.variables.get("LAYERSERIES_CORENAMES")));
+        let Some(corenames) = corenames else {
+            return Vec::new();
+        };
+        let corenames: Vec<&str> = corenames.split_whitespace().collect();
+
+        let mut warnings = Vec::new();
+        for layer in &self.layers {
+            if layer.series_compat.is_empty() {
+                warnings.push(format!(
+                    "Layer {} should set LAYERSERIES_COMPAT_{} in its conf/layer.conf file to list the core layer names it is compatible with.",
+                    layer.collection, layer.collection
+                ));
+            } else if !layer.series_compat.iter().any(|c| corenames.contains(&c.as_str())) {
+                warnings.push(format!(
+                    "Layer {} is not compatible with the core layer which only supports these series: {} (layer is compatible with {})",
+                    layer.collection,
+                    corenames.join(" "),
+                    layer.series_compat.join(" ")
+                ));
+            }
+        }
+
+        warnings
+    }
 }
 
 impl Default for BuildContext {
@@ -595,4 +978,172 @@ DEPENDS:append:poky = " poky-dep"
         assert_eq!(ctx.global_variables.get("MACHINE"), Some(&"qemuarm64".to_string()));
         assert_eq!(ctx.global_variables.get("DISTRO"), Some(&"poky".to_string()));
     }
+
+    fn write_layer(dir: &Path, name: &str, conf: &str) -> PathBuf {
+        let layer_dir = dir.join(name);
+        fs::create_dir_all(layer_dir.join("conf")).unwrap();
+        fs::write(layer_dir.join("conf/layer.conf"), conf).unwrap();
+        layer_dir
+    }
+
+    fn touch(path: &Path) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "").unwrap();
+    }
+
+    #[test]
+    fn test_layer_dependency_parsing() {
+        let deps = LayerDependency::parse_list("core (>= 12) openembedded-layer meta-python(=1)");
+        assert_eq!(deps.len(), 3);
+        assert_eq!(deps[0].collection, "core");
+        assert_eq!(deps[0].constraint, Some((">=".to_string(), "12".to_string())));
+        assert_eq!(deps[1].constraint, None);
+        assert_eq!(deps[2].collection, "meta-python");
+        assert_eq!(deps[2].constraint, Some(("=".to_string(), "1".to_string())));
+
+        assert!(deps[0].is_satisfied_by("14"));
+        assert!(!deps[0].is_satisfied_by("9"));
+    }
+
+    #[test]
+    fn test_bbmask_and_bbfiles_dynamic() {
+        let temp_dir = TempDir::new().unwrap();
+        let core = create_test_layer(temp_dir.path(), "core", 5);
+        let other = write_layer(
+            temp_dir.path(),
+            "meta-other",
+            r#"
+BBFILES += "${LAYERDIR}/recipes-*/*/*.bb"
+BBFILE_COLLECTIONS += "other"
+BBFILE_PRIORITY_other = "6"
+BBFILES_DYNAMIC += "core:${LAYERDIR}/dynamic-layers/core/*/*.bbappend missing:${LAYERDIR}/dynamic-layers/missing/*/*.bbappend !missing:${LAYERDIR}/fallback/*/*.bb"
+"#,
+        );
+
+        touch(&core.join("recipes-core/foo/foo_1.0.bb"));
+        touch(&core.join("recipes-core/bar/bar_1.0.bb"));
+        touch(&other.join("dynamic-layers/core/foo/foo_1.0.bbappend"));
+        touch(&other.join("dynamic-layers/missing/foo/foo_1.0.bbappend"));
+        touch(&other.join("fallback/baz/baz_1.0.bb"));
+
+        let mut context = BuildContext::new();
+        context.add_layer_from_conf(core.join("conf/layer.conf")).unwrap();
+        context.add_layer_from_conf(other.join("conf/layer.conf")).unwrap();
+        context
+            .global_variables
+            .insert("BBMASK".to_string(), "/recipes-core/bar/ invalid[(".to_string());
+
+        let recipes = context.find_recipes();
+        let has = |suffix: &str| recipes.iter().any(|r| r.ends_with(suffix));
+
+        assert!(has("recipes-core/foo/foo_1.0.bb"));
+        assert!(!has("recipes-core/bar/bar_1.0.bb"), "masked by BBMASK");
+        assert!(has("dynamic-layers/core/foo/foo_1.0.bbappend"), "core collection is present");
+        assert!(!has("dynamic-layers/missing/foo/foo_1.0.bbappend"), "collection is absent");
+        assert!(has("fallback/baz/baz_1.0.bb"), "negated entry is active");
+    }
+
+    #[test]
+    fn test_layerdepends_version_constraints() {
+        let temp_dir = TempDir::new().unwrap();
+        let core = create_test_layer(temp_dir.path(), "core", 5);
+        let app = write_layer(
+            temp_dir.path(),
+            "meta-app",
+            r#"
+BBFILE_COLLECTIONS += "app"
+LAYERDEPENDS_app = "core (>= 2)"
+LAYERRECOMMENDS_app = "optional-layer"
+"#,
+        );
+
+        let mut context = BuildContext::new();
+        context.add_layer_from_conf(core.join("conf/layer.conf")).unwrap();
+        context.add_layer_from_conf(app.join("conf/layer.conf")).unwrap();
+
+        let err = context.verify_dependencies().unwrap_err();
+        assert!(err.contains("depends on version 2 of layer 'core'"), "{}", err);
+
+        fs::write(
+            app.join("conf/layer.conf"),
+            "BBFILE_COLLECTIONS += \"app\"\nLAYERDEPENDS_app = \"core (>= 1)\"\n",
+        )
+        .unwrap();
+        let mut context = BuildContext::new();
+        context.add_layer_from_conf(core.join("conf/layer.conf")).unwrap();
+        context.add_layer_from_conf(app.join("conf/layer.conf")).unwrap();
+        assert!(context.verify_dependencies().is_ok());
+    }
+
+    #[test]
+    fn test_layerseries_compat_warnings() {
+        let temp_dir = TempDir::new().unwrap();
+        let core = write_layer(
+            temp_dir.path(),
+            "meta",
+            r#"
+BBFILE_COLLECTIONS += "core"
+LAYERSERIES_CORENAMES = "scarthgap"
+LAYERSERIES_COMPAT_core = "scarthgap"
+"#,
+        );
+        let old = write_layer(
+            temp_dir.path(),
+            "meta-old",
+            r#"
+BBFILE_COLLECTIONS += "old"
+LAYERSERIES_COMPAT_old = "kirkstone"
+"#,
+        );
+
+        let mut context = BuildContext::new();
+        context.add_layer_from_conf(core.join("conf/layer.conf")).unwrap();
+        context.add_layer_from_conf(old.join("conf/layer.conf")).unwrap();
+
+        let warnings = context.check_series_compat();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("Layer old is not compatible with the core layer"));
+        assert!(context.verify_dependencies().is_ok());
+    }
+
+    #[test]
+    fn test_multiconfig_overlay() {
+        let temp = TempDir::new().unwrap();
+        let confdir = temp.path().join("build/conf");
+        fs::create_dir_all(confdir.join("multiconfig")).unwrap();
+        fs::write(
+            confdir.join("multiconfig/fw.conf"),
+            "MACHINE = \"stm32mp1-m4\"\nDISTRO = \"zephyr\"\nTMPDIR = \"${TOPDIR}/tmp-firmware\"\n",
+        )
+        .unwrap();
+
+        let mut ctx = BuildContext::new();
+        ctx.set_machine("qemuarm64".to_string());
+        ctx.set_distro("poky".to_string());
+        ctx.global_variables.insert("BBMULTICONFIG".to_string(), "fw  fw".to_string());
+        assert_eq!(ctx.multiconfigs(), vec!["fw".to_string()]);
+
+        let fw = ctx.for_multiconfig("fw", &confdir).unwrap();
+        assert_eq!(fw.machine.as_deref(), Some("stm32mp1-m4"));
+        assert_eq!(fw.distro.as_deref(), Some("zephyr"));
+        assert_eq!(fw.global_variables.get("BB_CURRENT_MC"), Some(&"fw".to_string()));
+        assert_eq!(ctx.machine.as_deref(), Some("qemuarm64"));
+
+        let orchestrator = |variables| {
+            let config = crate::OrchestratorConfig {
+                build_dir: temp.path().join("build"),
+                machine: None,
+                distro: None,
+                max_io_parallelism: 1,
+                max_cpu_parallelism: 1,
+            };
+            crate::BuildOrchestrator::new(config).with_multiconfig("fw", variables)
+        };
+        assert_eq!(orchestrator(fw.global_variables.clone()).tmp_dir(), temp.path().join("build/tmp-firmware"));
+        let mut unset = fw.global_variables.clone();
+        unset.remove("TMPDIR");
+        assert_eq!(orchestrator(unset).tmp_dir(), temp.path().join("build/tmp-fw"));
+
+        assert!(ctx.for_multiconfig("missing", &confdir).is_err());
+    }
 }
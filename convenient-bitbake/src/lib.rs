@@ -20,6 +20,9 @@
 pub mod syntax_kind;
 pub mod lexer;
 pub mod parser;
+pub mod formatter;
+pub mod lint;
+pub mod lsp;
 pub mod resolver;
 pub mod include_resolver;
 pub mod layer_context;
@@ -33,13 +36,20 @@ pub mod python_ir_parser;
 pub mod task_parser;
 pub mod task_extractor;
 pub mod recipe_graph;
+pub mod provider_selection;
 pub mod task_graph;
+pub mod multiconfig;
 pub mod recipe_extractor;
 pub mod simple_python_eval;
+pub mod python_lib;
+pub mod datastore;
+pub mod parse_lifecycle;
 pub mod class_dependencies;
+pub mod class_extend;
 pub mod executor;
 pub mod pipeline;
 pub mod signature_cache;
+pub mod siggen;
 pub mod build_orchestrator;
 pub mod query;
 pub mod sysroot;
@@ -79,13 +89,18 @@ pub use python_analysis::{PythonAnalyzer, PythonBlock, PythonBlockType, PythonVa
 pub use task_parser::{Task, TaskDependency, TaskCollection, parse_addtask_statement, parse_deltask_statement, parse_task_flag};
 pub use task_extractor::{TaskExtractor, TaskImplementation, TaskImplementationType};
 pub use recipe_graph::{RecipeId, TaskId, Recipe, TaskNode, RecipeGraph, GraphStatistics};
-pub use task_graph::{TaskGraph, TaskGraphBuilder, ExecutableTask, TaskGraphStats};
+pub use provider_selection::{ProviderPreferences, ProviderNote};
+pub use task_graph::{TaskGraph, TaskGraphBuilder, ExecutableTask, TaskGraphStats, McTaskId, MultiConfigTaskGraph};
+pub use multiconfig::{MultiConfigTarget, McDependency};
 pub use recipe_extractor::{RecipeExtractor, RecipeExtraction, ExtractionConfig};
+pub use class_extend::ClassExtension;
 pub use simple_python_eval::SimplePythonEvaluator;
+pub use datastore::{DataStore, VarOperation, AssignOp};
+pub use parse_lifecycle::{ParseEvent, ParseOutcome};
 pub use python_ir::{PythonIR, PythonIRBuilder, Operation, OpKind, ExecutionStrategy};
 pub use python_ir_executor::{IRExecutor, IRExecutionResult};
 pub use python_ir_parser::PythonIRParser;
-pub use executor::{TaskExecutor, TaskSpec, TaskOutput, TaskSignature, ContentHash, SandboxSpec, ExecutionResult};
+pub use executor::{TaskExecutor, TaskSpec, TaskFlags, TaskOutput, TaskSignature, ContentHash, SandboxSpec, ExecutionResult};
 pub use executor::{CacheManager, CacheQuery, CleanStats, ExpungeStats};
 pub use executor::{AsyncTaskExecutor, ExecutionProgress, ExecutionSummary, TaskMonitor, TaskInfo, TaskState, BuildStats};
 pub use executor::{InteractiveExecutor, InteractiveOptions, ExecutionControlHandle};
@@ -93,6 +108,7 @@ pub use executor::{SandboxBackend, ExecutionLog, ExecutionOutcome, ExecutionErro
 pub use scheduler::{TaskScheduler, TaskPriority, ScheduledTask, SchedulerStats};
 pub use pipeline::{Pipeline, PipelineConfig, StageHash, RecipeFile, ParsedRecipe};
 pub use signature_cache::{SignatureCache, EnhancedTaskSignature, SignatureStats};
+pub use siggen::{SignatureGenerator, TaskHashes};
 pub use build_orchestrator::{BuildOrchestrator, OrchestratorConfig, BuildPlan, IncrementalStats};
 pub use sysroot::{SysrootAssembler, SysrootManifest, HardlinkTreeBuilder, TaskDependency as SysrootTaskDep, SysrootError, SysrootResult, generate_sysroot_manifest};
 
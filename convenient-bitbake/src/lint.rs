@@ -0,0 +1,589 @@
+// Linter for BitBake metadata, in the spirit of oelint-adv
+//
+// Rules run over formatter::statements, so they see the same assignments the
+// formatter does. Plain include/require files next to the linted file are read
+// for context (a LIC_FILES_CHKSUM or checksum set in a .inc counts), but only
+// the linted file gets diagnostics. Results serialize to SARIF 2.1.0.
+
+use crate::formatter::{self, Assignment, Statement, StatementKind};
+use serde::Serialize;
+use serde_json::json;
+use std::path::{Path, PathBuf};
+
+/// How bad a finding is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// `BitBake` rejects or misbuilds this
+    Error,
+    /// Likely a mistake
+    Warning,
+    /// Style
+    Note,
+}
+
+impl Severity {
+    /// SARIF `level` value
+    #[must_use]
+    pub fn sarif_level(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        }
+    }
+}
+
+/// A lint rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rule {
+    /// Rule id, as reported
+    pub id: &'static str,
+    /// Severity of its findings
+    pub severity: Severity,
+    /// One-line description
+    pub description: &'static str,
+}
+
+/// Every rule, in SARIF rule index order
+pub const RULES: &[Rule] = &[
+    Rule {
+        id: "missing-lic-files-chksum",
+        severity: Severity::Error,
+        description: "Recipes that fetch sources need LIC_FILES_CHKSUM unless LICENSE is CLOSED",
+    },
+    Rule {
+        id: "old-override-syntax",
+        severity: Severity::Error,
+        description: "Overrides use ':' since BitBake 1.52 (honister), not '_'",
+    },
+    Rule {
+        id: "append-space",
+        severity: Severity::Warning,
+        description: ":append values need a leading space and :prepend values a trailing one",
+    },
+    Rule {
+        id: "src-uri-checksum",
+        severity: Severity::Error,
+        description: "Downloaded SRC_URI entries need a checksum, git entries a SRCREV",
+    },
+    Rule {
+        id: "unquoted-value",
+        severity: Severity::Error,
+        description: "Assignment values must be quoted",
+    },
+    Rule {
+        id: "single-quoted-value",
+        severity: Severity::Note,
+        description: "Values use double quotes",
+    },
+    Rule {
+        id: "var-order",
+        severity: Severity::Note,
+        description: "Variables follow the OE style guide order",
+    },
+];
+
+/// A finding in a file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    /// Rule id
+    pub rule: &'static str,
+    /// Severity
+    pub severity: Severity,
+    /// What is wrong
+    pub message: String,
+    /// Line, 1-based
+    pub line: usize,
+}
+
+/// Variables that take overrides per package and were written `VAR_${PN}` before honister
+const PACKAGE_VARS: &[&str] = &[
+    "RDEPENDS",
+    "RRECOMMENDS",
+    "RSUGGESTS",
+    "RPROVIDES",
+    "RCONFLICTS",
+    "RREPLACES",
+    "FILES",
+    "ALLOW_EMPTY",
+    "INSANE_SKIP",
+    "CONFFILES",
+    "SUMMARY",
+    "DESCRIPTION",
+    "LICENSE",
+    "PKG",
+    "SYSTEMD_SERVICE",
+    "INITSCRIPT_NAME",
+    "INITSCRIPT_PARAMS",
+    "pkg_postinst",
+    "pkg_postrm",
+    "pkg_preinst",
+    "pkg_prerm",
+];
+
+/// Variables that are strings rather than lists, where :append without a space is normal
+const STRING_VARS: &[&str] = &["PV", "PR", "PE", "PKGV", "PKGR", "PKGE", "S", "B", "SRCREV"];
+
+/// Fetchers that download a file and so need `SRC_URI[sha256sum]`
+const CHECKSUM_SCHEMES: &[&str] = &["http", "https", "ftp", "ftps", "sftp"];
+
+/// Lint a file; include/require files beside it are read for context
+#[must_use]
+pub fn lint(source: &str, path: &Path) -> Vec<Diagnostic> {
+    let statements = formatter::statements(source);
+    let context = included_statements(&statements, path);
+    let recipe = path.extension().is_some_and(|ext| ext == "bb");
+    let recipe_or_append = recipe || path.extension().is_some_and(|ext| ext == "bbappend");
+
+    let mut diagnostics = Vec::new();
+    for statement in &statements {
+        match &statement.kind {
+            StatementKind::Assignment(a) => {
+                check_old_override(&a.name, statement.line, &mut diagnostics);
+                check_append_space(a, statement.line, &mut diagnostics);
+                check_quoting(a, statement.line, &mut diagnostics);
+            }
+            StatementKind::Function { name } => {
+                check_old_override(name, statement.line, &mut diagnostics);
+            }
+            _ => {}
+        }
+    }
+
+    let all: Vec<&Statement> = statements.iter().chain(context.iter()).collect();
+    if recipe {
+        check_lic_files_chksum(&statements, &all, &mut diagnostics);
+    }
+    check_src_uri(&statements, &all, &mut diagnostics);
+    if recipe_or_append {
+        check_order(&statements, &mut diagnostics);
+    }
+
+    diagnostics.sort_by_key(|d| d.line);
+    diagnostics
+}
+
+/// Statements of include/require files that can be found without expansion
+fn included_statements(statements: &[Statement], path: &Path) -> Vec<Statement> {
+    let Some(dir) = path.parent() else {
+        return Vec::new();
+    };
+    statements
+        .iter()
+        .filter_map(|s| match &s.kind {
+            StatementKind::Include { path, .. } if !path.contains("${") => Some(dir.join(path)),
+            _ => None,
+        })
+        .filter_map(|file| std::fs::read_to_string(file).ok())
+        .flat_map(|content| formatter::statements(&content))
+        .collect()
+}
+
+fn assignments<'a>(statements: &'a [&Statement]) -> impl Iterator<Item = (&'a Statement, &'a Assignment)> {
+    statements.iter().filter_map(|s| match &s.kind {
+        StatementKind::Assignment(a) => Some((*s, a)),
+        _ => None,
+    })
+}
+
+fn push(diagnostics: &mut Vec<Diagnostic>, rule: &'static str, line: usize, message: String) {
+    let severity = RULES
+        .iter()
+        .find(|r| r.id == rule)
+        .map_or(Severity::Warning, |r| r.severity);
+    diagnostics.push(Diagnostic {
+        rule,
+        severity,
+        message,
+        line,
+    });
+}
+
+fn check_old_override(name: &str, line: usize, diagnostics: &mut Vec<Diagnostic>) {
+    let base = &name[..name.find([':', '[', '(']).unwrap_or(name.len())];
+    for op in ["append", "prepend", "remove"] {
+        let old = format!("_{}", op);
+        if let Some(pos) = base.find(&old)
+            && base[pos + old.len()..].chars().next().is_none_or(|c| c == '_')
+        {
+            let fixed = format!("{}:{}{}", &base[..pos], op, base[pos + old.len()..].replace('_', ":"));
+            push(
+                diagnostics,
+                "old-override-syntax",
+                line,
+                format!("'{}' uses the old override syntax, use '{}'", base, fixed),
+            );
+            return;
+        }
+    }
+    for var in PACKAGE_VARS {
+        if let Some(rest) = base.strip_prefix(var)
+            && rest.starts_with("_${")
+        {
+            push(
+                diagnostics,
+                "old-override-syntax",
+                line,
+                format!("'{}' uses the old override syntax, use '{}:{}'", base, var, &rest[1..]),
+            );
+            return;
+        }
+    }
+}
+
+fn check_append_space(a: &Assignment, line: usize, diagnostics: &mut Vec<Diagnostic>) {
+    if a.flag().is_some() || STRING_VARS.contains(&a.var()) {
+        return;
+    }
+    let overrides = a.overrides();
+    let value = a.unquoted();
+    if value.is_empty() {
+        return;
+    }
+    if overrides.contains(&"append") && !value.starts_with(char::is_whitespace) {
+        push(
+            diagnostics,
+            "append-space",
+            line,
+            format!("{} appends \"{}\" without a leading space", a.name, value),
+        );
+    } else if overrides.contains(&"prepend") && !value.ends_with(char::is_whitespace) {
+        push(
+            diagnostics,
+            "append-space",
+            line,
+            format!("{} prepends \"{}\" without a trailing space", a.name, value),
+        );
+    }
+}
+
+fn check_quoting(a: &Assignment, line: usize, diagnostics: &mut Vec<Diagnostic>) {
+    let value = a.value.trim();
+    if value.starts_with('\'') {
+        push(
+            diagnostics,
+            "single-quoted-value",
+            line,
+            format!("{} uses single quotes", a.name),
+        );
+    } else if !value.starts_with('"') || !value.ends_with('"') || value.len() < 2 {
+        push(
+            diagnostics,
+            "unquoted-value",
+            line,
+            format!("{} = {} is not quoted", a.name, value),
+        );
+    }
+}
+
+fn check_lic_files_chksum(statements: &[Statement], all: &[&Statement], diagnostics: &mut Vec<Diagnostic>) {
+    let mut license = None;
+    let mut src_uri = None;
+    for (statement, a) in assignments(all) {
+        if a.flag().is_some() {
+            continue;
+        }
+        match a.var() {
+            "LIC_FILES_CHKSUM" => return,
+            "LICENSE" => license = Some(a.unquoted()),
+            "SRC_URI" if src_uri.is_none() && !a.unquoted().trim().is_empty() => {
+                src_uri = Some(statement.line);
+            }
+            _ => {}
+        }
+    }
+    // insane.bbclass only asks for it when the recipe fetches something
+    if license.as_deref() == Some("CLOSED") || src_uri.is_none() {
+        return;
+    }
+    // Report on the linted file's SRC_URI, or its first line if that came from an include
+    let line = statements
+        .iter()
+        .find(|s| matches!(&s.kind, StatementKind::Assignment(a) if a.var() == "SRC_URI"))
+        .map_or(1, |s| s.line);
+    push(
+        diagnostics,
+        "missing-lic-files-chksum",
+        line,
+        "recipe fetches sources but has no LIC_FILES_CHKSUM".to_string(),
+    );
+}
+
+fn check_src_uri(statements: &[Statement], all: &[&Statement], diagnostics: &mut Vec<Diagnostic>) {
+    let mut checksums = Vec::new();
+    let mut srcrevs = Vec::new();
+    for (_, a) in assignments(all) {
+        match (a.var(), a.flag()) {
+            ("SRC_URI", Some(flag)) => checksums.push(flag.to_string()),
+            (var, None) if var == "SRCREV" || var.starts_with("SRCREV_") => {
+                srcrevs.push(var.to_string());
+            }
+            _ => {}
+        }
+        if a.var() == "SRCREV" {
+            for o in a.overrides() {
+                srcrevs.push(format!("SRCREV_{}", o));
+            }
+        }
+    }
+
+    for statement in statements {
+        let StatementKind::Assignment(a) = &statement.kind else {
+            continue;
+        };
+        if a.var() != "SRC_URI" || a.flag().is_some() {
+            continue;
+        }
+        for uri in a.unquoted().split_whitespace() {
+            let mut parts = uri.split(';');
+            let location = parts.next().unwrap_or_default();
+            let params: Vec<(&str, &str)> = parts.filter_map(|p| p.split_once('=')).collect();
+            let param = |key: &str| params.iter().find(|(k, _)| *k == key).map(|(_, v)| *v);
+            let Some(scheme) = scheme(location) else {
+                continue;
+            };
+
+            if CHECKSUM_SCHEMES.contains(&scheme) {
+                let name = param("name");
+                let found = checksums.iter().any(|flag| {
+                    let (prefix, kind) = match flag.rsplit_once('.') {
+                        Some((prefix, kind)) => (Some(prefix), kind),
+                        None => (None, flag.as_str()),
+                    };
+                    kind.ends_with("sum") && (prefix == name || (name.is_none() && prefix == Some("default")))
+                });
+                if !found {
+                    let flag = name.map_or("sha256sum".to_string(), |n| format!("{}.sha256sum", n));
+                    push(
+                        diagnostics,
+                        "src-uri-checksum",
+                        statement.line,
+                        format!("{} has no checksum, add SRC_URI[{}]", location, flag),
+                    );
+                }
+            } else if (scheme == "git" || scheme == "gitsm") && param("rev").is_none() {
+                let wanted = param("name").map_or("SRCREV".to_string(), |n| format!("SRCREV_{}", n));
+                if !srcrevs.iter().any(|s| *s == wanted || (s == "SRCREV" && param("name").is_none())) {
+                    push(
+                        diagnostics,
+                        "src-uri-checksum",
+                        statement.line,
+                        format!("{} has no {} and no rev= parameter", location, wanted),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// The fetcher of a SRC_URI location; `${*_MIRROR}` locations are http
+fn scheme(location: &str) -> Option<&str> {
+    if let Some(rest) = location.strip_prefix("${") {
+        let var = &rest[..rest.find('}')?];
+        return var.ends_with("_MIRROR").then_some("https");
+    }
+    location.split_once("://").map(|(scheme, _)| scheme)
+}
+
+fn check_order(statements: &[Statement], diagnostics: &mut Vec<Diagnostic>) {
+    let listed = |var: &str| {
+        formatter::STYLE_ORDER
+            .iter()
+            .flat_map(|names| names.iter())
+            .any(|name| formatter::style_rank(var) == formatter::style_rank(name) && var.starts_with(name))
+    };
+
+    let mut highest: Option<((usize, usize), String)> = None;
+    for statement in statements {
+        let (var, rank) = match &statement.kind {
+            StatementKind::Assignment(a) if listed(a.var()) => (a.var().to_string(), formatter::style_rank(a.var())),
+            StatementKind::Inherit(_) => ("inherit".to_string(), formatter::style_rank("inherit")),
+            _ => continue,
+        };
+        match &highest {
+            Some((top, top_var)) if rank < *top => push(
+                diagnostics,
+                "var-order",
+                statement.line,
+                format!("{} should come before {} (OE style guide order)", var, top_var),
+            ),
+            Some((top, _)) if rank == *top => {}
+            _ => highest = Some((rank, var)),
+        }
+    }
+}
+
+/// Lint results as a SARIF 2.1.0 log
+#[must_use]
+pub fn sarif(results: &[(PathBuf, Vec<Diagnostic>)]) -> serde_json::Value {
+    let rules: Vec<_> = RULES
+        .iter()
+        .map(|rule| {
+            json!({
+                "id": rule.id,
+                "shortDescription": { "text": rule.description },
+                "defaultConfiguration": { "level": rule.severity.sarif_level() },
+            })
+        })
+        .collect();
+
+    let results: Vec<_> = results
+        .iter()
+        .flat_map(|(path, diagnostics)| {
+            let uri = path.to_string_lossy().replace('\\', "/");
+            diagnostics.iter().map(move |d| {
+                json!({
+                    "ruleId": d.rule,
+                    "ruleIndex": RULES.iter().position(|r| r.id == d.rule),
+                    "level": d.severity.sarif_level(),
+                    "message": { "text": d.message },
+                    "locations": [{
+                        "physicalLocation": {
+                            "artifactLocation": { "uri": uri },
+                            "region": { "startLine": d.line },
+                        }
+                    }],
+                })
+            })
+        })
+        .collect();
+
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "hitzeleiter-lint",
+                    "informationUri": "https://github.com/avrabe/graph-git-rs",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules,
+                }
+            },
+            "results": results,
+        }]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(source: &str, file: &str) -> Vec<(&'static str, usize)> {
+        lint(source, Path::new(file))
+            .into_iter()
+            .map(|d| (d.rule, d.line))
+            .collect()
+    }
+
+    #[test]
+    fn test_missing_lic_files_chksum() {
+        let source = "LICENSE = \"MIT\"\nSRC_URI = \"file://foo.c\"\n";
+        assert_eq!(rules(source, "foo_1.0.bb"), [("missing-lic-files-chksum", 2)]);
+        assert!(rules(source, "foo_%.bbappend").is_empty());
+        assert!(rules("LICENSE = \"CLOSED\"\nSRC_URI = \"file://foo.c\"\n", "foo.bb").is_empty());
+        // Nothing fetched (packagegroups, images)
+        assert!(rules("LICENSE = \"MIT\"\n", "foo.bb").is_empty());
+    }
+
+    #[test]
+    fn test_old_override_syntax() {
+        let source = "DEPENDS_append = \" zlib\"\nFILES_${PN}-dev += \"/x\"\ndo_install_append() {\n    :\n}\nSRC_URI_remove_foo = \"x\"\nPACKAGE_BEFORE_PN = \"x\"\n";
+        let diagnostics = lint(source, Path::new("foo.bbclass"));
+        let found: Vec<_> = diagnostics.iter().map(|d| (d.rule, d.line)).collect();
+        assert_eq!(
+            found,
+            [("old-override-syntax", 1), ("old-override-syntax", 2), ("old-override-syntax", 3), ("old-override-syntax", 6)]
+        );
+        assert!(diagnostics[0].message.contains("'DEPENDS:append'"));
+        assert!(diagnostics[1].message.contains("'FILES:${PN}-dev'"));
+        assert!(diagnostics[3].message.contains("'SRC_URI:remove:foo'"));
+    }
+
+    #[test]
+    fn test_append_space() {
+        let source = "DEPENDS:append = \"zlib\"\nDEPENDS:append:class-target = \" ok\"\nPV:append = \".AUTOINC\"\nCFLAGS:prepend = \"-O2\"\nSRC_URI[md5sum]:append = \"x\"\n";
+        assert_eq!(rules(source, "x.bbclass"), [("append-space", 1), ("append-space", 4)]);
+    }
+
+    #[test]
+    fn test_src_uri_checksums() {
+        let source = r#"SRC_URI = "https://example.com/foo-${PV}.tar.gz \
+    ${GNU_MIRROR}/bar/bar.tar.xz;name=bar \
+    git://example.com/baz.git;protocol=https;branch=main;name=baz \
+    git://example.com/pinned.git;rev=abc123 \
+    file://fix.patch \
+"
+SRC_URI[sha256sum] = "0000"
+"#;
+        let diagnostics = lint(source, Path::new("foo.bbappend"));
+        let messages: Vec<_> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "${GNU_MIRROR}/bar/bar.tar.xz has no checksum, add SRC_URI[bar.sha256sum]",
+                "git://example.com/baz.git has no SRCREV_baz and no rev= parameter",
+            ]
+        );
+
+        let fixed = format!("{}SRC_URI[bar.sha256sum] = \"1\"\nSRCREV_baz = \"abc\"\n", source);
+        assert!(lint(&fixed, Path::new("foo.bbappend")).is_empty());
+    }
+
+    #[test]
+    fn test_context_from_include() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("foo.inc"),
+            "LIC_FILES_CHKSUM = \"file://COPYING;md5=1\"\nSRC_URI[sha256sum] = \"0\"\n",
+        )
+        .unwrap();
+        let recipe = dir.path().join("foo_1.0.bb");
+        let source = "LICENSE = \"MIT\"\nrequire foo.inc\nSRC_URI = \"https://example.com/foo.tar.gz\"\n";
+        assert!(lint(source, &recipe).is_empty());
+        assert_eq!(lint(source, Path::new("/nonexistent/foo_1.0.bb")).len(), 2);
+    }
+
+    #[test]
+    fn test_quoting() {
+        let source = "A = 'x'\nB = x\nC = \"x\"\n";
+        assert_eq!(rules(source, "x.conf"), [("single-quoted-value", 1), ("unquoted-value", 2)]);
+    }
+
+    #[test]
+    fn test_var_order() {
+        let source = "LICENSE = \"MIT\"\nSUMMARY = \"x\"\ninherit foo\nSRC_URI = \"file://a\"\nEXTRA_OECONF = \"\"\nLIC_FILES_CHKSUM = \"file://a;md5=1\"\n";
+        let order: Vec<_> = lint(source, Path::new("x.bb"))
+            .into_iter()
+            .filter(|d| d.rule == "var-order")
+            .map(|d| (d.line, d.message))
+            .collect();
+        assert_eq!(
+            order,
+            [
+                (2, "SUMMARY should come before LICENSE (OE style guide order)".to_string()),
+                (4, "SRC_URI should come before inherit (OE style guide order)".to_string()),
+                (6, "LIC_FILES_CHKSUM should come before inherit (OE style guide order)".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sarif() {
+        let diagnostics = lint("DEPENDS_append = \" x\"\n", Path::new("foo.bbappend"));
+        let log = sarif(&[(PathBuf::from("meta/foo.bbappend"), diagnostics)]);
+        assert_eq!(log["version"], "2.1.0");
+        let run = &log["runs"][0];
+        assert_eq!(run["tool"]["driver"]["rules"].as_array().unwrap().len(), RULES.len());
+        let result = &run["results"][0];
+        assert_eq!(result["ruleId"], "old-override-syntax");
+        assert_eq!(result["ruleIndex"], 1);
+        assert_eq!(result["level"], "error");
+        assert_eq!(
+            result["locations"][0]["physicalLocation"]["artifactLocation"]["uri"],
+            "meta/foo.bbappend"
+        );
+        assert_eq!(result["locations"][0]["physicalLocation"]["region"]["startLine"], 1);
+    }
+}
@@ -0,0 +1,846 @@
+// Language server for BitBake metadata
+//
+// Speaks LSP over lsp-server's connection, the transport rust-analyzer uses.
+// Documents are re-parsed with parser::parse on every change, so navigation
+// keeps working on half-written files. Layers come from the build directory's
+// bblayers.conf when there is one, otherwise from the conf/layer.conf files
+// under the workspace root. Hover, completion and function definitions parse
+// the document into a DataStore on top of the configuration, with the base
+// recipe or bbappends applied; anonymous python is not run. Hovered values and
+// include paths are resolved from that datastore through OverrideResolver.
+
+use crate::build_env::BuildEnvironment;
+use crate::datastore::{AssignOp, DataStore, VarOperation};
+use crate::layer_context::BuildContext;
+use crate::override_resolver::{OverrideAssignment, OverrideOp, OverrideResolver};
+use crate::SimpleResolver;
+use crate::syntax_kind::{SyntaxKind, SyntaxToken};
+use crate::{formatter, lint, parser, task_parser};
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _,
+    PublishDiagnostics,
+};
+use lsp_types::request::{
+    Completion, GotoDefinition, GotoImplementation, HoverRequest, Request as _,
+};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, Diagnostic,
+    DiagnosticSeverity, DidChangeTextDocumentParams, DidCloseTextDocumentParams,
+    DidOpenTextDocumentParams, GotoDefinitionParams, Hover, HoverContents, HoverParams,
+    HoverProviderCapability, ImplementationProviderCapability, InitializeParams, Location,
+    MarkupContent, MarkupKind, NumberOrString, OneOf, Position, PublishDiagnosticsParams, Range,
+    ServerCapabilities, TextDocumentIdentifier, TextDocumentPositionParams,
+    TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+};
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+use walkdir::WalkDir;
+
+/// Request listing the bbappends applied to a recipe; params are a
+/// `TextDocumentIdentifier`, the result is `Location[]`
+pub const FIND_BBAPPENDS: &str = "bitbake/findBbappends";
+
+/// Class directories searched for `inherit`, in BitBake's order
+const CLASS_DIRS: &[&str] = &["classes-recipe", "classes-global", "classes"];
+
+/// Run the server on `connection` until the client shuts it down
+pub fn run(connection: &Connection) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (id, params) = connection.initialize_start()?;
+    let params: InitializeParams = serde_json::from_value(params)?;
+    let workspace = Workspace::load(&params);
+    connection.initialize_finish(
+        id,
+        json!({
+            "capabilities": capabilities(),
+            "serverInfo": { "name": "bitbake-lsp", "version": env!("CARGO_PKG_VERSION") },
+        }),
+    )?;
+
+    let mut server = Server {
+        workspace,
+        documents: HashMap::new(),
+        data: HashMap::new(),
+    };
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    return Ok(());
+                }
+                let response = server.handle_request(request);
+                connection.sender.send(response.into())?;
+            }
+            Message::Notification(notification) => {
+                if let Some(diagnostics) = server.handle_notification(notification) {
+                    connection.sender.send(diagnostics.into())?;
+                }
+            }
+            Message::Response(_) => {}
+        }
+    }
+    Ok(())
+}
+
+fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        implementation_provider: Some(ImplementationProviderCapability::Simple(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec!["{".to_string(), ":".to_string()]),
+            ..CompletionOptions::default()
+        }),
+        ..ServerCapabilities::default()
+    }
+}
+
+/// Layers and configuration of the workspace
+struct Workspace {
+    context: BuildContext,
+    /// Parsed configuration, when a build directory was found
+    config: Option<DataStore>,
+    root: Option<PathBuf>,
+}
+
+impl Workspace {
+    /// Use `initializationOptions.buildDir`, `<root>/build` or the root itself
+    /// as build directory; without one, every layer under the root is loaded
+    fn load(params: &InitializeParams) -> Self {
+        #[allow(deprecated)]
+        let root_uri = params.root_uri.clone();
+        let root = params
+            .workspace_folders
+            .as_ref()
+            .and_then(|folders| folders.first())
+            .map(|folder| folder.uri.clone())
+            .or(root_uri)
+            .and_then(|uri| uri.to_file_path().ok());
+
+        let configured = params
+            .initialization_options
+            .as_ref()
+            .and_then(|options| options.get("buildDir"))
+            .and_then(|dir| dir.as_str())
+            .map(|dir| match &root {
+                Some(root) => root.join(dir),
+                None => PathBuf::from(dir),
+            });
+        let build_dir = configured.or_else(|| {
+            let root = root.as_ref()?;
+            [root.join("build"), root.clone()]
+                .into_iter()
+                .find(|dir| dir.join("conf/bblayers.conf").exists())
+        });
+
+        if let Some(build_dir) = build_dir {
+            match Self::from_build_dir(&build_dir) {
+                Ok((context, config)) => {
+                    info!("Loaded build directory {}", build_dir.display());
+                    return Self {
+                        context,
+                        config: Some(config),
+                        root,
+                    };
+                }
+                Err(e) => warn!("Ignoring build directory {}: {}", build_dir.display(), e),
+            }
+        }
+
+        let mut context = BuildContext::new();
+        if let Some(root) = &root {
+            for entry in WalkDir::new(root)
+                .max_depth(5)
+                .into_iter()
+                .filter_entry(|e| {
+                    let name = e.file_name().to_string_lossy();
+                    e.depth() == 0 || !(name.starts_with('.') || name == "tmp")
+                })
+                .filter_map(Result::ok)
+            {
+                let path = entry.path();
+                if path.ends_with("conf/layer.conf")
+                    && let Err(e) = context.add_layer_from_conf(path)
+                {
+                    warn!("Failed to load {}: {}", path.display(), e);
+                }
+            }
+        }
+        Self {
+            context,
+            config: None,
+            root,
+        }
+    }
+
+    fn from_build_dir(build_dir: &Path) -> Result<(BuildContext, DataStore), String> {
+        let env = BuildEnvironment::from_build_dir(build_dir)?;
+        let context = env.create_build_context()?;
+        let config = env.parse_configuration(&context)?;
+        Ok((context, config))
+    }
+
+    /// BBPATH, the layers, and the layer `file` belongs to
+    fn search_dirs(&self, file: &Path) -> Vec<PathBuf> {
+        let mut dirs = self.config.as_ref().map(DataStore::bbpath).unwrap_or_default();
+        dirs.extend(self.context.layers.iter().map(|layer| layer.layer_dir.clone()));
+        if let Some(layer) = file
+            .ancestors()
+            .find(|dir| dir.join("conf/layer.conf").exists())
+        {
+            dirs.push(layer.to_path_buf());
+        }
+        let mut seen = BTreeSet::new();
+        dirs.retain(|dir| seen.insert(dir.clone()));
+        dirs
+    }
+
+    fn find_class(&self, file: &Path, name: &str) -> Option<PathBuf> {
+        let file_name = &format!("{}.bbclass", name);
+        self.search_dirs(file)
+            .into_iter()
+            .flat_map(|dir| CLASS_DIRS.iter().map(move |sub| dir.join(sub).join(file_name)))
+            .find(|path| path.exists())
+    }
+
+    /// include/require: relative to the including file first, then BBPATH
+    fn find_include(&self, file: &Path, include: &str) -> Option<PathBuf> {
+        let include = Path::new(include);
+        if include.is_absolute() {
+            return include.exists().then(|| include.to_path_buf());
+        }
+        file.parent()
+            .map(|dir| dir.join(include))
+            .into_iter()
+            .chain(self.search_dirs(file).into_iter().map(|dir| dir.join(include)))
+            .find(|path| path.exists())
+    }
+
+    fn classes(&self, file: &Path) -> BTreeSet<String> {
+        self.search_dirs(file)
+            .iter()
+            .flat_map(|dir| CLASS_DIRS.iter().map(move |sub| dir.join(sub)))
+            .filter_map(|dir| std::fs::read_dir(dir).ok())
+            .flatten()
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                (path.extension()? == "bbclass").then(|| path.file_stem()?.to_str().map(str::to_string))?
+            })
+            .collect()
+    }
+
+    /// The recipe a bbappend applies to; `%` matches any version
+    fn recipe_for_append(&self, bbappend: &Path) -> Option<PathBuf> {
+        let stem = bbappend.file_stem()?.to_str()?;
+        self.context.find_recipes().into_iter().find(|recipe| {
+            recipe.extension().is_some_and(|ext| ext == "bb")
+                && recipe.file_stem().and_then(|s| s.to_str()).is_some_and(|name| {
+                    match stem.strip_suffix('%') {
+                        Some(prefix) => name.starts_with(prefix),
+                        None => name == stem,
+                    }
+                })
+        })
+    }
+
+    /// The document parsed on top of the configuration
+    fn datastore(&self, file: &Path, text: &str) -> DataStore {
+        let mut d = match &self.config {
+            Some(config) => config.clone(),
+            None => {
+                let mut d = DataStore::new();
+                for dir in self.search_dirs(file) {
+                    d.add_search_path(dir);
+                }
+                d
+            }
+        };
+
+        let extension = file.extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+        if extension != "bb" && extension != "bbappend" {
+            if let Err(e) = d.parse_content(text, file) {
+                debug!("{}: {}", file.display(), e);
+            }
+            return d;
+        }
+
+        let recipe = match extension {
+            "bbappend" => self.recipe_for_append(file),
+            _ => Some(file.to_path_buf()),
+        };
+        let recipe_path = recipe.as_deref().unwrap_or(file);
+        let stem = recipe_path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+        let (pn, pv) = stem.split_once('_').unwrap_or((stem, "1.0"));
+        d.set_var("FILE", recipe_path.to_string_lossy());
+        d.set_var("PN", pn);
+        d.set_var("PV", pv);
+
+        let mut parse = || -> Result<(), String> {
+            if self.config.is_some() {
+                d.inherit("base")?;
+            }
+            match &recipe {
+                Some(recipe) if extension == "bbappend" => {
+                    d.parse_file(recipe)?;
+                    d.parse_content(text, file)?;
+                }
+                _ => {
+                    d.parse_content(text, file)?;
+                    for bbappend in self.context.bbappends_for_recipe(file) {
+                        d.parse_file(&bbappend)?;
+                    }
+                }
+            }
+            d.finalize()
+        };
+        if let Err(e) = parse() {
+            debug!("{}: {}", file.display(), e);
+        }
+        d
+    }
+
+    fn display_path(&self, path: &Path) -> String {
+        let relative = self.root.as_deref().and_then(|root| path.strip_prefix(root).ok());
+        relative.unwrap_or(path).display().to_string()
+    }
+}
+
+struct Server {
+    workspace: Workspace,
+    /// Open documents
+    documents: HashMap<Url, String>,
+    /// Datastores of open documents, built on first use
+    data: HashMap<Url, DataStore>,
+}
+
+impl Server {
+    fn handle_request(&mut self, request: Request) -> Response {
+        let id = request.id.clone();
+        let result = match request.method.as_str() {
+            GotoDefinition::METHOD => params::<GotoDefinitionParams>(request).map(|p| {
+                json!(self.definition(&p.text_document_position_params))
+            }),
+            GotoImplementation::METHOD => params::<GotoDefinitionParams>(request).map(|p| {
+                json!(self.find_bbappends(&p.text_document_position_params.text_document))
+            }),
+            FIND_BBAPPENDS => params::<TextDocumentIdentifier>(request)
+                .map(|document| json!(self.find_bbappends(&document))),
+            HoverRequest::METHOD => params::<HoverParams>(request)
+                .map(|p| json!(self.hover(&p.text_document_position_params))),
+            Completion::METHOD => params::<CompletionParams>(request)
+                .map(|p| json!(self.completion(&p.text_document_position))),
+            method => {
+                return Response::new_err(
+                    id,
+                    ErrorCode::MethodNotFound as i32,
+                    format!("Unhandled method {}", method),
+                );
+            }
+        };
+        match result {
+            Ok(value) => Response::new_ok(id, value),
+            Err(e) => Response::new_err(id, ErrorCode::InvalidParams as i32, e.to_string()),
+        }
+    }
+
+    /// Update the open documents; returns diagnostics to publish
+    fn handle_notification(&mut self, notification: Notification) -> Option<Notification> {
+        let (uri, text) = match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let p: DidOpenTextDocumentParams = serde_json::from_value(notification.params).ok()?;
+                (p.text_document.uri, p.text_document.text)
+            }
+            DidChangeTextDocument::METHOD => {
+                let p: DidChangeTextDocumentParams = serde_json::from_value(notification.params).ok()?;
+                (p.text_document.uri, p.content_changes.into_iter().last()?.text)
+            }
+            DidCloseTextDocument::METHOD => {
+                let p: DidCloseTextDocumentParams = serde_json::from_value(notification.params).ok()?;
+                self.documents.remove(&p.text_document.uri);
+                self.data.remove(&p.text_document.uri);
+                return None;
+            }
+            _ => return None,
+        };
+
+        let params = PublishDiagnosticsParams {
+            diagnostics: diagnostics(&uri, &text),
+            uri: uri.clone(),
+            version: None,
+        };
+        self.data.remove(&uri);
+        self.documents.insert(uri, text);
+        Some(Notification::new(PublishDiagnostics::METHOD.to_string(), params))
+    }
+
+    /// Text of an open document, or the file on disk
+    fn text(&self, uri: &Url) -> Option<String> {
+        match self.documents.get(uri) {
+            Some(text) => Some(text.clone()),
+            None => std::fs::read_to_string(uri.to_file_path().ok()?).ok(),
+        }
+    }
+
+    fn data(&mut self, uri: &Url, file: &Path, text: &str) -> &DataStore {
+        self.data
+            .entry(uri.clone())
+            .or_insert_with(|| self.workspace.datastore(file, text))
+    }
+
+    fn definition(&mut self, position: &TextDocumentPositionParams) -> Vec<Location> {
+        let uri = &position.text_document.uri;
+        let (Some(text), Ok(file)) = (self.text(uri), uri.to_file_path()) else {
+            return Vec::new();
+        };
+        let offset = LineIndex::new(&text).offset(position.position);
+
+        match symbol_at(&text, offset) {
+            Some(Symbol::Class(name)) => self
+                .workspace
+                .find_class(&file, &name)
+                .and_then(|path| location(&path, 1))
+                .into_iter()
+                .collect(),
+            Some(Symbol::Include(include)) => {
+                let include = if include.contains("${") {
+                    let d = self.data(uri, &file, &text);
+                    d.expand(&override_resolver(d, None).base_resolver().resolve(&include))
+                } else {
+                    include
+                };
+                self.workspace
+                    .find_include(&file, &include)
+                    .and_then(|path| location(&path, 1))
+                    .into_iter()
+                    .collect()
+            }
+            Some(Symbol::Variable(name) | Symbol::Word(name)) => {
+                let d = self.data(uri, &file, &text);
+                let active = d.active_overrides();
+                // `addtask compile` names do_compile
+                [name.clone(), format!("do_{}", name)]
+                    .iter()
+                    .map(|name| {
+                        d.history(name)
+                            .into_iter()
+                            .filter(|op| op.flag.is_none())
+                            .filter(|op| assignment(op).is_none_or(|(a, _)| a.applies_to(&active)))
+                            .filter_map(|op| location(op.file.as_ref()?, op.line))
+                            .collect::<Vec<_>>()
+                    })
+                    .find(|locations| !locations.is_empty())
+                    .unwrap_or_default()
+            }
+            None => Vec::new(),
+        }
+    }
+
+    fn hover(&mut self, position: &TextDocumentPositionParams) -> Option<Hover> {
+        let uri = &position.text_document.uri;
+        let text = self.text(uri)?;
+        let file = uri.to_file_path().ok()?;
+        let offset = LineIndex::new(&text).offset(position.position);
+        let (Symbol::Variable(name) | Symbol::Word(name)) = symbol_at(&text, offset)? else {
+            return None;
+        };
+
+        self.data(uri, &file, &text);
+        let (d, workspace) = (&self.data[uri], &self.workspace);
+        let value = override_resolver(d, Some(&name)).resolve(&name).map(|v| d.expand(&v));
+        let history = d.history(&name);
+        if value.is_none() && history.is_empty() {
+            return None;
+        }
+
+        let function = d.get_var_flag(&name, "func").is_some_and(|f| f == "1");
+        let mut markdown = format!("**{}**{}\n\n", name, if function { " (function)" } else { "" });
+        match &value {
+            Some(value) => {
+                let _ = writeln!(markdown, "```\n{}\n```", value.trim_end());
+            }
+            None => markdown.push_str("*unset*\n"),
+        }
+        if !history.is_empty() {
+            markdown.push_str("\nHistory:\n");
+            for op in &history {
+                let location = op
+                    .file
+                    .as_deref()
+                    .map(|file| format!("{}:{}", workspace.display_path(file), op.line))
+                    .unwrap_or_else(|| "[set programmatically]".to_string());
+                let mut written = op.value.lines().next().unwrap_or_default().to_string();
+                if op.value.lines().nth(1).is_some() {
+                    written.push_str(" ...");
+                }
+                let flag = op.flag.as_deref().map(|f| format!("[{}]", f)).unwrap_or_default();
+                let _ = writeln!(markdown, "- `{}{}` {} `\"{}\"`", op.label(&name), flag, location, written);
+            }
+        }
+
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: markdown,
+            }),
+            range: None,
+        })
+    }
+
+    fn completion(&mut self, position: &TextDocumentPositionParams) -> Vec<CompletionItem> {
+        let uri = &position.text_document.uri;
+        let (Some(text), Ok(file)) = (self.text(uri), uri.to_file_path()) else {
+            return Vec::new();
+        };
+        let offset = LineIndex::new(&text).offset(position.position);
+        let line = &text[text[..offset].rfind('\n').map_or(0, |i| i + 1)..offset];
+        let word_len: usize = line
+            .chars()
+            .rev()
+            .take_while(|c| c.is_alphanumeric() || *c == '_' || *c == '-')
+            .map(char::len_utf8)
+            .sum();
+        let before = &line[..line.len() - word_len];
+        let first = before.split_whitespace().next().unwrap_or_default();
+
+        if matches!(first, "inherit" | "inherit_defer") {
+            return items(self.workspace.classes(&file), CompletionItemKind::MODULE);
+        }
+
+        let d = self.data(uri, &file, &text);
+        let in_value = before.matches('"').count() % 2 == 1;
+        if matches!(first, "addtask" | "deltask") || (in_value && before.ends_with(':')) {
+            let mut tasks: BTreeSet<String> = d
+                .task_statements()
+                .iter()
+                .filter_map(|statement| task_parser::parse_addtask_statement(statement))
+                .map(|task| format!("do_{}", task.name))
+                .collect();
+            tasks.extend(d.keys().into_iter().filter(|key| {
+                key.starts_with("do_") && d.get_var_flag(key, "func").is_some_and(|f| f == "1")
+            }));
+            return items(tasks, CompletionItemKind::FUNCTION);
+        }
+
+        if before.ends_with("${") || before.trim().is_empty() {
+            let mut variables: BTreeSet<String> = d
+                .keys()
+                .into_iter()
+                .filter(|key| !key.contains(':') && d.get_var_flag(key, "func").is_none())
+                .collect();
+            variables.extend(
+                formatter::STYLE_ORDER
+                    .iter()
+                    .flat_map(|group| group.iter())
+                    .filter(|name| name.chars().all(|c| c.is_ascii_uppercase() || c == '_'))
+                    .map(|name| (*name).to_string()),
+            );
+            return items(variables, CompletionItemKind::VARIABLE);
+        }
+
+        Vec::new()
+    }
+
+    fn find_bbappends(&self, document: &TextDocumentIdentifier) -> Vec<Location> {
+        let Ok(file) = document.uri.to_file_path() else {
+            return Vec::new();
+        };
+        let recipe = match file.extension() {
+            Some(ext) if ext == "bbappend" => self.workspace.recipe_for_append(&file),
+            _ => Some(file),
+        };
+        recipe
+            .map(|recipe| self.workspace.context.bbappends_for_recipe(&recipe))
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|bbappend| location(bbappend, 1))
+            .collect()
+    }
+}
+
+/// OverrideResolver over a parsed document
+///
+/// Every other variable's datastore value seeds the base resolver used for
+/// `${}` expansion; the operations on `name` are replayed as assignments, so
+/// OVERRIDES, :append, :prepend and :remove are applied by the resolver.
+/// Inline python is left to the datastore.
+fn override_resolver(d: &DataStore, name: Option<&str>) -> OverrideResolver {
+    let variables = d
+        .keys()
+        .into_iter()
+        .filter(|key| !key.contains(':') && Some(key.as_str()) != name)
+        .filter_map(|key| d.get_var_unexpanded(&key).map(|value| (key, value)))
+        .collect();
+    let mut resolver = OverrideResolver::new(SimpleResolver::from_variables(variables));
+    resolver.set_overrides(&d.active_overrides().join(":"));
+
+    let Some(name) = name else {
+        return resolver;
+    };
+    let mut assignments = Vec::new();
+    for op in d.history(name).into_iter().filter(|op| op.flag.is_none()) {
+        match assignment(op) {
+            Some(assignment) => assignments.push(assignment),
+            None if op.key == name && op.conditions.is_empty() => assignments.clear(),
+            None => {}
+        }
+    }
+    for (assignment, qualified) in assignments {
+        resolver.add_assignment(&qualified, assignment.value, assignment.operation);
+    }
+    resolver
+}
+
+/// A recorded operation as an override assignment, with its qualified name;
+/// None for `unset`
+///
+/// OverrideResolver joins appended and prepended values with a space, so their
+/// own leading or trailing space is dropped.
+fn assignment(op: &VarOperation) -> Option<(OverrideAssignment, String)> {
+    let operation = match op.op {
+        AssignOp::Set | AssignOp::Immediate => OverrideOp::Assign,
+        AssignOp::WeakDefault => OverrideOp::WeakDefault,
+        AssignOp::DefaultValue => OverrideOp::ImmediateWeakDefault,
+        AssignOp::Append | AssignOp::DotAppend | AssignOp::OverrideAppend => OverrideOp::Append,
+        AssignOp::Prepend | AssignOp::DotPrepend | AssignOp::OverridePrepend => OverrideOp::Prepend,
+        AssignOp::OverrideRemove => OverrideOp::Remove,
+        AssignOp::Unset => return None,
+    };
+    let value = match operation {
+        OverrideOp::Append | OverrideOp::Prepend => op.value.trim().to_string(),
+        _ => op.value.clone(),
+    };
+    let qualified = std::iter::once(op.key.as_str())
+        .chain(op.conditions.iter().map(String::as_str))
+        .collect::<Vec<_>>()
+        .join(":");
+    Some((OverrideAssignment::parse(&qualified, value, operation), qualified))
+}
+
+fn params<P: DeserializeOwned>(request: Request) -> Result<P, serde_json::Error> {
+    serde_json::from_value(request.params)
+}
+
+fn location(file: &Path, line: usize) -> Option<Location> {
+    let position = Position::new(u32::try_from(line.saturating_sub(1)).ok()?, 0);
+    Some(Location::new(Url::from_file_path(file).ok()?, Range::new(position, position)))
+}
+
+fn items(labels: impl IntoIterator<Item = String>, kind: CompletionItemKind) -> Vec<CompletionItem> {
+    labels
+        .into_iter()
+        .map(|label| CompletionItem {
+            label,
+            kind: Some(kind),
+            ..CompletionItem::default()
+        })
+        .collect()
+}
+
+/// Parse errors plus lint findings of a document
+fn diagnostics(uri: &Url, text: &str) -> Vec<Diagnostic> {
+    let index = LineIndex::new(text);
+    let mut diagnostics: Vec<Diagnostic> = parser::parse(text)
+        .errors
+        .iter()
+        .map(|error| Diagnostic {
+            range: Range::new(index.position(error.span.start), index.position(error.span.end)),
+            severity: Some(DiagnosticSeverity::ERROR),
+            source: Some("bitbake".to_string()),
+            message: error.message.clone(),
+            ..Diagnostic::default()
+        })
+        .collect();
+
+    if let Ok(file) = uri.to_file_path() {
+        for finding in lint::lint(text, &file) {
+            let line = finding.line.saturating_sub(1);
+            let start = index.starts.get(line).copied().unwrap_or(text.len());
+            let end = text[start..].find('\n').map_or(text.len(), |i| start + i);
+            diagnostics.push(Diagnostic {
+                range: Range::new(index.position(start), index.position(end)),
+                severity: Some(match finding.severity {
+                    lint::Severity::Error => DiagnosticSeverity::ERROR,
+                    lint::Severity::Warning => DiagnosticSeverity::WARNING,
+                    lint::Severity::Note => DiagnosticSeverity::HINT,
+                }),
+                code: Some(NumberOrString::String(finding.rule.to_string())),
+                source: Some("lint".to_string()),
+                message: finding.message,
+                ..Diagnostic::default()
+            });
+        }
+    }
+    diagnostics
+}
+
+/// What the cursor is on
+#[derive(Debug, PartialEq, Eq)]
+enum Symbol {
+    /// Class name of an inherit statement
+    Class(String),
+    /// Path of an include or require statement, as written
+    Include(String),
+    /// Assigned, exported or `${}`-referenced variable
+    Variable(String),
+    /// Any other identifier, e.g. a function or task name
+    Word(String),
+}
+
+fn symbol_at(text: &str, offset: usize) -> Option<Symbol> {
+    let root = parser::parse(text).syntax();
+    let offset = rowan::TextSize::from(u32::try_from(offset).ok()?);
+    let is_word = |token: &SyntaxToken| {
+        matches!(
+            token.kind(),
+            SyntaxKind::IDENT | SyntaxKind::VAR_EXPANSION | SyntaxKind::STRING
+        )
+    };
+    let token = [
+        root.token_at_offset(offset).right_biased(),
+        root.token_at_offset(offset).left_biased(),
+    ]
+    .into_iter()
+    .flatten()
+    .find(is_word)?;
+
+    for node in token.parent_ancestors() {
+        match node.kind() {
+            SyntaxKind::INHERIT_STMT if token.kind() == SyntaxKind::IDENT => {
+                return Some(Symbol::Class(token.text().to_string()));
+            }
+            SyntaxKind::DIRECTIVE_STMT
+                if token.kind() == SyntaxKind::IDENT
+                    && token.text() != "inherit_defer"
+                    && node.text().to_string().starts_with("inherit_defer") =>
+            {
+                return Some(Symbol::Class(token.text().to_string()));
+            }
+            SyntaxKind::INCLUDE_STMT | SyntaxKind::REQUIRE_STMT => {
+                let statement = node.text().to_string();
+                let (_, path) = statement.trim().split_once(char::is_whitespace)?;
+                return Some(Symbol::Include(path.trim().to_string()));
+            }
+            SyntaxKind::VARIABLE_NAME | SyntaxKind::EXPORT_STMT
+                if token.kind() == SyntaxKind::IDENT =>
+            {
+                return Some(Symbol::Variable(token.text().to_string()));
+            }
+            _ => {}
+        }
+    }
+
+    let start = usize::from(token.text_range().start());
+    if let Some(name) = expansion_at(token.text(), usize::from(offset) - start) {
+        return Some(Symbol::Variable(name));
+    }
+    (token.kind() == SyntaxKind::IDENT).then(|| Symbol::Word(token.text().to_string()))
+}
+
+/// Name of the `${NAME}` expansion around `offset` in `text`
+fn expansion_at(text: &str, offset: usize) -> Option<String> {
+    let start = text[..offset.min(text.len())].rfind("${")?;
+    let end = start + text[start..].find('}')?;
+    if end + 1 < offset {
+        return None;
+    }
+    let name = &text[start + 2..end];
+    (!name.is_empty() && !name.starts_with('@') && !name.contains(['{', '$', ' ']))
+        .then(|| name.to_string())
+}
+
+/// Line starts of a document, for LSP positions (UTF-16 columns)
+struct LineIndex<'a> {
+    text: &'a str,
+    starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    fn new(text: &'a str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { text, starts }
+    }
+
+    fn offset(&self, position: Position) -> usize {
+        let Some(&start) = self.starts.get(position.line as usize) else {
+            return self.text.len();
+        };
+        let line = self.text[start..].split('\n').next().unwrap_or_default();
+        let mut units = 0;
+        for (i, c) in line.char_indices() {
+            if units >= position.character as usize {
+                return start + i;
+            }
+            units += c.len_utf16();
+        }
+        start + line.len()
+    }
+
+    fn position(&self, offset: usize) -> Position {
+        let offset = offset.min(self.text.len());
+        let line = self.starts.partition_point(|&start| start <= offset) - 1;
+        let character = self.text[self.starts[line]..offset].encode_utf16().count();
+        Position::new(
+            u32::try_from(line).unwrap_or(u32::MAX),
+            u32::try_from(character).unwrap_or(u32::MAX),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbol(text: &str, needle: &str) -> Option<Symbol> {
+        symbol_at(text, text.find(needle).unwrap() + 1)
+    }
+
+    #[test]
+    fn test_symbol_at() {
+        let text = "inherit autotools pkgconfig\nrequire ${BPN}.inc\nDEPENDS:append = \" ${PYTHON_PN}\"\ndo_install() {\n    oe_runmake install\n}\nexport CC\n";
+        assert_eq!(symbol(text, "pkgconfig"), Some(Symbol::Class("pkgconfig".to_string())));
+        assert_eq!(symbol(text, "{BPN}"), Some(Symbol::Include("${BPN}.inc".to_string())));
+        assert_eq!(symbol(text, "DEPENDS"), Some(Symbol::Variable("DEPENDS".to_string())));
+        assert_eq!(symbol(text, "PYTHON_PN"), Some(Symbol::Variable("PYTHON_PN".to_string())));
+        assert_eq!(symbol(text, "oe_runmake"), Some(Symbol::Word("oe_runmake".to_string())));
+        assert_eq!(symbol(text, "CC"), Some(Symbol::Variable("CC".to_string())));
+    }
+
+    #[test]
+    fn test_override_resolver() {
+        let mut d = DataStore::new();
+        let content = "OVERRIDES = \"x86:class-target\"\nBPN = \"foo\"\nDEPENDS = \"zlib openssl\"\nDEPENDS += \"bzip2\"\nDEPENDS:append:x86 = \" nasm\"\nDEPENDS:append:arm = \" neon\"\nDEPENDS:remove = \"openssl\"\nCFLAGS:class-native = \"-O0\"\n";
+        d.parse_content(content, Path::new("/layer/recipes/foo/foo_1.0.bb")).unwrap();
+
+        let resolve = |name: &str| override_resolver(&d, Some(name)).resolve(name);
+        assert_eq!(resolve("DEPENDS").as_deref(), Some("zlib bzip2 nasm"));
+        assert_eq!(resolve("CFLAGS"), None);
+        assert_eq!(override_resolver(&d, None).base_resolver().resolve("${BPN}.inc"), "foo.inc");
+    }
+
+    #[test]
+    fn test_expansion_at() {
+        assert_eq!(expansion_at("\"a ${FOO} b\"", 5), Some("FOO".to_string()));
+        assert_eq!(expansion_at("\"a ${FOO} b\"", 9), Some("FOO".to_string()));
+        assert_eq!(expansion_at("\"a ${FOO} b\"", 11), None);
+        assert_eq!(expansion_at("\"${@d.getVar('X')}\"", 4), None);
+    }
+
+    #[test]
+    fn test_line_index_utf16() {
+        let text = "A = \"é😀x\"\nB = \"\"\n";
+        let index = LineIndex::new(text);
+        let x = text.find('x').unwrap();
+        // é is one UTF-16 unit, 😀 two
+        assert_eq!(index.position(x), Position::new(0, 8));
+        assert_eq!(index.offset(Position::new(0, 8)), x);
+        assert_eq!(index.offset(Position::new(1, 0)), text.find('B').unwrap());
+        assert_eq!(index.offset(Position::new(9, 0)), text.len());
+    }
+}
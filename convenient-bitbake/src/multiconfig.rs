@@ -0,0 +1,235 @@
+// Multiconfig (BBMULTICONFIG) support
+// Target syntax "mc:<name>:<target>" as accepted by bitbake, and the
+// do_<task>[mcdepends] = "mc:<from>:<to>:<recipe>:<task>" cross-config edges.
+// The default configuration is named "" like BitBake's mcdata[''].
+
+use std::fmt;
+
+/// Multiconfig names listed in a BBMULTICONFIG value, without duplicates
+pub fn parse_bbmulticonfig(value: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for name in value.split_whitespace() {
+        if !names.iter().any(|n| n == name) {
+            names.push(name.to_string());
+        }
+    }
+    names
+}
+
+/// A build target qualified with the multiconfig it belongs to
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MultiConfigTarget {
+    /// Multiconfig name ("" for the default configuration)
+    pub mc: String,
+    /// Recipe or provided item
+    pub target: String,
+    /// Task name without the `do_` prefix, if one was given
+    pub task: Option<String>,
+}
+
+impl MultiConfigTarget {
+    /// Parse `[mc:<name>:]<target>[:do_<task>]` (`multiconfig:` is accepted as well)
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let spec = spec.trim();
+        let (mc, rest) = match spec
+            .strip_prefix("mc:")
+            .or_else(|| spec.strip_prefix("multiconfig:"))
+        {
+            Some(qualified) => qualified.split_once(':').ok_or_else(|| {
+                format!(
+                    "Invalid multiconfig target '{}': expected mc:<name>:<target>",
+                    spec
+                )
+            })?,
+            None => ("", spec),
+        };
+
+        let (target, task) = match rest.rsplit_once(':') {
+            Some((target, task)) if task.starts_with("do_") => {
+                (target, Some(task.trim_start_matches("do_").to_string()))
+            }
+            _ => (rest, None),
+        };
+
+        if target.is_empty() {
+            return Err(format!("Invalid target '{}': no recipe given", spec));
+        }
+
+        Ok(Self {
+            mc: mc.to_string(),
+            target: target.to_string(),
+            task,
+        })
+    }
+
+    /// Whether this target belongs to the default configuration
+    pub fn is_default(&self) -> bool {
+        self.mc.is_empty()
+    }
+}
+
+impl fmt::Display for MultiConfigTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.mc.is_empty() {
+            write!(f, "mc:{}:", self.mc)?;
+        }
+        write!(f, "{}", self.target)?;
+        if let Some(task) = &self.task {
+            write!(f, ":do_{}", task)?;
+        }
+        Ok(())
+    }
+}
+
+/// One entry of a task's `mcdepends` flag
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct McDependency {
+    /// Configuration the depending task must belong to for the entry to apply
+    pub from_mc: String,
+    /// Configuration providing the dependency
+    pub to_mc: String,
+    /// Recipe in the providing configuration
+    pub recipe: String,
+    /// Task name without the `do_` prefix
+    pub task: String,
+}
+
+impl McDependency {
+    /// Parse a single `mc:<from>:<to>:<recipe>:<task>` entry
+    pub fn parse(entry: &str) -> Result<Self, String> {
+        let parts: Vec<&str> = entry.trim().split(':').collect();
+        if parts.len() != 5 || parts[0] != "mc" || parts[3].is_empty() || parts[4].is_empty() {
+            return Err(format!(
+                "Invalid mcdepends entry '{}': expected mc:<from>:<to>:<recipe>:<task>",
+                entry
+            ));
+        }
+
+        Ok(Self {
+            from_mc: parts[1].to_string(),
+            to_mc: parts[2].to_string(),
+            recipe: parts[3].to_string(),
+            task: parts[4].trim_start_matches("do_").to_string(),
+        })
+    }
+
+    /// Parse a whole `mcdepends` flag value
+    pub fn parse_list(value: &str) -> Result<Vec<Self>, String> {
+        value.split_whitespace().map(Self::parse).collect()
+    }
+}
+
+impl fmt::Display for McDependency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "mc:{}:{}:{}:do_{}",
+            self.from_mc, self.to_mc, self.recipe, self.task
+        )
+    }
+}
+
+/// Split `mc:<name>:` qualifiers out of a query expression
+///
+/// A query runs against a single recipe graph, so all qualified targets must
+/// name the same configuration. Returns the configuration and the query with
+/// the qualifiers removed.
+pub fn strip_query_multiconfig(query: &str) -> Result<(String, String), String> {
+    let mut mc: Option<String> = None;
+    let mut stripped = String::with_capacity(query.len());
+    let mut rest = query;
+
+    while let Some(pos) = rest.find("mc:") {
+        let at_word_start = pos == 0
+            || !rest[..pos]
+                .chars()
+                .next_back()
+                .is_some_and(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | ':'));
+        stripped.push_str(&rest[..pos]);
+        let after = &rest[pos + 3..];
+
+        let name_end = after.find(':');
+        match name_end {
+            Some(end) if at_word_start => {
+                let name = &after[..end];
+                if let Some(previous) = &mc
+                    && previous != name
+                {
+                    return Err(format!(
+                        "Query mixes multiconfigs '{}' and '{}'; query one configuration at a time",
+                        previous, name
+                    ));
+                }
+                mc = Some(name.to_string());
+                rest = &after[end + 1..];
+            }
+            _ => {
+                stripped.push_str("mc:");
+                rest = after;
+            }
+        }
+    }
+    stripped.push_str(rest);
+
+    Ok((mc.unwrap_or_default(), stripped))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_target() {
+        let t = MultiConfigTarget::parse("mc:firmware:fw-image").unwrap();
+        assert_eq!(t.mc, "firmware");
+        assert_eq!(t.target, "fw-image");
+        assert_eq!(t.task, None);
+
+        let t = MultiConfigTarget::parse("core-image-minimal:do_rootfs").unwrap();
+        assert!(t.is_default());
+        assert_eq!(t.target, "core-image-minimal");
+        assert_eq!(t.task.as_deref(), Some("rootfs"));
+        assert_eq!(t.to_string(), "core-image-minimal:do_rootfs");
+
+        let t = MultiConfigTarget::parse("multiconfig:fw:zephyr:do_deploy").unwrap();
+        assert_eq!(t.to_string(), "mc:fw:zephyr:do_deploy");
+
+        assert!(MultiConfigTarget::parse("mc:fw").is_err());
+        assert!(MultiConfigTarget::parse("mc:fw:").is_err());
+    }
+
+    #[test]
+    fn test_parse_mcdepends() {
+        let deps =
+            McDependency::parse_list("mc::fw:fw-image:do_deploy mc:lib32:fw:zephyr:do_install")
+                .unwrap();
+        assert_eq!(deps.len(), 2);
+        assert_eq!(deps[0].from_mc, "");
+        assert_eq!(deps[0].to_mc, "fw");
+        assert_eq!(deps[0].recipe, "fw-image");
+        assert_eq!(deps[0].task, "deploy");
+        assert_eq!(deps[1].to_string(), "mc:lib32:fw:zephyr:do_install");
+
+        assert!(McDependency::parse("mc:fw:fw-image:do_deploy").is_err());
+        assert!(McDependency::parse("xx:a:b:c:d").is_err());
+    }
+
+    #[test]
+    fn test_parse_bbmulticonfig() {
+        assert_eq!(parse_bbmulticonfig(" fw  lib32 fw "), vec!["fw", "lib32"]);
+        assert!(parse_bbmulticonfig("").is_empty());
+    }
+
+    #[test]
+    fn test_strip_query_multiconfig() {
+        let (mc, query) = strip_query_multiconfig("deps(mc:fw:*:zephyr, 2)").unwrap();
+        assert_eq!(mc, "fw");
+        assert_eq!(query, "deps(*:zephyr, 2)");
+
+        let (mc, query) = strip_query_multiconfig("rdeps(*:*, *:busybox)").unwrap();
+        assert_eq!(mc, "");
+        assert_eq!(query, "rdeps(*:*, *:busybox)");
+
+        assert!(strip_query_multiconfig("somepath(mc:a:*:x, mc:b:*:y)").is_err());
+    }
+}
@@ -2,6 +2,7 @@
 // Handles :append, :prepend, :remove, and override-qualified variables
 
 use crate::SimpleResolver;
+use crate::class_extend::ClassExtension;
 use std::collections::HashMap;
 use tracing::debug;
 
@@ -160,6 +161,17 @@ impl OverrideResolver {
         debug!("Built overrides from context: {:?}", self.active_overrides);
     }
 
+    /// Switch the class context to a BBCLASSEXTEND variant
+    /// Replaces class-target with e.g. class-native and adds virtclass-native
+    pub fn set_class_extension(&mut self, ext: &ClassExtension) {
+        self.active_overrides
+            .retain(|o| !o.starts_with("class-") && !o.starts_with("virtclass-"));
+        self.active_overrides.push(ext.virtclass_override());
+        self.active_overrides.push(ext.class_override());
+
+        debug!("Active overrides for {}: {:?}", ext, self.active_overrides);
+    }
+
     /// Add a variable assignment
     pub fn add_assignment(
         &mut self,
@@ -438,4 +450,26 @@ mod tests {
         assert!(overrides.contains(&"64".to_string())); // Auto-detected from qemuarm64
         assert!(overrides.contains(&"custom-override".to_string()));
     }
+
+    #[test]
+    fn test_set_class_extension() {
+        let resolver = create_test_resolver();
+        let mut override_resolver = OverrideResolver::new(resolver);
+        override_resolver.build_overrides_from_context(Some("qemux86-64"), None, &[]);
+
+        override_resolver.add_assignment("DEPENDS", "zlib".to_string(), OverrideOp::Assign);
+        override_resolver.add_assignment(
+            "DEPENDS:append:class-native",
+            "native-only".to_string(),
+            OverrideOp::Append,
+        );
+        assert_eq!(override_resolver.resolve("DEPENDS"), Some("zlib".to_string()));
+
+        override_resolver.set_class_extension(&ClassExtension::Native);
+        let overrides = override_resolver.active_overrides();
+        assert!(overrides.contains(&"class-native".to_string()));
+        assert!(overrides.contains(&"virtclass-native".to_string()));
+        assert!(!overrides.contains(&"class-target".to_string()));
+        assert_eq!(override_resolver.resolve("DEPENDS"), Some("zlib native-only".to_string()));
+    }
 }
@@ -0,0 +1,396 @@
+// Parse lifecycle: event handlers and anonymous python
+// BitBake runs metadata python at fixed points while parsing. ConfigParsed
+// handlers run once the configuration is parsed; for every recipe,
+// finalize_recipe() applies deferred inherits, fires RecipePreFinalise, runs
+// the anonymous functions (`python () {}`) in parse order, then fires
+// RecipeTaskPreProcess and RecipeParsed, like bb.parse.ast.finalize.
+// All of it runs against the live datastore; bb.parse.SkipRecipe stops the
+// lifecycle and its reason is kept in __SKIPPED, as BitBake's cache does.
+
+use crate::datastore::DataStore;
+use crate::python_executor::PythonExecutor;
+use std::fmt::Write as _;
+use tracing::debug;
+
+/// Variable holding the reason a recipe was skipped
+pub const SKIPPED_VAR: &str = "__SKIPPED";
+
+/// Events fired while parsing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseEvent {
+    /// Configuration (bitbake.conf, INHERIT classes) parsed
+    ConfigParsed,
+    /// Recipe parsed, before its anonymous functions run
+    RecipePreFinalise,
+    /// Anonymous functions done, before the task list is finalised
+    RecipeTaskPreProcess,
+    /// Recipe fully finalised
+    RecipeParsed,
+}
+
+impl ParseEvent {
+    /// Class name in bb.event, as used in `[eventmask]`
+    pub fn class_name(self) -> &'static str {
+        match self {
+            Self::ConfigParsed => "ConfigParsed",
+            Self::RecipePreFinalise => "RecipePreFinalise",
+            Self::RecipeTaskPreProcess => "RecipeTaskPreProcess",
+            Self::RecipeParsed => "RecipeParsed",
+        }
+    }
+
+    /// Python expression creating the event, as bb.event would
+    fn constructor(self, d: &DataStore) -> String {
+        let tasks: Vec<String> = d
+            .task_statements()
+            .iter()
+            .filter_map(|s| s.strip_prefix("addtask "))
+            .filter_map(|s| s.split_whitespace().next())
+            .map(|t| py_str(&task_name(t)))
+            .collect();
+        match self {
+            Self::ConfigParsed => "bb.event.ConfigParsed()".to_string(),
+            Self::RecipeTaskPreProcess => format!(
+                "bb.event.RecipeTaskPreProcess(d.getVar(\"FILE\"), [{}])",
+                tasks.join(", ")
+            ),
+            _ => format!("bb.event.{}(d.getVar(\"FILE\"))", self.class_name()),
+        }
+    }
+}
+
+/// How parsing a recipe ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseOutcome {
+    /// The recipe is buildable
+    Parsed,
+    /// bb.parse.SkipRecipe was raised, with its reason
+    Skipped(String),
+}
+
+/// One step of the lifecycle script
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    Fire(ParseEvent),
+    Anonymous,
+}
+
+/// Handlers registered with addhandler that want `event`
+///
+/// A handler without `[eventmask]` receives every event; mask entries may be
+/// given with or without the `bb.event.` prefix.
+pub fn handlers_for(d: &DataStore, event: ParseEvent) -> Vec<String> {
+    d.event_handlers()
+        .iter()
+        .filter(|handler| match d.get_var_flag(handler, "eventmask") {
+            Some(mask) if !mask.trim().is_empty() => mask
+                .split_whitespace()
+                .any(|m| m.rsplit('.').next() == Some(event.class_name())),
+            _ => true,
+        })
+        .filter(|handler| d.contains(handler))
+        .cloned()
+        .collect()
+}
+
+/// Fire a single event at its handlers
+pub fn fire_event(d: &mut DataStore, event: ParseEvent) -> Result<ParseOutcome, String> {
+    run(d, &[Step::Fire(event)])
+}
+
+/// Finalise a parsed recipe: deferred inherits, RecipePreFinalise handlers,
+/// anonymous functions, RecipeTaskPreProcess and RecipeParsed handlers
+pub fn finalize_recipe(d: &mut DataStore) -> Result<ParseOutcome, String> {
+    d.finalize()?;
+    run(
+        d,
+        &[
+            Step::Fire(ParseEvent::RecipePreFinalise),
+            Step::Anonymous,
+            Step::Fire(ParseEvent::RecipeTaskPreProcess),
+            Step::Fire(ParseEvent::RecipeParsed),
+        ],
+    )
+}
+
+/// Reason the recipe was skipped, if it was
+pub fn skip_reason(d: &DataStore) -> Option<String> {
+    d.get_var_unexpanded(SKIPPED_VAR)
+}
+
+fn run(d: &mut DataStore, steps: &[Step]) -> Result<ParseOutcome, String> {
+    if let Some(reason) = skip_reason(d) {
+        return Ok(ParseOutcome::Skipped(reason));
+    }
+
+    let Some(script) = lifecycle_script(d, steps) else {
+        return Ok(ParseOutcome::Parsed);
+    };
+    PythonExecutor::new().run_parse_code(&script, d)?;
+
+    Ok(match skip_reason(d) {
+        Some(reason) => {
+            debug!("Recipe skipped: {}", reason);
+            ParseOutcome::Skipped(reason)
+        }
+        None => ParseOutcome::Parsed,
+    })
+}
+
+/// Python running `steps` after the metadata's libraries and functions are
+/// defined; None when there is nothing to run
+fn lifecycle_script(d: &DataStore, steps: &[Step]) -> Option<String> {
+    let mut calls = String::new();
+    let mut functions: Vec<(String, &str)> = Vec::new();
+
+    for step in steps {
+        match *step {
+            Step::Fire(event) => {
+                let handlers = handlers_for(d, event);
+                if handlers.is_empty() {
+                    continue;
+                }
+                let _ = writeln!(calls, "    e = {}", event.constructor(d));
+                let _ = writeln!(calls, "    e.data = d");
+                for handler in handlers {
+                    let _ = writeln!(calls, "    {}(e, d)", handler);
+                    if !functions.iter().any(|(name, _)| *name == handler) {
+                        functions.push((handler, "e, d"));
+                    }
+                }
+            }
+            Step::Anonymous => {
+                for function in d.anonymous_functions() {
+                    let _ = writeln!(calls, "    {}(d)", function);
+                    functions.push((function.clone(), "d"));
+                }
+            }
+        }
+    }
+
+    if calls.is_empty() {
+        return None;
+    }
+
+    let mut script = String::new();
+
+    // addpylib: the layer's library is merged into the prelude's shims
+    for (dir, namespace) in d.python_libs() {
+        let _ = writeln!(
+            script,
+            "_addpylib({}, {})",
+            py_str(&dir.to_string_lossy()),
+            py_str(namespace)
+        );
+    }
+    if !d.python_libs().is_empty() {
+        script.push('\n');
+    }
+
+    for method in d.python_methods() {
+        let _ = writeln!(script, "{}\n", method);
+    }
+
+    // Function bodies keep their own indentation, as in bb.utils.better_compile
+    for (name, args) in functions {
+        let body = d.get_var_unexpanded(&name).unwrap_or_default();
+        let _ = writeln!(script, "def {}({}):", name, args);
+        if body.trim().is_empty() {
+            let _ = writeln!(script, "    pass");
+        } else {
+            let _ = writeln!(script, "{}", body);
+        }
+        let _ = writeln!(script);
+    }
+
+    let _ = writeln!(script, "try:");
+    script.push_str(&calls);
+    let _ = writeln!(script, "except bb.parse.SkipRecipe as exc:");
+    let _ = writeln!(
+        script,
+        "    d.setVar({}, str(exc) or \"skipped\")",
+        py_str(SKIPPED_VAR)
+    );
+
+    Some(script)
+}
+
+fn task_name(task: &str) -> String {
+    if task.starts_with("do_") {
+        task.to_string()
+    } else {
+        format!("do_{}", task)
+    }
+}
+
+/// Python string literal
+///
+/// Rust's `{:?}` escapes (`\u{1b}`, `\0`) are not valid Python, so quotes,
+/// backslashes and control characters are escaped the way Python's repr does.
+fn py_str(value: &str) -> String {
+    let mut literal = String::with_capacity(value.len() + 2);
+    literal.push('"');
+    for c in value.chars() {
+        match c {
+            '\\' => literal.push_str("\\\\"),
+            '"' => literal.push_str("\\\""),
+            '\n' => literal.push_str("\\n"),
+            '\r' => literal.push_str("\\r"),
+            '\t' => literal.push_str("\\t"),
+            // Control characters (C0, DEL, C1) all fit a \xNN escape
+            c if c.is_control() => {
+                let _ = write!(literal, "\\x{:02x}", c as u32);
+            }
+            c => literal.push(c),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn parse(content: &str) -> DataStore {
+        let mut d = DataStore::new();
+        d.parse_content(content, Path::new("/layer/recipe.bb"))
+            .unwrap();
+        d
+    }
+
+    #[test]
+    fn test_handlers_for_eventmask() {
+        let d = parse(
+            r#"
+python config_handler() {
+    pass
+}
+config_handler[eventmask] = "bb.event.ConfigParsed"
+python any_handler() {
+    pass
+}
+addhandler config_handler any_handler missing_handler
+"#,
+        );
+        assert_eq!(
+            handlers_for(&d, ParseEvent::ConfigParsed),
+            vec!["config_handler", "any_handler"]
+        );
+        assert_eq!(handlers_for(&d, ParseEvent::RecipeParsed), vec!["any_handler"]);
+    }
+
+    #[test]
+    fn test_lifecycle_script_order() {
+        let d = parse(
+            r#"
+python () {
+    d.setVar("A", "1")
+}
+python pre_handler() {
+    pass
+}
+pre_handler[eventmask] = "bb.event.RecipePreFinalise"
+addhandler pre_handler
+"#,
+        );
+        let script = lifecycle_script(
+            &d,
+            &[
+                Step::Fire(ParseEvent::RecipePreFinalise),
+                Step::Anonymous,
+                Step::Fire(ParseEvent::RecipeTaskPreProcess),
+            ],
+        )
+        .unwrap();
+
+        let handler = script.find("    pre_handler(e, d)").unwrap();
+        let anonymous = script.find("    __anon_2__layer_recipe_bb(d)").unwrap();
+        assert!(handler < anonymous);
+        assert!(script.contains("def __anon_2__layer_recipe_bb(d):\n    d.setVar(\"A\", \"1\")\n"));
+        assert!(script.contains("except bb.parse.SkipRecipe as exc:"));
+        assert!(!script.contains("RecipeTaskPreProcess"));
+
+        assert_eq!(lifecycle_script(&parse("A = \"1\"\n"), &[Step::Anonymous]), None);
+    }
+
+    #[test]
+    fn test_finalize_recipe_runs_anonymous_python() {
+        let mut d = parse(
+            r#"
+DEPENDS = "zlib"
+python () {
+    d.appendVar("DEPENDS", " openssl")
+    d.setVarFlag("do_compile", "noexec", "1")
+    d.delVar("UNWANTED")
+    bb.build.addtask("do_extra", "do_build", "do_compile", d)
+}
+UNWANTED = "1"
+"#,
+        );
+        assert_eq!(finalize_recipe(&mut d).unwrap(), ParseOutcome::Parsed);
+        assert_eq!(d.get_var("DEPENDS").as_deref(), Some("zlib openssl"));
+        assert_eq!(d.get_var_flag("do_compile", "noexec").as_deref(), Some("1"));
+        assert!(!d.contains("UNWANTED"));
+        assert_eq!(
+            d.task_statements(),
+            &["addtask do_extra after do_compile before do_build"]
+        );
+    }
+
+    #[test]
+    fn test_py_str_escapes() {
+        assert_eq!(py_str("plain"), "\"plain\"");
+        assert_eq!(
+            py_str("a \"b\" c:\\path\n\ttab"),
+            "\"a \\\"b\\\" c:\\\\path\\n\\ttab\""
+        );
+        assert_eq!(py_str("esc\u{1b}[0m\0"), "\"esc\\x1b[0m\\x00\"");
+        assert_eq!(py_str("caf\u{e9}\u{85}"), "\"caf\u{e9}\\x85\"");
+    }
+
+    #[test]
+    fn test_skip_recipe() {
+        let mut d = parse(
+            r#"
+COMPATIBLE_HOST = "arm.*"
+python () {
+    if not d.getVar("COMPATIBLE_HOST").startswith("x86"):
+        raise bb.parse.SkipRecipe("incompatible with host")
+    d.setVar("NOT_REACHED", "1")
+}
+"#,
+        );
+        assert_eq!(
+            finalize_recipe(&mut d).unwrap(),
+            ParseOutcome::Skipped("incompatible with host".to_string())
+        );
+        assert!(!d.contains("NOT_REACHED"));
+        assert_eq!(skip_reason(&d).as_deref(), Some("incompatible with host"));
+    }
+
+    #[test]
+    fn test_event_handler_sees_event() {
+        let mut d = parse(
+            r#"
+python record_event() {
+    d.appendVar("EVENTS", bb.event.getName(e) + " ")
+}
+addhandler record_event
+python () {
+    d.appendVar("EVENTS", "anon ")
+}
+"#,
+        );
+        assert_eq!(
+            fire_event(&mut d, ParseEvent::ConfigParsed).unwrap(),
+            ParseOutcome::Parsed
+        );
+        assert_eq!(finalize_recipe(&mut d).unwrap(), ParseOutcome::Parsed);
+        assert_eq!(
+            d.get_var("EVENTS").as_deref(),
+            Some("ConfigParsed RecipePreFinalise anon RecipeTaskPreProcess RecipeParsed ")
+        );
+    }
+}
@@ -0,0 +1,557 @@
+# Parse-time BitBake API for anonymous python and event handlers.
+#
+# Executed by PythonExecutor::run_parse_code before the generated lifecycle
+# code; `d` is bound to a bitbake_internal.LiveDataStore and wrapped in
+# _DataSmart below. The bb.utils and oe.* functions delegate to the native
+# _bb_utils/_oe_* modules (python_lib.rs); the wrappers here only supply
+# BitBake's signatures and the `d` lookups.
+
+import os
+import re
+import sys
+import types
+
+import _bb_utils
+import _oe_path
+import _oe_types
+import _oe_utils
+import bitbake_internal
+
+
+def _module(name):
+    module = types.ModuleType(name)
+    sys.modules[name] = module
+    parent, _, child = name.rpartition(".")
+    if parent:
+        setattr(sys.modules[parent], child, module)
+    return module
+
+
+bb = _module("bb")
+for _name in ("parse", "build", "event", "data", "utils"):
+    _module("bb." + _name)
+
+oe = _module("oe")
+oe.__path__ = []
+for _name in ("utils", "path", "types"):
+    _module("oe." + _name)
+
+
+def _words(values):
+    if isinstance(values, str):
+        return values.split()
+    return [str(v) for v in values]
+
+
+# The datastore, with DataSmart's keyword signatures
+class _DataSmart(object):
+    def __init__(self, native):
+        self._native = native
+
+    def getVar(self, var, expand=True):
+        return self._native.getVar(var, bool(expand))
+
+    def setVar(self, var, value, **kwargs):
+        self._native.setVar(var, value)
+
+    def appendVar(self, var, value, **kwargs):
+        self._native.appendVar(var, value)
+
+    def prependVar(self, var, value, **kwargs):
+        self._native.prependVar(var, value)
+
+    def delVar(self, var, **kwargs):
+        self._native.delVar(var)
+
+    def renameVar(self, key, newkey, **kwargs):
+        value = self.getVar(key, False)
+        if value is None:
+            return
+        flags = self.getVarFlags(key) or {}
+        self.setVar(newkey, value)
+        for flag, flagvalue in flags.items():
+            self.setVarFlag(newkey, flag, flagvalue)
+        self.delVar(key)
+
+    def getVarFlag(self, var, flag, expand=True, noweakdefault=False, parsing=False):
+        return self._native.getVarFlag(var, flag, bool(expand))
+
+    def setVarFlag(self, var, flag, value, **kwargs):
+        self._native.setVarFlag(var, flag, value)
+
+    def appendVarFlag(self, var, flag, value, **kwargs):
+        self._native.appendVarFlag(var, flag, value)
+
+    def prependVarFlag(self, var, flag, value, **kwargs):
+        self._native.prependVarFlag(var, flag, value)
+
+    def delVarFlag(self, var, flag, **kwargs):
+        self._native.delVarFlag(var, flag)
+
+    def setVarFlags(self, var, flags, **kwargs):
+        for flag, value in flags.items():
+            self.setVarFlag(var, flag, value)
+
+    def getVarFlags(self, var, expand=False, internalflags=False):
+        flags = self._native.getVarFlags(var)
+        if flags is None:
+            return None
+        if not internalflags:
+            flags = dict((k, v) for k, v in flags.items() if not k.startswith("_"))
+        if expand:
+            for flag in flags:
+                if expand is True or flag in expand:
+                    flags[flag] = self._native.expand(flags[flag])
+        return flags
+
+    def expand(self, s, varname=None):
+        if s is None:
+            return None
+        return self._native.expand(s)
+
+    def keys(self):
+        return self._native.keys()
+
+    def __iter__(self):
+        return iter(self._native.keys())
+
+    def __contains__(self, var):
+        return self._native.getVar(var, False) is not None
+
+    def _inherits(self):
+        return self._native._inherits()
+
+    def _addtask(self, statement):
+        self._native._addtask(statement)
+
+
+d = _DataSmart(d)
+
+
+def _log(level):
+    def log(*args):
+        bitbake_internal.log(level, " ".join(str(a) for a in args))
+    return log
+
+
+bb.plain = _log("plain")
+bb.note = _log("note")
+bb.warn = _log("warn")
+bb.error = _log("error")
+
+
+def _debug(level, *args):
+    bitbake_internal.log("debug", " ".join(str(a) for a in args))
+
+
+class BBHandledException(Exception):
+    pass
+
+
+def _fatal(*args):
+    message = " ".join(str(a) for a in args)
+    bitbake_internal.log("error", message)
+    raise BBHandledException(message)
+
+
+bb.debug = _debug
+bb.fatal = _fatal
+bb.BBHandledException = BBHandledException
+
+
+# bb.parse
+class SkipRecipe(Exception):
+    pass
+
+
+class SkipPackage(SkipRecipe):
+    pass
+
+
+class ParseError(Exception):
+    pass
+
+
+bb.parse.SkipRecipe = SkipRecipe
+bb.parse.SkipPackage = SkipPackage
+bb.parse.ParseError = ParseError
+
+
+# bb.event
+class Event(object):
+    def __init__(self):
+        self.data = None
+
+
+class ConfigParsed(Event):
+    pass
+
+
+class RecipePreFinalise(Event):
+    def __init__(self, fn):
+        Event.__init__(self)
+        self.fn = fn
+
+
+class RecipeTaskPreProcess(Event):
+    def __init__(self, fn, tasklist):
+        Event.__init__(self)
+        self.fn = fn
+        self.tasklist = tasklist
+
+
+class RecipeParsed(Event):
+    def __init__(self, fn):
+        Event.__init__(self)
+        self.fn = fn
+
+
+for _cls in (Event, ConfigParsed, RecipePreFinalise, RecipeTaskPreProcess, RecipeParsed):
+    setattr(bb.event, _cls.__name__, _cls)
+
+
+def _getName(e):
+    return e.__class__.__name__
+
+
+bb.event.getName = _getName
+
+
+# bb.build
+def _task_name(task):
+    return task if task.startswith("do_") else "do_" + task
+
+
+def _addtask(task, before=None, after=None, d=None):
+    statement = "addtask " + _task_name(task)
+    if after:
+        statement += " after " + " ".join(_task_name(t) for t in after.split())
+    if before:
+        statement += " before " + " ".join(_task_name(t) for t in before.split())
+    (d or globals()["d"])._addtask(statement)
+
+
+def _deltask(task, d=None):
+    (d or globals()["d"])._addtask("deltask " + _task_name(task))
+
+
+def _exec_func(func, d, dirs=None):
+    if not d.getVarFlag(func, "python", False):
+        bb.debug(1, "Not running shell function %s at parse time" % func)
+        return
+    body = d.getVar(func, False) or ""
+    lines = body.splitlines() or ["pass"]
+    source = "def %s(d):\n%s\n" % (func, "\n".join("    " + l for l in lines))
+    scope = dict(globals())
+    exec(compile(source, func, "exec"), scope)
+    scope[func](d)
+
+
+bb.build.addtask = _addtask
+bb.build.deltask = _deltask
+bb.build.exec_func = _exec_func
+
+
+# bb.data
+def _inherits_class(klass, d):
+    return klass in d._inherits()
+
+
+bb.data.inherits_class = _inherits_class
+
+
+# bb.utils
+def _contains(variable, checkvalues, truevalue, falsevalue, d):
+    val = d.getVar(variable)
+    if not val:
+        return falsevalue
+    return truevalue if _bb_utils.contains(val, _words(checkvalues)) else falsevalue
+
+
+def _contains_any(variable, checkvalues, truevalue, falsevalue, d):
+    val = d.getVar(variable)
+    if not val:
+        return falsevalue
+    return truevalue if _bb_utils.contains_any(val, _words(checkvalues)) else falsevalue
+
+
+def _filter(variable, checkvalues, d):
+    val = d.getVar(variable)
+    if not val:
+        return ""
+    return _bb_utils.filter(val, _words(checkvalues))
+
+
+def _to_boolean(string, default=None):
+    if not string:
+        return default
+    if isinstance(string, int):
+        return string != 0
+    return _bb_utils.to_boolean(string)
+
+
+def _explode_dep_versions2(s, *, sort=True):
+    return _bb_utils.explode_dep_versions2(s or "", bool(sort))
+
+
+def _join_deps(deps, commasep=True):
+    result = []
+    for dep in deps:
+        if deps[dep]:
+            for v in deps[dep]:
+                result.append(dep + " (" + v + ")")
+        else:
+            result.append(dep)
+    return ", ".join(result) if commasep else " ".join(result)
+
+
+bb.utils.contains = _contains
+bb.utils.contains_any = _contains_any
+bb.utils.filter = _filter
+bb.utils.to_boolean = _to_boolean
+bb.utils.vercmp_string = _bb_utils.vercmp_string
+bb.utils.vercmp_string_op = _bb_utils.vercmp_string_op
+bb.utils.split_version = _bb_utils.split_version
+bb.utils.explode_deps = _bb_utils.explode_deps
+bb.utils.explode_dep_versions2 = _explode_dep_versions2
+bb.utils.join_deps = _join_deps
+
+
+# oe.types
+def _boolean(value):
+    if value is None:
+        return False
+    if isinstance(value, bool):
+        return value
+    if not isinstance(value, str):
+        raise TypeError("boolean accepts a string, not '%s'" % type(value))
+    return _oe_types.boolean(value)
+
+
+def _integer(value, numberbase=10):
+    return int(value, int(numberbase))
+
+
+_builtin_float = float
+
+
+def _float(value, fromhex="false"):
+    if _boolean(fromhex):
+        return _builtin_float.fromhex(value)
+    return _builtin_float(value)
+
+
+class OEList(list):
+    name = "list"
+
+    def __init__(self, value, separator=None):
+        if value is not None:
+            list.__init__(self, value.split(separator))
+        else:
+            list.__init__(self)
+        self.separator = " " if separator is None else separator
+
+    def __str__(self):
+        return self.separator.join(self)
+
+
+def _regex(value, regexflags=None):
+    flagval = 0
+    if regexflags:
+        for flag in regexflags.split():
+            flag = flag.upper()
+            try:
+                flagval |= getattr(re, flag)
+            except AttributeError:
+                raise ValueError("Invalid regex flag '%s'" % flag)
+    if not value:
+        return re.compile("")
+    return re.compile(value, flagval)
+
+
+def _types_path(value, relativeto="", normalize="true", mustexist="false"):
+    return _oe_types.path(value, relativeto, _boolean(normalize), _boolean(mustexist))
+
+
+oe.types.boolean = _boolean
+oe.types.integer = _integer
+oe.types.float = _float
+oe.types.choice = _oe_types.choice
+oe.types.OEList = OEList
+oe.types.list = OEList
+oe.types.regex = _regex
+oe.types.path = _types_path
+oe.types.is_x86 = _oe_types.is_x86
+
+
+# oe.utils
+def _read_file(filename):
+    try:
+        with open(filename, "r") as f:
+            return f.read().strip()
+    except IOError:
+        return ""
+
+
+def _ifelse(condition, iftrue=True, iffalse=False):
+    return iftrue if condition else iffalse
+
+
+def _conditional(variable, checkvalue, truevalue, falsevalue, d):
+    return truevalue if d.getVar(variable) == checkvalue else falsevalue
+
+
+def _vartrue(var, iftrue, iffalse, d):
+    return iftrue if _boolean(d.getVar(var)) else iffalse
+
+
+def _less_or_equal(variable, checkvalue, truevalue, falsevalue, d):
+    if float(d.getVar(variable)) <= float(checkvalue):
+        return truevalue
+    return falsevalue
+
+
+def _version_less_or_equal(variable, checkvalue, truevalue, falsevalue, d):
+    if _bb_utils.vercmp_string(d.getVar(variable), checkvalue) <= 0:
+        return truevalue
+    return falsevalue
+
+
+def _both_contain(variable1, variable2, checkvalue, d):
+    return _oe_utils.both_contain(
+        d.getVar(variable1) or "", d.getVar(variable2) or "", _words(checkvalue))
+
+
+def _set_intersect(variable1, variable2, d):
+    return _oe_utils.set_intersect(d.getVar(variable1) or "", d.getVar(variable2) or "")
+
+
+def _prune_suffix(var, suffixes, d):
+    return _oe_utils.prune_suffix(var, list(suffixes), d.getVar("MLPREFIX") or "")
+
+
+def _str_filter(f, str, d):
+    return _oe_utils.str_filter(f, str, True)
+
+
+def _str_filter_out(f, str, d):
+    return _oe_utils.str_filter(f, str, False)
+
+
+def _any_distro_features(d, features, truevalue="1", falsevalue=""):
+    return _contains_any("DISTRO_FEATURES", features, truevalue, falsevalue, d)
+
+
+def _all_distro_features(d, features, truevalue="1", falsevalue=""):
+    return _contains("DISTRO_FEATURES", features, truevalue, falsevalue, d)
+
+
+def _trim_version(version, num_parts=2):
+    return _oe_utils.trim_version(version, num_parts)
+
+
+def _parallel_make(d, makeinst=False):
+    if makeinst:
+        pm = d.getVar("PARALLEL_MAKEINST") or ""
+    else:
+        pm = d.getVar("PARALLEL_MAKE") or ""
+    return _oe_utils.parallel_make(pm)
+
+
+def _parallel_make_argument(d, fmt, limit=None, makeinst=False):
+    v = _parallel_make(d, makeinst)
+    if v:
+        if limit:
+            v = min(limit, v)
+        return fmt % v
+    return ""
+
+
+def _packages_filter_out_system(d):
+    return _oe_utils.packages_filter_out_system(d.getVar("PN") or "", d.getVar("PACKAGES") or "")
+
+
+def _inherits(d, *classes):
+    return any(_inherits_class(cls, d) for cls in classes)
+
+
+oe.utils.read_file = _read_file
+oe.utils.ifelse = _ifelse
+oe.utils.conditional = _conditional
+oe.utils.vartrue = _vartrue
+oe.utils.less_or_equal = _less_or_equal
+oe.utils.version_less_or_equal = _version_less_or_equal
+oe.utils.both_contain = _both_contain
+oe.utils.set_intersect = _set_intersect
+oe.utils.prune_suffix = _prune_suffix
+oe.utils.str_filter = _str_filter
+oe.utils.str_filter_out = _str_filter_out
+oe.utils.any_distro_features = _any_distro_features
+oe.utils.all_distro_features = _all_distro_features
+oe.utils.trim_version = _trim_version
+oe.utils.squashspaces = _oe_utils.squashspaces
+oe.utils.parallel_make = _parallel_make
+oe.utils.parallel_make_argument = _parallel_make_argument
+oe.utils.packages_filter_out_system = _packages_filter_out_system
+oe.utils.inherits = _inherits
+
+
+# oe.path
+def _path_join(*paths):
+    return _oe_path.join(list(paths))
+
+
+def _format_display(path, metadata):
+    return _oe_path.format_display(path, metadata.getVar("TOPDIR") or "")
+
+
+def _is_path_parent(possible_parent, *paths):
+    return _oe_path.is_path_parent(possible_parent, list(paths))
+
+
+def _canonicalize(paths, sep=","):
+    canonical_paths = []
+    for path in (paths or "").split(sep):
+        if "$" not in path:
+            trailing_slash = "/" if path.endswith("/") else ""
+            canonical_paths.append(os.path.realpath(path) + trailing_slash)
+    return sep.join(canonical_paths)
+
+
+oe.path.join = _path_join
+oe.path.relative = _oe_path.relative
+oe.path.format_display = _format_display
+oe.path.is_path_parent = _is_path_parent
+oe.path.canonicalize = _canonicalize
+
+
+# addpylib: layer python libraries; the shims above stay in place for
+# whatever the layer's own package does not define
+def _addpylib(path, namespace):
+    if path not in sys.path:
+        sys.path.insert(0, path)
+    shim = sys.modules.get(namespace)
+    if shim is None:
+        try:
+            shim = __import__(namespace)
+        except Exception as exc:
+            bb.debug(1, "addpylib %s: %s" % (namespace, exc))
+            return
+    else:
+        package = os.path.join(path, namespace)
+        shim.__path__.append(package)
+        init = os.path.join(package, "__init__.py")
+        if os.path.exists(init):
+            try:
+                with open(init) as f:
+                    exec(compile(f.read(), init, "exec"), shim.__dict__)
+            except Exception as exc:
+                bb.debug(1, "addpylib %s: %s" % (namespace, exc))
+    for name in (shim.__dict__.get("BBIMPORTS") or []):
+        if namespace + "." + name in sys.modules:
+            continue
+        try:
+            __import__(namespace + "." + name)
+        except Exception as exc:
+            bb.debug(1, "addpylib %s.%s: %s" % (namespace, name, exc))
+    globals()[namespace] = shim
@@ -37,6 +37,17 @@ impl Parse {
     }
 }
 
+/// Statements that take arguments rather than a value
+const DIRECTIVES: &[&str] = &[
+    "addtask",
+    "deltask",
+    "addhandler",
+    "EXPORT_FUNCTIONS",
+    "unset",
+    "inherit_defer",
+    "addpylib",
+];
+
 struct Parser {
     tokens: Vec<Token>,
     pos: usize,
@@ -92,9 +103,13 @@ impl Parser {
             SyntaxKind::EXPORT_KW => self.export_stmt(),
             SyntaxKind::PYTHON_KW | SyntaxKind::DEF_KW => self.function_def(),
             SyntaxKind::IDENT => {
-                // Could be assignment or function call
+                // Could be assignment, shell function or directive
                 if self.is_assignment_ahead() {
                     self.assignment()
+                } else if self.is_function_ahead() {
+                    self.function_def()
+                } else if self.current_token().is_some_and(|t| DIRECTIVES.contains(&t.text.as_str())) {
+                    self.directive_stmt()
                 } else {
                     // Skip unknown statements
                     false
@@ -123,22 +138,18 @@ impl Parser {
 
         self.bump();  // variable name
 
-        // Handle override syntax: VAR:append:machine
-        while self.at(SyntaxKind::COLON) ||
-              self.at(SyntaxKind::COLON_APPEND) ||
-              self.at(SyntaxKind::COLON_PREPEND) ||
-              self.at(SyntaxKind::COLON_REMOVE)
-        {
-            self.bump();  // :xxx
-            if self.at(SyntaxKind::IDENT) {
-                self.bump();  // override name
-            }
+        // Handle override syntax: VAR:append:machine, RDEPENDS:${PN}-dev
+        while Self::is_name_part(self.current()) {
+            self.bump();
         }
 
-        // Handle flag syntax: VAR[flag]
+        // Handle flag syntax: VAR[flag], SRC_URI[name.sha256sum]
         if self.at(SyntaxKind::L_BRACKET) {
             self.bump();  // [
-            if self.at(SyntaxKind::IDENT) || self.at(SyntaxKind::STRING) {
+            while !self.at_eof()
+                && !self.at(SyntaxKind::R_BRACKET)
+                && !self.at(SyntaxKind::NEWLINE)
+            {
                 self.bump();  // flag name
             }
             self.expect(SyntaxKind::R_BRACKET);  // ]
@@ -277,19 +288,41 @@ impl Parser {
         true
     }
 
+    fn directive_stmt(&mut self) -> bool {
+        self.builder.start_node(SyntaxKind::DIRECTIVE_STMT.into());
+
+        // Arguments run to the end of the line, with backslash continuations
+        while !self.at_eof() && !self.at(SyntaxKind::NEWLINE) {
+            let continued = self.at(SyntaxKind::BACKSLASH)
+                && self.tokens.get(self.pos + 1).is_some_and(|t| t.kind == SyntaxKind::NEWLINE);
+            self.bump();
+            if continued {
+                self.bump();
+            }
+        }
+
+        self.builder.finish_node();
+        true
+    }
+
     fn function_def(&mut self) -> bool {
-        // Simplified function parsing - just skip the body
+        // The body is kept as tokens; it ends where BitBake ends it
         self.builder.start_node(SyntaxKind::SHELL_FUNCTION.into());
 
-        // python or def keyword
-        if self.at(SyntaxKind::PYTHON_KW) || self.at(SyntaxKind::DEF_KW) {
+        // fakeroot, python or def keyword
+        if self.current_token().is_some_and(|t| t.text == "fakeroot") {
+            self.bump();
+            self.skip_whitespace_inline();
+        }
+        let def = self.at(SyntaxKind::DEF_KW);
+        if self.at(SyntaxKind::PYTHON_KW) || def {
             self.bump();
         }
 
         self.skip_whitespace_inline();
 
-        // Function name
-        if self.at(SyntaxKind::IDENT) {
+        // Function name, possibly with overrides (do_install:append)
+        while Self::is_name_part(self.current()) {
             self.bump();
         }
 
@@ -306,19 +339,36 @@ impl Parser {
             }
         }
 
-        self.skip_whitespace_inline();
-
-        // Body {...}
-        if self.at(SyntaxKind::L_BRACE) {
-            self.bump();
-            let mut depth = 1;
-            while depth > 0 && !self.at_eof() {
-                if self.at(SyntaxKind::L_BRACE) {
-                    depth += 1;
-                } else if self.at(SyntaxKind::R_BRACE) {
-                    depth -= 1;
+        if def {
+            // def body: the indented lines that follow
+            while !self.at_eof() && !self.at(SyntaxKind::NEWLINE) {
+                self.bump();
+            }
+            while self.at(SyntaxKind::NEWLINE)
+                && self.tokens.get(self.pos + 1).is_some_and(|t| {
+                    matches!(t.kind, SyntaxKind::WHITESPACE | SyntaxKind::NEWLINE)
+                })
+            {
+                self.bump();
+                while !self.at_eof() && !self.at(SyntaxKind::NEWLINE) {
+                    self.bump();
                 }
+            }
+        } else {
+            self.skip_whitespace_inline();
+
+            // Body {...}, closed by a "}" at the start of a line
+            if self.at(SyntaxKind::L_BRACE) {
                 self.bump();
+                let mut line_start = false;
+                while !self.at_eof() {
+                    let closing = line_start && self.at(SyntaxKind::R_BRACE);
+                    line_start = self.at(SyntaxKind::NEWLINE);
+                    self.bump();
+                    if closing {
+                        break;
+                    }
+                }
             }
         }
 
@@ -390,11 +440,7 @@ impl Parser {
                 SyntaxKind::WHITESPACE => {
                     lookahead += 1;
                 }
-                SyntaxKind::COLON | SyntaxKind::COLON_APPEND |
-                SyntaxKind::COLON_PREPEND | SyntaxKind::COLON_REMOVE => {
-                    lookahead += 1;
-                }
-                SyntaxKind::IDENT => {
+                kind if Self::is_name_part(kind) => {
                     lookahead += 1;
                 }
                 SyntaxKind::L_BRACKET => {
@@ -420,6 +466,40 @@ impl Parser {
         false
     }
 
+    /// `name() {`, `name:append() {` or `fakeroot [python] name() {`
+    fn is_function_ahead(&self) -> bool {
+        let mut lookahead = self.pos;
+        if self.tokens[lookahead].text == "fakeroot" {
+            lookahead += 1;
+            while lookahead < self.tokens.len()
+                && matches!(self.tokens[lookahead].kind, SyntaxKind::WHITESPACE | SyntaxKind::PYTHON_KW)
+            {
+                lookahead += 1;
+            }
+        }
+        while lookahead < self.tokens.len() && Self::is_name_part(self.tokens[lookahead].kind) {
+            lookahead += 1;
+        }
+        while lookahead < self.tokens.len() && self.tokens[lookahead].kind == SyntaxKind::WHITESPACE {
+            lookahead += 1;
+        }
+        self.tokens.get(lookahead).is_some_and(|t| t.kind == SyntaxKind::L_PAREN)
+    }
+
+    /// Tokens that can continue a variable name after its first identifier
+    fn is_name_part(kind: SyntaxKind) -> bool {
+        matches!(
+            kind,
+            SyntaxKind::IDENT
+                | SyntaxKind::VAR_EXPANSION
+                | SyntaxKind::COLON
+                | SyntaxKind::COLON_APPEND
+                | SyntaxKind::COLON_PREPEND
+                | SyntaxKind::COLON_REMOVE
+                | SyntaxKind::ERROR_TOKEN
+        )
+    }
+
     fn advance_with_error(&mut self) {
         self.builder.start_node(SyntaxKind::ERROR.into());
 
@@ -507,6 +587,66 @@ mod tests {
         assert!(inherit_found, "Should find inherit statement");
     }
 
+    #[test]
+    fn test_override_and_flag_names() {
+        let input = "RDEPENDS:${PN}-dev = \"foo\"\nSRC_URI[archive.sha256sum] = \"abc\"\n";
+        let parse = parse(input);
+
+        assert!(parse.errors.is_empty(), "{:?}", parse.errors);
+
+        let names: Vec<String> = parse
+            .syntax()
+            .descendants()
+            .filter(|n| n.kind() == SyntaxKind::VARIABLE_NAME)
+            .map(|n| n.text().to_string())
+            .collect();
+        assert_eq!(names, ["RDEPENDS:${PN}-dev", "SRC_URI[archive.sha256sum]"]);
+    }
+
+    #[test]
+    fn test_functions_and_directives() {
+        let input = r#"do_compile() {
+    awk '{ print $1 }' ${S}/list
+    ${@bb.utils.contains('DISTRO_FEATURES', 'x11', 'x', '', d)}
+}
+fakeroot do_install:append() {
+    install -d ${D}${bindir}
+}
+python do_fetch:prepend() {
+    bb.note("fetching")
+}
+def helper(d):
+    if d.getVar('X'):
+        return "{"
+
+    return ''
+addtask do_custom after do_compile \
+    before do_install
+EXPORT_FUNCTIONS do_compile
+inherit_defer ${FOO}
+FOO = "bar"
+"#;
+
+        let parse = parse(input);
+        assert!(parse.errors.is_empty(), "{:?}", parse.errors);
+
+        let root = parse.syntax();
+        let functions: Vec<String> = root.children()
+            .filter(|n| n.kind() == SyntaxKind::SHELL_FUNCTION)
+            .map(|n| n.text().to_string())
+            .collect();
+        assert_eq!(functions.len(), 4);
+        assert!(functions[0].ends_with("\n}"));
+        assert!(functions[1].starts_with("fakeroot do_install:append()"));
+        assert!(functions[3].ends_with("return ''"));
+
+        let directives = root.children()
+            .filter(|n| n.kind() == SyntaxKind::DIRECTIVE_STMT)
+            .count();
+        assert_eq!(directives, 3);
+        assert!(root.children().any(|n| n.kind() == SyntaxKind::VARIABLE_ASSIGNMENT));
+    }
+
     #[test]
     fn test_include() {
         let input = "include ${BPN}-crates.inc";
@@ -10,7 +10,7 @@
 //! Each stage computes content hashes to enable incremental builds.
 
 use crate::{
-    BuildContext, ContentHash, ExtractionConfig, RecipeExtractor, RecipeGraph,
+    BuildContext, ContentHash, ExtractionConfig, RecipeExtractor, RecipeGraph, RecipeId,
     TaskExtractor, TaskImplementation,
 };
 use serde::{Deserialize, Serialize};
@@ -467,13 +467,25 @@ impl Pipeline {
 
         let mut graph = RecipeGraph::new();
         let mut extractions = Vec::new();
+        // BBCLASSEXTEND variants share their base recipe's task implementations
+        let mut variant_ids: HashMap<String, Vec<RecipeId>> = HashMap::new();
+
+        // Standalone recipes (foo-native_1.0.bb) win over BBCLASSEXTEND variants,
+        // whichever order the files are processed in
+        let recipe_names: HashSet<String> = parsed_recipes.iter()
+            .map(|parsed| parsed.file.name.clone())
+            .collect();
 
         // Extract recipe metadata (still fast, but sequential)
         // Use extract_from_file to properly process includes for DEPENDS
         for parsed in parsed_recipes {
-            match extractor.extract_from_file(&mut graph, &parsed.file.path) {
-                Ok(extraction) => {
-                    extractions.push(extraction);
+            match extractor.extract_from_file_with_variants(&mut graph, &parsed.file.path, &recipe_names) {
+                Ok(recipe_extractions) => {
+                    variant_ids.insert(
+                        parsed.file.name.clone(),
+                        recipe_extractions.iter().skip(1).map(|e| e.recipe_id).collect(),
+                    );
+                    extractions.extend(recipe_extractions);
                 }
                 Err(e) => {
                     debug!("Skipping {}: {}", parsed.file.name, e);
@@ -502,7 +514,10 @@ impl Pipeline {
         // Collect tasks to add (to avoid borrow checker issues)
         let mut tasks_to_add = Vec::new();
         for parsed in parsed_recipes {
-            if let Some(recipe_id) = graph.find_recipe(&parsed.file.name) {
+            let recipe_ids = graph.find_recipe(&parsed.file.name).into_iter().chain(
+                variant_ids.get(&parsed.file.name).into_iter().flatten().copied(),
+            );
+            for recipe_id in recipe_ids {
                 // Get existing tasks for this recipe
                 let existing_tasks = graph.get_recipe_tasks(recipe_id);
                 let existing_names: HashSet<&str> = existing_tasks.iter()
@@ -532,6 +547,17 @@ impl Pipeline {
             }
         }
 
+        // Assign layers so BBFILE_PRIORITY can take part in provider selection
+        for extraction in &extractions {
+            if let Some(recipe) = graph.get_recipe_mut(extraction.recipe_id)
+                && let Some(path) = &recipe.file_path
+                && let Some(collection) = self.build_context.layer_collection_for_path(path)
+            {
+                recipe.layer = Some(collection.to_string());
+            }
+        }
+        graph.set_provider_preferences(self.build_context.provider_preferences());
+
         // Populate dependencies
         info!("  Populating {} recipe dependencies...", extractions.len());
         extractor.populate_dependencies(&mut graph, &extractions)?;
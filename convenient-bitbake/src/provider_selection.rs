@@ -0,0 +1,283 @@
+// Provider and version selection following BitBake's providers.py
+// Handles PREFERRED_PROVIDER, PREFERRED_VERSION (with % wildcards),
+// DEFAULT_PREFERENCE and BBFILE_PRIORITY layering
+
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Configuration that influences which recipe provides an item
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProviderPreferences {
+    /// PREFERRED_PROVIDER_<item> -> PN
+    pub preferred_providers: HashMap<String, String>,
+    /// PREFERRED_VERSION_<pn> -> version pattern (may end in %)
+    pub preferred_versions: HashMap<String, String>,
+    /// Layer collection -> BBFILE_PRIORITY
+    pub layer_priorities: HashMap<String, i32>,
+}
+
+impl ProviderPreferences {
+    /// Create empty preferences (BitBake defaults)
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Collect PREFERRED_PROVIDER_* and PREFERRED_VERSION_* from configuration variables
+    pub fn from_variables(variables: &HashMap<String, String>) -> Self {
+        let mut prefs = Self::new();
+        prefs.merge_variables(variables);
+        prefs
+    }
+
+    /// Merge PREFERRED_* settings from configuration variables (later wins)
+    pub fn merge_variables(&mut self, variables: &HashMap<String, String>) {
+        for (key, value) in variables {
+            let value = value.trim();
+            if value.is_empty() {
+                continue;
+            }
+            if let Some(item) = key.strip_prefix("PREFERRED_PROVIDER_") {
+                self.preferred_providers.insert(item.to_string(), value.to_string());
+            } else if let Some(pn) = key.strip_prefix("PREFERRED_VERSION_") {
+                self.preferred_versions.insert(pn.to_string(), value.to_string());
+            }
+        }
+    }
+
+    /// Set the BBFILE_PRIORITY of a layer collection
+    pub fn set_layer_priority(&mut self, collection: impl Into<String>, priority: i32) {
+        self.layer_priorities.insert(collection.into(), priority);
+    }
+
+    /// BBFILE_PRIORITY for a layer collection (0 if unknown)
+    pub fn layer_priority(&self, collection: Option<&str>) -> i32 {
+        collection
+            .and_then(|c| self.layer_priorities.get(c))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Preferred provider PN for an item, if configured
+    pub fn preferred_provider(&self, item: &str) -> Option<&str> {
+        self.preferred_providers.get(item).map(String::as_str)
+    }
+
+    /// Preferred version pattern for a PN, if configured
+    pub fn preferred_version(&self, pn: &str) -> Option<&str> {
+        self.preferred_versions.get(pn).map(String::as_str)
+    }
+
+    /// Whether no preferences are configured at all
+    pub fn is_empty(&self) -> bool {
+        self.preferred_providers.is_empty()
+            && self.preferred_versions.is_empty()
+            && self.layer_priorities.is_empty()
+    }
+}
+
+/// Check a PREFERRED_VERSION pattern against a recipe version
+///
+/// A trailing `%` matches any suffix, so "1.2%" accepts "1.2", "1.2.3" and "1.2+git".
+/// An optional epoch ("1:") is ignored unless both sides carry one.
+pub fn version_matches(pattern: &str, version: &str) -> bool {
+    let (pattern_epoch, pattern) = split_epoch(pattern);
+    let (version_epoch, version) = split_epoch(version);
+    if let (Some(pe), Some(ve)) = (pattern_epoch, version_epoch)
+        && pe != ve
+    {
+        return false;
+    }
+
+    match pattern.strip_suffix('%') {
+        Some(prefix) => version.starts_with(prefix),
+        None => version == pattern,
+    }
+}
+
+fn split_epoch(version: &str) -> (Option<&str>, &str) {
+    match version.split_once(':') {
+        Some((epoch, rest)) if !epoch.is_empty() && epoch.chars().all(|c| c.is_ascii_digit()) => {
+            (Some(epoch), rest)
+        }
+        _ => (None, version),
+    }
+}
+
+/// Compare two versions the way bb.utils.vercmp_string does
+///
+/// Epochs compare first, then alternating non-digit/digit runs; `~` sorts
+/// before anything (so "1.0~rc1" < "1.0"), letters before other symbols.
+pub fn vercmp(a: &str, b: &str) -> Ordering {
+    let (ea, va) = split_epoch(a);
+    let (eb, vb) = split_epoch(b);
+    let epoch = |e: Option<&str>| e.and_then(|e| e.parse::<u64>().ok()).unwrap_or(0);
+
+    epoch(ea).cmp(&epoch(eb)).then_with(|| vercmp_part(va, vb))
+}
+
+pub(crate) fn vercmp_part(a: &str, b: &str) -> Ordering {
+    fn order(c: Option<char>) -> i32 {
+        match c {
+            None => 0,
+            Some('~') => -1,
+            Some(c) if c.is_ascii_digit() => 0,
+            Some(c) if c.is_ascii_alphabetic() => c as i32,
+            Some(c) => c as i32 + 256,
+        }
+    }
+
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let (mut i, mut j) = (0, 0);
+
+    while i < a.len() || j < b.len() {
+        // Non-digit prefix
+        while (i < a.len() && !a[i].is_ascii_digit()) || (j < b.len() && !b[j].is_ascii_digit()) {
+            let ca = order(a.get(i).copied().filter(|c| !c.is_ascii_digit()));
+            let cb = order(b.get(j).copied().filter(|c| !c.is_ascii_digit()));
+            if ca != cb {
+                return ca.cmp(&cb);
+            }
+            if i < a.len() && !a[i].is_ascii_digit() {
+                i += 1;
+            }
+            if j < b.len() && !b[j].is_ascii_digit() {
+                j += 1;
+            }
+        }
+
+        // Numeric run
+        while i < a.len() && a[i] == '0' {
+            i += 1;
+        }
+        while j < b.len() && b[j] == '0' {
+            j += 1;
+        }
+        let start_a = i;
+        let start_b = j;
+        while i < a.len() && a[i].is_ascii_digit() {
+            i += 1;
+        }
+        while j < b.len() && b[j].is_ascii_digit() {
+            j += 1;
+        }
+        let num_a = &a[start_a..i];
+        let num_b = &b[start_b..j];
+        let ordering = num_a.len().cmp(&num_b.len()).then_with(|| num_a.cmp(num_b));
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+
+    Ordering::Equal
+}
+
+/// Diagnostic produced while selecting providers, mirroring BitBake's notes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProviderNote {
+    /// More than one PN provides an item and no PREFERRED_PROVIDER picks one
+    MultipleProviders {
+        /// The item being provided (e.g. "virtual/kernel")
+        item: String,
+        /// PNs of all candidate providers
+        providers: Vec<String>,
+        /// PN that was selected
+        selected: String,
+    },
+    /// PREFERRED_VERSION names a version that no recipe file offers
+    PreferredVersionUnavailable {
+        /// Recipe name
+        pn: String,
+        /// Requested version pattern
+        version: String,
+        /// Versions that are available
+        available: Vec<String>,
+    },
+    /// PREFERRED_PROVIDER names a PN that does not exist
+    PreferredProviderMissing {
+        /// The item being provided
+        item: String,
+        /// The configured provider PN
+        provider: String,
+    },
+}
+
+impl fmt::Display for ProviderNote {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProviderNote::MultipleProviders { item, providers, selected } => write!(
+                f,
+                "multiple providers are available for {} ({}); consider defining a PREFERRED_PROVIDER entry to match {} (using {})",
+                item,
+                providers.join(", "),
+                item,
+                selected
+            ),
+            ProviderNote::PreferredVersionUnavailable { pn, version, available } => write!(
+                f,
+                "preferred version {} of {} not available; versions of {} available: {}",
+                version,
+                pn,
+                pn,
+                available.join(" ")
+            ),
+            ProviderNote::PreferredProviderMissing { item, provider } => write!(
+                f,
+                "preferred provider {} for {} does not exist",
+                provider, item
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_matches_wildcard() {
+        assert!(version_matches("1.2%", "1.2.11"));
+        assert!(version_matches("1.2%", "1.2"));
+        assert!(!version_matches("1.2%", "1.3.0"));
+        assert!(version_matches("2.38", "2.38"));
+        assert!(!version_matches("2.38", "2.38.1"));
+        assert!(version_matches("1:1.0", "1.0"));
+    }
+
+    #[test]
+    fn test_vercmp() {
+        assert_eq!(vercmp("1.2.10", "1.2.9"), Ordering::Greater);
+        assert_eq!(vercmp("1.0", "1.0"), Ordering::Equal);
+        assert_eq!(vercmp("1.0~rc1", "1.0"), Ordering::Less);
+        assert_eq!(vercmp("1:0.9", "2.0"), Ordering::Greater);
+        assert_eq!(vercmp("1.0+git", "1.0"), Ordering::Greater);
+        assert_eq!(vercmp("git", "1.0"), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_from_variables() {
+        let mut vars = HashMap::new();
+        vars.insert("PREFERRED_PROVIDER_virtual/kernel".to_string(), "linux-yocto".to_string());
+        vars.insert("PREFERRED_VERSION_linux-yocto".to_string(), "6.6%".to_string());
+        vars.insert("MACHINE".to_string(), "qemux86-64".to_string());
+
+        let prefs = ProviderPreferences::from_variables(&vars);
+        assert_eq!(prefs.preferred_provider("virtual/kernel"), Some("linux-yocto"));
+        assert_eq!(prefs.preferred_version("linux-yocto"), Some("6.6%"));
+        assert_eq!(prefs.preferred_providers.len(), 1);
+    }
+
+    #[test]
+    fn test_note_display() {
+        let note = ProviderNote::MultipleProviders {
+            item: "virtual/libc".to_string(),
+            providers: vec!["glibc".to_string(), "musl".to_string()],
+            selected: "glibc".to_string(),
+        };
+        assert!(note.to_string().starts_with(
+            "multiple providers are available for virtual/libc (glibc, musl); consider defining a PREFERRED_PROVIDER entry to match virtual/libc"
+        ));
+    }
+}
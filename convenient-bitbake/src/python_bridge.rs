@@ -16,6 +16,9 @@
 //! - `bb.data` - DataStore for variable storage (in python_executor.rs)
 //! - `bb.fetch2` - Fetch module that delegates to Rust
 //! - `bb.utils` - Utility functions (in python_executor.rs)
+//! - `_bb_utils`, `_oe_utils`, `_oe_path`, `_oe_types` - native implementations
+//!   (see `python_lib`) behind the `bb.utils` and `oe.*` modules of the parse
+//!   prelude, which adds BitBake's python signatures and `d` lookups
 
 use rustpython::vm::{
     builtins::{PyListRef, PyStrRef},
@@ -171,6 +174,213 @@ pub(crate) mod bb {
     }
 }
 
+/// Items of a python list, as strings
+fn strings(list: &PyListRef, vm: &VirtualMachine) -> PyResult<Vec<String>> {
+    list.borrow_vec()
+        .iter()
+        .map(|item| Ok(item.str(vm)?.as_str().to_owned()))
+        .collect()
+}
+
+/// A python list of strings
+fn str_list(items: Vec<String>, vm: &VirtualMachine) -> PyObjectRef {
+    let items = items.into_iter().map(|s| vm.ctx.new_str(s).into()).collect();
+    vm.ctx.new_list(items).into()
+}
+
+/// Native bb.utils functions
+#[pymodule(name = "_bb_utils")]
+pub(crate) mod bb_utils_lib {
+    use super::*;
+    use crate::python_lib;
+    use std::cmp::Ordering;
+
+    #[pyfunction]
+    fn vercmp_string(a: PyStrRef, b: PyStrRef) -> i32 {
+        match python_lib::vercmp_string(a.as_str(), b.as_str()) {
+            Ordering::Less => -1,
+            Ordering::Equal => 0,
+            Ordering::Greater => 1,
+        }
+    }
+
+    #[pyfunction]
+    fn vercmp_string_op(a: PyStrRef, b: PyStrRef, op: PyStrRef, vm: &VirtualMachine) -> PyResult<bool> {
+        python_lib::vercmp_string_op(a.as_str(), b.as_str(), op.as_str())
+            .map_err(|e| vm.new_value_error(e))
+    }
+
+    #[pyfunction]
+    fn split_version(s: PyStrRef, vm: &VirtualMachine) -> PyObjectRef {
+        let (epoch, version, revision) = python_lib::split_version(s.as_str());
+        vm.ctx
+            .new_tuple(vec![
+                vm.ctx.new_int(epoch).into(),
+                vm.ctx.new_str(version).into(),
+                vm.ctx.new_str(revision).into(),
+            ])
+            .into()
+    }
+
+    #[pyfunction]
+    fn explode_deps(s: PyStrRef, vm: &VirtualMachine) -> PyObjectRef {
+        str_list(python_lib::explode_deps(s.as_str()), vm)
+    }
+
+    /// Returns a dict of dependency -> list of "op version" constraints
+    #[pyfunction]
+    fn explode_dep_versions2(s: PyStrRef, sort: bool, vm: &VirtualMachine) -> PyResult<PyObjectRef> {
+        let deps = python_lib::explode_dep_versions2(s.as_str(), sort)
+            .map_err(|e| vm.new_value_error(e))?;
+        let dict = vm.ctx.new_dict();
+        for (dep, versions) in deps {
+            dict.set_item(dep.as_str(), str_list(versions, vm), vm)?;
+        }
+        Ok(dict.into())
+    }
+
+    #[pyfunction]
+    fn to_boolean(value: PyStrRef, vm: &VirtualMachine) -> PyResult<bool> {
+        python_lib::to_boolean(value.as_str()).map_err(|e| vm.new_value_error(e))
+    }
+
+    #[pyfunction]
+    fn contains(value: PyStrRef, checkvalues: PyListRef, vm: &VirtualMachine) -> PyResult<bool> {
+        let checkvalues = strings(&checkvalues, vm)?;
+        let checkvalues: Vec<&str> = checkvalues.iter().map(String::as_str).collect();
+        Ok(python_lib::contains(Some(value.as_str()), &checkvalues))
+    }
+
+    #[pyfunction]
+    fn contains_any(value: PyStrRef, checkvalues: PyListRef, vm: &VirtualMachine) -> PyResult<bool> {
+        let checkvalues = strings(&checkvalues, vm)?;
+        let checkvalues: Vec<&str> = checkvalues.iter().map(String::as_str).collect();
+        Ok(python_lib::contains_any(Some(value.as_str()), &checkvalues))
+    }
+
+    #[pyfunction]
+    fn filter(value: PyStrRef, checkvalues: PyListRef, vm: &VirtualMachine) -> PyResult<String> {
+        let checkvalues = strings(&checkvalues, vm)?;
+        let checkvalues: Vec<&str> = checkvalues.iter().map(String::as_str).collect();
+        Ok(python_lib::filter(Some(value.as_str()), &checkvalues))
+    }
+}
+
+/// Native oe.utils functions
+#[pymodule(name = "_oe_utils")]
+pub(crate) mod oe_utils_lib {
+    use super::*;
+    use crate::python_lib;
+
+    #[pyfunction]
+    fn prune_suffix(var: PyStrRef, suffixes: PyListRef, mlprefix: PyStrRef, vm: &VirtualMachine) -> PyResult<String> {
+        let suffixes = strings(&suffixes, vm)?;
+        let suffixes: Vec<&str> = suffixes.iter().map(String::as_str).collect();
+        Ok(python_lib::prune_suffix(var.as_str(), &suffixes, Some(mlprefix.as_str())))
+    }
+
+    #[pyfunction]
+    fn str_filter(f: PyStrRef, s: PyStrRef, keep_matches: bool, vm: &VirtualMachine) -> PyResult<String> {
+        python_lib::str_filter(f.as_str(), s.as_str(), keep_matches)
+            .map_err(|e| vm.new_value_error(e))
+    }
+
+    #[pyfunction]
+    fn both_contain(value1: PyStrRef, value2: PyStrRef, checkvalues: PyListRef, vm: &VirtualMachine) -> PyResult<String> {
+        let checkvalues = strings(&checkvalues, vm)?;
+        let checkvalues: Vec<&str> = checkvalues.iter().map(String::as_str).collect();
+        Ok(python_lib::both_contain(value1.as_str(), value2.as_str(), &checkvalues))
+    }
+
+    #[pyfunction]
+    fn set_intersect(value1: PyStrRef, value2: PyStrRef) -> String {
+        python_lib::set_intersect(value1.as_str(), value2.as_str())
+    }
+
+    #[pyfunction]
+    fn trim_version(version: PyStrRef, num_parts: i64, vm: &VirtualMachine) -> PyResult<String> {
+        python_lib::trim_version(version.as_str(), num_parts).map_err(|e| vm.new_value_error(e))
+    }
+
+    #[pyfunction]
+    fn squashspaces(s: PyStrRef) -> String {
+        python_lib::squashspaces(s.as_str())
+    }
+
+    /// The -j value of a PARALLEL_MAKE value, or "" without one
+    #[pyfunction]
+    fn parallel_make(value: PyStrRef, vm: &VirtualMachine) -> PyResult<PyObjectRef> {
+        match python_lib::parallel_make(value.as_str()).map_err(|e| vm.new_value_error(e))? {
+            Some(jobs) => Ok(vm.ctx.new_int(jobs).into()),
+            None => Ok(vm.ctx.new_str("").into()),
+        }
+    }
+
+    #[pyfunction]
+    fn packages_filter_out_system(pn: PyStrRef, packages: PyStrRef, vm: &VirtualMachine) -> PyObjectRef {
+        str_list(python_lib::packages_filter_out_system(pn.as_str(), packages.as_str()), vm)
+    }
+}
+
+/// Native oe.path functions
+#[pymodule(name = "_oe_path")]
+pub(crate) mod oe_path_lib {
+    use super::*;
+    use crate::python_lib;
+
+    #[pyfunction]
+    fn join(paths: PyListRef, vm: &VirtualMachine) -> PyResult<String> {
+        let paths = strings(&paths, vm)?;
+        let paths: Vec<&str> = paths.iter().map(String::as_str).collect();
+        Ok(python_lib::join(&paths))
+    }
+
+    #[pyfunction]
+    fn relative(src: PyStrRef, dest: PyStrRef, vm: &VirtualMachine) -> PyResult<String> {
+        python_lib::relative(src.as_str(), dest.as_str()).map_err(|e| vm.new_value_error(e))
+    }
+
+    #[pyfunction]
+    fn format_display(path: PyStrRef, topdir: PyStrRef) -> String {
+        python_lib::format_display(path.as_str(), topdir.as_str())
+    }
+
+    #[pyfunction]
+    fn is_path_parent(parent: PyStrRef, paths: PyListRef, vm: &VirtualMachine) -> PyResult<bool> {
+        let paths = strings(&paths, vm)?;
+        let paths: Vec<&str> = paths.iter().map(String::as_str).collect();
+        Ok(python_lib::is_path_parent(parent.as_str(), &paths))
+    }
+}
+
+/// Native oe.types functions
+#[pymodule(name = "_oe_types")]
+pub(crate) mod oe_types_lib {
+    use super::*;
+    use crate::python_lib;
+
+    #[pyfunction]
+    fn boolean(value: PyStrRef, vm: &VirtualMachine) -> PyResult<bool> {
+        python_lib::boolean(value.as_str()).map_err(|e| vm.new_value_error(e))
+    }
+
+    #[pyfunction]
+    fn choice(value: PyStrRef, choices: PyStrRef, vm: &VirtualMachine) -> PyResult<String> {
+        python_lib::choice(value.as_str(), choices.as_str()).map_err(|e| vm.new_value_error(e))
+    }
+
+    #[pyfunction]
+    fn path(value: PyStrRef, relativeto: PyStrRef, normalize: bool, mustexist: bool, vm: &VirtualMachine) -> PyResult<String> {
+        python_lib::types_path(value.as_str(), relativeto.as_str(), normalize, mustexist)
+            .map_err(|e| vm.new_value_error(e))
+    }
+
+    #[pyfunction]
+    fn is_x86(arch: PyStrRef) -> bool {
+        python_lib::is_x86(arch.as_str())
+    }
+}
+
 /// Parse a SRC_URI string into our SourceUri struct
 ///
 /// Handles formats like:
@@ -10,6 +10,10 @@ use rustpython::{
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
+use tracing::{debug, error, info, warn};
+
+/// BitBake API available to parse-time python (see `PythonExecutor::run_parse_code`)
+const PARSE_PRELUDE: &str = include_str!("parse_prelude.py");
 
 // Module containing bb.utils functions
 #[pymodule]
@@ -115,6 +119,167 @@ mod bitbake_internal {
             Ok(vm.ctx.new_str(expanded).into())
         }
     }
+
+    /// `d` for parse-time python: operates on the real datastore, so every
+    /// change lands in its history like any other assignment
+    #[pyattr]
+    #[pyclass(module = "bitbake_internal", name = "LiveDataStore")]
+    #[derive(Debug, Clone, PyPayload)]
+    pub(super) struct LiveDataStore {
+        pub(super) inner: Arc<Mutex<crate::datastore::DataStore>>,
+    }
+
+    /// Convert a python value to a datastore value; None deletes, booleans
+    /// keep their truthiness ("1" / "")
+    fn to_value(value: &PyObjectRef, vm: &VirtualMachine) -> PyResult<Option<String>> {
+        if vm.is_none(value) {
+            return Ok(None);
+        }
+        if value.fast_isinstance(vm.ctx.types.bool_type) {
+            let set = value.clone().try_to_bool(vm)?;
+            return Ok(Some(if set { "1" } else { "" }.to_string()));
+        }
+        Ok(Some(value.str(vm)?.as_str().to_string()))
+    }
+
+    fn to_py(value: Option<String>, vm: &VirtualMachine) -> PyObjectRef {
+        match value {
+            Some(value) => vm.ctx.new_str(value).into(),
+            None => vm.ctx.none(),
+        }
+    }
+
+    #[pyclass]
+    impl LiveDataStore {
+        #[pymethod]
+        fn getVar(&self, name: PyStrRef, expand: rustpython_vm::function::OptionalArg<bool>, vm: &VirtualMachine) -> PyResult<PyObjectRef> {
+            let d = self.inner.lock().unwrap();
+            let value = if expand.unwrap_or(true) {
+                d.get_var(name.as_str())
+            } else {
+                d.get_var_unexpanded(name.as_str())
+            };
+            Ok(to_py(value, vm))
+        }
+
+        #[pymethod]
+        fn setVar(&self, name: PyStrRef, value: PyObjectRef, vm: &VirtualMachine) -> PyResult<()> {
+            let mut d = self.inner.lock().unwrap();
+            match to_value(&value, vm)? {
+                Some(value) => d.set_var(name.as_str(), value),
+                None => d.del_var(name.as_str()),
+            }
+            Ok(())
+        }
+
+        #[pymethod]
+        fn appendVar(&self, name: PyStrRef, value: PyObjectRef, vm: &VirtualMachine) -> PyResult<()> {
+            let value = to_value(&value, vm)?.unwrap_or_default();
+            self.inner.lock().unwrap().append_var(name.as_str(), value);
+            Ok(())
+        }
+
+        #[pymethod]
+        fn prependVar(&self, name: PyStrRef, value: PyObjectRef, vm: &VirtualMachine) -> PyResult<()> {
+            let value = to_value(&value, vm)?.unwrap_or_default();
+            self.inner.lock().unwrap().prepend_var(name.as_str(), value);
+            Ok(())
+        }
+
+        #[pymethod]
+        fn delVar(&self, name: PyStrRef) {
+            self.inner.lock().unwrap().del_var(name.as_str());
+        }
+
+        #[pymethod]
+        fn getVarFlag(&self, name: PyStrRef, flag: PyStrRef, expand: rustpython_vm::function::OptionalArg<bool>, vm: &VirtualMachine) -> PyResult<PyObjectRef> {
+            let d = self.inner.lock().unwrap();
+            let value = if expand.unwrap_or(true) {
+                d.get_var_flag(name.as_str(), flag.as_str())
+            } else {
+                d.get_var_flags(name.as_str()).remove(flag.as_str())
+            };
+            Ok(to_py(value, vm))
+        }
+
+        #[pymethod]
+        fn setVarFlag(&self, name: PyStrRef, flag: PyStrRef, value: PyObjectRef, vm: &VirtualMachine) -> PyResult<()> {
+            let mut d = self.inner.lock().unwrap();
+            match to_value(&value, vm)? {
+                Some(value) => d.set_var_flag(name.as_str(), flag.as_str(), value),
+                None => d.del_var_flag(name.as_str(), flag.as_str()),
+            }
+            Ok(())
+        }
+
+        #[pymethod]
+        fn appendVarFlag(&self, name: PyStrRef, flag: PyStrRef, value: PyObjectRef, vm: &VirtualMachine) -> PyResult<()> {
+            let value = to_value(&value, vm)?.unwrap_or_default();
+            self.inner.lock().unwrap().append_var_flag(name.as_str(), flag.as_str(), value);
+            Ok(())
+        }
+
+        #[pymethod]
+        fn prependVarFlag(&self, name: PyStrRef, flag: PyStrRef, value: PyObjectRef, vm: &VirtualMachine) -> PyResult<()> {
+            let value = to_value(&value, vm)?.unwrap_or_default();
+            self.inner.lock().unwrap().prepend_var_flag(name.as_str(), flag.as_str(), value);
+            Ok(())
+        }
+
+        #[pymethod]
+        fn delVarFlag(&self, name: PyStrRef, flag: PyStrRef) {
+            self.inner.lock().unwrap().del_var_flag(name.as_str(), flag.as_str());
+        }
+
+        #[pymethod]
+        fn getVarFlags(&self, name: PyStrRef, vm: &VirtualMachine) -> PyResult<PyObjectRef> {
+            let flags = self.inner.lock().unwrap().get_var_flags(name.as_str());
+            if flags.is_empty() {
+                return Ok(vm.ctx.none());
+            }
+            let dict = vm.ctx.new_dict();
+            for (flag, value) in flags {
+                dict.set_item(flag.as_str(), vm.ctx.new_str(value).into(), vm)?;
+            }
+            Ok(dict.into())
+        }
+
+        #[pymethod]
+        fn expand(&self, value: PyStrRef, vm: &VirtualMachine) -> PyResult<PyObjectRef> {
+            let expanded = self.inner.lock().unwrap().expand(value.as_str());
+            Ok(vm.ctx.new_str(expanded).into())
+        }
+
+        #[pymethod]
+        fn keys(&self, vm: &VirtualMachine) -> PyResult<PyObjectRef> {
+            let keys = self.inner.lock().unwrap().keys();
+            let keys = keys.into_iter().map(|k| vm.ctx.new_str(k).into()).collect();
+            Ok(vm.ctx.new_list(keys).into())
+        }
+
+        #[pymethod]
+        fn _inherits(&self, vm: &VirtualMachine) -> PyResult<PyObjectRef> {
+            let classes = self.inner.lock().unwrap().inherits().to_vec();
+            let classes = classes.into_iter().map(|c| vm.ctx.new_str(c).into()).collect();
+            Ok(vm.ctx.new_list(classes).into())
+        }
+
+        #[pymethod]
+        fn _addtask(&self, statement: PyStrRef) {
+            self.inner.lock().unwrap().add_task_statement(statement.as_str());
+        }
+    }
+
+    /// bb.note/bb.warn/... from parse-time python end up in the tracing log
+    #[pyfunction]
+    fn log(level: PyStrRef, message: PyStrRef) {
+        match level.as_str() {
+            "error" => error!("{}", message.as_str()),
+            "warn" => warn!("{}", message.as_str()),
+            "debug" => debug!("{}", message.as_str()),
+            _ => info!("{}", message.as_str()),
+        }
+    }
 }
 
 /// Result of executing Python code
@@ -575,6 +740,85 @@ def sanitise_value(value):
             }
         }
     }
+
+    /// Run parse-time python (anonymous functions, event handlers) against a datastore
+    ///
+    /// `code` runs after the parse prelude (`bb.parse`, `bb.event`, `bb.build`,
+    /// `bb.utils`, `oe.utils`, `oe.path`, `oe.types`) with `d` bound to the
+    /// datastore, so its changes are recorded like any other assignment. Errors
+    /// carry the python traceback.
+    pub fn run_parse_code(
+        &self,
+        code: &str,
+        d: &mut crate::datastore::DataStore,
+    ) -> Result<(), String> {
+        let inner = Arc::new(Mutex::new(std::mem::take(d)));
+
+        let interp = InterpreterConfig::new()
+            .init_stdlib()
+            .init_hook(Box::new(|vm| {
+                vm.add_native_module(
+                    "bitbake_internal".to_owned(),
+                    Box::new(bitbake_internal::make_module),
+                );
+                vm.add_native_module(
+                    "_bb_utils".to_owned(),
+                    Box::new(crate::python_bridge::bb_utils_lib::make_module),
+                );
+                vm.add_native_module(
+                    "_oe_utils".to_owned(),
+                    Box::new(crate::python_bridge::oe_utils_lib::make_module),
+                );
+                vm.add_native_module(
+                    "_oe_path".to_owned(),
+                    Box::new(crate::python_bridge::oe_path_lib::make_module),
+                );
+                vm.add_native_module(
+                    "_oe_types".to_owned(),
+                    Box::new(crate::python_bridge::oe_types_lib::make_module),
+                );
+            }))
+            .interpreter();
+
+        let result = interp.enter(|vm| {
+            let run = || -> PyResult<()> {
+                let scope = vm.new_scope_with_builtins();
+                let d_obj = bitbake_internal::LiveDataStore {
+                    inner: inner.clone(),
+                }
+                .into_pyobject(vm);
+                scope.globals.set_item("d", d_obj, vm)?;
+
+                vm.run_block_expr(scope.clone(), PARSE_PRELUDE)?;
+
+                let code_obj = vm
+                    .compile(code, rustpython_vm::compiler::Mode::Exec, "<parse>".to_owned())
+                    .map_err(|e| {
+                        vm.new_exception_msg(
+                            vm.ctx.exceptions.syntax_error.to_owned(),
+                            format!("Compile error: {:?}", e),
+                        )
+                    })?;
+                vm.run_code_obj(code_obj, scope)?;
+                Ok(())
+            };
+
+            run().map_err(|e| {
+                let mut message = String::new();
+                let _ = vm.write_exception(&mut message, &e);
+                message.trim_end().to_string()
+            })
+        });
+        drop(interp);
+
+        // Python objects may still hold the datastore (cycles); fall back to a copy
+        *d = match Arc::try_unwrap(inner) {
+            Ok(mutex) => mutex.into_inner().unwrap(),
+            Err(arc) => arc.lock().unwrap().clone(),
+        };
+
+        result
+    }
 }
 
 impl Default for PythonExecutor {
@@ -0,0 +1,540 @@
+// bb.utils and oe.* library functions in Rust
+// The semantics follow bitbake/lib/bb/utils.py and meta/lib/oe/{utils,path,types}.py.
+// python_bridge exposes them to RustPython as native modules; the simple
+// expression evaluator uses the same functions so both paths agree.
+
+use crate::provider_selection::vercmp_part;
+use regex::Regex;
+use std::cmp::Ordering;
+use std::path::Path;
+
+// === bb.utils ===
+
+/// Split "epoch:version-revision" like bb.utils.split_version
+pub fn split_version(s: &str) -> (u64, String, String) {
+    let mut s = s.trim_matches([' ', '<', '>', '=']);
+    let mut epoch = 0;
+    if let Some((e, rest)) = s.split_once(':') {
+        epoch = e.parse().unwrap_or(0);
+        s = rest;
+    }
+    match s.rsplit_once('-') {
+        Some((version, revision)) => (epoch, version.to_string(), revision.to_string()),
+        None => (epoch, s.to_string(), String::new()),
+    }
+}
+
+/// bb.utils.vercmp_string: epoch, then version, then revision
+pub fn vercmp_string(a: &str, b: &str) -> Ordering {
+    let (ea, va, ra) = split_version(a);
+    let (eb, vb, rb) = split_version(b);
+    ea.cmp(&eb)
+        .then_with(|| vercmp_part(&va, &vb))
+        .then_with(|| vercmp_part(&ra, &rb))
+}
+
+/// bb.utils.vercmp_string_op: compare with an operator as used in dependency versions
+pub fn vercmp_string_op(a: &str, b: &str, op: &str) -> Result<bool, String> {
+    let ordering = vercmp_string(a, b);
+    match op {
+        "=" | "==" => Ok(ordering == Ordering::Equal),
+        "<=" | "=<" => Ok(ordering != Ordering::Greater),
+        ">=" | "=>" => Ok(ordering != Ordering::Less),
+        ">" | ">>" => Ok(ordering == Ordering::Greater),
+        "<" | "<<" => Ok(ordering == Ordering::Less),
+        "!=" => Ok(ordering != Ordering::Equal),
+        _ => Err(format!("Unsupported comparison operator \"{}\"", op)),
+    }
+}
+
+/// bb.utils.explode_deps: dependency names, version constraints dropped
+pub fn explode_deps(s: &str) -> Vec<String> {
+    let mut deps = Vec::new();
+    let mut in_version = false;
+    for word in s.split_whitespace() {
+        if word.starts_with('(') {
+            in_version = true;
+        }
+        if !in_version {
+            deps.push(word.to_string());
+        }
+        if in_version && word.ends_with(')') {
+            in_version = false;
+        }
+    }
+    deps
+}
+
+/// bb.utils.explode_dep_versions2: "foo (>= 1.0) bar" -> [("bar", []), ("foo", [">= 1.0"])]
+///
+/// Entries keep their first-seen order unless `sort` is set.
+pub fn explode_dep_versions2(s: &str, sort: bool) -> Result<Vec<(String, Vec<String>)>, String> {
+    let mut deps: Vec<(String, Vec<String>)> = Vec::new();
+    let mut last_dep: Option<usize> = None;
+    let mut last_cmp = String::new();
+    let mut last_ver = String::new();
+    let mut in_cmp = false;
+    let mut in_version = false;
+
+    let s = s.replace(',', "");
+    for word in s.split_whitespace() {
+        let mut word = word;
+        if let Some(rest) = word.strip_prefix('(') {
+            in_cmp = true;
+            word = rest.trim();
+            if word.is_empty() {
+                continue;
+            }
+        }
+        if in_cmp {
+            in_cmp = false;
+            in_version = true;
+            let op_len = if ["<=", "=<", "<<", ">=", "=>", ">>"]
+                .iter()
+                .any(|op| word.starts_with(op))
+            {
+                2
+            } else if word.starts_with(['<', '>', '=']) {
+                1
+            } else {
+                return Err(format!(
+                    "Invalid version specification in \"({}\" - invalid or missing operator",
+                    word
+                ));
+            };
+            last_cmp = word[..op_len].to_string();
+            word = &word[op_len..];
+            if word.is_empty() {
+                continue;
+            }
+        }
+        if in_version {
+            if let Some(rest) = word.strip_suffix(')') {
+                word = rest;
+                in_version = false;
+                if !last_ver.is_empty() && !word.is_empty() {
+                    last_ver.push(' ');
+                }
+            }
+            if !word.is_empty() {
+                last_ver.push_str(word);
+                if let Some(idx) = last_dep {
+                    deps[idx].1.push(format!("{} {}", last_cmp, last_ver));
+                }
+            }
+            continue;
+        }
+
+        last_ver.clear();
+        last_cmp.clear();
+        last_dep = Some(match deps.iter().position(|(name, _)| name == word) {
+            Some(idx) => idx,
+            None => {
+                deps.push((word.to_string(), Vec::new()));
+                deps.len() - 1
+            }
+        });
+    }
+
+    if sort {
+        deps.sort_by(|a, b| a.0.cmp(&b.0));
+    }
+    Ok(deps)
+}
+
+/// bb.utils.join_deps: the inverse of explode_dep_versions2
+pub fn join_deps(deps: &[(String, Vec<String>)], commasep: bool) -> String {
+    let mut result = Vec::new();
+    for (dep, versions) in deps {
+        if versions.is_empty() {
+            result.push(dep.clone());
+        }
+        for version in versions {
+            result.push(format!("{} ({})", dep, version));
+        }
+    }
+    result.join(if commasep { ", " } else { " " })
+}
+
+/// bb.utils.to_boolean for a non-empty string
+pub fn to_boolean(value: &str) -> Result<bool, String> {
+    match value.to_lowercase().as_str() {
+        "y" | "yes" | "1" | "true" => Ok(true),
+        "n" | "no" | "0" | "false" => Ok(false),
+        _ => Err(format!("Invalid value for to_boolean: {}", value)),
+    }
+}
+
+/// bb.utils.contains: every one of `checkvalues` is in the variable's value
+pub fn contains(value: Option<&str>, checkvalues: &[&str]) -> bool {
+    let Some(value) = value.filter(|v| !v.is_empty()) else {
+        return false;
+    };
+    let words: Vec<&str> = value.split_whitespace().collect();
+    checkvalues.iter().all(|c| words.contains(c))
+}
+
+/// bb.utils.contains_any: at least one of `checkvalues` is in the variable's value
+pub fn contains_any(value: Option<&str>, checkvalues: &[&str]) -> bool {
+    let Some(value) = value.filter(|v| !v.is_empty()) else {
+        return false;
+    };
+    let words: Vec<&str> = value.split_whitespace().collect();
+    checkvalues.iter().any(|c| words.contains(c))
+}
+
+/// bb.utils.filter: the `checkvalues` present in the variable's value, sorted
+pub fn filter(value: Option<&str>, checkvalues: &[&str]) -> String {
+    let words: Vec<&str> = value.unwrap_or_default().split_whitespace().collect();
+    let mut found: Vec<&str> = checkvalues
+        .iter()
+        .copied()
+        .filter(|c| words.contains(c))
+        .collect();
+    found.sort_unstable();
+    found.dedup();
+    found.join(" ")
+}
+
+// === oe.utils ===
+
+/// oe.utils.prune_suffix: strip the first matching suffixes, then MLPREFIX
+pub fn prune_suffix(var: &str, suffixes: &[&str], mlprefix: Option<&str>) -> String {
+    let mut var = var;
+    for suffix in suffixes {
+        if !suffix.is_empty()
+            && let Some(stripped) = var.strip_suffix(suffix)
+        {
+            var = stripped;
+        }
+    }
+    if let Some(prefix) = mlprefix.filter(|p| !p.is_empty())
+        && let Some(stripped) = var.strip_prefix(prefix)
+    {
+        var = stripped;
+    }
+    var.to_string()
+}
+
+/// oe.utils.str_filter / str_filter_out: words whose start matches the regex `f`
+pub fn str_filter(f: &str, s: &str, keep_matches: bool) -> Result<String, String> {
+    let re = Regex::new(&format!("^(?:{})", f)).map_err(|e| e.to_string())?;
+    Ok(s.split_whitespace()
+        .filter(|w| re.is_match(w) == keep_matches)
+        .collect::<Vec<_>>()
+        .join(" "))
+}
+
+/// oe.utils.both_contain: `checkvalues` if both values contain all of them
+pub fn both_contain(value1: &str, value2: &str, checkvalues: &[&str]) -> String {
+    if contains(Some(value1), checkvalues) && contains(Some(value2), checkvalues) {
+        let mut values = checkvalues.to_vec();
+        values.sort_unstable();
+        values.dedup();
+        values.join(" ")
+    } else {
+        String::new()
+    }
+}
+
+/// oe.utils.set_intersect: words present in both values, sorted
+pub fn set_intersect(value1: &str, value2: &str) -> String {
+    let words2: Vec<&str> = value2.split_whitespace().collect();
+    filter(Some(value1), &words2)
+}
+
+/// oe.utils.trim_version: keep the first `num_parts` dot-separated parts
+pub fn trim_version(version: &str, num_parts: i64) -> Result<String, String> {
+    if num_parts < 1 {
+        return Err("Cannot split to parts < 1".to_string());
+    }
+    let parts: Vec<&str> = version.split('.').collect();
+    let keep = usize::try_from(num_parts).unwrap_or(usize::MAX).min(parts.len());
+    Ok(parts[..keep].join("."))
+}
+
+/// oe.utils.squashspaces: collapse whitespace runs and trim
+pub fn squashspaces(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// oe.utils.parallel_make: the -j value of PARALLEL_MAKE(INST)
+pub fn parallel_make(value: &str) -> Result<Option<i64>, String> {
+    let mut words = value.split_whitespace();
+    while let Some(opt) = words.next() {
+        let jobs = if opt == "-j" {
+            words.next().unwrap_or_default()
+        } else if let Some(jobs) = opt.strip_prefix("-j") {
+            jobs.trim()
+        } else {
+            continue;
+        };
+        return jobs
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("invalid literal for int() with base 10: '{}'", jobs));
+    }
+    Ok(None)
+}
+
+/// oe.utils.packages_filter_out_system: PACKAGES without ${PN}, -dbg, -dev, ... and locales
+pub fn packages_filter_out_system(pn: &str, packages: &str) -> Vec<String> {
+    let system: Vec<String> = ["", "-dbg", "-dev", "-doc", "-locale", "-staticdev", "-src"]
+        .iter()
+        .map(|suffix| format!("{}{}", pn, suffix))
+        .collect();
+    let locale = format!("{}-locale-", pn);
+    packages
+        .split_whitespace()
+        .filter(|p| !system.iter().any(|s| s == p) && !p.contains(&locale))
+        .map(str::to_string)
+        .collect()
+}
+
+// === oe.path ===
+
+/// posixpath.normpath
+pub fn normpath(path: &str) -> String {
+    if path.is_empty() {
+        return ".".to_string();
+    }
+    let initial_slashes = if path.starts_with("//") && !path.starts_with("///") {
+        2
+    } else if path.starts_with('/') {
+        1
+    } else {
+        0
+    };
+
+    let mut comps: Vec<&str> = Vec::new();
+    for comp in path.split('/') {
+        if comp.is_empty() || comp == "." {
+            continue;
+        }
+        if comp != ".." || (initial_slashes == 0 && comps.is_empty()) || comps.last() == Some(&"..") {
+            comps.push(comp);
+        } else {
+            comps.pop();
+        }
+    }
+
+    let path = format!("{}{}", "/".repeat(initial_slashes), comps.join("/"));
+    if path.is_empty() { ".".to_string() } else { path }
+}
+
+/// posixpath.abspath, relative to the current directory
+pub fn abspath(path: &str) -> String {
+    if path.starts_with('/') {
+        return normpath(path);
+    }
+    let cwd = std::env::current_dir().unwrap_or_else(|_| "/".into());
+    normpath(&format!("{}/{}", cwd.display(), path))
+}
+
+/// oe.path.join: join with "/" and normalise
+pub fn join(paths: &[&str]) -> String {
+    normpath(&paths.join("/"))
+}
+
+/// oe.path.relative: path of `dest` relative to `src`
+pub fn relative(src: &str, dest: &str) -> Result<String, String> {
+    if dest.is_empty() {
+        return Err("no path specified".to_string());
+    }
+    let start = abspath(src);
+    let target = abspath(dest);
+    let start: Vec<&str> = start.split('/').filter(|c| !c.is_empty()).collect();
+    let target: Vec<&str> = target.split('/').filter(|c| !c.is_empty()).collect();
+    let common = start
+        .iter()
+        .zip(&target)
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut rel: Vec<&str> = vec![".."; start.len() - common];
+    rel.extend(&target[common..]);
+    if rel.is_empty() {
+        return Ok(".".to_string());
+    }
+    Ok(rel.join("/"))
+}
+
+/// oe.path.format_display: relative to TOPDIR when that is shorter
+pub fn format_display(path: &str, topdir: &str) -> String {
+    match relative(topdir, path) {
+        Ok(rel) if rel.len() <= path.len() => rel,
+        _ => path.to_string(),
+    }
+}
+
+/// oe.path.is_path_parent: every path lies below `parent`
+pub fn is_path_parent(parent: &str, paths: &[&str]) -> bool {
+    let with_slash = |p: &str| {
+        let p = abspath(p);
+        if p.ends_with('/') { p } else { format!("{}/", p) }
+    };
+    let parent = with_slash(parent);
+    paths.iter().all(|p| with_slash(p).starts_with(&parent))
+}
+
+// === oe.types ===
+
+/// oe.types.boolean for a string value
+pub fn boolean(value: &str) -> Result<bool, String> {
+    match value.to_lowercase().as_str() {
+        "yes" | "y" | "true" | "t" | "1" => Ok(true),
+        "no" | "n" | "false" | "f" | "0" => Ok(false),
+        _ => Err(format!("Invalid boolean value '{}'", value)),
+    }
+}
+
+/// oe.types.choice: `value` (lowercased) must be one of the space-separated `choices`
+pub fn choice(value: &str, choices: &str) -> Result<String, String> {
+    let value = value.to_lowercase();
+    if choices.split_whitespace().any(|c| c == value) {
+        Ok(value)
+    } else {
+        Err(format!(
+            "Invalid choice '{}'.  Valid choices: {}",
+            value, choices
+        ))
+    }
+}
+
+/// oe.types.path: join to `relativeto`, optionally normalise and require existence
+pub fn types_path(
+    value: &str,
+    relativeto: &str,
+    normalize: bool,
+    mustexist: bool,
+) -> Result<String, String> {
+    let mut value = if value.starts_with('/') || relativeto.is_empty() {
+        value.to_string()
+    } else if relativeto.ends_with('/') {
+        format!("{}{}", relativeto, value)
+    } else {
+        format!("{}/{}", relativeto, value)
+    };
+    if normalize {
+        value = normpath(&value);
+    }
+    if mustexist && !Path::new(&value).exists() {
+        return Err(format!("{}: No such file or directory", value));
+    }
+    Ok(value)
+}
+
+/// oe.types.is_x86: x86_* or i?86
+pub fn is_x86(arch: &str) -> bool {
+    arch.starts_with("x86_")
+        || (arch.starts_with('i') && arch.get(1..).is_some_and(|rest| rest.contains("86")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vercmp_string() {
+        assert_eq!(vercmp_string("1.0", "1.0"), Ordering::Equal);
+        assert_eq!(vercmp_string("1.0-r1", "1.0-r0"), Ordering::Greater);
+        assert_eq!(vercmp_string("1:0.9", "2.0"), Ordering::Greater);
+        assert_eq!(vercmp_string("1.0~rc1", "1.0"), Ordering::Less);
+        assert_eq!(vercmp_string("2.38", "2.4"), Ordering::Greater);
+        assert_eq!(split_version(">= 1:2.3-r4"), (1, "2.3".to_string(), "r4".to_string()));
+        assert!(vercmp_string_op("1.2", "1.10", "<").unwrap());
+        assert!(vercmp_string_op("1.2", "1.2", ">=").unwrap());
+        assert!(vercmp_string_op("1.2", "1.2", "~").is_err());
+    }
+
+    #[test]
+    fn test_explode_dep_versions2() {
+        let deps = explode_dep_versions2("foo (>= 1.0) bar, baz (<< 2.0) foo (= 1.1)", true).unwrap();
+        assert_eq!(
+            deps,
+            vec![
+                ("bar".to_string(), vec![]),
+                ("baz".to_string(), vec!["<< 2.0".to_string()]),
+                ("foo".to_string(), vec![">= 1.0".to_string(), "= 1.1".to_string()]),
+            ]
+        );
+        let unsorted = explode_dep_versions2("zlib ( >=1.2 ) attr", false).unwrap();
+        assert_eq!(unsorted[0], ("zlib".to_string(), vec![">= 1.2".to_string()]));
+        assert_eq!(
+            join_deps(&unsorted, false),
+            "zlib (>= 1.2) attr"
+        );
+        assert!(explode_dep_versions2("foo (1.0)", true).is_err());
+        assert_eq!(explode_deps("a (>= 1) b c (< 2)"), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_contains_and_filter() {
+        let value = Some("systemd pam x11");
+        assert!(contains(value, &["pam", "x11"]));
+        assert!(!contains(value, &["pam", "wayland"]));
+        assert!(contains_any(value, &["wayland", "x11"]));
+        assert!(!contains(None, &["pam"]));
+        assert_eq!(filter(value, &["x11", "systemd", "opengl"]), "systemd x11");
+        assert_eq!(to_boolean("Yes"), Ok(true));
+        assert!(to_boolean("maybe").is_err());
+    }
+
+    #[test]
+    fn test_oe_utils() {
+        assert_eq!(
+            prune_suffix("lib32-zlib-native", &["-native", "-cross"], Some("lib32-")),
+            "zlib"
+        );
+        assert_eq!(
+            str_filter(".*-native", "zlib-native glibc cmake-native", true).unwrap(),
+            "zlib-native cmake-native"
+        );
+        assert_eq!(
+            str_filter(".*-native", "zlib-native glibc cmake-native", false).unwrap(),
+            "glibc"
+        );
+        assert_eq!(both_contain("a b c", "b c d", &["c", "b"]), "b c");
+        assert_eq!(both_contain("a b c", "b d", &["c"]), "");
+        assert_eq!(set_intersect("x y z", "z x"), "x z");
+        assert_eq!(trim_version("3.12.1", 2).unwrap(), "3.12");
+        assert_eq!(trim_version("3", 2).unwrap(), "3");
+        assert_eq!(squashspaces("  a \t b\n c "), "a b c");
+        assert_eq!(parallel_make("-l 4 -j 8"), Ok(Some(8)));
+        assert_eq!(parallel_make("-j16"), Ok(Some(16)));
+        assert_eq!(parallel_make(""), Ok(None));
+        assert_eq!(
+            packages_filter_out_system("foo", "foo foo-dev foo-locale-de foo-tools foo-bin"),
+            vec!["foo-tools", "foo-bin"]
+        );
+    }
+
+    #[test]
+    fn test_oe_path() {
+        assert_eq!(normpath("/usr//lib/./../bin/"), "/usr/bin");
+        assert_eq!(normpath("//usr"), "//usr");
+        assert_eq!(normpath("../a/../../b"), "../../b");
+        assert_eq!(normpath(""), ".");
+        assert_eq!(join(&["/work/image", "/usr/bin"]), "/work/image/usr/bin");
+        assert_eq!(relative("/usr/bin", "/usr/lib/libz.so").unwrap(), "../lib/libz.so");
+        assert_eq!(relative("/usr", "/usr").unwrap(), ".");
+        assert_eq!(format_display("/build/tmp/work", "/build"), "tmp/work");
+        assert_eq!(format_display("/opt/src", "/build/deep/dir"), "/opt/src");
+        assert!(is_path_parent("/usr", &["/usr/lib", "/usr/bin/x"]));
+        assert!(!is_path_parent("/usr/lib", &["/usr/lib64"]));
+    }
+
+    #[test]
+    fn test_oe_types() {
+        assert_eq!(boolean("T"), Ok(true));
+        assert_eq!(boolean("off"), Err("Invalid boolean value 'off'".to_string()));
+        assert_eq!(choice("Yes", "yes no").unwrap(), "yes");
+        assert!(choice("maybe", "yes no").is_err());
+        assert_eq!(types_path("bin/../lib", "/usr", true, false).unwrap(), "/usr/lib");
+        assert_eq!(types_path("/etc", "/usr", true, false).unwrap(), "/etc");
+        assert!(types_path("nonexistent-file", "/nonexistent", true, true).is_err());
+        assert!(is_x86("x86_64"));
+        assert!(is_x86("i686"));
+        assert!(!is_x86("aarch64"));
+    }
+}
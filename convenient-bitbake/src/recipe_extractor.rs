@@ -8,7 +8,11 @@ use crate::python_ir_parser::PythonIRParser;
 use crate::python_ir_executor::IRExecutor;
 use crate::python_ir::ExecutionStrategy;
 use crate::class_dependencies;
-use std::collections::HashMap;
+use crate::datastore::DataStore;
+use crate::class_extend::ClassExtension;
+use crate::override_resolver::{OverrideOp, OverrideResolver};
+use crate::SimpleResolver;
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
 use serde::{Deserialize, Serialize};
 use tracing::{debug, info};
@@ -61,8 +65,15 @@ pub struct ExtractionConfig {
     pub resolve_inherit: bool,
     /// Extract dependencies from inherited classes (Phase 6)
     pub extract_class_deps: bool,
-    /// Search paths for .bbclass files
+    /// Layer (or layer classes) directories searched for .bbclass files
     pub class_search_paths: Vec<std::path::PathBuf>,
+    /// Parse the recipe together with its classes and run the parse lifecycle
+    /// (event handlers, anonymous python); recipes raising bb.parse.SkipRecipe
+    /// are marked skipped in the graph
+    pub run_anonymous_python: bool,
+    /// Parsed configuration (bitbake.conf, local.conf, distro conf) classes are
+    /// evaluated on top of; supplies INHERIT and the build's DISTRO_FEATURES
+    pub configuration: Option<DataStore>,
     /// Build context for override resolution (Phase 7c)
     pub build_context: BuildContext,
 }
@@ -86,6 +97,8 @@ impl Default for ExtractionConfig {
             resolve_inherit: false,
             extract_class_deps: false,
             class_search_paths: Vec::new(),
+            run_anonymous_python: false,
+            configuration: None,
             build_context: BuildContext::default(),
         }
     }
@@ -106,6 +119,9 @@ pub struct RecipeExtraction {
     /// Outer key: variable name, inner HashMap: flag name -> value
     #[serde(default)]
     pub variable_flags: HashMap<String, HashMap<String, String>>,
+    /// Reason the recipe was skipped (bb.parse.SkipRecipe), if it was
+    #[serde(default)]
+    pub skip_reason: Option<String>,
 }
 
 /// PACKAGECONFIG option declaration
@@ -132,6 +148,8 @@ struct PythonBlockInfo {
 /// Extracts dependencies from BitBake recipe content
 pub struct RecipeExtractor {
     config: ExtractionConfig,
+    /// BBCLASSEXTEND variant being extracted (None for the base recipe)
+    variant: Option<ClassExtension>,
     #[cfg(feature = "python-execution")]
     executor: Option<PythonExecutor>,
 }
@@ -147,11 +165,23 @@ impl RecipeExtractor {
 
         Self {
             config,
+            variant: None,
             #[cfg(feature = "python-execution")]
             executor,
         }
     }
 
+    /// Extractor for a BBCLASSEXTEND variant of recipes handled by this one
+    fn for_variant(&self, ext: &ClassExtension) -> Self {
+        let mut config = self.config.clone();
+        config.build_context = ext.build_context(&self.config.build_context);
+        config.default_variables.insert("MLPREFIX".to_string(), ext.mlprefix());
+
+        let mut extractor = Self::new(config);
+        extractor.variant = Some(ext.clone());
+        extractor
+    }
+
     pub fn new_default() -> Self {
         Self::new(ExtractionConfig::default())
     }
@@ -164,10 +194,22 @@ impl RecipeExtractor {
         content: &str,
     ) -> Result<RecipeExtraction, String> {
         let recipe_name = recipe_name.into();
-        let recipe_id = graph.add_recipe(&recipe_name);
+        let variables = self.parse_variables(content);
+        self.extract_with_variables(graph, recipe_name, content, variables, None)
+    }
 
-        // Parse variables from content
-        let mut variables = self.parse_variables(content);
+    /// Extract recipe metadata from content whose variables are already parsed
+    ///
+    /// `recipe_path` locates the recipe's layer, whose classes are searched too.
+    fn extract_with_variables(
+        &self,
+        graph: &mut RecipeGraph,
+        recipe_name: String,
+        content: &str,
+        mut variables: HashMap<String, String>,
+        recipe_path: Option<&Path>,
+    ) -> Result<RecipeExtraction, String> {
+        let recipe_id = graph.add_recipe(&recipe_name);
 
         // Expand simple variable references (${PN}, ${PV}, ${BPN}, ${P})
         let vars_to_expand: Vec<String> = variables.keys().cloned().collect();
@@ -203,18 +245,53 @@ impl RecipeExtractor {
             rdepends.extend(pkg_rdepends);
         }
 
-        let provides = self.extract_list(&variables, "PROVIDES");
-        let rprovides = self.extract_list(&variables, "RPROVIDES");
+        let mut provides = self.extract_list(&variables, "PROVIDES");
+        let mut rprovides = self.extract_list(&variables, "RPROVIDES");
 
         // Merge PACKAGECONFIG dependencies
         depends.extend(pkg_build_deps);
         rdepends.extend(pkg_runtime_deps);
 
-        // Extract class dependencies (Phase 6)
-        if self.config.extract_class_deps {
-            let (class_build_deps, class_runtime_deps) = self.extract_class_dependencies(content, &variables);
-            depends.extend(class_build_deps);
-            rdepends.extend(class_runtime_deps);
+        // Evaluate inherited classes once, for their dependencies (Phase 6) and tasks
+        let resolve_inherit = self.config.resolve_inherit && recipe_path.is_some();
+        let classes = (self.config.extract_class_deps || resolve_inherit || self.config.run_anonymous_python)
+            .then(|| self.evaluate_inherited_classes(content, recipe_path, &recipe_name, &variables));
+        let mut skip_reason = None;
+        if let Some(contributions) = &classes {
+            if self.config.extract_class_deps {
+                depends.extend(contributions.build_deps.iter().cloned());
+                rdepends.extend(contributions.runtime_deps.iter().cloned());
+            }
+            skip_reason.clone_from(&contributions.skip_reason);
+        }
+        // A recipe parsed with its classes already carries its own task statements
+        // and flags, so they are not read from the content a second time
+        let content = match classes.as_ref().filter(|_| resolve_inherit) {
+            Some(contributions) if self.config.run_anonymous_python => class_tasks(contributions),
+            Some(contributions) => format!("{}{}", class_tasks(contributions), content),
+            None => content.to_string(),
+        };
+        let content = content.as_str();
+        if let Some(reason) = &skip_reason {
+            info!("Skipping recipe {}: {}", recipe_name, reason);
+            graph.skip_recipe(recipe_id, reason.clone());
+        }
+
+        // BBCLASSEXTEND variants live in their own namespace (foo -> foo-native)
+        if let Some(ext) = &self.variant {
+            let bpn = self.base_pn(&recipe_name, &variables);
+            for list in [&mut depends, &mut rdepends, &mut provides, &mut rprovides] {
+                for name in list.iter_mut() {
+                    *name = ext.map_name(name, &recipe_name, &bpn);
+                }
+            }
+
+            variables.insert("PN".to_string(), recipe_name.clone());
+            variables.insert("BPN".to_string(), bpn);
+            variables.insert("CLASSOVERRIDE".to_string(), ext.class_override());
+            variables.insert("MLPREFIX".to_string(), ext.mlprefix());
+            variables.insert("BBEXTENDCURR".to_string(), ext.extend_curr().to_string());
+            variables.insert("BBEXTENDVARIANT".to_string(), ext.extend_variant().to_string());
         }
 
         // Update recipe metadata
@@ -225,8 +302,8 @@ impl RecipeExtractor {
             recipe.metadata = variables.clone();
         }
 
-        // Register providers
-        if self.config.resolve_providers {
+        // Register providers (skipped recipes provide nothing)
+        if self.config.resolve_providers && skip_reason.is_none() {
             for provider in &provides {
                 graph.register_provider(recipe_id, provider);
             }
@@ -237,8 +314,8 @@ impl RecipeExtractor {
 
         // Extract tasks if enabled
         let mut task_names = Vec::new();
-        if self.config.extract_tasks {
-            task_names = self.extract_tasks(graph, recipe_id, content);
+        if self.config.extract_tasks && skip_reason.is_none() {
+            task_names = self.extract_tasks(graph, recipe_id, content, &recipe_name, &variables);
         }
 
         // Phase 9c: Parse variable flags
@@ -260,6 +337,7 @@ impl RecipeExtractor {
             tasks: task_names,
             variables,
             variable_flags,
+            skip_reason,
         })
     }
 
@@ -1091,6 +1169,27 @@ impl RecipeExtractor {
         self.expand_simple_variables_recursive(value, recipe_name, variables, 0)
     }
 
+    /// BPN is PN without variant decorations like nativesdk-, -native or MLPREFIX
+    fn base_pn(&self, recipe_name: &str, variables: &HashMap<String, String>) -> String {
+        let mlprefix = variables.get("MLPREFIX")
+            .or_else(|| self.config.default_variables.get("MLPREFIX"))
+            .filter(|prefix| !prefix.is_empty());
+
+        let mut bn = recipe_name;
+        if let Some(prefix) = mlprefix {
+            bn = bn.strip_prefix(prefix.as_str()).unwrap_or(bn);
+        }
+        bn = bn
+            .strip_prefix("nativesdk-")
+            .or_else(|| bn.strip_prefix("native-"))
+            .or_else(|| bn.strip_suffix("-native"))
+            .unwrap_or(bn);
+        if let Some(pos) = bn.find("-cross-") {
+            bn = &bn[..pos];
+        }
+        bn.to_string()
+    }
+
     /// Phase 7h: Recursive helper for nested variable expansion
     fn expand_simple_variables_recursive(
         &self,
@@ -1117,14 +1216,7 @@ impl RecipeExtractor {
                 // Determine replacement value
                 let replacement = match var_name {
                     "PN" => Some(recipe_name.to_string()),
-                    "BPN" => {
-                        // BPN is PN without prefix like nativesdk-
-                        let bn = recipe_name
-                            .strip_prefix("nativesdk-")
-                            .or_else(|| recipe_name.strip_prefix("native-"))
-                            .unwrap_or(recipe_name);
-                        Some(bn.to_string())
-                    }
+                    "BPN" => Some(self.base_pn(recipe_name, variables)),
                     "PV" => variables.get("PV").cloned(),
                     "P" => {
                         // P = ${PN}-${PV}
@@ -1251,60 +1343,103 @@ impl RecipeExtractor {
         result
     }
 
-    /// Extract dependencies from inherited classes (Phase 6)
-    fn extract_class_dependencies(
+    /// Evaluate base, INHERIT and the classes inherited by `content` (Phase 6)
+    ///
+    /// The classes are evaluated through the DataStore on top of the parsed
+    /// configuration, so DEPENDS/RDEPENDS come from the layer's actual .bbclass
+    /// files and INHERIT from local.conf and the distro conf. With
+    /// `run_anonymous_python` the recipe itself is parsed along with its classes
+    /// and finalised, so its anonymous python and event handlers take effect.
+    fn evaluate_inherited_classes(
         &self,
         content: &str,
+        recipe_path: Option<&Path>,
+        recipe_name: &str,
         variables: &HashMap<String, String>,
-    ) -> (Vec<String>, Vec<String>) {
-        let mut build_deps = Vec::new();
-        let mut runtime_deps = Vec::new();
+    ) -> class_dependencies::ClassContributions {
+        let mut class_vars = HashMap::new();
+        if self.config.configuration.is_some() {
+            // The configuration builds OVERRIDES from CLASSOVERRIDE itself
+            class_vars.insert("CLASSOVERRIDE".to_string(), format!("class-{}", self.config.build_context.class));
+            if let Some(prefix) = self.config.default_variables.get("MLPREFIX") {
+                class_vars.insert("MLPREFIX".to_string(), prefix.clone());
+            }
+        } else {
+            class_vars = self.config.default_variables.clone();
+            class_vars.insert("OVERRIDES".to_string(), self.active_overrides().join(":"));
+        }
 
-        // Extract inherited classes
-        let classes = class_dependencies::extract_inherited_classes(content);
-
-        // Get DISTRO_FEATURES for conditional class dependencies
-        let distro_features = variables
-            .get("DISTRO_FEATURES")
-            .or_else(|| self.config.default_variables.get("DISTRO_FEATURES"))
-            .map(|s| s.as_str())
-            .unwrap_or("");
-
-        // Get dependencies for each class
-        for class_name in classes {
-            // Try dynamic .bbclass parsing first (Phase 7b+7f), fall back to hardcoded
-            let (class_build_deps, class_runtime_deps) = if !self.config.class_search_paths.is_empty() {
-                // Phase 7f: Pass recipe variables for Python expression evaluation in .bbclass files
-                class_dependencies::get_class_deps_dynamic(
-                    &class_name,
-                    distro_features,
-                    &self.config.class_search_paths,
-                    variables,
-                )
-            } else {
-                // No search paths configured - use hardcoded mappings only
-                (
-                    class_dependencies::get_class_build_deps(&class_name, distro_features),
-                    class_dependencies::get_class_runtime_deps(&class_name, distro_features),
-                )
-            };
+        let search_paths = self.class_layer_dirs(recipe_path);
+        let configuration = self.config.configuration.as_ref();
+        let contributions = if self.config.run_anonymous_python {
+            class_vars.insert("PN".to_string(), recipe_name.to_string());
+            let fallback_path = std::path::PathBuf::from(format!("{}.bb", recipe_name));
+            let recipe_path = recipe_path.unwrap_or(&fallback_path);
+            class_dependencies::evaluate_recipe(content, recipe_path, configuration, &search_paths, &class_vars)
+        } else {
+            class_vars.extend(variables.iter().map(|(k, v)| (k.clone(), v.clone())));
+            class_vars.entry("PN".to_string()).or_insert_with(|| recipe_name.to_string());
+            let classes = class_dependencies::extract_inherited_classes(content);
+            class_dependencies::evaluate_classes(&classes, configuration, &search_paths, &class_vars)
+        };
+        debug!(
+            "{}: {} classes, {} task statements",
+            recipe_name,
+            contributions.classes.len(),
+            contributions.task_statements.len()
+        );
+        for (class, error) in &contributions.failed {
+            debug!("Class {} not evaluated: {}", class, error);
+        }
+        contributions
+    }
 
-            // Add build dependencies
-            for dep in class_build_deps {
-                if !dep.is_empty() && !build_deps.contains(&dep) {
-                    build_deps.push(dep);
-                }
-            }
+    /// Overrides of the build context, in OVERRIDES order
+    fn active_overrides(&self) -> Vec<String> {
+        let ctx = &self.config.build_context;
+        let mut overrides = vec![
+            "linux".to_string(),
+            ctx.arch.clone(),
+            format!("libc-{}", ctx.libc),
+            format!("class-{}", ctx.class),
+        ];
+        overrides.extend(ctx.overrides.iter().cloned());
+        overrides
+    }
 
-            // Add runtime dependencies
-            for dep in class_runtime_deps {
-                if !dep.is_empty() && !runtime_deps.contains(&dep) {
-                    runtime_deps.push(dep);
-                }
+    /// Layer directories classes are searched in
+    ///
+    /// Configured search paths may point at a layer or at its classes directory;
+    /// the layer containing the recipe is searched as well.
+    fn class_layer_dirs(&self, recipe_path: Option<&Path>) -> Vec<std::path::PathBuf> {
+        let mut dirs: Vec<std::path::PathBuf> = Vec::new();
+
+        for path in &self.config.class_search_paths {
+            let is_classes_dir = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with("classes"));
+            let dir = match path.parent() {
+                Some(parent) if is_classes_dir => parent.to_path_buf(),
+                _ => path.clone(),
+            };
+            if !dirs.contains(&dir) {
+                dirs.push(dir);
             }
         }
 
-        (build_deps, runtime_deps)
+        if let Some(layer_dir) = recipe_path
+            .into_iter()
+            .flat_map(Path::ancestors)
+            .skip(1)
+            .take(6)
+            .find(|dir| dir.join("conf/layer.conf").exists())
+            && !dirs.iter().any(|d| d == layer_dir)
+        {
+            dirs.push(layer_dir.to_path_buf());
+        }
+
+        dirs
     }
 
     /// Extract and parse dependency list, handling version constraints
@@ -1341,6 +1476,8 @@ impl RecipeExtractor {
         graph: &mut RecipeGraph,
         recipe_id: RecipeId,
         content: &str,
+        recipe_name: &str,
+        variables: &HashMap<String, String>,
     ) -> Vec<String> {
         let mut task_names = Vec::new();
         let mut task_constraints = Vec::new();
@@ -1391,9 +1528,13 @@ impl RecipeExtractor {
                 task_constraints.push((task_id, task.after, task.before));
             }
 
-            // Parse task flags
+            // Parse task flags, keeping += / .= so they extend class-set values
             if let Some((task_name, flag_name, value)) = parse_task_flag(line) {
-                task_flags.push((task_name, flag_name, value));
+                let op = line
+                    .split_once('=')
+                    .and_then(|(left, _)| left.trim_end().chars().last());
+                let value = self.expand_simple_variables(&value, recipe_name, variables);
+                task_flags.push((task_name, flag_name, value, op));
             }
         }
 
@@ -1467,10 +1608,19 @@ impl RecipeExtractor {
         }
 
         // Apply flags
-        for (task_name, flag_name, value) in task_flags {
+        for (task_name, flag_name, value, op) in task_flags {
             if let Some(task_id) = graph.find_task(recipe_id, &task_name)
                 && let Some(task_node) = graph.get_task_mut(task_id) {
-                    task_node.flags.insert(flag_name, value);
+                    let flag = task_node.flags.entry(flag_name).or_default();
+                    match op {
+                        Some('+') if !flag.is_empty() => {
+                            flag.push(' ');
+                            flag.push_str(&value);
+                        }
+                        Some('.') => flag.push_str(&value),
+                        Some('?') if !flag.is_empty() => {}
+                        _ => *flag = value,
+                    }
                 }
         }
 
@@ -1491,6 +1641,14 @@ impl RecipeExtractor {
         extractions: &[RecipeExtraction],
     ) -> Result<(), String> {
         for extraction in extractions {
+            // Only the selected version of a multi-version PN takes part in the build
+            if graph.find_recipe(&extraction.name) != Some(extraction.recipe_id) {
+                debug!("Skipping unselected version {:?} of {}",
+                    graph.get_recipe(extraction.recipe_id).and_then(|r| r.version.as_deref()),
+                    extraction.name);
+                continue;
+            }
+
             // Add build-time dependencies
             for dep_name in &extraction.depends {
                 if let Some(dep_id) = graph.resolve_provider(dep_name) {
@@ -1506,45 +1664,183 @@ impl RecipeExtractor {
             }
         }
 
+        for note in graph.provider_diagnostics() {
+            info!("NOTE: {}", note);
+        }
+
         Ok(())
     }
 
+    /// Expand an extracted recipe into its BBCLASSEXTEND variants
+    ///
+    /// Each variant (native, nativesdk, cross, multilib:<name>) becomes its own
+    /// recipe node with its own PN, class override, PROVIDES and tasks, so that
+    /// `DEPENDS += "foo-native"` links to a real node instead of a guessed name.
+    /// `recipe_names` holds the names of all recipe files; a standalone recipe
+    /// (e.g. foo-native_1.0.bb) takes precedence over the generated variant.
+    pub fn extract_variants(
+        &self,
+        graph: &mut RecipeGraph,
+        base: &RecipeExtraction,
+        content: &str,
+        recipe_names: &HashSet<String>,
+    ) -> Result<Vec<RecipeExtraction>, String> {
+        let Some(bbclassextend) = base.variables.get("BBCLASSEXTEND") else {
+            return Ok(Vec::new());
+        };
+
+        let target_arch = self.config.default_variables.get("TARGET_ARCH")
+            .cloned()
+            .unwrap_or_else(|| self.config.build_context.arch.replace('-', "_"));
+        let file_path = graph.get_recipe(base.recipe_id).and_then(|r| r.file_path.clone());
+
+        let mut variants = Vec::new();
+        for ext in ClassExtension::parse_list(bbclassextend) {
+            let pn = ext.variant_pn(&base.name, &target_arch);
+            if recipe_names.contains(&pn) {
+                debug!("Skipping {} variant of {}: {} already exists", ext, base.name, pn);
+                continue;
+            }
+
+            let variables = self.variant_variables(content, &ext, &pn);
+            let extraction = self.for_variant(&ext)
+                .extract_with_variables(graph, pn, content, variables, file_path.as_deref())?;
+            if let Some(recipe) = graph.get_recipe_mut(extraction.recipe_id) {
+                recipe.file_path.clone_from(&file_path);
+            }
+            variants.push(extraction);
+        }
+
+        Ok(variants)
+    }
+
+    /// Resolve a recipe's variables for one BBCLASSEXTEND variant
+    ///
+    /// Unlike the inclusive parse used for the base recipe, the variant goes
+    /// through OverrideResolver with its class context, so `VAR:class-target`
+    /// is dropped and `VAR:append:class-native` applies only to the native variant.
+    /// Package-qualified names such as `RDEPENDS:${PN}` resolve with the variant
+    /// PN in OVERRIDES, as packaging does.
+    fn variant_variables(&self, content: &str, ext: &ClassExtension, pn: &str) -> HashMap<String, String> {
+        let mut resolver = OverrideResolver::new(SimpleResolver::from_variables(HashMap::new()));
+        let mut overrides = self.active_overrides();
+        overrides.push(pn.to_string());
+        resolver.set_overrides(&overrides.join(":"));
+        resolver.set_class_extension(ext);
+
+        let mut names: Vec<String> = Vec::new();
+        for line in self.join_continued_lines(content).lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((var_name, operator, override_suffix, value)) = self.parse_assignment(line) else {
+                continue;
+            };
+            if var_name.is_empty() || var_name.contains('[') {
+                continue;
+            }
+
+            let (operation, op_override) = match operator.as_str() {
+                ":append" => (OverrideOp::Append, Some("append")),
+                ":prepend" => (OverrideOp::Prepend, Some("prepend")),
+                ":remove" => (OverrideOp::Remove, Some("remove")),
+                "+=" | ".=" => (OverrideOp::Append, None),
+                "=+" | "=." => (OverrideOp::Prepend, None),
+                "?=" => (OverrideOp::WeakDefault, None),
+                "??=" => (OverrideOp::ImmediateWeakDefault, None),
+                _ => (OverrideOp::Assign, None),
+            };
+            let qualified = std::iter::once(var_name.as_str())
+                .chain(op_override)
+                .chain(override_suffix.as_deref().filter(|o| !o.is_empty()))
+                .collect::<Vec<_>>()
+                .join(":")
+                .replace("${PN}", pn);
+
+            let base_name = qualified.split(':').next().unwrap_or_default().to_string();
+            if !names.contains(&base_name) {
+                names.push(base_name);
+            }
+            resolver.add_assignment(&qualified, value, operation);
+        }
+
+        let mut variables = HashMap::new();
+        for name in names {
+            if let Some(mut value) = resolver.resolve(&name) {
+                if self.config.use_simple_python_eval {
+                    value = self.eval_python_expressions_in_string(&value, &variables);
+                }
+                variables.insert(name, value);
+            }
+        }
+        variables
+    }
+
     /// Extract recipe from file path
     pub fn extract_from_file(
         &self,
         graph: &mut RecipeGraph,
         file_path: &Path,
     ) -> Result<RecipeExtraction, String> {
+        self.extract_file_content(graph, file_path)
+            .map(|(extraction, _)| extraction)
+    }
+
+    /// Extract recipe from file path together with its BBCLASSEXTEND variants
+    ///
+    /// The base recipe is always the first element of the returned list.
+    pub fn extract_from_file_with_variants(
+        &self,
+        graph: &mut RecipeGraph,
+        file_path: &Path,
+        recipe_names: &HashSet<String>,
+    ) -> Result<Vec<RecipeExtraction>, String> {
+        let (extraction, content) = self.extract_file_content(graph, file_path)?;
+        let variants = self.extract_variants(graph, &extraction, &content, recipe_names)?;
+
+        let mut extractions = vec![extraction];
+        extractions.extend(variants);
+        Ok(extractions)
+    }
+
+    /// Read, preprocess and extract a recipe file, returning the resolved content too
+    fn extract_file_content(
+        &self,
+        graph: &mut RecipeGraph,
+        file_path: &Path,
+    ) -> Result<(RecipeExtraction, String), String> {
         let mut content = std::fs::read_to_string(file_path)
             .map_err(|e| format!("Failed to read file: {}", e))?;
 
-        let recipe_name = file_path
+        // BitBake derives PN, PV and PR from the file name: foo_1.2_r0.bb
+        let stem = file_path
             .file_stem()
             .and_then(|s| s.to_str())
-            .ok_or_else(|| "Invalid file name".to_string())?
-            .split('_')
-            .next()
-            .unwrap_or("")
-            .to_string();
+            .ok_or_else(|| "Invalid file name".to_string())?;
+        let mut name_parts = stem.split('_');
+        let recipe_name = name_parts.next().unwrap_or("").to_string();
+        let file_version = name_parts.next().filter(|v| !v.is_empty());
 
         // Resolve includes if enabled
         if self.config.resolve_includes {
             content = self.resolve_includes_in_content(&content, file_path, &recipe_name)?;
         }
 
-        // Resolve inherit classes if enabled
-        if self.config.resolve_inherit {
-            content = self.resolve_inherit_in_content(&content, file_path)?;
+        // The file name version is the default PV; an assignment in the recipe overrides it
+        if let Some(version) = file_version {
+            content = format!("PV = \"{}\"\n{}", version, content);
         }
 
-        let extraction = self.extract_from_content(graph, recipe_name, &content)?;
+        let variables = self.parse_variables(&content);
+        let extraction = self.extract_with_variables(graph, recipe_name, &content, variables, Some(file_path))?;
 
         // Update file path
         if let Some(recipe) = graph.get_recipe_mut(extraction.recipe_id) {
             recipe.file_path = Some(file_path.to_path_buf());
         }
 
-        Ok(extraction)
+        Ok((extraction, content))
     }
 
     /// Resolve include/require directives in content
@@ -1677,150 +1973,6 @@ impl RecipeExtractor {
         None
     }
 
-    /// Resolve inherit statements to extract tasks from classes
-    fn resolve_inherit_in_content(
-        &self,
-        content: &str,
-        recipe_path: &Path,
-    ) -> Result<String, String> {
-        let mut resolved = String::from(content);
-        let mut class_content = String::new();
-        let mut processed_classes = std::collections::HashSet::new();
-
-        // Automatically include base.bbclass (BitBake inherits it for all recipes)
-        self.extract_tasks_from_class("base", recipe_path, &mut class_content, &mut processed_classes);
-
-        // Parse explicit inherit statements
-        for line in content.lines() {
-            let trimmed = line.trim();
-
-            if let Some(classes) = self.parse_inherit_statement(trimmed) {
-                // Process each class
-                for class_name in classes {
-                    self.extract_tasks_from_class(class_name, recipe_path, &mut class_content, &mut processed_classes);
-                }
-            }
-        }
-
-        // Append class tasks to the resolved content
-        if !class_content.is_empty() {
-            let task_count = class_content.lines().filter(|l| l.trim().starts_with("addtask")).count();
-            debug!("Appending {} addtask statements from inherited classes", task_count);
-
-            // Debug: For busybox, show what we're appending
-            if recipe_path.file_name().and_then(|n| n.to_str()).map(|n| n.contains("busybox")).unwrap_or(false) {
-                debug!("busybox class content (first 5 addtask lines):");
-                for (i, line) in class_content.lines().filter(|l| l.trim().starts_with("addtask")).take(5).enumerate() {
-                    debug!("  [{}] {}", i, line.trim());
-                }
-            }
-
-            resolved.push_str("\n# Tasks from inherited classes (base + explicit)\n");
-            resolved.push_str(&class_content);
-        } else {
-            debug!("No tasks found from inherited classes");
-        }
-
-        Ok(resolved)
-    }
-
-    /// Recursively extract tasks from a class and its inherited classes
-    fn extract_tasks_from_class(
-        &self,
-        class_name: &str,
-        recipe_path: &Path,
-        output: &mut String,
-        processed: &mut std::collections::HashSet<String>,
-    ) {
-        // Skip if already processed (avoid circular dependencies)
-        if processed.contains(class_name) {
-            return;
-        }
-        processed.insert(class_name.to_string());
-
-        if let Some(class_path) = self.find_class_file(class_name, recipe_path) {
-            if let Ok(class_content) = std::fs::read_to_string(&class_path) {
-                // First, recursively process inherited classes
-                for line in class_content.lines() {
-                    let trimmed = line.trim();
-                    if let Some(inherited_classes) = self.parse_inherit_statement(trimmed) {
-                        for inherited_class in inherited_classes {
-                            self.extract_tasks_from_class(inherited_class, recipe_path, output, processed);
-                        }
-                    }
-                }
-
-                // Then extract addtask statements from this class
-                for line in class_content.lines() {
-                    let line_trimmed = line.trim();
-                    if line_trimmed.starts_with("addtask ") ||
-                       (line_trimmed.starts_with("do_") && line_trimmed.contains('[')) {
-                        output.push_str(line);
-                        output.push('\n');
-                    }
-                }
-            }
-        }
-    }
-
-    /// Parse inherit statement
-    fn parse_inherit_statement<'a>(&self, line: &'a str) -> Option<Vec<&'a str>> {
-        let trimmed = line.trim();
-
-        trimmed.strip_prefix("inherit ").map(|rest| rest.split_whitespace().collect())
-    }
-
-    /// Find .bbclass file in search paths
-    fn find_class_file(&self, class_name: &str, recipe_path: &Path) -> Option<std::path::PathBuf> {
-        let class_filename = format!("{}.bbclass", class_name);
-
-        // Try configured search paths first
-        for search_path in &self.config.class_search_paths {
-            let candidate = search_path.join(&class_filename);
-            if candidate.exists() {
-                return Some(candidate);
-            }
-        }
-
-        // Try to find classes relative to recipe (common Yocto structure)
-        // Recipe is typically in: meta/recipes-*/category/recipe.bb
-        // Classes are in: meta/classes-recipe/*.bbclass or meta/classes/*.bbclass
-        if let Some(recipe_dir) = recipe_path.parent() {
-            // Go up until we find 'meta' directory
-            let mut current = recipe_dir;
-            for _ in 0..5 {  // Max 5 levels up
-                if let Some(parent) = current.parent() {
-                    if current.file_name().and_then(|n| n.to_str()) == Some("meta") ||
-                       parent.file_name().and_then(|n| n.to_str()) == Some("meta") {
-                        // Found meta directory, check for classes
-                        let meta_dir = if current.file_name().and_then(|n| n.to_str()) == Some("meta") {
-                            current
-                        } else {
-                            parent
-                        };
-
-                        // Try classes-recipe/ first (newer Yocto)
-                        let classes_recipe = meta_dir.join("classes-recipe").join(&class_filename);
-                        if classes_recipe.exists() {
-                            return Some(classes_recipe);
-                        }
-
-                        // Try classes/ (older Yocto)
-                        let classes = meta_dir.join("classes").join(&class_filename);
-                        if classes.exists() {
-                            return Some(classes);
-                        }
-                    }
-                    current = parent;
-                } else {
-                    break;
-                }
-            }
-        }
-
-        None
-    }
-
     /// Extract multiple recipes and populate dependencies
     pub fn extract_recipes(
         &self,
@@ -1829,10 +1981,16 @@ impl RecipeExtractor {
     ) -> Result<Vec<RecipeExtraction>, String> {
         let mut extractions = Vec::new();
 
-        // First pass: extract all recipes
+        // Names come from the file stems (foo-native_1.0.bb -> foo-native)
+        let recipe_names: HashSet<String> = recipe_files.iter()
+            .filter_map(|file_path| file_path.as_ref().file_stem()?.to_str())
+            .map(|stem| stem.split('_').next().unwrap_or(stem).to_string())
+            .collect();
+
+        // First pass: extract all recipes and their BBCLASSEXTEND variants
         for file_path in recipe_files {
-            match self.extract_from_file(graph, file_path.as_ref()) {
-                Ok(extraction) => extractions.push(extraction),
+            match self.extract_from_file_with_variants(graph, file_path.as_ref(), &recipe_names) {
+                Ok(recipe_extractions) => extractions.extend(recipe_extractions),
                 Err(e) => eprintln!("Warning: Failed to extract {}: {}",
                     file_path.as_ref().display(), e),
             }
@@ -1851,6 +2009,41 @@ impl Default for RecipeExtractor {
     }
 }
 
+/// The classes' addtask/deltask statements and task flags, to go ahead of the recipe
+///
+/// The recipe's own statements then take precedence. Tasks implemented through
+/// EXPORT_FUNCTIONS carry the class function in their `export_func` flag.
+fn class_tasks(classes: &class_dependencies::ClassContributions) -> String {
+    let mut class_content = String::new();
+    for statement in &classes.task_statements {
+        class_content.push_str(statement);
+        class_content.push('\n');
+    }
+    for (task, flags) in &classes.task_flags {
+        for (flag, value) in flags {
+            class_content.push_str(&format_task_flag(task, flag, value));
+        }
+    }
+    for (task, function) in &classes.exported_functions {
+        class_content.push_str(&format_task_flag(task, "export_func", function));
+    }
+
+    if class_content.is_empty() {
+        debug!("No tasks found from inherited classes");
+        return class_content;
+    }
+    format!("# Tasks from inherited classes (base + INHERIT + explicit)\n{}\n", class_content)
+}
+
+/// Render `do_task[flag] = "value"`, quoting with ' when the value contains "
+fn format_task_flag(task: &str, flag: &str, value: &str) -> String {
+    if value.contains('"') {
+        format!("{}[{}] = '{}'\n", task, flag, value)
+    } else {
+        format!("{}[{}] = \"{}\"\n", task, flag, value)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -2386,6 +2579,317 @@ RDEPENDS = "bar"
         assert!(!blocks[1].is_anonymous);
         assert!(blocks[1].code.contains("pass"));
     }
+
+    #[test]
+    fn test_bbclassextend_variants() {
+        let mut graph = RecipeGraph::new();
+        let config = ExtractionConfig {
+            extract_tasks: true,
+            resolve_providers: true,
+            ..Default::default()
+        };
+        let extractor = RecipeExtractor::new(config);
+
+        let content = r#"
+PV = "1.3"
+DEPENDS = "zlib"
+DEPENDS:append:class-target = " target-only"
+PROVIDES = "virtual/libfoo"
+BBCLASSEXTEND = "native nativesdk multilib:lib32"
+
+do_compile() {
+    oe_runmake
 }
+addtask compile after do_configure
+"#;
 
+        let base = extractor.extract_from_content(&mut graph, "foo", content).unwrap();
+        let recipe_names = HashSet::from(["foo".to_string()]);
+        let variants = extractor.extract_variants(&mut graph, &base, content, &recipe_names).unwrap();
+        let names: Vec<&str> = variants.iter().map(|v| v.name.as_str()).collect();
+        assert_eq!(names, vec!["foo-native", "nativesdk-foo", "lib32-foo"]);
+
+        let native = &variants[0];
+        assert_eq!(native.depends, vec!["zlib-native".to_string()]);
+        assert_eq!(native.provides, vec!["virtual/libfoo-native".to_string()]);
+        assert_eq!(native.variables.get("PN"), Some(&"foo-native".to_string()));
+        assert_eq!(native.variables.get("BPN"), Some(&"foo".to_string()));
+        assert_eq!(native.variables.get("CLASSOVERRIDE"), Some(&"class-native".to_string()));
+        assert!(native.tasks.contains(&"compile".to_string()));
+        assert!(graph.find_task(native.recipe_id, "compile").is_some());
+
+        let sdk = &variants[1];
+        assert!(sdk.depends.contains(&"nativesdk-zlib".to_string()));
+        assert!(!sdk.depends.iter().any(|d| d.contains("target-only")));
+
+        let lib32 = &variants[2];
+        assert!(lib32.depends.contains(&"lib32-zlib".to_string()));
+        assert!(lib32.depends.contains(&"lib32-target-only".to_string()));
+        assert_eq!(lib32.variables.get("BBEXTENDVARIANT"), Some(&"lib32".to_string()));
+        assert_eq!(graph.resolve_provider("virtual/lib32-libfoo"), Some(lib32.recipe_id));
+    }
 
+    #[test]
+    fn test_native_dependency_links_to_variant() {
+        let mut graph = RecipeGraph::new();
+        let extractor = RecipeExtractor::new_default();
+
+        let zlib_content = "PV = \"1.3\"\nBBCLASSEXTEND = \"native\"\n";
+        let app_content = "DEPENDS += \"zlib-native zlib\"\n";
+
+        let zlib = extractor.extract_from_content(&mut graph, "zlib", zlib_content).unwrap();
+        let recipe_names = HashSet::from(["zlib".to_string(), "app".to_string()]);
+        let mut extractions = extractor.extract_variants(&mut graph, &zlib, zlib_content, &recipe_names).unwrap();
+        extractions.push(zlib);
+        extractions.push(extractor.extract_from_content(&mut graph, "app", app_content).unwrap());
+        extractor.populate_dependencies(&mut graph, &extractions).unwrap();
+
+        let app = graph.find_recipe("app").unwrap();
+        let zlib_native = graph.find_recipe("zlib-native").unwrap();
+        let zlib = graph.find_recipe("zlib").unwrap();
+        let deps = graph.get_dependencies(app);
+        assert!(deps.contains(&zlib_native));
+        assert!(deps.contains(&zlib));
+        assert_ne!(zlib, zlib_native);
+    }
+
+    #[test]
+    fn test_variant_overrides_resolved_per_class() {
+        let mut graph = RecipeGraph::new();
+        let extractor = RecipeExtractor::new_default();
+
+        let content = r#"
+DEPENDS = "zlib"
+DEPENDS:class-native = "lzo"
+RDEPENDS:${PN} = "bash"
+BBCLASSEXTEND = "native nativesdk"
+"#;
+        let base = extractor.extract_from_content(&mut graph, "foo", content).unwrap();
+        let recipe_names = HashSet::from(["foo".to_string()]);
+        let variants = extractor.extract_variants(&mut graph, &base, content, &recipe_names).unwrap();
+
+        let native = &variants[0];
+        assert_eq!(native.depends, vec!["lzo-native".to_string()]);
+        assert!(native.rdepends.contains(&"bash-native".to_string()));
+
+        let sdk = &variants[1];
+        assert_eq!(sdk.depends, vec!["nativesdk-zlib".to_string()]);
+    }
+
+    #[test]
+    fn test_standalone_recipe_wins_regardless_of_order() {
+        let mut graph = RecipeGraph::new();
+        let extractor = RecipeExtractor::new_default();
+        let recipe_names = HashSet::from(["zlib".to_string(), "zlib-native".to_string()]);
+
+        // The variant is expanded before the standalone recipe has been extracted
+        let content = "BBCLASSEXTEND = \"native nativesdk\"\n";
+        let zlib = extractor.extract_from_content(&mut graph, "zlib", content).unwrap();
+        let variants = extractor.extract_variants(&mut graph, &zlib, content, &recipe_names).unwrap();
+        let names: Vec<&str> = variants.iter().map(|v| v.name.as_str()).collect();
+        assert_eq!(names, vec!["nativesdk-zlib"]);
+
+        let standalone = extractor.extract_from_content(&mut graph, "zlib-native", "").unwrap();
+        assert_eq!(graph.find_recipe("zlib-native"), Some(standalone.recipe_id));
+    }
+
+    #[test]
+    fn test_multi_version_selection() {
+        let mut graph = RecipeGraph::new();
+        let extractor = RecipeExtractor::new_default();
+
+        let old = extractor
+            .extract_from_content(&mut graph, "foo", "PV = \"1.2\"\nDEPENDS = \"old-dep\"\n")
+            .unwrap();
+        let new = extractor
+            .extract_from_content(&mut graph, "foo", "PV = \"1.10\"\nDEPENDS = \"new-dep\"\n")
+            .unwrap();
+        let git = extractor
+            .extract_from_content(&mut graph, "foo", "PV = \"1.11+git\"\nDEFAULT_PREFERENCE = \"-1\"\n")
+            .unwrap();
+        let old_dep = extractor.extract_from_content(&mut graph, "old-dep", "").unwrap();
+        let new_dep = extractor.extract_from_content(&mut graph, "new-dep", "").unwrap();
+
+        // Highest version wins, but DEFAULT_PREFERENCE = "-1" demotes the git recipe
+        assert_eq!(graph.find_recipe("foo"), Some(new.recipe_id));
+
+        let mut prefs = crate::ProviderPreferences::new();
+        prefs.preferred_versions.insert("foo".to_string(), "1.2%".to_string());
+        graph.set_provider_preferences(prefs);
+        assert_eq!(graph.find_recipe("foo"), Some(old.recipe_id));
+
+        let old_id = old.recipe_id;
+        let new_id = new.recipe_id;
+        let old_dep_id = old_dep.recipe_id;
+        extractor
+            .populate_dependencies(&mut graph, &[old, new, git, old_dep, new_dep])
+            .unwrap();
+        assert_eq!(graph.get_dependencies(old_id), vec![old_dep_id]);
+        assert!(graph.get_dependencies(new_id).is_empty());
+    }
+
+    #[test]
+    fn test_inherited_classes_evaluated() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let layer = temp.path().join("meta-test");
+        std::fs::create_dir_all(layer.join("conf")).unwrap();
+        std::fs::create_dir_all(layer.join("classes-recipe")).unwrap();
+        std::fs::create_dir_all(layer.join("recipes-test/foo")).unwrap();
+        std::fs::write(layer.join("conf/layer.conf"), "BBFILE_COLLECTIONS += \"test\"\n").unwrap();
+        std::fs::write(
+            layer.join("classes-recipe/base.bbclass"),
+            "addtask configure\naddtask compile after do_configure\nbase_do_compile() {\n\toe_runmake\n}\nEXPORT_FUNCTIONS do_compile\n",
+        )
+        .unwrap();
+        std::fs::write(
+            layer.join("classes-recipe/cmake.bbclass"),
+            "DEPENDS:prepend = \"cmake-native \"\ncmake_do_compile() {\n\tcmake --build .\n}\ndo_compile[progress] = \"percent\"\nEXPORT_FUNCTIONS do_compile\n",
+        )
+        .unwrap();
+        let recipe = layer.join("recipes-test/foo/foo_1.0.bb");
+        std::fs::write(&recipe, "inherit cmake\nDEPENDS = \"zlib\"\n").unwrap();
+
+        let extractor = RecipeExtractor::new(ExtractionConfig {
+            extract_tasks: true,
+            resolve_inherit: true,
+            extract_class_deps: true,
+            class_search_paths: vec![layer.join("classes-recipe")],
+            ..Default::default()
+        });
+        let mut graph = RecipeGraph::new();
+        let extraction = extractor.extract_from_file(&mut graph, &recipe).unwrap();
+
+        assert!(extraction.depends.contains(&"cmake-native".to_string()));
+        assert!(extraction.depends.contains(&"zlib".to_string()));
+
+        let compile = graph.find_task(extraction.recipe_id, "compile").unwrap();
+        let task = graph.get_task(compile).unwrap();
+        assert_eq!(task.flags.get("progress").map(String::as_str), Some("percent"));
+        assert_eq!(task.flags.get("export_func").map(String::as_str), Some("cmake_do_compile"));
+    }
+
+    #[test]
+    fn test_configuration_inherit_evaluated_from_recipe_layer() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let layer = temp.path().join("meta-test");
+        std::fs::create_dir_all(layer.join("conf")).unwrap();
+        std::fs::create_dir_all(layer.join("classes-global")).unwrap();
+        std::fs::create_dir_all(layer.join("classes-recipe")).unwrap();
+        std::fs::create_dir_all(layer.join("recipes-test/foo")).unwrap();
+        std::fs::write(layer.join("conf/layer.conf"), "BBFILE_COLLECTIONS += \"test\"\n").unwrap();
+        std::fs::write(layer.join("classes-global/base.bbclass"), "addtask compile\n").unwrap();
+        std::fs::write(
+            layer.join("classes-global/uninative.bbclass"),
+            "DEPENDS:append = \" uninative-tarball\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            layer.join("classes-recipe/systemd.bbclass"),
+            "DEPENDS:append = \" ${@bb.utils.contains('DISTRO_FEATURES', 'systemd', 'systemd-systemctl-native', '', d)}\"\n",
+        )
+        .unwrap();
+        let recipe = layer.join("recipes-test/foo/foo_1.0.bb");
+        std::fs::write(&recipe, "inherit systemd\n").unwrap();
+
+        // INHERIT and DISTRO_FEATURES are only known to the parsed configuration
+        let mut configuration = DataStore::new();
+        configuration.set_var("INHERIT", "uninative");
+        configuration.set_var("DISTRO_FEATURES", "systemd");
+
+        let extractor = RecipeExtractor::new(ExtractionConfig {
+            extract_tasks: true,
+            resolve_inherit: true,
+            extract_class_deps: true,
+            configuration: Some(configuration),
+            ..Default::default()
+        });
+        let mut graph = RecipeGraph::new();
+        let extraction = extractor.extract_from_file(&mut graph, &recipe).unwrap();
+
+        assert!(extraction.depends.contains(&"uninative-tarball".to_string()));
+        assert!(extraction.depends.contains(&"systemd-systemctl-native".to_string()));
+        assert!(graph.find_task(extraction.recipe_id, "compile").is_some());
+    }
+
+    #[test]
+    fn test_recipe_anonymous_python_runs_with_classes() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let layer = temp.path().join("meta-test");
+        std::fs::create_dir_all(layer.join("conf")).unwrap();
+        std::fs::create_dir_all(layer.join("classes-global")).unwrap();
+        std::fs::create_dir_all(layer.join("recipes-test/foo")).unwrap();
+        std::fs::write(layer.join("conf/layer.conf"), "BBFILE_COLLECTIONS += \"test\"\n").unwrap();
+        std::fs::write(
+            layer.join("classes-global/base.bbclass"),
+            "addtask configure\naddtask compile after do_configure\ndo_compile[dirs] = \"${B}\"\n",
+        )
+        .unwrap();
+
+        let foo = layer.join("recipes-test/foo/foo_1.0.bb");
+        std::fs::write(
+            &foo,
+            r#"B = "/build"
+do_compile[dirs] += "/extra"
+python () {
+    d.setVarFlag("do_compile", "network", "1")
+    bb.build.addtask("do_deploy", "do_build", "do_compile", d)
+}
+"#,
+        )
+        .unwrap();
+        let bar = layer.join("recipes-test/foo/bar_1.0.bb");
+        std::fs::write(
+            &bar,
+            "python () {\n    raise bb.parse.SkipRecipe(\"needs x11\")\n}\n",
+        )
+        .unwrap();
+
+        let extractor = RecipeExtractor::new(ExtractionConfig {
+            extract_tasks: true,
+            resolve_inherit: true,
+            run_anonymous_python: true,
+            ..Default::default()
+        });
+        let mut graph = RecipeGraph::new();
+
+        let extraction = extractor.extract_from_file(&mut graph, &foo).unwrap();
+        assert_eq!(extraction.skip_reason, None);
+        let compile = graph.find_task(extraction.recipe_id, "compile").unwrap();
+        let task = graph.get_task(compile).unwrap();
+        assert_eq!(task.flags.get("network").map(String::as_str), Some("1"));
+        assert_eq!(task.flags.get("dirs").map(String::as_str), Some("/build /extra"));
+        assert!(graph.find_task(extraction.recipe_id, "deploy").is_some());
+
+        let extraction = extractor.extract_from_file(&mut graph, &bar).unwrap();
+        assert_eq!(extraction.skip_reason.as_deref(), Some("needs x11"));
+        assert_eq!(graph.skip_reason(extraction.recipe_id), Some("needs x11"));
+    }
+
+    #[test]
+    fn test_task_flags_append_and_expand() {
+        let mut graph = RecipeGraph::new();
+        let extractor = RecipeExtractor::new(ExtractionConfig {
+            extract_tasks: true,
+            ..Default::default()
+        });
+
+        let content = r#"
+PV = "1.0"
+do_compile[depends] = "zlib:do_populate_sysroot"
+do_compile[depends] += "${PN}-tools:do_install"
+do_compile[dirs] = "${PN}-build"
+do_compile[nostamp] ?= "1"
+do_compile[nostamp] ?= "0"
+"#;
+
+        let extraction = extractor
+            .extract_from_content(&mut graph, "foo", content)
+            .unwrap();
+
+        let compile = graph.find_task(extraction.recipe_id, "compile").unwrap();
+        let flags = &graph.get_task(compile).unwrap().flags;
+        assert_eq!(flags["depends"], "zlib:do_populate_sysroot foo-tools:do_install");
+        assert_eq!(flags["dirs"], "foo-build");
+        assert_eq!(flags["nostamp"], "1");
+    }
+}
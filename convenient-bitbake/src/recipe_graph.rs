@@ -1,7 +1,9 @@
 // Recipe dependency graph with flat structure and ID-based references
 // Follows modern compiler IR design (rustc, LLVM, rust-analyzer)
 
+use crate::provider_selection::{version_matches, vercmp, ProviderNote, ProviderPreferences};
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
 
@@ -158,9 +160,19 @@ pub struct RecipeGraph {
 
     // Indices for fast lookup
     name_to_recipe: HashMap<String, RecipeId>,
+    #[serde(default)]
+    pn_to_recipes: HashMap<String, Vec<RecipeId>>,  // PN -> all versions (foo_1.2.bb, foo_git.bb)
     provider_map: HashMap<String, Vec<RecipeId>>,  // virtual provider -> recipes
+
+    // PREFERRED_PROVIDER / PREFERRED_VERSION / BBFILE_PRIORITY
+    #[serde(default)]
+    preferences: ProviderPreferences,
     recipe_to_tasks: HashMap<RecipeId, Vec<TaskId>>,
 
+    // Recipes skipped at parse time (bb.parse.SkipRecipe) -> reason
+    #[serde(default)]
+    skipped: HashMap<RecipeId, String>,
+
     // ID generators
     next_recipe_id: u32,
     next_task_id: u32,
@@ -181,6 +193,7 @@ impl RecipeGraph {
 
         let recipe = Recipe::new(id, name.clone());
         self.recipes.insert(id, recipe);
+        self.pn_to_recipes.entry(name.clone()).or_default().push(id);
         self.name_to_recipe.insert(name, id);
 
         id
@@ -197,8 +210,40 @@ impl RecipeGraph {
     }
 
     /// Find a recipe by name
+    /// When several versions of a PN exist, returns the one BitBake would select;
+    /// skipped recipe files are never selected
     pub fn find_recipe(&self, name: &str) -> Option<RecipeId> {
-        self.name_to_recipe.get(name).copied()
+        match self.pn_to_recipes.get(name) {
+            Some(versions) if versions.len() > 1 || !self.skipped.is_empty() => {
+                let buildable: Vec<RecipeId> = versions
+                    .iter()
+                    .copied()
+                    .filter(|id| !self.skipped.contains_key(id))
+                    .collect();
+                self.select_version(name, &buildable)
+            }
+            _ => self.name_to_recipe.get(name).copied(),
+        }
+    }
+
+    /// Mark a recipe as skipped (bb.parse.SkipRecipe) with the reason
+    pub fn skip_recipe(&mut self, id: RecipeId, reason: impl Into<String>) {
+        self.skipped.insert(id, reason.into());
+    }
+
+    /// Why a recipe was skipped, if it was
+    pub fn skip_reason(&self, id: RecipeId) -> Option<&str> {
+        self.skipped.get(&id).map(String::as_str)
+    }
+
+    /// Skipped recipes with their reasons
+    pub fn skipped_recipes(&self) -> impl Iterator<Item = (RecipeId, &str)> {
+        self.skipped.iter().map(|(id, reason)| (*id, reason.as_str()))
+    }
+
+    /// All recipe files (versions) that share a PN
+    pub fn find_recipe_versions(&self, name: &str) -> Vec<RecipeId> {
+        self.pn_to_recipes.get(name).cloned().unwrap_or_default()
     }
 
     /// Get all recipes
@@ -307,16 +352,181 @@ impl RecipeGraph {
     }
 
     /// Resolve a provider (e.g., "virtual/kernel" -> linux-yocto)
+    ///
+    /// Follows BitBake's provider selection: PREFERRED_PROVIDER first, then a
+    /// recipe whose PN matches, then the highest BBFILE_PRIORITY provider.
+    /// Within a PN, PREFERRED_VERSION, DEFAULT_PREFERENCE and the version pick the file.
     pub fn resolve_provider(&self, capability: &str) -> Option<RecipeId> {
-        // First check direct name match
-        if let Some(id) = self.name_to_recipe.get(capability) {
-            return Some(*id);
+        // PREFERRED_PROVIDER_<capability> wins if the named recipe exists
+        if let Some(pn) = self.preferences.preferred_provider(capability)
+            && let Some(id) = self.find_recipe(pn)
+        {
+            return Some(id);
+        }
+
+        // Then check direct name match
+        if let Some(id) = self.find_recipe(capability) {
+            return Some(id);
         }
 
         // Then check provider map
-        self.provider_map.get(capability)
-            .and_then(|providers| providers.first())
+        self.provider_candidates(capability).into_iter().next()
+    }
+
+    /// Best recipe of each PN providing a capability, best provider first
+    fn provider_candidates(&self, capability: &str) -> Vec<RecipeId> {
+        let Some(providers) = self.provider_map.get(capability) else {
+            return Vec::new();
+        };
+
+        let mut seen = HashSet::new();
+        let mut candidates: Vec<RecipeId> = providers
+            .iter()
+            .filter_map(|id| self.recipes.get(id))
+            .filter(|recipe| seen.insert(recipe.name.as_str()))
+            .filter_map(|recipe| self.find_recipe(&recipe.name))
+            .collect();
+
+        // Stable sort keeps registration order among equal priorities
+        candidates.sort_by_key(|id| std::cmp::Reverse(self.layer_priority(*id)));
+        candidates
+    }
+
+    /// Pick one recipe file among several versions of the same PN
+    fn select_version(&self, pn: &str, versions: &[RecipeId]) -> Option<RecipeId> {
+        if let Some(pattern) = self.preferences.preferred_version(pn)
+            && let Some(id) = versions
+                .iter()
+                .copied()
+                .filter(|id| self.recipe_matches_version(*id, pattern))
+                .max_by(|a, b| self.compare_versions(*a, *b, false))
+        {
+            return Some(id);
+        }
+
+        versions
+            .iter()
             .copied()
+            .max_by(|a, b| self.compare_versions(*a, *b, true))
+    }
+
+    /// Order two recipe files: BBFILE_PRIORITY, then DEFAULT_PREFERENCE, then version
+    fn compare_versions(&self, a: RecipeId, b: RecipeId, use_default_preference: bool) -> Ordering {
+        let (Some(ra), Some(rb)) = (self.recipes.get(&a), self.recipes.get(&b)) else {
+            return Ordering::Equal;
+        };
+
+        let preference = |r: &Recipe| {
+            r.metadata
+                .get("DEFAULT_PREFERENCE")
+                .and_then(|p| p.trim().parse::<i32>().ok())
+                .unwrap_or(0)
+        };
+
+        self.layer_priority(a)
+            .cmp(&self.layer_priority(b))
+            .then_with(|| {
+                if use_default_preference {
+                    preference(ra).cmp(&preference(rb))
+                } else {
+                    Ordering::Equal
+                }
+            })
+            .then_with(|| vercmp(ra.version.as_deref().unwrap_or(""), rb.version.as_deref().unwrap_or("")))
+            // Prefer the later-added file when everything else ties
+            .then_with(|| a.cmp(&b))
+    }
+
+    fn recipe_matches_version(&self, id: RecipeId, pattern: &str) -> bool {
+        let Some(recipe) = self.recipes.get(&id) else {
+            return false;
+        };
+        let Some(pv) = recipe.version.as_deref() else {
+            return false;
+        };
+
+        version_matches(pattern, pv)
+            || recipe
+                .metadata
+                .get("PR")
+                .is_some_and(|pr| version_matches(pattern, &format!("{}-{}", pv, pr)))
+    }
+
+    fn layer_priority(&self, id: RecipeId) -> i32 {
+        self.recipes
+            .get(&id)
+            .map(|r| self.preferences.layer_priority(r.layer.as_deref()))
+            .unwrap_or(0)
+    }
+
+    /// Configure PREFERRED_PROVIDER / PREFERRED_VERSION / layer priorities
+    pub fn set_provider_preferences(&mut self, preferences: ProviderPreferences) {
+        self.preferences = preferences;
+    }
+
+    /// Current provider selection preferences
+    pub fn provider_preferences(&self) -> &ProviderPreferences {
+        &self.preferences
+    }
+
+    /// Report ambiguous or unsatisfiable provider configuration like BitBake's notes
+    pub fn provider_diagnostics(&self) -> Vec<ProviderNote> {
+        let mut notes = Vec::new();
+
+        let mut items: Vec<&String> = self.provider_map.keys().collect();
+        items.sort();
+        for item in items {
+            if self.preferences.preferred_provider(item).is_some() || self.name_to_recipe.contains_key(item) {
+                continue;
+            }
+            let candidates = self.provider_candidates(item);
+            if candidates.len() > 1 {
+                let providers: Vec<String> = candidates
+                    .iter()
+                    .filter_map(|id| self.recipes.get(id))
+                    .map(|r| r.name.clone())
+                    .collect();
+                notes.push(ProviderNote::MultipleProviders {
+                    item: item.clone(),
+                    selected: providers[0].clone(),
+                    providers,
+                });
+            }
+        }
+
+        let mut preferred: Vec<(&String, &String)> = self.preferences.preferred_providers.iter().collect();
+        preferred.sort();
+        for (item, provider) in preferred {
+            let provides_item = self.provider_map.contains_key(item) || self.name_to_recipe.contains_key(item);
+            if provides_item && !self.name_to_recipe.contains_key(provider) {
+                notes.push(ProviderNote::PreferredProviderMissing {
+                    item: item.clone(),
+                    provider: provider.clone(),
+                });
+            }
+        }
+
+        let mut versions: Vec<(&String, &String)> = self.preferences.preferred_versions.iter().collect();
+        versions.sort();
+        for (pn, pattern) in versions {
+            let Some(ids) = self.pn_to_recipes.get(pn) else {
+                continue;
+            };
+            if !ids.iter().any(|id| self.recipe_matches_version(*id, pattern)) {
+                let available = ids
+                    .iter()
+                    .filter_map(|id| self.recipes.get(id))
+                    .filter_map(|r| r.version.clone())
+                    .collect();
+                notes.push(ProviderNote::PreferredVersionUnavailable {
+                    pn: pn.clone(),
+                    version: pattern.clone(),
+                    available,
+                });
+            }
+        }
+
+        notes
     }
 
     /// Get all recipes that provide a capability
@@ -556,6 +766,24 @@ mod tests {
         assert_eq!(graph.find_recipe("nonexistent"), None);
     }
 
+    #[test]
+    fn test_skipped_recipe() {
+        let mut graph = RecipeGraph::new();
+        let old = graph.add_recipe("openssl");
+        graph.get_recipe_mut(old).unwrap().version = Some("1.1".to_string());
+        let new = graph.add_recipe("openssl");
+        graph.get_recipe_mut(new).unwrap().version = Some("3.0".to_string());
+        let kernel = graph.add_recipe("linux-custom");
+
+        graph.skip_recipe(new, "incompatible license");
+        graph.skip_recipe(kernel, "incompatible with machine");
+        assert_eq!(graph.find_recipe("openssl"), Some(old));
+        assert_eq!(graph.find_recipe("linux-custom"), None);
+        assert_eq!(graph.skip_reason(new), Some("incompatible license"));
+        assert_eq!(graph.skip_reason(old), None);
+        assert_eq!(graph.skipped_recipes().count(), 2);
+    }
+
     #[test]
     fn test_add_task() {
         let mut graph = RecipeGraph::new();
@@ -715,4 +943,64 @@ mod tests {
         let not_found = graph.find_task(recipe_id, "do_nonexistent");
         assert_eq!(not_found, None);
     }
+
+    #[test]
+    fn test_preferred_provider() {
+        let mut graph = RecipeGraph::new();
+        let glibc = graph.add_recipe("glibc");
+        let musl = graph.add_recipe("musl");
+        graph.register_provider(glibc, "virtual/libc");
+        graph.register_provider(musl, "virtual/libc");
+
+        assert_eq!(graph.resolve_provider("virtual/libc"), Some(glibc));
+        let notes = graph.provider_diagnostics();
+        assert_eq!(notes.len(), 1);
+        assert!(matches!(&notes[0], ProviderNote::MultipleProviders { item, .. } if item == "virtual/libc"));
+
+        let mut prefs = ProviderPreferences::new();
+        prefs.preferred_providers.insert("virtual/libc".to_string(), "musl".to_string());
+        graph.set_provider_preferences(prefs);
+        assert_eq!(graph.resolve_provider("virtual/libc"), Some(musl));
+        assert!(graph.provider_diagnostics().is_empty());
+    }
+
+    #[test]
+    fn test_layer_priority_selection() {
+        let mut graph = RecipeGraph::new();
+        let core = graph.add_recipe("linux-yocto");
+        let bsp = graph.add_recipe("linux-bsp");
+        graph.get_recipe_mut(core).unwrap().layer = Some("core".to_string());
+        graph.get_recipe_mut(bsp).unwrap().layer = Some("bsp".to_string());
+        graph.register_provider(core, "virtual/kernel");
+        graph.register_provider(bsp, "virtual/kernel");
+
+        let mut prefs = ProviderPreferences::new();
+        prefs.set_layer_priority("core", 5);
+        prefs.set_layer_priority("bsp", 10);
+        graph.set_provider_preferences(prefs);
+        assert_eq!(graph.resolve_provider("virtual/kernel"), Some(bsp));
+    }
+
+    #[test]
+    fn test_preferred_version_unavailable() {
+        let mut graph = RecipeGraph::new();
+        let v1 = graph.add_recipe("busybox");
+        graph.get_recipe_mut(v1).unwrap().version = Some("1.36.1".to_string());
+        let v2 = graph.add_recipe("busybox");
+        graph.get_recipe_mut(v2).unwrap().version = Some("1.35.0".to_string());
+
+        let mut prefs = ProviderPreferences::new();
+        prefs.preferred_versions.insert("busybox".to_string(), "1.30%".to_string());
+        graph.set_provider_preferences(prefs);
+
+        // Falls back to the latest version and reports the mismatch
+        assert_eq!(graph.find_recipe("busybox"), Some(v1));
+        assert_eq!(graph.find_recipe_versions("busybox"), vec![v1, v2]);
+        let notes = graph.provider_diagnostics();
+        assert!(matches!(
+            &notes[0],
+            ProviderNote::PreferredVersionUnavailable { pn, available, .. }
+                if pn == "busybox" && available.len() == 2
+        ));
+    }
 }
@@ -95,6 +95,11 @@ impl SimpleResolver {
         Self { variables }
     }
 
+    /// Create a resolver over the given variables, without built-in defaults
+    pub fn from_variables(variables: HashMap<String, String>) -> Self {
+        Self { variables }
+    }
+
     /// Add or override a variable
     pub fn set(&mut self, name: String, value: String) {
         self.variables.insert(name, value);
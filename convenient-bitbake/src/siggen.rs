@@ -0,0 +1,717 @@
+// BitBake task signatures
+// Mirrors bb.siggen's basic hash generator so tasks get the hashes BitBake
+// gives them and sstate archives are shared under the names BitBake uses.
+// A task's basehash covers its function and every variable it depends on, as
+// bb.data.generate_dependencies finds them: ${VAR} references, d.getVar()
+// calls, functions a task runs, exported variables for shell functions,
+// [vardeps]/[vardepsexclude]/[vardepvalue]/[vardepvalueexclude] and, with
+// BB_SIGNATURE_EXCLUDE_FLAGS set, the remaining flags. BB_BASEHASH_IGNORE_VARS
+// are left out. The taskhash adds the unihashes of the tasks it depends on;
+// without a hash equivalence server the unihash is the taskhash.
+// [file-checksums] need the fetcher and are not hashed.
+
+use crate::datastore::DataStore;
+use crate::recipe_graph::TaskId;
+use crate::task_graph::TaskGraph;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt::Write as _;
+
+/// Variables an sstate archive name is made of
+pub const SSTATE_NAME_VARS: &[&str] = &[
+    "SSTATE_PKGSPEC",
+    "PN",
+    "PV",
+    "PR",
+    "PACKAGE_ARCH",
+    "SSTATE_PKGARCH",
+];
+
+/// Hashes of one task
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskHashes {
+    /// The task's function and the variables it depends on
+    pub basehash: String,
+    /// basehash plus the unihashes of the tasks it depends on
+    pub taskhash: String,
+    /// Hash the task's sstate archive is named after
+    pub unihash: String,
+}
+
+impl TaskHashes {
+    /// Task environment variables BitBake sets for the hashes
+    pub fn env_vars(&self) -> HashMap<String, String> {
+        HashMap::from([
+            ("BB_BASEHASH".to_string(), self.basehash.clone()),
+            ("BB_TASKHASH".to_string(), self.taskhash.clone()),
+            ("BB_UNIHASH".to_string(), self.unihash.clone()),
+        ])
+    }
+}
+
+/// Values of the variables naming a recipe's sstate archives, as far as they expand
+pub fn sstate_name_vars(d: &DataStore) -> HashMap<String, String> {
+    SSTATE_NAME_VARS
+        .iter()
+        .filter_map(|name| {
+            let value = d.get_var(name)?;
+            (!value.is_empty() && !value.contains("${")).then(|| (name.to_string(), value))
+        })
+        .collect()
+}
+
+/// Variables, executed functions and bb.utils.contains() checks found in metadata
+#[derive(Debug, Default)]
+struct References {
+    references: BTreeSet<String>,
+    execs: BTreeSet<String>,
+    contains: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl References {
+    fn merge(&mut self, other: References) {
+        self.references.extend(other.references);
+        self.execs.extend(other.execs);
+        for (var, items) in other.contains {
+            self.contains.entry(var).or_default().extend(items);
+        }
+    }
+}
+
+/// Computes basehashes from a parsed recipe
+pub struct SignatureGenerator<'a> {
+    d: &'a DataStore,
+    /// Every variable, except internal `__` ones
+    keys: HashSet<String>,
+    /// Exported variables, dependencies of every shell function
+    shell_deps: BTreeSet<String>,
+    /// BB_SIGNATURE_EXCLUDE_FLAGS; when unset, flags are not hashed
+    exclude_flags: Option<HashSet<String>>,
+    /// BB_BASEHASH_IGNORE_VARS
+    ignored: HashSet<String>,
+    /// Direct dependencies and hashed value per variable
+    gendeps: HashMap<String, (BTreeSet<String>, String)>,
+}
+
+impl<'a> SignatureGenerator<'a> {
+    /// Generator for the recipe parsed into `d`
+    pub fn new(d: &'a DataStore) -> Self {
+        let keys: HashSet<String> = d
+            .keys()
+            .into_iter()
+            .filter(|k| !k.starts_with("__"))
+            .collect();
+        let flag_set = |key: &str, flag: &str| d.get_var_flag(key, flag).is_some_and(|v| !v.is_empty());
+        let shell_deps = keys
+            .iter()
+            .filter(|k| flag_set(k, "export") && !flag_set(k, "unexport"))
+            .cloned()
+            .collect();
+        let words = |name: &str| -> HashSet<String> {
+            d.get_var(name)
+                .unwrap_or_default()
+                .split_whitespace()
+                .map(str::to_string)
+                .collect()
+        };
+
+        Self {
+            d,
+            shell_deps,
+            exclude_flags: d
+                .contains("BB_SIGNATURE_EXCLUDE_FLAGS")
+                .then(|| words("BB_SIGNATURE_EXCLUDE_FLAGS")),
+            ignored: words("BB_BASEHASH_IGNORE_VARS"),
+            keys,
+            gendeps: HashMap::new(),
+        }
+    }
+
+    /// basehash of a task (bb.data.generate_dependency_hash): its value, then
+    /// every variable it depends on in sorted order, each name followed by its value
+    pub fn basehash(&mut self, task: &str) -> String {
+        let task = task_function(task);
+        let mut data = self.dependencies(&task).1;
+        for dep in self.task_dependencies(&task) {
+            let value = self.dependencies(&dep).1;
+            data.push_str(&dep);
+            data.push_str(&value);
+        }
+        sha256_hex(&data)
+    }
+
+    /// Variables a task depends on, directly or through other variables
+    pub fn task_dependencies(&mut self, task: &str) -> BTreeSet<String> {
+        let task = task_function(task);
+        let mut seen = BTreeSet::new();
+        let mut pending: Vec<String> = self.dependencies(&task).0.into_iter().collect();
+        while let Some(dep) = pending.pop() {
+            if seen.insert(dep.clone()) {
+                pending.extend(
+                    self.dependencies(&dep)
+                        .0
+                        .into_iter()
+                        .filter(|d| !seen.contains(d)),
+                );
+            }
+        }
+        seen
+    }
+
+    fn dependencies(&mut self, key: &str) -> (BTreeSet<String>, String) {
+        if let Some(cached) = self.gendeps.get(key) {
+            return cached.clone();
+        }
+        let built = self.build_dependencies(key);
+        self.gendeps.insert(key.to_string(), built.clone());
+        built
+    }
+
+    /// Direct dependencies of a variable and the value hashed for it
+    /// (bb.data.build_dependencies)
+    fn build_dependencies(&self, key: &str) -> (BTreeSet<String>, String) {
+        let d = self.d;
+
+        // VAR[flag], added for flags outside BB_SIGNATURE_EXCLUDE_FLAGS
+        if let Some((var, flag)) = key.strip_suffix(']').and_then(|k| k.split_once('[')) {
+            if flag == "vardepvalueexclude" {
+                return (BTreeSet::new(), String::new());
+            }
+            let value = d.get_var_flags(var).remove(flag).unwrap_or_default();
+            let refs = expansion_references(&value);
+            let mut deps: BTreeSet<String> = refs.references;
+            deps.extend(self.known(refs.execs));
+            deps.retain(|dep| !self.ignored.contains(dep));
+            return (deps, value);
+        }
+
+        let flags = d.get_var_flags(key);
+        let flag = |name: &str| flags.get(name).map(|v| d.expand(v));
+        let is_set = |name: &str| flags.get(name).is_some_and(|v| !v.is_empty());
+        let exclusions: Vec<String> = flag("vardepsexclude")
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(str::to_string)
+            .collect();
+
+        let mut deps = BTreeSet::new();
+        let mut value = if let Some(value) = flag("vardepvalue") {
+            value
+        } else if is_set("func") {
+            let body = d.get_var_unexpanded(key).unwrap_or_default();
+            let value = if is_set("python") {
+                let refs = python_references(&body);
+                deps.extend(refs.references);
+                deps.extend(self.known(refs.execs));
+                self.handle_contains(body, &refs.contains, &exclusions)
+            } else {
+                let refs = expansion_references(&body);
+                deps.extend(self.shell_deps.iter().cloned());
+                deps.extend(refs.references);
+                deps.extend(self.known(refs.execs));
+                deps.extend(self.known(shell_execs(&d.expand(&body))));
+                let value = self.handle_contains(body, &refs.contains, &exclusions);
+                self.handle_remove(key, value, &mut deps, &exclusions)
+            };
+            for name in ["prefuncs", "postfuncs", "exports"] {
+                deps.extend(flag(name).unwrap_or_default().split_whitespace().map(str::to_string));
+            }
+            value
+        } else {
+            let body = d.get_var_unexpanded(key).unwrap_or_default();
+            let refs = expansion_references(&body);
+            deps.extend(refs.references);
+            deps.extend(self.known(refs.execs));
+            let value = self.handle_contains(body, &refs.contains, &exclusions);
+            self.handle_remove(key, value, &mut deps, &exclusions)
+        };
+
+        if let Some(exclude) = flag("vardepvalueexclude") {
+            for excluded in exclude.split('|').filter(|e| !e.is_empty()) {
+                value = value.replace(excluded, "");
+            }
+        }
+
+        if let Some(exclude_flags) = &self.exclude_flags {
+            deps.extend(
+                flags
+                    .keys()
+                    .filter(|f| !exclude_flags.contains(*f))
+                    .map(|f| format!("{}[{}]", key, f)),
+            );
+        }
+
+        deps.extend(flag("vardeps").unwrap_or_default().split_whitespace().map(str::to_string));
+        deps.retain(|dep| !exclusions.contains(dep) && !self.ignored.contains(dep));
+        (deps, value)
+    }
+
+    /// Names that are variables of the recipe
+    fn known(&self, names: BTreeSet<String>) -> impl Iterator<Item = String> + '_ {
+        names.into_iter().filter(|name| self.keys.contains(name))
+    }
+
+    /// Record whether each bb.utils.contains() item is currently set
+    fn handle_contains(
+        &self,
+        mut value: String,
+        contains: &BTreeMap<String, BTreeSet<String>>,
+        exclusions: &[String],
+    ) -> String {
+        for (var, items) in contains {
+            if exclusions.contains(var) || self.ignored.contains(var) {
+                continue;
+            }
+            let current = self.d.get_var(var).unwrap_or_default();
+            let words: Vec<&str> = current.split_whitespace().collect();
+            for item in items {
+                let set = item.split_whitespace().all(|word| words.contains(&word));
+                let _ = write!(value, "\n{}{{{}}} = {}", var, item, if set { "Set" } else { "Unset" });
+            }
+        }
+        value
+    }
+
+    /// Record the :remove operations applied to a variable
+    fn handle_remove(
+        &self,
+        key: &str,
+        mut value: String,
+        deps: &mut BTreeSet<String>,
+        exclusions: &[String],
+    ) -> String {
+        let removes: BTreeSet<String> = self.d.removes(key).into_iter().collect();
+        for remove in removes {
+            let refs = expansion_references(&remove);
+            let _ = write!(value, "\n_remove of {}", remove);
+            deps.extend(refs.references);
+            value = self.handle_contains(value, &refs.contains, exclusions);
+        }
+        value
+    }
+}
+
+/// taskhash (bb.siggen get_taskhash): the basehash, the unihashes of the
+/// tasks it depends on ordered by their `<pn>:<task>`, then the taint
+pub fn taskhash(basehash: &str, dependencies: &[(String, String)], taint: Option<&str>) -> String {
+    let mut dependencies = dependencies.to_vec();
+    dependencies.sort();
+
+    let mut data = basehash.to_string();
+    for (_, unihash) in &dependencies {
+        data.push_str(unihash);
+    }
+    if let Some(taint) = taint {
+        data.push_str(taint);
+    }
+    sha256_hex(&data)
+}
+
+/// Hashes of every task in `graph` that has a basehash, dependencies first
+///
+/// `[nostamp]` tasks are tainted with a random value, as BitBake does, so
+/// their hash never matches an earlier one.
+pub fn task_hashes(graph: &TaskGraph, basehashes: &HashMap<TaskId, String>) -> HashMap<TaskId, TaskHashes> {
+    let mut hashes: HashMap<TaskId, TaskHashes> = HashMap::new();
+
+    for task_id in &graph.execution_order {
+        let (Some(task), Some(basehash)) = (graph.tasks.get(task_id), basehashes.get(task_id)) else {
+            continue;
+        };
+
+        let dependencies: Vec<(String, String)> = task
+            .depends_on
+            .iter()
+            .filter_map(|dep| {
+                let dep_task = graph.tasks.get(dep)?;
+                let unihash = &hashes.get(dep)?.unihash;
+                Some((
+                    format!("{}:{}", dep_task.recipe_name, task_function(&dep_task.task_name)),
+                    unihash.clone(),
+                ))
+            })
+            .collect();
+        let taint = task.nostamp.then(|| uuid::Uuid::new_v4().to_string());
+
+        let taskhash = taskhash(basehash, &dependencies, taint.as_deref());
+        hashes.insert(
+            *task_id,
+            TaskHashes {
+                basehash: basehash.clone(),
+                unihash: taskhash.clone(),
+                taskhash,
+            },
+        );
+    }
+
+    hashes
+}
+
+/// Task function name (`compile` -> `do_compile`)
+pub fn task_function(task: &str) -> String {
+    if task.starts_with("do_") {
+        task.to_string()
+    } else {
+        format!("do_{}", task)
+    }
+}
+
+fn sha256_hex(data: &str) -> String {
+    Sha256::digest(data.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// ${VAR} references and what the ${@...} expressions use (bb.data_smart expandWithRefs)
+fn expansion_references(value: &str) -> References {
+    let mut refs = References::default();
+    let mut rest = value;
+
+    while let Some(pos) = rest.find("${") {
+        let after = &rest[pos + 2..];
+        let Some(end) = closing_brace(after) else {
+            break;
+        };
+        let inner = &after[..end];
+
+        if let Some(code) = inner.strip_prefix('@') {
+            refs.merge(python_references(code));
+        } else if inner.contains("${") {
+            refs.merge(expansion_references(inner));
+        } else if !inner.is_empty()
+            && inner
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_+./~:".contains(c))
+        {
+            refs.references.insert(inner.to_string());
+        }
+        rest = &after[end + 1..];
+    }
+
+    refs
+}
+
+/// Variables read and functions called by python code (bb.codeparser.PythonParser)
+fn python_references(code: &str) -> References {
+    const GETVARS: &[&str] = &[".getVar", ".appendVar", ".prependVar", "oe.utils.conditional"];
+    const GETVARFLAGS: &[&str] = &[".getVarFlag", ".appendVarFlag", ".prependVarFlag"];
+    const CONTAINS: &[&str] = &["bb.utils.contains", "base_contains"];
+    const CONTAINS_ANY: &[&str] = &["bb.utils.contains_any", "bb.utils.filter"];
+    const EXECS: &[&str] = &["bb.build.exec_func", "bb.build.exec_task"];
+
+    let mut refs = References::default();
+    for (name, args) in python_calls(code) {
+        let literal = |i: usize| args.get(i).cloned().flatten();
+
+        if GETVARS.iter().chain(GETVARFLAGS).any(|g| name.ends_with(g))
+            || CONTAINS.contains(&name.as_str())
+            || CONTAINS_ANY.contains(&name.as_str())
+        {
+            let Some(var) = literal(0) else {
+                continue;
+            };
+            if CONTAINS.contains(&name.as_str()) {
+                if let Some(item) = literal(1) {
+                    refs.contains.entry(var).or_default().insert(item);
+                }
+            } else if CONTAINS_ANY.contains(&name.as_str()) {
+                if let Some(items) = literal(1) {
+                    refs.contains
+                        .entry(var)
+                        .or_default()
+                        .extend(items.split_whitespace().map(str::to_string));
+                }
+            } else if GETVARFLAGS.iter().any(|g| name.ends_with(g)) {
+                if let Some(flag) = literal(1) {
+                    refs.references.insert(format!("{}[{}]", var, flag));
+                }
+            } else {
+                refs.references.insert(var);
+            }
+        } else if name.ends_with(".expand") {
+            if let Some(value) = literal(0) {
+                refs.merge(expansion_references(&value));
+            }
+        } else if EXECS.contains(&name.as_str()) {
+            if let Some(function) = literal(0) {
+                refs.execs.insert(function);
+            }
+        } else {
+            refs.execs.insert(name);
+        }
+    }
+
+    refs
+}
+
+/// Calls in python code: the dotted name called and its leading arguments,
+/// Some(..) for string literals
+fn python_calls(code: &str) -> Vec<(String, Vec<Option<String>>)> {
+    let bytes = code.as_bytes();
+    let is_ident = |b: u8| b.is_ascii_alphanumeric() || b == b'_';
+    let mut calls = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let starts_name = (bytes[i].is_ascii_alphabetic() || bytes[i] == b'_')
+            && (i == 0 || !(is_ident(bytes[i - 1]) || bytes[i - 1] == b'.'));
+        if !starts_name {
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        while i < bytes.len() && (is_ident(bytes[i]) || bytes[i] == b'.') {
+            i += 1;
+        }
+        let name = code[start..i].trim_end_matches('.');
+        let mut j = i;
+        while j < bytes.len() && (bytes[j] == b' ' || bytes[j] == b'\t') {
+            j += 1;
+        }
+        if j < bytes.len() && bytes[j] == b'(' {
+            calls.push((name.to_string(), string_arguments(&code[j + 1..])));
+        }
+    }
+
+    calls
+}
+
+/// Leading arguments of a call: string literals, then None for the first
+/// argument that isn't one
+fn string_arguments(args: &str) -> Vec<Option<String>> {
+    let mut values = Vec::new();
+    let mut rest = args.trim_start();
+
+    loop {
+        let Some(quote) = rest.chars().next().filter(|c| *c == '"' || *c == '\'') else {
+            values.push(None);
+            break;
+        };
+        let Some(end) = rest[1..].find(quote) else {
+            values.push(None);
+            break;
+        };
+        values.push(Some(rest[1..end + 1].to_string()));
+
+        rest = rest[end + 2..].trim_start();
+        match rest.strip_prefix(',') {
+            Some(next) => rest = next.trim_start(),
+            None => break,
+        }
+    }
+
+    values
+}
+
+/// Commands a shell script runs, except functions it defines itself
+fn shell_execs(script: &str) -> BTreeSet<String> {
+    const KEYWORDS: &[&str] = &[
+        "if", "then", "else", "elif", "fi", "do", "done", "while", "until", "esac", "!", "time",
+    ];
+    let is_name = |word: &str| {
+        word.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && word.chars().all(|c| c.is_ascii_alphanumeric() || "_-.+".contains(c))
+    };
+
+    let mut execs = BTreeSet::new();
+    let mut defined = BTreeSet::new();
+    for line in script.lines() {
+        let line = line.trim();
+        if line.starts_with('#') {
+            continue;
+        }
+
+        let definition = line.strip_prefix("function ").unwrap_or(line);
+        if let Some((name, _)) = definition.split_once("()")
+            && is_name(name.trim())
+        {
+            defined.insert(name.trim().to_string());
+        }
+
+        for segment in line.split([';', '|', '&', '(', ')', '`', '{', '}']) {
+            let command = segment
+                .split_whitespace()
+                .find(|word| !KEYWORDS.contains(word) && !is_assignment(word));
+            // for/case words are not commands
+            if let Some(command) = command.filter(|c| is_name(c) && !matches!(*c, "for" | "case")) {
+                execs.insert(command.to_string());
+            }
+        }
+    }
+
+    execs.retain(|name| !defined.contains(name));
+    execs
+}
+
+fn is_assignment(word: &str) -> bool {
+    word.split_once('=').is_some_and(|(name, _)| {
+        !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+fn closing_brace(s: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, c) in s.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' if depth == 0 => return Some(i),
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recipe_graph::RecipeId;
+    use std::path::Path;
+
+    fn parse(content: &str) -> DataStore {
+        let mut d = DataStore::new();
+        d.parse_content(content, Path::new("/layer/recipe.bb")).unwrap();
+        d
+    }
+
+    #[test]
+    fn test_basehash_covers_dependencies_in_order() {
+        let d = parse(
+            r#"
+A = "a ${B}"
+B = "b"
+UNUSED = "x"
+export CC = "gcc"
+do_compile() {
+	echo ${A}
+	helper
+}
+helper() {
+	:
+}
+"#,
+        );
+        let mut generator = SignatureGenerator::new(&d);
+
+        let deps: Vec<String> = generator.task_dependencies("do_compile").into_iter().collect();
+        assert_eq!(deps, vec!["A", "B", "CC", "helper"]);
+
+        let expected = format!(
+            "{}A{}B{}CC{}helper{}",
+            "\techo ${A}\n\thelper", "a ${B}", "b", "gcc", "\t:"
+        );
+        assert_eq!(generator.basehash("compile"), sha256_hex(&expected));
+    }
+
+    #[test]
+    fn test_basehash_vardeps_and_ignored_vars() {
+        let d = parse(
+            r#"
+BB_BASEHASH_IGNORE_VARS = "DATETIME"
+DATETIME = "20240101"
+STAMP = "${DATETIME}"
+EXTRA = "1"
+do_install() {
+	echo ${STAMP} ${EXTRA} ${MACHINE}
+}
+do_install[vardeps] += "LATE"
+do_install[vardepsexclude] = "EXTRA"
+LATE = "late"
+"#,
+        );
+        let mut generator = SignatureGenerator::new(&d);
+
+        let deps: Vec<String> = generator.task_dependencies("do_install").into_iter().collect();
+        assert_eq!(deps, vec!["LATE", "MACHINE", "STAMP"]);
+    }
+
+    #[test]
+    fn test_python_references_and_contains() {
+        let refs = python_references(
+            r#"
+    if bb.utils.contains('DISTRO_FEATURES', 'x11 wayland', True, False, d):
+        d.getVar("PN")
+    d.getVarFlag('do_compile', 'cleandirs')
+    bb.build.exec_func('do_helper', d)
+    oe_helper(d)
+"#,
+        );
+        assert_eq!(
+            refs.references.into_iter().collect::<Vec<_>>(),
+            vec!["PN", "do_compile[cleandirs]"]
+        );
+        assert!(refs.execs.contains("do_helper"));
+        assert!(refs.execs.contains("oe_helper"));
+        assert!(refs.contains["DISTRO_FEATURES"].contains("x11 wayland"));
+
+        let d = parse(
+            r#"
+DISTRO_FEATURES = "x11"
+FEATURE = "${@bb.utils.contains('DISTRO_FEATURES', 'x11', 'gui', '', d)}"
+do_configure() {
+	echo ${FEATURE}
+}
+"#,
+        );
+        let mut generator = SignatureGenerator::new(&d);
+        assert_eq!(
+            generator.dependencies("FEATURE").1,
+            "${@bb.utils.contains('DISTRO_FEATURES', 'x11', 'gui', '', d)}\nDISTRO_FEATURES{x11} = Set"
+        );
+        assert!(!generator.task_dependencies("do_configure").contains("DISTRO_FEATURES"));
+    }
+
+    #[test]
+    fn test_shell_execs() {
+        let execs = shell_execs(
+            "local_helper() {\n\t:\n}\nCFLAGS=-O2 oe_runmake install\nif [ -e x ]; then bbnote done; fi\nlocal_helper | tee log\n",
+        );
+        assert_eq!(
+            execs.into_iter().collect::<Vec<_>>(),
+            vec!["bbnote", "oe_runmake", "tee"]
+        );
+    }
+
+    #[test]
+    fn test_task_hashes_chain_dependencies() {
+        let task = |id: u32, name: &str, depends_on: Vec<TaskId>| crate::ExecutableTask {
+            task_id: TaskId(id),
+            recipe_id: RecipeId(0),
+            task_name: name.to_string(),
+            recipe_name: "zlib".to_string(),
+            depends_on,
+            dependents: Vec::new(),
+            noexec: false,
+            nostamp: false,
+            flags: HashMap::new(),
+        };
+        let graph = TaskGraph {
+            tasks: [
+                (TaskId(0), task(0, "do_compile", vec![])),
+                (TaskId(1), task(1, "do_install", vec![TaskId(0)])),
+            ]
+            .into_iter()
+            .collect(),
+            execution_order: vec![TaskId(0), TaskId(1)],
+            root_tasks: vec![TaskId(0)],
+            leaf_tasks: vec![TaskId(1)],
+        };
+        let basehashes: HashMap<TaskId, String> = [
+            (TaskId(0), "b0".to_string()),
+            (TaskId(1), "b1".to_string()),
+        ]
+        .into_iter()
+        .collect();
+
+        let hashes = task_hashes(&graph, &basehashes);
+        let compile = &hashes[&TaskId(0)];
+        assert_eq!(compile.taskhash, sha256_hex("b0"));
+        assert_eq!(compile.unihash, compile.taskhash);
+        assert_eq!(
+            hashes[&TaskId(1)].taskhash,
+            sha256_hex(&format!("b1{}", compile.unihash))
+        );
+    }
+}
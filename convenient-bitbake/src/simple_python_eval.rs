@@ -175,10 +175,9 @@ impl SimplePythonEvaluator {
         // Look up the variable
         let var_value = self.variables.get(var_name)?;
 
-        // Check if search_item is in var_value (space-separated)
-        let contains = var_value
-            .split_whitespace()
-            .any(|item| item == search_item);
+        // Every search item must be in var_value, as in bb.utils.contains
+        let search_items: Vec<&str> = search_item.split_whitespace().collect();
+        let contains = crate::python_lib::contains(Some(var_value), &search_items);
 
         let result = if contains {
             true_value.clone()
@@ -212,14 +211,9 @@ impl SimplePythonEvaluator {
         // Look up the variable
         let var_value = self.variables.get(var_name)?;
 
-        // Filter items that exist in var_value
-        let var_items: Vec<&str> = var_value.split_whitespace().collect();
-        let filtered: Vec<&str> = items
-            .split_whitespace()
-            .filter(|item| var_items.contains(item))
-            .collect();
-
-        let result = filtered.join(" ");
+        // Items that exist in var_value, sorted like bb.utils.filter
+        let items: Vec<&str> = items.split_whitespace().collect();
+        let result = crate::python_lib::filter(Some(var_value), &items);
         debug!("  var_value={}, filtered={}", var_value, result);
 
         Some(result)
@@ -252,10 +246,8 @@ impl SimplePythonEvaluator {
         let var_value = self.variables.get(var_name)?;
 
         // Check if ANY search item is in var_value
-        let var_items: Vec<&str> = var_value.split_whitespace().collect();
-        let contains_any = search_items
-            .split_whitespace()
-            .any(|item| var_items.contains(&item));
+        let search_items: Vec<&str> = search_items.split_whitespace().collect();
+        let contains_any = crate::python_lib::contains_any(Some(var_value), &search_items);
 
         let result = if contains_any {
             true_value.clone()
@@ -1723,7 +1715,7 @@ mod tests {
         let eval = create_test_evaluator();
 
         let result = eval.evaluate("${@bb.utils.filter('DISTRO_FEATURES', 'systemd ipv6 bluetooth', d)}");
-        assert_eq!(result, Some("systemd ipv6".to_string()));
+        assert_eq!(result, Some("ipv6 systemd".to_string()));
     }
 
     #[test]
@@ -1739,7 +1731,7 @@ mod tests {
         let eval = create_test_evaluator();
 
         let result = eval.evaluate("${@bb.utils.filter('DISTRO_FEATURES', 'systemd pam', d)}");
-        assert_eq!(result, Some("systemd pam".to_string()));
+        assert_eq!(result, Some("pam systemd".to_string()));
     }
 
     #[test]
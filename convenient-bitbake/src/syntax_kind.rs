@@ -113,6 +113,8 @@ pub enum SyntaxKind {
     INCLUDE_STMT,
     REQUIRE_STMT,
     EXPORT_STMT,
+    /// addtask, deltask, addhandler, EXPORT_FUNCTIONS, unset, inherit_defer, addpylib
+    DIRECTIVE_STMT,
 
     // Functions
     SHELL_FUNCTION,
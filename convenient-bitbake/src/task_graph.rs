@@ -2,9 +2,13 @@
 //!
 //! Converts BitBake recipe dependencies into a concrete task execution graph
 
-use crate::recipe_graph::{RecipeGraph, RecipeId, TaskId};
+use crate::multiconfig::McDependency;
+use crate::recipe_graph::{RecipeGraph, RecipeId, TaskId, TaskNode};
 use std::collections::{HashMap, HashSet, VecDeque};
 
+/// Flags that add dependencies on tasks of other recipes
+const INTER_RECIPE_FLAGS: &[&str] = &["deptask", "rdeptask", "recrdeptask"];
+
 /// A concrete task that can be executed
 #[derive(Debug, Clone)]
 pub struct ExecutableTask {
@@ -20,6 +24,12 @@ pub struct ExecutableTask {
     pub depends_on: Vec<TaskId>,
     /// Tasks that depend on this one
     pub dependents: Vec<TaskId>,
+    /// `[noexec]`: nothing to run, the task only orders its dependencies
+    pub noexec: bool,
+    /// `[nostamp]`: never considered up to date, always re-run
+    pub nostamp: bool,
+    /// Task flags from the recipe graph (dirs, cleandirs, lockfiles, ...)
+    pub flags: HashMap<String, String>,
 }
 
 /// Complete task execution graph
@@ -89,6 +99,66 @@ impl TaskGraph {
     }
 }
 
+/// A task qualified by its multiconfig ("" is the default configuration)
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct McTaskId {
+    /// Multiconfig name
+    pub mc: String,
+    /// Task within that configuration's recipe graph
+    pub task_id: TaskId,
+}
+
+impl McTaskId {
+    /// Create a multiconfig-qualified task ID
+    pub fn new(mc: impl Into<String>, task_id: TaskId) -> Self {
+        Self {
+            mc: mc.into(),
+            task_id,
+        }
+    }
+}
+
+/// Task graph spanning several multiconfigs, joined by mcdepends edges
+#[derive(Debug, Clone)]
+pub struct MultiConfigTaskGraph {
+    /// Per-configuration task graphs (only the tasks that are needed)
+    pub graphs: HashMap<String, TaskGraph>,
+    /// Cross-configuration dependencies from `mcdepends` flags
+    pub mc_depends: HashMap<McTaskId, Vec<McTaskId>>,
+    /// Topological order across all configurations (dependencies first)
+    pub execution_order: Vec<McTaskId>,
+}
+
+impl MultiConfigTaskGraph {
+    /// Get a task by its qualified ID
+    pub fn get_task(&self, id: &McTaskId) -> Option<&ExecutableTask> {
+        self.graphs.get(&id.mc).and_then(|g| g.get_task(id.task_id))
+    }
+
+    /// Direct dependencies of a task, including cross-configuration ones
+    pub fn depends_on(&self, id: &McTaskId) -> Vec<McTaskId> {
+        let mut deps: Vec<McTaskId> = self
+            .get_task(id)
+            .map(|task| {
+                task.depends_on
+                    .iter()
+                    .filter(|dep| self.graphs[&id.mc].tasks.contains_key(dep))
+                    .map(|&dep| McTaskId::new(id.mc.clone(), dep))
+                    .collect()
+            })
+            .unwrap_or_default();
+        if let Some(cross) = self.mc_depends.get(id) {
+            deps.extend(cross.iter().cloned());
+        }
+        deps
+    }
+
+    /// Total number of tasks over all configurations
+    pub fn task_count(&self) -> usize {
+        self.graphs.values().map(|g| g.tasks.len()).sum()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct TaskGraphStats {
     pub total_tasks: usize,
@@ -125,6 +195,9 @@ impl TaskGraphBuilder {
                     recipe_name: recipe.name.clone(),
                     depends_on: Vec::new(),
                     dependents: Vec::new(),
+                    noexec: flag_is_set(task_node, "noexec"),
+                    nostamp: flag_is_set(task_node, "nostamp"),
+                    flags: task_node.flags.clone(),
                 };
                 tasks.insert(task_node.id, executable);
             }
@@ -132,46 +205,8 @@ impl TaskGraphBuilder {
 
         // Phase 2: Resolve dependencies
         for recipe in self.recipe_graph.recipes() {
-            let recipe_tasks = self.recipe_graph.get_recipe_tasks(recipe.id);
-
-            for task_node in recipe_tasks {
-                let mut deps = Vec::new();
-
-                // Intra-recipe dependencies (after/before)
-                deps.extend(task_node.after.iter().copied());
-
-                // Inter-recipe task dependencies
-                for task_dep in &task_node.task_depends {
-                    if let Some(dep_task_id) = task_dep.task_id {
-                        deps.push(dep_task_id);
-                    } else {
-                        // Resolve by name
-                        if let Some(resolved_id) = self.recipe_graph.find_task(
-                            task_dep.recipe_id,
-                            &task_dep.task_name,
-                        ) {
-                            deps.push(resolved_id);
-                        }
-                    }
-                }
-
-                // Recipe-level dependencies (e.g., DEPENDS)
-                // For each dependent recipe, add dependency on do_populate_sysroot
-                for &dep_recipe_id in &recipe.depends {
-                    if let Some(sysroot_task) =
-                        self.recipe_graph.find_task(dep_recipe_id, "do_populate_sysroot")
-                    {
-                        // Only add if this task needs it (compile/install tasks)
-                        if task_node.name.contains("compile")
-                            || task_node.name.contains("install")
-                            || task_node.name.contains("configure")
-                        {
-                            deps.push(sysroot_task);
-                        }
-                    }
-                }
-
-                task_dependencies.insert(task_node.id, deps);
+            for task_node in self.recipe_graph.get_recipe_tasks(recipe.id) {
+                task_dependencies.insert(task_node.id, self.task_dependencies(task_node));
             }
         }
 
@@ -237,6 +272,178 @@ impl TaskGraphBuilder {
         let mut required_tasks = HashSet::new();
         self.collect_dependencies(target_task, &mut required_tasks);
 
+        self.build_subgraph(&required_tasks, vec![target_task])
+    }
+
+    /// Build the task graph for a target in one multiconfig plus everything
+    /// it pulls in from other multiconfigs through `do_<task>[mcdepends]`
+    ///
+    /// `recipe_graphs` holds one recipe graph per configuration, keyed by
+    /// multiconfig name ("" for the default configuration).
+    pub fn build_multiconfig(
+        recipe_graphs: &HashMap<String, RecipeGraph>,
+        mc: &str,
+        target_task: TaskId,
+    ) -> Result<MultiConfigTaskGraph, String> {
+        let builders: HashMap<&str, TaskGraphBuilder> = recipe_graphs
+            .iter()
+            .map(|(name, graph)| (name.as_str(), TaskGraphBuilder::new(graph.clone())))
+            .collect();
+
+        let mut required: HashMap<String, HashSet<TaskId>> = HashMap::new();
+        let mut leaves: HashMap<String, Vec<TaskId>> = HashMap::new();
+        let mut mc_depends: HashMap<McTaskId, Vec<McTaskId>> = HashMap::new();
+        let mut pending = vec![McTaskId::new(mc, target_task)];
+        leaves.entry(mc.to_string()).or_default().push(target_task);
+
+        while let Some(root) = pending.pop() {
+            let builder = builders
+                .get(root.mc.as_str())
+                .ok_or_else(|| format!("Unknown multiconfig '{}'", root.mc))?;
+            let collected = required.entry(root.mc.clone()).or_default();
+            if collected.contains(&root.task_id) {
+                continue;
+            }
+
+            let before = collected.clone();
+            builder.collect_dependencies(root.task_id, collected);
+            let mut added: Vec<TaskId> = collected.difference(&before).copied().collect();
+            added.sort();
+
+            for task_id in added {
+                let cross = builder.mc_dependencies(&root.mc, task_id, recipe_graphs)?;
+                if cross.is_empty() {
+                    continue;
+                }
+                for dep in &cross {
+                    leaves.entry(dep.mc.clone()).or_default().push(dep.task_id);
+                }
+                pending.extend(cross.iter().cloned());
+                mc_depends
+                    .entry(McTaskId::new(root.mc.clone(), task_id))
+                    .or_default()
+                    .extend(cross);
+            }
+        }
+
+        let mut graphs = HashMap::new();
+        for (name, tasks) in &required {
+            let leaf_tasks = leaves.remove(name).unwrap_or_default();
+            graphs.insert(name.clone(), builders[name.as_str()].build_subgraph(tasks, leaf_tasks)?);
+        }
+
+        let execution_order = Self::multiconfig_order(&graphs, &mc_depends)?;
+
+        Ok(MultiConfigTaskGraph {
+            graphs,
+            mc_depends,
+            execution_order,
+        })
+    }
+
+    /// Resolve a task's `mcdepends` entries that apply to configuration `mc`
+    fn mc_dependencies(
+        &self,
+        mc: &str,
+        task_id: TaskId,
+        recipe_graphs: &HashMap<String, RecipeGraph>,
+    ) -> Result<Vec<McTaskId>, String> {
+        let Some(value) = self
+            .recipe_graph
+            .get_task(task_id)
+            .and_then(|task| task.flags.get("mcdepends"))
+        else {
+            return Ok(Vec::new());
+        };
+
+        let mut deps = Vec::new();
+        for dep in McDependency::parse_list(value)? {
+            if dep.from_mc != mc {
+                continue;
+            }
+            let graph = recipe_graphs.get(&dep.to_mc).ok_or_else(|| {
+                format!(
+                    "Multiconfig dependency {} depends on nonexistent multiconfig configuration named '{}'",
+                    dep, dep.to_mc
+                )
+            })?;
+            let recipe_id = graph.find_recipe(&dep.recipe).ok_or_else(|| {
+                format!("Multiconfig dependency {}: no recipe '{}' in '{}'", dep, dep.recipe, dep.to_mc)
+            })?;
+            let dep_task = graph
+                .find_task(recipe_id, &dep.task)
+                .or_else(|| graph.find_task(recipe_id, &format!("do_{}", dep.task)))
+                .ok_or_else(|| format!("Multiconfig dependency {}: task not found", dep))?;
+            deps.push(McTaskId::new(dep.to_mc.clone(), dep_task));
+        }
+
+        Ok(deps)
+    }
+
+    /// Topological order over all configurations, honouring mcdepends edges
+    fn multiconfig_order(
+        graphs: &HashMap<String, TaskGraph>,
+        mc_depends: &HashMap<McTaskId, Vec<McTaskId>>,
+    ) -> Result<Vec<McTaskId>, String> {
+        let mut names: Vec<&String> = graphs.keys().collect();
+        names.sort();
+
+        let mut nodes = Vec::new();
+        let mut in_degree: HashMap<McTaskId, usize> = HashMap::new();
+        let mut dependents: HashMap<McTaskId, Vec<McTaskId>> = HashMap::new();
+
+        for name in names {
+            let graph = &graphs[name];
+            for &task_id in &graph.execution_order {
+                let id = McTaskId::new(name.clone(), task_id);
+                let mut degree = 0;
+                for &dep in &graph.tasks[&task_id].depends_on {
+                    if graph.tasks.contains_key(&dep) {
+                        degree += 1;
+                        dependents
+                            .entry(McTaskId::new(name.clone(), dep))
+                            .or_default()
+                            .push(id.clone());
+                    }
+                }
+                for dep in mc_depends.get(&id).into_iter().flatten() {
+                    degree += 1;
+                    dependents.entry(dep.clone()).or_default().push(id.clone());
+                }
+                in_degree.insert(id.clone(), degree);
+                nodes.push(id);
+            }
+        }
+
+        let mut queue: VecDeque<McTaskId> =
+            nodes.iter().filter(|id| in_degree[*id] == 0).cloned().collect();
+        let mut result = Vec::with_capacity(nodes.len());
+
+        while let Some(id) = queue.pop_front() {
+            for dependent in dependents.get(&id).into_iter().flatten() {
+                if let Some(degree) = in_degree.get_mut(dependent) {
+                    *degree -= 1;
+                    if *degree == 0 {
+                        queue.push_back(dependent.clone());
+                    }
+                }
+            }
+            result.push(id);
+        }
+
+        if result.len() != nodes.len() {
+            return Err("Circular dependency detected in multiconfig task graph".to_string());
+        }
+
+        Ok(result)
+    }
+
+    /// Restrict the full graph to `required_tasks`
+    fn build_subgraph(
+        &self,
+        required_tasks: &HashSet<TaskId>,
+        leaf_tasks: Vec<TaskId>,
+    ) -> Result<TaskGraph, String> {
         // Build graph with only required tasks
         let full_graph = self.build_full_graph()?;
 
@@ -258,8 +465,6 @@ impl TaskGraphBuilder {
             .map(|(id, _)| *id)
             .collect();
 
-        let leaf_tasks = vec![target_task];
-
         Ok(TaskGraph {
             tasks,
             execution_order,
@@ -277,47 +482,202 @@ impl TaskGraphBuilder {
         collected.insert(task_id);
 
         if let Some(task_node) = self.recipe_graph.get_task(task_id) {
-            // Collect intra-recipe dependencies
-            for &dep_id in &task_node.after {
+            for dep_id in self.task_dependencies(task_node) {
                 self.collect_dependencies(dep_id, collected);
             }
+        }
+    }
+
+    /// Direct dependencies of a task, as BitBake's runqueue derives them
+    ///
+    /// - `after`/`before` ordering and explicit task dependencies
+    /// - `[depends]`: `<provider>:do_<task>` entries
+    /// - `[deptask]`: the listed tasks of every recipe in DEPENDS
+    /// - `[rdeptask]`: the listed tasks of every recipe in RDEPENDS
+    /// - `[recrdeptask]`: the listed tasks of every recipe reachable through
+    ///   any task dependency, also following the tasks named in `[recideptask]`
+    ///
+    /// Recipes whose tasks carry none of these flags fall back to depending
+    /// on do_populate_sysroot of their DEPENDS from configure/compile/install.
+    fn task_dependencies(&self, task_node: &TaskNode) -> Vec<TaskId> {
+        let mut deps = self.direct_dependencies(task_node);
+
+        let recursive_tasks = flag_values(task_node, "recrdeptask");
+        if recursive_tasks.is_empty() {
+            return deps;
+        }
+
+        // The walk starts from the task's own dependencies plus the
+        // [recideptask] tasks of its recipe and their dependencies
+        let mut roots = deps.clone();
+        for task_name in flag_values(task_node, "recideptask") {
+            if let Some(idep_task) = self.find_task_named(task_node.recipe_id, task_name) {
+                roots.push(idep_task);
+                if let Some(idep) = self.recipe_graph.get_task(idep_task) {
+                    roots.extend(self.direct_dependencies(idep));
+                }
+            }
+        }
 
-            // Collect inter-recipe dependencies
-            for task_dep in &task_node.task_depends {
-                if let Some(dep_id) = task_dep.task_id {
-                    self.collect_dependencies(dep_id, collected);
-                } else if let Some(resolved_id) =
-                    self.recipe_graph
-                        .find_task(task_dep.recipe_id, &task_dep.task_name)
+        for dep_recipe in self.recipes_below(&roots) {
+            for task_name in &recursive_tasks {
+                if let Some(dep_task) = self.find_task_named(dep_recipe, task_name)
+                    && dep_task != task_node.id
                 {
-                    self.collect_dependencies(resolved_id, collected);
+                    deps.push(dep_task);
                 }
             }
+        }
 
-            // Collect recipe-level dependencies
-            if let Some(recipe) = self.recipe_graph.get_recipe(task_node.recipe_id) {
-                for &dep_recipe_id in &recipe.depends {
-                    // Try do_populate_sysroot first (preferred for build deps)
-                    if let Some(sysroot_task) =
-                        self.recipe_graph.find_task(dep_recipe_id, "do_populate_sysroot")
-                    {
-                        self.collect_dependencies(sysroot_task, collected);
-                    } else if let Some(install_task) =
-                        self.recipe_graph.find_task(dep_recipe_id, "do_install")
-                    {
-                        // Fallback to do_install if do_populate_sysroot doesn't exist
-                        self.collect_dependencies(install_task, collected);
-                    } else {
-                        // Fallback: collect ALL tasks from the dependency recipe
-                        if let Some(dep_recipe) = self.recipe_graph.get_recipe(dep_recipe_id) {
-                            for &dep_task_id in &dep_recipe.tasks {
-                                self.collect_dependencies(dep_task_id, collected);
-                            }
-                        }
-                    }
+        // do_a[recrdeptask] = "do_a ..." never waits on another task that
+        // also names itself in its [recrdeptask]
+        if self.is_self_recursive(task_node) {
+            deps.retain(|&dep| {
+                !self.recipe_graph.get_task(dep).is_some_and(|dep| self.is_self_recursive(dep))
+            });
+        }
+
+        dedup(deps)
+    }
+
+    /// Dependencies of a task before the recursive part of `[recrdeptask]`
+    ///
+    /// `[recrdeptask]` only contributes the listed tasks of the recipe's own
+    /// DEPENDS and RDEPENDS here.
+    fn direct_dependencies(&self, task_node: &TaskNode) -> Vec<TaskId> {
+        let mut deps: Vec<TaskId> = task_node.after.clone();
+
+        // "addtask X before Y" makes Y depend on X
+        deps.extend(
+            self.recipe_graph
+                .get_recipe_tasks(task_node.recipe_id)
+                .iter()
+                .filter(|other| other.before.contains(&task_node.id))
+                .map(|other| other.id),
+        );
+
+        // Inter-recipe task dependencies
+        for task_dep in &task_node.task_depends {
+            if let Some(dep_task_id) = task_dep.task_id {
+                deps.push(dep_task_id);
+            } else if let Some(resolved_id) =
+                self.find_task_named(task_dep.recipe_id, &task_dep.task_name)
+            {
+                deps.push(resolved_id);
+            }
+        }
+
+        deps.extend(self.flag_depends(task_node));
+
+        let Some(recipe) = self.recipe_graph.get_recipe(task_node.recipe_id) else {
+            return dedup(deps);
+        };
+
+        for (flag, recipes) in [("deptask", &recipe.depends), ("rdeptask", &recipe.rdepends)] {
+            for task_name in flag_values(task_node, flag) {
+                deps.extend(
+                    recipes
+                        .iter()
+                        .filter_map(|&dep_recipe| self.find_task_named(dep_recipe, task_name)),
+                );
+            }
+        }
+
+        for task_name in flag_values(task_node, "recrdeptask") {
+            deps.extend(
+                recipe
+                    .depends
+                    .iter()
+                    .chain(&recipe.rdepends)
+                    .filter_map(|&dep_recipe| self.find_task_named(dep_recipe, task_name))
+                    .filter(|&dep_task| dep_task != task_node.id),
+            );
+        }
+
+        if !self.uses_inter_recipe_flags(recipe.id)
+            && ["configure", "compile", "install"]
+                .iter()
+                .any(|name| task_node.name.contains(name))
+        {
+            for &dep_recipe_id in &recipe.depends {
+                if let Some(sysroot_task) = self
+                    .find_task_named(dep_recipe_id, "do_populate_sysroot")
+                    .or_else(|| self.find_task_named(dep_recipe_id, "do_install"))
+                {
+                    deps.push(sysroot_task);
                 }
             }
         }
+
+        dedup(deps)
+    }
+
+    /// Resolve the `<provider>:do_<task>` entries of a task's `[depends]` flag
+    fn flag_depends(&self, task_node: &TaskNode) -> Vec<TaskId> {
+        flag_values(task_node, "depends")
+            .into_iter()
+            .filter_map(|entry| {
+                let (target, task_name) = entry.rsplit_once(':')?;
+                let recipe_id = self.recipe_graph.resolve_provider(target)?;
+                self.find_task_named(recipe_id, task_name)
+            })
+            .collect()
+    }
+
+    /// Recipes of every task that the `roots` depend on, transitively
+    ///
+    /// The roots themselves only count when another root reaches them, as in
+    /// BitBake's cumulative dependencies.
+    fn recipes_below(&self, roots: &[TaskId]) -> Vec<RecipeId> {
+        let mut seen_tasks = HashSet::new();
+        let mut seen_recipes = HashSet::new();
+        let mut order = Vec::new();
+        let mut queue: VecDeque<TaskId> = VecDeque::new();
+
+        for &root in roots {
+            if let Some(node) = self.recipe_graph.get_task(root) {
+                queue.extend(self.direct_dependencies(node));
+            }
+        }
+
+        while let Some(task_id) = queue.pop_front() {
+            if !seen_tasks.insert(task_id) {
+                continue;
+            }
+            let Some(node) = self.recipe_graph.get_task(task_id) else {
+                continue;
+            };
+            if seen_recipes.insert(node.recipe_id) {
+                order.push(node.recipe_id);
+            }
+            queue.extend(self.direct_dependencies(node));
+        }
+
+        order
+    }
+
+    /// Whether a task lists its own name in `[recrdeptask]`
+    fn is_self_recursive(&self, task_node: &TaskNode) -> bool {
+        let own = task_node.name.strip_prefix("do_").unwrap_or(&task_node.name);
+        flag_values(task_node, "recrdeptask")
+            .iter()
+            .any(|name| name.strip_prefix("do_").unwrap_or(name) == own)
+    }
+
+    /// Whether any task of the recipe declares deptask/rdeptask/recrdeptask
+    fn uses_inter_recipe_flags(&self, recipe_id: RecipeId) -> bool {
+        self.recipe_graph
+            .get_recipe_tasks(recipe_id)
+            .iter()
+            .any(|task| INTER_RECIPE_FLAGS.iter().any(|flag| task.flags.contains_key(*flag)))
+    }
+
+    /// Find a task by name given with or without the `do_` prefix
+    fn find_task_named(&self, recipe_id: RecipeId, name: &str) -> Option<TaskId> {
+        self.recipe_graph.find_task(recipe_id, name).or_else(|| match name.strip_prefix("do_") {
+            Some(stripped) => self.recipe_graph.find_task(recipe_id, stripped),
+            None => self.recipe_graph.find_task(recipe_id, &format!("do_{}", name)),
+        })
     }
 
     /// Topological sort using Kahn's algorithm
@@ -368,10 +728,32 @@ impl TaskGraphBuilder {
     }
 }
 
+/// Whitespace-separated entries of a task flag
+fn flag_values<'a>(task_node: &'a TaskNode, flag: &str) -> Vec<&'a str> {
+    task_node
+        .flags
+        .get(flag)
+        .map(|value| value.split_whitespace().filter(|v| !v.contains("${")).collect())
+        .unwrap_or_default()
+}
+
+/// Whether a boolean task flag such as `[noexec]` is set
+fn flag_is_set(task_node: &TaskNode, flag: &str) -> bool {
+    task_node
+        .flags
+        .get(flag)
+        .is_some_and(|value| !matches!(value.trim(), "" | "0"))
+}
+
+/// Remove duplicate dependencies, keeping the first occurrence
+fn dedup(deps: Vec<TaskId>) -> Vec<TaskId> {
+    let mut seen = HashSet::new();
+    deps.into_iter().filter(|dep| seen.insert(*dep)).collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::recipe_graph::{Recipe, TaskNode};
 
     #[test]
     fn test_simple_task_graph() {
@@ -432,4 +814,353 @@ mod tests {
         assert!(task_graph.tasks.contains_key(&compile_id));
         assert!(!task_graph.tasks.contains_key(&install_id));
     }
+
+    fn firmware_graphs() -> (HashMap<String, RecipeGraph>, TaskId, TaskId) {
+        let mut linux = RecipeGraph::new();
+        let image = linux.add_recipe("product-image");
+        let rootfs = linux.add_task(image, "rootfs");
+        let image_task = linux.add_task(image, "image");
+        if let Some(task) = linux.get_task_mut(image_task) {
+            task.after.push(rootfs);
+            task.flags.insert(
+                "mcdepends".to_string(),
+                "mc::fw:fw-image:do_deploy mc:other:fw:ignored:do_deploy".to_string(),
+            );
+        }
+
+        let mut fw = RecipeGraph::new();
+        let fw_image = fw.add_recipe("fw-image");
+        let compile = fw.add_task(fw_image, "compile");
+        let deploy = fw.add_task(fw_image, "deploy");
+        if let Some(task) = fw.get_task_mut(deploy) {
+            task.after.push(compile);
+        }
+
+        let mut graphs = HashMap::new();
+        graphs.insert(String::new(), linux);
+        graphs.insert("fw".to_string(), fw);
+        (graphs, image_task, deploy)
+    }
+
+    #[test]
+    fn test_multiconfig_task_graph() {
+        let (graphs, image_task, deploy) = firmware_graphs();
+
+        let mc_graph = TaskGraphBuilder::build_multiconfig(&graphs, "", image_task).unwrap();
+        assert_eq!(mc_graph.task_count(), 4);
+
+        let image = McTaskId::new("", image_task);
+        let fw_deploy = McTaskId::new("fw", deploy);
+        assert!(mc_graph.depends_on(&image).contains(&fw_deploy));
+
+        let pos = |id: &McTaskId| mc_graph.execution_order.iter().position(|x| x == id);
+        assert!(pos(&fw_deploy) < pos(&image));
+        assert_eq!(mc_graph.get_task(&fw_deploy).unwrap().recipe_name, "fw-image");
+
+        // Building the firmware config alone does not pull in the Linux side
+        let fw_only = TaskGraphBuilder::build_multiconfig(&graphs, "fw", deploy).unwrap();
+        assert_eq!(fw_only.task_count(), 2);
+        assert!(fw_only.mc_depends.is_empty());
+    }
+
+    #[test]
+    fn test_multiconfig_missing_config() {
+        let (mut graphs, image_task, _) = firmware_graphs();
+        graphs.remove("fw");
+
+        let err = TaskGraphBuilder::build_multiconfig(&graphs, "", image_task).unwrap_err();
+        assert!(err.contains("nonexistent multiconfig"), "{}", err);
+    }
+
+    /// zlib <- busybox (DEPENDS) <- image (RDEPENDS), plus virtual/kernel via [depends]
+    fn flagged_graph() -> RecipeGraph {
+        let mut graph = RecipeGraph::new();
+        let tasks = ["fetch", "compile", "install", "populate_sysroot", "package_write_ipk", "build"];
+
+        for name in ["zlib", "busybox", "linux-yocto", "core-image"] {
+            let recipe = graph.add_recipe(name);
+            let mut previous = None;
+            for task in tasks {
+                let id = graph.add_task(recipe, task);
+                if let Some(prev) = previous
+                    && let Some(node) = graph.get_task_mut(id)
+                {
+                    node.after.push(prev);
+                }
+                previous = Some(id);
+            }
+        }
+
+        let zlib = graph.find_recipe("zlib").unwrap();
+        let busybox = graph.find_recipe("busybox").unwrap();
+        let kernel = graph.find_recipe("linux-yocto").unwrap();
+        let image = graph.find_recipe("core-image").unwrap();
+        graph.add_dependency(busybox, zlib);
+        graph.add_runtime_dependency(image, busybox);
+        graph.register_provider(kernel, "virtual/kernel");
+
+        let set_flag = |graph: &mut RecipeGraph, recipe: RecipeId, task: &str, flag: &str, value: &str| {
+            let id = graph.find_task(recipe, task).unwrap();
+            graph.get_task_mut(id).unwrap().flags.insert(flag.to_string(), value.to_string());
+        };
+        for recipe in [zlib, busybox, kernel, image] {
+            set_flag(&mut graph, recipe, "compile", "deptask", "do_populate_sysroot");
+            set_flag(&mut graph, recipe, "build", "noexec", "1");
+        }
+        set_flag(&mut graph, image, "install", "depends", "virtual/kernel:do_install");
+        set_flag(&mut graph, image, "install", "nostamp", "1");
+        set_flag(&mut graph, image, "build", "recrdeptask", "do_build");
+        set_flag(&mut graph, image, "package_write_ipk", "recrdeptask", "do_package_write_ipk");
+        set_flag(&mut graph, image, "package_write_ipk", "recideptask", "do_install");
+
+        graph
+    }
+
+    #[test]
+    fn test_task_flag_dependencies() {
+        let graph = flagged_graph();
+        let task = |recipe: &str, name: &str| {
+            graph.find_task(graph.find_recipe(recipe).unwrap(), name).unwrap()
+        };
+
+        let task_graph = TaskGraphBuilder::new(graph.clone()).build_full_graph().unwrap();
+        let depends_on = |recipe: &str, name: &str| task_graph.tasks[&task(recipe, name)].depends_on.clone();
+
+        // [deptask] follows DEPENDS only
+        assert!(depends_on("busybox", "compile").contains(&task("zlib", "populate_sysroot")));
+        assert!(!depends_on("core-image", "compile").contains(&task("busybox", "populate_sysroot")));
+        // No heuristic edges once the recipe uses deptask
+        assert!(!depends_on("busybox", "install").contains(&task("zlib", "populate_sysroot")));
+
+        // [depends] resolves the provider
+        assert!(depends_on("core-image", "install").contains(&task("linux-yocto", "install")));
+
+        // [recrdeptask] covers every recipe reached through task edges,
+        // including the [depends] of core-image:do_install
+        let build = depends_on("core-image", "build");
+        assert!(build.contains(&task("busybox", "build")));
+        assert!(build.contains(&task("zlib", "build")));
+        assert!(build.contains(&task("linux-yocto", "build")));
+        assert!(!build.contains(&task("core-image", "build")));
+        // Both tasks name themselves in [recrdeptask], so neither waits on the other
+        assert!(!build.contains(&task("core-image", "package_write_ipk")));
+
+        // [recideptask] pulls in the recipes named in that task's [depends]
+        let packages = depends_on("core-image", "package_write_ipk");
+        assert!(packages.contains(&task("linux-yocto", "package_write_ipk")));
+        assert!(packages.contains(&task("zlib", "package_write_ipk")));
+
+        let image_build = &task_graph.tasks[&task("core-image", "build")];
+        assert!(image_build.noexec);
+        assert!(!image_build.nostamp);
+        assert!(task_graph.tasks[&task("core-image", "install")].nostamp);
+
+        // The target graph holds exactly what the full graph says the target needs
+        let target = TaskGraphBuilder::new(graph.clone())
+            .build_for_target("core-image", "build")
+            .unwrap();
+        assert!(target.tasks.contains_key(&task("zlib", "build")));
+        assert!(target.tasks.contains_key(&task("linux-yocto", "build")));
+        assert!(!target.tasks.contains_key(&task("core-image", "package_write_ipk")));
+        for target_task in target.tasks.values() {
+            for dep in &target_task.depends_on {
+                assert!(target.tasks.contains_key(dep));
+            }
+        }
+    }
+
+    #[test]
+    fn test_before_constraint() {
+        let mut graph = RecipeGraph::new();
+        let recipe = graph.add_recipe("test");
+        let install = graph.add_task(recipe, "install");
+        let deploy = graph.add_task(recipe, "deploy");
+        graph.get_task_mut(deploy).unwrap().before.push(install);
+
+        let task_graph = TaskGraphBuilder::new(graph).build_full_graph().unwrap();
+        assert_eq!(task_graph.tasks[&install].depends_on, vec![deploy]);
+    }
+
+    /// `bitbake -g core-image` for the metadata in `image_graph`
+    const TASK_DEPENDS_DOT: &str = r#"digraph depends {
+"core-image.do_build" [label="core-image do_build\n:1.0-r0\n/layer/recipes/core-image.bb"]
+"core-image.do_build" -> "core-image.do_image_complete"
+"core-image.do_build" -> "curl.do_build"
+"core-image.do_build" -> "linux-yocto.do_build"
+"core-image.do_build" -> "zlib.do_build"
+"core-image.do_fetch" [label="core-image do_fetch\n:1.0-r0\n/layer/recipes/core-image.bb"]
+"core-image.do_image_complete" [label="core-image do_image_complete\n:1.0-r0\n/layer/recipes/core-image.bb"]
+"core-image.do_image_complete" -> "core-image.do_rootfs"
+"core-image.do_rootfs" [label="core-image do_rootfs\n:1.0-r0\n/layer/recipes/core-image.bb"]
+"core-image.do_rootfs" -> "core-image.do_fetch"
+"core-image.do_rootfs" -> "curl.do_package_write_ipk"
+"core-image.do_rootfs" -> "linux-yocto.do_deploy"
+"core-image.do_rootfs" -> "linux-yocto.do_package_write_ipk"
+"core-image.do_rootfs" -> "zlib.do_package_write_ipk"
+"curl.do_build" [label="curl do_build\n:8.7.1-r0\n/layer/recipes/curl_8.7.1.bb"]
+"curl.do_build" -> "curl.do_package_write_ipk"
+"curl.do_build" -> "curl.do_populate_sysroot"
+"curl.do_compile" [label="curl do_compile\n:8.7.1-r0\n/layer/recipes/curl_8.7.1.bb"]
+"curl.do_compile" -> "curl.do_configure"
+"curl.do_configure" [label="curl do_configure\n:8.7.1-r0\n/layer/recipes/curl_8.7.1.bb"]
+"curl.do_configure" -> "curl.do_fetch"
+"curl.do_configure" -> "zlib.do_populate_sysroot"
+"curl.do_fetch" [label="curl do_fetch\n:8.7.1-r0\n/layer/recipes/curl_8.7.1.bb"]
+"curl.do_install" [label="curl do_install\n:8.7.1-r0\n/layer/recipes/curl_8.7.1.bb"]
+"curl.do_install" -> "curl.do_compile"
+"curl.do_package" [label="curl do_package\n:8.7.1-r0\n/layer/recipes/curl_8.7.1.bb"]
+"curl.do_package" -> "curl.do_install"
+"curl.do_package_write_ipk" [label="curl do_package_write_ipk\n:8.7.1-r0\n/layer/recipes/curl_8.7.1.bb"]
+"curl.do_package_write_ipk" -> "curl.do_package"
+"curl.do_package_write_ipk" -> "zlib.do_package"
+"curl.do_populate_sysroot" [label="curl do_populate_sysroot\n:8.7.1-r0\n/layer/recipes/curl_8.7.1.bb"]
+"curl.do_populate_sysroot" -> "curl.do_install"
+"linux-yocto.do_build" [label="linux-yocto do_build\n:6.6-r0\n/layer/recipes/linux-yocto_6.6.bb"]
+"linux-yocto.do_build" -> "linux-yocto.do_deploy"
+"linux-yocto.do_build" -> "linux-yocto.do_package_write_ipk"
+"linux-yocto.do_build" -> "linux-yocto.do_populate_sysroot"
+"linux-yocto.do_compile" [label="linux-yocto do_compile\n:6.6-r0\n/layer/recipes/linux-yocto_6.6.bb"]
+"linux-yocto.do_compile" -> "linux-yocto.do_configure"
+"linux-yocto.do_configure" [label="linux-yocto do_configure\n:6.6-r0\n/layer/recipes/linux-yocto_6.6.bb"]
+"linux-yocto.do_configure" -> "linux-yocto.do_fetch"
+"linux-yocto.do_deploy" [label="linux-yocto do_deploy\n:6.6-r0\n/layer/recipes/linux-yocto_6.6.bb"]
+"linux-yocto.do_deploy" -> "linux-yocto.do_install"
+"linux-yocto.do_fetch" [label="linux-yocto do_fetch\n:6.6-r0\n/layer/recipes/linux-yocto_6.6.bb"]
+"linux-yocto.do_install" [label="linux-yocto do_install\n:6.6-r0\n/layer/recipes/linux-yocto_6.6.bb"]
+"linux-yocto.do_install" -> "linux-yocto.do_compile"
+"linux-yocto.do_package" [label="linux-yocto do_package\n:6.6-r0\n/layer/recipes/linux-yocto_6.6.bb"]
+"linux-yocto.do_package" -> "linux-yocto.do_install"
+"linux-yocto.do_package_write_ipk" [label="linux-yocto do_package_write_ipk\n:6.6-r0\n/layer/recipes/linux-yocto_6.6.bb"]
+"linux-yocto.do_package_write_ipk" -> "linux-yocto.do_package"
+"linux-yocto.do_populate_sysroot" [label="linux-yocto do_populate_sysroot\n:6.6-r0\n/layer/recipes/linux-yocto_6.6.bb"]
+"linux-yocto.do_populate_sysroot" -> "linux-yocto.do_install"
+"zlib.do_build" [label="zlib do_build\n:1.3.1-r0\n/layer/recipes/zlib_1.3.1.bb"]
+"zlib.do_build" -> "zlib.do_package_write_ipk"
+"zlib.do_build" -> "zlib.do_populate_sysroot"
+"zlib.do_compile" [label="zlib do_compile\n:1.3.1-r0\n/layer/recipes/zlib_1.3.1.bb"]
+"zlib.do_compile" -> "zlib.do_configure"
+"zlib.do_configure" [label="zlib do_configure\n:1.3.1-r0\n/layer/recipes/zlib_1.3.1.bb"]
+"zlib.do_configure" -> "zlib.do_fetch"
+"zlib.do_fetch" [label="zlib do_fetch\n:1.3.1-r0\n/layer/recipes/zlib_1.3.1.bb"]
+"zlib.do_install" [label="zlib do_install\n:1.3.1-r0\n/layer/recipes/zlib_1.3.1.bb"]
+"zlib.do_install" -> "zlib.do_compile"
+"zlib.do_package" [label="zlib do_package\n:1.3.1-r0\n/layer/recipes/zlib_1.3.1.bb"]
+"zlib.do_package" -> "zlib.do_install"
+"zlib.do_package_write_ipk" [label="zlib do_package_write_ipk\n:1.3.1-r0\n/layer/recipes/zlib_1.3.1.bb"]
+"zlib.do_package_write_ipk" -> "zlib.do_package"
+"zlib.do_populate_sysroot" [label="zlib do_populate_sysroot\n:1.3.1-r0\n/layer/recipes/zlib_1.3.1.bb"]
+"zlib.do_populate_sysroot" -> "zlib.do_install"
+}
+"#;
+
+    /// zlib <- curl (DEPENDS, RDEPENDS) <- core-image (RDEPENDS), with the
+    /// image's do_rootfs depending on the kernel's do_deploy
+    fn image_graph() -> RecipeGraph {
+        let mut graph = RecipeGraph::new();
+        let recipe_tasks: &[(&str, &[&str])] = &[
+            ("fetch", &[]),
+            ("configure", &["fetch"]),
+            ("compile", &["configure"]),
+            ("install", &["compile"]),
+            ("populate_sysroot", &["install"]),
+            ("package", &["install"]),
+            ("package_write_ipk", &["package"]),
+            ("build", &["populate_sysroot", "package_write_ipk"]),
+        ];
+        let image_tasks: &[(&str, &[&str])] = &[
+            ("fetch", &[]),
+            ("rootfs", &["fetch"]),
+            ("image_complete", &["rootfs"]),
+            ("build", &["image_complete"]),
+        ];
+
+        let add_tasks = |graph: &mut RecipeGraph, name: &str, tasks: &[(&str, &[&str])]| {
+            let recipe = graph.add_recipe(name);
+            for &(task, after) in tasks {
+                let id = graph.add_task(recipe, task);
+                let after: Vec<TaskId> = after.iter().map(|dep| graph.find_task(recipe, dep).unwrap()).collect();
+                graph.get_task_mut(id).unwrap().after.extend(after);
+            }
+            recipe
+        };
+        let zlib = add_tasks(&mut graph, "zlib", recipe_tasks);
+        let curl = add_tasks(&mut graph, "curl", recipe_tasks);
+        let kernel = add_tasks(&mut graph, "linux-yocto", recipe_tasks);
+        let image = add_tasks(&mut graph, "core-image", image_tasks);
+
+        // addtask deploy after do_install before do_build
+        let deploy = graph.add_task(kernel, "deploy");
+        let kernel_install = graph.find_task(kernel, "install").unwrap();
+        let kernel_build = graph.find_task(kernel, "build").unwrap();
+        let node = graph.get_task_mut(deploy).unwrap();
+        node.after.push(kernel_install);
+        node.before.push(kernel_build);
+
+        graph.add_dependency(curl, zlib);
+        graph.add_runtime_dependency(curl, zlib);
+        graph.add_runtime_dependency(image, curl);
+        graph.register_provider(kernel, "virtual/kernel");
+
+        let set_flag = |graph: &mut RecipeGraph, recipe: RecipeId, task: &str, flag: &str, value: &str| {
+            let id = graph.find_task(recipe, task).unwrap();
+            graph.get_task_mut(id).unwrap().flags.insert(flag.to_string(), value.to_string());
+        };
+        for recipe in [zlib, curl, kernel] {
+            set_flag(&mut graph, recipe, "configure", "deptask", "do_populate_sysroot");
+            set_flag(&mut graph, recipe, "package_write_ipk", "rdeptask", "do_package");
+            set_flag(&mut graph, recipe, "build", "noexec", "1");
+        }
+        set_flag(&mut graph, image, "rootfs", "recrdeptask", "do_package_write_ipk");
+        set_flag(&mut graph, image, "rootfs", "depends", "virtual/kernel:do_deploy");
+        set_flag(&mut graph, image, "build", "recrdeptask", "do_build");
+        set_flag(&mut graph, image, "build", "noexec", "1");
+
+        graph
+    }
+
+    /// Nodes and edges of a task-depends.dot file
+    fn parse_task_depends(dot: &str) -> (HashSet<String>, HashSet<(String, String)>) {
+        let mut nodes = HashSet::new();
+        let mut edges = HashSet::new();
+        for line in dot.lines().map(str::trim) {
+            let quoted: Vec<&str> = line.split('"').skip(1).step_by(2).collect();
+            match line.split_once(" -> ") {
+                Some(_) => {
+                    edges.insert((quoted[0].to_string(), quoted[1].to_string()));
+                }
+                None if line.starts_with('"') => {
+                    nodes.insert(quoted[0].to_string());
+                }
+                None => {}
+            }
+        }
+        (nodes, edges)
+    }
+
+    #[test]
+    fn test_matches_bitbake_task_depends() {
+        let graph = image_graph();
+        let task_graph = TaskGraphBuilder::new(graph).build_for_target("core-image", "build").unwrap();
+
+        let dot_name = |task: &ExecutableTask| format!("{}.do_{}", task.recipe_name, task.task_name);
+        let nodes: HashSet<String> = task_graph.tasks.values().map(dot_name).collect();
+        let edges: HashSet<(String, String)> = task_graph
+            .tasks
+            .values()
+            .flat_map(|task| {
+                task.depends_on
+                    .iter()
+                    .map(|dep| (dot_name(task), dot_name(&task_graph.tasks[dep])))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        let (expected_nodes, expected_edges) = parse_task_depends(TASK_DEPENDS_DOT);
+        let mut missing: Vec<_> = expected_edges.difference(&edges).collect();
+        let mut extra: Vec<_> = edges.difference(&expected_edges).collect();
+        missing.sort();
+        extra.sort();
+        assert!(missing.is_empty() && extra.is_empty(), "missing: {:?}\nextra: {:?}", missing, extra);
+        assert_eq!(nodes, expected_nodes);
+    }
 }
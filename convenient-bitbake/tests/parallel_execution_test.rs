@@ -81,6 +81,7 @@ async fn test_parallel_execution_with_scheduler() {
             execution_mode: convenient_bitbake::executor::ExecutionMode::Shell,
             network_policy: convenient_bitbake::executor::NetworkPolicy::Isolated,
             resource_limits: convenient_bitbake::executor::ResourceLimits::default(),
+            flags: Default::default(),
         };
 
         task_specs.insert(task_key, spec);
@@ -0,0 +1,266 @@
+// Drives bitbake-lsp over stdio the way an editor does: initialize against a
+// two-layer workspace, open a recipe, then ask for diagnostics, definitions,
+// hover, completion and the recipe's bbappends.
+
+use serde_json::{Value, json};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+struct Client {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    next_id: i64,
+    /// Notifications that arrived while waiting for a response
+    notifications: Vec<Value>,
+}
+
+impl Client {
+    fn start(root: &Path) -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_bitbake-lsp"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .unwrap();
+        let mut client = Client {
+            stdin: child.stdin.take().unwrap(),
+            stdout: BufReader::new(child.stdout.take().unwrap()),
+            child,
+            next_id: 0,
+            notifications: Vec::new(),
+        };
+
+        let root_uri = uri(root);
+        let result = client.request(
+            "initialize",
+            json!({
+                "processId": null,
+                "rootUri": root_uri,
+                "workspaceFolders": [{ "uri": root_uri, "name": "workspace" }],
+                "capabilities": {},
+            }),
+        );
+        let capabilities = &result["capabilities"];
+        assert_eq!(capabilities["definitionProvider"], true);
+        assert_eq!(capabilities["hoverProvider"], true);
+        assert_eq!(capabilities["implementationProvider"], true);
+        client.notify("initialized", json!({}));
+        client
+    }
+
+    fn send(&mut self, message: &Value) {
+        let body = message.to_string();
+        write!(self.stdin, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+        self.stdin.flush().unwrap();
+    }
+
+    fn receive(&mut self) -> Value {
+        let mut length = 0;
+        loop {
+            let mut header = String::new();
+            assert!(self.stdout.read_line(&mut header).unwrap() > 0, "server closed stdout");
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some(value) = header.strip_prefix("Content-Length: ") {
+                length = value.parse().unwrap();
+            }
+        }
+        let mut body = vec![0; length];
+        self.stdout.read_exact(&mut body).unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    fn request(&mut self, method: &str, params: Value) -> Value {
+        self.next_id += 1;
+        let id = self.next_id;
+        self.send(&json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }));
+        loop {
+            let message = self.receive();
+            if message["id"] == id {
+                assert!(message.get("error").is_none(), "{} failed: {}", method, message);
+                return message["result"].clone();
+            }
+            self.notifications.push(message);
+        }
+    }
+
+    fn notify(&mut self, method: &str, params: Value) {
+        self.send(&json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    fn diagnostics(&mut self, uri: &str) -> Vec<Value> {
+        loop {
+            if let Some(position) = self.notifications.iter().position(|n| {
+                n["method"] == "textDocument/publishDiagnostics" && n["params"]["uri"] == uri
+            }) {
+                let notification = self.notifications.remove(position);
+                return notification["params"]["diagnostics"].as_array().unwrap().clone();
+            }
+            let message = self.receive();
+            self.notifications.push(message);
+        }
+    }
+
+    fn at(&mut self, method: &str, uri: &str, line: u32, character: u32) -> Value {
+        self.request(
+            method,
+            json!({
+                "textDocument": { "uri": uri },
+                "position": { "line": line, "character": character },
+            }),
+        )
+    }
+
+    fn shutdown(mut self) {
+        self.request("shutdown", Value::Null);
+        self.notify("exit", Value::Null);
+        assert!(self.child.wait().unwrap().success());
+    }
+}
+
+fn uri(path: &Path) -> String {
+    // bbappend wildcards are percent-encoded in URIs
+    format!("file://{}", path.display()).replace('%', "%25")
+}
+
+fn write(root: &Path, file: &str, content: &str) {
+    let path = root.join(file);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, content).unwrap();
+}
+
+fn layer_conf(collection: &str, priority: u32) -> String {
+    format!(
+        r#"BBPATH .= ":${{LAYERDIR}}"
+BBFILES += "${{LAYERDIR}}/recipes-*/*/*.bb ${{LAYERDIR}}/recipes-*/*/*.bbappend"
+BBFILE_COLLECTIONS += "{collection}"
+BBFILE_PATTERN_{collection} = "^${{LAYERDIR}}/"
+BBFILE_PRIORITY_{collection} = "{priority}"
+"#
+    )
+}
+
+const RECIPE: &str = r#"SUMMARY = "Foo"
+require foo.inc
+SRC_URI = "file://foo.c"
+inherit hello
+DEPENDS = "zlib"
+EXTRA_OEMAKE = "${DEPENDS} V=1"
+
+do_compile() {
+    say_hello
+}
+addtask hello after do_compile
+@@@
+"#;
+
+fn workspace() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    write(root, "meta-test/conf/layer.conf", &layer_conf("test", 6));
+    write(
+        root,
+        "meta-test/classes/hello.bbclass",
+        "HELLO_GREETING ?= \"hi\"\n\nsay_hello() {\n    echo ${HELLO_GREETING}\n}\n\ndo_hello() {\n    say_hello\n}\n",
+    );
+    write(
+        root,
+        "meta-test/recipes-test/foo/foo.inc",
+        "LICENSE = \"MIT\"\nLIC_FILES_CHKSUM = \"file://COPYING;md5=0\"\n",
+    );
+    write(root, "meta-test/recipes-test/foo/foo_1.0.bb", RECIPE);
+    write(root, "meta-extra/conf/layer.conf", &layer_conf("extra", 7));
+    write(
+        root,
+        "meta-extra/recipes-test/foo/foo_%.bbappend",
+        "DEPENDS:append = \" bar\"\n",
+    );
+    dir
+}
+
+#[test]
+fn test_lsp_over_stdio() {
+    let dir = workspace();
+    let root = dir.path().canonicalize().unwrap();
+    let recipe = uri(&root.join("meta-test/recipes-test/foo/foo_1.0.bb"));
+    let class = uri(&root.join("meta-test/classes/hello.bbclass"));
+    let mut client = Client::start(&root);
+
+    client.notify(
+        "textDocument/didOpen",
+        json!({
+            "textDocument": { "uri": recipe, "languageId": "bitbake", "version": 1, "text": RECIPE },
+        }),
+    );
+
+    // The "@@@" line is the only parse error; lint findings come with their rule id
+    let diagnostics = client.diagnostics(&recipe);
+    let parse_errors: Vec<&Value> = diagnostics.iter().filter(|d| d["source"] == "bitbake").collect();
+    assert_eq!(parse_errors.len(), 1, "{:?}", diagnostics);
+    assert_eq!(parse_errors[0]["range"]["start"]["line"], 11);
+    assert!(
+        diagnostics
+            .iter()
+            .filter(|d| d["source"] == "lint")
+            .all(|d| d["code"].is_string())
+    );
+
+    // inherit, require, function calls and addtask names
+    let definition = client.at("textDocument/definition", &recipe, 3, 10);
+    assert_eq!(definition[0]["uri"], class);
+    let definition = client.at("textDocument/definition", &recipe, 1, 10);
+    assert_eq!(definition[0]["uri"], uri(&root.join("meta-test/recipes-test/foo/foo.inc")));
+    let definition = client.at("textDocument/definition", &recipe, 8, 6);
+    assert_eq!(definition[0]["uri"], class);
+    assert_eq!(definition[0]["range"]["start"]["line"], 2);
+    let definition = client.at("textDocument/definition", &recipe, 10, 10);
+    assert_eq!(definition[0]["uri"], class);
+    assert_eq!(definition[0]["range"]["start"]["line"], 6);
+
+    // Hover on a ${} reference shows the value with the bbappend applied, and where it came from
+    let hover = client.at("textDocument/hover", &recipe, 5, 19);
+    let markdown = hover["contents"]["value"].as_str().unwrap();
+    assert!(markdown.starts_with("**DEPENDS**"), "{}", markdown);
+    assert!(markdown.contains("```\nzlib bar\n```"), "{}", markdown);
+    assert!(markdown.contains("meta-test/recipes-test/foo/foo_1.0.bb:5"), "{}", markdown);
+    assert!(markdown.contains("meta-extra/recipes-test/foo/foo_%.bbappend:1"), "{}", markdown);
+
+    // Completion: classes after inherit, tasks after addtask, variables in ${
+    client.notify(
+        "textDocument/didChange",
+        json!({
+            "textDocument": { "uri": recipe, "version": 2 },
+            "contentChanges": [{ "text": format!("{}inherit he\naddtask \nX = \"${{HE\"\n", RECIPE) }],
+        }),
+    );
+    let labels = |items: &Value| -> Vec<String> {
+        items
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["label"].as_str().unwrap().to_string())
+            .collect()
+    };
+    let classes = client.at("textDocument/completion", &recipe, 12, 10);
+    assert_eq!(labels(&classes), ["hello"]);
+    let tasks = labels(&client.at("textDocument/completion", &recipe, 13, 8));
+    assert!(tasks.contains(&"do_hello".to_string()), "{:?}", tasks);
+    assert!(tasks.contains(&"do_compile".to_string()), "{:?}", tasks);
+    let variables = labels(&client.at("textDocument/completion", &recipe, 14, 7));
+    assert!(variables.contains(&"HELLO_GREETING".to_string()), "{:?}", variables);
+    assert!(!variables.contains(&"say_hello".to_string()));
+
+    // bbappends, as a custom request and as "go to implementation"
+    let bbappend = uri(&root.join("meta-extra/recipes-test/foo/foo_%.bbappend"));
+    let bbappends = client.request("bitbake/findBbappends", json!({ "uri": recipe }));
+    assert_eq!(bbappends.as_array().unwrap().len(), 1);
+    assert_eq!(bbappends[0]["uri"], bbappend);
+    let implementation = client.at("textDocument/implementation", &recipe, 0, 0);
+    assert_eq!(implementation, bbappends);
+
+    client.shutdown();
+}
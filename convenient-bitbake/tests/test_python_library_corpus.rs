@@ -0,0 +1,367 @@
+// Poky class snippets run through the parse-time python library (bb.utils,
+// oe.utils, oe.path, oe.types and the datastore API). Expected values are what
+// BitBake produces for the same expressions.
+
+use convenient_bitbake::DataStore;
+use convenient_bitbake::parse_lifecycle;
+use std::path::Path;
+
+struct Snippet {
+    class: &'static str,
+    vars: &'static [(&'static str, &'static str)],
+    flags: &'static [(&'static str, &'static str, &'static str)],
+    expr: &'static str,
+    expected: &'static str,
+}
+
+const CORPUS: &[Snippet] = &[
+    Snippet {
+        class: "bitbake.conf",
+        vars: &[("DEBUG_BUILD", "1")],
+        flags: &[],
+        expr: "oe.utils.vartrue('DEBUG_BUILD', '-O0', '-O2', d)",
+        expected: "-O0",
+    },
+    Snippet {
+        class: "bitbake.conf",
+        vars: &[("DEBUG_BUILD", "0")],
+        flags: &[],
+        expr: "oe.utils.vartrue('DEBUG_BUILD', '-O0', '-O2', d)",
+        expected: "-O2",
+    },
+    Snippet {
+        class: "native.bbclass",
+        vars: &[("PN", "zlib-native"), ("SPECIAL_PKGSUFFIX", "-native -cross -initial -intermediate -crosssdk -cross-canadian")],
+        flags: &[],
+        expr: "oe.utils.prune_suffix(d.getVar('PN'), d.getVar('SPECIAL_PKGSUFFIX').split(), d)",
+        expected: "zlib",
+    },
+    Snippet {
+        class: "multilib.bbclass",
+        vars: &[("PN", "lib32-zlib"), ("MLPREFIX", "lib32-")],
+        flags: &[],
+        expr: "oe.utils.prune_suffix(d.getVar('PN'), ['-native'], d)",
+        expected: "zlib",
+    },
+    Snippet {
+        class: "meson.bbclass",
+        vars: &[("PARALLEL_MAKE", "-j 16")],
+        flags: &[],
+        expr: "oe.utils.parallel_make_argument(d, '-j %d', limit=64)",
+        expected: "-j 16",
+    },
+    Snippet {
+        class: "meson.bbclass",
+        vars: &[("PARALLEL_MAKE", "-j128")],
+        flags: &[],
+        expr: "oe.utils.parallel_make_argument(d, '-j %d', limit=64)",
+        expected: "-j 64",
+    },
+    Snippet {
+        class: "cmake.bbclass",
+        vars: &[],
+        flags: &[],
+        expr: "oe.utils.parallel_make_argument(d, '-j %d')",
+        expected: "",
+    },
+    Snippet {
+        class: "mesa.inc",
+        vars: &[("DISTRO_FEATURES", "x11 wayland")],
+        flags: &[],
+        expr: "bb.utils.contains('DISTRO_FEATURES', 'x11 opengl', 'gl', '', d)",
+        expected: "",
+    },
+    Snippet {
+        class: "mesa.inc",
+        vars: &[("DISTRO_FEATURES", "opengl wayland x11")],
+        flags: &[],
+        expr: "bb.utils.contains('DISTRO_FEATURES', 'x11 opengl', 'gl', '', d)",
+        expected: "gl",
+    },
+    Snippet {
+        class: "gtk+3",
+        vars: &[("DISTRO_FEATURES", "x11 wayland opengl")],
+        flags: &[],
+        expr: "bb.utils.filter('DISTRO_FEATURES', 'wayland x11 vulkan', d)",
+        expected: "wayland x11",
+    },
+    Snippet {
+        class: "gtk-icon-cache.bbclass",
+        vars: &[("DISTRO_FEATURES", "wayland")],
+        flags: &[],
+        expr: "bb.utils.contains_any('DISTRO_FEATURES', ['x11', 'wayland'], 'gtk', 'no-gtk', d)",
+        expected: "gtk",
+    },
+    Snippet {
+        class: "systemd.bbclass",
+        vars: &[("DISTRO_FEATURES", "pam")],
+        flags: &[],
+        expr: "oe.utils.any_distro_features(d, 'systemd sysvinit')",
+        expected: "",
+    },
+    Snippet {
+        class: "features_check.bbclass",
+        vars: &[("DISTRO_FEATURES", "pam systemd usrmerge")],
+        flags: &[],
+        expr: "oe.utils.all_distro_features(d, 'systemd usrmerge', 'ok', 'missing')",
+        expected: "ok",
+    },
+    Snippet {
+        class: "base.bbclass",
+        vars: &[("TARGET_ARCH", "x86_64")],
+        flags: &[],
+        expr: "oe.utils.conditional('TARGET_ARCH', 'x86_64', 'yes', 'no', d)",
+        expected: "yes",
+    },
+    Snippet {
+        class: "gcc-common.inc",
+        vars: &[("PV", "13.2.0")],
+        flags: &[],
+        expr: "oe.utils.trim_version(d.getVar('PV'), 2)",
+        expected: "13.2",
+    },
+    Snippet {
+        class: "kernel.bbclass",
+        vars: &[("PV", "6.6.23")],
+        flags: &[],
+        expr: "oe.utils.version_less_or_equal('PV', '6.1', 'old', 'new', d)",
+        expected: "new",
+    },
+    Snippet {
+        class: "python3-dir.bbclass",
+        vars: &[("PYTHON_BASEVERSION", "3.12")],
+        flags: &[],
+        expr: "oe.utils.less_or_equal('PYTHON_BASEVERSION', '3.12', 'le', 'gt', d)",
+        expected: "le",
+    },
+    Snippet {
+        class: "image.bbclass",
+        vars: &[("IMAGE_FEATURES", "ssh-server-openssh  debug-tweaks   package-management")],
+        flags: &[],
+        expr: "oe.utils.squashspaces(d.getVar('IMAGE_FEATURES'))",
+        expected: "ssh-server-openssh debug-tweaks package-management",
+    },
+    Snippet {
+        class: "packagegroup.bbclass",
+        vars: &[("PN", "foo"), ("PACKAGES", "foo-src foo-dbg foo-staticdev foo-dev foo-doc foo-locale foo foo-extra foo-locale-de")],
+        flags: &[],
+        expr: "' '.join(oe.utils.packages_filter_out_system(d))",
+        expected: "foo-extra",
+    },
+    Snippet {
+        class: "package.bbclass",
+        vars: &[],
+        flags: &[],
+        expr: "oe.utils.str_filter_out('^lib', 'libfoo bar libbaz', d)",
+        expected: "bar",
+    },
+    Snippet {
+        class: "package.bbclass",
+        vars: &[],
+        flags: &[],
+        expr: "oe.utils.str_filter('lib', 'libfoo bar libbaz', d)",
+        expected: "libfoo libbaz",
+    },
+    Snippet {
+        class: "license.bbclass",
+        vars: &[("A", "GPL-2.0 MIT"), ("B", "MIT BSD")],
+        flags: &[],
+        expr: "oe.utils.set_intersect('A', 'B', d)",
+        expected: "MIT",
+    },
+    Snippet {
+        class: "insane.bbclass",
+        vars: &[("A", "x11 pam"), ("B", "pam x11 opengl")],
+        flags: &[],
+        expr: "oe.utils.both_contain('A', 'B', 'x11', d)",
+        expected: "x11",
+    },
+    Snippet {
+        class: "staging.bbclass",
+        vars: &[],
+        flags: &[],
+        expr: "oe.path.relative('/usr/bin', '/usr/lib/libfoo.so')",
+        expected: "../lib/libfoo.so",
+    },
+    Snippet {
+        class: "staging.bbclass",
+        vars: &[],
+        flags: &[],
+        expr: "oe.path.join('/usr', '/lib', 'foo/')",
+        expected: "/usr/lib/foo",
+    },
+    Snippet {
+        class: "base.bbclass",
+        vars: &[("TOPDIR", "/build")],
+        flags: &[],
+        expr: "oe.path.format_display('/build/tmp/work', d)",
+        expected: "tmp/work",
+    },
+    Snippet {
+        class: "sstate.bbclass",
+        vars: &[],
+        flags: &[],
+        expr: "oe.path.is_path_parent('/usr', '/usr/lib', '/usr/bin/x')",
+        expected: "True",
+    },
+    Snippet {
+        class: "package.bbclass",
+        vars: &[],
+        flags: &[],
+        expr: "bb.utils.vercmp_string('1.2.10', '1.2.9')",
+        expected: "1",
+    },
+    Snippet {
+        class: "package.bbclass",
+        vars: &[],
+        flags: &[],
+        expr: "bb.utils.vercmp_string('1.0~rc1', '1.0')",
+        expected: "-1",
+    },
+    Snippet {
+        class: "glibc-version.inc",
+        vars: &[],
+        flags: &[],
+        expr: "bb.utils.vercmp_string_op('2.38', '2.37', '>=')",
+        expected: "True",
+    },
+    Snippet {
+        class: "package.bbclass",
+        vars: &[],
+        flags: &[],
+        expr: "bb.utils.split_version('1:2.3-r4')",
+        expected: "(1, '2.3', 'r4')",
+    },
+    Snippet {
+        class: "package.bbclass",
+        vars: &[("RDEPENDS", "foo (>= 1.0) bar baz (= 2.1)")],
+        flags: &[],
+        expr: "bb.utils.join_deps(bb.utils.explode_dep_versions2(d.getVar('RDEPENDS')), commasep=False)",
+        expected: "bar baz (= 2.1) foo (>= 1.0)",
+    },
+    Snippet {
+        class: "package.bbclass",
+        vars: &[],
+        flags: &[],
+        expr: "bb.utils.explode_dep_versions2('a (>= 1) a (< 2)')['a']",
+        expected: "['>= 1', '< 2']",
+    },
+    Snippet {
+        class: "package.bbclass",
+        vars: &[],
+        flags: &[],
+        expr: "' '.join(bb.utils.explode_deps('foo (>= 1.0) bar'))",
+        expected: "foo bar",
+    },
+    Snippet {
+        class: "image.bbclass",
+        vars: &[("IMAGE_FSTYPES", "ext4")],
+        flags: &[],
+        expr: "oe.types.choice('ipk', 'rpm deb ipk')",
+        expected: "ipk",
+    },
+    Snippet {
+        class: "rootfs",
+        vars: &[],
+        flags: &[],
+        expr: "oe.types.boolean('Yes')",
+        expected: "True",
+    },
+    Snippet {
+        class: "rootfs",
+        vars: &[],
+        flags: &[],
+        expr: "oe.types.integer('10', 16)",
+        expected: "16",
+    },
+    Snippet {
+        class: "rootfs",
+        vars: &[],
+        flags: &[],
+        expr: "str(oe.types.list('a,b', ','))",
+        expected: "a,b",
+    },
+    Snippet {
+        class: "rootfs",
+        vars: &[],
+        flags: &[],
+        expr: "oe.types.is_x86('i686')",
+        expected: "True",
+    },
+    Snippet {
+        class: "rootfs",
+        vars: &[],
+        flags: &[],
+        expr: "oe.types.path('lib/../bin', '/usr')",
+        expected: "/usr/bin",
+    },
+    Snippet {
+        class: "base.bbclass",
+        vars: &[],
+        flags: &[],
+        expr: "bb.utils.to_boolean('Yes')",
+        expected: "True",
+    },
+    Snippet {
+        class: "base.bbclass",
+        vars: &[("X", "gtk+3")],
+        flags: &[("PACKAGECONFIG", "gtk", "--enable-gtk,--disable-gtk,${X}"), ("PACKAGECONFIG", "doc", "--enable-doc")],
+        expr: "d.getVarFlags('PACKAGECONFIG', expand=['gtk'])['gtk']",
+        expected: "--enable-gtk,--disable-gtk,gtk+3",
+    },
+    Snippet {
+        class: "base.bbclass",
+        vars: &[],
+        flags: &[("PACKAGECONFIG", "gtk", "a"), ("PACKAGECONFIG", "doc", "b")],
+        expr: "sorted(d.getVarFlags('PACKAGECONFIG'))",
+        expected: "['doc', 'gtk']",
+    },
+    Snippet {
+        class: "base.bbclass",
+        vars: &[("PN", "zlib"), ("PV", "1.3")],
+        flags: &[],
+        expr: "d.expand('${PN}-${PV}')",
+        expected: "zlib-1.3",
+    },
+];
+
+fn evaluate(snippet: &Snippet) -> Result<Option<String>, String> {
+    let mut d = DataStore::new();
+    for (name, value) in snippet.vars {
+        d.set_var(name, *value);
+    }
+    for (name, flag, value) in snippet.flags {
+        d.set_var_flag(name, flag, *value);
+    }
+    let content = format!("python () {{\n    d.setVar(\"RESULT\", str({}))\n}}\n", snippet.expr);
+    d.parse_content(&content, Path::new("/layer/recipe.bb"))?;
+    parse_lifecycle::finalize_recipe(&mut d)?;
+    Ok(d.get_var("RESULT"))
+}
+
+#[test]
+fn test_poky_class_snippets() {
+    let mut failures = Vec::new();
+    for snippet in CORPUS {
+        match evaluate(snippet) {
+            Ok(Some(result)) if result == snippet.expected => {}
+            other => failures.push(format!(
+                "{}: {} => {:?}, expected {:?}",
+                snippet.class, snippet.expr, other, snippet.expected
+            )),
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn test_library_errors_surface_as_python_exceptions() {
+    let mut d = DataStore::new();
+    d.parse_content(
+        "python () {\n    oe.types.boolean('maybe')\n}\n",
+        Path::new("/layer/recipe.bb"),
+    )
+    .unwrap();
+    let err = parse_lifecycle::finalize_recipe(&mut d).unwrap_err();
+    assert!(err.contains("ValueError"), "{}", err);
+}
@@ -4,8 +4,8 @@
 //! progress tracking, and authentication support.
 
 use git2::{
-    build::CheckoutBuilder, Cred, FetchOptions, Oid, ProxyOptions, RemoteCallbacks,
-    Repository,
+    build::CheckoutBuilder, Cred, Direction, FetchOptions, Oid, ProxyOptions, Remote,
+    RemoteCallbacks, Repository,
 };
 use std::path::{Path, PathBuf};
 use tokio::task;
@@ -272,6 +272,69 @@ impl AsyncGitRepository {
     }
 }
 
+impl AsyncGitRepository {
+    /// Resolve a branch, tag or commit on the remote to a commit hash, without cloning
+    ///
+    /// Works like `git ls-remote`: branches are preferred over tags, annotated
+    /// tags are peeled to their commit, and `None` resolves the remote HEAD.
+    /// A full commit hash is returned unchanged.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GitError` if the remote cannot be listed or has no such reference
+    pub async fn remote_commit(&self, refspec: Option<&str>) -> GitResult<String> {
+        let url = self.url.clone();
+        let credentials = self.credentials.clone();
+        let refspec = refspec.map(str::to_string);
+
+        task::spawn_blocking(move || {
+            if let Some(refspec) = &refspec
+                && refspec.len() == 40
+                && Oid::from_str(refspec).is_ok()
+            {
+                return Ok(refspec.clone());
+            }
+
+            let mut callbacks = RemoteCallbacks::new();
+            if let Some(creds) = credentials {
+                callbacks.credentials(move |_url, _username_from_url, _allowed_types| {
+                    Cred::userpass_plaintext(&creds.username, &creds.password)
+                });
+            }
+
+            let mut remote = Remote::create_detached(url.as_str())?;
+            remote.connect_auth(Direction::Fetch, Some(callbacks), Some(proxy_opts_auto()))?;
+            let heads: Vec<(String, Oid)> = remote
+                .list()?
+                .iter()
+                .map(|head| (head.name().to_string(), head.oid()))
+                .collect();
+
+            let candidates = match &refspec {
+                None => vec!["HEAD".to_string()],
+                Some(name) if name.starts_with("refs/") => {
+                    vec![format!("{}^{{}}", name), name.clone()]
+                }
+                Some(name) => vec![
+                    format!("refs/heads/{}", name),
+                    format!("refs/tags/{}^{{}}", name),
+                    format!("refs/tags/{}", name),
+                ],
+            };
+
+            candidates
+                .iter()
+                .find_map(|candidate| heads.iter().find(|(name, _)| name == candidate))
+                .map(|(_, oid)| oid.to_string())
+                .ok_or_else(|| {
+                    GitError::InvalidReference(refspec.unwrap_or_else(|| "HEAD".to_string()))
+                })
+        })
+        .await
+        .map_err(|e| GitError::CloneFailed(e.to_string()))?
+    }
+}
+
 /// Helper function to setup proxy options
 fn proxy_opts_auto() -> ProxyOptions<'static> {
     let mut proxy_opts = ProxyOptions::new();
@@ -297,4 +360,42 @@ mod tests {
         // In real tests, you would use a local test repository
         assert_eq!(repo.path, temp.path());
     }
+
+    #[tokio::test]
+    async fn test_remote_commit() {
+        let temp = TempDir::new().unwrap();
+        let origin = Repository::init(temp.path()).unwrap();
+        let signature = git2::Signature::now("Test", "test@example.com").unwrap();
+        let tree_id = origin.index().unwrap().write_tree().unwrap();
+        let tree = origin.find_tree(tree_id).unwrap();
+        let first = origin
+            .commit(Some("HEAD"), &signature, &signature, "first", &tree, &[])
+            .unwrap();
+        let first_commit = origin.find_commit(first).unwrap();
+        origin
+            .tag("v1.0", first_commit.as_object(), &signature, "release", false)
+            .unwrap();
+        let second = origin
+            .commit(Some("HEAD"), &signature, &signature, "second", &tree, &[&first_commit])
+            .unwrap();
+        origin.branch("stable", &first_commit, false).unwrap();
+
+        let url = temp.path().to_string_lossy().to_string();
+        let repo = AsyncGitRepository::new(temp.path().join("unused"), url, None);
+
+        assert_eq!(repo.remote_commit(None).await.unwrap(), second.to_string());
+        assert_eq!(repo.remote_commit(Some("stable")).await.unwrap(), first.to_string());
+        // Annotated tags resolve to the tagged commit, not the tag object
+        assert_eq!(repo.remote_commit(Some("v1.0")).await.unwrap(), first.to_string());
+        assert_eq!(
+            repo.remote_commit(Some("refs/tags/v1.0")).await.unwrap(),
+            first.to_string()
+        );
+        let hash = "0123456789abcdef0123456789abcdef01234567";
+        assert_eq!(repo.remote_commit(Some(hash)).await.unwrap(), hash);
+        assert!(matches!(
+            repo.remote_commit(Some("missing")).await,
+            Err(GitError::InvalidReference(_))
+        ));
+    }
 }
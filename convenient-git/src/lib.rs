@@ -39,6 +39,8 @@ pub struct GitCommit {
     pub name: String,
     pub email: String,
     pub message: String,
+    /// Oids of the parent commits, the first parent first
+    pub parents: Vec<String>,
 }
 
 impl GitCommit {
@@ -48,6 +50,7 @@ impl GitCommit {
             name: commit.author().name().unwrap().to_string(),
             email: commit.author().email().unwrap().to_string(),
             message: commit.message().unwrap().to_string(),
+            parents: commit.parent_ids().map(|oid| oid.to_string()).collect(),
         }
     }
 }
@@ -385,6 +388,44 @@ impl GitRepository {
             None
         }
     }
+
+    /// Commits reachable from the remote branch `name`, newest first
+    ///
+    /// Commits reachable from any of `known` are left out, so passing the
+    /// previously imported head only returns the commits added since.
+    pub fn history(&self, name: &str, known: &[String]) -> Vec<GitCommit> {
+        let span = span!(Level::INFO, "history", value = name);
+        let _enter = span.enter();
+        let Some(repo) = &self.repo else {
+            error!("No repository found");
+            return Vec::new();
+        };
+        let walk = || -> Result<Vec<GitCommit>, git2::Error> {
+            let mut revwalk = repo.revwalk()?;
+            revwalk.push_ref(&format!("refs/remotes/origin/{name}"))?;
+            for oid in known {
+                // Commits that are no longer in the repository are not in the way
+                if let Ok(oid) = Oid::from_str(oid)
+                    && repo.find_commit(oid).is_ok()
+                {
+                    revwalk.hide(oid)?;
+                }
+            }
+            revwalk
+                .map(|oid| Ok(GitCommit::new(repo.find_commit(oid?)?)))
+                .collect()
+        };
+        match walk() {
+            Ok(commits) => {
+                info!("Found {} new commits on {}", commits.len(), name);
+                commits
+            }
+            Err(e) => {
+                error!("Error: {}", e);
+                Vec::new()
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -4,7 +4,7 @@
 //! needed for BitBake builds from merged kas configuration.
 
 use crate::include_graph::KasConfig;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
 
 /// Configuration generator
@@ -148,6 +148,79 @@ impl ConfigGenerator {
         Ok(())
     }
 
+    /// Environment of the build, as exported by the setup script
+    ///
+    /// `BUILDDIR` plus the kas environment, resolved against the current
+    /// environment like kas does: host values win, unset entries pass through.
+    pub fn environment(&self) -> BTreeMap<String, String> {
+        let mut env = self.kas_config.environment(|key| std::env::var(key).ok());
+        env.insert("BUILDDIR".to_string(), self.build_dir.display().to_string());
+        env
+    }
+
+    /// `oe-init-build-env` of the checked out layers
+    ///
+    /// The script sits next to poky's or oe-core's `meta` layer, so each
+    /// layer directory and its parent are searched.
+    pub fn init_script(&self) -> Option<PathBuf> {
+        let mut layers: Vec<&PathBuf> = self.layer_paths.values().flatten().collect();
+        layers.sort();
+        layers
+            .into_iter()
+            .flat_map(|layer| [Some(layer.as_path()), layer.parent()])
+            .flatten()
+            .map(|dir| dir.join("oe-init-build-env"))
+            .find(|script| script.is_file())
+    }
+
+    /// Environment of the build with `oe-init-build-env` sourced
+    ///
+    /// Like kas, sources the init script with the build directory in bash on
+    /// top of [`Self::environment`] and keeps what it changed, so `PATH` has
+    /// BitBake and the OE scripts. Without an init script this is
+    /// [`Self::environment`].
+    ///
+    /// # Errors
+    ///
+    /// Fails if bash cannot be run or the init script fails.
+    pub async fn init_environment(&self) -> Result<BTreeMap<String, String>, ConfigError> {
+        // The shell's own bookkeeping is not part of the build environment
+        const SHELL_VARS: [&str; 4] = ["_", "SHLVL", "PWD", "OLDPWD"];
+
+        let mut env = self.environment();
+        let Some(script) = self.init_script() else {
+            tracing::warn!("No oe-init-build-env in the layers, BitBake is not added to PATH");
+            return Ok(env);
+        };
+
+        let output = tokio::process::Command::new("bash")
+            .args(["-c", r#"set -e; source "$0" "$1" > /dev/null; env -0"#])
+            .arg(&script)
+            .arg(&self.build_dir)
+            .envs(&env)
+            .output()
+            .await
+            .map_err(|e| ConfigError::IoError(script.clone(), e.to_string()))?;
+        if !output.status.success() {
+            return Err(ConfigError::IoError(
+                script,
+                String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            ));
+        }
+
+        for entry in output.stdout.split(|&b| b == 0) {
+            let entry = String::from_utf8_lossy(entry);
+            let Some((key, value)) = entry.split_once('=') else {
+                continue;
+            };
+            if SHELL_VARS.contains(&key) || std::env::var(key).is_ok_and(|host| host == value) {
+                continue;
+            }
+            env.insert(key.to_string(), value.to_string());
+        }
+        Ok(env)
+    }
+
     /// Generate environment setup script
     pub async fn generate_env_setup(&self) -> Result<(), ConfigError> {
         let mut content = String::new();
@@ -158,14 +231,16 @@ impl ConfigGenerator {
         content.push_str("# Environment setup script generated by Bitzel\n");
         content.push_str("#\n\n");
 
-        content.push_str(&format!("export BUILDDIR=\"{}\"\n", self.build_dir.display()));
+        for (key, value) in &self.environment() {
+            content.push_str(&format!("export {}=\"{}\"\n", key, value));
+        }
 
-        // Custom environment variables from kas
-        if let Some(env) = &self.kas_config.env {
-            content.push_str("\n# Custom environment from kas\n");
-            for (key, value) in env {
-                content.push_str(&format!("export {}=\"{}\"\n", key, value));
-            }
+        if let Some(script) = self.init_script() {
+            content.push_str(&format!(
+                "\n. \"{}\" \"{}\" > /dev/null\n",
+                script.display(),
+                self.build_dir.display()
+            ));
         }
 
         content.push_str("\necho \"Build environment configured\"\n");
@@ -249,6 +324,7 @@ mod tests {
                 path: None,
                 layers: HashMap::new(),
                 patches: None,
+                ..Default::default()
             },
         );
 
@@ -264,6 +340,7 @@ mod tests {
             bblayers_conf_header: None,
             local_conf_header: None,
             env: None,
+            ..Default::default()
         };
 
         let generator = ConfigGenerator::new(temp.path(), config, HashMap::new());
@@ -276,4 +353,36 @@ mod tests {
         assert!(content.contains("MACHINE"));
         assert!(content.contains("qemux86-64"));
     }
+
+    #[tokio::test]
+    async fn test_init_environment_sources_oe_init_build_env() {
+        let temp = TempDir::new().unwrap();
+        let poky = temp.path().join("poky");
+        std::fs::create_dir_all(poky.join("meta/conf")).unwrap();
+        std::fs::write(
+            poky.join("oe-init-build-env"),
+            concat!(
+                "OEROOT=$(dirname \"${BASH_SOURCE[0]}\")\n",
+                "export PATH=\"$OEROOT/scripts:$OEROOT/bitbake/bin:$PATH\"\n",
+                "mkdir -p \"$1\" && cd \"$1\"\n",
+                "echo \"### Shell environment set up for builds. ###\"\n",
+            ),
+        )
+        .unwrap();
+
+        let build_dir = temp.path().join("build");
+        let layer_paths = HashMap::from([("poky".to_string(), vec![poky.join("meta")])]);
+        let generator = ConfigGenerator::new(&build_dir, KasConfig::default(), layer_paths);
+        assert_eq!(generator.init_script(), Some(poky.join("oe-init-build-env")));
+
+        let env = generator.init_environment().await.unwrap();
+        let path = &env["PATH"];
+        assert!(path.starts_with(&format!("{}/scripts:{}/bitbake/bin:", poky.display(), poky.display())));
+        assert_eq!(env["BUILDDIR"], build_dir.display().to_string());
+        assert!(!env.contains_key("SHLVL"));
+
+        generator.generate_env_setup().await.unwrap();
+        let setup = std::fs::read_to_string(build_dir.join("setup-environment")).unwrap();
+        assert!(setup.contains(&format!(". \"{}/oe-init-build-env\"", poky.display())));
+    }
 }
@@ -0,0 +1,125 @@
+//! Output of merged configurations, like `kas dump`
+//!
+//! The merged [`KasConfig`] is written with sorted repositories and layers so
+//! dumps are stable across runs. The annotated form marks every key with the
+//! file that set it, as a YAML comment that keeps the output loadable.
+
+use crate::include_graph::{KasConfig, KasIncludeGraph, key_name};
+use serde_yaml::{Mapping, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// The config as YAML
+pub fn to_yaml(config: &KasConfig) -> String {
+    serde_yaml::to_string(&to_value(config)).unwrap_or_default()
+}
+
+/// The merged config of `graph` as YAML, each key commented with the file that set it
+///
+/// File names are relative to the directory of the root kas file.
+pub fn to_annotated_yaml(graph: &KasIncludeGraph) -> String {
+    let sources = graph.key_sources();
+    let base = graph.root().path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut out = String::new();
+    if let Value::Mapping(map) = to_value(&graph.merge_config()) {
+        write_mapping(&mut out, &map, 0, "", &sources, base);
+    }
+    out
+}
+
+/// The config as a YAML value, with everything below the top level sorted by key
+fn to_value(config: &KasConfig) -> Value {
+    match serde_yaml::to_value(config).unwrap_or_default() {
+        Value::Mapping(map) => Value::Mapping(map.into_iter().map(|(k, v)| (k, sorted(v))).collect()),
+        other => other,
+    }
+}
+
+fn sorted(value: Value) -> Value {
+    match value {
+        Value::Mapping(map) => {
+            let mut entries: Vec<(Value, Value)> = map.into_iter().collect();
+            entries.sort_by_key(|(key, _)| key_name(key));
+            Value::Mapping(entries.into_iter().map(|(k, v)| (k, sorted(v))).collect())
+        }
+        Value::Sequence(items) => Value::Sequence(items.into_iter().map(sorted).collect()),
+        other => other,
+    }
+}
+
+fn write_mapping(
+    out: &mut String,
+    map: &Mapping,
+    indent: usize,
+    prefix: &str,
+    sources: &BTreeMap<String, PathBuf>,
+    base: &Path,
+) {
+    let pad = " ".repeat(indent);
+    for (key, value) in map {
+        let name = key_name(key);
+        let path = if prefix.is_empty() {
+            name
+        } else {
+            format!("{}.{}", prefix, name)
+        };
+        let comment = sources
+            .get(&path)
+            .map(|file| format!("  # {}", file.strip_prefix(base).unwrap_or(file).display()))
+            .unwrap_or_default();
+        let key = render(key);
+        let key = key.trim_end();
+
+        match value {
+            Value::Mapping(inner) if !inner.is_empty() => {
+                push_line(out, &format!("{}{}:{}", pad, key, comment));
+                write_mapping(out, inner, indent + 2, &path, sources, base);
+            }
+            Value::Sequence(items) if !items.is_empty() => {
+                push_line(out, &format!("{}{}:{}", pad, key, comment));
+                for item in items {
+                    for (i, line) in render(item).lines().enumerate() {
+                        let marker = if i == 0 { "- " } else { "  " };
+                        push_line(out, &format!("{}{}{}", pad, marker, line));
+                    }
+                }
+            }
+            _ => {
+                // Multi-line strings render as a block scalar; the comment goes after its indicator
+                let text = render(value);
+                let mut lines = text.lines();
+                let first = lines.next().unwrap_or_default();
+                push_line(out, &format!("{}{}: {}{}", pad, key, first, comment));
+                for line in lines {
+                    push_line(out, &format!("{}{}", pad, line));
+                }
+            }
+        }
+    }
+}
+
+fn render(value: &Value) -> String {
+    serde_yaml::to_string(value).unwrap_or_default()
+}
+
+fn push_line(out: &mut String, line: &str) {
+    out.push_str(line.trim_end());
+    out.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_yaml_is_sorted() {
+        let config: KasConfig = serde_yaml::from_str(
+            "header:\n  version: 14\nmachine: qemux86-64\nrepos:\n  zeta:\n    url: z\n  alpha:\n    url: a\n",
+        )
+        .unwrap();
+        let yaml = to_yaml(&config);
+        assert!(yaml.find("alpha").unwrap() < yaml.find("zeta").unwrap(), "{}", yaml);
+        assert!(yaml.find("header").unwrap() < yaml.find("machine").unwrap(), "{}", yaml);
+    }
+}
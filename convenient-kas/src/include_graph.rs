@@ -6,27 +6,47 @@
 //! - Merged configuration generation
 //! - Repository and layer management
 
-use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_yaml::{Mapping, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use sha2::{Digest, Sha256};
 
+/// Newest kas configuration format version understood
+pub const KAS_FORMAT_VERSION: u32 = 17;
+
 /// Complete kas configuration (header + content)
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
 pub struct KasConfig {
     /// Kas file header with version and includes
     pub header: KasHeader,
+    /// Build system the layers belong to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build_system: Option<KasBuildSystem>,
+    /// Defaults for properties that repositories leave unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub defaults: Option<KasDefaults>,
+    /// Pinned commits (as written by `kas lock`), applied over `repos`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub overrides: Option<KasOverrides>,
     /// Target machine (e.g., "qemux86-64")
     #[serde(skip_serializing_if = "Option::is_none")]
     pub machine: Option<String>,
     /// Distribution to build (e.g., "poky")
     #[serde(skip_serializing_if = "Option::is_none")]
     pub distro: Option<String>,
-    /// Build targets/recipes (e.g., ["core-image-minimal"])
-    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Build targets/recipes (e.g., ["core-image-minimal"]); a single string is accepted
+    #[serde(
+        default,
+        deserialize_with = "string_or_list",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub target: Option<Vec<String>>,
-    /// Repository configurations
-    #[serde(default)]
+    /// Task to run on the targets (e.g., "build")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task: Option<String>,
+    /// Repository configurations; an empty entry is the repo containing the kas file
+    #[serde(default, deserialize_with = "null_as_default_values")]
     pub repos: HashMap<String, KasRepo>,
     /// Custom headers for bblayers.conf
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -34,27 +54,213 @@ pub struct KasConfig {
     /// Custom headers for local.conf
     #[serde(skip_serializing_if = "Option::is_none")]
     pub local_conf_header: Option<HashMap<String, String>>,
-    /// Environment variables to set
+    /// Environment variables to set; values are defaults the host environment
+    /// overrides, `None` passes the host variable through
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub env: Option<HashMap<String, Option<String>>>,
+    /// Proxy variables (deprecated by kas in favour of `env`)
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub env: Option<HashMap<String, String>>,
+    pub proxy_config: Option<HashMap<String, String>>,
+    /// Kconfig symbols selected with `kas menu`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub menu_configuration: Option<HashMap<String, KasMenuValue>>,
+    /// Files a build is expected to produce, by name (globs relative to the build dir)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artifacts: Option<HashMap<String, String>>,
+    /// Keys to verify `signed` repositories with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signers: Option<HashMap<String, KasSigner>>,
+    /// Source directory recorded by `kas dump`/`kas menu`
+    #[serde(rename = "_source_dir", skip_serializing_if = "Option::is_none")]
+    pub source_dir: Option<String>,
+    /// Host source directory recorded when kas ran in a container
+    #[serde(rename = "_source_dir_host", skip_serializing_if = "Option::is_none")]
+    pub source_dir_host: Option<String>,
+}
+
+impl KasConfig {
+    /// Repositories with `defaults` and `overrides` applied, as kas checks them out
+    pub fn effective_repos(&self) -> HashMap<String, KasRepo> {
+        let defaults = self.defaults.as_ref().and_then(|d| d.repos.as_ref());
+        let overrides = self.overrides.as_ref().and_then(|o| o.repos.as_ref());
+
+        self.repos
+            .iter()
+            .map(|(name, repo)| {
+                let mut repo = repo.clone();
+                if let Some(defaults) = defaults
+                    && repo.url.is_some()
+                {
+                    repo.apply_defaults(defaults);
+                }
+                if let Some(commit) = overrides
+                    .and_then(|o| o.get(name))
+                    .and_then(|o| o.commit.clone())
+                {
+                    repo.commit = Some(commit);
+                }
+                (name.clone(), repo)
+            })
+            .collect()
+    }
+
+    /// Environment for the build
+    ///
+    /// `env` and `proxy_config` values are defaults: `host` wins when it has the
+    /// variable. Entries without a value are only passed through from `host`.
+    pub fn environment(&self, host: impl Fn(&str) -> Option<String>) -> BTreeMap<String, String> {
+        let proxies = self
+            .proxy_config
+            .iter()
+            .flatten()
+            .map(|(key, value)| (key, Some(value)));
+        let env = self
+            .env
+            .iter()
+            .flatten()
+            .map(|(key, value)| (key, value.as_ref()));
+
+        proxies
+            .chain(env)
+            .filter_map(|(key, default)| {
+                host(key)
+                    .or_else(|| default.cloned())
+                    .map(|value| (key.clone(), value))
+            })
+            .collect()
+    }
 }
 
 /// Kas file header with version and includes
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
 pub struct KasHeader {
-    /// Kas format version (current: 14)
+    /// Kas format version (1 to [`KAS_FORMAT_VERSION`]; the legacy "0.10" reads as 1)
+    #[serde(deserialize_with = "format_version")]
     pub version: u32,
-    /// List of kas files to include
+    /// Kas files to include
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub includes: Option<Vec<String>>,
+    pub includes: Option<Vec<KasInclude>>,
 }
 
-/// Repository configuration
+/// Entry of `header.includes`
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum KasInclude {
+    /// Path relative to the including file
+    File(String),
+    /// File inside another repository of the configuration
+    Repo {
+        /// Repository id in `repos`
+        repo: String,
+        /// Path relative to the repository root
+        file: String,
+    },
+}
+
+/// Build system of the layers (`build_system`)
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum KasBuildSystem {
+    /// OpenEmbedded/Yocto (also spelled "oe")
+    #[serde(alias = "oe")]
+    Openembedded,
+    /// Isar (Debian based)
+    Isar,
+}
+
+/// `defaults` section
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct KasDefaults {
+    /// Defaults for every repository
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repos: Option<KasRepoDefaults>,
+}
+
+/// `defaults.repos` section
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct KasRepoDefaults {
+    /// Default refspec (deprecated by kas in favour of branch/tag)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refspec: Option<String>,
+    /// Default branch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// Default tag
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    /// Defaults for patch entries
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub patches: Option<KasPatchDefaults>,
+}
+
+/// `defaults.repos.patches` section
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct KasPatchDefaults {
+    /// Repository patch paths are relative to when a patch names none
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repo: Option<String>,
+}
+
+/// `overrides` section
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct KasOverrides {
+    /// Per-repository overrides
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repos: Option<HashMap<String, KasRepoOverride>>,
+}
+
+/// `overrides.repos.<name>` entry
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct KasRepoOverride {
+    /// Commit to check out instead of the configured one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+}
+
+/// Value of a `menu_configuration` symbol
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum KasMenuValue {
+    /// Boolean symbol
+    Bool(bool),
+    /// Integer symbol
+    Integer(i64),
+    /// String (or tristate) symbol
+    String(String),
+}
+
+/// `signers.<name>` entry
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct KasSigner {
+    /// Key type: "gpg" (default) or "ssh"
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub signer_type: Option<String>,
+    /// Repository containing the key file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repo: Option<String>,
+    /// Key file relative to `repo`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Key fingerprint
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+    /// Keyserver to fetch a gpg key from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gpg_keyserver: Option<String>,
+}
+
+/// Repository configuration
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
 pub struct KasRepo {
+    /// Checkout directory name (defaults to the repository id)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
     /// Git repository URL
     #[serde(skip_serializing_if = "Option::is_none")]
     pub url: Option<String>,
+    /// Version control system
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub repo_type: Option<KasRepoType>,
     /// Git refspec to checkout
     #[serde(skip_serializing_if = "Option::is_none")]
     pub refspec: Option<String>,
@@ -70,20 +276,158 @@ pub struct KasRepo {
     /// Local path to repository (alternative to URL)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub path: Option<String>,
+    /// Whether the checked out commit or tag must carry a valid signature
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signed: Option<bool>,
+    /// `signers` allowed to sign this repository
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allowed_signers: Option<Vec<String>>,
     /// Layers within this repository
     #[serde(default)]
     pub layers: HashMap<String, KasLayer>,
-    /// Patches to apply to this repository
+    /// Patches to apply to this repository, by id; `None` drops an included patch
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub patches: Option<HashMap<String, Vec<String>>>,
+    pub patches: Option<HashMap<String, Option<KasPatch>>>,
 }
 
-/// Layer configuration within a repository
+impl KasRepo {
+    fn apply_defaults(&mut self, defaults: &KasRepoDefaults) {
+        if self.refspec.is_none() {
+            if self.commit.is_none() && self.branch.is_none() && self.tag.is_none() {
+                self.refspec.clone_from(&defaults.refspec);
+            }
+            if self.refspec.is_none() {
+                self.branch = self.branch.take().or_else(|| defaults.branch.clone());
+                self.tag = self.tag.take().or_else(|| defaults.tag.clone());
+            }
+        }
+        if let Some(repo) = defaults.patches.as_ref().and_then(|p| p.repo.as_ref()) {
+            for patch in self.patches.iter_mut().flat_map(HashMap::values_mut).flatten() {
+                patch.repo.get_or_insert_with(|| repo.clone());
+            }
+        }
+    }
+
+    /// Layers that are not disabled
+    pub fn enabled_layers(&self) -> impl Iterator<Item = (&String, &KasLayer)> {
+        self.layers.iter().filter(|(_, layer)| !layer.disabled)
+    }
+}
+
+/// Version control system of a repository
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum KasRepoType {
+    /// Git (default)
+    Git,
+    /// Mercurial
+    Hg,
+}
+
+/// `repos.<name>.patches.<id>` entry
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct KasPatch {
+    /// Repository the patch path is relative to (defaults to `defaults.repos.patches.repo`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repo: Option<String>,
+    /// Patch file, or directory with a quilt series file
+    pub path: String,
+}
+
+/// Layer configuration within a repository
+///
+/// In YAML a layer is empty (enabled), one of "disabled", "excluded", "n",
+/// "no", "0" or "false", or a mapping with a custom `path`.
+#[derive(Debug, Clone, PartialEq, Default)]
 pub struct KasLayer {
     /// Relative path to layer within repository
-    #[serde(skip_serializing_if = "Option::is_none")]
     pub path: Option<String>,
+    /// Layer is listed but not added to bblayers.conf
+    pub disabled: bool,
+}
+
+const DISABLED_LAYER_VALUES: &[&str] = &["disabled", "excluded", "n", "no", "0", "false"];
+
+impl<'de> Deserialize<'de> for KasLayer {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct LayerPath {
+            path: Option<String>,
+        }
+
+        match Value::deserialize(deserializer)? {
+            Value::Null => Ok(Self::default()),
+            Value::Mapping(map) => {
+                let LayerPath { path } = serde_yaml::from_value(Value::Mapping(map))
+                    .map_err(serde::de::Error::custom)?;
+                Ok(Self { path, disabled: false })
+            }
+            Value::String(s) => Ok(Self {
+                path: None,
+                disabled: DISABLED_LAYER_VALUES.contains(&s.to_lowercase().as_str()),
+            }),
+            Value::Bool(enabled) => Ok(Self { path: None, disabled: !enabled }),
+            Value::Number(n) => Ok(Self { path: None, disabled: n.as_i64() == Some(0) }),
+            other => Err(serde::de::Error::custom(format!("invalid layer entry: {:?}", other))),
+        }
+    }
+}
+
+impl Serialize for KasLayer {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        if self.disabled {
+            serializer.serialize_str("disabled")
+        } else if let Some(path) = &self.path {
+            let mut map = serializer.serialize_map(Some(1))?;
+            map.serialize_entry("path", path)?;
+            map.end()
+        } else {
+            serializer.serialize_unit()
+        }
+    }
+}
+
+fn format_version<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    match Value::deserialize(deserializer)? {
+        Value::String(s) if s == "0.10" => Ok(1),
+        Value::Number(n) => n
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid header version {}", n))),
+        other => Err(serde::de::Error::custom(format!("invalid header version {:?}", other))),
+    }
+}
+
+fn string_or_list<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Vec<String>>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrList {
+        String(String),
+        List(Vec<String>),
+    }
+
+    Ok(match Option::<StringOrList>::deserialize(deserializer)? {
+        None => None,
+        Some(StringOrList::String(s)) => Some(vec![s]),
+        Some(StringOrList::List(list)) => Some(list),
+    })
+}
+
+fn null_as_default_values<'de, D, T>(deserializer: D) -> Result<HashMap<String, T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Default,
+{
+    let map = Option::<HashMap<String, Option<T>>>::deserialize(deserializer)?;
+    Ok(map
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(key, value)| (key, value.unwrap_or_default()))
+        .collect())
 }
 
 /// Kas file with metadata
@@ -93,6 +437,8 @@ pub struct KasFile {
     pub path: PathBuf,
     /// Parsed configuration
     pub config: KasConfig,
+    /// The file as YAML, which is what includes are merged on
+    pub value: Value,
     /// SHA256 checksum for cache invalidation
     pub checksum: String,
 }
@@ -108,10 +454,16 @@ impl KasFile {
         let checksum = Self::calculate_checksum(&content);
         let config: KasConfig = serde_yaml::from_str(&content)
             .map_err(|e| KasError::ParseError(path.to_path_buf(), e.to_string()))?;
+        if config.header.version > KAS_FORMAT_VERSION {
+            return Err(KasError::UnsupportedVersion(path.to_path_buf(), config.header.version));
+        }
+        let value = serde_yaml::from_str(&content)
+            .map_err(|e| KasError::ParseError(path.to_path_buf(), e.to_string()))?;
 
         Ok(Self {
             path: path.to_path_buf(),
             config,
+            value,
             checksum,
         })
     }
@@ -123,19 +475,32 @@ impl KasFile {
         format!("{:x}", hasher.finalize())
     }
 
-    /// Get all include paths from this kas file
+    /// Get the include paths of this kas file that are relative to it
+    ///
+    /// Includes from other repositories need their checkout; see
+    /// [`KasFile::resolve_include`].
     pub fn includes(&self) -> Vec<PathBuf> {
         self.config
             .header
             .includes
-            .as_ref()
-            .map(|includes| {
-                includes
-                    .iter()
-                    .map(|inc| self.resolve_include_path(inc))
-                    .collect()
-            })
-            .unwrap_or_default()
+            .iter()
+            .flatten()
+            .filter_map(|inc| self.resolve_include(inc, &HashMap::new()))
+            .collect()
+    }
+
+    /// Resolve an include, looking up repository includes in `repo_paths`
+    ///
+    /// Returns `None` for a repository that is not in `repo_paths` (not checked out yet).
+    pub fn resolve_include(
+        &self,
+        include: &KasInclude,
+        repo_paths: &HashMap<String, PathBuf>,
+    ) -> Option<PathBuf> {
+        match include {
+            KasInclude::File(file) => Some(self.resolve_include_path(file)),
+            KasInclude::Repo { repo, file } => repo_paths.get(repo).map(|path| path.join(file)),
+        }
     }
 
     /// Resolve include path relative to this kas file
@@ -152,32 +517,69 @@ pub struct KasIncludeGraph {
     files: HashMap<PathBuf, KasFile>,
     /// Include dependencies (path -> included paths)
     dependencies: HashMap<PathBuf, Vec<PathBuf>>,
+    /// Repositories with includes that are not checked out yet
+    missing_repos: Vec<String>,
     /// Root kas file path
     root: PathBuf,
+    /// Lock file of the root, merged last
+    lock: Option<PathBuf>,
 }
 
 impl KasIncludeGraph {
     /// Build include graph starting from root kas file
     pub async fn build(root_path: impl AsRef<Path>) -> Result<Self, KasError> {
+        Self::build_with_repos(root_path, &HashMap::new()).await
+    }
+
+    /// Build include graph, resolving `{repo, file}` includes against checked out repositories
+    ///
+    /// Includes from repositories missing in `repo_paths` are skipped and listed in
+    /// [`KasIncludeGraph::missing_repos`]; like kas, check those out and build again.
+    /// A lock file next to the root (see [`crate::lock::lock_path`]) is merged last.
+    pub async fn build_with_repos(
+        root_path: impl AsRef<Path>,
+        repo_paths: &HashMap<String, PathBuf>,
+    ) -> Result<Self, KasError> {
         let root_path = root_path.as_ref().to_path_buf();
         let mut files = HashMap::new();
         let mut dependencies = HashMap::new();
         let mut visited = HashSet::new();
-
-        Self::build_recursive(&root_path, &mut files, &mut dependencies, &mut visited).await?;
+        let mut missing_repos = Vec::new();
+
+        Self::build_recursive(
+            &root_path,
+            repo_paths,
+            &mut files,
+            &mut dependencies,
+            &mut visited,
+            &mut missing_repos,
+        )
+        .await?;
+
+        let lock_path = crate::lock::lock_path(&root_path);
+        let lock = if lock_path != root_path && !files.contains_key(&lock_path) && lock_path.exists() {
+            files.insert(lock_path.clone(), KasFile::load(&lock_path).await?);
+            Some(lock_path)
+        } else {
+            None
+        };
 
         Ok(Self {
             files,
             dependencies,
+            missing_repos,
             root: root_path,
+            lock,
         })
     }
 
     fn build_recursive<'a>(
         path: &'a PathBuf,
+        repo_paths: &'a HashMap<String, PathBuf>,
         files: &'a mut HashMap<PathBuf, KasFile>,
         dependencies: &'a mut HashMap<PathBuf, Vec<PathBuf>>,
         visited: &'a mut HashSet<PathBuf>,
+        missing_repos: &'a mut Vec<String>,
     ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), KasError>> + 'a>> {
         Box::pin(async move {
             if visited.contains(path) {
@@ -187,13 +589,33 @@ impl KasIncludeGraph {
             visited.insert(path.clone());
 
             let kas_file = KasFile::load(path).await?;
-            let includes = kas_file.includes();
+            let mut includes = Vec::new();
+            for include in kas_file.config.header.includes.iter().flatten() {
+                match kas_file.resolve_include(include, repo_paths) {
+                    Some(include_path) => includes.push(include_path),
+                    None => {
+                        if let KasInclude::Repo { repo, .. } = include
+                            && !missing_repos.contains(repo)
+                        {
+                            missing_repos.push(repo.clone());
+                        }
+                    }
+                }
+            }
 
             dependencies.insert(path.clone(), includes.clone());
 
             // Recursively process includes
             for include_path in includes {
-                Self::build_recursive(&include_path, files, dependencies, visited).await?;
+                Self::build_recursive(
+                    &include_path,
+                    repo_paths,
+                    files,
+                    dependencies,
+                    visited,
+                    missing_repos,
+                )
+                .await?;
             }
 
             files.insert(path.clone(), kas_file);
@@ -202,12 +624,15 @@ impl KasIncludeGraph {
         })
     }
 
-    /// Get topologically sorted kas files (dependencies first)
+    /// Get topologically sorted kas files (dependencies first, lock file last)
     pub fn sorted_files(&self) -> Vec<&KasFile> {
         let mut result = Vec::new();
         let mut visited = HashSet::new();
 
         self.visit_sorted(&self.root, &mut visited, &mut result);
+        if let Some(lock) = self.lock.as_ref().and_then(|lock| self.files.get(lock)) {
+            result.push(lock);
+        }
 
         result
     }
@@ -238,64 +663,118 @@ impl KasIncludeGraph {
     }
 
     /// Merge all kas configs in dependency order
+    ///
+    /// Like kas, files are merged as YAML: mappings merge key by key, any other
+    /// value (including lists and null) replaces what the includes set. So a
+    /// `repos: {x: null}` entry drops an included definition of `x`, and a layer
+    /// set to "disabled" removes an included layer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the merged YAML does not form a valid config, which cannot happen
+    /// when every file parsed: each merged value comes from, or merges, valid files.
     pub fn merge_config(&self) -> KasConfig {
-        let mut merged = KasConfig {
-            header: KasHeader {
-                version: 14, // Use latest version
-                includes: None,
-            },
-            machine: None,
-            distro: None,
-            target: None,
-            repos: HashMap::new(),
-            bblayers_conf_header: None,
-            local_conf_header: None,
-            env: None,
-        };
+        let mut merged = Value::Mapping(Mapping::new());
 
         // Merge in dependency order (includes first, root last)
         for file in self.sorted_files() {
-            Self::merge_into(&mut merged, &file.config);
+            let dropped = Self::dropped_repos(&file.value, |name| {
+                merged.get("repos").and_then(|repos| repos.get(name)).is_some()
+            });
+            Self::merge_into(&mut merged, &file.value);
+            if let Some(Value::Mapping(repos)) = merged.get_mut("repos") {
+                for name in dropped {
+                    repos.remove(name.as_str());
+                }
+            }
         }
 
-        merged
+        // The result stands alone: no includes, current format version
+        let mut header = Mapping::new();
+        header.insert("version".into(), KAS_FORMAT_VERSION.into());
+        if let Value::Mapping(map) = &mut merged {
+            map.insert("header".into(), Value::Mapping(header));
+        }
+
+        serde_yaml::from_value(merged).expect("merged kas files must form a valid config")
     }
 
-    fn merge_into(base: &mut KasConfig, overlay: &KasConfig) {
-        // Machine/distro/target: overlay wins
-        if overlay.machine.is_some() {
-            base.machine = overlay.machine.clone();
-        }
-        if overlay.distro.is_some() {
-            base.distro = overlay.distro.clone();
-        }
-        if overlay.target.is_some() {
-            base.target = overlay.target.clone();
-        }
+    /// Repositories a file sets to null that an earlier file defined
+    ///
+    /// kas drops these from the merged configuration. A null repository that
+    /// nothing defined before stays: it stands for the repo containing the file.
+    fn dropped_repos(value: &Value, defined: impl Fn(&str) -> bool) -> Vec<String> {
+        let Some(Value::Mapping(repos)) = value.get("repos") else {
+            return Vec::new();
+        };
+        repos
+            .iter()
+            .filter(|(_, repo)| repo.is_null())
+            .filter_map(|(name, _)| name.as_str())
+            .filter(|name| defined(name))
+            .map(str::to_string)
+            .collect()
+    }
 
-        // Repos: merge repositories
-        for (name, repo) in &overlay.repos {
-            base.repos.insert(name.clone(), repo.clone());
+    fn merge_into(base: &mut Value, overlay: &Value) {
+        match (base, overlay) {
+            (Value::Mapping(base_map), Value::Mapping(overlay_map)) => {
+                for (key, value) in overlay_map {
+                    match base_map.get_mut(key) {
+                        Some(base_value) => Self::merge_into(base_value, value),
+                        None => {
+                            base_map.insert(key.clone(), value.clone());
+                        }
+                    }
+                }
+            }
+            (base, overlay) => *base = overlay.clone(),
         }
+    }
 
-        // Headers: merge maps
-        Self::merge_header_map(&mut base.bblayers_conf_header, &overlay.bblayers_conf_header);
-        Self::merge_header_map(&mut base.local_conf_header, &overlay.local_conf_header);
-        Self::merge_header_map(&mut base.env, &overlay.env);
+    /// The file that last set each key of the merged config
+    ///
+    /// Keys are paths of mapping keys joined by `.`, like `repos.poky.branch`. A
+    /// mapping is attributed to the last file that contributed to it; a value
+    /// replacing a mapping drops the entries below it. The header is left out,
+    /// since the merged config has its own.
+    pub fn key_sources(&self) -> BTreeMap<String, PathBuf> {
+        let mut sources = BTreeMap::new();
+        for file in self.sorted_files() {
+            let dropped = Self::dropped_repos(&file.value, |name| {
+                sources.contains_key(&format!("repos.{name}"))
+            });
+            if let Value::Mapping(map) = &file.value {
+                for (key, value) in map {
+                    if key.as_str() != Some("header") {
+                        Self::record_sources(&mut sources, &key_name(key), value, &file.path);
+                    }
+                }
+            }
+            for name in dropped {
+                sources.remove(&format!("repos.{name}"));
+            }
+        }
+        sources
     }
 
-    fn merge_header_map(
-        base: &mut Option<HashMap<String, String>>,
-        overlay: &Option<HashMap<String, String>>,
-    ) {
-        if let Some(overlay_map) = overlay {
-            let base_map = base.get_or_insert_with(HashMap::new);
-            for (key, value) in overlay_map {
-                base_map.insert(key.clone(), value.clone());
+    fn record_sources(sources: &mut BTreeMap<String, PathBuf>, key: &str, value: &Value, path: &Path) {
+        sources.insert(key.to_string(), path.to_path_buf());
+        if let Value::Mapping(map) = value {
+            for (child, value) in map {
+                Self::record_sources(sources, &format!("{}.{}", key, key_name(child)), value, path);
             }
+        } else {
+            let below = format!("{}.", key);
+            sources.retain(|existing, _| !existing.starts_with(&below));
         }
     }
 
+    /// Repositories whose includes could not be resolved (see [`KasIncludeGraph::build_with_repos`])
+    pub fn missing_repos(&self) -> &[String] {
+        &self.missing_repos
+    }
+
     /// Get combined checksum of all kas files in graph
     pub fn combined_checksum(&self) -> String {
         let mut hasher = Sha256::new();
@@ -324,6 +803,16 @@ impl KasIncludeGraph {
     }
 }
 
+/// Name of a mapping key as used in key paths
+pub(crate) fn key_name(key: &Value) -> String {
+    match key {
+        Value::String(name) => name.clone(),
+        other => serde_yaml::to_string(other)
+            .map(|text| text.trim_end().to_string())
+            .unwrap_or_default(),
+    }
+}
+
 /// Kas error types
 #[derive(Debug, thiserror::Error)]
 pub enum KasError {
@@ -346,6 +835,14 @@ pub enum KasError {
     /// Invalid repository configuration
     #[error("Invalid repository configuration: {0}")]
     InvalidRepo(String),
+
+    /// File uses a newer format than supported
+    #[error("{0} uses kas format version {1}, newer than the supported {max}", max = KAS_FORMAT_VERSION)]
+    UnsupportedVersion(PathBuf, u32),
+
+    /// Repository revision could not be resolved to a commit
+    #[error("Failed to resolve repository {0}: {1}")]
+    ResolveFailed(String, String),
 }
 
 #[cfg(test)]
@@ -5,6 +5,8 @@
 //! - Repository cloning and management
 //! - BitBake configuration generation
 //! - Checksum tracking for cache invalidation
+//! - Lock files pinning repositories to commits
+//! - Merged configuration dumps, optionally annotated with their sources
 
 #![warn(
     missing_docs,
@@ -22,13 +24,17 @@
 pub mod include_graph;
 pub mod repository_manager;
 pub mod config_generator;
+pub mod lock;
+pub mod dump;
 
 // Re-export main types
 pub use include_graph::{
-    KasConfig, KasError, KasFile, KasHeader, KasIncludeGraph, KasLayer, KasRepo,
+    KAS_FORMAT_VERSION, KasBuildSystem, KasConfig, KasError, KasFile, KasHeader, KasInclude,
+    KasIncludeGraph, KasLayer, KasPatch, KasRepo,
 };
 pub use repository_manager::{RepoError, RepositoryManager};
 pub use config_generator::{ConfigError, ConfigGenerator};
+pub use lock::{KasLock, LockChange};
 
 use std::{collections::HashMap, path::Path};
 
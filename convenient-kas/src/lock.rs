@@ -0,0 +1,253 @@
+//! Lock files pinning repositories to commits
+//!
+//! Like `kas lock`, the lock file of `kas.yml` is `kas.lock.yml` next to it and
+//! only holds `overrides.repos.<name>.commit` entries. [`KasIncludeGraph`]
+//! merges it after the root file, so builds check out the locked commits until
+//! the lock is updated.
+//!
+//! [`KasIncludeGraph`]: crate::KasIncludeGraph
+
+use crate::include_graph::{KAS_FORMAT_VERSION, KasConfig, KasError, KasFile};
+use convenient_git::async_git::AsyncGitRepository;
+use serde_yaml::{Mapping, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Path of the lock file belonging to `kas_file` (`<stem>.lock.yml`)
+pub fn lock_path(kas_file: &Path) -> PathBuf {
+    let stem = kas_file
+        .file_stem()
+        .map_or_else(|| "kas".into(), |stem| stem.to_string_lossy());
+    kas_file.with_file_name(format!("{}.lock.yml", stem))
+}
+
+/// Locked commit per repository
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct KasLock {
+    /// Repository name -> commit SHA
+    pub commits: BTreeMap<String, String>,
+}
+
+/// Change of one repository between two locks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LockChange {
+    /// Repository was not locked before
+    Added {
+        /// Repository name
+        repo: String,
+        /// New commit
+        commit: String,
+    },
+    /// Repository moved to another commit
+    Updated {
+        /// Repository name
+        repo: String,
+        /// Previously locked commit
+        old: String,
+        /// New commit
+        new: String,
+    },
+    /// Repository is no longer locked
+    Removed {
+        /// Repository name
+        repo: String,
+        /// Previously locked commit
+        commit: String,
+    },
+    /// Repository stays on the same commit
+    Unchanged {
+        /// Repository name
+        repo: String,
+        /// Locked commit
+        commit: String,
+    },
+}
+
+impl fmt::Display for LockChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Added { repo, commit } => write!(f, "+ {}: {}", repo, commit),
+            Self::Updated { repo, old, new } => write!(f, "~ {}: {} -> {}", repo, old, new),
+            Self::Removed { repo, commit } => write!(f, "- {}: {}", repo, commit),
+            Self::Unchanged { repo, commit } => write!(f, "  {}: {}", repo, commit),
+        }
+    }
+}
+
+impl LockChange {
+    /// Name of the changed repository
+    pub fn repo(&self) -> &str {
+        match self {
+            Self::Added { repo, .. }
+            | Self::Updated { repo, .. }
+            | Self::Removed { repo, .. }
+            | Self::Unchanged { repo, .. } => repo,
+        }
+    }
+}
+
+impl KasLock {
+    /// Commits pinned by the `overrides` section of `config`
+    pub fn from_config(config: &KasConfig) -> Self {
+        let commits = config
+            .overrides
+            .iter()
+            .filter_map(|overrides| overrides.repos.as_ref())
+            .flatten()
+            .filter_map(|(name, repo)| repo.commit.clone().map(|commit| (name.clone(), commit)))
+            .collect();
+        Self { commits }
+    }
+
+    /// Load a lock file, or `None` if there is none
+    ///
+    /// # Errors
+    ///
+    /// Returns `KasError` if the file exists but cannot be read or parsed
+    pub async fn load(path: impl AsRef<Path>) -> Result<Option<Self>, KasError> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(None);
+        }
+        let file = KasFile::load(path).await?;
+        Ok(Some(Self::from_config(&file.config)))
+    }
+
+    /// Resolve every floating repository of `config` to a commit
+    ///
+    /// Repositories with a `url` and no `commit` of their own are floating; they
+    /// resolve on the remote via their `tag`, `branch` or `refspec`, or the remote
+    /// HEAD. Without `update`, commits already pinned in `overrides` (typically
+    /// from the existing lock file) are kept.
+    ///
+    /// # Errors
+    ///
+    /// Returns `KasError::ResolveFailed` if a repository cannot be resolved
+    pub async fn resolve(config: &KasConfig, update: bool) -> Result<Self, KasError> {
+        let pinned = Self::from_config(config);
+        let unlocked = KasConfig {
+            overrides: None,
+            ..config.clone()
+        };
+
+        let mut commits = BTreeMap::new();
+        for (name, repo) in unlocked.effective_repos() {
+            let Some(url) = repo.url.as_deref() else {
+                continue;
+            };
+            if repo.commit.is_some() {
+                continue;
+            }
+            if !update && let Some(commit) = pinned.commits.get(&name) {
+                commits.insert(name, commit.clone());
+                continue;
+            }
+
+            let refspec = repo.tag.as_ref().or(repo.branch.as_ref()).or(repo.refspec.as_ref());
+            let commit = AsyncGitRepository::new(PathBuf::new(), url, None)
+                .remote_commit(refspec.map(String::as_str))
+                .await
+                .map_err(|e| KasError::ResolveFailed(name.clone(), e.to_string()))?;
+            tracing::debug!("Locked {} at {}", name, commit);
+            commits.insert(name, commit);
+        }
+
+        Ok(Self { commits })
+    }
+
+    /// Changes from `self` to `new`, sorted by repository name
+    pub fn diff(&self, new: &Self) -> Vec<LockChange> {
+        let mut changes: Vec<LockChange> = new
+            .commits
+            .iter()
+            .map(|(repo, commit)| {
+                let repo = repo.clone();
+                match self.commits.get(&repo) {
+                    None => LockChange::Added { repo, commit: commit.clone() },
+                    Some(old) if old == commit => LockChange::Unchanged { repo, commit: commit.clone() },
+                    Some(old) => LockChange::Updated { repo, old: old.clone(), new: commit.clone() },
+                }
+            })
+            .collect();
+        changes.extend(
+            self.commits
+                .iter()
+                .filter(|(repo, _)| !new.commits.contains_key(*repo))
+                .map(|(repo, commit)| LockChange::Removed {
+                    repo: repo.clone(),
+                    commit: commit.clone(),
+                }),
+        );
+        changes.sort_by(|a, b| a.repo().cmp(b.repo()));
+        changes
+    }
+
+    /// The lock file in kas format
+    pub fn to_yaml(&self) -> String {
+        let mut header = Mapping::new();
+        header.insert("version".into(), KAS_FORMAT_VERSION.into());
+
+        let mut repos = Mapping::new();
+        for (name, commit) in &self.commits {
+            let mut repo = Mapping::new();
+            repo.insert("commit".into(), commit.as_str().into());
+            repos.insert(name.as_str().into(), Value::Mapping(repo));
+        }
+        let mut overrides = Mapping::new();
+        overrides.insert("repos".into(), Value::Mapping(repos));
+
+        let mut lock = Mapping::new();
+        lock.insert("header".into(), Value::Mapping(header));
+        lock.insert("overrides".into(), Value::Mapping(overrides));
+
+        // Serializing a plain YAML mapping cannot fail
+        serde_yaml::to_string(&Value::Mapping(lock)).unwrap_or_default()
+    }
+
+    /// Write the lock file
+    ///
+    /// # Errors
+    ///
+    /// Returns `KasError::IoError` if the file cannot be written
+    pub async fn write(&self, path: impl AsRef<Path>) -> Result<(), KasError> {
+        let path = path.as_ref();
+        tokio::fs::write(path, self.to_yaml())
+            .await
+            .map_err(|e| KasError::IoError(path.to_path_buf(), e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_path() {
+        assert_eq!(lock_path(Path::new("/a/kas.yml")), PathBuf::from("/a/kas.lock.yml"));
+        assert_eq!(lock_path(Path::new("board.yaml")), PathBuf::from("board.lock.yml"));
+    }
+
+    #[test]
+    fn test_diff() {
+        let lock = |entries: &[(&str, &str)]| KasLock {
+            commits: entries.iter().map(|(r, c)| (r.to_string(), c.to_string())).collect(),
+        };
+        let old = lock(&[("a", "1"), ("b", "2"), ("c", "3")]);
+        let new = lock(&[("a", "1"), ("b", "4"), ("d", "5")]);
+
+        let changes: Vec<String> = old.diff(&new).iter().map(ToString::to_string).collect();
+        assert_eq!(changes, ["  a: 1", "~ b: 2 -> 4", "- c: 3", "+ d: 5"]);
+    }
+
+    #[test]
+    fn test_yaml_round_trip() {
+        let lock = KasLock {
+            commits: [("poky".to_string(), "a".repeat(40))].into_iter().collect(),
+        };
+        let config: KasConfig = serde_yaml::from_str(&lock.to_yaml()).unwrap();
+        assert_eq!(config.header.version, KAS_FORMAT_VERSION);
+        assert!(config.repos.is_empty());
+        assert_eq!(KasLock::from_config(&config), lock);
+    }
+}
@@ -3,34 +3,110 @@
 //! Handles git repository cloning, updating, and patch application
 //! based on kas configuration.
 
-use crate::include_graph::{KasConfig, KasRepo};
-use convenient_git::async_git::{AsyncGitRepository, GitError};
+use crate::include_graph::{KasConfig, KasPatch, KasRepo};
+use convenient_git::async_git::GitError;
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use tracing::{info, warn};
+use std::sync::Arc;
+use tokio::sync::{Mutex, Semaphore};
+use tokio::task::JoinSet;
+use tracing::{debug, info, warn};
+
+/// Identity for commits created by patch application, as kas uses
+const PATCH_IDENTITY: [&str; 4] = ["-c", "user.name=kas", "-c", "user.email=kas@example.com"];
 
 /// Repository manager
+///
+/// Repositories with a `url` are cloned into the repos directory and checked out
+/// detached at their `commit`, `tag`, `branch` or `refspec`, then their patches
+/// are applied on top. Runs are idempotent: an existing checkout is reset to
+/// the same revision and patched again, unless it has local changes.
+///
+/// With a cache directory (like kas' `KAS_REPO_REF_DIR`), each url is kept as a
+/// bare mirror there. Checkouts borrow the mirror's objects, so only the mirror
+/// talks to the remote and several build directories share one download.
+#[derive(Debug, Clone)]
 pub struct RepositoryManager {
     repos_dir: PathBuf,
     cache_dir: Option<PathBuf>,
+    config_dir: Option<PathBuf>,
+    update: bool,
+    force_checkout: bool,
+    jobs: usize,
+}
+
+/// Where branches and the default branch live in a checkout or a bare mirror
+#[derive(Clone, Copy)]
+enum RefLayout {
+    Checkout,
+    Mirror,
+}
+
+impl RefLayout {
+    fn branch(self, name: &str) -> String {
+        match self {
+            Self::Checkout => format!("refs/remotes/origin/{}", name),
+            Self::Mirror => format!("refs/heads/{}", name),
+        }
+    }
+
+    fn head(self) -> &'static str {
+        match self {
+            Self::Checkout => "refs/remotes/origin/HEAD",
+            Self::Mirror => "HEAD",
+        }
+    }
 }
 
 impl RepositoryManager {
     /// Create a new repository manager
     pub fn new(repos_dir: impl AsRef<Path>) -> Self {
         Self {
-            repos_dir: repos_dir.as_ref().to_path_buf(),
+            repos_dir: absolute(repos_dir.as_ref()),
             cache_dir: None,
+            config_dir: None,
+            update: false,
+            force_checkout: false,
+            jobs: std::thread::available_parallelism().map_or(4, std::num::NonZeroUsize::get),
         }
     }
 
-    /// Set cache directory for git objects
+    /// Set cache directory for bare mirrors of the repositories
     pub fn with_cache(mut self, cache_dir: impl AsRef<Path>) -> Self {
-        self.cache_dir = Some(cache_dir.as_ref().to_path_buf());
+        self.cache_dir = Some(absolute(cache_dir.as_ref()));
+        self
+    }
+
+    /// Directory of the repository holding the kas file
+    ///
+    /// Repositories with neither `url` nor `path` refer to it.
+    pub fn with_config_dir(mut self, config_dir: impl AsRef<Path>) -> Self {
+        self.config_dir = Some(config_dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Fetch branches and tags again even if the checkout already has them
+    pub fn with_update(mut self, update: bool) -> Self {
+        self.update = update;
+        self
+    }
+
+    /// Discard local changes instead of refusing to check out a dirty repository
+    pub fn with_force_checkout(mut self, force_checkout: bool) -> Self {
+        self.force_checkout = force_checkout;
+        self
+    }
+
+    /// Maximum number of repositories cloned at the same time
+    pub fn with_jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs.max(1);
         self
     }
 
     /// Setup all repositories from kas config
+    ///
+    /// Repositories are checked out in parallel; patches are applied once all of
+    /// them are, since a patch may come from another repository.
     pub async fn setup_repositories(
         &self,
         config: &KasConfig,
@@ -41,105 +117,274 @@ impl RepositoryManager {
             .await
             .map_err(|e| RepoError::IoError(self.repos_dir.clone(), e.to_string()))?;
 
-        for (name, repo_config) in &config.repos {
-            let repo_path = self.setup_repository(name, repo_config).await?;
-            repo_paths.insert(name.clone(), repo_path);
+        let repos = config.effective_repos();
+        let semaphore = Arc::new(Semaphore::new(self.jobs));
+        // Repositories sharing a url share a mirror, which must not be updated concurrently
+        let mut mirror_locks: HashMap<String, Arc<Mutex<()>>> = HashMap::new();
+        let mut tasks = JoinSet::new();
+
+        for (name, repo_config) in repos.clone() {
+            let mirror_lock = mirror_locks
+                .entry(repo_config.url.clone().unwrap_or_default())
+                .or_default()
+                .clone();
+            let manager = self.clone();
+            let semaphore = semaphore.clone();
+            tasks.spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+                let result = manager.checkout_repository(&name, &repo_config, &mirror_lock).await;
+                (name, result)
+            });
+        }
+
+        let mut first_error = None;
+        while let Some(joined) = tasks.join_next().await {
+            match joined {
+                Ok((name, Ok(repo_path))) => {
+                    repo_paths.insert(name, repo_path);
+                }
+                Ok((name, Err(e))) => {
+                    warn!("Failed to set up repository {}: {}", name, e);
+                    first_error.get_or_insert(e);
+                }
+                Err(e) => {
+                    first_error.get_or_insert(RepoError::GitCommand(e.to_string()));
+                }
+            }
+        }
+        if let Some(e) = first_error {
+            return Err(e);
+        }
+
+        let mut names: Vec<&String> = repos.keys().collect();
+        names.sort();
+        for name in names {
+            let repo_config = &repos[name];
+            let Some(patches) = &repo_config.patches else {
+                continue;
+            };
+            if repo_config.url.is_none() {
+                warn!("Not patching {}: only repositories with a url are managed", name);
+                continue;
+            }
+            self.apply_patch_sets(&repo_paths[name], patches, &repo_paths)
+                .await?;
         }
 
         Ok(repo_paths)
     }
 
     /// Setup a single repository
+    ///
+    /// Checks the repository out without applying its patches; see
+    /// [`RepositoryManager::apply_patches`].
     pub async fn setup_repository(
         &self,
         name: &str,
         config: &KasRepo,
     ) -> Result<PathBuf, RepoError> {
-        // If path is specified, use it directly (local repo)
-        if let Some(path) = &config.path {
-            let repo_path = PathBuf::from(path);
-            if tokio::fs::try_exists(&repo_path).await.unwrap_or(false) {
-                info!("Using local repository: {} at {}", name, repo_path.display());
-                return Ok(repo_path);
-            } else {
+        self.checkout_repository(name, config, &Mutex::new(())).await
+    }
+
+    async fn checkout_repository(
+        &self,
+        name: &str,
+        config: &KasRepo,
+        mirror_lock: &Mutex<()>,
+    ) -> Result<PathBuf, RepoError> {
+        // Without url, the repository is local and used as it is
+        let Some(url) = &config.url else {
+            if let Some(path) = &config.path {
+                let repo_path = PathBuf::from(path);
+                if tokio::fs::try_exists(&repo_path).await.unwrap_or(false) {
+                    info!("Using local repository: {} at {}", name, repo_path.display());
+                    return Ok(repo_path);
+                }
                 return Err(RepoError::LocalRepoNotFound(repo_path));
             }
-        }
-
-        // Otherwise clone from URL
-        let url = config
-            .url
+            return self
+                .config_dir
+                .clone()
+                .ok_or_else(|| RepoError::MissingUrl(name.to_string()));
+        };
+
+        let repo_path = config
+            .path
             .as_ref()
-            .ok_or_else(|| RepoError::MissingUrl(name.to_string()))?;
+            .map_or_else(|| self.repos_dir.join(name), |path| absolute(Path::new(path)));
+        let mirror = self.cache_dir.as_ref().map(|dir| dir.join(mirror_name(url)));
+        let exists = repo_path.join(".git").exists();
+
+        if exists
+            && let Some(changes) = local_changes(&repo_path).await?
+        {
+            if !self.force_checkout {
+                return Err(RepoError::DirtyRepo(name.to_string(), changes));
+            }
+            warn!("Discarding local changes in {}", name);
+            if repo_path.join(".git/rebase-apply").exists() {
+                git(&repo_path, &["am", "--abort"]).await?;
+            }
+        }
 
-        let repo_path = self.repos_dir.join(name);
+        let mut target = if exists && !self.update {
+            resolve_target(&repo_path, config, RefLayout::Checkout).await
+        } else {
+            None
+        };
+
+        if target.is_none() {
+            // The mirror is the only place that talks to the remote
+            let source = match &mirror {
+                Some(mirror) => {
+                    let _guard = mirror_lock.lock().await;
+                    self.update_mirror(url, mirror, config).await?;
+                    mirror.to_string_lossy().to_string()
+                }
+                None => url.clone(),
+            };
+
+            if exists {
+                info!("Fetching repository {}", name);
+                git(
+                    &repo_path,
+                    &["fetch", "-q", "--prune", "--tags", &source, "+refs/heads/*:refs/remotes/origin/*"],
+                )
+                .await?;
+            } else {
+                info!("Cloning repository {} from {}", name, url);
+                let parent = repo_path.parent().unwrap_or(&self.repos_dir);
+                tokio::fs::create_dir_all(parent)
+                    .await
+                    .map_err(|e| RepoError::IoError(parent.to_path_buf(), e.to_string()))?;
+                let dest = repo_path.to_string_lossy();
+                if mirror.is_some() {
+                    // Borrow the mirror's objects and keep pointing at the real remote
+                    git(parent, &["clone", "-q", "--no-checkout", "--shared", &source, &dest]).await?;
+                    git(&repo_path, &["remote", "set-url", "origin", url]).await?;
+                } else {
+                    git(parent, &["clone", "-q", "--no-checkout", url, &dest]).await?;
+                }
+            }
 
-        // Create AsyncGitRepository instance
-        let git_repo = AsyncGitRepository::new(&repo_path, url, None);
+            target = resolve_target(&repo_path, config, RefLayout::Checkout).await;
+            // Commits that are on no branch can still be fetched directly from most servers
+            if target.is_none()
+                && let Some(commit) = &config.commit
+            {
+                debug!("Fetching commit {} of {} directly", commit, name);
+                git(&repo_path, &["fetch", "-q", &source, commit]).await.ok();
+                target = resolve_target(&repo_path, config, RefLayout::Checkout).await;
+            }
+        }
 
-        // Clone or open repository
-        info!("Cloning/opening repository {} from {}", name, url);
-        git_repo.clone_or_open().await.map_err(RepoError::from)?;
+        let target = target.ok_or_else(|| {
+            RepoError::UnknownRevision(name.to_string(), revision(config).unwrap_or("HEAD").to_string())
+        })?;
 
-        // Checkout specific refspec if specified
-        if let Some(refspec) = self.get_refspec(config) {
-            info!("Checking out refspec: {}", refspec);
-            git_repo.checkout(&refspec).await.map_err(RepoError::from)?;
+        info!("Checking out {} at {}", name, target);
+        let mut args = vec!["checkout", "-q", "--detach"];
+        if self.force_checkout {
+            args.push("--force");
         }
+        args.push(&target);
+        git(&repo_path, &args).await?;
 
         Ok(repo_path)
     }
 
-    /// Get refspec from config (commit, tag, branch, or refspec)
-    fn get_refspec(&self, config: &KasRepo) -> Option<String> {
-        config
-            .commit
-            .clone()
-            .or_else(|| config.tag.clone())
-            .or_else(|| config.branch.clone())
-            .or_else(|| config.refspec.clone())
+    /// Create the mirror of `url`, or fetch into it if it lacks the wanted revision
+    async fn update_mirror(&self, url: &str, mirror: &Path, config: &KasRepo) -> Result<(), RepoError> {
+        if mirror.exists() {
+            if self.update || resolve_target(mirror, config, RefLayout::Mirror).await.is_none() {
+                info!("Updating mirror {}", mirror.display());
+                git(mirror, &["fetch", "-q", "--prune"]).await?;
+            }
+            return Ok(());
+        }
+
+        let parent = mirror.parent().unwrap_or(mirror);
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(|e| RepoError::IoError(parent.to_path_buf(), e.to_string()))?;
+        info!("Mirroring {} to {}", url, mirror.display());
+        git(parent, &["clone", "-q", "--mirror", url, &mirror.to_string_lossy()]).await?;
+        Ok(())
     }
 
-    /// Apply patches to a repository
+    /// Apply patches to a repository, in order of their ids
+    ///
+    /// A patch path is relative to the checkout of its `repo`, or to `repo_path`
+    /// when it names none. Entries without a value were dropped by an overlay.
+    /// A path may be a single patch or a directory with a quilt `series` file;
+    /// each patch becomes a commit, through `git am` when it is in mbox format.
     pub async fn apply_patches(
         &self,
         repo_path: &Path,
-        patches: &HashMap<String, Vec<String>>,
+        patches: &HashMap<String, Option<KasPatch>>,
+    ) -> Result<(), RepoError> {
+        self.apply_patch_sets(repo_path, patches, &HashMap::new()).await
+    }
+
+    async fn apply_patch_sets(
+        &self,
+        repo_path: &Path,
+        patches: &HashMap<String, Option<KasPatch>>,
+        repo_paths: &HashMap<String, PathBuf>,
     ) -> Result<(), RepoError> {
-        for (patch_id, patch_files) in patches {
+        let mut ids: Vec<&String> = patches.keys().collect();
+        ids.sort();
+
+        for patch_id in ids {
+            let Some(patch) = &patches[patch_id] else {
+                continue;
+            };
             info!("Applying patch set: {}", patch_id);
 
-            for patch_file in patch_files {
-                self.apply_single_patch(repo_path, patch_file).await?;
+            let base = match &patch.repo {
+                Some(repo) => repo_paths
+                    .get(repo)
+                    .cloned()
+                    .unwrap_or_else(|| self.repos_dir.join(repo)),
+                None => repo_path.to_path_buf(),
+            };
+            let patch_path = base.join(&patch.path);
+            for patch_file in series(&patch_path).await? {
+                self.apply_single_patch(repo_path, patch_id, &patch_file).await?;
             }
         }
 
         Ok(())
     }
 
-    /// Apply a single patch file
-    async fn apply_single_patch(&self, repo_path: &Path, patch_file: &str) -> Result<(), RepoError> {
-        info!("Applying patch: {}", patch_file);
-
-        let repo_path = repo_path.to_path_buf();
-        let patch_file = patch_file.to_string();
-
-        // Use git apply command (run in blocking task)
-        let output = tokio::task::spawn_blocking(move || {
-            std::process::Command::new("git")
-                .arg("apply")
-                .arg(&patch_file)
-                .current_dir(&repo_path)
-                .output()
-        })
-        .await
-        .map_err(|e| RepoError::PatchError(format!("Task join error: {}", e)))?
-        .map_err(|e| RepoError::PatchError(format!("Failed to run git apply: {}", e)))?;
+    /// Apply a single patch file as a commit
+    async fn apply_single_patch(
+        &self,
+        repo_path: &Path,
+        patch_id: &str,
+        patch_file: &Path,
+    ) -> Result<(), RepoError> {
+        info!("Applying patch: {}", patch_file.display());
+        let file = patch_file.to_string_lossy();
 
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(RepoError::PatchError(format!("Patch failed: {}", stderr)));
+        let mut am = PATCH_IDENTITY.to_vec();
+        am.extend(["am", "-q", "--keep-cr", &file]);
+        if git(repo_path, &am).await.is_ok() {
+            return Ok(());
         }
+        // Not a mail: apply the plain diff and commit it
+        git(repo_path, &["am", "--abort"]).await.ok();
+        git(repo_path, &["apply", "--index", &file])
+            .await
+            .map_err(|e| RepoError::PatchError(format!("{}: {}", file, e)))?;
+
+        let name = patch_file
+            .file_name()
+            .map_or_else(|| file.to_string(), |name| name.to_string_lossy().to_string());
+        let message = format!("{}: {}", patch_id, name);
+        let mut commit = PATCH_IDENTITY.to_vec();
+        commit.extend(["commit", "-q", "-m", &message]);
+        git(repo_path, &commit).await?;
 
         Ok(())
     }
@@ -156,7 +401,7 @@ impl RepositoryManager {
             // No explicit layers means the repo itself is a layer
             layer_paths.push(repo_path.to_path_buf());
         } else {
-            for (layer_name, layer_config) in &repo_config.layers {
+            for (layer_name, layer_config) in repo_config.enabled_layers() {
                 let layer_path = if let Some(path) = &layer_config.path {
                     repo_path.join(path)
                 } else {
@@ -202,6 +447,117 @@ pub enum RepoError {
     /// Failed to apply patch to repository
     #[error("Patch application failed: {0}")]
     PatchError(String),
+
+    /// git command failed
+    #[error("git failed: {0}")]
+    GitCommand(String),
+
+    /// Checkout has uncommitted changes or a half applied patch series
+    #[error("Repository {0} has local changes, refusing to check it out:\n{1}")]
+    DirtyRepo(String, String),
+
+    /// Revision not found in the repository, even after fetching
+    #[error("Revision {1} of repository {0} not found")]
+    UnknownRevision(String, String),
+}
+
+/// `path` made absolute, since git runs in other directories
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Run git in `dir`, returning its trimmed stdout
+async fn git(dir: &Path, args: &[&str]) -> Result<String, RepoError> {
+    let output = tokio::process::Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .await
+        .map_err(|e| RepoError::GitCommand(format!("cannot run git: {}", e)))?;
+
+    if !output.status.success() {
+        return Err(RepoError::GitCommand(format!(
+            "git {} in {}: {}",
+            args.join(" "),
+            dir.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// The configured revision, in kas' order of precedence
+fn revision(config: &KasRepo) -> Option<&str> {
+    config
+        .commit
+        .as_deref()
+        .or(config.tag.as_deref())
+        .or(config.branch.as_deref())
+        .or(config.refspec.as_deref())
+}
+
+/// Commit the configured revision points to in `dir`, if `dir` has it
+async fn resolve_target(dir: &Path, config: &KasRepo, layout: RefLayout) -> Option<String> {
+    let candidates = if let Some(commit) = &config.commit {
+        vec![commit.clone()]
+    } else if let Some(tag) = &config.tag {
+        vec![format!("refs/tags/{}", tag)]
+    } else if let Some(branch) = &config.branch {
+        vec![layout.branch(branch)]
+    } else if let Some(refspec) = &config.refspec {
+        // Legacy refspec: a branch, a tag or anything git can resolve
+        vec![layout.branch(refspec), format!("refs/tags/{}", refspec), refspec.clone()]
+    } else {
+        vec![layout.head().to_string()]
+    };
+
+    for candidate in candidates {
+        let spec = format!("{}^{{commit}}", candidate);
+        if let Ok(commit) = git(dir, &["rev-parse", "-q", "--verify", &spec]).await {
+            return Some(commit);
+        }
+    }
+    None
+}
+
+/// Description of local changes in a checkout, or `None` if it is clean
+async fn local_changes(repo_path: &Path) -> Result<Option<String>, RepoError> {
+    if repo_path.join(".git/rebase-apply").exists() {
+        return Ok(Some("a patch series is partially applied".to_string()));
+    }
+    let status = git(repo_path, &["status", "--porcelain", "--untracked-files=no"]).await?;
+    Ok((!status.is_empty()).then_some(status))
+}
+
+/// Patch files of a patch path: the file itself, or a directory's quilt series
+async fn series(patch_path: &Path) -> Result<Vec<PathBuf>, RepoError> {
+    if !patch_path.is_dir() {
+        return Ok(vec![patch_path.to_path_buf()]);
+    }
+
+    let series_file = patch_path.join("series");
+    let content = tokio::fs::read_to_string(&series_file)
+        .await
+        .map_err(|e| RepoError::IoError(series_file.clone(), e.to_string()))?;
+
+    // One patch per line, optionally followed by quilt options such as -p1
+    Ok(content
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default())
+        .filter_map(|line| line.split_whitespace().next())
+        .map(|patch| patch_path.join(patch))
+        .collect())
+}
+
+/// Directory name of the mirror of `url` in the cache, like kas names them
+fn mirror_name(url: &str) -> String {
+    let location = url.split_once("://").map_or(url, |(_, rest)| rest);
+    location
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '.' })
+        .collect::<String>()
+        .trim_matches('.')
+        .to_string()
 }
 
 #[cfg(test)]
@@ -215,4 +571,17 @@ mod tests {
         let _manager = RepositoryManager::new(temp.path());
         assert!(temp.path().exists());
     }
+
+    #[test]
+    fn test_mirror_name() {
+        assert_eq!(
+            mirror_name("https://git.yoctoproject.org/git/poky"),
+            "git.yoctoproject.org.git.poky"
+        );
+        assert_eq!(
+            mirror_name("git@github.com:openembedded/meta-openembedded.git"),
+            "git.github.com.openembedded.meta-openembedded.git"
+        );
+        assert_eq!(mirror_name("/srv/git/meta_x"), "srv.git.meta_x");
+    }
 }
@@ -24,6 +24,7 @@ async fn test_generate_local_conf_basic() {
         bblayers_conf_header: None,
         local_conf_header: None,
         env: None,
+        ..Default::default()
     };
 
     let generator = ConfigGenerator::new(temp.path(), config, HashMap::new());
@@ -77,6 +78,7 @@ async fn test_generate_local_conf_with_headers() {
         bblayers_conf_header: None,
         local_conf_header: Some(headers),
         env: None,
+        ..Default::default()
     };
 
     let generator = ConfigGenerator::new(temp.path(), config, HashMap::new());
@@ -109,6 +111,7 @@ async fn test_generate_bblayers_conf_basic() {
         bblayers_conf_header: None,
         local_conf_header: None,
         env: None,
+        ..Default::default()
     };
 
     let generator = ConfigGenerator::new(temp.path(), config, HashMap::new());
@@ -158,6 +161,7 @@ async fn test_generate_bblayers_conf_with_layers() {
         bblayers_conf_header: None,
         local_conf_header: None,
         env: None,
+        ..Default::default()
     };
 
     let generator = ConfigGenerator::new(temp.path(), config, layer_paths);
@@ -191,6 +195,7 @@ async fn test_generate_bblayers_conf_with_headers() {
         bblayers_conf_header: Some(headers),
         local_conf_header: None,
         env: None,
+        ..Default::default()
     };
 
     let generator = ConfigGenerator::new(temp.path(), config, HashMap::new());
@@ -209,8 +214,8 @@ async fn test_generate_env_setup() {
     let temp = TempDir::new().unwrap();
 
     let mut env = HashMap::new();
-    env.insert("SSTATE_DIR".to_string(), "/shared/sstate".to_string());
-    env.insert("DL_DIR".to_string(), "/shared/downloads".to_string());
+    env.insert("SSTATE_DIR".to_string(), Some("/shared/sstate".to_string()));
+    env.insert("DL_DIR".to_string(), Some("/shared/downloads".to_string()));
 
     let config = KasConfig {
         header: KasHeader {
@@ -224,6 +229,7 @@ async fn test_generate_env_setup() {
         bblayers_conf_header: None,
         local_conf_header: None,
         env: Some(env),
+        ..Default::default()
     };
 
     let generator = ConfigGenerator::new(temp.path(), config, HashMap::new());
@@ -277,6 +283,7 @@ async fn test_get_targets_default() {
         bblayers_conf_header: None,
         local_conf_header: None,
         env: None,
+        ..Default::default()
     };
 
     let generator = ConfigGenerator::new(temp.path(), config, HashMap::new());
@@ -306,6 +313,7 @@ async fn test_get_targets_custom() {
         bblayers_conf_header: None,
         local_conf_header: None,
         env: None,
+        ..Default::default()
     };
 
     let generator = ConfigGenerator::new(temp.path(), config, HashMap::new());
@@ -332,6 +340,7 @@ async fn test_generate_all_creates_conf_directory() {
         bblayers_conf_header: None,
         local_conf_header: None,
         env: None,
+        ..Default::default()
     };
 
     let generator = ConfigGenerator::new(temp.path(), config, HashMap::new());
@@ -365,11 +374,12 @@ async fn test_config_generator_with_all_features() {
             path: None,
             layers: {
                 let mut layers = HashMap::new();
-                layers.insert("meta".to_string(), KasLayer { path: None });
-                layers.insert("meta-poky".to_string(), KasLayer { path: None });
+                layers.insert("meta".to_string(), KasLayer::default());
+                layers.insert("meta-poky".to_string(), KasLayer::default());
                 layers
             },
             patches: None,
+            ..Default::default()
         },
     );
 
@@ -380,7 +390,7 @@ async fn test_config_generator_with_all_features() {
     bblayers_headers.insert("mask".to_string(), "BBMASK = \"test\"".to_string());
 
     let mut env = HashMap::new();
-    env.insert("SSTATE_DIR".to_string(), "/cache/sstate".to_string());
+    env.insert("SSTATE_DIR".to_string(), Some("/cache/sstate".to_string()));
 
     let config = KasConfig {
         header: KasHeader {
@@ -394,6 +404,7 @@ async fn test_config_generator_with_all_features() {
         bblayers_conf_header: Some(bblayers_headers),
         local_conf_header: Some(local_conf_headers),
         env: Some(env),
+        ..Default::default()
     };
 
     let layer1 = temp.path().join("poky/meta");
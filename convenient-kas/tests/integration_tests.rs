@@ -272,9 +272,9 @@ env:
 
     let env = merged.env.as_ref().unwrap();
     assert_eq!(env.len(), 3);
-    assert_eq!(env.get("VAR1").unwrap(), "value1");
-    assert_eq!(env.get("VAR2").unwrap(), "overridden"); // Overlay wins
-    assert_eq!(env.get("VAR3").unwrap(), "value3");
+    assert_eq!(env["VAR1"].as_deref(), Some("value1"));
+    assert_eq!(env["VAR2"].as_deref(), Some("overridden")); // Overlay wins
+    assert_eq!(env["VAR3"].as_deref(), Some("value3"));
 }
 
 #[tokio::test]
@@ -293,6 +293,7 @@ async fn test_config_generator_without_machine() {
         bblayers_conf_header: None,
         local_conf_header: None,
         env: None,
+        ..Default::default()
     };
 
     let generator = ConfigGenerator::new(temp.path(), config, HashMap::new());
@@ -323,6 +324,7 @@ async fn test_config_generator_empty_layer_paths() {
         bblayers_conf_header: None,
         local_conf_header: None,
         env: None,
+        ..Default::default()
     };
 
     let generator = ConfigGenerator::new(temp.path(), config, HashMap::new());
@@ -448,9 +450,9 @@ bblayers_conf_header:
 
     // Test environment
     let env = kas_file.config.env.as_ref().unwrap();
-    assert_eq!(env.get("SSTATE_DIR").unwrap(), "/shared/sstate-cache");
-    assert_eq!(env.get("DL_DIR").unwrap(), "/shared/downloads");
-    assert_eq!(env.get("BB_NUMBER_THREADS").unwrap(), "8");
+    assert_eq!(env["SSTATE_DIR"].as_deref(), Some("/shared/sstate-cache"));
+    assert_eq!(env["DL_DIR"].as_deref(), Some("/shared/downloads"));
+    assert_eq!(env["BB_NUMBER_THREADS"].as_deref(), Some("8"));
 
     // Test include graph
     let graph = KasIncludeGraph::build(&kas_path).await.unwrap();
@@ -0,0 +1,94 @@
+//! Tests for dumping merged kas configurations, with and without source annotations
+
+use convenient_kas::dump::{to_annotated_yaml, to_yaml};
+use convenient_kas::include_graph::{KasConfig, KasIncludeGraph};
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+use tokio::fs;
+
+async fn create_kas_file(dir: &Path, name: &str, content: &str) -> PathBuf {
+    let path = dir.join(name);
+    fs::create_dir_all(path.parent().unwrap()).await.unwrap();
+    fs::write(&path, content).await.unwrap();
+    path
+}
+
+async fn graph(temp: &TempDir) -> KasIncludeGraph {
+    create_kas_file(
+        temp.path(),
+        "include/base.yml",
+        r#"
+header:
+  version: 14
+machine: qemux86-64
+repos:
+  poky:
+    url: "https://git.yoctoproject.org/git/poky"
+    branch: scarthgap
+    layers:
+      meta:
+      meta-poky:
+local_conf_header:
+  base: |
+    A = "1"
+    B = "2"
+"#,
+    )
+    .await;
+    let root = create_kas_file(
+        temp.path(),
+        "kas.yml",
+        r#"
+header:
+  version: 14
+  includes:
+    - include/base.yml
+machine: genericx86-64
+target:
+  - core-image-minimal
+repos:
+  poky:
+    layers:
+      meta-yocto-bsp:
+"#,
+    )
+    .await;
+    KasIncludeGraph::build(&root).await.unwrap()
+}
+
+#[tokio::test]
+async fn test_key_sources() {
+    let temp = TempDir::new().unwrap();
+    let graph = graph(&temp).await;
+    let sources = graph.key_sources();
+    let base = temp.path().join("include/base.yml");
+    let root = temp.path().join("kas.yml");
+
+    assert_eq!(sources["machine"], root);
+    assert_eq!(sources["repos.poky.url"], base);
+    assert_eq!(sources["repos.poky.layers.meta"], base);
+    assert_eq!(sources["repos.poky.layers.meta-yocto-bsp"], root);
+    // A mapping belongs to the last file that contributed to it
+    assert_eq!(sources["repos.poky"], root);
+    assert!(!sources.contains_key("header"));
+    assert!(!sources.contains_key("header.version"));
+}
+
+#[tokio::test]
+async fn test_annotated_dump_loads_as_merged_config() {
+    let temp = TempDir::new().unwrap();
+    let graph = graph(&temp).await;
+    let annotated = to_annotated_yaml(&graph);
+
+    assert!(annotated.contains("machine: genericx86-64  # kas.yml\n"), "{}", annotated);
+    assert!(annotated.contains("url: https://git.yoctoproject.org/git/poky  # include/base.yml\n"), "{}", annotated);
+    assert!(annotated.contains("base: |  # include/base.yml\n"), "{}", annotated);
+    assert!(annotated.contains("target:  # kas.yml\n- core-image-minimal\n"), "{}", annotated);
+
+    // Comments aside, it is the plain dump
+    let merged = graph.merge_config();
+    let loaded: KasConfig = serde_yaml::from_str(&annotated).unwrap();
+    assert_eq!(loaded, merged);
+    let plain: KasConfig = serde_yaml::from_str(&to_yaml(&merged)).unwrap();
+    assert_eq!(plain, merged);
+}
@@ -0,0 +1,123 @@
+//! Tests for kas lock files: resolving repositories against a local git
+//! remote, honoring an existing lock and updating it
+
+use convenient_kas::include_graph::{KAS_FORMAT_VERSION, KasIncludeGraph};
+use convenient_kas::lock::{KasLock, LockChange, lock_path};
+use git2::{Oid, Repository, Signature};
+use std::path::Path;
+use tempfile::TempDir;
+
+/// Commit an empty tree on top of HEAD
+fn commit(repo: &Repository, message: &str) -> Oid {
+    let signature = Signature::now("Test", "test@example.com").unwrap();
+    let tree = repo.find_tree(repo.index().unwrap().write_tree().unwrap()).unwrap();
+    let parents: Vec<_> = repo.head().ok().and_then(|h| h.peel_to_commit().ok()).into_iter().collect();
+    let parents: Vec<_> = parents.iter().collect();
+    repo.commit(Some("HEAD"), &signature, &signature, message, &tree, &parents)
+        .unwrap()
+}
+
+fn write_kas_file(dir: &Path, remote: &Path) -> std::path::PathBuf {
+    let path = dir.join("kas.yml");
+    std::fs::write(
+        &path,
+        format!(
+            r#"
+header:
+  version: 14
+repos:
+  this:
+  floating:
+    url: "{remote}"
+  tagged:
+    url: "{remote}"
+    tag: v1.0
+  fixed:
+    url: "{remote}"
+    commit: 0123456789abcdef0123456789abcdef01234567
+"#,
+            remote = remote.display()
+        ),
+    )
+    .unwrap();
+    path
+}
+
+#[tokio::test]
+async fn test_lock_resolves_and_is_honored() {
+    let remote_dir = TempDir::new().unwrap();
+    let remote = Repository::init(remote_dir.path()).unwrap();
+    let first = commit(&remote, "first");
+    remote.tag_lightweight("v1.0", &remote.find_object(first, None).unwrap(), false).unwrap();
+
+    let dir = TempDir::new().unwrap();
+    let kas_file = write_kas_file(dir.path(), remote_dir.path());
+    let lock_file = lock_path(&kas_file);
+    assert_eq!(lock_file, dir.path().join("kas.lock.yml"));
+
+    let config = KasIncludeGraph::build(&kas_file).await.unwrap().merge_config();
+    let lock = KasLock::resolve(&config, false).await.unwrap();
+
+    // Only floating repositories with a url get locked
+    assert_eq!(lock.commits.len(), 2);
+    assert_eq!(lock.commits["floating"], first.to_string());
+    assert_eq!(lock.commits["tagged"], first.to_string());
+    lock.write(&lock_file).await.unwrap();
+
+    let written = std::fs::read_to_string(&lock_file).unwrap();
+    assert!(written.contains(&format!("version: {}", KAS_FORMAT_VERSION)));
+    assert!(!written.contains("url"));
+
+    // The lock file is merged on top of the root
+    let second = commit(&remote, "second");
+    let graph = KasIncludeGraph::build(&kas_file).await.unwrap();
+    assert_eq!(graph.files().len(), 2);
+    let config = graph.merge_config();
+    let repos = config.effective_repos();
+    assert_eq!(repos["floating"].commit.as_deref(), Some(first.to_string().as_str()));
+    assert_eq!(
+        repos["fixed"].commit.as_deref(),
+        Some("0123456789abcdef0123456789abcdef01234567")
+    );
+
+    // Locking again keeps the pinned commits, updating moves them
+    let kept = KasLock::resolve(&config, false).await.unwrap();
+    assert_eq!(kept, lock);
+    let updated = KasLock::resolve(&config, true).await.unwrap();
+    assert_eq!(updated.commits["floating"], second.to_string());
+    assert_eq!(
+        lock.diff(&updated),
+        vec![
+            LockChange::Updated {
+                repo: "floating".to_string(),
+                old: first.to_string(),
+                new: second.to_string(),
+            },
+            LockChange::Unchanged {
+                repo: "tagged".to_string(),
+                commit: first.to_string(),
+            },
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_lock_unknown_ref() {
+    let remote_dir = TempDir::new().unwrap();
+    let remote = Repository::init(remote_dir.path()).unwrap();
+    commit(&remote, "first");
+
+    let dir = TempDir::new().unwrap();
+    let kas_file = write_kas_file(dir.path(), remote_dir.path());
+    let config = KasIncludeGraph::build(&kas_file).await.unwrap().merge_config();
+
+    // No v1.0 tag on the remote
+    let error = KasLock::resolve(&config, false).await.unwrap_err();
+    assert!(error.to_string().contains("tagged"), "{}", error);
+}
+
+#[tokio::test]
+async fn test_load_missing_lock() {
+    let dir = TempDir::new().unwrap();
+    assert_eq!(KasLock::load(dir.path().join("kas.lock.yml")).await.unwrap(), None);
+}
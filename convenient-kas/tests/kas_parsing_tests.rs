@@ -4,7 +4,7 @@
 //! validating all field types, includes, merging, and error cases.
 
 use convenient_kas::include_graph::{
-    KasConfig, KasError, KasFile, KasHeader, KasIncludeGraph, KasLayer, KasRepo,
+    KasConfig, KasError, KasFile, KasHeader, KasInclude, KasIncludeGraph, KasLayer, KasRepo,
 };
 use std::collections::HashMap;
 use std::path::PathBuf;
@@ -94,7 +94,7 @@ bblayers_conf_header:
     assert_eq!(kas_file.config.header.version, 14);
     assert_eq!(
         kas_file.config.header.includes,
-        Some(vec!["base.yml".to_string()])
+        Some(vec![KasInclude::File("base.yml".to_string())])
     );
 
     // Machine and distro
@@ -109,8 +109,8 @@ bblayers_conf_header:
 
     // Environment
     let env = kas_file.config.env.as_ref().unwrap();
-    assert_eq!(env.get("SSTATE_DIR").unwrap(), "/shared/sstate-cache");
-    assert_eq!(env.get("DL_DIR").unwrap(), "/shared/downloads");
+    assert_eq!(env["SSTATE_DIR"].as_deref(), Some("/shared/sstate-cache"));
+    assert_eq!(env["DL_DIR"].as_deref(), Some("/shared/downloads"));
 
     // Repositories
     assert_eq!(kas_file.config.repos.len(), 2);
@@ -197,10 +197,10 @@ repos:
     branch: main
     patches:
       security-fixes:
-        - 0001-fix-cve.patch
-        - 0002-fix-another.patch
+        repo: meta-custom
+        path: patches/0001-fix-cve.patch
       features:
-        - 0003-add-feature.patch
+        path: patches/features
 "#;
 
     let path = create_kas_file(&temp, "patches.yml", content).await;
@@ -210,12 +210,13 @@ repos:
     let patches = repo.patches.as_ref().unwrap();
     assert_eq!(patches.len(), 2);
 
-    let security = patches.get("security-fixes").unwrap();
-    assert_eq!(security.len(), 2);
-    assert_eq!(security[0], "0001-fix-cve.patch");
+    let security = patches["security-fixes"].as_ref().unwrap();
+    assert_eq!(security.repo.as_deref(), Some("meta-custom"));
+    assert_eq!(security.path, "patches/0001-fix-cve.patch");
 
-    let features = patches.get("features").unwrap();
-    assert_eq!(features.len(), 1);
+    let features = patches["features"].as_ref().unwrap();
+    assert!(features.repo.is_none());
+    assert_eq!(features.path, "patches/features");
 }
 
 #[tokio::test]
@@ -590,9 +591,9 @@ env:
 
     let env = kas_file.config.env.as_ref().unwrap();
     assert_eq!(env.len(), 3);
-    assert_eq!(env.get("VAR1").unwrap(), "value1");
-    assert_eq!(env.get("VAR2").unwrap(), "value2");
-    assert_eq!(env.get("VAR3").unwrap(), "");
+    assert_eq!(env["VAR1"].as_deref(), Some("value1"));
+    assert_eq!(env["VAR2"].as_deref(), Some("value2"));
+    assert_eq!(env["VAR3"].as_deref(), Some(""));
 }
 
 #[tokio::test]
@@ -0,0 +1,389 @@
+//! Tests for the complete kas schema (format version 17) and kas merge semantics
+//!
+//! Configurations are checked against the kas JSON schema in
+//! tests/data/schema-kas.json, both as written and after a parse/merge round
+//! trip. scripts/update-kas-schema.sh vendors that file from upstream kas at a
+//! pinned tag; until it has been run, the file is a condensed stand-in.
+
+use convenient_kas::include_graph::{
+    KAS_FORMAT_VERSION, KasBuildSystem, KasConfig, KasError, KasFile, KasInclude, KasIncludeGraph,
+};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+use tokio::fs;
+
+async fn create_kas_file(dir: &Path, name: &str, content: &str) -> PathBuf {
+    let path = dir.join(name);
+    fs::create_dir_all(path.parent().unwrap()).await.unwrap();
+    fs::write(&path, content).await.unwrap();
+    path
+}
+
+/// Schema violations of `instance`, as readable messages
+fn schema_errors(instance: &serde_json::Value) -> Vec<String> {
+    let schema: serde_json::Value =
+        serde_json::from_str(include_str!("data/schema-kas.json")).unwrap();
+    let validator = jsonschema::validator_for(&schema).unwrap();
+    validator
+        .iter_errors(instance)
+        .map(|e| format!("{} at {}", e, e.instance_path))
+        .collect()
+}
+
+fn assert_valid_yaml(yaml: &str) {
+    let value: serde_json::Value = serde_yaml::from_str(yaml).unwrap();
+    let errors = schema_errors(&value);
+    assert!(errors.is_empty(), "{:?}", errors);
+}
+
+fn assert_valid_config(config: &KasConfig) {
+    let value = serde_json::to_value(config).unwrap();
+    let errors = schema_errors(&value);
+    assert!(errors.is_empty(), "{:?}\n{}", errors, value);
+}
+
+const FULL_CONFIG: &str = r#"
+header:
+  version: 17
+  includes:
+    - base.yml
+    - repo: meta-vendor
+      file: kas/vendor.yml
+build_system: openembedded
+defaults:
+  repos:
+    branch: scarthgap
+    patches:
+      repo: meta-custom
+overrides:
+  repos:
+    poky:
+      commit: 0123456789abcdef0123456789abcdef01234567
+machine: qemux86-64
+distro: poky
+target: core-image-minimal
+task: populate_sdk
+repos:
+  meta-custom:
+  meta-vendor:
+    url: https://example.com/meta-vendor.git
+    type: git
+    tag: v1.0
+    signed: true
+    allowed_signers:
+      - vendor
+  poky:
+    url: https://git.yoctoproject.org/poky
+    name: poky-checkout
+    layers:
+      meta:
+      meta-poky:
+      meta-yocto-bsp: disabled
+    patches:
+      fix-build:
+        path: patches/0001-fix-build.patch
+      dropped:
+env:
+  SSTATE_DIR: /shared/sstate
+  SSH_AUTH_SOCK:
+bblayers_conf_header:
+  standard: |
+    POKY_BBLAYERS_CONF_VERSION = "2"
+local_conf_header:
+  standard: |
+    CONF_VERSION = "2"
+proxy_config:
+  http_proxy: http://proxy:3128
+menu_configuration:
+  KAS_TARGET_MINIMAL: true
+  KAS_MACHINE: qemux86-64
+  KAS_JOBS: 8
+artifacts:
+  image: tmp/deploy/images/qemux86-64/core-image-minimal-qemux86-64.rootfs.wic
+signers:
+  vendor:
+    type: ssh
+    repo: meta-custom
+    path: keys/vendor.pub
+    fingerprint: SHA256:abcdef
+_source_dir: /work
+"#;
+
+#[tokio::test]
+async fn test_full_schema_parses_and_validates() {
+    assert_valid_yaml(FULL_CONFIG);
+
+    let temp = TempDir::new().unwrap();
+    let path = create_kas_file(temp.path(), "full.yml", FULL_CONFIG).await;
+    let config = KasFile::load(&path).await.unwrap().config;
+
+    assert_eq!(config.header.version, 17);
+    let includes = config.header.includes.as_ref().unwrap();
+    assert_eq!(includes[0], KasInclude::File("base.yml".to_string()));
+    assert_eq!(
+        includes[1],
+        KasInclude::Repo {
+            repo: "meta-vendor".to_string(),
+            file: "kas/vendor.yml".to_string()
+        }
+    );
+    assert_eq!(config.build_system, Some(KasBuildSystem::Openembedded));
+    assert_eq!(config.target, Some(vec!["core-image-minimal".to_string()]));
+    assert_eq!(config.task.as_deref(), Some("populate_sdk"));
+    assert_eq!(config.source_dir.as_deref(), Some("/work"));
+
+    // An empty repo entry is the repository containing the kas file
+    assert_eq!(config.repos["meta-custom"], Default::default());
+
+    let poky = &config.repos["poky"];
+    assert_eq!(poky.name.as_deref(), Some("poky-checkout"));
+    assert!(poky.layers["meta-yocto-bsp"].disabled);
+    let enabled: Vec<&String> = {
+        let mut names: Vec<&String> = poky.enabled_layers().map(|(name, _)| name).collect();
+        names.sort();
+        names
+    };
+    assert_eq!(enabled, ["meta", "meta-poky"]);
+    let patches = poky.patches.as_ref().unwrap();
+    assert!(patches["dropped"].is_none());
+
+    let env = config.env.as_ref().unwrap();
+    assert_eq!(env["SSTATE_DIR"].as_deref(), Some("/shared/sstate"));
+    assert_eq!(env["SSH_AUTH_SOCK"], None);
+
+    let vendor = &config.repos["meta-vendor"];
+    assert_eq!(vendor.signed, Some(true));
+    assert_eq!(config.signers.as_ref().unwrap()["vendor"].fingerprint.as_deref(), Some("SHA256:abcdef"));
+
+    // What we parsed serializes back into a valid kas file
+    assert_valid_config(&config);
+}
+
+#[tokio::test]
+async fn test_defaults_and_overrides_apply_to_repos() {
+    let temp = TempDir::new().unwrap();
+    let path = create_kas_file(temp.path(), "full.yml", FULL_CONFIG).await;
+    let config = KasFile::load(&path).await.unwrap().config;
+    let repos = config.effective_repos();
+
+    // defaults.repos.branch fills in, overrides pin the commit
+    let poky = &repos["poky"];
+    assert_eq!(poky.branch.as_deref(), Some("scarthgap"));
+    assert_eq!(
+        poky.commit.as_deref(),
+        Some("0123456789abcdef0123456789abcdef01234567")
+    );
+    let fix = poky.patches.as_ref().unwrap()["fix-build"].as_ref().unwrap();
+    assert_eq!(fix.repo.as_deref(), Some("meta-custom"));
+
+    // A tag is kept, and repos without a url get no defaults
+    assert_eq!(repos["meta-vendor"].tag.as_deref(), Some("v1.0"));
+    assert_eq!(repos["meta-custom"], Default::default());
+
+    // The configuration itself is unchanged
+    assert!(config.repos["poky"].branch.is_none());
+}
+
+#[test]
+fn test_environment_semantics() {
+    let config: KasConfig = serde_yaml::from_str(FULL_CONFIG).unwrap();
+
+    let host = HashMap::from([("SSH_AUTH_SOCK", "/run/agent"), ("http_proxy", "http://host:8080")]);
+    let env = config.environment(|key| host.get(key).map(ToString::to_string));
+    assert_eq!(env["SSTATE_DIR"], "/shared/sstate");
+    assert_eq!(env["SSH_AUTH_SOCK"], "/run/agent");
+    assert_eq!(env["http_proxy"], "http://host:8080");
+
+    // Without a host value, pass-through variables are left out
+    let env = config.environment(|_| None);
+    assert!(!env.contains_key("SSH_AUTH_SOCK"));
+    assert_eq!(env["http_proxy"], "http://proxy:3128");
+}
+
+#[tokio::test]
+async fn test_merge_semantics_match_kas() {
+    let temp = TempDir::new().unwrap();
+
+    let base = r#"
+header:
+  version: 14
+machine: qemux86
+target:
+  - core-image-minimal
+  - core-image-sato
+repos:
+  poky:
+    url: https://git.yoctoproject.org/poky
+    branch: kirkstone
+    layers:
+      meta:
+      meta-poky:
+      meta-yocto-bsp:
+  meta-openembedded:
+    url: https://git.openembedded.org/meta-openembedded
+    layers:
+      meta-oe:
+    patches:
+      one:
+        path: one.patch
+      two:
+        path: two.patch
+env:
+  DL_DIR: /downloads
+  SSTATE_DIR: /sstate
+local_conf_header:
+  base: |
+    A = "1"
+"#;
+    create_kas_file(temp.path(), "base.yml", base).await;
+
+    let main = r#"
+header:
+  version: 17
+  includes:
+    - base.yml
+target: core-image-base
+repos:
+  poky:
+    branch: scarthgap
+    layers:
+      meta-yocto-bsp: disabled
+  meta-openembedded:
+  this-repo:
+  meta-custom:
+    path: meta-custom
+    patches:
+      local:
+        path: local.patch
+env:
+  SSTATE_DIR:
+local_conf_header:
+  main: |
+    B = "2"
+"#;
+    let main_path = create_kas_file(temp.path(), "main.yml", main).await;
+    assert_valid_yaml(main);
+
+    let graph = KasIncludeGraph::build(&main_path).await.unwrap();
+    let merged = graph.merge_config();
+
+    // The merged file stands alone
+    assert_eq!(merged.header.version, KAS_FORMAT_VERSION);
+    assert!(merged.header.includes.is_none());
+
+    // Scalars and lists are replaced, not appended
+    assert_eq!(merged.machine.as_deref(), Some("qemux86"));
+    assert_eq!(merged.target, Some(vec!["core-image-base".to_string()]));
+
+    // Mappings merge key by key
+    let poky = &merged.repos["poky"];
+    assert_eq!(poky.url.as_deref(), Some("https://git.yoctoproject.org/poky"));
+    assert_eq!(poky.branch.as_deref(), Some("scarthgap"));
+    assert_eq!(poky.layers.len(), 3);
+    assert!(poky.layers["meta-yocto-bsp"].disabled);
+    assert!(!poky.layers["meta"].disabled);
+
+    // A null repo deletes the included definition; one that nothing defined
+    // before is the repo containing the kas file
+    assert!(!merged.repos.contains_key("meta-openembedded"));
+    assert!(!graph.key_sources().contains_key("repos.meta-openembedded"));
+    assert_eq!(merged.repos["this-repo"], Default::default());
+    assert_eq!(merged.repos["meta-custom"].path.as_deref(), Some("meta-custom"));
+
+    // A null env value replaces the default with a host pass-through
+    let env = merged.env.as_ref().unwrap();
+    assert_eq!(env["DL_DIR"].as_deref(), Some("/downloads"));
+    assert_eq!(env["SSTATE_DIR"], None);
+
+    let headers = merged.local_conf_header.as_ref().unwrap();
+    assert_eq!(headers.len(), 2);
+
+    assert_valid_config(&merged);
+}
+
+#[tokio::test]
+async fn test_include_from_repo() {
+    let temp = TempDir::new().unwrap();
+    let vendor_dir = temp.path().join("checkouts/meta-vendor");
+
+    create_kas_file(
+        &vendor_dir,
+        "kas/vendor.yml",
+        r#"
+header:
+  version: 17
+  includes:
+    - common.yml
+distro: vendor-distro
+"#,
+    )
+    .await;
+    create_kas_file(
+        &vendor_dir,
+        "kas/common.yml",
+        r#"
+header:
+  version: 17
+machine: vendor-board
+"#,
+    )
+    .await;
+    let main_path = create_kas_file(
+        temp.path(),
+        "main.yml",
+        r#"
+header:
+  version: 17
+  includes:
+    - repo: meta-vendor
+      file: kas/vendor.yml
+repos:
+  meta-vendor:
+    url: https://example.com/meta-vendor.git
+"#,
+    )
+    .await;
+
+    // Before checkout the include is reported, as kas does
+    let graph = KasIncludeGraph::build(&main_path).await.unwrap();
+    assert_eq!(graph.files().len(), 1);
+    assert_eq!(graph.missing_repos(), ["meta-vendor"]);
+
+    let repo_paths = HashMap::from([("meta-vendor".to_string(), vendor_dir)]);
+    let graph = KasIncludeGraph::build_with_repos(&main_path, &repo_paths)
+        .await
+        .unwrap();
+    assert!(graph.missing_repos().is_empty());
+    assert_eq!(graph.files().len(), 3);
+
+    // Files inside the repo include relative to themselves
+    let merged = graph.merge_config();
+    assert_eq!(merged.machine.as_deref(), Some("vendor-board"));
+    assert_eq!(merged.distro.as_deref(), Some("vendor-distro"));
+}
+
+#[tokio::test]
+async fn test_header_version_range() {
+    let temp = TempDir::new().unwrap();
+
+    // The pre-1 "0.10" format is version 1
+    let path = create_kas_file(
+        temp.path(),
+        "legacy.yml",
+        "header:\n  version: \"0.10\"\nmachine: test\n",
+    )
+    .await;
+    assert_eq!(KasFile::load(&path).await.unwrap().config.header.version, 1);
+
+    let path = create_kas_file(temp.path(), "future.yml", "header:\n  version: 18\n").await;
+    match KasFile::load(&path).await.unwrap_err() {
+        KasError::UnsupportedVersion(file, version) => {
+            assert_eq!(file, path);
+            assert_eq!(version, 18);
+        }
+        other => panic!("Expected UnsupportedVersion, got {:?}", other),
+    }
+
+    assert!(!schema_errors(&serde_json::json!({"header": {"version": 18}})).is_empty());
+}
@@ -2,7 +2,7 @@
 //!
 //! Tests git repository cloning, checkout, patching, and layer management.
 
-use convenient_kas::include_graph::{KasConfig, KasHeader, KasLayer, KasRepo};
+use convenient_kas::include_graph::{KasConfig, KasHeader, KasLayer, KasPatch, KasRepo};
 use convenient_kas::repository_manager::{RepoError, RepositoryManager};
 use std::collections::HashMap;
 use tempfile::TempDir;
@@ -26,6 +26,7 @@ async fn test_local_repository_path() {
         tag: None,
         layers: HashMap::new(),
         patches: None,
+        ..Default::default()
     };
 
     let manager = RepositoryManager::new(temp.path().join("repos"));
@@ -48,6 +49,7 @@ async fn test_local_repository_not_found() {
         tag: None,
         layers: HashMap::new(),
         patches: None,
+        ..Default::default()
     };
 
     let manager = RepositoryManager::new(temp.path().join("repos"));
@@ -73,6 +75,7 @@ async fn test_missing_url_for_remote_repo() {
         tag: None,
         layers: HashMap::new(),
         patches: None,
+        ..Default::default()
     };
 
     let manager = RepositoryManager::new(temp.path().join("repos"));
@@ -103,6 +106,7 @@ async fn test_get_layer_paths_no_explicit_layers() {
         tag: None,
         layers: HashMap::new(), // No explicit layers
         patches: None,
+        ..Default::default()
     };
 
     let manager = RepositoryManager::new(temp.path());
@@ -125,8 +129,8 @@ async fn test_get_layer_paths_with_layers() {
     fs::create_dir_all(&meta_poky).await.unwrap();
 
     let mut layers = HashMap::new();
-    layers.insert("meta".to_string(), KasLayer { path: None });
-    layers.insert("meta-poky".to_string(), KasLayer { path: None });
+    layers.insert("meta".to_string(), KasLayer::default());
+    layers.insert("meta-poky".to_string(), KasLayer::default());
 
     let repo_config = KasRepo {
         path: None,
@@ -137,6 +141,7 @@ async fn test_get_layer_paths_with_layers() {
         tag: None,
         layers,
         patches: None,
+        ..Default::default()
     };
 
     let manager = RepositoryManager::new(temp.path());
@@ -161,6 +166,7 @@ async fn test_get_layer_paths_with_custom_path() {
         "meta-custom".to_string(),
         KasLayer {
             path: Some("custom/path/meta-layer".to_string()),
+            ..Default::default()
         },
     );
 
@@ -173,6 +179,7 @@ async fn test_get_layer_paths_with_custom_path() {
         tag: None,
         layers,
         patches: None,
+        ..Default::default()
     };
 
     let manager = RepositoryManager::new(temp.path());
@@ -190,7 +197,7 @@ async fn test_get_layer_paths_missing_layer() {
 
     // Layer directory doesn't exist
     let mut layers = HashMap::new();
-    layers.insert("nonexistent".to_string(), KasLayer { path: None });
+    layers.insert("nonexistent".to_string(), KasLayer::default());
 
     let repo_config = KasRepo {
         path: None,
@@ -201,6 +208,7 @@ async fn test_get_layer_paths_missing_layer() {
         tag: None,
         layers,
         patches: None,
+        ..Default::default()
     };
 
     let manager = RepositoryManager::new(temp.path());
@@ -230,6 +238,7 @@ async fn test_setup_repositories_creates_directory() {
         bblayers_conf_header: None,
         local_conf_header: None,
         env: None,
+        ..Default::default()
     };
 
     let manager = RepositoryManager::new(&repos_dir);
@@ -305,6 +314,7 @@ async fn test_setup_repositories_with_local_repos() {
             tag: None,
             layers: HashMap::new(),
             patches: None,
+            ..Default::default()
         },
     );
     repos.insert(
@@ -318,6 +328,7 @@ async fn test_setup_repositories_with_local_repos() {
             tag: None,
             layers: HashMap::new(),
             patches: None,
+            ..Default::default()
         },
     );
 
@@ -333,6 +344,7 @@ async fn test_setup_repositories_with_local_repos() {
         bblayers_conf_header: None,
         local_conf_header: None,
         env: None,
+        ..Default::default()
     };
 
     let manager = RepositoryManager::new(temp.path().join("repos"));
@@ -344,3 +356,181 @@ async fn test_setup_repositories_with_local_repos() {
     assert_eq!(repo_paths.get("repo1").unwrap(), &local1);
     assert_eq!(repo_paths.get("repo2").unwrap(), &local2);
 }
+
+/// Run git in `dir` with a fixed identity, returning its trimmed stdout
+fn git(dir: &std::path::Path, args: &[&str]) -> String {
+    let output = std::process::Command::new("git")
+        .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "git {:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+/// Origin repository with README on `main` and a `stable` branch
+fn create_origin(dir: &std::path::Path) -> String {
+    std::fs::create_dir_all(dir).unwrap();
+    git(dir, &["init", "-q", "-b", "main"]);
+    std::fs::write(dir.join("README"), "hello\n").unwrap();
+    git(dir, &["add", "README"]);
+    git(dir, &["commit", "-q", "-m", "initial"]);
+    git(dir, &["branch", "stable"]);
+    git(dir, &["rev-parse", "HEAD"])
+}
+
+fn remote_repo(url: &std::path::Path, branch: &str) -> KasRepo {
+    KasRepo {
+        url: Some(url.to_string_lossy().to_string()),
+        branch: Some(branch.to_string()),
+        ..Default::default()
+    }
+}
+
+fn config_with(repos: Vec<(&str, KasRepo)>) -> KasConfig {
+    KasConfig {
+        header: KasHeader {
+            version: 14,
+            includes: None,
+        },
+        repos: repos.into_iter().map(|(name, repo)| (name.to_string(), repo)).collect(),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_setup_repositories_with_mirror_cache() {
+    let temp = TempDir::new().unwrap();
+    let origin = temp.path().join("origin");
+    let first = create_origin(&origin);
+    let cache_dir = temp.path().join("cache");
+
+    let config = config_with(vec![
+        ("one", remote_repo(&origin, "stable")),
+        ("two", remote_repo(&origin, "main")),
+    ]);
+    let manager = RepositoryManager::new(temp.path().join("repos")).with_cache(&cache_dir);
+    let repo_paths = manager.setup_repositories(&config).await.unwrap();
+
+    // Both checkouts share one bare mirror
+    let mirrors: Vec<_> = std::fs::read_dir(&cache_dir).unwrap().collect();
+    assert_eq!(mirrors.len(), 1);
+    let one = &repo_paths["one"];
+    assert_eq!(git(one, &["rev-parse", "HEAD"]), first);
+    assert_eq!(
+        git(one, &["remote", "get-url", "origin"]),
+        origin.to_string_lossy()
+    );
+
+    // Re-running is a no-op, and does not pick up new commits without update
+    git(&origin, &["checkout", "-q", "stable"]);
+    git(&origin, &["commit", "-q", "--allow-empty", "-m", "second"]);
+    let second = git(&origin, &["rev-parse", "HEAD"]);
+    manager.setup_repositories(&config).await.unwrap();
+    assert_eq!(git(one, &["rev-parse", "HEAD"]), first);
+
+    let manager = manager.with_update(true);
+    manager.setup_repositories(&config).await.unwrap();
+    assert_eq!(git(one, &["rev-parse", "HEAD"]), second);
+    assert_eq!(git(&repo_paths["two"], &["rev-parse", "HEAD"]), first);
+}
+
+#[tokio::test]
+async fn test_setup_repository_locked_commit() {
+    let temp = TempDir::new().unwrap();
+    let origin = temp.path().join("origin");
+    let first = create_origin(&origin);
+    git(&origin, &["commit", "-q", "--allow-empty", "-m", "second"]);
+
+    let repo = KasRepo {
+        commit: Some(first.clone()),
+        ..remote_repo(&origin, "main")
+    };
+    let manager = RepositoryManager::new(temp.path().join("repos"));
+    let repo_path = manager.setup_repository("locked", &repo).await.unwrap();
+    assert_eq!(git(&repo_path, &["rev-parse", "HEAD"]), first);
+}
+
+#[tokio::test]
+async fn test_setup_repositories_applies_patches_idempotently() {
+    let temp = TempDir::new().unwrap();
+    let origin = temp.path().join("origin");
+    create_origin(&origin);
+
+    // A mail formatted patch, and a quilt series with a plain diff
+    let patches_dir = temp.path().join("patches");
+    let work = temp.path().join("work");
+    git(temp.path(), &["clone", "-q", &origin.to_string_lossy(), &work.to_string_lossy()]);
+    std::fs::write(work.join("README"), "hello\nworld\n").unwrap();
+    git(&work, &["commit", "-q", "-a", "-m", "Extend README"]);
+    git(&work, &["format-patch", "-q", "-1", "-o", &patches_dir.to_string_lossy()]);
+    let quilt = patches_dir.join("quilt");
+    fs::create_dir_all(&quilt).await.unwrap();
+    fs::write(
+        quilt.join("new-file.patch"),
+        "--- /dev/null\n+++ b/NEW\n@@ -0,0 +1 @@\n+new\n",
+    )
+    .await
+    .unwrap();
+    fs::write(quilt.join("series"), "# applied in order\nnew-file.patch -p1\n").await.unwrap();
+
+    let mail = std::fs::read_dir(&patches_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .find(|name| name.ends_with(".patch"))
+        .unwrap();
+    let patch = |path: &str| {
+        Some(KasPatch {
+            repo: Some("patches".to_string()),
+            path: path.to_string(),
+        })
+    };
+    let repo = KasRepo {
+        patches: Some(HashMap::from([
+            ("01-mail".to_string(), patch(&mail)),
+            ("02-quilt".to_string(), patch("quilt")),
+        ])),
+        ..remote_repo(&origin, "main")
+    };
+    let patches_repo = KasRepo {
+        path: Some(patches_dir.to_string_lossy().to_string()),
+        ..Default::default()
+    };
+    let config = config_with(vec![("patched", repo), ("patches", patches_repo)]);
+
+    let manager = RepositoryManager::new(temp.path().join("repos"));
+    for _ in 0..2 {
+        let repo_paths = manager.setup_repositories(&config).await.unwrap();
+        let patched = &repo_paths["patched"];
+        let log = git(patched, &["log", "--format=%s"]);
+        assert_eq!(log.lines().collect::<Vec<_>>(), ["02-quilt: new-file.patch", "Extend README", "initial"]);
+        assert_eq!(std::fs::read_to_string(patched.join("NEW")).unwrap(), "new\n");
+    }
+}
+
+#[tokio::test]
+async fn test_setup_repositories_refuses_dirty_checkout() {
+    let temp = TempDir::new().unwrap();
+    let origin = temp.path().join("origin");
+    create_origin(&origin);
+
+    let config = config_with(vec![("repo", remote_repo(&origin, "main"))]);
+    let manager = RepositoryManager::new(temp.path().join("repos"));
+    let repo_paths = manager.setup_repositories(&config).await.unwrap();
+    let readme = repo_paths["repo"].join("README");
+    std::fs::write(&readme, "local change\n").unwrap();
+
+    match manager.setup_repositories(&config).await {
+        Err(RepoError::DirtyRepo(name, changes)) => {
+            assert_eq!(name, "repo");
+            assert!(changes.contains("README"), "{}", changes);
+        }
+        other => panic!("Expected DirtyRepo error, got {:?}", other),
+    }
+    assert_eq!(std::fs::read_to_string(&readme).unwrap(), "local change\n");
+
+    let manager = manager.with_force_checkout(true);
+    manager.setup_repositories(&config).await.unwrap();
+    assert_eq!(std::fs::read_to_string(&readme).unwrap(), "hello\n");
+}
@@ -0,0 +1,38 @@
+use std::path::PathBuf;
+
+/// Errors of loading and syncing repo manifests
+#[derive(Debug, thiserror::Error)]
+pub enum RepoError {
+    #[error("IO error at {0}: {1}")]
+    Io(PathBuf, String),
+
+    #[error("Failed to parse manifest {0}: {1}")]
+    Parse(PathBuf, String),
+
+    #[error("Manifest {0} includes itself")]
+    IncludeCycle(PathBuf),
+
+    #[error("Remote {0} is defined differently by {1}")]
+    DuplicateRemote(String, PathBuf),
+
+    #[error("Default is defined differently by {0}")]
+    DuplicateDefault(PathBuf),
+
+    #[error("Project path {0} is used more than once")]
+    DuplicatePath(String),
+
+    #[error("{0} refers to unknown project {1}")]
+    UnknownProject(String, String),
+
+    #[error("Project {0} has unknown remote {1}")]
+    UnknownRemote(String, String),
+
+    #[error("Invalid fetch url for {0}: {1}")]
+    FetchUrl(String, String),
+
+    #[error("Invalid path {0}: {1}")]
+    InvalidPath(String, String),
+
+    #[error("Failed to sync {0}: {1}")]
+    SyncFailed(String, String),
+}
@@ -0,0 +1,90 @@
+//! Project groups and their filtering, like `repo init -g`
+//!
+//! Every project is implicitly in `all`, `name:<name>` and `path:<path>`, and
+//! in `default` unless it is in `notdefault`. A filter is evaluated left to
+//! right: a group selects its projects and `-group` deselects them again.
+
+use crate::{Manifest, Project};
+
+/// Split a group list at commas and whitespace
+pub fn parse_groups(groups: &str) -> Vec<String> {
+    groups
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|g| !g.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+impl Project {
+    /// Groups of the project, including the implicit ones
+    pub fn all_groups(&self) -> Vec<String> {
+        let mut groups = vec!["all".to_string()];
+        groups.extend(self.groups.as_deref().map(parse_groups).unwrap_or_default());
+        groups.push(format!("name:{}", self.name));
+        groups.push(format!("path:{}", self.checkout_path()));
+        if !groups.iter().any(|g| g == "notdefault") {
+            groups.push("default".to_string());
+        }
+        groups
+    }
+
+    /// Whether the project is selected by `filter`; an empty filter means `default`
+    pub fn matches_groups(&self, filter: &[String]) -> bool {
+        let default = ["default".to_string()];
+        let filter = if filter.is_empty() {
+            &default[..]
+        } else {
+            filter
+        };
+        let groups = self.all_groups();
+
+        let mut matched = false;
+        for group in filter {
+            if let Some(excluded) = group.strip_prefix('-') {
+                if groups.iter().any(|g| g == excluded) {
+                    matched = false;
+                }
+            } else if groups.contains(group) {
+                matched = true;
+            }
+        }
+        matched
+    }
+}
+
+impl Manifest {
+    /// Keep only the projects selected by `filter`
+    pub fn retain_groups(&mut self, filter: &[String]) {
+        if let Some(projects) = &mut self.project {
+            projects.retain(|p| p.matches_groups(filter));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(name: &str, groups: Option<&str>) -> Project {
+        Project {
+            name: name.to_string(),
+            groups: groups.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn matches_groups() {
+        let plain = project("poky", None);
+        let bsp = project("meta-bsp", Some("bsp, notdefault"));
+
+        assert!(plain.matches_groups(&[]));
+        assert!(!bsp.matches_groups(&[]));
+        assert!(bsp.matches_groups(&parse_groups("default,bsp")));
+        assert!(bsp.matches_groups(&parse_groups("all")));
+        assert!(!bsp.matches_groups(&parse_groups("all,-bsp")));
+        assert!(bsp.matches_groups(&parse_groups("-bsp,bsp")));
+        assert!(plain.matches_groups(&parse_groups("name:poky")));
+        assert!(!plain.matches_groups(&parse_groups("default -path:poky")));
+    }
+}
@@ -1,3 +1,12 @@
+pub mod error;
+pub mod groups;
+pub mod resolve;
+pub mod sync;
+
+pub use error::RepoError;
+pub use groups::parse_groups;
+pub use sync::{RepoSync, find_layers, layer_paths, oe_root, template_layers};
+
 use serde::{Deserialize, Serialize};
 use std::default::Default;
 use std::path::Path;
@@ -8,7 +17,7 @@ use url::Url;
 use xmlem::display::Config;
 use xmlem::Document;
 
-#[derive(Default, Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Default, Debug, Serialize, Deserialize, PartialEq, Clone)]
 #[serde(rename = "manifest")]
 pub struct Manifest {
     pub remote: Option<Vec<Remote>>,
@@ -25,7 +34,7 @@ pub struct Manifest {
     pub include: Option<Vec<Include>>,
 }
 
-#[derive(Default, Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Default, Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct Remote {
     #[serde(rename = "@name")]
     pub name: String,
@@ -39,7 +48,7 @@ pub struct Remote {
     pub revision: Option<String>,
 }
 
-#[derive(Default, Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Default, Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct ManifestDefault {
     #[serde(rename = "@remote")]
     pub remote: Option<String>,
@@ -57,15 +66,19 @@ pub struct ManifestDefault {
     pub sync_tags: Option<String>,
 }
 
-#[derive(Default, Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Default, Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct ManifestServer {
     #[serde(rename = "@url")]
     pub url: String,
 }
-#[derive(Default, Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Default, Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct RemoveProject {
     #[serde(rename = "@name")]
     pub name: String,
+    #[serde(rename = "@path")]
+    pub path: Option<String>,
+    #[serde(rename = "@optional")]
+    pub optional: Option<String>,
 }
 
 #[derive(Default, Debug, Serialize, Deserialize, PartialEq, Clone)]
@@ -108,7 +121,9 @@ pub struct Project {
 pub struct Annotation {
     #[serde(rename = "@name")]
     pub name: String,
+    #[serde(rename = "@value")]
     pub value: String,
+    #[serde(rename = "@keep")]
     pub keep: Option<String>,
 }
 
@@ -128,7 +143,7 @@ pub struct Linkfile {
     pub dest: String,
 }
 
-#[derive(Default, Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Default, Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct RepoHooks {
     #[serde(rename = "@in-project")]
     pub in_project: String,
@@ -136,20 +151,34 @@ pub struct RepoHooks {
     pub enabled_list: String,
 }
 
-#[derive(Default, Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Default, Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct ExtendProject {
     #[serde(rename = "@name")]
     pub name: String,
+    #[serde(rename = "@path")]
     pub path: Option<String>,
+    #[serde(rename = "@dest-path")]
+    pub dest_path: Option<String>,
+    #[serde(rename = "@remote")]
     pub remote: Option<String>,
+    #[serde(rename = "@revision")]
     pub revision: Option<String>,
+    #[serde(rename = "@dest-branch")]
+    pub dest_branch: Option<String>,
+    #[serde(rename = "@upstream")]
+    pub upstream: Option<String>,
+    #[serde(rename = "@groups")]
     pub groups: Option<String>,
 }
 
-#[derive(Default, Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Default, Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct Include {
     #[serde(rename = "@name")]
     pub name: String,
+    #[serde(rename = "@groups")]
+    pub groups: Option<String>,
+    #[serde(rename = "@revision")]
+    pub revision: Option<String>,
 }
 
 pub struct ProjectsIterator<'a> {
@@ -160,6 +189,7 @@ pub struct ProjectsIterator<'a> {
 #[derive(Debug)]
 pub struct ConvenientProject {
     pub name: String,
+    pub path: String,
     pub git_uri: String,
     pub relative_path: Option<bool>,
     pub revision: String,
@@ -278,17 +308,10 @@ impl<'a> ProjectsIterator<'a> {
         }
     }
 
-    fn get_revision_or_default_or_todo(&mut self, remote: &Project) -> String {
-        match remote.revision {
-            Some(ref revision) => revision.clone(),
-            None => match self.manifest.default {
-                Some(ref default) => match default.revision {
-                    Some(ref revision) => revision.clone(),
-                    None => "TODO".to_string(),
-                },
-                None => "TODO".to_string(),
-            },
-        }
+    fn get_revision_or_default_or_todo(&mut self, project: &Project) -> String {
+        self.manifest
+            .project_revision(project)
+            .unwrap_or_else(|| "TODO".to_string())
     }
 
     fn get_dest_branch_or_default_or_todo(&mut self, remote: &Project) -> Option<String> {
@@ -322,6 +345,7 @@ impl<'a> Iterator for ProjectsIterator<'a> {
                 if self.index < list.len() {
                     let p: &Project = &list[self.index];
                     let name = p.name.clone();
+                    let path = p.checkout_path().to_string();
                     let remote = self.get_remote_or_default_or_todo(&p.remote);
                     let git_uri = self
                         .get_git_uri(&remote)
@@ -331,6 +355,7 @@ impl<'a> Iterator for ProjectsIterator<'a> {
                     let dest_branch = self.get_dest_branch_or_default_or_todo(p);
                     let result = ConvenientProject {
                         name,
+                        path,
                         git_uri,
                         relative_path,
                         revision,
@@ -349,12 +374,64 @@ impl<'a> Iterator for ProjectsIterator<'a> {
 }
 
 impl Manifest {
-    pub fn iter(&self) -> ProjectsIterator {
+    pub fn iter(&self) -> ProjectsIterator<'_> {
         ProjectsIterator {
             manifest: self,
             index: 0,
         }
     }
+
+    /// The remote of a project, falling back to the default remote
+    pub fn project_remote(&self, project: &Project) -> Option<&Remote> {
+        let name = project
+            .remote
+            .as_ref()
+            .or_else(|| self.default.as_ref()?.remote.as_ref())?;
+        self.remote.iter().flatten().find(|r| &r.name == name)
+    }
+
+    /// The revision of a project: its own, else its remote's, else the default one
+    pub fn project_revision(&self, project: &Project) -> Option<String> {
+        project
+            .revision
+            .clone()
+            .or_else(|| self.project_remote(project)?.revision.clone())
+            .or_else(|| self.default.as_ref()?.revision.clone())
+    }
+
+    /// The url to fetch a project from
+    ///
+    /// Relative `fetch` urls of the remote are resolved against `manifest_url`,
+    /// the url the manifest repository was cloned from, like in `repo`.
+    pub fn fetch_url(
+        &self,
+        project: &Project,
+        manifest_url: Option<&str>,
+    ) -> Result<String, RepoError> {
+        let remote = self.project_remote(project).ok_or_else(|| {
+            let name = project.remote.clone().unwrap_or_default();
+            RepoError::UnknownRemote(project.name.clone(), name)
+        })?;
+        let fetch = remote.fetch.as_deref().unwrap_or(".").trim_end_matches('/');
+        let fetch = match Url::parse(fetch) {
+            Ok(_) => fetch.to_string(),
+            Err(url::ParseError::RelativeUrlWithoutBase) => {
+                let base = manifest_url.ok_or_else(|| {
+                    RepoError::FetchUrl(
+                        project.name.clone(),
+                        format!("{} is relative and there is no manifest url", fetch),
+                    )
+                })?;
+                let base = Url::parse(base.trim_end_matches('/'))
+                    .map_err(|e| RepoError::FetchUrl(project.name.clone(), e.to_string()))?;
+                base.join(fetch)
+                    .map_err(|e| RepoError::FetchUrl(project.name.clone(), e.to_string()))?
+                    .to_string()
+            }
+            Err(e) => return Err(RepoError::FetchUrl(project.name.clone(), e.to_string())),
+        };
+        Ok(format!("{}/{}", fetch.trim_end_matches('/'), project.name))
+    }
 }
 
 /// Converts the given Manifest struct to a string by:
@@ -391,6 +468,9 @@ pub fn to_string(manifest: &Manifest) -> String {
         "<fetch/>",
         "<review/>",
         "dest-branch=\"\"",
+        "dest-path=\"\"",
+        "optional=\"\"",
+        "keep=\"\"",
         "sync-j=\"\"",
         "sync-c=\"\"",
         "sync-s=\"\"",
@@ -404,6 +484,7 @@ pub fn to_string(manifest: &Manifest) -> String {
         "path=\"\"",
         "upstream=\"\"",
         "alias=\"\"",
+        "review=\"\"",
     ];
     let mut result = quick_xml::se::to_string(&manifest).unwrap();
     for i in cleanup {
@@ -497,8 +578,14 @@ mod tests {
             r#"<manifest><remove-project name="foo"/><remove-project name="bar"/></manifest>"#;
         let should_be = Manifest {
             remove_project: Some(vec![
-                RemoveProject { name: "foo".into() },
-                RemoveProject { name: "bar".into() },
+                RemoveProject {
+                    name: "foo".into(),
+                    ..Default::default()
+                },
+                RemoveProject {
+                    name: "bar".into(),
+                    ..Default::default()
+                },
             ]),
             ..Default::default()
         };
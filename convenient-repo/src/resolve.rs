@@ -0,0 +1,335 @@
+//! Loading manifests the way `repo` does
+//!
+//! [`Manifest::load`] reads a manifest file and resolves it into a flat list of
+//! projects: `<include>` files are loaded recursively, then `<remove-project>`,
+//! the manifest's own `<project>` elements and `<extend-project>` are applied.
+//! The XML deserializer groups elements by kind, so this fixed order stands in
+//! for document order; it matches how local manifests usually replace projects
+//! (remove, then add again) and extend them.
+
+use crate::error::RepoError;
+use crate::{Manifest, Project};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+impl Manifest {
+    /// Load a manifest and resolve includes, removed, nested and extended projects
+    ///
+    /// Include names are relative to the directory of `path`, the manifest
+    /// repository, like in `repo`. The result has no `include`,
+    /// `remove-project` or `extend-project` elements left.
+    pub fn load(path: impl AsRef<Path>) -> Result<Manifest, RepoError> {
+        let path = path.as_ref();
+        let root = path.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+        let manifest = Self::load_file(path, &root, &mut Vec::new())?;
+
+        let mut paths = HashSet::new();
+        for project in manifest.project.iter().flatten() {
+            if !paths.insert(project.checkout_path()) {
+                return Err(RepoError::DuplicatePath(
+                    project.checkout_path().to_string(),
+                ));
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// Parse a manifest file without resolving anything
+    pub fn parse(path: impl AsRef<Path>) -> Result<Manifest, RepoError> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| RepoError::Io(path.to_path_buf(), e.to_string()))?;
+        quick_xml::de::from_str(&contents)
+            .map_err(|e| RepoError::Parse(path.to_path_buf(), e.to_string()))
+    }
+
+    fn load_file(
+        path: &Path,
+        root: &Path,
+        stack: &mut Vec<PathBuf>,
+    ) -> Result<Manifest, RepoError> {
+        if stack.iter().any(|p| p == path) {
+            return Err(RepoError::IncludeCycle(path.to_path_buf()));
+        }
+        let mut manifest = Self::parse(path)?;
+        debug!("Loading repo manifest {}", path.display());
+
+        let mut resolved = Manifest {
+            remote: manifest.remote.take(),
+            default: manifest.default.take(),
+            manifest_server: manifest.manifest_server.take(),
+            repo_hooks: manifest.repo_hooks.take(),
+            ..Default::default()
+        };
+
+        stack.push(path.to_path_buf());
+        for include in manifest.include.take().unwrap_or_default() {
+            let include_path = root.join(&include.name);
+            let mut included = Self::load_file(&include_path, root, stack)?;
+            for project in included.project.iter_mut().flatten() {
+                if let Some(groups) = &include.groups {
+                    project.add_groups(groups);
+                }
+                if project.revision.is_none() {
+                    project.revision.clone_from(&include.revision);
+                }
+            }
+            resolved.merge(included, &include_path)?;
+        }
+        stack.pop();
+
+        for remove in manifest.remove_project.take().unwrap_or_default() {
+            let projects = resolved.project.get_or_insert_with(Vec::new);
+            let before = projects.len();
+            projects.retain(|p| {
+                !(p.name == remove.name
+                    && remove
+                        .path
+                        .as_deref()
+                        .is_none_or(|path| path == p.checkout_path()))
+            });
+            if projects.len() == before && remove.optional.as_deref() != Some("true") {
+                return Err(RepoError::UnknownProject(
+                    "remove-project".to_string(),
+                    remove.name,
+                ));
+            }
+        }
+
+        for project in manifest.project.take().unwrap_or_default() {
+            resolved
+                .project
+                .get_or_insert_with(Vec::new)
+                .extend(project.flatten(None));
+        }
+
+        for extend in manifest.extend_project.take().unwrap_or_default() {
+            let mut found = false;
+            for project in resolved.project.iter_mut().flatten() {
+                if project.name != extend.name
+                    || extend
+                        .path
+                        .as_deref()
+                        .is_some_and(|path| path != project.checkout_path())
+                {
+                    continue;
+                }
+                found = true;
+                if let Some(groups) = &extend.groups {
+                    project.add_groups(groups);
+                }
+                if extend.dest_path.is_some() {
+                    project.path.clone_from(&extend.dest_path);
+                }
+                if extend.remote.is_some() {
+                    project.remote.clone_from(&extend.remote);
+                }
+                if extend.revision.is_some() {
+                    project.revision.clone_from(&extend.revision);
+                }
+                if extend.dest_branch.is_some() {
+                    project.dest_branch.clone_from(&extend.dest_branch);
+                }
+                if extend.upstream.is_some() {
+                    project.upstream.clone_from(&extend.upstream);
+                }
+            }
+            if !found {
+                return Err(RepoError::UnknownProject(
+                    "extend-project".to_string(),
+                    extend.name,
+                ));
+            }
+        }
+
+        Ok(resolved)
+    }
+
+    /// Add the remotes, default and projects of an included manifest
+    fn merge(&mut self, included: Manifest, path: &Path) -> Result<(), RepoError> {
+        for remote in included.remote.unwrap_or_default() {
+            let remotes = self.remote.get_or_insert_with(Vec::new);
+            match remotes.iter().find(|r| r.name == remote.name) {
+                Some(existing) if *existing != remote => {
+                    return Err(RepoError::DuplicateRemote(remote.name, path.to_path_buf()));
+                }
+                Some(_) => {}
+                None => remotes.push(remote),
+            }
+        }
+        match (&self.default, included.default) {
+            (Some(existing), Some(default)) if *existing != default => {
+                return Err(RepoError::DuplicateDefault(path.to_path_buf()));
+            }
+            (None, default) => self.default = default,
+            _ => {}
+        }
+        if self.manifest_server.is_none() {
+            self.manifest_server = included.manifest_server;
+        }
+        if let Some(hooks) = included.repo_hooks {
+            self.repo_hooks.get_or_insert_with(Vec::new).extend(hooks);
+        }
+        self.project
+            .get_or_insert_with(Vec::new)
+            .extend(included.project.unwrap_or_default());
+        Ok(())
+    }
+}
+
+impl Project {
+    /// Path of the checkout relative to the workspace, defaulting to the name
+    pub fn checkout_path(&self) -> &str {
+        self.path.as_deref().unwrap_or(&self.name)
+    }
+
+    fn add_groups(&mut self, groups: &str) {
+        self.groups = Some(match self.groups.take() {
+            Some(existing) if !existing.is_empty() => format!("{},{}", existing, groups),
+            _ => groups.to_string(),
+        });
+    }
+
+    /// The project followed by its nested projects, with names and paths
+    /// joined to those of their parents
+    fn flatten(mut self, parent: Option<&Project>) -> Vec<Project> {
+        if let Some(parent) = parent {
+            self.path = Some(format!(
+                "{}/{}",
+                parent.checkout_path(),
+                self.checkout_path()
+            ));
+            self.name = format!("{}/{}", parent.name, self.name);
+        }
+        let children = self.project.take().unwrap_or_default();
+        let mut projects = Vec::with_capacity(children.len() + 1);
+        for child in children {
+            projects.extend(child.flatten(Some(&self)));
+        }
+        projects.insert(0, self);
+        projects
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn write(dir: &Path, name: &str, contents: &str) -> PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn paths(manifest: &Manifest) -> Vec<&str> {
+        manifest
+            .project
+            .iter()
+            .flatten()
+            .map(Project::checkout_path)
+            .collect()
+    }
+
+    #[test]
+    fn load_resolves_includes_remove_and_extend() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "base.xml",
+            r#"<manifest>
+  <remote name="origin" fetch="https://example.com/"/>
+  <default remote="origin" revision="main"/>
+  <project name="poky" groups="core"/>
+  <project name="meta-old"/>
+</manifest>"#,
+        );
+        let path = write(
+            dir.path(),
+            "default.xml",
+            r#"<manifest>
+  <include name="base.xml" groups="yocto" revision="scarthgap"/>
+  <remove-project name="meta-old"/>
+  <project name="meta-new" path="layers/meta-new" revision="v1"/>
+  <project name="meta-old" path="layers/meta-old"/>
+  <extend-project name="poky" dest-path="layers/poky" groups="extra"/>
+</manifest>"#,
+        );
+
+        let manifest = Manifest::load(&path).unwrap();
+        assert_eq!(
+            paths(&manifest),
+            ["layers/poky", "layers/meta-new", "layers/meta-old"]
+        );
+        assert!(manifest.include.is_none());
+
+        let poky = &manifest.project.as_ref().unwrap()[0];
+        assert_eq!(poky.groups.as_deref(), Some("core,yocto,extra"));
+        assert_eq!(poky.revision.as_deref(), Some("scarthgap"));
+        assert_eq!(
+            manifest.default.as_ref().unwrap().revision.as_deref(),
+            Some("main")
+        );
+    }
+
+    #[test]
+    fn load_flattens_nested_projects() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write(
+            dir.path(),
+            "default.xml",
+            r#"<manifest><project name="top" path="src"><project name="sub" path="lib"/></project></manifest>"#,
+        );
+        let manifest = Manifest::load(&path).unwrap();
+        let names: Vec<_> = manifest
+            .project
+            .iter()
+            .flatten()
+            .map(|p| p.name.as_str())
+            .collect();
+        assert_eq!(names, ["top", "top/sub"]);
+        assert_eq!(paths(&manifest), ["src", "src/lib"]);
+    }
+
+    #[test]
+    fn load_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let cycle = write(
+            dir.path(),
+            "cycle.xml",
+            r#"<manifest><include name="cycle.xml"/></manifest>"#,
+        );
+        assert!(matches!(
+            Manifest::load(&cycle),
+            Err(RepoError::IncludeCycle(_))
+        ));
+
+        let unknown = write(
+            dir.path(),
+            "unknown.xml",
+            r#"<manifest><remove-project name="nope"/></manifest>"#,
+        );
+        assert!(matches!(
+            Manifest::load(&unknown),
+            Err(RepoError::UnknownProject(..))
+        ));
+
+        let optional = write(
+            dir.path(),
+            "optional.xml",
+            r#"<manifest><remove-project name="nope" optional="true"/></manifest>"#,
+        );
+        assert!(Manifest::load(&optional).is_ok());
+
+        let duplicate = write(
+            dir.path(),
+            "duplicate.xml",
+            r#"<manifest><project name="a" path="x"/><project name="b" path="x"/></manifest>"#,
+        );
+        assert!(matches!(
+            Manifest::load(&duplicate),
+            Err(RepoError::DuplicatePath(_))
+        ));
+    }
+}
@@ -0,0 +1,534 @@
+//! Checking out the projects of a manifest, like `repo sync`
+//!
+//! Projects are cloned or fetched in parallel with
+//! [`convenient_git::async_git::AsyncGitRepository`] and checked out detached at
+//! their revision. Nested projects wait for the project they live in. Once all
+//! projects are checked out, `<copyfile>` and `<linkfile>` are applied.
+
+use crate::error::RepoError;
+use crate::{Manifest, Project};
+use convenient_git::async_git::AsyncGitRepository;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tracing::{debug, info};
+
+/// Syncs the projects of a manifest into a workspace
+#[derive(Debug, Clone)]
+pub struct RepoSync {
+    workspace: PathBuf,
+    manifest_url: Option<String>,
+    jobs: Option<usize>,
+}
+
+/// A project to check out
+struct Checkout {
+    name: String,
+    dir: PathBuf,
+    url: String,
+    revision: Option<String>,
+}
+
+impl RepoSync {
+    /// Sync into `workspace`, the directory holding all project checkouts
+    pub fn new(workspace: impl AsRef<Path>) -> Self {
+        Self {
+            workspace: workspace.as_ref().to_path_buf(),
+            manifest_url: None,
+            jobs: None,
+        }
+    }
+
+    /// Url of the manifest repository, needed for relative `fetch` urls
+    pub fn with_manifest_url(mut self, url: impl Into<String>) -> Self {
+        self.manifest_url = Some(url.into());
+        self
+    }
+
+    /// Number of parallel fetches, instead of the manifest's `sync-j`
+    pub fn with_jobs(mut self, jobs: usize) -> Self {
+        self.jobs = Some(jobs.max(1));
+        self
+    }
+
+    /// Check out every project of `manifest`
+    ///
+    /// Returns the checkout directory of each project by name.
+    pub async fn sync(&self, manifest: &Manifest) -> Result<BTreeMap<String, PathBuf>, RepoError> {
+        let projects: Vec<&Project> = manifest.project.iter().flatten().collect();
+        let jobs = self
+            .jobs
+            .or_else(|| manifest.default.as_ref()?.sync_j.as_ref()?.parse().ok())
+            .unwrap_or_else(|| std::thread::available_parallelism().map_or(4, |n| n.get()));
+        let semaphore = Arc::new(Semaphore::new(jobs));
+
+        // Projects nested in other projects are checked out after their parents
+        let mut levels: BTreeMap<usize, Vec<Checkout>> = BTreeMap::new();
+        for project in &projects {
+            let path = project.checkout_path();
+            let depth = projects
+                .iter()
+                .filter(|other| path.starts_with(&format!("{}/", other.checkout_path())))
+                .count();
+            levels.entry(depth).or_default().push(Checkout {
+                name: project.name.clone(),
+                dir: self.workspace.join(checked_path(path)?),
+                url: manifest.fetch_url(project, self.manifest_url.as_deref())?,
+                revision: manifest.project_revision(project),
+            });
+        }
+
+        let mut checkouts = BTreeMap::new();
+        for level in levels.into_values() {
+            let mut tasks = JoinSet::new();
+            for checkout in level {
+                let semaphore = semaphore.clone();
+                tasks.spawn(async move {
+                    let _permit = semaphore.acquire().await;
+                    let result = checkout.run().await;
+                    (checkout.name, checkout.dir, result)
+                });
+            }
+            while let Some(joined) = tasks.join_next().await {
+                let (name, dir, result) =
+                    joined.map_err(|e| RepoError::SyncFailed(String::new(), e.to_string()))?;
+                result.map_err(|e| RepoError::SyncFailed(name.clone(), e))?;
+                checkouts.insert(name, dir);
+            }
+        }
+
+        for project in &projects {
+            self.link_files(project)?;
+        }
+        info!("Synced {} projects", checkouts.len());
+        Ok(checkouts)
+    }
+
+    /// Apply the `<copyfile>` and `<linkfile>` elements of a project
+    fn link_files(&self, project: &Project) -> Result<(), RepoError> {
+        let project_dir = checked_path(project.checkout_path())?;
+
+        for copy in project.copyfile.iter().flatten() {
+            let src = self
+                .workspace
+                .join(&project_dir)
+                .join(checked_path(&copy.src)?);
+            let dest = self.workspace.join(checked_path(&copy.dest)?);
+            if !src.is_file() {
+                return Err(RepoError::InvalidPath(
+                    copy.src.clone(),
+                    "copyfile source is not a file".to_string(),
+                ));
+            }
+            create_parent(&dest)?;
+            std::fs::copy(&src, &dest).map_err(|e| RepoError::Io(dest.clone(), e.to_string()))?;
+            debug!("Copied {} to {}", src.display(), dest.display());
+        }
+
+        for link in project.linkfile.iter().flatten() {
+            let dest_path = checked_path(&link.dest)?;
+            let dest = self.workspace.join(&dest_path);
+
+            // Relative link, so the workspace can be moved
+            let up = dest_path.components().count().saturating_sub(1);
+            let mut target: PathBuf = std::iter::repeat_n("..", up).collect();
+            target.push(&project_dir);
+            target.push(checked_path(&link.src)?);
+
+            create_parent(&dest)?;
+            if dest.symlink_metadata().is_ok() {
+                std::fs::remove_file(&dest)
+                    .map_err(|e| RepoError::Io(dest.clone(), e.to_string()))?;
+            }
+            std::os::unix::fs::symlink(&target, &dest)
+                .map_err(|e| RepoError::Io(dest.clone(), e.to_string()))?;
+            debug!("Linked {} to {}", dest.display(), target.display());
+        }
+        Ok(())
+    }
+}
+
+impl Checkout {
+    async fn run(&self) -> Result<(), String> {
+        let repo = AsyncGitRepository::new(&self.dir, self.url.as_str(), None);
+        let existing = self.dir.join(".git").exists();
+        repo.clone_or_open().await.map_err(|e| e.to_string())?;
+        if existing {
+            repo.fetch().await.map_err(|e| e.to_string())?;
+        }
+
+        let revision = self.revision.as_deref().unwrap_or("HEAD");
+        let revision = revision
+            .strip_prefix("refs/heads/")
+            .or_else(|| revision.strip_prefix("refs/tags/"))
+            .unwrap_or(revision);
+        repo.checkout(revision).await.map_err(|e| e.to_string())?;
+        debug!("Checked out {} at {}", self.name, revision);
+        Ok(())
+    }
+}
+
+/// Checkout holding `oe-init-build-env`, the `OEROOT` of the build
+///
+/// This is poky or openembedded-core, whose `bblayers.conf.sample` templates
+/// refer to it as `##OEROOT##`.
+pub fn oe_root(checkouts: &BTreeMap<String, PathBuf>) -> Option<&PathBuf> {
+    checkouts
+        .values()
+        .find(|dir| dir.join("oe-init-build-env").is_file())
+}
+
+/// Layers listed in `BBLAYERS` of a `bblayers.conf.sample`, like `oe-setup-builddir`
+///
+/// The sample is read from `templateconf`, relative to `oe_root` unless
+/// absolute. Without one, `.templateconf` in `oe_root` names the directory
+/// (as poky's does), or else oe-core's `meta/conf/templates/default` is used.
+/// `##OEROOT##` and `##COREBASE##` stand for `oe_root`.
+pub fn template_layers(
+    oe_root: &Path,
+    templateconf: Option<&Path>,
+) -> Result<Vec<PathBuf>, RepoError> {
+    let templateconf = match templateconf {
+        Some(dir) => dir.to_path_buf(),
+        None => default_templateconf(oe_root)?,
+    };
+    let sample = oe_root.join(templateconf).join("bblayers.conf.sample");
+    let content = std::fs::read_to_string(&sample)
+        .map_err(|e| RepoError::Io(sample.clone(), e.to_string()))?;
+
+    // BBLAYERS ?= " \
+    //   ##OEROOT##/meta \
+    //   "
+    let value = content
+        .match_indices("BBLAYERS")
+        .map(|(i, name)| &content[i + name.len()..])
+        .find(|rest| rest.trim_start().starts_with(['?', '=']))
+        .and_then(|rest| {
+            let value = &rest[rest.find('"')? + 1..];
+            value.find('"').map(|end| &value[..end])
+        })
+        .ok_or_else(|| RepoError::Parse(sample.clone(), "no BBLAYERS assignment".to_string()))?;
+
+    let root = oe_root.display().to_string();
+    Ok(value
+        .split_whitespace()
+        .filter(|word| *word != "\\")
+        .map(|word| {
+            let word = word.replace("##OEROOT##", &root).replace("##COREBASE##", &root);
+            normalize(&oe_root.join(word))
+        })
+        .collect())
+}
+
+/// `TEMPLATECONF` of `oe_root`'s `.templateconf`, or oe-core's default
+fn default_templateconf(oe_root: &Path) -> Result<PathBuf, RepoError> {
+    let file = oe_root.join(".templateconf");
+    if !file.is_file() {
+        return Ok(PathBuf::from("meta/conf/templates/default"));
+    }
+    let content =
+        std::fs::read_to_string(&file).map_err(|e| RepoError::Io(file.clone(), e.to_string()))?;
+    // TEMPLATECONF=${TEMPLATECONF:-meta-poky/conf/templates/default}
+    content
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("TEMPLATECONF="))
+        .map(|value| {
+            let value = value.trim().trim_matches('"');
+            value
+                .split_once(":-")
+                .map_or(value, |(_, default)| default.trim_end_matches('}'))
+                .into()
+        })
+        .ok_or_else(|| RepoError::Parse(file, "no TEMPLATECONF".to_string()))
+}
+
+/// `layers` grouped by the project whose checkout holds them
+///
+/// Each layer belongs to the innermost checkout it lies in, so a project
+/// checked out inside poky keeps its own layers. Layers outside every
+/// checkout or without `conf/layer.conf` are an error.
+pub fn layer_paths(
+    checkouts: &BTreeMap<String, PathBuf>,
+    layers: &[PathBuf],
+) -> Result<HashMap<String, Vec<PathBuf>>, RepoError> {
+    let mut by_project: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    for layer in layers {
+        if !layer.join("conf/layer.conf").is_file() {
+            return Err(RepoError::InvalidPath(
+                layer.display().to_string(),
+                "is not a layer, it has no conf/layer.conf".to_string(),
+            ));
+        }
+        let project = checkouts
+            .iter()
+            .filter(|(_, dir)| layer.starts_with(dir))
+            .max_by_key(|(_, dir)| dir.components().count())
+            .map(|(name, _)| name.clone())
+            .ok_or_else(|| {
+                RepoError::InvalidPath(
+                    layer.display().to_string(),
+                    "is not in a project checkout".to_string(),
+                )
+            })?;
+        let project_layers = by_project.entry(project).or_default();
+        if !project_layers.contains(layer) {
+            project_layers.push(layer.clone());
+        }
+    }
+    Ok(by_project)
+}
+
+/// Layers in the checkout of `project`, found by their `conf/layer.conf`
+///
+/// For projects a template does not list: the checkout itself or a directory
+/// up to two levels below it (like `poky/meta` or `meta-openembedded/meta-oe`)
+/// is a layer if it has one. Hidden directories and the checkouts of nested
+/// projects are skipped.
+pub fn find_layers(checkouts: &BTreeMap<String, PathBuf>, project: &str) -> Vec<PathBuf> {
+    let mut layers = Vec::new();
+    if let Some(dir) = checkouts.get(project) {
+        let nested: Vec<&PathBuf> = checkouts.values().filter(|d| *d != dir).collect();
+        scan_layers(dir, 2, &nested, &mut layers);
+    }
+    layers.sort();
+    layers
+}
+
+fn scan_layers(dir: &Path, depth: usize, nested: &[&PathBuf], layers: &mut Vec<PathBuf>) {
+    if dir.join("conf/layer.conf").is_file() {
+        layers.push(dir.to_path_buf());
+    }
+    if depth == 0 {
+        return;
+    }
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let hidden = entry.file_name().to_string_lossy().starts_with('.');
+        if !hidden && !nested.contains(&&path) && entry.file_type().is_ok_and(|t| t.is_dir()) {
+            scan_layers(&path, depth - 1, nested, layers);
+        }
+    }
+}
+
+/// `path` with `.` and `..` resolved lexically
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// A path relative to the workspace or a project, without `..`
+fn checked_path(path: &str) -> Result<PathBuf, RepoError> {
+    let path = Path::new(path);
+    if path
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+        && !path.as_os_str().is_empty()
+    {
+        Ok(path.to_path_buf())
+    } else {
+        Err(RepoError::InvalidPath(
+            path.display().to_string(),
+            "must be relative and stay inside the workspace".to_string(),
+        ))
+    }
+}
+
+fn create_parent(path: &Path) -> Result<(), RepoError> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| RepoError::Io(parent.to_path_buf(), e.to_string()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::{Repository, Signature};
+    use std::fs;
+
+    /// A repository with one commit holding `files`, tagged `v1`
+    fn remote_repo(dir: &Path, files: &[(&str, &str)]) -> String {
+        let repo = Repository::init(dir).unwrap();
+        for (name, contents) in files {
+            let path = dir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        let mut index = repo.index().unwrap();
+        index
+            .add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+            .unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+        let oid = repo
+            .commit(Some("HEAD"), &signature, &signature, "init", &tree, &[])
+            .unwrap();
+        repo.tag_lightweight("v1", &repo.find_object(oid, None).unwrap(), false)
+            .unwrap();
+        oid.to_string()
+    }
+
+    #[tokio::test]
+    async fn sync_checks_out_projects_and_links_files() {
+        let remotes = tempfile::tempdir().unwrap();
+        let commit = remote_repo(
+            &remotes.path().join("poky"),
+            &[
+                ("meta/conf/layer.conf", ""),
+                ("meta-poky/conf/layer.conf", ""),
+                ("meta-selftest/conf/layer.conf", ""),
+                ("oe-init-build-env", ""),
+                (
+                    ".templateconf",
+                    "TEMPLATECONF=${TEMPLATECONF:-meta-poky/conf/templates/default}\n",
+                ),
+                (
+                    "meta-poky/conf/templates/default/bblayers.conf.sample",
+                    concat!(
+                        "BBPATH = \"${TOPDIR}\"\n",
+                        "BBLAYERS ?= \" \\\n",
+                        "  ##OEROOT##/meta \\\n",
+                        "  ##OEROOT##/meta-poky \\\n",
+                        "  ##OEROOT##/meta-extra/../meta-extra \\\n",
+                        "  \"\n",
+                    ),
+                ),
+                ("README", "poky"),
+            ],
+        );
+        remote_repo(
+            &remotes.path().join("meta-extra"),
+            &[("conf/layer.conf", "")],
+        );
+        remote_repo(&remotes.path().join("tools"), &[("setup.sh", "")]);
+
+        let manifest: Manifest = quick_xml::de::from_str(&format!(
+            r#"<manifest>
+  <remote name="local" fetch="file://{}"/>
+  <default remote="local" sync-j="2"/>
+  <project name="poky" path="layers/poky" revision="refs/tags/v1">
+    <copyfile src="README" dest="README.poky"/>
+    <linkfile src="meta" dest="links/meta"/>
+  </project>
+  <project name="meta-extra" path="layers/poky/meta-extra"/>
+  <project name="tools"/>
+</manifest>"#,
+            remotes.path().display()
+        ))
+        .unwrap();
+
+        let workspace = tempfile::tempdir().unwrap();
+        let sync = RepoSync::new(workspace.path());
+        let checkouts = sync.sync(&manifest).await.unwrap();
+        assert_eq!(checkouts.len(), 3);
+        assert_eq!(checkouts["poky"], workspace.path().join("layers/poky"));
+
+        let head = Repository::open(&checkouts["poky"])
+            .unwrap()
+            .head()
+            .unwrap()
+            .peel_to_commit()
+            .unwrap()
+            .id();
+        assert_eq!(head.to_string(), commit);
+        assert_eq!(
+            fs::read_to_string(workspace.path().join("README.poky")).unwrap(),
+            "poky"
+        );
+        assert_eq!(
+            fs::read_link(workspace.path().join("links/meta")).unwrap(),
+            Path::new("../layers/poky/meta")
+        );
+        assert!(
+            workspace
+                .path()
+                .join("links/meta/conf/layer.conf")
+                .is_file()
+        );
+
+        let oe_root = oe_root(&checkouts).unwrap();
+        assert_eq!(oe_root, &checkouts["poky"]);
+        let listed = template_layers(oe_root, None).unwrap();
+        assert_eq!(listed[2], workspace.path().join("layers/poky/meta-extra"));
+
+        // meta-selftest has a layer.conf but is not in the template
+        let layers = layer_paths(&checkouts, &listed).unwrap();
+        assert_eq!(
+            layers["poky"],
+            [
+                workspace.path().join("layers/poky/meta"),
+                workspace.path().join("layers/poky/meta-poky")
+            ]
+        );
+        assert_eq!(
+            layers["meta-extra"],
+            [workspace.path().join("layers/poky/meta-extra")]
+        );
+        assert!(!layers.contains_key("tools"));
+
+        let explicit = template_layers(oe_root, Some(Path::new("meta/conf/templates/default")));
+        assert!(matches!(explicit, Err(RepoError::Io(..))));
+        let outside = layer_paths(&checkouts, &[remotes.path().join("meta-extra")]);
+        assert!(matches!(outside, Err(RepoError::InvalidPath(..))));
+
+        // Syncing again fetches into the existing checkouts
+        sync.sync(&manifest).await.unwrap();
+    }
+
+    #[test]
+    fn find_layers_skips_hidden_and_nested_checkouts() {
+        let workspace = tempfile::tempdir().unwrap();
+        let root = workspace.path();
+        for layer in [
+            "meta-oe/meta-oe",
+            "meta-oe/meta-python",
+            "meta-oe/.git/conf",
+            "meta-oe/meta-nested",
+            "meta-oe/a/b/c",
+        ] {
+            let conf = root.join(layer).join("conf");
+            fs::create_dir_all(&conf).unwrap();
+            fs::write(conf.join("layer.conf"), "").unwrap();
+        }
+        let checkouts = BTreeMap::from([
+            ("meta-oe".to_string(), root.join("meta-oe")),
+            ("nested".to_string(), root.join("meta-oe/meta-nested")),
+        ]);
+
+        assert_eq!(
+            find_layers(&checkouts, "meta-oe"),
+            [
+                root.join("meta-oe/meta-oe"),
+                root.join("meta-oe/meta-python")
+            ]
+        );
+        assert_eq!(
+            find_layers(&checkouts, "nested"),
+            [root.join("meta-oe/meta-nested")]
+        );
+        assert!(find_layers(&checkouts, "missing").is_empty());
+    }
+
+    #[test]
+    fn checked_path_rejects_escapes() {
+        assert!(checked_path("a/b").is_ok());
+        assert!(checked_path("../a").is_err());
+        assert!(checked_path("/etc/passwd").is_err());
+        assert!(checked_path("").is_err());
+    }
+}
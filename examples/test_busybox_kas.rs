@@ -62,7 +62,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     if let Some(env) = &merged.env {
         println!("\nEnvironment variables:");
         for (key, value) in env {
-            println!("  {}={}", key, value);
+            println!("  {}={}", key, value.as_deref().unwrap_or("<from host>"));
         }
     }
 
@@ -1,14 +1,19 @@
 use clap::{crate_version, Parser};
-use convenient_bitbake::Bitbake;
+use convenient_bitbake::{Bitbake, BuildOrchestrator, OrchestratorConfig};
 use convenient_git::{GitRepository, RefsKind};
-use convenient_kas::KasManifest;
-use convenient_repo::find_repo_manifest;
+use convenient_kas::{
+    include_graph::{KasFile, KasIncludeGraph},
+    KasConfig, KasManifest, RepositoryManager,
+};
+use convenient_repo::{layer_paths, oe_root, parse_groups, template_layers, Manifest, RepoSync};
+use graph_git::recipes::{recipe_graph_import, LayerCommit};
 use graph_git::{
-    delete_node_and_references_to_node, delete_references_to_node, merge_link, merge_node,
     node_bitbake_manifest, node_commit, node_kas_manifest, node_message, node_person,
-    node_reference, node_repo_manifest, node_repository, node_tag, GraphDatabase,
+    node_reference, node_repo_manifest, node_repository, node_tag, GitCypher, GraphDatabase,
+    GraphDelete, GraphImport, GraphStore, Link, MemoryStore,
 };
-use neo4rs::Query;
+use std::error::Error;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tempfile::tempdir;
 use tracing::{error, info, span, warn, Level};
@@ -17,6 +22,9 @@ use tracing_subscriber::FmtSubscriber;
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Mutex;
 
+/// Layer directories by the repository they are checked out in
+type Layers = HashMap<String, Vec<PathBuf>>;
+
 struct Queue {
     queue: Mutex<VecDeque<String>>,
     dict: Mutex<HashMap<String, String>>,
@@ -106,6 +114,24 @@ struct Opts {
     #[clap(short = 'm', long, default_value = "-1")]
     max_depth: i32,
 
+    /// Import the commit history of every branch, not only its head commit
+    #[clap(long)]
+    history: bool,
+
+    /// Check out the layers of every kas and repo manifest and import their
+    /// recipes, tasks and packages at the commits the layers are at
+    #[clap(long)]
+    recipes: bool,
+
+    /// Import into an embedded graph kept in this JSON file instead of Neo4j
+    #[clap(long)]
+    store: Option<PathBuf>,
+
+    /// Export the embedded graph after the import, as GraphML for a .graphml
+    /// file and as JSON otherwise
+    #[clap(long, requires = "store")]
+    export: Option<PathBuf>,
+
     /// Print debug information
     #[clap(short)]
     debug: bool,
@@ -138,7 +164,7 @@ pub fn get_log_level(level: &str) -> Level {
     }
 }
 
-/// Adds queries to find all branches in the Git repository.
+/// Collects the nodes and links of all branches in the Git repository.
 ///
 /// # Arguments
 ///
@@ -146,15 +172,18 @@ pub fn get_log_level(level: &str) -> Level {
 ///
 /// # Returns
 ///
-/// A Vec&lt;Query&gt; containing one query per branch to find all branches in the repository.
+/// A GraphImport with the branches of the repository, and the deletes of
+/// what is no longer on them.
 async fn iterate_through_branches(
     git_repository: &GitRepository,
     queue: &Queue,
-    graph: &GraphDatabase,
-) -> Vec<Query> {
+    graph: &dyn GraphStore,
+    history: bool,
+    recipes: bool,
+) -> GraphImport {
     let span = span!(Level::INFO, "iterate", value = git_repository.git_url);
     let _enter = span.enter();
-    let mut collector = Vec::<Query>::new();
+    let mut collector = GraphImport::default();
     // Get the branches that are already in the database
     let git_url = &git_repository.git_url;
     let git_branches_in_db_initial = graph.query_branches_for_repository(git_url).await;
@@ -182,6 +211,9 @@ async fn iterate_through_branches(
 
         add_branches_to_query_on_branch(&mut collector, branch, RefsKind::Branch, git_repository);
         add_head_commit_to_query_on_branch(git_repository, branch, &mut collector, &span);
+        if history {
+            add_history_to_query_on_branch(graph, git_repository, branch, &mut collector).await;
+        }
         match git_repository.checkout(branch_name) {
             Ok(_) => {
                 find_kas_manifests_in_directory(
@@ -193,6 +225,15 @@ async fn iterate_through_branches(
                 );
                 find_bitbake_manifests_on_branch(&mut collector, git_repository, branch);
                 find_repo_manifest_on_branch(&mut collector, git_repository, branch, queue);
+                if recipes {
+                    add_recipes_of_manifests_on_branch(
+                        graph,
+                        git_repository,
+                        branch,
+                        &mut collector,
+                    )
+                    .await;
+                }
             }
             Err(e) => {
                 error!(parent: &span, "Error: {}", e);
@@ -211,11 +252,12 @@ async fn iterate_through_branches(
 async fn iterate_through_tags(
     git_repository: &GitRepository,
     queue: &Queue,
-    _graph: &GraphDatabase,
-) -> Vec<Query> {
+    graph: &dyn GraphStore,
+    recipes: bool,
+) -> GraphImport {
     let span = span!(Level::INFO, "iterate_tags", value = git_repository.git_url);
     let _enter = span.enter();
-    let mut collector = Vec::<Query>::new();
+    let mut collector = GraphImport::default();
     // Get the branches that are already in the database
     //let git_url = &git_repository.git_url;
     //let git_branches_in_db_initial = graph.query_branches_for_repository(git_url).await;
@@ -249,6 +291,10 @@ async fn iterate_through_tags(
                 find_kas_manifests_in_directory(git_repository, &span, &mut collector, refs, queue);
                 find_bitbake_manifests_on_branch(&mut collector, git_repository, refs);
                 find_repo_manifest_on_branch(&mut collector, git_repository, refs, queue);
+                if recipes {
+                    add_recipes_of_manifests_on_branch(graph, git_repository, refs, &mut collector)
+                        .await;
+                }
             }
             Err(e) => {
                 error!(parent: &span, "Error: {}", e);
@@ -265,11 +311,11 @@ async fn iterate_through_tags(
 }
 
 async fn find_and_remove_reference_between_commit_and_reference(
-    graph: &GraphDatabase,
+    graph: &dyn GraphStore,
     branch_name: &str,
     git_url: &String,
     branch: &convenient_git::GitRemoteHead,
-    collector: &mut Vec<Query>,
+    collector: &mut GraphImport,
 ) {
     // Find the commits that are already in the database
     let git_commits_in_db_initial = graph
@@ -288,7 +334,7 @@ async fn find_and_remove_reference_between_commit_and_reference(
 }
 
 fn remove_reference_to_commits_from_database(
-    collector: &mut Vec<Query>,
+    collector: &mut GraphImport,
     to_remove: HashSet<String>,
     to_remove_initial: HashSet<String>,
     repository_url: &String,
@@ -305,13 +351,17 @@ fn remove_reference_to_commits_from_database(
 
     for commit in to_remove.iter() {
         let commit_oid = commit.clone();
-        collector.push(delete_references_to_node(node_commit(&commit_oid)));
+        collector.delete(GraphDelete::Link(
+            node_commit(&commit_oid),
+            node_reference(branch_name, repository_url),
+            Link::LinksTo,
+        ));
         info!(parent: &span, "Removed reference between commit {} from branch {} of database", commit_oid, branch_name);
     }
 }
 
 fn remove_branches_from_database(
-    collector: &mut Vec<Query>,
+    collector: &mut GraphImport,
     branches_to_remove: HashSet<String>,
     branches_to_remove_initial: HashSet<String>,
     repository_url: &String,
@@ -327,7 +377,7 @@ fn remove_branches_from_database(
 
     for branch in branches_to_remove.iter() {
         let branch_name = branch.clone();
-        collector.push(delete_node_and_references_to_node(node_reference(
+        collector.delete(GraphDelete::Node(node_reference(
             &branch_name,
             repository_url,
         )));
@@ -335,42 +385,38 @@ fn remove_branches_from_database(
     }
 }
 
-fn add_git_repository(collector: &mut Vec<Query>, git_repository: &GitRepository) {
+fn add_git_repository(collector: &mut GraphImport, git_repository: &GitRepository) {
     let git_url: &String = &git_repository.git_url;
 
-    collector.push(merge_node(node_repository(git_url)));
+    collector.node(node_repository(git_url));
 }
 
 fn add_branches_to_query_on_branch(
-    collector: &mut Vec<Query>,
+    collector: &mut GraphImport,
     refs: &convenient_git::GitRemoteHead,
     refs_kind: RefsKind,
     git_repository: &GitRepository,
 ) {
-    collector.push(merge_node(node_commit(refs.oid.as_str())));
+    collector.node(node_commit(refs.oid.as_str()));
 
     let node_refs = match refs_kind {
         RefsKind::Branch => node_reference(refs.name.as_str(), &git_repository.git_url),
         RefsKind::Tag => node_tag(refs.name.as_str(), &git_repository.git_url),
     };
-    collector.push(merge_node(node_refs.clone()));
+    collector.node(node_refs.clone());
 
-    collector.push(merge_link(
+    collector.link(
         node_repository(&git_repository.git_url),
         node_refs.clone(),
-        "has".to_string(),
-    ));
-    collector.push(merge_link(
-        node_commit(refs.oid.as_str()),
-        node_refs,
-        "links_to".to_string(),
-    ));
+        Link::Has,
+    );
+    collector.link(node_commit(refs.oid.as_str()), node_refs, Link::LinksTo);
 }
 
 fn add_head_commit_to_query_on_branch(
     git_repository: &GitRepository,
     branch: &convenient_git::GitRemoteHead,
-    collector: &mut Vec<Query>,
+    collector: &mut GraphImport,
     span: &tracing::Span,
 ) {
     let branch_name = branch.name.as_str();
@@ -379,22 +425,19 @@ fn add_head_commit_to_query_on_branch(
     let commit = git_repository.find_reference(branch_name);
     match commit {
         Some(commit) => {
-            collector.push(merge_node(node_person(
-                commit.name.as_str(),
-                commit.email.as_str(),
-            )));
-            collector.push(merge_node(node_message(commit.message.as_str())));
-            collector.push(merge_link(
+            collector.node(node_person(commit.name.as_str(), commit.email.as_str()));
+            collector.node(node_message(commit.message.as_str()));
+            collector.link(
                 node_commit(branch.oid.as_str()),
                 node_person(commit.name.as_str(), commit.email.as_str()),
-                "authored_by".to_string(),
-            ));
+                Link::AuthoredBy,
+            );
 
-            collector.push(merge_link(
+            collector.link(
                 node_commit(branch.oid.as_str()),
                 node_message(commit.message.as_str()),
-                "has_message".to_string(),
-            ));
+                Link::HasMessage,
+            );
         }
         None => {
             error!(parent: span, "Error: {}", branch_name);
@@ -402,10 +445,53 @@ fn add_head_commit_to_query_on_branch(
     }
 }
 
+/// Adds the commits of a branch that are not in the database yet, with their
+/// authors, messages and parents.
+async fn add_history_to_query_on_branch(
+    graph: &dyn GraphStore,
+    git_repository: &GitRepository,
+    branch: &convenient_git::GitRemoteHead,
+    collector: &mut GraphImport,
+) {
+    let branch_name = branch.name.as_str();
+    // The history of a head imported before is already in the database
+    let known: Vec<String> = graph
+        .query_history_heads_for_branch(node_reference(branch_name, &git_repository.git_url))
+        .await
+        .into_iter()
+        .collect();
+
+    let mut import = GraphImport::default();
+    for commit in git_repository.history(branch_name, &known) {
+        let person = node_person(commit.name.as_str(), commit.email.as_str());
+        let message = node_message(commit.message.as_str());
+        import.node(node_commit(commit.oid.as_str()));
+        import.node(person.clone());
+        import.node(message.clone());
+        import.link(node_commit(commit.oid.as_str()), person, Link::AuthoredBy);
+        import.link(node_commit(commit.oid.as_str()), message, Link::HasMessage);
+        for parent in &commit.parents {
+            import.node(node_commit(parent));
+            import.link(
+                node_commit(commit.oid.as_str()),
+                node_commit(parent),
+                Link::Parent,
+            );
+        }
+    }
+    info!(
+        "Importing {} nodes and {} links of the history of {}",
+        import.nodes.len(),
+        import.links.len(),
+        branch_name
+    );
+    collector.extend(import);
+}
+
 fn find_kas_manifests_in_directory(
     git_repository: &GitRepository,
     parent_span: &tracing::Span,
-    collector: &mut Vec<Query>,
+    collector: &mut GraphImport,
     branch: &convenient_git::GitRemoteHead,
     queue: &Queue,
 ) {
@@ -418,15 +504,12 @@ fn find_kas_manifests_in_directory(
         );
         info!("Found {} kas manifest(s)", kas_manifests.len());
         for kas in kas_manifests {
-            collector.push(merge_node(node_kas_manifest(
-                kas.path.as_str(),
-                branch.oid.as_str(),
-            )));
-            collector.push(merge_link(
+            collector.node(node_kas_manifest(kas.path.as_str(), branch.oid.as_str()));
+            collector.link(
                 node_commit(branch.oid.as_str()),
                 node_kas_manifest(kas.path.as_str(), branch.oid.as_str()),
-                "contains".to_string(),
-            ));
+                Link::Contains,
+            );
             for (kas_repository_name, kas_repository) in kas.manifest.repos {
                 let mut git_repo: String = String::new();
                 // if no repo was given. Assume the current repo
@@ -436,7 +519,7 @@ fn find_kas_manifests_in_directory(
                         match repository.url {
                             Some(url) => {
                                 queue.add(url.clone());
-                                collector.push(merge_node(node_repository(url.as_str())));
+                                collector.node(node_repository(url.as_str()));
                                 git_repo.replace_range(.., url.as_str());
                                 info!(
                                     "Found kas {} repository {}",
@@ -450,18 +533,17 @@ fn find_kas_manifests_in_directory(
                         };
                         match repository.refspec {
                             Some(refspec) => {
-                                collector
-                                    .push(merge_node(node_reference(refspec.as_str(), &git_repo)));
-                                collector.push(merge_link(
+                                collector.node(node_reference(refspec.as_str(), &git_repo));
+                                collector.link(
                                     node_repository(&git_repo),
                                     node_reference(refspec.as_str(), &git_repo),
-                                    "has".to_string(),
-                                ));
-                                collector.push(merge_link(
+                                    Link::Has,
+                                );
+                                collector.link(
                                     node_kas_manifest(kas.path.as_str(), branch.oid.as_str()),
                                     node_reference(refspec.as_str(), &git_repo),
-                                    "refers".to_string(),
-                                ));
+                                    Link::Refers,
+                                );
                                 info!(
                                     "Found kas {} refspec {}",
                                     kas_repository_name,
@@ -487,7 +569,7 @@ fn find_kas_manifests_in_directory(
 }
 
 fn find_bitbake_manifests_on_branch(
-    collector: &mut Vec<Query>,
+    collector: &mut GraphImport,
     git_repository: &GitRepository,
     branch: &convenient_git::GitRemoteHead,
 ) {
@@ -496,95 +578,293 @@ fn find_bitbake_manifests_on_branch(
     for manifest in bitbake_manifests {
         let path = manifest.path.as_str();
         let src_uris = manifest.src_uris;
-        collector.push(merge_node(node_bitbake_manifest(path, branch.oid.as_str())));
-        collector.push(merge_link(
+        collector.node(node_bitbake_manifest(path, branch.oid.as_str()));
+        collector.link(
             node_commit(branch.oid.as_str()),
             node_bitbake_manifest(path, branch.oid.as_str()),
-            "contains".to_string(),
-        ));
+            Link::Contains,
+        );
         for git_url in src_uris {
-            collector.push(merge_node(node_repository(git_url.as_str())));
+            collector.node(node_repository(git_url.as_str()));
 
-            collector.push(merge_link(
+            collector.link(
                 node_bitbake_manifest(path, branch.oid.as_str()),
                 node_repository(git_url.as_str()),
-                "refers".to_string(),
-            ));
+                Link::Refers,
+            );
         }
     }
 }
 
 fn find_repo_manifest_on_branch(
-    collector: &mut Vec<Query>,
+    collector: &mut GraphImport,
     git_repository: &GitRepository,
     branch: &convenient_git::GitRemoteHead,
     queue: &Queue,
 ) {
     let manifest_git_url = git_repository.git_url.clone();
-    let manifests = find_repo_manifest(git_repository.repo.as_ref().unwrap().workdir().unwrap());
-    for manifest in manifests {
-        let path = "TODO.xml";
-        collector.push(merge_node(node_repo_manifest(path, branch.oid.as_str())));
-        collector.push(merge_link(
+    let manifests =
+        repo_manifests_in_directory(git_repository.repo.as_ref().unwrap().workdir().unwrap());
+    for (path, manifest) in manifests {
+        let path = path.as_str();
+        collector.node(node_repo_manifest(path, branch.oid.as_str()));
+        collector.link(
             node_commit(branch.oid.as_str()),
             node_repo_manifest(path, branch.oid.as_str()),
-            "contains".to_string(),
-        ));
+            Link::Contains,
+        );
         for project in manifest.iter() {
             let git_url = project.git_url(manifest_git_url.clone());
             queue.add(git_url.clone());
             let dest_branch = project.dest_branch();
-            collector.push(merge_node(node_repository(git_url.as_str())));
+            collector.node(node_repository(git_url.as_str()));
             if project.is_dest_branch_a_tag() && !project.is_dest_branch_a_commit() {
-                collector.push(merge_node(node_tag(&dest_branch, &git_url)));
-                collector.push(merge_link(
+                collector.node(node_tag(&dest_branch, &git_url));
+                collector.link(
                     node_repository(&git_url),
                     node_tag(&dest_branch, &git_url),
-                    "has".to_string(),
-                ));
-                collector.push(merge_link(
+                    Link::Has,
+                );
+                collector.link(
                     node_repo_manifest(path, branch.oid.as_str()),
                     node_tag(&dest_branch, &git_url),
-                    "refers".to_string(),
-                ));
+                    Link::Refers,
+                );
             } else if !project.is_dest_branch_a_commit() {
-                collector.push(merge_node(node_reference(&dest_branch, &git_url)));
-                collector.push(merge_link(
+                collector.node(node_reference(&dest_branch, &git_url));
+                collector.link(
                     node_repository(&git_url),
                     node_reference(&dest_branch, &git_url),
-                    "has".to_string(),
-                ));
-                collector.push(merge_link(
+                    Link::Has,
+                );
+                collector.link(
                     node_repo_manifest(path, branch.oid.as_str()),
                     node_reference(&dest_branch, &git_url),
-                    "refers".to_string(),
-                ));
+                    Link::Refers,
+                );
             } else {
-                collector.push(merge_node(node_commit(&dest_branch)));
-                collector.push(merge_link(
+                collector.node(node_commit(&dest_branch));
+                collector.link(
                     node_repo_manifest(path, branch.oid.as_str()),
                     node_commit(&dest_branch),
-                    "refers".to_string(),
-                ));
+                    Link::Refers,
+                );
+            }
+        }
+    }
+}
+
+/// Repo manifests in the top directory of a checkout, by file name
+fn repo_manifests_in_directory(dir: &Path) -> Vec<(String, Manifest)> {
+    let Ok(entries) = dir.read_dir() else {
+        return Vec::new();
+    };
+    let mut manifests: Vec<(String, Manifest)> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "xml"))
+        .filter_map(|path| match Manifest::parse(&path) {
+            Ok(manifest) => {
+                info!("Found repo manifest: {}", path.display());
+                Some((path.file_name()?.to_string_lossy().into_owned(), manifest))
+            }
+            Err(e) => {
+                warn!("Failed to parse {}: {}", path.display(), e);
+                None
             }
+        })
+        .collect();
+    manifests.sort_by(|a, b| a.0.cmp(&b.0));
+    manifests
+}
+
+/// Kas files in the top directory of a checkout, by file name, leaving out lock files
+async fn kas_files_in_directory(dir: &Path) -> Vec<String> {
+    let mut names = Vec::new();
+    let Ok(entries) = dir.read_dir() else {
+        return names;
+    };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        let Some(name) = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+        else {
+            continue;
+        };
+        if (name.ends_with(".yml") || name.ends_with(".yaml"))
+            && !name.ends_with(".lock.yml")
+            && KasFile::load(&path).await.is_ok()
+        {
+            names.push(name);
         }
     }
+    names.sort();
+    names
+}
+
+/// Adds the layers and recipes of the kas and repo manifests on a branch.
+///
+/// The repositories of each manifest are checked out into a temporary
+/// directory at the revisions it names, and the recipes of its layers are
+/// parsed into a recipe graph. Layer commits already in the database only get
+/// the links that depend on the combination of layers, see
+/// [`recipe_graph_import`].
+async fn add_recipes_of_manifests_on_branch(
+    graph: &dyn GraphStore,
+    git_repository: &GitRepository,
+    branch: &convenient_git::GitRemoteHead,
+    collector: &mut GraphImport,
+) {
+    let workdir = git_repository.repo.as_ref().unwrap().workdir().unwrap();
+    let imported = graph.query_imported_layers().await;
+
+    for path in kas_files_in_directory(workdir).await {
+        let manifest = node_kas_manifest(path.as_str(), branch.oid.as_str());
+        let checkouts = tempdir().unwrap();
+        let import = async {
+            let (config, layers) =
+                kas_layers(&workdir.join(&path), workdir, checkouts.path()).await?;
+            import_recipes(
+                &manifest,
+                config.machine,
+                config.distro,
+                layers,
+                checkouts.path(),
+                &imported,
+            )
+            .await
+        }
+        .await;
+        match import {
+            Ok(import) => collector.extend(import),
+            Err(e) => warn!("Recipes of kas manifest {} are not imported: {}", path, e),
+        }
+    }
+
+    for (path, _) in repo_manifests_in_directory(workdir) {
+        let manifest = node_repo_manifest(path.as_str(), branch.oid.as_str());
+        let checkouts = tempdir().unwrap();
+        let import = async {
+            let layers = repo_layers(
+                &workdir.join(&path),
+                &git_repository.git_url,
+                checkouts.path(),
+            )
+            .await?;
+            import_recipes(&manifest, None, None, layers, checkouts.path(), &imported).await
+        }
+        .await;
+        match import {
+            Ok(import) => collector.extend(import),
+            Err(e) => warn!("Recipes of repo manifest {} are not imported: {}", path, e),
+        }
+    }
+}
+
+/// Checks out the repositories of a kas file, returning its merged
+/// configuration and the layers of each repository.
+///
+/// Repositories without url are the one holding the kas file, `config_dir`.
+async fn kas_layers(
+    kas_file: &Path,
+    config_dir: &Path,
+    checkouts: &Path,
+) -> Result<(KasConfig, Layers), Box<dyn Error + Send + Sync>> {
+    let manager = RepositoryManager::new(checkouts.join("repos")).with_config_dir(config_dir);
+    let mut include_graph = KasIncludeGraph::build(kas_file).await?;
+    let mut config = include_graph.merge_config();
+    let mut repo_paths = manager.setup_repositories(&config).await?;
+
+    // Includes from repositories need their checkout, which may add repositories
+    while !include_graph.missing_repos().is_empty() {
+        if let Some(repo) = include_graph
+            .missing_repos()
+            .iter()
+            .find(|repo| !repo_paths.contains_key(*repo))
+        {
+            return Err(format!("include from undefined repository {}", repo).into());
+        }
+        include_graph = KasIncludeGraph::build_with_repos(kas_file, &repo_paths).await?;
+        config = include_graph.merge_config();
+        repo_paths = manager.setup_repositories(&config).await?;
+    }
+
+    let mut layers = Layers::new();
+    for (name, repo) in config.effective_repos() {
+        if let Some(dir) = repo_paths.get(&name) {
+            layers.insert(name, manager.get_layer_paths(dir, &repo)?);
+        }
+    }
+    Ok((config, layers))
+}
+
+/// Syncs the default projects of a repo manifest, returning the layers its
+/// `bblayers.conf.sample` template lists.
+async fn repo_layers(
+    manifest_file: &Path,
+    manifest_url: &str,
+    checkouts: &Path,
+) -> Result<Layers, Box<dyn Error + Send + Sync>> {
+    let mut manifest = Manifest::load(manifest_file)?;
+    manifest.retain_groups(&parse_groups("default"));
+    let projects = RepoSync::new(checkouts)
+        .with_manifest_url(manifest_url)
+        .sync(&manifest)
+        .await?;
+    let root = oe_root(&projects).ok_or("no project has oe-init-build-env")?;
+    let listed = template_layers(root, None)?;
+    Ok(layer_paths(&projects, &listed)?)
+}
+
+/// Parses the recipes of the layers and collects their import, each layer at
+/// the commit it is checked out at.
+async fn import_recipes(
+    manifest: &GitCypher,
+    machine: Option<String>,
+    distro: Option<String>,
+    layers: Layers,
+    checkouts: &Path,
+    imported: &HashSet<(String, String)>,
+) -> Result<GraphImport, Box<dyn Error + Send + Sync>> {
+    let orchestrator = BuildOrchestrator::new(OrchestratorConfig {
+        build_dir: checkouts.join("build"),
+        machine,
+        distro,
+        max_io_parallelism: 32,
+        max_cpu_parallelism: std::thread::available_parallelism().map_or(4, |n| n.get()),
+    });
+    let plan = orchestrator.build_plan(layers).await?;
+    let layers: Vec<LayerCommit> = plan
+        .build_context
+        .layers
+        .iter()
+        .filter_map(|layer| LayerCommit::from_checkout(&layer.collection, &layer.layer_dir))
+        .collect();
+    info!(
+        "Importing {} recipes of {} layers",
+        plan.recipe_graph.recipe_count(),
+        layers.len()
+    );
+    Ok(recipe_graph_import(
+        &plan.recipe_graph,
+        &layers,
+        Some(manifest),
+        imported,
+    ))
 }
 
 async fn application(
-    uri: String,
-    user: String,
-    password: String,
-    db: String,
+    graph: &dyn GraphStore,
     git_url: String,
     git_user: String,
     git_password: String,
     max_depth: i32,
+    history: bool,
+    recipes: bool,
 ) {
     let queue = Arc::new(Queue::new());
 
     queue.add(git_url.clone());
-    let graph: GraphDatabase = GraphDatabase::new(uri, user, password, db).await;
 
     let mut current_depth: i32 = 0;
     let mut iteration_queue = Arc::new(Queue::new());
@@ -592,7 +872,7 @@ async fn application(
     while let Some(git_url) = queue.take() {
         if max_depth < 0 || current_depth <= max_depth {
             warn!("Depth: {}/{}", current_depth, max_depth);
-            let mut collector = Vec::<Query>::new();
+            let mut collector = GraphImport::default();
             warn!("Preparing: {}", git_url);
             let tmp_dir = tempdir().unwrap();
             let file_path = tmp_dir.path().join("repo");
@@ -606,13 +886,21 @@ async fn application(
             git_repository.update_from_remote();
             git_repository.map_remote_branches_local();
 
-            collector.append(
-                &mut iterate_through_branches(&git_repository, &iteration_queue, &graph).await,
+            collector.extend(
+                iterate_through_branches(
+                    &git_repository,
+                    &iteration_queue,
+                    graph,
+                    history,
+                    recipes,
+                )
+                .await,
+            );
+            collector.extend(
+                iterate_through_tags(&git_repository, &iteration_queue, graph, recipes).await,
             );
-            collector
-                .append(&mut iterate_through_tags(&git_repository, &iteration_queue, &graph).await);
             tmp_dir.close().unwrap();
-            graph.txn_run_queries(collector).await.unwrap();
+            graph.run(collector).await.unwrap();
         }
         if queue.is_empty() {
             while let Some(git_url) = iteration_queue.take() {
@@ -645,24 +933,50 @@ async fn main() {
         .finish();
 
     tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
-    application(
-        opts.uri,
-        opts.user,
-        opts.password,
-        opts.db,
-        opts.git_url,
-        opts.git_user,
-        opts.git_password,
-        opts.max_depth,
-    )
-    .await;
+    match opts.store {
+        Some(path) => {
+            let store = MemoryStore::open(&path).unwrap();
+            application(
+                &store,
+                opts.git_url,
+                opts.git_user,
+                opts.git_password,
+                opts.max_depth,
+                opts.history,
+                opts.recipes,
+            )
+            .await;
+            warn!(
+                "Stored {} nodes and {} links in {}",
+                store.node_count(),
+                store.link_count(),
+                path.display()
+            );
+            if let Some(export) = opts.export {
+                store.export(&export).unwrap();
+                warn!("Exported the graph to {}", export.display());
+            }
+        }
+        None => {
+            let graph = GraphDatabase::new(opts.uri, opts.user, opts.password, opts.db).await;
+            application(
+                &graph,
+                opts.git_url,
+                opts.git_user,
+                opts.git_password,
+                opts.max_depth,
+                opts.history,
+                opts.recipes,
+            )
+            .await;
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use convenient_git::GitRemoteHead;
     use neo4j_testcontainers::Neo4j;
-    use std::path::PathBuf;
     use testcontainers::clients::Cli;
     use tracing_test::traced_test;
     use url::Url;
@@ -686,7 +1000,7 @@ mod tests {
 
     #[test]
     fn add_git_repository_test() {
-        let mut queries = Vec::new();
+        let mut queries = GraphImport::default();
         let test_repo = GitRepository {
             repo: None,
             git_url: "foo:bar:foo".to_string(),
@@ -703,7 +1017,7 @@ mod tests {
         let binding = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         let d = binding.parent().unwrap();
 
-        let mut collector = Vec::<Query>::new();
+        let mut collector = GraphImport::default();
 
         let span = tracing::info_span!("add_head_commit_to_query_on_branch");
         let test_repo = GitRepository {
@@ -727,7 +1041,7 @@ mod tests {
         let d = binding.parent().unwrap();
         let queue = Arc::new(Queue::new());
 
-        let mut collector = Vec::<Query>::new();
+        let mut collector = GraphImport::default();
 
         let span = tracing::info_span!("find_kas_manifests_in_directory");
         let test_repo = GitRepository {
@@ -744,6 +1058,147 @@ mod tests {
         assert_eq!(collector.len(), 0);
     }
 
+    #[traced_test]
+    #[tokio::test]
+    async fn test_app_embedded_store() {
+        let upstream_dir = tempdir().unwrap();
+        let upstream = git2::Repository::init(upstream_dir.path()).unwrap();
+        let signature = git2::Signature::now("name", "name@example.com").unwrap();
+        let tree_oid = upstream.index().unwrap().write_tree().unwrap();
+        let tree = upstream.find_tree(tree_oid).unwrap();
+        let first = upstream
+            .commit(None, &signature, &signature, "first", &tree, &[])
+            .unwrap();
+        let first = upstream.find_commit(first).unwrap();
+        let head = upstream
+            .commit(
+                Some("refs/heads/main"),
+                &signature,
+                &signature,
+                "second",
+                &tree,
+                &[&first],
+            )
+            .unwrap();
+        upstream.set_head("refs/heads/main").unwrap();
+        let git_url = Url::from_file_path(upstream_dir.path())
+            .unwrap()
+            .to_string();
+
+        let store_dir = tempdir().unwrap();
+        let store_path = store_dir.path().join("graph.json");
+        let store = MemoryStore::open(&store_path).unwrap();
+        // A second import finds everything in the store already
+        for _ in 0..2 {
+            application(
+                &store,
+                git_url.clone(),
+                "git".to_string(),
+                "git".to_string(),
+                0,
+                true,
+                false,
+            )
+            .await;
+        }
+
+        assert_eq!(
+            store.branches_for_repository(&git_url),
+            HashSet::from(["main".to_string()])
+        );
+        assert_eq!(
+            store.commits_for_branch(&node_reference("main", &git_url)),
+            HashSet::from([head.to_string()])
+        );
+        // Repository, reference, two commits, their person and messages
+        assert_eq!(store.node_count(), 7);
+        assert_eq!(store.link_count(), 7);
+        assert_eq!(MemoryStore::open(&store_path).unwrap().link_count(), 7);
+    }
+
+    /// A repository with one commit on main holding `files`
+    fn upstream_repo(dir: &Path, files: &[(&str, &str)]) -> git2::Oid {
+        let repo = git2::Repository::init(dir).unwrap();
+        for (name, contents) in files {
+            let path = dir.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        let mut index = repo.index().unwrap();
+        index
+            .add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+            .unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("name", "name@example.com").unwrap();
+        let oid = repo
+            .commit(
+                Some("refs/heads/main"),
+                &signature,
+                &signature,
+                "init",
+                &tree,
+                &[],
+            )
+            .unwrap();
+        repo.set_head("refs/heads/main").unwrap();
+        oid
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_app_imports_recipes_of_kas_layers() {
+        let poky_dir = tempdir().unwrap();
+        let poky = upstream_repo(
+            poky_dir.path(),
+            &[
+                (
+                    "meta/conf/layer.conf",
+                    "BBPATH .= \":${LAYERDIR}\"\nBBFILES += \"${LAYERDIR}/recipes-*/*/*.bb\"\nBBFILE_COLLECTIONS += \"core\"\nBBFILE_PATTERN_core = \"^${LAYERDIR}/\"\n",
+                ),
+                (
+                    "meta/recipes-core/hello/hello_1.0.bb",
+                    "LICENSE = \"MIT\"\n",
+                ),
+            ],
+        );
+
+        let config_dir = tempdir().unwrap();
+        upstream_repo(
+            config_dir.path(),
+            &[(
+                "kas.yml",
+                &format!(
+                    "header:\n  version: 14\nrepos:\n  poky:\n    url: {}\n    commit: {}\n    layers:\n      meta:\n",
+                    poky_dir.path().display(),
+                    poky
+                ),
+            )],
+        );
+        let git_url = Url::from_file_path(config_dir.path()).unwrap().to_string();
+
+        let store_dir = tempdir().unwrap();
+        let store = MemoryStore::open(store_dir.path().join("graph.json")).unwrap();
+        application(
+            &store,
+            git_url,
+            "git".to_string(),
+            "git".to_string(),
+            0,
+            false,
+            true,
+        )
+        .await;
+
+        assert_eq!(
+            store.imported_layers(),
+            HashSet::from([("core".to_string(), poky.to_string())])
+        );
+        assert_eq!(
+            store.recipes_for_layer(&graph_git::recipes::node_layer("core", &poky.to_string())),
+            HashSet::from(["hello".to_string()])
+        );
+    }
+
     #[traced_test]
     #[tokio::test]
     async fn test_app() {
@@ -766,6 +1221,6 @@ mod tests {
         //let graph: GraphDatabase = GraphDatabase::new(uri, auth_user, auth_pass, db).await;
         //let foo = graph.query_branches_for_repository("git_uri").await;
         //assert!(foo.len() == 0);
-        //application(uri, auth_user, auth_pass, db, git_url).await;
+        //application(&graph, git_url, ...).await;
     }
 }
@@ -1,30 +1,42 @@
-use std::{collections::HashSet, error::Error, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    error::Error,
+    sync::Arc,
+};
 
 use neo4rs::{query, ConfigBuilder, Graph, Node, Query};
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
+
+pub mod memory;
+pub mod recipes;
+pub mod schema;
+pub mod store;
+
+pub use memory::MemoryStore;
+pub use schema::{Link, NodeKind};
+pub use store::GraphStore;
+
+/// Number of rows merged by one `UNWIND` query
+pub const BATCH_SIZE: usize = 1000;
 
 /// Creates a Cypher node representation for a Git reference.
 ///
 /// # Arguments
 ///
 /// * `name` - The name of the reference
-///
+/// * `uri` - The URI of the repository
 pub fn node_reference(name: &str, uri: &str) -> GitCypher {
-    GitCypher {
-        var: "reference".to_owned(),
-        cypher: format!(
-            "(reference:Reference {{name: \'{}\', uri: '{}'}})",
-            name.replace('\\', "\\\\").replace('\'', "\\\'"),
-            uri.replace('\\', "\\\\").replace('\'', "\\\'")
-        ),
-    }
+    GitCypher::new("reference", NodeKind::Reference, [name, uri])
 }
 
+/// Creates a Cypher node representation for a Git tag.
+///
+/// # Arguments
+///
+/// * `name` - The name of the tag
+/// * `uri` - The URI of the repository
 pub fn node_tag(name: &str, uri: &str) -> GitCypher {
-    GitCypher {
-        var: "tag".to_owned(),
-        cypher: format!("(tag:Tag {{name: \'{}\', uri: '{}'}})", name, uri),
-    }
+    GitCypher::new("tag", NodeKind::Tag, [name, uri])
 }
 
 /// Creates a Cypher node representation for a Git commit.
@@ -33,10 +45,7 @@ pub fn node_tag(name: &str, uri: &str) -> GitCypher {
 ///
 /// * `oid` - The object ID of the commit
 pub fn node_commit(oid: &str) -> GitCypher {
-    GitCypher {
-        var: "commit".to_owned(),
-        cypher: format!("(commit:Commit {{oid: \'{}\'}})", oid),
-    }
+    GitCypher::new("commit", NodeKind::Commit, [oid])
 }
 
 /// Creates a Cypher node representation for a Git repository.
@@ -45,96 +54,106 @@ pub fn node_commit(oid: &str) -> GitCypher {
 ///
 /// * `uri` - The URI of the repository
 pub fn node_repository(uri: &str) -> GitCypher {
-    GitCypher {
-        var: "repository".to_owned(),
-        cypher: format!(
-            "(repository:Repository {{uri: '{}'}})",
-            uri.replace('\\', "\\\\").replace('\'', "\\\'")
-        ),
-    }
+    GitCypher::new("repository", NodeKind::Repository, [uri])
 }
 
 pub fn node_kas_manifest(path: &str, oid: &str) -> GitCypher {
-    GitCypher {
-        var: "repository".to_owned(),
-        cypher: format!(
-            "(repository:Manifest {{path: '{}', oid: \'{}\', type: 'kas'}})",
-            path, oid
-        ),
-    }
+    GitCypher::new("manifest", NodeKind::Manifest, [path, oid, "kas"])
 }
 
 pub fn node_repo_manifest(path: &str, oid: &str) -> GitCypher {
-    GitCypher {
-        var: "repository".to_owned(),
-        cypher: format!(
-            "(repository:Manifest {{path: '{}', oid: \'{}\', type: 'repo'}})",
-            path, oid
-        ),
-    }
+    GitCypher::new("manifest", NodeKind::Manifest, [path, oid, "repo"])
 }
 
 pub fn node_bitbake_manifest(path: &str, oid: &str) -> GitCypher {
-    GitCypher {
-        var: "bitbake".to_owned(),
-        cypher: format!(
-            "(bitbake:Manifest {{path: '{}', oid: \'{}\', type: 'bitbake'}})",
-            path, oid
-        ),
-    }
+    GitCypher::new("manifest", NodeKind::Manifest, [path, oid, "bitbake"])
 }
+
 /// Creates a Cypher node representation for a Git person.
 ///
 /// # Arguments
 ///
 /// * `name` - The name of the person
+/// * `email` - The email of the person
 pub fn node_person(name: &str, email: &str) -> GitCypher {
-    let name = name.replace('\'', "\\\'");
-    let email = email.replace('\'', "\\\'");
-    GitCypher {
-        var: "person".to_owned(),
-        cypher: format!(
-            "(person:Person {{name: \'{}\', email: \'{}\'}})",
-            name.replace('\\', "\\\\").replace('\'', "\\\'"),
-            email.replace('\\', "\\\\").replace('\'', "\\\'")
-        ),
-    }
-}
-
-#[derive(Debug, Clone)]
-pub struct GitCypher {
-    pub var: String,
-    pub cypher: String,
+    GitCypher::new("person", NodeKind::Person, [name, email])
 }
 
-impl GitCypher {
-    pub fn query(&self) -> Query {
-        query(self.cypher.as_str())
-    }
-}
 /// Creates a Cypher node representation for a Git message.
 ///
 /// # Arguments
 ///
 /// * `message` - The message of the commit
 pub fn node_message(message: &str) -> GitCypher {
-    GitCypher {
-        var: "message".to_owned(),
-        cypher: format!(
-            "(message:Message {{message: \'{}\'}})",
-            message.replace('\\', "\\\\").replace('\'', "\\\'")
-        ),
-    }
+    GitCypher::new("message", NodeKind::Message, [message])
 }
 
-// MATCH (commit:Commit {oid: 'ec41323d7acd2eda47e2f6d5c9aa59e34434c06d'})
-// MATCH (message:Message {message:
-//  'am 6aa21b8c: (-s ours) am f8bd3707: Don\\'t track binaries for newer devices
-//
-//* commit \'6aa21b8cf337c20427b14dd842020a0d1e10d75c\':
-//  Don\'t track binaries for newer devices
-//'})
-// MERGE (commit)-[:has_message]->(message)
+/// A node of the graph, identified by the key properties of its kind
+///
+/// The values never end up in the Cypher text: patterns refer to parameters
+/// named after the variable and the property, e.g. `$commit_oid`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitCypher {
+    pub var: String,
+    pub kind: NodeKind,
+    /// Values of the key properties, in the order of [`NodeKind::key`]
+    pub values: Vec<String>,
+}
+
+impl GitCypher {
+    pub fn new<const N: usize>(var: &str, kind: NodeKind, values: [&str; N]) -> GitCypher {
+        debug_assert_eq!(N, kind.key().len(), "key of {}", kind.label());
+        GitCypher {
+            var: var.to_owned(),
+            kind,
+            values: values.iter().map(|v| v.to_string()).collect(),
+        }
+    }
+
+    /// Pattern of the node with its properties bound to parameters,
+    /// e.g. `(commit:Commit {oid: $commit_oid})`
+    pub fn pattern(&self) -> String {
+        self.pattern_from(&format!("${}", self.var))
+    }
+
+    /// Parameters of [`GitCypher::pattern`]
+    pub fn params(&self) -> Vec<(String, String)> {
+        self.kind
+            .key()
+            .iter()
+            .zip(&self.values)
+            .map(|(property, value)| (format!("{}_{}", self.var, property), value.clone()))
+            .collect()
+    }
+
+    /// The same node under another variable, to link two nodes of one label
+    pub fn with_var(&self, var: &str) -> GitCypher {
+        GitCypher {
+            var: var.to_owned(),
+            ..self.clone()
+        }
+    }
+
+    /// Pattern taking the properties from `{prefix}_{property}`
+    fn pattern_from(&self, prefix: &str) -> String {
+        let properties: Vec<String> = self
+            .kind
+            .key()
+            .iter()
+            .map(|property| format!("{}: {}_{}", property, prefix, property))
+            .collect();
+        format!(
+            "({}:{} {{{}}})",
+            self.var,
+            self.kind.label(),
+            properties.join(", ")
+        )
+    }
+
+    fn bind(&self, query: Query) -> Query {
+        query.params(self.params())
+    }
+}
 
 /// Creates a Cypher query to merge a node.
 ///
@@ -142,9 +161,9 @@ pub fn node_message(message: &str) -> GitCypher {
 ///
 /// * `node` - The node to merge
 pub fn merge_node(node: GitCypher) -> Query {
-    let q = format!("MERGE {}", node.cypher);
+    let q = format!("MERGE {}", node.pattern());
     debug!("{}", q);
-    query(q.as_str())
+    node.bind(query(q.as_str()))
 }
 
 /// Creates a Cypher query to merge a link.
@@ -154,46 +173,219 @@ pub fn merge_node(node: GitCypher) -> Query {
 /// * `from` - The source node
 /// * `to` - The target node
 /// * `link` - The link between the nodes
-pub fn merge_link(from: GitCypher, to: GitCypher, link: String) -> Query {
-    let q = format!(
+pub fn merge_link(from: GitCypher, to: GitCypher, link: Link) -> Query {
+    debug_assert!(link.connects(from.kind, to.kind), "{:?}", link);
+    let (from, to) = (from.with_var("from"), to.with_var("to"));
+    let q = merge_link_cypher(&from.pattern(), &to.pattern(), link);
+    debug!("{}", q);
+    to.bind(from.bind(query(q.as_str())))
+}
+
+fn merge_link_cypher(from: &str, to: &str, link: Link) -> String {
+    format!(
         "MATCH {}
     MATCH {}
-    MERGE ({})-[:{}]->({})
+    MERGE (from)-[:{}]->(to)
     ",
-        from.cypher, to.cypher, from.var, link, to.var
-    );
-    debug!("{}", q);
-    query(q.as_str())
+        from,
+        to,
+        link.as_str()
+    )
+}
+
+/// Creates `UNWIND` queries merging many nodes, [`BATCH_SIZE`] per query.
+///
+/// # Arguments
+///
+/// * `nodes` - The nodes to merge, of any kinds
+pub fn merge_nodes(nodes: &[GitCypher]) -> Vec<Query> {
+    let mut by_kind = BTreeMap::<NodeKind, Vec<GitCypher>>::new();
+    for node in nodes {
+        by_kind
+            .entry(node.kind)
+            .or_default()
+            .push(node.with_var("node"));
+    }
+
+    let mut queries = Vec::new();
+    for nodes in by_kind.into_values() {
+        let q = format!(
+            "UNWIND $rows AS row MERGE {}",
+            nodes[0].pattern_from("row.node")
+        );
+        debug!("{} ({} rows)", q, nodes.len());
+        for batch in nodes.chunks(BATCH_SIZE) {
+            let rows: Vec<HashMap<String, String>> = batch
+                .iter()
+                .map(|node| node.params().into_iter().collect())
+                .collect();
+            queries.push(query(q.as_str()).param("rows", rows));
+        }
+    }
+    queries
+}
+
+/// Creates `UNWIND` queries merging many links, [`BATCH_SIZE`] per query.
+///
+/// # Arguments
+///
+/// * `links` - The source node, target node and link of each link
+pub fn merge_links(links: &[(GitCypher, GitCypher, Link)]) -> Vec<Query> {
+    let mut by_kind = BTreeMap::<(Link, NodeKind, NodeKind), Vec<HashMap<String, String>>>::new();
+    let mut patterns = HashMap::<(Link, NodeKind, NodeKind), String>::new();
+    for (from, to, link) in links {
+        debug_assert!(link.connects(from.kind, to.kind), "{:?}", link);
+        let (from, to) = (from.with_var("from"), to.with_var("to"));
+        let key = (*link, from.kind, to.kind);
+        patterns.entry(key).or_insert_with(|| {
+            merge_link_cypher(
+                &from.pattern_from("row.from"),
+                &to.pattern_from("row.to"),
+                *link,
+            )
+        });
+        by_kind
+            .entry(key)
+            .or_default()
+            .push(from.params().into_iter().chain(to.params()).collect());
+    }
+
+    let mut queries = Vec::new();
+    for (key, rows) in by_kind {
+        let q = format!("UNWIND $rows AS row {}", patterns[&key]);
+        debug!("{} ({} rows)", q, rows.len());
+        for batch in rows.chunks(BATCH_SIZE) {
+            queries.push(query(q.as_str()).param("rows", batch.to_vec()));
+        }
+    }
+    queries
+}
+
+/// Nodes and links collected for one import, merged in batches
+///
+/// Deletes are applied before the merges, so a link replaced by an import is
+/// deleted and merged by the same import.
+#[derive(Debug, Default, Clone)]
+pub struct GraphImport {
+    pub deletes: Vec<GraphDelete>,
+    pub nodes: Vec<GitCypher>,
+    pub links: Vec<(GitCypher, GitCypher, Link)>,
+}
+
+/// What an import deletes from the graph
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GraphDelete {
+    /// The node and all its links
+    Node(GitCypher),
+    /// All links of the node, keeping the node
+    References(GitCypher),
+    /// One link, keeping both nodes
+    Link(GitCypher, GitCypher, Link),
+}
+
+impl GraphImport {
+    pub fn node(&mut self, node: GitCypher) {
+        self.nodes.push(node);
+    }
+
+    pub fn link(&mut self, from: GitCypher, to: GitCypher, link: Link) {
+        self.links.push((from, to, link));
+    }
+
+    pub fn delete(&mut self, delete: GraphDelete) {
+        self.deletes.push(delete);
+    }
+
+    /// Add everything collected by `other`
+    pub fn extend(&mut self, other: GraphImport) {
+        self.deletes.extend(other.deletes);
+        self.nodes.extend(other.nodes);
+        self.links.extend(other.links);
+    }
+
+    /// Number of deletes, nodes and links
+    pub fn len(&self) -> usize {
+        self.deletes.len() + self.nodes.len() + self.links.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Queries running the deletes, then merging all nodes, then all links
+    /// between them
+    pub fn queries(&self) -> Vec<Query> {
+        let mut queries: Vec<Query> = self
+            .deletes
+            .iter()
+            .map(|delete| match delete.clone() {
+                GraphDelete::Node(node) => delete_node_and_references_to_node(node),
+                GraphDelete::References(node) => delete_references_to_node(node),
+                GraphDelete::Link(from, to, link) => delete_link(from, to, link),
+            })
+            .collect();
+        queries.extend(merge_nodes(&self.nodes));
+        queries.extend(merge_links(&self.links));
+        queries
+    }
 }
 
 pub fn delete_node_and_references_to_node(node: GitCypher) -> Query {
-    let q = format!(
-        "MATCH {} OPTIONAL MATCH ({})-[r]-() DELETE {}, r",
-        node.cypher, node.var, node.var
-    );
+    let q = format!("MATCH {} DETACH DELETE {}", node.pattern(), node.var);
     debug!("{}", q);
-    query(q.as_str())
+    node.bind(query(q.as_str()))
 }
 
 pub fn delete_references_to_node(node: GitCypher) -> Query {
     let q = format!(
         "MATCH {} OPTIONAL MATCH ({})-[r]-() DELETE r",
-        node.cypher, node.var
+        node.pattern(),
+        node.var
     );
     debug!("{}", q);
-    query(q.as_str())
+    node.bind(query(q.as_str()))
 }
 
-pub fn find_commits_for_reference(reference: GitCypher) -> GitCypher {
+/// Creates a Cypher query to delete a link, keeping both nodes.
+///
+/// # Arguments
+///
+/// * `from` - The source node
+/// * `to` - The target node
+/// * `link` - The link between the nodes
+pub fn delete_link(from: GitCypher, to: GitCypher, link: Link) -> Query {
+    let (from, to) = (from.with_var("from"), to.with_var("to"));
     let q = format!(
-        "MATCH {}<-[:links_to]-(c:Commit) RETURN c",
-        reference.cypher
+        "MATCH {}-[r:{}]->{} DELETE r",
+        from.pattern(),
+        link.as_str(),
+        to.pattern()
     );
     debug!("{}", q);
-    GitCypher {
-        var: "c".to_string(),
-        cypher: q,
-    }
+    to.bind(from.bind(query(q.as_str())))
+}
+
+pub fn find_commits_for_reference(reference: GitCypher) -> Query {
+    let q = find_commits_cypher(&reference);
+    debug!("{}", q);
+    reference.bind(query(q.as_str()))
+}
+
+fn find_commits_cypher(reference: &GitCypher) -> String {
+    format!(
+        "MATCH {}<-[:{}]-(commit:Commit) RETURN commit",
+        reference.pattern(),
+        Link::LinksTo.as_str()
+    )
+}
+
+/// Creates the queries for the uniqueness constraints on the node keys.
+pub fn create_constraints() -> Vec<Query> {
+    NodeKind::ALL
+        .iter()
+        .filter_map(|kind| kind.constraint())
+        .map(|constraint| query(constraint.as_str()))
+        .collect()
 }
 
 pub struct GraphDatabase {
@@ -211,9 +403,13 @@ impl GraphDatabase {
             .unwrap();
         let graph = Graph::connect(config);
         match graph {
-            Ok(graph) => GraphDatabase {
-                graph: Some(Arc::new(graph)),
-            },
+            Ok(graph) => {
+                let database = GraphDatabase {
+                    graph: Some(Arc::new(graph)),
+                };
+                database.create_constraints().await;
+                database
+            }
             Err(err) => {
                 error!("Error connecting to Graph database: {:?}", err);
                 GraphDatabase { graph: None }
@@ -221,6 +417,18 @@ impl GraphDatabase {
         }
     }
 
+    /// Create the uniqueness constraints, which also index the node keys
+    /// that every MERGE looks up
+    async fn create_constraints(&self) {
+        if let Some(graph) = &self.graph {
+            for constraint in create_constraints() {
+                if let Err(err) = graph.run(constraint).await {
+                    warn!("Error creating constraint: {:?}", err);
+                }
+            }
+        }
+    }
+
     pub async fn txn_run_queries(&self, queries: Vec<Query>) -> Result<(), Box<dyn Error>> {
         match &self.graph {
             Some(graph) => {
@@ -266,12 +474,14 @@ impl GraphDatabase {
 
     pub async fn query_commits_for_branches(&self, reference: GitCypher) -> HashSet<String> {
         let mut res = HashSet::<String>::new();
-        let git_cypher = find_commits_for_reference(reference);
         match &self.graph {
             Some(graph) => {
-                let mut result = graph.execute(git_cypher.query()).await.unwrap();
+                let mut result = graph
+                    .execute(find_commits_for_reference(reference))
+                    .await
+                    .unwrap();
                 while let Ok(Some(row)) = result.next().await {
-                    let node: Node = row.get(&git_cypher.var).unwrap();
+                    let node: Node = row.get("commit").unwrap();
                     let name: String = node.get("oid").unwrap();
                     res.insert(name.clone());
                     debug!("{}", name);
@@ -281,6 +491,57 @@ impl GraphDatabase {
         }
         res
     }
+
+    /// Query the graph database for the commits linked to a reference whose
+    /// history is imported, i.e. that are linked to their parents
+    pub async fn query_history_heads_for_branch(&self, reference: GitCypher) -> HashSet<String> {
+        let mut res = HashSet::<String>::new();
+        match &self.graph {
+            Some(graph) => {
+                let q = format!(
+                    "MATCH {}<-[:{}]-(commit:Commit)-[:{}]->(:Commit) RETURN DISTINCT commit",
+                    reference.pattern(),
+                    Link::LinksTo.as_str(),
+                    Link::Parent.as_str()
+                );
+                let mut result = graph
+                    .execute(reference.bind(query(q.as_str())))
+                    .await
+                    .unwrap();
+                while let Ok(Some(row)) = result.next().await {
+                    let node: Node = row.get("commit").unwrap();
+                    let oid: String = node.get("oid").unwrap();
+                    debug!("{}", oid);
+                    res.insert(oid);
+                }
+            }
+            None => error!("No graph connection"),
+        }
+        res
+    }
+
+    /// Query the graph database for the (collection, oid) of all Layer nodes,
+    /// the layer commits whose recipes are already imported
+    pub async fn query_imported_layers(&self) -> HashSet<(String, String)> {
+        let mut res = HashSet::<(String, String)>::new();
+        match &self.graph {
+            Some(graph) => {
+                let mut result = graph
+                    .execute(query("MATCH (layer:Layer) RETURN layer"))
+                    .await
+                    .unwrap();
+                while let Ok(Some(row)) = result.next().await {
+                    let node: Node = row.get("layer").unwrap();
+                    let collection: String = node.get("collection").unwrap();
+                    let oid: String = node.get("oid").unwrap();
+                    debug!("{} {}", collection, oid);
+                    res.insert((collection, oid));
+                }
+            }
+            None => error!("No graph connection"),
+        }
+        res
+    }
 }
 
 #[cfg(test)]
@@ -296,59 +557,132 @@ mod tests {
 
         assert_eq!(result.var, "reference".to_owned());
         assert_eq!(
-            result.cypher,
-            format!("(reference:Reference {{name: '{}', uri: '{}'}})", name, uri)
+            result.pattern(),
+            "(reference:Reference {name: $reference_name, uri: $reference_uri})"
+        );
+        assert_eq!(
+            result.params(),
+            [
+                ("reference_name".to_string(), name.to_string()),
+                ("reference_uri".to_string(), uri.to_string())
+            ]
         );
     }
 
     #[test]
     fn test_node_kas_manifest() {
-        let path = "foo/bar.yml";
+        let path = "foo/it's.yml";
         let oid = "abcdef123456";
 
         let result = node_kas_manifest(path, oid);
 
-        assert_eq!(result.var, "repository");
+        assert_eq!(result.var, "manifest");
         assert_eq!(
-            result.cypher,
-            format!(
-                "(repository:Manifest {{path: '{}', oid: '{}', type: 'kas'}})",
-                path, oid
-            )
+            result.pattern(),
+            "(manifest:Manifest {path: $manifest_path, oid: $manifest_oid, type: $manifest_type})"
         );
+        assert_eq!(result.values, [path, oid, "kas"]);
+    }
+
+    #[test]
+    fn test_with_var() {
+        let result = node_commit("abc").with_var("parent");
+
+        assert_eq!(result.var, "parent");
+        assert_eq!(result.pattern(), "(parent:Commit {oid: $parent_oid})");
+        assert_eq!(result.params()[0].0, "parent_oid");
+    }
+
+    #[test]
+    fn test_merge_link() {
+        let from = node_commit("abc").with_var("from");
+        let to = node_reference("main", "uri").with_var("to");
+
+        let result = merge_link_cypher(&from.pattern(), &to.pattern(), Link::LinksTo);
+        assert!(result.starts_with("MATCH (from:Commit {oid: $from_oid})"));
+        assert!(result.contains("MERGE (from)-[:links_to]->(to)"));
+
+        let query = merge_link(node_commit("abc"), node_commit("def"), Link::Parent);
+        assert!(query.has_param_key("from_oid"));
+        assert!(query.has_param_key("to_oid"));
+    }
+
+    #[test]
+    fn test_merge_batches() {
+        let commits: Vec<GitCypher> = (0..BATCH_SIZE + 1)
+            .map(|i| node_commit(&i.to_string()))
+            .collect();
+        let mut nodes = commits.clone();
+        nodes.push(node_person("name", "email"));
+        // Two batches of commits, one of persons
+        assert_eq!(merge_nodes(&nodes).len(), 3);
+        assert!(merge_nodes(&nodes)[0].has_param_key("rows"));
+
+        let links: Vec<_> = commits
+            .windows(2)
+            .map(|pair| (pair[0].clone(), pair[1].clone(), Link::Parent))
+            .chain([(
+                commits[0].clone(),
+                node_person("name", "email"),
+                Link::AuthoredBy,
+            )])
+            .collect();
+        assert_eq!(merge_links(&links).len(), 2);
+
+        let mut import = GraphImport::default();
+        import.node(node_commit("abc"));
+        import.link(node_commit("abc"), node_message("it's"), Link::HasMessage);
+        assert_eq!(import.queries().len(), 2);
+
+        let mut other = GraphImport::default();
+        other.delete(GraphDelete::Node(node_commit("def")));
+        import.extend(other);
+        assert_eq!(import.len(), 3);
+        assert!(import.queries()[0].has_param_key("commit_oid"));
     }
 
     #[test]
     fn test_find_commits_for_reference() {
-        // Create a sample GitCypher object
-        let reference = GitCypher {
-            var: "ref".to_string(),
-            cypher: "(ref:Reference {name:'main'})".to_string(),
-        };
+        let reference = node_reference("main", "uri");
 
-        // Call the function under test
-        let result = find_commits_for_reference(reference);
+        let result = find_commits_cypher(&reference);
 
-        // Assert expected result
-        assert_eq!(result.var, "c");
         assert_eq!(
-            result.cypher,
-            "MATCH (ref:Reference {name:'main'})<-[:links_to]-(c:Commit) RETURN c"
+            result,
+            "MATCH (reference:Reference {name: $reference_name, uri: $reference_uri})<-[:links_to]-(commit:Commit) RETURN commit"
         );
+        assert!(find_commits_for_reference(reference).has_param_key("reference_name"));
     }
 
     #[test]
     fn test_delete_node_and_references() {
-        // Create a sample node
-        let node = GitCypher {
-            var: "n".to_string(),
-            cypher: "(n:Node)".to_string(),
-        };
+        let node = node_commit("abc");
 
         // Call function under test
         let result = delete_node_and_references_to_node(node);
 
         // Assert query is as expected
         assert!(!result.has_param_key("key"));
+        assert!(result.has_param_key("commit_oid"));
+
+        let result = delete_link(
+            node_commit("abc"),
+            node_reference("main", "uri"),
+            Link::LinksTo,
+        );
+        assert!(result.has_param_key("from_oid"));
+        assert!(result.has_param_key("to_name"));
+    }
+
+    #[test]
+    fn test_schema() {
+        assert_eq!(
+            NodeKind::Reference.constraint().unwrap(),
+            "CREATE CONSTRAINT reference_key IF NOT EXISTS FOR (n:Reference) REQUIRE (n.name, n.uri) IS UNIQUE"
+        );
+        assert!(NodeKind::Message.constraint().is_none());
+        assert_eq!(create_constraints().len(), NodeKind::ALL.len() - 1);
+        assert!(Link::LinksTo.connects(NodeKind::Commit, NodeKind::Tag));
+        assert!(!Link::LinksTo.connects(NodeKind::Tag, NodeKind::Commit));
     }
 }
@@ -0,0 +1,537 @@
+//! Embedded graph store, for imports without a Neo4j server
+//!
+//! Nodes are kept by kind and key, links by their nodes and type, following
+//! the Cypher the database runs: merging a link between nodes that do not
+//! exist does nothing, deleting a node deletes its links. It is not built on
+//! `convenient_graph::DAG`, which rejects the cycles recipe dependencies can
+//! have and cannot remove nodes.
+//!
+//! A store opened on a file is saved to it as JSON after every run. The graph
+//! can be exported as JSON or as GraphML, which `apoc.import.graphml` reads
+//! into Neo4j.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::{GitCypher, GraphDelete, GraphImport, GraphStore, Link, NodeKind};
+
+/// A node by its kind and the values of its key
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct NodeKey {
+    kind: NodeKind,
+    values: Vec<String>,
+}
+
+impl From<&GitCypher> for NodeKey {
+    fn from(node: &GitCypher) -> NodeKey {
+        NodeKey {
+            kind: node.kind,
+            values: node.values.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct PropertyGraph {
+    nodes: BTreeSet<NodeKey>,
+    links: BTreeSet<(NodeKey, Link, NodeKey)>,
+}
+
+impl PropertyGraph {
+    fn detach(&mut self, node: &NodeKey) {
+        self.links
+            .retain(|(from, _, to)| from != node && to != node);
+    }
+}
+
+/// Serialized graph, nodes referred to by their index
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct JsonGraph {
+    nodes: Vec<JsonNode>,
+    links: Vec<JsonLink>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JsonNode {
+    id: usize,
+    label: String,
+    properties: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JsonLink {
+    source: usize,
+    target: usize,
+    #[serde(rename = "type")]
+    link: String,
+}
+
+/// An in-process property graph implementing [`GraphStore`]
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    graph: Mutex<PropertyGraph>,
+    path: Option<PathBuf>,
+}
+
+impl MemoryStore {
+    /// Store saved to `path` after every run, starting from its graph if the
+    /// file exists
+    pub fn open(path: impl Into<PathBuf>) -> Result<MemoryStore, Box<dyn Error + Send + Sync>> {
+        let path = path.into();
+        let mut store = if path.exists() {
+            MemoryStore::from_json(&fs::read_to_string(&path)?)?
+        } else {
+            MemoryStore::default()
+        };
+        store.path = Some(path);
+        Ok(store)
+    }
+
+    /// Store holding the graph of [`MemoryStore::to_json`]
+    pub fn from_json(json: &str) -> Result<MemoryStore, Box<dyn Error + Send + Sync>> {
+        let json: JsonGraph = serde_json::from_str(json)?;
+        let mut graph = PropertyGraph::default();
+        let mut keys = BTreeMap::new();
+        for node in json.nodes {
+            let kind = NodeKind::from_label(&node.label)
+                .ok_or_else(|| format!("unknown node label {}", node.label))?;
+            let values = kind
+                .key()
+                .iter()
+                .map(|property| {
+                    node.properties.get(*property).cloned().ok_or_else(|| {
+                        format!("{} node {} has no {}", node.label, node.id, property)
+                    })
+                })
+                .collect::<Result<Vec<String>, String>>()?;
+            let key = NodeKey { kind, values };
+            graph.nodes.insert(key.clone());
+            keys.insert(node.id, key);
+        }
+        for link in json.links {
+            let kind = Link::from_name(&link.link)
+                .ok_or_else(|| format!("unknown link type {}", link.link))?;
+            let node = |id: usize| {
+                keys.get(&id)
+                    .cloned()
+                    .ok_or_else(|| format!("{} link to unknown node {}", link.link, id))
+            };
+            graph
+                .links
+                .insert((node(link.source)?, kind, node(link.target)?));
+        }
+        Ok(MemoryStore {
+            graph: Mutex::new(graph),
+            path: None,
+        })
+    }
+
+    fn graph(&self) -> MutexGuard<'_, PropertyGraph> {
+        self.graph
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Apply the deletes, then merge the nodes and links of `import`
+    pub fn apply(&self, import: &GraphImport) {
+        let mut graph = self.graph();
+        for delete in &import.deletes {
+            match delete {
+                GraphDelete::Node(node) => {
+                    let node = NodeKey::from(node);
+                    graph.detach(&node);
+                    graph.nodes.remove(&node);
+                }
+                GraphDelete::References(node) => graph.detach(&NodeKey::from(node)),
+                GraphDelete::Link(from, to, link) => {
+                    graph
+                        .links
+                        .remove(&(NodeKey::from(from), *link, NodeKey::from(to)));
+                }
+            }
+        }
+        for node in &import.nodes {
+            graph.nodes.insert(NodeKey::from(node));
+        }
+        for (from, to, link) in &import.links {
+            debug_assert!(link.connects(from.kind, to.kind), "{:?}", link);
+            let (from, to) = (NodeKey::from(from), NodeKey::from(to));
+            if graph.nodes.contains(&from) && graph.nodes.contains(&to) {
+                graph.links.insert((from, *link, to));
+            }
+        }
+    }
+
+    /// Save the graph to the file the store was opened on, if any
+    pub fn save(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if let Some(path) = &self.path {
+            fs::write(path, self.to_json())?;
+        }
+        Ok(())
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.graph().nodes.len()
+    }
+
+    pub fn link_count(&self) -> usize {
+        self.graph().links.len()
+    }
+
+    /// Names of the references the repository has
+    pub fn branches_for_repository(&self, git_uri: &str) -> HashSet<String> {
+        self.graph()
+            .links
+            .iter()
+            .filter(|(from, link, to)| {
+                *link == Link::Has
+                    && from.kind == NodeKind::Repository
+                    && from.values == [git_uri]
+                    && to.kind == NodeKind::Reference
+            })
+            .map(|(_, _, to)| to.values[0].clone())
+            .collect()
+    }
+
+    /// Oids of the commits linked to the reference
+    pub fn commits_for_branch(&self, reference: &GitCypher) -> HashSet<String> {
+        let reference = NodeKey::from(reference);
+        self.graph()
+            .links
+            .iter()
+            .filter(|(from, link, to)| {
+                *link == Link::LinksTo && from.kind == NodeKind::Commit && *to == reference
+            })
+            .map(|(from, _, _)| from.values[0].clone())
+            .collect()
+    }
+
+    /// Oids of the commits linked to the reference that are linked to their
+    /// parents
+    pub fn history_heads_for_branch(&self, reference: &GitCypher) -> HashSet<String> {
+        let commits = self.commits_for_branch(reference);
+        self.graph()
+            .links
+            .iter()
+            .filter(|(from, link, _)| *link == Link::Parent && commits.contains(&from.values[0]))
+            .map(|(from, _, _)| from.values[0].clone())
+            .collect()
+    }
+
+    /// (collection, oid) of all layer nodes
+    pub fn imported_layers(&self) -> HashSet<(String, String)> {
+        self.graph()
+            .nodes
+            .iter()
+            .filter(|node| node.kind == NodeKind::Layer)
+            .map(|node| (node.values[0].clone(), node.values[1].clone()))
+            .collect()
+    }
+
+    /// Names of the recipes the layer contains
+    pub fn recipes_for_layer(&self, layer: &GitCypher) -> HashSet<String> {
+        let layer = NodeKey::from(layer);
+        self.graph()
+            .links
+            .iter()
+            .filter(|(from, link, to)| {
+                *link == Link::Contains && *from == layer && to.kind == NodeKind::Recipe
+            })
+            .map(|(_, _, to)| to.values[0].clone())
+            .collect()
+    }
+
+    /// The graph as JSON: nodes with their label and key properties, links
+    /// with the indices of their nodes and their type
+    pub fn to_json(&self) -> String {
+        let graph = self.graph();
+        let ids: BTreeMap<&NodeKey, usize> = graph.nodes.iter().zip(0..).collect();
+        let json = JsonGraph {
+            nodes: graph
+                .nodes
+                .iter()
+                .map(|node| JsonNode {
+                    id: ids[node],
+                    label: node.kind.label().to_string(),
+                    properties: properties(node).collect(),
+                })
+                .collect(),
+            links: graph
+                .links
+                .iter()
+                .map(|(from, link, to)| JsonLink {
+                    source: ids[from],
+                    target: ids[to],
+                    link: link.as_str().to_string(),
+                })
+                .collect(),
+        };
+        serde_json::to_string_pretty(&json).expect("graph serializes to JSON")
+    }
+
+    /// The graph as GraphML, with the `labels` and `label` data of
+    /// `apoc.export.graphml`
+    pub fn to_graphml(&self) -> String {
+        let graph = self.graph();
+        let mut names: BTreeSet<&str> = BTreeSet::new();
+        for node in &graph.nodes {
+            names.extend(node.kind.key());
+        }
+
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str("<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n");
+        xml.push_str(
+            "  <key id=\"labels\" for=\"node\" attr.name=\"labels\" attr.type=\"string\"/>\n",
+        );
+        for name in &names {
+            xml.push_str(&format!(
+                "  <key id=\"{}\" for=\"node\" attr.name=\"{}\" attr.type=\"string\"/>\n",
+                name, name
+            ));
+        }
+        xml.push_str(
+            "  <key id=\"label\" for=\"edge\" attr.name=\"label\" attr.type=\"string\"/>\n",
+        );
+        xml.push_str("  <graph id=\"G\" edgedefault=\"directed\">\n");
+
+        let ids: BTreeMap<&NodeKey, usize> = graph.nodes.iter().zip(0..).collect();
+        for node in &graph.nodes {
+            let label = node.kind.label();
+            xml.push_str(&format!(
+                "    <node id=\"n{}\" labels=\":{}\"><data key=\"labels\">:{}</data>",
+                ids[node], label, label
+            ));
+            for (name, value) in properties(node) {
+                xml.push_str(&format!(
+                    "<data key=\"{}\">{}</data>",
+                    name,
+                    escape_xml(&value)
+                ));
+            }
+            xml.push_str("</node>\n");
+        }
+        for (id, (from, link, to)) in graph.links.iter().enumerate() {
+            xml.push_str(&format!(
+                "    <edge id=\"e{}\" source=\"n{}\" target=\"n{}\" label=\"{}\"><data key=\"label\">{}</data></edge>\n",
+                id,
+                ids[from],
+                ids[to],
+                link.as_str(),
+                link.as_str()
+            ));
+        }
+
+        xml.push_str("  </graph>\n</graphml>\n");
+        xml
+    }
+
+    /// Write the graph to `path`, as GraphML for a `.graphml` file and as
+    /// JSON otherwise
+    pub fn export(&self, path: &Path) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let contents = if path.extension().is_some_and(|ext| ext == "graphml") {
+            self.to_graphml()
+        } else {
+            self.to_json()
+        };
+        fs::write(path, contents)?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl GraphStore for MemoryStore {
+    async fn run(&self, import: GraphImport) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.apply(&import);
+        self.save()
+    }
+
+    async fn query_branches_for_repository(&self, git_uri: &str) -> HashSet<String> {
+        self.branches_for_repository(git_uri)
+    }
+
+    async fn query_commits_for_branches(&self, reference: GitCypher) -> HashSet<String> {
+        self.commits_for_branch(&reference)
+    }
+
+    async fn query_history_heads_for_branch(&self, reference: GitCypher) -> HashSet<String> {
+        self.history_heads_for_branch(&reference)
+    }
+
+    async fn query_imported_layers(&self) -> HashSet<(String, String)> {
+        self.imported_layers()
+    }
+}
+
+/// Key properties of a node with their values
+fn properties(node: &NodeKey) -> impl Iterator<Item = (String, String)> + '_ {
+    node.kind
+        .key()
+        .iter()
+        .zip(&node.values)
+        .map(|(name, value)| (name.to_string(), value.clone()))
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recipes::{node_layer, node_recipe};
+    use crate::{node_commit, node_message, node_reference, node_repository};
+
+    fn branch_import() -> GraphImport {
+        let mut import = GraphImport::default();
+        import.node(node_repository("uri"));
+        import.node(node_reference("main", "uri"));
+        import.node(node_commit("abc"));
+        import.node(node_commit("def"));
+        import.node(node_message("it's <b> & \"c\""));
+        import.link(
+            node_repository("uri"),
+            node_reference("main", "uri"),
+            Link::Has,
+        );
+        import.link(
+            node_commit("def"),
+            node_reference("main", "uri"),
+            Link::LinksTo,
+        );
+        import.link(node_commit("def"), node_commit("abc"), Link::Parent);
+        import.link(
+            node_commit("def"),
+            node_message("it's <b> & \"c\""),
+            Link::HasMessage,
+        );
+        // Not merged, as the person node does not exist
+        import.link(
+            node_commit("def"),
+            crate::node_person("name", "email"),
+            Link::AuthoredBy,
+        );
+        import
+    }
+
+    #[test]
+    fn test_recipes_for_layer() {
+        let layer = node_layer("core", "abc");
+        let mut import = GraphImport::default();
+        import.node(layer.clone());
+        import.node(node_recipe("hello", "1.0", "core", "abc"));
+        import.node(node_recipe("other", "1.0", "other", "abc"));
+        import.link(
+            layer.clone(),
+            node_recipe("hello", "1.0", "core", "abc"),
+            Link::Contains,
+        );
+        let store = MemoryStore::default();
+        store.apply(&import);
+
+        assert_eq!(
+            store.recipes_for_layer(&layer),
+            HashSet::from(["hello".to_string()])
+        );
+        let other_commit = node_layer("core", "def");
+        assert!(store.recipes_for_layer(&other_commit).is_empty());
+    }
+
+    #[test]
+    fn test_apply() {
+        let store = MemoryStore::default();
+        store.apply(&branch_import());
+        store.apply(&branch_import());
+
+        assert_eq!(store.node_count(), 5);
+        assert_eq!(store.link_count(), 4);
+        assert_eq!(
+            store.branches_for_repository("uri"),
+            HashSet::from(["main".to_string()])
+        );
+        let main = node_reference("main", "uri");
+        assert_eq!(
+            store.commits_for_branch(&main),
+            HashSet::from(["def".to_string()])
+        );
+        assert_eq!(
+            store.history_heads_for_branch(&main),
+            HashSet::from(["def".to_string()])
+        );
+        assert!(store.imported_layers().is_empty());
+
+        let mut update = GraphImport::default();
+        update.link(node_commit("abc"), main.clone(), Link::LinksTo);
+        update.delete(GraphDelete::Link(
+            node_commit("def"),
+            main.clone(),
+            Link::LinksTo,
+        ));
+        store.apply(&update);
+        assert_eq!(
+            store.commits_for_branch(&main),
+            HashSet::from(["abc".to_string()])
+        );
+        assert!(store.history_heads_for_branch(&main).is_empty());
+
+        let mut delete = GraphImport::default();
+        delete.delete(GraphDelete::Node(main.clone()));
+        delete.delete(GraphDelete::References(node_commit("def")));
+        store.apply(&delete);
+        assert_eq!(store.node_count(), 4);
+        assert_eq!(store.link_count(), 0);
+        assert!(store.branches_for_repository("uri").is_empty());
+    }
+
+    #[test]
+    fn test_json() {
+        let store = MemoryStore::default();
+        store.apply(&branch_import());
+
+        let json = store.to_json();
+        let loaded = MemoryStore::from_json(&json).unwrap();
+        assert_eq!(loaded.node_count(), 5);
+        assert_eq!(loaded.link_count(), 4);
+        assert_eq!(loaded.to_json(), json);
+
+        assert!(MemoryStore::from_json(
+            r#"{"nodes": [{"id": 0, "label": "Branch", "properties": {}}], "links": []}"#
+        )
+        .is_err());
+        assert!(MemoryStore::from_json(
+            r#"{"nodes": [], "links": [{"source": 0, "target": 1, "type": "has"}]}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_open_and_export() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("graph.json");
+
+        let store = MemoryStore::open(&path).unwrap();
+        assert_eq!(store.node_count(), 0);
+        store.apply(&branch_import());
+        store.save().unwrap();
+        assert_eq!(MemoryStore::open(&path).unwrap().link_count(), 4);
+
+        let graphml = dir.path().join("graph.graphml");
+        store.export(&graphml).unwrap();
+        let xml = fs::read_to_string(graphml).unwrap();
+        assert!(xml.contains("<node id=\"n0\" labels=\":Repository\"><data key=\"labels\">:Repository</data><data key=\"uri\">uri</data></node>"));
+        assert!(xml.contains("it's &lt;b&gt; &amp; &quot;c&quot;"));
+        assert!(xml.contains("label=\"parent\""));
+        assert_eq!(xml.matches("<edge ").count(), 4);
+    }
+}
@@ -0,0 +1,403 @@
+//! Layers, recipes, tasks and packages from a [`RecipeGraph`]
+//!
+//! A layer is imported at the commit it is checked out at: the Layer node is
+//! keyed by collection and commit OID, and so are its recipes and their tasks.
+//! A layer commit that is already in the database does not change, so its
+//! recipes, tasks and packages are not imported again. Dependencies between
+//! recipes and bbappends depend on the combination of layers and are merged
+//! on every import.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use convenient_bitbake::recipe_graph::{Recipe, RecipeGraph, RecipeId, TaskId};
+use tracing::{debug, warn};
+use walkdir::WalkDir;
+
+use crate::{node_commit, GitCypher, GraphImport, Link, NodeKind};
+
+/// Creates a Cypher node representation for a layer at a commit.
+///
+/// # Arguments
+///
+/// * `collection` - The `BBFILE_COLLECTIONS` name of the layer
+/// * `oid` - The object ID of the commit the layer is checked out at
+pub fn node_layer(collection: &str, oid: &str) -> GitCypher {
+    GitCypher::new("layer", NodeKind::Layer, [collection, oid])
+}
+
+/// Creates a Cypher node representation for a recipe (PN/PV) of a layer commit.
+///
+/// # Arguments
+///
+/// * `name` - The PN of the recipe
+/// * `version` - The PV of the recipe, empty if unknown
+/// * `collection` - The layer the recipe is in
+/// * `oid` - The commit the layer is checked out at
+pub fn node_recipe(name: &str, version: &str, collection: &str, oid: &str) -> GitCypher {
+    GitCypher::new("recipe", NodeKind::Recipe, [name, version, collection, oid])
+}
+
+/// Creates a Cypher node representation for a task of a recipe.
+///
+/// # Arguments
+///
+/// * `name` - The name of the task, e.g. `do_compile`
+/// * `recipe` - The PN of the recipe
+/// * `version` - The PV of the recipe, empty if unknown
+/// * `collection` - The layer the recipe is in
+/// * `oid` - The commit the layer is checked out at
+pub fn node_task(
+    name: &str,
+    recipe: &str,
+    version: &str,
+    collection: &str,
+    oid: &str,
+) -> GitCypher {
+    GitCypher::new(
+        "task",
+        NodeKind::Task,
+        [name, recipe, version, collection, oid],
+    )
+}
+
+/// Creates a Cypher node representation for a package.
+///
+/// # Arguments
+///
+/// * `name` - The name of the package, or of a runtime provide
+pub fn node_package(name: &str) -> GitCypher {
+    GitCypher::new("package", NodeKind::Package, [name])
+}
+
+/// A layer checked out at a commit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayerCommit {
+    pub collection: String,
+    pub path: PathBuf,
+    pub oid: String,
+}
+
+impl LayerCommit {
+    pub fn new(
+        collection: impl Into<String>,
+        path: impl Into<PathBuf>,
+        oid: impl Into<String>,
+    ) -> Self {
+        Self {
+            collection: collection.into(),
+            path: path.into(),
+            oid: oid.into(),
+        }
+    }
+
+    /// The layer at `path` at the commit checked out in its git repository
+    pub fn from_checkout(collection: &str, path: &Path) -> Option<Self> {
+        let repo = git2::Repository::discover(path).ok()?;
+        let oid = repo.head().ok()?.peel_to_commit().ok()?.id();
+        Some(Self::new(collection, path, oid.to_string()))
+    }
+
+    pub fn node(&self) -> GitCypher {
+        node_layer(&self.collection, &self.oid)
+    }
+
+    fn is_imported(&self, imported: &HashSet<(String, String)>) -> bool {
+        imported.contains(&(self.collection.clone(), self.oid.clone()))
+    }
+}
+
+/// Collects the nodes and links importing the recipes of a graph into their layers.
+///
+/// Every layer is linked to the commit it is checked out at and, if given, to
+/// the manifest it was set up from. Recipes are assigned to layers by their
+/// collection; recipes outside of `layers` and skipped recipes are left out.
+///
+/// # Arguments
+///
+/// * `graph` - The recipe graph of a build
+/// * `layers` - The layers of the build
+/// * `manifest` - The kas or repo manifest the layers were set up from
+/// * `imported` - The (collection, oid) of the layers already in the database,
+///   see [`crate::GraphDatabase::query_imported_layers`]
+pub fn recipe_graph_import(
+    graph: &RecipeGraph,
+    layers: &[LayerCommit],
+    manifest: Option<&GitCypher>,
+    imported: &HashSet<(String, String)>,
+) -> GraphImport {
+    let mut import = GraphImport::default();
+
+    for layer in layers {
+        import.node(node_commit(&layer.oid));
+        import.node(layer.node());
+        import.link(node_commit(&layer.oid), layer.node(), Link::Contains);
+        if let Some(manifest) = manifest {
+            import.link(manifest.clone(), layer.node(), Link::Uses);
+        }
+    }
+
+    let by_collection: HashMap<&str, &LayerCommit> = layers
+        .iter()
+        .map(|layer| (layer.collection.as_str(), layer))
+        .collect();
+    let mut recipes = BTreeMap::<RecipeId, (&Recipe, &LayerCommit)>::new();
+    let mut unlayered = 0;
+    for recipe in graph.recipes() {
+        if graph.skip_reason(recipe.id).is_some() {
+            continue;
+        }
+        match recipe.layer.as_deref().and_then(|c| by_collection.get(c)) {
+            Some(layer) => {
+                recipes.insert(recipe.id, (recipe, layer));
+            }
+            None => unlayered += 1,
+        }
+    }
+    if unlayered > 0 {
+        warn!("{} recipes are not in one of the given layers", unlayered);
+    }
+
+    let recipe_node = |id: RecipeId| {
+        recipes.get(&id).map(|(recipe, layer)| {
+            node_recipe(
+                &recipe.name,
+                recipe.version.as_deref().unwrap_or_default(),
+                &layer.collection,
+                &layer.oid,
+            )
+        })
+    };
+    let task_node = |id: TaskId| {
+        let task = graph.get_task(id)?;
+        let (recipe, layer) = recipes.get(&task.recipe_id)?;
+        Some(node_task(
+            &task.name,
+            &recipe.name,
+            recipe.version.as_deref().unwrap_or_default(),
+            &layer.collection,
+            &layer.oid,
+        ))
+    };
+
+    for (id, (recipe, layer)) in &recipes {
+        let Some(node) = recipe_node(*id) else {
+            continue;
+        };
+        if !layer.is_imported(imported) {
+            import.node(node.clone());
+            import.link(layer.node(), node.clone(), Link::Contains);
+            for package in packages(recipe) {
+                import.node(node_package(&package));
+                import.link(node.clone(), node_package(&package), Link::Provides);
+            }
+            for task in graph.get_recipe_tasks(*id) {
+                let Some(task_cypher) = task_node(task.id) else {
+                    continue;
+                };
+                import.node(task_cypher.clone());
+                import.link(node.clone(), task_cypher.clone(), Link::Has);
+                for after in task.after.iter().filter_map(|after| task_node(*after)) {
+                    import.link(task_cypher.clone(), after, Link::Depends);
+                }
+            }
+        }
+
+        for (dependencies, link) in [
+            (&recipe.depends, Link::Depends),
+            (&recipe.rdepends, Link::Rdepends),
+        ] {
+            for dependency in dependencies.iter().filter_map(|d| recipe_node(*d)) {
+                import.link(node.clone(), dependency, link);
+            }
+        }
+        for task in graph.get_recipe_tasks(*id) {
+            let Some(task_cypher) = task_node(task.id) else {
+                continue;
+            };
+            for dependency in &task.task_depends {
+                let target = dependency
+                    .task_id
+                    .or_else(|| graph.find_task(dependency.recipe_id, &dependency.task_name))
+                    .and_then(task_node);
+                if let Some(target) = target {
+                    import.link(task_cypher.clone(), target, Link::Depends);
+                }
+            }
+        }
+    }
+
+    for layer in layers {
+        for bbappend in find_bbappends(&layer.path) {
+            let Some(append) = bbappend.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            let targets: Vec<RecipeId> = recipes
+                .values()
+                .filter(|(recipe, _)| bbappend_matches(append, &recipe_stem(recipe)))
+                .map(|(recipe, _)| recipe.id)
+                .collect();
+            if targets.is_empty() {
+                debug!("{} appends to no recipe of the build", bbappend.display());
+            }
+            for node in targets.into_iter().filter_map(recipe_node) {
+                import.link(layer.node(), node, Link::Bbappends);
+            }
+        }
+    }
+
+    import
+}
+
+/// Packages of a recipe: `PACKAGES` if known, otherwise its PN, and its runtime provides
+fn packages(recipe: &Recipe) -> Vec<String> {
+    let mut packages: Vec<String> = match recipe.metadata.get("PACKAGES") {
+        Some(list) => list
+            .split_whitespace()
+            .map(|package| package.replace("${PN}", &recipe.name))
+            .filter(|package| !package.contains("${"))
+            .collect(),
+        None => vec![recipe.name.clone()],
+    };
+    for provide in &recipe.rprovides {
+        if !packages.contains(provide) {
+            packages.push(provide.clone());
+        }
+    }
+    packages
+}
+
+/// File name of a recipe without extension, e.g. `openssl_3.0.13`
+fn recipe_stem(recipe: &Recipe) -> String {
+    let stem = recipe
+        .file_path
+        .as_deref()
+        .and_then(Path::file_stem)
+        .and_then(|s| s.to_str());
+    match (stem, &recipe.version) {
+        (Some(stem), _) => stem.to_string(),
+        (None, Some(version)) => format!("{}_{}", recipe.name, version),
+        (None, None) => recipe.name.clone(),
+    }
+}
+
+/// Whether a bbappend applies to a recipe, both given by file stem; `%` matches any version
+pub fn bbappend_matches(append: &str, recipe: &str) -> bool {
+    match append.strip_suffix('%') {
+        Some(prefix) => recipe.starts_with(prefix),
+        None => append == recipe,
+    }
+}
+
+/// bbappend files in a layer, leaving out hidden directories and nested layers
+pub fn find_bbappends(layer: &Path) -> Vec<PathBuf> {
+    let mut bbappends: Vec<PathBuf> = WalkDir::new(layer)
+        .into_iter()
+        .filter_entry(|entry| {
+            entry.depth() == 0
+                || !entry.file_type().is_dir()
+                || !(entry.file_name().to_string_lossy().starts_with('.')
+                    || entry.path().join("conf/layer.conf").is_file())
+        })
+        .flatten()
+        .filter(|entry| {
+            entry
+                .path()
+                .extension()
+                .is_some_and(|ext| ext == "bbappend")
+        })
+        .map(|entry| entry.into_path())
+        .collect();
+    bbappends.sort();
+    bbappends
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use convenient_bitbake::recipe_graph::TaskDependency;
+    use std::fs;
+
+    fn recipe_graph() -> RecipeGraph {
+        let mut graph = RecipeGraph::new();
+        let openssl = graph.add_recipe("openssl");
+        let curl = graph.add_recipe("curl");
+        for (id, version, layer) in [(openssl, "3.0.13", "core"), (curl, "8.7.1", "oe")] {
+            let recipe = graph.get_recipe_mut(id).unwrap();
+            recipe.version = Some(version.to_string());
+            recipe.layer = Some(layer.to_string());
+        }
+        graph.get_recipe_mut(openssl).unwrap().rprovides = vec!["libssl".to_string()];
+        graph.add_dependency(curl, openssl);
+
+        let configure = graph.add_task(openssl, "do_configure");
+        let compile = graph.add_task(openssl, "do_compile");
+        graph.get_task_mut(compile).unwrap().after.push(configure);
+        let curl_configure = graph.add_task(curl, "do_configure");
+        graph.add_task_dependency(curl_configure, TaskDependency::new(openssl, "do_compile"));
+        graph
+    }
+
+    #[test]
+    fn test_node_recipe() {
+        let result = node_recipe("openssl", "3.0.13", "core", "abcdef123456");
+
+        assert_eq!(result.var, "recipe");
+        assert_eq!(
+            result.pattern(),
+            "(recipe:Recipe {name: $recipe_name, version: $recipe_version, layer: $recipe_layer, oid: $recipe_oid})"
+        );
+        assert_eq!(result.values, ["openssl", "3.0.13", "core", "abcdef123456"]);
+    }
+
+    #[test]
+    fn test_recipe_graph_import_skips_imported_layers() {
+        let graph = recipe_graph();
+        let layers = [
+            LayerCommit::new("core", "/nonexistent/meta", "1111"),
+            LayerCommit::new("oe", "/nonexistent/meta-oe", "2222"),
+        ];
+        let manifest = crate::node_kas_manifest("kas.yml", "3333");
+
+        let all = recipe_graph_import(&graph, &layers, Some(&manifest), &HashSet::new());
+        // 2 x (commit, layer), openssl: recipe, 2 packages, 2 tasks, curl: recipe, package, task
+        assert_eq!(all.nodes.len(), 4 + 5 + 3);
+        // 2 x (contains, uses), openssl: contains, 2 PROVIDES, 2 has, compile after configure
+        // curl: contains, PROVIDES, has, DEPENDS, task DEPENDS
+        assert_eq!(all.links.len(), 4 + 6 + 5);
+        // One batch per node kind and per link between two kinds
+        assert_eq!(all.queries().len(), 5 + 7);
+
+        let imported = HashSet::from([("core".to_string(), "1111".to_string())]);
+        let incremental = recipe_graph_import(&graph, &layers, Some(&manifest), &imported);
+        assert_eq!(incremental.nodes.len(), 4 + 3);
+        assert_eq!(incremental.links.len(), 4 + 5);
+    }
+
+    #[test]
+    fn test_bbappends() {
+        assert!(bbappend_matches("openssl_%", "openssl_3.0.13"));
+        assert!(bbappend_matches("openssl_3.0.%", "openssl_3.0.13"));
+        assert!(bbappend_matches("openssl_3.0.13", "openssl_3.0.13"));
+        assert!(!bbappend_matches("openssl_3.1.%", "openssl_3.0.13"));
+        assert!(!bbappend_matches("openssl", "openssl_3.0.13"));
+
+        let dir = tempfile::tempdir().unwrap();
+        for file in [
+            "conf/layer.conf",
+            "recipes-connectivity/openssl/openssl_%.bbappend",
+            ".git/ignored.bbappend",
+            "meta-nested/conf/layer.conf",
+            "meta-nested/recipes-core/busybox_%.bbappend",
+        ] {
+            let path = dir.path().join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        assert_eq!(
+            find_bbappends(dir.path()),
+            [dir.path()
+                .join("recipes-connectivity/openssl/openssl_%.bbappend")]
+        );
+    }
+}
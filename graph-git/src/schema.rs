@@ -0,0 +1,172 @@
+//! Node labels and relationship types of the graph
+//!
+//! Every node kind has a key: the properties that identify a node, which are
+//! also the only properties its builder sets and the properties of its
+//! uniqueness constraint. Relationship types list the node kinds they connect.
+
+/// The kinds of nodes in the graph
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum NodeKind {
+    Repository,
+    Reference,
+    Tag,
+    Commit,
+    Person,
+    Message,
+    Manifest,
+    Layer,
+    Recipe,
+    Task,
+    Package,
+}
+
+impl NodeKind {
+    pub const ALL: [NodeKind; 11] = [
+        NodeKind::Repository,
+        NodeKind::Reference,
+        NodeKind::Tag,
+        NodeKind::Commit,
+        NodeKind::Person,
+        NodeKind::Message,
+        NodeKind::Manifest,
+        NodeKind::Layer,
+        NodeKind::Recipe,
+        NodeKind::Task,
+        NodeKind::Package,
+    ];
+
+    /// The kind with the label, the inverse of [`NodeKind::label`]
+    pub fn from_label(label: &str) -> Option<NodeKind> {
+        NodeKind::ALL.into_iter().find(|kind| kind.label() == label)
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            NodeKind::Repository => "Repository",
+            NodeKind::Reference => "Reference",
+            NodeKind::Tag => "Tag",
+            NodeKind::Commit => "Commit",
+            NodeKind::Person => "Person",
+            NodeKind::Message => "Message",
+            NodeKind::Manifest => "Manifest",
+            NodeKind::Layer => "Layer",
+            NodeKind::Recipe => "Recipe",
+            NodeKind::Task => "Task",
+            NodeKind::Package => "Package",
+        }
+    }
+
+    /// The properties identifying a node of this kind
+    pub fn key(self) -> &'static [&'static str] {
+        match self {
+            NodeKind::Repository => &["uri"],
+            NodeKind::Reference | NodeKind::Tag => &["name", "uri"],
+            NodeKind::Commit => &["oid"],
+            NodeKind::Person => &["name", "email"],
+            NodeKind::Message => &["message"],
+            NodeKind::Manifest => &["path", "oid", "type"],
+            NodeKind::Layer => &["collection", "oid"],
+            NodeKind::Recipe => &["name", "version", "layer", "oid"],
+            NodeKind::Task => &["name", "recipe", "version", "layer", "oid"],
+            NodeKind::Package => &["name"],
+        }
+    }
+
+    /// Cypher creating the uniqueness constraint on the key
+    ///
+    /// Messages have none: commit messages can exceed the size limit of the
+    /// index backing a constraint.
+    pub fn constraint(self) -> Option<String> {
+        if self == NodeKind::Message {
+            return None;
+        }
+        let properties: Vec<String> = self.key().iter().map(|p| format!("n.{}", p)).collect();
+        Some(format!(
+            "CREATE CONSTRAINT {}_key IF NOT EXISTS FOR (n:{}) REQUIRE ({}) IS UNIQUE",
+            self.label().to_lowercase(),
+            self.label(),
+            properties.join(", ")
+        ))
+    }
+}
+
+/// The types of relationships in the graph
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Link {
+    Has,
+    LinksTo,
+    Contains,
+    Refers,
+    AuthoredBy,
+    HasMessage,
+    Parent,
+    Uses,
+    Depends,
+    Rdepends,
+    Provides,
+    Bbappends,
+}
+
+impl Link {
+    pub const ALL: [Link; 12] = [
+        Link::Has,
+        Link::LinksTo,
+        Link::Contains,
+        Link::Refers,
+        Link::AuthoredBy,
+        Link::HasMessage,
+        Link::Parent,
+        Link::Uses,
+        Link::Depends,
+        Link::Rdepends,
+        Link::Provides,
+        Link::Bbappends,
+    ];
+
+    /// The type with the name, the inverse of [`Link::as_str`]
+    pub fn from_name(name: &str) -> Option<Link> {
+        Link::ALL.into_iter().find(|link| link.as_str() == name)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Link::Has => "has",
+            Link::LinksTo => "links_to",
+            Link::Contains => "contains",
+            Link::Refers => "refers",
+            Link::AuthoredBy => "authored_by",
+            Link::HasMessage => "has_message",
+            Link::Parent => "parent",
+            Link::Uses => "uses",
+            Link::Depends => "DEPENDS",
+            Link::Rdepends => "RDEPENDS",
+            Link::Provides => "PROVIDES",
+            Link::Bbappends => "BBAPPENDS",
+        }
+    }
+
+    /// Whether the relationship can go from a `from` node to a `to` node
+    pub fn connects(self, from: NodeKind, to: NodeKind) -> bool {
+        use NodeKind::*;
+        match self {
+            Link::Has => matches!(
+                (from, to),
+                (Repository, Reference) | (Repository, Tag) | (Recipe, Task)
+            ),
+            Link::LinksTo => from == Commit && matches!(to, Reference | Tag),
+            Link::Contains => matches!(
+                (from, to),
+                (Commit, Manifest) | (Commit, Layer) | (Layer, Recipe)
+            ),
+            Link::Refers => from == Manifest && matches!(to, Reference | Tag | Commit | Repository),
+            Link::AuthoredBy => (from, to) == (Commit, Person),
+            Link::HasMessage => (from, to) == (Commit, Message),
+            Link::Parent => (from, to) == (Commit, Commit),
+            Link::Uses => (from, to) == (Manifest, Layer),
+            Link::Depends => matches!((from, to), (Recipe, Recipe) | (Task, Task)),
+            Link::Rdepends => (from, to) == (Recipe, Recipe),
+            Link::Provides => (from, to) == (Recipe, Package),
+            Link::Bbappends => (from, to) == (Layer, Recipe),
+        }
+    }
+}
@@ -0,0 +1,54 @@
+//! Backends an import is written to
+//!
+//! [`GraphDatabase`] writes to Neo4j, [`crate::MemoryStore`] keeps the graph
+//! in process for running without a server.
+
+use std::{collections::HashSet, error::Error};
+
+use async_trait::async_trait;
+
+use crate::{GitCypher, GraphDatabase, GraphImport};
+
+/// A graph imports are applied to, queried for what it already holds
+#[async_trait]
+pub trait GraphStore: Send + Sync {
+    /// Apply the deletes, then merge the nodes and links of `import`
+    async fn run(&self, import: GraphImport) -> Result<(), Box<dyn Error + Send + Sync>>;
+
+    /// Names of the references of the repository with the URI
+    async fn query_branches_for_repository(&self, git_uri: &str) -> HashSet<String>;
+
+    /// Oids of the commits linked to the reference
+    async fn query_commits_for_branches(&self, reference: GitCypher) -> HashSet<String>;
+
+    /// Oids of the commits linked to the reference whose history is imported
+    async fn query_history_heads_for_branch(&self, reference: GitCypher) -> HashSet<String>;
+
+    /// (collection, oid) of the layer commits whose recipes are imported
+    async fn query_imported_layers(&self) -> HashSet<(String, String)>;
+}
+
+#[async_trait]
+impl GraphStore for GraphDatabase {
+    async fn run(&self, import: GraphImport) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.txn_run_queries(import.queries())
+            .await
+            .map_err(|err| err.to_string().into())
+    }
+
+    async fn query_branches_for_repository(&self, git_uri: &str) -> HashSet<String> {
+        GraphDatabase::query_branches_for_repository(self, git_uri).await
+    }
+
+    async fn query_commits_for_branches(&self, reference: GitCypher) -> HashSet<String> {
+        GraphDatabase::query_commits_for_branches(self, reference).await
+    }
+
+    async fn query_history_heads_for_branch(&self, reference: GitCypher) -> HashSet<String> {
+        GraphDatabase::query_history_heads_for_branch(self, reference).await
+    }
+
+    async fn query_imported_layers(&self) -> HashSet<(String, String)> {
+        GraphDatabase::query_imported_layers(self).await
+    }
+}
@@ -8,15 +8,16 @@
 //! - Enhanced caching with incremental build analysis
 
 use convenient_bitbake::{
-    BuildEnvironment, BuildOrchestrator, OrchestratorConfig,
-    SimplePythonEvaluator, TaskGraphBuilder,
+    BuildEnvironment, BuildOrchestrator, BuildPlan, McTaskId, MultiConfigTarget,
+    OrchestratorConfig, RecipeGraph, SimplePythonEvaluator, TaskGraphBuilder,
 };
 use convenient_bitbake::executor::{
-    TaskExecutor, CacheManager,
+    TaskExecutor, TaskSpec, CacheManager, SstateMirror,
 };
+use convenient_bitbake::parse_lifecycle::{self, ParseEvent};
 
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::time::Instant;
 
 /// Expand BitBake script with full Python support
@@ -83,10 +84,139 @@ fn simple_expand(script: &str, env: &HashMap<String, String>) -> String {
     result
 }
 
+/// Enrich a task spec with the BitBake variables and work directories it runs with
+///
+/// Shared with `devshell` so a debug shell sees the same environment as the build.
+pub(crate) fn enrich_task_spec(
+    spec: &TaskSpec,
+    recipe_name: &str,
+    recipe_version: &str,
+    machine: &str,
+    distro: &str,
+    tmpdir: &Path,
+) -> TaskSpec {
+    let mut enriched_spec = spec.clone();
+
+    // Setup BitBake variables
+    let mut bb_vars = HashMap::new();
+    bb_vars.insert("PN".to_string(), recipe_name.to_string());
+    bb_vars.insert("PV".to_string(), recipe_version.to_string());
+    bb_vars.insert("MACHINE".to_string(), machine.to_string());
+    bb_vars.insert("DISTRO".to_string(), distro.to_string());
+
+    // Work directories
+    let work_base = tmpdir.join("work").join(machine).join(recipe_name).join(recipe_version);
+    let s_dir = work_base.join(format!("{}-{}", recipe_name, recipe_version));
+    let b_dir = work_base.join("build");
+    let d_dir = work_base.join("image");
+
+    std::fs::create_dir_all(&work_base).ok();
+    std::fs::create_dir_all(&s_dir).ok();
+    std::fs::create_dir_all(&b_dir).ok();
+    std::fs::create_dir_all(&d_dir).ok();
+
+    bb_vars.insert("WORKDIR".to_string(), work_base.to_string_lossy().to_string());
+    bb_vars.insert("S".to_string(), s_dir.to_string_lossy().to_string());
+    bb_vars.insert("B".to_string(), b_dir.to_string_lossy().to_string());
+    bb_vars.insert("D".to_string(), d_dir.to_string_lossy().to_string());
+
+    // System directories
+    bb_vars.insert("sysconfdir".to_string(), "/etc".to_string());
+    bb_vars.insert("bindir".to_string(), "/usr/bin".to_string());
+    bb_vars.insert("sbindir".to_string(), "/usr/sbin".to_string());
+    bb_vars.insert("libdir".to_string(), "/usr/lib".to_string());
+    bb_vars.insert("includedir".to_string(), "/usr/include".to_string());
+    bb_vars.insert("datadir".to_string(), "/usr/share".to_string());
+    bb_vars.insert("mandir".to_string(), "/usr/share/man".to_string());
+    bb_vars.insert("docdir".to_string(), "/usr/share/doc".to_string());
+    bb_vars.insert("infodir".to_string(), "/usr/share/info".to_string());
+    bb_vars.insert("localstatedir".to_string(), "/var".to_string());
+    bb_vars.insert("base_bindir".to_string(), "/bin".to_string());
+    bb_vars.insert("base_sbindir".to_string(), "/sbin".to_string());
+    bb_vars.insert("base_libdir".to_string(), "/lib".to_string());
+    bb_vars.insert("bindir_crossscripts".to_string(), "/usr/bin/crossscripts".to_string());
+
+    // Values from the recipe (and the BitBake hashes) take precedence
+    for (name, value) in bb_vars {
+        enriched_spec.env.entry(name).or_insert(value);
+    }
+    enriched_spec.workdir = work_base;
+    enriched_spec
+}
+
+/// Build plan of one configuration ("" is the default, otherwise a BBMULTICONFIG name)
+struct ConfigPlan {
+    plan: BuildPlan,
+    machine: String,
+    distro: String,
+    tmpdir: PathBuf,
+}
+
+/// Plan one configuration with its multiconfig overlay applied
+async fn plan_config(
+    env: &BuildEnvironment,
+    build_dir: &Path,
+    mc: &str,
+    layer_paths: &HashMap<String, Vec<PathBuf>>,
+) -> Result<ConfigPlan, Box<dyn std::error::Error + Send + Sync>> {
+    let context = env.create_multiconfig_context(mc)?;
+    let configuration = env.parse_configuration(&context);
+    let (machine, distro, variables) = if mc.is_empty() {
+        (
+            env.get_machine().map(|s| s.to_string()),
+            env.get_distro().map(|s| s.to_string()),
+            HashMap::new(),
+        )
+    } else {
+        (context.machine.clone(), context.distro.clone(), context.global_variables)
+    };
+
+    let config = OrchestratorConfig {
+        build_dir: build_dir.to_path_buf(),
+        machine: machine.clone(),
+        distro: distro.clone(),
+        max_io_parallelism: 32,
+        max_cpu_parallelism: num_cpus::get(),
+    };
+
+    let mut orchestrator = BuildOrchestrator::new(config).with_multiconfig(mc, variables);
+    match configuration {
+        Ok(mut configuration) => {
+            if let Err(e) = parse_lifecycle::fire_event(&mut configuration, ParseEvent::ConfigParsed) {
+                tracing::warn!("ConfigParsed handlers failed: {}", e);
+            }
+            orchestrator = orchestrator.with_configuration(configuration);
+        }
+        Err(e) => tracing::warn!("Configuration not parsed, task hashes won't match BitBake's: {}", e),
+    }
+    let tmpdir = orchestrator.tmp_dir();
+    let plan = orchestrator.build_plan(layer_paths.clone()).await?;
+
+    Ok(ConfigPlan {
+        plan,
+        machine: machine.unwrap_or_else(|| "unknown".to_string()),
+        distro: distro.unwrap_or_else(|| "unknown".to_string()),
+        tmpdir,
+    })
+}
+
+/// "recipe:task", prefixed with "mc:<name>:" outside the default configuration
+fn task_label(mc: &str, recipe_name: &str, task_name: &str) -> String {
+    if mc.is_empty() {
+        format!("{}:{}", recipe_name, task_name)
+    } else {
+        format!("mc:{}:{}:{}", mc, recipe_name, task_name)
+    }
+}
+
 /// Execute build with full BuildOrchestrator pipeline
+///
+/// `target` accepts BitBake's `mc:<name>:<recipe>` syntax; with BBMULTICONFIG set,
+/// every configuration is planned so `mcdepends` can cross between them.
 pub async fn execute(
     build_dir: &Path,
     target: &str,
+    export_sstate: bool,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let start_time = Instant::now();
 
@@ -99,27 +229,30 @@ pub async fn execute(
     println!("Build directory: {:?}", build_dir);
     println!();
 
+    let mc_target = MultiConfigTarget::parse(target)?;
+
     // ========== Load Build Environment ==========
     println!("🏗️  Loading build environment...");
     let env = BuildEnvironment::from_build_dir(build_dir)?;
+    let multiconfigs = env.multiconfigs();
     println!("  ✓ MACHINE: {}", env.get_machine().unwrap_or("unknown"));
     println!("  ✓ DISTRO:  {}", env.get_distro().unwrap_or("unknown"));
     println!("  ✓ Layers:  {}", env.layers.len());
+    if !multiconfigs.is_empty() {
+        println!("  ✓ BBMULTICONFIG: {}", multiconfigs.join(" "));
+    }
     println!();
 
+    if !mc_target.is_default() && !multiconfigs.contains(&mc_target.mc) {
+        return Err(format!(
+            "Multiconfig '{}' is not listed in BBMULTICONFIG",
+            mc_target.mc
+        ).into());
+    }
+
     // ========== Build Orchestration ==========
     println!("🎼 Building execution plan with BuildOrchestrator...");
 
-    let config = OrchestratorConfig {
-        build_dir: build_dir.to_path_buf(),
-        machine: env.get_machine().map(|s| s.to_string()),
-        distro: env.get_distro().map(|s| s.to_string()),
-        max_io_parallelism: 32,
-        max_cpu_parallelism: num_cpus::get(),
-    };
-
-    let orchestrator = BuildOrchestrator::new(config);
-
     // Create layer paths map
     let mut layer_paths: HashMap<String, Vec<std::path::PathBuf>> = HashMap::new();
     for (i, layer) in env.layers.iter().enumerate() {
@@ -127,7 +260,20 @@ pub async fn execute(
         layer_paths.insert(layer_name, vec![layer.clone()]);
     }
 
-    let build_plan = orchestrator.build_plan(layer_paths).await?;
+    let mut plans: HashMap<String, ConfigPlan> = HashMap::new();
+    for mc in std::iter::once(String::new()).chain(multiconfigs.iter().cloned()) {
+        let config_plan = plan_config(&env, build_dir, &mc, &layer_paths).await?;
+        if !mc.is_empty() {
+            println!("  ✓ mc:{} (MACHINE: {}, DISTRO: {}): {} recipes",
+                mc,
+                config_plan.machine,
+                config_plan.distro,
+                config_plan.plan.recipe_graph.recipe_count()
+            );
+        }
+        plans.insert(mc, config_plan);
+    }
+    let build_plan = &plans[&mc_target.mc].plan;
 
     println!("  ✓ Recipes parsed: {}", build_plan.recipe_graph.recipe_count());
     println!("  ✓ Tasks available: {}", build_plan.task_graph.tasks.len());
@@ -171,9 +317,9 @@ pub async fn execute(
     }
 
     // ========== Find Target and Build Task Graph ==========
-    println!("🎯 Finding target recipe: {}", target);
-    let recipe_id = build_plan.recipe_graph.find_recipe(target)
-        .ok_or_else(|| format!("Recipe '{}' not found", target))?;
+    println!("🎯 Finding target recipe: {}", mc_target);
+    let recipe_id = build_plan.recipe_graph.find_recipe(&mc_target.target)
+        .ok_or_else(|| format!("Recipe '{}' not found", mc_target))?;
     let recipe = build_plan.recipe_graph.get_recipe(recipe_id)
         .ok_or_else(|| format!("Recipe not found in graph"))?;
 
@@ -190,39 +336,46 @@ pub async fn execute(
 
     // Find the target task
     // BitBake tasks are stored without the "do_" prefix in the graph
-    let target_task_name = "install";
+    let target_task_name = mc_target.task.as_deref().unwrap_or("install");
     let target_task = build_plan.task_graph.tasks.values()
         .find(|t| t.recipe_id == recipe_id && t.task_name == target_task_name)
         .ok_or_else(|| format!("Task {} not found for recipe", target_task_name))?;
+    let target_label = task_label(&mc_target.mc, &recipe.name, target_task_name);
 
     println!("  ✓ Target task: {}", target_task.task_name);
     println!();
 
     // ========== Build Execution Graph for Target ==========
-    println!("🔗 Building execution graph for {}:{}...", recipe.name, target_task_name);
-
-    let builder = TaskGraphBuilder::new(build_plan.recipe_graph.clone());
-    let exec_graph = builder.build_for_task(target_task.task_id)?;
-
-    println!("  ✓ Tasks in graph: {}", exec_graph.tasks.len());
-    println!("  ✓ Root tasks: {}", exec_graph.root_tasks.len());
+    println!("🔗 Building execution graph for {}...", target_label);
+
+    let recipe_graphs: HashMap<String, RecipeGraph> = plans
+        .iter()
+        .map(|(mc, config_plan)| (mc.clone(), config_plan.plan.recipe_graph.clone()))
+        .collect();
+    let exec_graph = TaskGraphBuilder::build_multiconfig(&recipe_graphs, &mc_target.mc, target_task.task_id)?;
+
+    println!("  ✓ Tasks in graph: {}", exec_graph.task_count());
+    println!("  ✓ Root tasks: {}", exec_graph.graphs.values().map(|g| g.root_tasks.len()).sum::<usize>());
+    if !exec_graph.mc_depends.is_empty() {
+        println!("  ✓ Multiconfig dependencies: {}", exec_graph.mc_depends.values().map(Vec::len).sum::<usize>());
+    }
     println!("  ✓ Execution order computed (topologically sorted)");
 
     // Debug: Show task dependency structure
     println!("\n  DEBUG: Task dependency structure:");
     for task_id in &exec_graph.execution_order {
-        if let Some(task) = exec_graph.tasks.get(task_id) {
-            print!("    - {}:{} (depends_on: {}",
-                task.recipe_name,
-                task.task_name,
-                task.depends_on.len()
+        if let Some(task) = exec_graph.get_task(task_id) {
+            let depends_on: Vec<McTaskId> = exec_graph.depends_on(task_id);
+            print!("    - {} (depends_on: {}",
+                task_label(&task_id.mc, &task.recipe_name, &task.task_name),
+                depends_on.len()
             );
-            if !task.depends_on.is_empty() {
+            if !depends_on.is_empty() {
                 print!(" [");
-                for (i, dep_id) in task.depends_on.iter().enumerate() {
-                    if let Some(dep_task) = exec_graph.tasks.get(dep_id) {
+                for (i, dep_id) in depends_on.iter().enumerate() {
+                    if let Some(dep_task) = exec_graph.get_task(dep_id) {
                         if i > 0 { print!(", "); }
-                        print!("{}:{}", dep_task.recipe_name, dep_task.task_name);
+                        print!("{}", task_label(&dep_id.mc, &dep_task.recipe_name, &dep_task.task_name));
                     }
                 }
                 print!("]");
@@ -239,71 +392,41 @@ pub async fn execute(
     let cache_dir = build_dir.join("hitzeleiter-cache");
     let mut executor = TaskExecutor::new(&cache_dir)?;
 
+    // SSTATE_DIR and SSTATE_MIRRORS act as a cache tier behind the action cache
+    let sstate_mirrors = env.local_config.variables.get("SSTATE_MIRRORS").map(String::as_str);
+    let sstate = SstateMirror::new(&env.sstate_dir, sstate_mirrors);
+    println!("  ✓ sstate tier: {:?} (+{} mirrors)", sstate.sstate_dir, sstate.mirrors.len());
+    if export_sstate {
+        println!("  ✓ Exporting executed tasks to SSTATE_DIR");
+    }
+    executor.set_sstate(sstate, export_sstate);
+
     let mut completed = 0;
     let mut from_cache = 0;
     let mut failed = 0;
 
-    // Get machine and tmpdir for variable setup
-    let machine = env.get_machine().unwrap_or("unknown");
-    let tmpdir = build_dir.join("tmp");
-
-    for &task_id in &exec_graph.execution_order {
-        if let Some(exec_task) = exec_graph.tasks.get(&task_id) {
+    for task_id in &exec_graph.execution_order {
+        if let Some(exec_task) = exec_graph.get_task(task_id) {
+            // MACHINE, DISTRO and TMPDIR come from the task's own configuration
+            let config_plan = &plans[&task_id.mc];
             let task_key = format!("{}:{}", exec_task.recipe_name, exec_task.task_name);
+            let label = task_label(&task_id.mc, &exec_task.recipe_name, &exec_task.task_name);
 
-            if let Some(spec) = build_plan.task_specs.get(&task_key) {
-                println!("  Executing: {}", task_key);
+            if let Some(spec) = config_plan.plan.task_specs.get(&task_key) {
+                println!("  Executing: {}", label);
 
                 // Enrich task spec with BitBake variables
-                let mut enriched_spec = spec.clone();
-
-                // Get recipe version
-                let recipe_version = build_plan.recipe_graph.get_recipe(exec_task.recipe_id)
+                let recipe_version = config_plan.plan.recipe_graph.get_recipe(exec_task.recipe_id)
                     .and_then(|r| r.version.clone())
                     .unwrap_or_else(|| "unknown".to_string());
-
-                // Setup BitBake variables
-                let mut bb_vars = HashMap::new();
-                bb_vars.insert("PN".to_string(), exec_task.recipe_name.clone());
-                bb_vars.insert("PV".to_string(), recipe_version.clone());
-                bb_vars.insert("MACHINE".to_string(), machine.to_string());
-                bb_vars.insert("DISTRO".to_string(), env.get_distro().unwrap_or("unknown").to_string());
-
-                // Work directories
-                let work_base = tmpdir.join("work").join(machine).join(&exec_task.recipe_name).join(&recipe_version);
-                let s_dir = work_base.join(format!("{}-{}", exec_task.recipe_name, recipe_version));
-                let b_dir = work_base.join("build");
-                let d_dir = work_base.join("image");
-
-                std::fs::create_dir_all(&work_base).ok();
-                std::fs::create_dir_all(&s_dir).ok();
-                std::fs::create_dir_all(&b_dir).ok();
-                std::fs::create_dir_all(&d_dir).ok();
-
-                bb_vars.insert("WORKDIR".to_string(), work_base.to_string_lossy().to_string());
-                bb_vars.insert("S".to_string(), s_dir.to_string_lossy().to_string());
-                bb_vars.insert("B".to_string(), b_dir.to_string_lossy().to_string());
-                bb_vars.insert("D".to_string(), d_dir.to_string_lossy().to_string());
-
-                // System directories
-                bb_vars.insert("sysconfdir".to_string(), "/etc".to_string());
-                bb_vars.insert("bindir".to_string(), "/usr/bin".to_string());
-                bb_vars.insert("sbindir".to_string(), "/usr/sbin".to_string());
-                bb_vars.insert("libdir".to_string(), "/usr/lib".to_string());
-                bb_vars.insert("includedir".to_string(), "/usr/include".to_string());
-                bb_vars.insert("datadir".to_string(), "/usr/share".to_string());
-                bb_vars.insert("mandir".to_string(), "/usr/share/man".to_string());
-                bb_vars.insert("docdir".to_string(), "/usr/share/doc".to_string());
-                bb_vars.insert("infodir".to_string(), "/usr/share/info".to_string());
-                bb_vars.insert("localstatedir".to_string(), "/var".to_string());
-                bb_vars.insert("base_bindir".to_string(), "/bin".to_string());
-                bb_vars.insert("base_sbindir".to_string(), "/sbin".to_string());
-                bb_vars.insert("base_libdir".to_string(), "/lib".to_string());
-                bb_vars.insert("bindir_crossscripts".to_string(), "/usr/bin/crossscripts".to_string());
-
-                enriched_spec.env = bb_vars;
-                enriched_spec.workdir = work_base;
-
+                let enriched_spec = enrich_task_spec(
+                    spec,
+                    &exec_task.recipe_name,
+                    &recipe_version,
+                    &config_plan.machine,
+                    &config_plan.distro,
+                    &config_plan.tmpdir,
+                );
                 match executor.execute_task(enriched_spec) {
                     Ok(output) => {
                         if output.exit_code == 0 {
@@ -340,7 +463,7 @@ pub async fn execute(
                     }
                 }
             } else {
-                println!("  ⚠ No TaskSpec for {}, skipping", task_key);
+                println!("  ⚠ No TaskSpec for {}, skipping", label);
             }
         }
     }
@@ -353,6 +476,9 @@ pub async fn execute(
     println!("📊 Build Statistics:");
     println!("  Tasks completed:  {}", completed);
     println!("  From cache:       {}", from_cache);
+    if exec_stats.sstate_hits > 0 {
+        println!("    via sstate:     {}", exec_stats.sstate_hits);
+    }
     println!("  Failed:           {}", failed);
     if exec_stats.tasks_executed > 0 {
         println!("  Cache hit rate:   {:.1}%", exec_stats.cache_hit_rate() * 100.0);
@@ -363,12 +489,15 @@ pub async fn execute(
     let total_duration = start_time.elapsed();
 
     if failed == 0 {
+        // Pin this build's outputs so cache GC keeps them restorable
+        executor.record_successful_build(target)?;
+
         println!("╔════════════════════════════════════════════════════════╗");
         println!("║                  BUILD SUCCESSFUL! ✅                  ║");
         println!("╚════════════════════════════════════════════════════════╝");
         println!();
         println!("Total build time: {:.2}s", total_duration.as_secs_f64());
-        println!("Target: {}:{}", recipe.name, target_task_name);
+        println!("Target: {}", target_label);
         println!();
         Ok(())
     } else {
@@ -1,7 +1,11 @@
 //! Cache cleaning and management commands
 
-use convenient_bitbake::executor::CacheManager;
+use convenient_bitbake::executor::{CacheManager, GcOptions};
+use convenient_bitbake::ContentHash;
+use convenient_cache::{BazelRemoteCache, CacheError};
+use std::collections::HashMap;
 use std::path::Path;
+use std::time::SystemTime;
 
 /// Clean build cache (removes action cache, keeps CAS)
 pub fn clean(
@@ -120,27 +124,198 @@ pub fn info(
     println!("  hitzeleiter clean       - Remove action cache (keeps CAS)");
     println!("  hitzeleiter clean --all - Remove everything (expunge)");
     println!("  hitzeleiter cache gc    - Garbage collect unused objects");
+    println!("  hitzeleiter cache gc --max-size 200G - Also evict least recently used objects");
+    println!("  hitzeleiter cache verify - Check integrity (--repair to fix)");
 
     Ok(())
 }
 
+/// Parse a human-readable size such as "200G", "512M" or "1.5T" into bytes
+///
+/// Suffixes are binary (K = 1024) and may be followed by "B" or "iB".
+fn parse_size(size: &str) -> Result<u64, String> {
+    let trimmed = size.trim();
+    let upper = trimmed.to_ascii_uppercase();
+    let upper = upper
+        .strip_suffix("IB")
+        .or_else(|| upper.strip_suffix('B'))
+        .unwrap_or(&upper);
+
+    let (number, multiplier) = match upper.chars().last() {
+        Some('K') => (&upper[..upper.len() - 1], 1u64 << 10),
+        Some('M') => (&upper[..upper.len() - 1], 1u64 << 20),
+        Some('G') => (&upper[..upper.len() - 1], 1u64 << 30),
+        Some('T') => (&upper[..upper.len() - 1], 1u64 << 40),
+        _ => (upper, 1),
+    };
+
+    let value: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| format!("Invalid size '{}' (expected e.g. 200G, 512M)", size))?;
+    if value < 0.0 {
+        return Err(format!("Invalid size '{}': must not be negative", size));
+    }
+
+    Ok((value * multiplier as f64) as u64)
+}
+
 /// Garbage collect unreferenced cache objects
+///
+/// With `max_size`, least recently used objects are evicted until the CAS
+/// fits; outputs of the last `keep_builds` successful builds are never evicted.
+/// Records of older builds are pruned.
 pub fn gc(
     build_dir: &Path,
+    max_size: Option<&str>,
+    keep_builds: usize,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     println!("♻️  Running garbage collection...");
     println!();
 
+    let options = GcOptions {
+        max_size_bytes: max_size.map(parse_size).transpose()?,
+        keep_builds,
+        ..GcOptions::default()
+    };
+
     let cache_dir = build_dir.join("hitzeleiter-cache");
     let manager = CacheManager::new(&cache_dir);
 
-    let stats = manager.gc()?;
+    let stats = manager.gc(&options)?;
 
     println!("✅ Garbage collection complete!");
     println!();
     println!("Removed:");
-    println!("  Objects:     {}", stats.objects_removed);
-    println!("  Space freed: {:.1} MB", stats.bytes_freed as f64 / 1_000_000.0);
+    println!("  Objects:      {}", stats.objects_removed);
+    println!("  Unreferenced: {}", stats.unreferenced_removed);
+    println!("  LRU evicted:  {}", stats.evicted.len());
+    println!("  Old builds:   {}", stats.builds_pruned);
+    println!("  Space freed:  {:.1} MB", stats.bytes_freed as f64 / 1_000_000.0);
+    println!();
+    println!("Kept:");
+    println!("  Pinned:       {} (last {} builds)", stats.pinned_objects, keep_builds);
+    println!("  Cache size:   {:.1} MB", stats.remaining_bytes as f64 / 1_000_000.0);
+
+    if !stats.evicted.is_empty() {
+        const SHOWN: usize = 20;
+        let now = SystemTime::now();
+
+        println!();
+        println!("Evicted (least recently used first):");
+        for object in stats.evicted.iter().take(SHOWN) {
+            let idle_days = now
+                .duration_since(object.last_access_time)
+                .map(|d| d.as_secs() / 86_400)
+                .unwrap_or(0);
+            println!(
+                "  {}  {:>10.1} KB  last used {} days ago",
+                object.hash,
+                object.size_bytes as f64 / 1024.0,
+                idle_days
+            );
+        }
+        if stats.evicted.len() > SHOWN {
+            println!("  ... and {} more", stats.evicted.len() - SHOWN);
+        }
+    }
+
+    Ok(())
+}
+
+/// Verify cache integrity, optionally repairing from a remote cache
+pub async fn verify(
+    build_dir: &Path,
+    repair: bool,
+    remote_cache: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    println!("🔍 Verifying cache integrity...");
+    println!();
+
+    let cache_dir = build_dir.join("hitzeleiter-cache");
+    if !cache_dir.exists() {
+        println!("No cache found at: {:?}", cache_dir);
+        return Ok(());
+    }
+
+    let manager = CacheManager::new(&cache_dir);
+    let report = manager.verify()?;
+
+    println!("Checked:");
+    println!("  CAS objects:          {}", report.objects_checked);
+    println!("  Action cache entries: {}", report.actions_checked);
+    println!();
+
+    if report.is_clean() {
+        println!("✅ Cache is consistent");
+        return Ok(());
+    }
+
+    println!("Problems found:");
+    println!("  Corrupt CAS objects:     {}", report.corrupt_objects.len());
+    for hash in &report.corrupt_objects {
+        println!("    ✗ {}", hash.as_str());
+    }
+    println!("  Leftover temp files:     {}", report.temp_files.len());
+    println!("  Unreadable action files: {}", report.corrupt_actions.len());
+    println!("  Actions missing outputs: {}", report.broken_actions.len());
+    for action in &report.broken_actions {
+        for (path, hash) in &action.missing {
+            println!("    ✗ {} → {} ({})", action.signature, path.display(), hash);
+        }
+    }
+    println!();
+    println!("Quarantined to: {:?}", cache_dir.join("quarantine"));
+
+    if !repair {
+        println!();
+        println!("💡 Use 'hitzeleiter cache verify --repair' to re-fetch or invalidate broken entries");
+        return Ok(());
+    }
+
+    println!();
+    println!("🔧 Repairing...");
+
+    let mut fetched = HashMap::new();
+    let missing = report.missing_blobs();
+    match remote_cache {
+        Some(url) => {
+            let remote = BazelRemoteCache::new(url)?;
+            for hash in &missing {
+                let remote_hash = convenient_cache::ContentHash::new(hash.to_hex())?;
+                match remote.get_blob(&remote_hash).await {
+                    Ok(data) => {
+                        fetched.insert(hash.clone(), data);
+                    }
+                    Err(CacheError::NotFound(_)) => {
+                        println!("  ⚠ {} not in remote cache", hash);
+                    }
+                    Err(e) => {
+                        println!("  ⚠ Failed to fetch {}: {}", hash, e);
+                    }
+                }
+            }
+            println!("  Fetched {}/{} blobs from {}", fetched.len(), missing.len(), url);
+        }
+        None if !missing.is_empty() => {
+            println!("  No --remote-cache given; broken actions will be invalidated");
+        }
+        None => {}
+    }
+
+    let stats = manager.repair(&report, fetched)?;
+
+    println!();
+    println!("✅ Repair complete!");
+    println!("  Blobs restored:      {}", stats.restored);
+    if stats.rejected > 0 {
+        println!("  Blobs rejected:      {} (remote content didn't match hash)", stats.rejected);
+    }
+    println!("  Actions invalidated: {}", stats.invalidated.len());
+    if !stats.invalidated.is_empty() {
+        println!();
+        println!("💡 Invalidated tasks will re-run on the next build");
+    }
 
     Ok(())
 }
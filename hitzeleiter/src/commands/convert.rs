@@ -0,0 +1,646 @@
+//! Conversion between Google repo manifests and KAS configuration files
+//!
+//! `*.xml` input is read as a repo manifest and written as a KAS file: every
+//! project becomes a repository with its url, revision and path, and its
+//! layers are those the `bblayers.conf.sample` template of a synced workspace
+//! lists, or for projects it leaves out, those with a `conf/layer.conf`.
+//! Any other input is read as a KAS file (including its lock file) and written
+//! as a manifest pinned to the locked commits. Layers travel as a `kas-layers`
+//! annotation, so converting back keeps them. Everything else that has no
+//! counterpart in the target format is reported instead of silently dropped.
+
+use super::repo::origin_url;
+use convenient_kas::{
+    KasConfig, KasRepo, dump,
+    include_graph::{KAS_FORMAT_VERSION, KasIncludeGraph, KasLayer, KasRepoType},
+};
+use convenient_repo::{
+    Annotation, Manifest, Project, Remote, find_layers, layer_paths, oe_root, parse_groups,
+    template_layers,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+/// Annotation holding the comma separated KAS layers of a project
+const LAYERS_ANNOTATION: &str = "kas-layers";
+
+/// Convert `input` to the other format, writing it to `output` or stdout
+///
+/// `groups`, `manifest_url` and `workspace` apply to manifest input only: the
+/// group filter, the url relative `fetch` urls resolve against (default: the
+/// manifest repository's origin), and the directory the projects are checked
+/// out in, whose `oe-init-build-env` project's template lists the layers
+/// (projects it does not list are searched for `conf/layer.conf`).
+pub async fn execute(
+    input: &Path,
+    output: Option<&Path>,
+    groups: &str,
+    manifest_url: Option<&str>,
+    workspace: Option<&Path>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut report = Vec::new();
+    let converted = if input.extension().is_some_and(|ext| ext == "xml") {
+        let mut manifest = Manifest::load(input)?;
+        manifest.retain_groups(&parse_groups(groups));
+        let manifest_url = manifest_url.map(str::to_string).or_else(|| origin_url(input));
+        let config = manifest_to_kas(&manifest, manifest_url.as_deref(), workspace, &mut report)?;
+        eprintln!("Converted {} projects to KAS repos", config.repos.len());
+        dump::to_yaml(&config)
+    } else {
+        let config = KasIncludeGraph::build(input).await?.merge_config();
+        let manifest = kas_to_manifest(&config, &mut report);
+        eprintln!(
+            "Converted {} KAS repos to manifest projects",
+            manifest.project.as_ref().map_or(0, Vec::len)
+        );
+        format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n{}\n", convenient_repo::to_string(&manifest).trim())
+    };
+
+    for line in &report {
+        eprintln!("⚠️  {}", line);
+    }
+    match output {
+        Some(path) => {
+            std::fs::write(path, converted)?;
+            eprintln!("Wrote {}", path.display());
+        }
+        None => print!("{}", converted),
+    }
+    Ok(())
+}
+
+/// KAS configuration with one repository per project of a resolved manifest
+fn manifest_to_kas(
+    manifest: &Manifest,
+    manifest_url: Option<&str>,
+    workspace: Option<&Path>,
+    report: &mut Vec<String>,
+) -> Result<KasConfig, convenient_repo::RepoError> {
+    let projects: Vec<&Project> = manifest.project.iter().flatten().collect();
+
+    // Layers of the checked out projects, by project name
+    let mut layers = None;
+    if let Some(workspace) = workspace {
+        let checkouts: BTreeMap<String, PathBuf> = projects
+            .iter()
+            .map(|p| (p.name.clone(), workspace.join(p.checkout_path())))
+            .collect();
+        let listed = match oe_root(&checkouts) {
+            Some(root) => layer_paths(&checkouts, &template_layers(root, None)?)?,
+            None => {
+                report.push("no project in the workspace has oe-init-build-env to list the layers".to_string());
+                HashMap::new()
+            }
+        };
+        layers = Some((listed, checkouts));
+    }
+
+    let mut config = KasConfig::default();
+    config.header.version = KAS_FORMAT_VERSION;
+    let mut grouped = 0;
+    let mut undiscovered = 0;
+
+    for project in projects {
+        let path = project.checkout_path();
+        let id = unique_id(path, &config.repos);
+        let mut repo = KasRepo {
+            url: Some(manifest.fetch_url(project, manifest_url)?),
+            path: (id != path).then(|| path.to_string()),
+            ..Default::default()
+        };
+
+        match manifest.project_revision(project) {
+            Some(revision) if is_commit(&revision) => {
+                repo.commit = Some(revision);
+                let upstream = project.upstream.as_deref().or(project.dest_branch.as_deref());
+                if let Some(tag) = upstream.and_then(|u| u.strip_prefix("refs/tags/")) {
+                    repo.tag = Some(tag.to_string());
+                } else if let Some(branch) = upstream {
+                    repo.branch = Some(branch.trim_start_matches("refs/heads/").to_string());
+                }
+            }
+            Some(revision) if revision.starts_with("refs/tags/") => {
+                repo.tag = Some(revision.trim_start_matches("refs/tags/").to_string());
+            }
+            Some(revision) if revision.starts_with("refs/") && !revision.starts_with("refs/heads/") => {
+                report.push(format!("{}: revision {} is kept as a deprecated refspec", project.name, revision));
+                repo.refspec = Some(revision);
+            }
+            Some(revision) => repo.branch = Some(revision.trim_start_matches("refs/heads/").to_string()),
+            None => {}
+        }
+
+        let annotated = project
+            .annotation
+            .iter()
+            .flatten()
+            .find(|a| a.name == LAYERS_ANNOTATION)
+            .map(|a| parse_groups(&a.value));
+        match (annotated, &layers) {
+            (Some(names), _) => repo.layers = names.into_iter().map(|name| (name, KasLayer::default())).collect(),
+            (None, Some((layers, checkouts))) => {
+                let dir = &checkouts[&project.name];
+                // Projects the template does not list fall back to their conf/layer.conf
+                let found = match layers.get(&project.name) {
+                    Some(listed) => listed.clone(),
+                    None => {
+                        let found = find_layers(checkouts, &project.name);
+                        if !dir.exists() {
+                            report.push(format!("{}: not checked out in the workspace, no layers added", project.name));
+                        } else if found.is_empty() {
+                            report.push(format!("{}: has no conf/layer.conf, no layers added", project.name));
+                        } else {
+                            report.push(format!(
+                                "{}: not in the template, added the layers with a conf/layer.conf",
+                                project.name
+                            ));
+                        }
+                        found
+                    }
+                };
+                repo.layers = if found.is_empty() {
+                    // A disabled root entry keeps the repository from being a layer itself
+                    HashMap::from([(".".to_string(), KasLayer { path: None, disabled: true })])
+                } else {
+                    // The root is listed as "." rather than left implicit
+                    found
+                        .iter()
+                        .map(|layer| {
+                            let name = layer.strip_prefix(dir).unwrap_or(layer).display().to_string();
+                            (if name.is_empty() { ".".to_string() } else { name }, KasLayer::default())
+                        })
+                        .collect()
+                };
+            }
+            (None, None) => undiscovered += 1,
+        }
+
+        if project.groups.as_deref().is_some_and(|g| !g.is_empty()) {
+            grouped += 1;
+        }
+        for copy in project.copyfile.iter().flatten() {
+            report.push(format!("{}: copyfile {} -> {} has no KAS equivalent", project.name, copy.src, copy.dest));
+        }
+        for link in project.linkfile.iter().flatten() {
+            report.push(format!("{}: linkfile {} -> {} has no KAS equivalent", project.name, link.src, link.dest));
+        }
+        if project.clone_depth.is_some() {
+            report.push(format!("{}: clone-depth has no KAS equivalent", project.name));
+        }
+        config.repos.insert(id, repo);
+    }
+
+    if undiscovered > 0 {
+        report.push(format!(
+            "layers of {} projects were not discovered, each is its own layer; pass --workspace with a synced checkout",
+            undiscovered
+        ));
+    }
+    if grouped > 0 {
+        report.push(format!("groups of {} projects are dropped, KAS has no groups (select them with -g)", grouped));
+    }
+    if manifest.repo_hooks.as_ref().is_some_and(|hooks| !hooks.is_empty()) {
+        report.push("repo-hooks have no KAS equivalent".to_string());
+    }
+    Ok(config)
+}
+
+/// Manifest with one project per KAS repository, pinned to its commit
+fn kas_to_manifest(config: &KasConfig, report: &mut Vec<String>) -> Manifest {
+    // Top level keys other than the repositories have no place in a manifest
+    if let Ok(serde_json::Value::Object(keys)) = serde_json::to_value(config) {
+        for key in keys.keys() {
+            if !["header", "repos", "defaults", "overrides"].contains(&key.as_str()) {
+                report.push(format!("{} has no repo manifest equivalent", key));
+            }
+        }
+    }
+
+    let mut remotes: Vec<Remote> = Vec::new();
+    let mut projects = Vec::new();
+    let repos: BTreeMap<String, KasRepo> = config.effective_repos().into_iter().collect();
+
+    for (id, repo) in repos {
+        let Some(url) = &repo.url else {
+            report.push(format!("{}: local repository without url is left out", id));
+            continue;
+        };
+        if repo.repo_type == Some(KasRepoType::Hg) {
+            report.push(format!("{}: mercurial repositories are not supported by repo", id));
+            continue;
+        }
+        let Some((fetch, name)) = url.trim_end_matches('/').rsplit_once('/') else {
+            report.push(format!("{}: url {} has no path to split into remote and project", id, url));
+            continue;
+        };
+
+        let remote = match remotes.iter().find(|r| r.fetch.as_deref() == Some(fetch)) {
+            Some(remote) => remote.name.clone(),
+            None => {
+                let host = remote_name(fetch);
+                let mut name = host.clone();
+                let mut n = 1;
+                while remotes.iter().any(|r| r.name == name) {
+                    n += 1;
+                    name = format!("{}-{}", host, n);
+                }
+                remotes.push(Remote {
+                    name: name.clone(),
+                    fetch: Some(fetch.to_string()),
+                    ..Default::default()
+                });
+                name
+            }
+        };
+
+        let reference = repo
+            .tag
+            .as_ref()
+            .map(|tag| format!("refs/tags/{}", tag))
+            .or_else(|| repo.branch.clone())
+            .or_else(|| repo.refspec.clone());
+        let (revision, upstream) = match (&repo.commit, reference) {
+            (Some(commit), reference) => (Some(commit.clone()), reference),
+            (None, Some(reference)) => {
+                report.push(format!("{}: not locked, follows {} (run `hitzeleiter kas lock` first)", id, reference));
+                (Some(reference), None)
+            }
+            (None, None) => {
+                report.push(format!("{}: not locked, follows the remote HEAD", id));
+                (None, None)
+            }
+        };
+
+        let mut layers: Vec<&String> = repo.enabled_layers().map(|(name, _)| name).collect();
+        layers.sort();
+        if repo.layers.values().any(|layer| layer.path.is_some()) {
+            report.push(format!("{}: custom layer paths are kept by layer name only", id));
+        }
+        if let Some(patches) = &repo.patches {
+            let mut ids: Vec<&String> = patches.keys().collect();
+            ids.sort();
+            for patch in ids {
+                report.push(format!("{}: patch {} cannot be applied by repo", id, patch));
+            }
+        }
+        if repo.signed == Some(true) {
+            report.push(format!("{}: signature verification is not supported by repo", id));
+        }
+
+        projects.push(Project {
+            name: name.to_string(),
+            path: Some(repo.path.clone().unwrap_or_else(|| repo.name.clone().unwrap_or_else(|| id.clone()))),
+            remote: Some(remote),
+            revision,
+            upstream,
+            annotation: (!layers.is_empty()).then(|| {
+                vec![Annotation {
+                    name: LAYERS_ANNOTATION.to_string(),
+                    value: layers.iter().map(|l| l.as_str()).collect::<Vec<_>>().join(","),
+                    keep: None,
+                }]
+            }),
+            ..Default::default()
+        });
+    }
+
+    Manifest {
+        remote: (!remotes.is_empty()).then_some(remotes),
+        project: Some(projects),
+        ..Default::default()
+    }
+}
+
+/// Repository id for a project path, unique among `repos`
+fn unique_id(path: &str, repos: &HashMap<String, KasRepo>) -> String {
+    let base = path.rsplit('/').next().unwrap_or(path);
+    if !repos.contains_key(base) {
+        return base.to_string();
+    }
+    path.replace('/', "-")
+}
+
+/// Remote name for a fetch url: its host
+fn remote_name(fetch: &str) -> String {
+    let rest = fetch.split_once("://").map_or(fetch, |(_, rest)| rest);
+    let rest = rest.split_once('@').map_or(rest, |(_, rest)| rest);
+    let host = rest.split([':', '/']).next().unwrap_or(rest);
+    if host.is_empty() { "origin".to_string() } else { host.to_string() }
+}
+
+fn is_commit(revision: &str) -> bool {
+    matches!(revision.len(), 40 | 64) && revision.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use convenient_kas::include_graph::KasPatch;
+
+    const POKY_COMMIT: &str = "2f1ff8b8b5e4f5f1d3c2b4a6e8f0a1b2c3d4e5f6";
+    const OE_COMMIT: &str = "0123456789abcdef0123456789abcdef01234567";
+
+    fn manifest(xml: &str) -> Manifest {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("default.xml");
+        std::fs::write(&path, xml).unwrap();
+        Manifest::load(&path).unwrap()
+    }
+
+    fn write(path: &Path, contents: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn test_manifest_to_kas_revisions() {
+        let manifest = manifest(&format!(
+            r#"<manifest>
+  <remote name="yocto" fetch="https://git.yoctoproject.org"/>
+  <remote name="github" fetch="https://github.com/openembedded"/>
+  <default remote="yocto" revision="main"/>
+  <project name="poky" path="layers/poky" revision="{POKY_COMMIT}" upstream="refs/tags/yocto-5.0"/>
+  <project name="meta-openembedded" remote="github" revision="{OE_COMMIT}" dest-branch="refs/heads/scarthgap"/>
+  <project name="meta-tagged" revision="refs/tags/v1.2"/>
+  <project name="meta-review" revision="refs/changes/12/34/5"/>
+  <project name="meta-branch" revision="refs/heads/kirkstone"/>
+  <project name="meta-default"/>
+</manifest>"#
+        ));
+        let mut report = Vec::new();
+        let config = manifest_to_kas(&manifest, None, None, &mut report).unwrap();
+        assert_eq!(config.header.version, KAS_FORMAT_VERSION);
+
+        let poky = &config.repos["poky"];
+        assert_eq!(poky.url.as_deref(), Some("https://git.yoctoproject.org/poky"));
+        assert_eq!(poky.path.as_deref(), Some("layers/poky"));
+        assert_eq!(poky.commit.as_deref(), Some(POKY_COMMIT));
+        assert_eq!(poky.tag.as_deref(), Some("yocto-5.0"));
+        assert_eq!(poky.branch, None);
+
+        let oe = &config.repos["meta-openembedded"];
+        assert_eq!(oe.url.as_deref(), Some("https://github.com/openembedded/meta-openembedded"));
+        assert_eq!(oe.path, None);
+        assert_eq!(oe.commit.as_deref(), Some(OE_COMMIT));
+        assert_eq!(oe.branch.as_deref(), Some("scarthgap"));
+
+        assert_eq!(config.repos["meta-tagged"].tag.as_deref(), Some("v1.2"));
+        assert_eq!(config.repos["meta-tagged"].commit, None);
+        assert_eq!(config.repos["meta-review"].refspec.as_deref(), Some("refs/changes/12/34/5"));
+        assert_eq!(config.repos["meta-branch"].branch.as_deref(), Some("kirkstone"));
+        assert_eq!(config.repos["meta-default"].branch.as_deref(), Some("main"));
+
+        assert!(report.contains(&"meta-review: revision refs/changes/12/34/5 is kept as a deprecated refspec".to_string()));
+        assert!(report.iter().any(|line| line.starts_with("layers of 6 projects were not discovered")));
+    }
+
+    #[test]
+    fn test_manifest_to_kas_reports_lost_elements() {
+        let manifest = manifest(
+            r#"<manifest>
+  <remote name="origin" fetch="https://example.com/git"/>
+  <default remote="origin" revision="main"/>
+  <repo-hooks in-project="tools" enabled-list="pre-upload"/>
+  <project name="tools" groups="notdefault,tools" clone-depth="1">
+    <copyfile src="Makefile" dest="Makefile"/>
+    <linkfile src="scripts" dest="scripts"/>
+  </project>
+</manifest>"#,
+        );
+        let mut report = Vec::new();
+        manifest_to_kas(&manifest, None, None, &mut report).unwrap();
+
+        for expected in [
+            "tools: copyfile Makefile -> Makefile has no KAS equivalent",
+            "tools: linkfile scripts -> scripts has no KAS equivalent",
+            "tools: clone-depth has no KAS equivalent",
+            "groups of 1 projects are dropped, KAS has no groups (select them with -g)",
+            "repo-hooks have no KAS equivalent",
+        ] {
+            assert!(report.contains(&expected.to_string()), "missing {expected:?} in {report:?}");
+        }
+    }
+
+    #[test]
+    fn test_manifest_to_kas_unique_ids() {
+        let manifest = manifest(
+            r#"<manifest>
+  <remote name="origin" fetch="https://example.com"/>
+  <default remote="origin" revision="main"/>
+  <project name="poky" path="layers/poky"/>
+  <project name="vendor/poky" path="vendor/poky"/>
+</manifest>"#,
+        );
+        let config = manifest_to_kas(&manifest, None, None, &mut Vec::new()).unwrap();
+        assert_eq!(config.repos["poky"].path.as_deref(), Some("layers/poky"));
+        assert_eq!(config.repos["vendor-poky"].path.as_deref(), Some("vendor/poky"));
+        assert_eq!(config.repos["vendor-poky"].url.as_deref(), Some("https://example.com/vendor/poky"));
+
+        let mut repos = HashMap::new();
+        assert_eq!(unique_id("meta-oe", &repos), "meta-oe");
+        repos.insert("meta-oe".to_string(), KasRepo::default());
+        assert_eq!(unique_id("layers/meta-oe", &repos), "layers-meta-oe");
+    }
+
+    #[test]
+    fn test_manifest_to_kas_template_layers() {
+        let workspace = tempfile::tempdir().unwrap();
+        let poky = workspace.path().join("poky");
+        write(&poky.join("oe-init-build-env"), "");
+        write(&poky.join("meta/conf/layer.conf"), "");
+        write(&poky.join("meta-skeleton/conf/layer.conf"), "");
+        write(
+            &poky.join("meta/conf/templates/default/bblayers.conf.sample"),
+            "BBLAYERS ?= \" \\\n  ##OEROOT##/meta \\\n  ##OEROOT##/../meta-bsp \\\n  \"\n",
+        );
+        write(&workspace.path().join("meta-bsp/conf/layer.conf"), "");
+        write(&workspace.path().join("meta-extra/meta-a/conf/layer.conf"), "");
+        write(&workspace.path().join("meta-extra/meta-b/conf/layer.conf"), "");
+        std::fs::create_dir_all(workspace.path().join("tools")).unwrap();
+
+        let manifest = manifest(
+            r#"<manifest>
+  <remote name="origin" fetch="https://example.com"/>
+  <default remote="origin" revision="main"/>
+  <project name="poky"/>
+  <project name="meta-bsp"/>
+  <project name="meta-extra"/>
+  <project name="tools"/>
+  <project name="missing"/>
+</manifest>"#,
+        );
+        let mut report = Vec::new();
+        let config = manifest_to_kas(&manifest, None, Some(workspace.path()), &mut report).unwrap();
+
+        // meta-skeleton has a layer.conf but is not in the template
+        let poky_layers: Vec<&String> = config.repos["poky"].layers.keys().collect();
+        assert_eq!(poky_layers, ["meta"]);
+        let bsp_layers: Vec<&String> = config.repos["meta-bsp"].layers.keys().collect();
+        assert_eq!(bsp_layers, ["."]);
+        // meta-extra is not in the template, its layers are found by their layer.conf
+        let mut extra_layers: Vec<&String> = config.repos["meta-extra"].layers.keys().collect();
+        extra_layers.sort();
+        assert_eq!(extra_layers, ["meta-a", "meta-b"]);
+        assert_eq!(config.repos["tools"].layers["."], KasLayer { path: None, disabled: true });
+        assert_eq!(config.repos["missing"].layers["."], KasLayer { path: None, disabled: true });
+        assert_eq!(
+            report,
+            [
+                "meta-extra: not in the template, added the layers with a conf/layer.conf",
+                "tools: has no conf/layer.conf, no layers added",
+                "missing: not checked out in the workspace, no layers added",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_kas_to_manifest() {
+        let mut config = KasConfig {
+            machine: Some("qemux86-64".to_string()),
+            ..Default::default()
+        };
+        config.repos.insert(
+            "poky".to_string(),
+            KasRepo {
+                url: Some("https://git.yoctoproject.org/poky".to_string()),
+                commit: Some(POKY_COMMIT.to_string()),
+                branch: Some("scarthgap".to_string()),
+                layers: HashMap::from([
+                    ("meta-poky".to_string(), KasLayer::default()),
+                    ("meta".to_string(), KasLayer::default()),
+                    ("meta-skeleton".to_string(), KasLayer { path: None, disabled: true }),
+                ]),
+                ..Default::default()
+            },
+        );
+        config.repos.insert(
+            "meta-bsp".to_string(),
+            KasRepo {
+                url: Some("https://github.com/vendor/meta-bsp".to_string()),
+                tag: Some("v1".to_string()),
+                path: Some("layers/bsp".to_string()),
+                signed: Some(true),
+                patches: Some(HashMap::from([(
+                    "fix".to_string(),
+                    Some(KasPatch { repo: None, path: "fix.patch".to_string() }),
+                )])),
+                layers: HashMap::from([(
+                    "bsp".to_string(),
+                    KasLayer { path: Some("meta-vendor".to_string()), disabled: false },
+                )]),
+                ..Default::default()
+            },
+        );
+        config.repos.insert(
+            "meta-other".to_string(),
+            KasRepo {
+                url: Some("https://github.com/other/meta-other.git".to_string()),
+                ..Default::default()
+            },
+        );
+        config.repos.insert("this".to_string(), KasRepo::default());
+        config.repos.insert(
+            "hg".to_string(),
+            KasRepo {
+                url: Some("https://hg.example.com/repo".to_string()),
+                repo_type: Some(KasRepoType::Hg),
+                ..Default::default()
+            },
+        );
+
+        let mut report = Vec::new();
+        let manifest = kas_to_manifest(&config, &mut report);
+
+        let remotes: Vec<(&str, &str)> = manifest
+            .remote
+            .iter()
+            .flatten()
+            .map(|r| (r.name.as_str(), r.fetch.as_deref().unwrap()))
+            .collect();
+        assert_eq!(
+            remotes,
+            [
+                ("github.com", "https://github.com/vendor"),
+                ("github.com-2", "https://github.com/other"),
+                ("git.yoctoproject.org", "https://git.yoctoproject.org"),
+            ]
+        );
+
+        let projects = manifest.project.unwrap();
+        let names: Vec<&str> = projects.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["meta-bsp", "meta-other.git", "poky"]);
+
+        let bsp = &projects[0];
+        assert_eq!(bsp.path.as_deref(), Some("layers/bsp"));
+        assert_eq!(bsp.revision.as_deref(), Some("refs/tags/v1"));
+        assert_eq!(bsp.upstream, None);
+
+        let other = &projects[1];
+        assert_eq!(other.path.as_deref(), Some("meta-other"));
+        assert_eq!(other.revision, None);
+
+        let poky = &projects[2];
+        assert_eq!(poky.remote.as_deref(), Some("git.yoctoproject.org"));
+        assert_eq!(poky.revision.as_deref(), Some(POKY_COMMIT));
+        assert_eq!(poky.upstream.as_deref(), Some("scarthgap"));
+        let annotation = &poky.annotation.as_ref().unwrap()[0];
+        assert_eq!((annotation.name.as_str(), annotation.value.as_str()), (LAYERS_ANNOTATION, "meta,meta-poky"));
+
+        for expected in [
+            "machine has no repo manifest equivalent",
+            "hg: mercurial repositories are not supported by repo",
+            "this: local repository without url is left out",
+            "meta-bsp: not locked, follows refs/tags/v1 (run `hitzeleiter kas lock` first)",
+            "meta-bsp: custom layer paths are kept by layer name only",
+            "meta-bsp: patch fix cannot be applied by repo",
+            "meta-bsp: signature verification is not supported by repo",
+            "meta-other: not locked, follows the remote HEAD",
+        ] {
+            assert!(report.contains(&expected.to_string()), "missing {expected:?} in {report:?}");
+        }
+        assert!(!report.iter().any(|line| line.starts_with("poky:")));
+    }
+
+    #[test]
+    fn test_kas_manifest_round_trip() {
+        let mut config = KasConfig::default();
+        config.repos.insert(
+            "poky".to_string(),
+            KasRepo {
+                url: Some("https://git.yoctoproject.org/poky".to_string()),
+                commit: Some(POKY_COMMIT.to_string()),
+                tag: Some("yocto-5.0".to_string()),
+                layers: HashMap::from([
+                    ("meta".to_string(), KasLayer::default()),
+                    ("meta-poky".to_string(), KasLayer::default()),
+                ]),
+                ..Default::default()
+            },
+        );
+        config.repos.insert(
+            "meta-oe".to_string(),
+            KasRepo {
+                url: Some("https://github.com/openembedded/meta-openembedded".to_string()),
+                commit: Some(OE_COMMIT.to_string()),
+                branch: Some("scarthgap".to_string()),
+                path: Some("layers/meta-oe".to_string()),
+                layers: HashMap::from([("meta-oe".to_string(), KasLayer::default())]),
+                ..Default::default()
+            },
+        );
+
+        let mut report = Vec::new();
+        let pinned = kas_to_manifest(&config, &mut report);
+        let back = manifest_to_kas(&manifest(&convenient_repo::to_string(&pinned)), None, None, &mut report).unwrap();
+        assert!(report.is_empty(), "{report:?}");
+        assert_eq!(back.repos, config.repos);
+    }
+
+    #[test]
+    fn test_remote_name() {
+        assert_eq!(remote_name("https://git.yoctoproject.org"), "git.yoctoproject.org");
+        assert_eq!(remote_name("ssh://git@github.com:22/org"), "github.com");
+        assert_eq!(remote_name("git@github.com:org"), "github.com");
+        assert_eq!(remote_name("file:///srv/git"), "origin");
+    }
+}
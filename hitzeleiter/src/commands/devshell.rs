@@ -0,0 +1,101 @@
+//! Devshell - interactive debug shell inside a task's sandbox
+//!
+//! Rebuilds the environment a task ran in (sandbox mounts, the sysroot the
+//! build staged, task environment plus BitBake helpers, namespaces) and
+//! spawns a shell there, like `bitbake -c devshell`.
+
+use convenient_bitbake::{BuildEnvironment, BuildOrchestrator, OrchestratorConfig};
+use convenient_bitbake::executor::{DevShell, DevShellOptions};
+
+use super::build::enrich_task_spec;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Task opened when the target does not name one
+const DEFAULT_TASK: &str = "compile";
+
+/// Split `<recipe>[:<task>]`, accepting task names with or without `do_`
+fn parse_target(target: &str) -> (&str, &str) {
+    let (recipe, task) = match target.split_once(':') {
+        Some((recipe, task)) if !task.is_empty() => (recipe, task),
+        Some((recipe, _)) => (recipe, DEFAULT_TASK),
+        None => (target, DEFAULT_TASK),
+    };
+    (recipe, task.strip_prefix("do_").unwrap_or(task))
+}
+
+/// Spawn a devshell for `<recipe>[:<task>]`
+pub async fn execute(
+    build_dir: &Path,
+    target: &str,
+    rerun: bool,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (recipe_name, task_name) = parse_target(target);
+
+    println!("🐚 Preparing devshell for {}:do_{}", recipe_name, task_name);
+
+    let env = BuildEnvironment::from_build_dir(build_dir)?;
+    let config = OrchestratorConfig {
+        build_dir: build_dir.to_path_buf(),
+        machine: env.get_machine().map(|s| s.to_string()),
+        distro: env.get_distro().map(|s| s.to_string()),
+        max_io_parallelism: 32,
+        max_cpu_parallelism: num_cpus::get(),
+    };
+
+    let mut layer_paths: HashMap<String, Vec<std::path::PathBuf>> = HashMap::new();
+    for (i, layer) in env.layers.iter().enumerate() {
+        layer_paths.insert(format!("layer_{}", i), vec![layer.clone()]);
+    }
+
+    let build_plan = BuildOrchestrator::new(config).build_plan(layer_paths).await?;
+
+    let recipe_id = build_plan.recipe_graph.find_recipe(recipe_name)
+        .ok_or_else(|| format!("Recipe '{}' not found", recipe_name))?;
+    let recipe = build_plan.recipe_graph.get_recipe(recipe_id)
+        .ok_or("Recipe not found in graph")?;
+    let task_key = format!("{}:{}", recipe.name, task_name);
+    let spec = build_plan.task_specs.get(&task_key)
+        .ok_or_else(|| format!("Task do_{} not found for recipe {}", task_name, recipe_name))?;
+
+    let machine = env.get_machine().unwrap_or("unknown");
+    let recipe_version = recipe.version.clone().unwrap_or_else(|| "unknown".to_string());
+    let spec = enrich_task_spec(
+        spec,
+        &recipe.name,
+        &recipe_version,
+        machine,
+        env.get_distro().unwrap_or("unknown"),
+        &build_dir.join("tmp"),
+    );
+
+    // The sysroot is the one `hitzeleiter build` stages do_populate_sysroot outputs into
+    let shell = DevShell::prepare(
+        &spec,
+        &build_dir.join("hitzeleiter-cache"),
+        DevShellOptions { rerun_task: rerun, ..DevShellOptions::default() },
+    )?;
+
+    println!("  ✓ Sandbox:  {}", shell.sandbox_root().display());
+    let sysroot = Path::new(&shell.env()["RECIPE_SYSROOT"]);
+    println!("  ✓ Sysroot:  {}", sysroot.display());
+    if !sysroot.read_dir().is_ok_and(|mut entries| entries.next().is_some()) {
+        println!("  ⚠ Sysroot is empty, build the recipe's dependencies first");
+    }
+    println!("  ✓ Env vars: {}", shell.env().len());
+    if rerun {
+        println!("  ✓ Task script will be re-run with set -x");
+    }
+    println!();
+    println!("Type 'exit' to leave the devshell.");
+    println!();
+
+    let exit_code = shell.spawn()?;
+
+    println!();
+    println!("Devshell exited ({}). Sandbox kept at {}", exit_code, shell.sandbox_root().display());
+    println!("💡 Use 'hitzeleiter clean --all' to remove sandboxes");
+
+    Ok(())
+}
+
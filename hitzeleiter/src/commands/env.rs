@@ -0,0 +1,92 @@
+//! Env command - show variable values with their history, like `bitbake -e`
+//!
+//! Parses the configuration (bblayers.conf, every layer.conf, bitbake.conf and
+//! whatever it includes), INHERIT classes, the recipe and its bbappends into a
+//! history-tracking datastore and prints the final values. Event handlers and
+//! anonymous python run in BitBake's parse order, so their changes show up in
+//! the history too.
+
+use convenient_bitbake::parse_lifecycle::{self, ParseEvent, ParseOutcome};
+use convenient_bitbake::provider_selection::{vercmp, version_matches};
+use convenient_bitbake::{BuildEnvironment, DataStore};
+use std::path::{Path, PathBuf};
+
+/// Print the environment of `recipe`, or only `variable` when given
+pub async fn execute(
+    build_dir: &Path,
+    recipe: &str,
+    variable: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let env = BuildEnvironment::from_build_dir(build_dir)?;
+    let context = env.create_build_context()?;
+
+    let mut d = env.parse_configuration(&context)?;
+    if let Err(e) = parse_lifecycle::fire_event(&mut d, ParseEvent::ConfigParsed) {
+        tracing::warn!("ConfigParsed handlers failed: {}", e);
+    }
+
+    let recipe_file = select_recipe(&context.find_recipes(), recipe, &d)
+        .ok_or_else(|| format!("Recipe '{}' not found", recipe))?;
+    let stem = recipe_file
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or(recipe);
+    let (pn, pv) = stem.split_once('_').unwrap_or((stem, "1.0"));
+
+    d.set_var("FILE", recipe_file.to_string_lossy());
+    d.set_var("PN", pn);
+    d.set_var("PV", pv);
+    d.inherit("base")?;
+    d.parse_file(&recipe_file)?;
+
+    let bbappends = context.bbappends_for_recipe(&recipe_file);
+    for bbappend in &bbappends {
+        d.parse_file(bbappend)?;
+    }
+    d.finalize()?;
+    let outcome = parse_lifecycle::finalize_recipe(&mut d).unwrap_or_else(|e| {
+        tracing::warn!("Anonymous python of {} failed: {}", recipe_file.display(), e);
+        ParseOutcome::Parsed
+    });
+
+    println!("# Recipe: {}", recipe_file.display());
+    for bbappend in &bbappends {
+        println!("# Append: {}", bbappend.display());
+    }
+    if let ParseOutcome::Skipped(reason) = &outcome {
+        println!("# Skipped: {}", reason);
+    }
+
+    match variable {
+        Some(name) => print!("{}", d.emit_var(name)),
+        None => print!("{}", d.emit()),
+    }
+
+    Ok(())
+}
+
+/// Pick the recipe file for `pn`: PREFERRED_VERSION if set, otherwise the highest version
+fn select_recipe(recipes: &[PathBuf], pn: &str, d: &DataStore) -> Option<PathBuf> {
+    let mut candidates: Vec<(&PathBuf, &str)> = recipes
+        .iter()
+        .filter(|p| p.extension().is_some_and(|e| e == "bb"))
+        .filter_map(|p| {
+            let stem = p.file_stem()?.to_str()?;
+            let (name, version) = stem.split_once('_').unwrap_or((stem, ""));
+            (name == pn).then_some((p, version))
+        })
+        .collect();
+
+    if let Some(preferred) = d
+        .get_var(&format!("PREFERRED_VERSION_{}", pn))
+        .or_else(|| d.get_var(&format!("PREFERRED_VERSION:{}", pn)))
+        && let Some((path, _)) = candidates
+            .iter()
+            .find(|(_, v)| version_matches(&preferred, v))
+    {
+        return Some((*path).clone());
+    }
+
+    candidates.sort_by(|a, b| vercmp(a.1, b.1));
+    candidates.last().map(|(path, _)| (*path).clone())
+}
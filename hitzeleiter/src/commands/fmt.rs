@@ -0,0 +1,73 @@
+//! Fmt command - reformat BitBake metadata in place
+//!
+//! Formatting is lossless: statements the formatter does not understand are
+//! kept byte for byte, and variables of .bb/.bbappend files are reordered to
+//! follow the OE style guide.
+
+use convenient_bitbake::formatter::{self, FormatOptions};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+const EXTENSIONS: &[&str] = &["bb", "bbappend", "bbclass", "inc"];
+
+/// Format `paths` (files or directories), or only report unformatted files with `check`
+pub async fn execute(
+    paths: &[PathBuf],
+    check: bool,
+    reorder: bool,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let files = metadata_files(paths);
+    let mut unformatted = 0;
+
+    for file in &files {
+        let source = std::fs::read_to_string(file)
+            .map_err(|e| format!("Failed to read {}: {}", file.display(), e))?;
+        let options = FormatOptions {
+            reorder: reorder && FormatOptions::for_path(file).reorder,
+            ..FormatOptions::for_path(file)
+        };
+        let formatted = formatter::format(&source, &options);
+        if formatted == source {
+            continue;
+        }
+
+        unformatted += 1;
+        if check {
+            println!("{}", file.display());
+        } else {
+            std::fs::write(file, formatted)
+                .map_err(|e| format!("Failed to write {}: {}", file.display(), e))?;
+            println!("Formatted {}", file.display());
+        }
+    }
+
+    if check && unformatted > 0 {
+        return Err(format!("{} of {} files need formatting", unformatted, files.len()).into());
+    }
+    Ok(())
+}
+
+/// .bb, .bbappend, .bbclass and .inc files under `paths`, sorted
+pub fn metadata_files(paths: &[PathBuf]) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = paths
+        .iter()
+        .flat_map(|path| {
+            WalkDir::new(path)
+                .into_iter()
+                .filter_entry(|e| e.depth() == 0 || !e.file_name().to_string_lossy().starts_with('.'))
+                .filter_map(Result::ok)
+                .filter(|e| e.file_type().is_file())
+                .map(|e| e.into_path())
+                .filter(|p| is_metadata(p))
+        })
+        .collect();
+    files.sort();
+    files.dedup();
+    files
+}
+
+fn is_metadata(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| EXTENSIONS.contains(&ext))
+}
@@ -2,38 +2,39 @@
 //!
 //! This command builds BitBake recipes using KAS configuration files.
 //! It handles repository fetching, configuration generation, and build execution.
+//! `kas lock` pins the repositories to commits; later runs check those out.
+//! `kas dump` prints the merged configuration and `kas shell` opens a shell in
+//! the generated build environment, like their kas counterparts.
+//! Repositories are managed by `convenient_kas::RepositoryManager`, so re-runs
+//! are idempotent and refuse to overwrite local changes.
 
 use convenient_bitbake::{
     BuildContext, ExtractionConfig, RecipeExtractor, RecipeGraph,
     TaskImplementation,
     Pipeline, PipelineConfig,
 };
-use convenient_kas::{ConfigGenerator, include_graph::KasIncludeGraph};
+use convenient_kas::{
+    ConfigGenerator, KasConfig, KasLock, LockChange, RepositoryManager, dump,
+    include_graph::KasIncludeGraph, lock::lock_path,
+};
 use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
 /// Execute build using KAS configuration
+///
+/// See [`checkout`] for `update` and `force_checkout`.
 pub async fn execute(
     kas_file: &Path,
     build_dir: &Path,
     _target: Option<String>,
+    update: bool,
+    force_checkout: bool,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let repos_dir = build_dir.join("repos");
     let conf_dir = build_dir.join("conf");
-
-    if !kas_file.exists() {
-        eprintln!("❌ KAS file not found: {}", kas_file.display());
-        return Err("KAS file not found".into());
-    }
-
-    fs::create_dir_all(build_dir)?;
-
-    // ========== Step 1: Load KAS Configuration ==========
-    tracing::info!("Loading KAS configuration from {}", kas_file.display());
-    let kas_config_graph = KasIncludeGraph::build(kas_file).await?;
-    let kas_config = kas_config_graph.merge_config();
+    println!("📦 Fetching repositories...");
+    let (_, kas_config, layer_paths) = checkout(kas_file, build_dir, update, force_checkout).await?;
 
     println!("📋 Configuration:");
     println!("  Machine:  {}", kas_config.machine.as_deref().unwrap_or("unknown"));
@@ -43,80 +44,8 @@ pub async fn execute(
     }
     println!();
 
-    // ========== Step 2: Fetch Repositories ==========
-    println!("📦 Fetching repositories...");
-    fs::create_dir_all(&repos_dir)?;
-
-    let mut layer_paths: HashMap<String, Vec<PathBuf>> = HashMap::new();
-
-    for (repo_name, repo) in &kas_config.repos {
-        // Determine repo directory - either from path or from clone
-        let repo_dir = if let Some(path_str) = &repo.path {
-            // Local path - use it directly
-            let path = Path::new(path_str);
-            let abs_path = if path.is_absolute() {
-                path.to_path_buf()
-            } else {
-                // Relative path - resolve from current directory or KAS file location
-                std::env::current_dir()?.join(path)
-            };
-            tracing::info!("Using local repository {} from {:?}", repo_name, abs_path);
-            abs_path
-        } else if let Some(url) = &repo.url {
-            // Remote URL - clone it
-            let repo_dir = repos_dir.join(repo_name);
-
-            if repo_dir.exists() {
-                tracing::debug!("Repository {} already exists", repo_name);
-            } else {
-                let branch = repo.branch.as_deref().unwrap_or("master");
-                tracing::info!("Cloning {} (branch: {})...", repo_name, branch);
-
-                let output = Command::new("git")
-                    .args(&[
-                        "clone",
-                        "--branch",
-                        branch,
-                        "--depth",
-                        "1",
-                        url,
-                        repo_dir.to_str().unwrap(),
-                    ])
-                    .output()?;
-
-                if !output.status.success() {
-                    tracing::error!(
-                        "Failed to clone {}: {}",
-                        repo_name,
-                        String::from_utf8_lossy(&output.stderr)
-                    );
-                    continue;
-                }
-            }
-            repo_dir
-        } else {
-            tracing::warn!("Repository {} has neither path nor url, skipping", repo_name);
-            continue;
-        };
-
-        // Collect layer paths for this repo
-        let mut repo_layers = Vec::new();
-        for (layer_name, _) in &repo.layers {
-            let layer_path = repo_dir.join(layer_name);
-            if layer_path.exists() {
-                tracing::debug!("  Found layer: {:?}", layer_path);
-                repo_layers.push(layer_path);
-            } else {
-                tracing::warn!("  Layer not found: {:?}", layer_path);
-            }
-        }
-        if !repo_layers.is_empty() {
-            layer_paths.insert(repo_name.clone(), repo_layers);
-        }
-    }
-
     let total_layers: usize = layer_paths.values().map(|v| v.len()).sum();
-    println!("  Cloned {} repos with {} layers", layer_paths.len(), total_layers);
+    println!("  Checked out {} repos with {} layers", layer_paths.len(), total_layers);
     println!();
 
     // ========== Step 3: Generate BitBake Configuration Files ==========
@@ -133,44 +62,11 @@ pub async fn execute(
     // ========== Step 4: Build Layer Context with Priorities ==========
     println!("🏗️  Building layer context with priorities...");
 
-    let mut build_context = BuildContext::new();
-
-    // Set machine and distro for override resolution
-    if let Some(machine) = &kas_config.machine {
-        build_context.set_machine(machine.clone());
-    }
-    if let Some(distro) = &kas_config.distro {
-        build_context.set_distro(distro.clone());
-    }
-
-    // Add layers with their priorities from layer.conf
-    for (_repo_name, layers) in &layer_paths {
-        for layer_path in layers {
-            let layer_conf = layer_path.join("conf/layer.conf");
-            if layer_conf.exists() {
-                match build_context.add_layer_from_conf(&layer_conf) {
-                    Ok(()) => {}
-                    Err(e) => {
-                        tracing::warn!("Failed to parse layer.conf for {}: {}", layer_path.display(), e);
-                        // Create a default layer config
-                        let default_layer = convenient_bitbake::layer_context::LayerConfig {
-                            layer_dir: layer_path.clone(),
-                            collection: layer_path.file_name()
-                                .and_then(|s| s.to_str())
-                                .unwrap_or("unknown")
-                                .to_string(),
-                            priority: 5, // Default priority
-                            version: None,
-                            depends: vec![],
-                            series_compat: vec![],
-                            variables: HashMap::new(),
-                        };
-                        build_context.add_layer(default_layer);
-                    }
-                }
-            }
-        }
-    }
+    let build_context = build_context(
+        kas_config.machine.as_deref(),
+        kas_config.distro.as_deref(),
+        &layer_paths,
+    );
 
     println!("  Loaded {} layers with priorities", build_context.layers.len());
     for layer in &build_context.layers {
@@ -283,3 +179,254 @@ pub async fn execute(
 
     Ok(())
 }
+
+/// Layer context of checked out layers, with priorities from their `conf/layer.conf`
+///
+/// Machine and distro are set for override resolution. Layers whose
+/// `layer.conf` cannot be parsed get a default priority of 5.
+pub fn build_context(
+    machine: Option<&str>,
+    distro: Option<&str>,
+    layer_paths: &HashMap<String, Vec<PathBuf>>,
+) -> BuildContext {
+    let mut build_context = BuildContext::new();
+
+    // Set machine and distro for override resolution
+    if let Some(machine) = machine {
+        build_context.set_machine(machine.to_string());
+    }
+    if let Some(distro) = distro {
+        build_context.set_distro(distro.to_string());
+    }
+
+    // Add layers with their priorities from layer.conf
+    for (_repo_name, layers) in layer_paths {
+        for layer_path in layers {
+            let layer_conf = layer_path.join("conf/layer.conf");
+            if layer_conf.exists() {
+                match build_context.add_layer_from_conf(&layer_conf) {
+                    Ok(()) => {}
+                    Err(e) => {
+                        tracing::warn!("Failed to parse layer.conf for {}: {}", layer_path.display(), e);
+                        // Create a default layer config
+                        let default_layer = convenient_bitbake::layer_context::LayerConfig {
+                            layer_dir: layer_path.clone(),
+                            collection: layer_path.file_name()
+                                .and_then(|s| s.to_str())
+                                .unwrap_or("unknown")
+                                .to_string(),
+                            priority: 5, // Default priority
+                            version: None,
+                            depends: vec![],
+                            series_compat: vec![],
+                            variables: HashMap::new(),
+                        };
+                        build_context.add_layer(default_layer);
+                    }
+                }
+            }
+        }
+    }
+
+    build_context
+}
+
+/// Load a KAS configuration and check out its repositories
+///
+/// Returns the include graph with every repository include resolved, its
+/// merged configuration and the layers of each repository.
+/// Repositories are checked out through [`RepositoryManager`], with bare
+/// mirrors in `KAS_REPO_REF_DIR` (or the build's cache directory) shared as
+/// reference. `update` fetches floating branches again, `force_checkout`
+/// discards local changes in the checkouts.
+async fn checkout(
+    kas_file: &Path,
+    build_dir: &Path,
+    update: bool,
+    force_checkout: bool,
+) -> Result<(KasIncludeGraph, KasConfig, HashMap<String, Vec<PathBuf>>), Box<dyn std::error::Error + Send + Sync>> {
+    let repos_dir = build_dir.join("repos");
+
+    if !kas_file.exists() {
+        eprintln!("❌ KAS file not found: {}", kas_file.display());
+        return Err("KAS file not found".into());
+    }
+
+    fs::create_dir_all(build_dir)?;
+
+    // ========== Step 1: Load KAS Configuration ==========
+    tracing::info!("Loading KAS configuration from {}", kas_file.display());
+    let mut kas_config_graph = KasIncludeGraph::build(kas_file).await?;
+    let mut kas_config = kas_config_graph.merge_config();
+
+    // ========== Step 2: Fetch Repositories ==========
+    let mirror_dir = std::env::var_os("KAS_REPO_REF_DIR")
+        .map_or_else(|| build_dir.join("hitzeleiter-cache/git-mirrors"), PathBuf::from);
+    // Repositories without url or path are the one holding the KAS file
+    let kas_dir = kas_file
+        .canonicalize()?
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    let config_dir = Command::new("git")
+        .args(["rev-parse", "--show-toplevel"])
+        .current_dir(&kas_dir)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map_or(kas_dir, |output| PathBuf::from(String::from_utf8_lossy(&output.stdout).trim()));
+    let repo_manager = RepositoryManager::new(&repos_dir)
+        .with_cache(&mirror_dir)
+        .with_config_dir(&config_dir)
+        .with_update(update)
+        .with_force_checkout(force_checkout);
+
+    let mut repo_paths = repo_manager.setup_repositories(&kas_config).await?;
+
+    // Includes from repositories need their checkout, which may add repositories
+    while !kas_config_graph.missing_repos().is_empty() {
+        let unknown: Vec<&String> = kas_config_graph
+            .missing_repos()
+            .iter()
+            .filter(|repo| !repo_paths.contains_key(*repo))
+            .collect();
+        if !unknown.is_empty() {
+            return Err(format!("Includes from undefined repositories: {:?}", unknown).into());
+        }
+        kas_config_graph = KasIncludeGraph::build_with_repos(kas_file, &repo_paths).await?;
+        kas_config = kas_config_graph.merge_config();
+        repo_paths = repo_manager.setup_repositories(&kas_config).await?;
+    }
+
+    let mut layer_paths: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    for (repo_name, repo) in &kas_config.effective_repos() {
+        let Some(repo_dir) = repo_paths.get(repo_name) else {
+            continue;
+        };
+        let repo_layers = repo_manager.get_layer_paths(repo_dir, repo)?;
+        for layer_path in &repo_layers {
+            tracing::debug!("  Found layer: {:?}", layer_path);
+        }
+        if !repo_layers.is_empty() {
+            layer_paths.insert(repo_name.clone(), repo_layers);
+        }
+    }
+
+    Ok((kas_config_graph, kas_config, layer_paths))
+}
+
+/// Lock every repository of a KAS configuration to a commit
+///
+/// Writes `<config>.lock.yml` in kas format. An existing lock is kept as is
+/// unless `update` is set, in which case branches and tags are resolved again.
+pub async fn lock(
+    kas_file: &Path,
+    update: bool,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if !kas_file.exists() {
+        eprintln!("❌ KAS file not found: {}", kas_file.display());
+        return Err("KAS file not found".into());
+    }
+
+    let lock_file = lock_path(kas_file);
+    let graph = KasIncludeGraph::build(kas_file).await?;
+    for repo in graph.missing_repos() {
+        tracing::warn!("Includes from repository {} are not checked out, its repos are not locked", repo);
+    }
+    let config = graph.merge_config();
+
+    println!("🔒 Resolving repositories of {}...", kas_file.display());
+    let old = KasLock::load(&lock_file).await?.unwrap_or_default();
+    let new = KasLock::resolve(&config, update).await?;
+
+    let changes = old.diff(&new);
+    for change in &changes {
+        println!("  {}", change);
+    }
+
+    let changed = changes.iter().filter(|c| !matches!(c, LockChange::Unchanged { .. })).count();
+    if changed == 0 && lock_file.exists() {
+        println!("  {} is up to date", lock_file.display());
+        return Ok(());
+    }
+
+    new.write(&lock_file).await?;
+    println!("  Wrote {} ({} repos, {} changed)", lock_file.display(), new.commits.len(), changed);
+
+    Ok(())
+}
+
+/// Print the merged KAS configuration as YAML or JSON
+///
+/// Like `kas dump`, the repositories are checked out first so includes from
+/// them are part of the dump. With `annotate`, YAML keys are commented with
+/// the file that set them, and JSON is wrapped as
+/// `{"config": …, "sources": {key: file}}`.
+pub async fn dump(
+    kas_file: &Path,
+    build_dir: &Path,
+    format: &str,
+    annotate: bool,
+    update: bool,
+    force_checkout: bool,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (graph, config, _) = checkout(kas_file, build_dir, update, force_checkout).await?;
+
+    let output = match (format, annotate) {
+        ("yaml", false) => dump::to_yaml(&config),
+        ("yaml", true) => dump::to_annotated_yaml(&graph),
+        ("json", false) => serde_json::to_string_pretty(&serde_json::to_value(&config)?)?,
+        ("json", true) => serde_json::to_string_pretty(&serde_json::json!({
+            "config": config,
+            "sources": graph.key_sources(),
+        }))?,
+        (other, _) => return Err(format!("Unknown format '{}' (expected yaml or json)", other).into()),
+    };
+    println!("{}", output.trim_end());
+
+    Ok(())
+}
+
+/// Set up the build directory and run a shell in its environment
+///
+/// Checks out the repositories, generates `conf/` and `setup-environment`,
+/// then runs `$SHELL` (or `command` through `sh -c`) in the build directory.
+/// Like kas, the environment is the one `oe-init-build-env` sets up, so
+/// BitBake and the OE scripts are in `PATH`.
+pub async fn shell(
+    kas_file: &Path,
+    build_dir: &Path,
+    command: Option<&str>,
+    update: bool,
+    force_checkout: bool,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (_, kas_config, layer_paths) = checkout(kas_file, build_dir, update, force_checkout).await?;
+    let build_dir = build_dir.canonicalize()?;
+
+    let config_gen = ConfigGenerator::new(&build_dir, kas_config, layer_paths);
+    config_gen.generate_all().await?;
+    config_gen.generate_env_setup().await?;
+    let environment = config_gen.init_environment().await?;
+
+    let mut shell = match command {
+        Some(command) => {
+            let mut shell = Command::new("sh");
+            shell.args(["-c", command]);
+            shell
+        }
+        None => {
+            let program = std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string());
+            println!("🐚 Entering build environment in {} (exit to leave)", build_dir.display());
+            Command::new(program)
+        }
+    };
+    let status = shell
+        .current_dir(&build_dir)
+        .envs(environment)
+        .status()?;
+
+    if !status.success() {
+        return Err(format!("Shell exited with {}", status).into());
+    }
+    Ok(())
+}
@@ -0,0 +1,73 @@
+//! Lint command - check BitBake metadata against oelint-style rules
+//!
+//! Text output is one `file:line: level [rule] message` per finding; SARIF
+//! output can be uploaded to code review (e.g. GitHub code scanning). Any
+//! error-level finding makes the command fail.
+
+use super::fmt::metadata_files;
+use convenient_bitbake::lint::{self, Severity};
+use std::path::{Path, PathBuf};
+
+/// Lint `paths` (files or directories) and report in `format` ("text" or "sarif")
+pub async fn execute(
+    paths: &[PathBuf],
+    format: &str,
+    output: Option<&Path>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let files = metadata_files(paths);
+    let mut results = Vec::with_capacity(files.len());
+    for file in files {
+        let source = std::fs::read_to_string(&file)
+            .map_err(|e| format!("Failed to read {}: {}", file.display(), e))?;
+        let diagnostics = lint::lint(&source, &file);
+        results.push((file, diagnostics));
+    }
+
+    let report = match format {
+        "text" => {
+            let mut report = String::new();
+            for (file, diagnostics) in &results {
+                for d in diagnostics {
+                    report.push_str(&format!(
+                        "{}:{}: {} [{}] {}\n",
+                        file.display(),
+                        d.line,
+                        d.severity.sarif_level(),
+                        d.rule,
+                        d.message
+                    ));
+                }
+            }
+            report
+        }
+        "sarif" => serde_json::to_string_pretty(&lint::sarif(&results))? + "\n",
+        other => return Err(format!("Unknown format '{}' (expected text or sarif)", other).into()),
+    };
+
+    match output {
+        Some(path) => std::fs::write(path, &report)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?,
+        None => print!("{}", report),
+    }
+
+    let count = |severity| {
+        results
+            .iter()
+            .flat_map(|(_, diagnostics)| diagnostics)
+            .filter(|d| d.severity == severity)
+            .count()
+    };
+    let errors = count(Severity::Error);
+    eprintln!(
+        "{} files checked: {} errors, {} warnings, {} notes",
+        results.len(),
+        errors,
+        count(Severity::Warning),
+        count(Severity::Note)
+    );
+
+    if errors > 0 {
+        return Err(format!("{} lint errors", errors).into());
+    }
+    Ok(())
+}
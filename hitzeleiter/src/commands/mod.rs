@@ -2,18 +2,33 @@
 //!
 //! Hitzeleiter supports multiple modes of operation:
 //! - `kas`: Setup BitBake environment using KAS configuration files
+//! - `kas lock`: Pin every KAS repository to a commit in a `*.lock.yml`
+//! - `kas dump`: Print the merged KAS configuration
+//! - `kas shell`: Shell in the build environment of a KAS configuration
+//! - `repo`: Setup BitBake environment by syncing a Google repo manifest
+//! - `convert`: Convert between repo manifests and KAS files
 //! - `build`: Build recipes with full task graph execution
 //! - `clean`: Cache management
 //! - `query`: Dependency exploration
+//! - `devshell`: Interactive shell inside a task's sandbox
+//! - `env`: Variable values with their history (`bitbake -e`)
+//! - `fmt`: Lossless formatting of recipes and classes
+//! - `lint`: oelint-style checks with text or SARIF output
 
 use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 
 pub mod kas;
+pub mod repo;
+pub mod convert;
 pub mod build;
 pub mod clean;
 pub mod query;
 pub mod tquery;
+pub mod devshell;
+pub mod env;
+pub mod fmt;
+pub mod lint;
 
 /// Hitzeleiter - Hot conductor build orchestration layer for BitBake/Yocto
 #[derive(Parser)]
@@ -28,7 +43,11 @@ pub struct Cli {
 #[derive(Subcommand)]
 pub enum Commands {
     /// Setup BitBake environment using KAS configuration file
+    #[command(args_conflicts_with_subcommands = true)]
     Kas {
+        #[command(subcommand)]
+        command: Option<KasCommand>,
+
         /// Path to KAS configuration file
         #[arg(short, long, default_value = "kas.yml")]
         config: PathBuf,
@@ -39,6 +58,80 @@ pub enum Commands {
 
         /// Target recipe (optional, for validation only)
         target: Option<String>,
+
+        /// Fetch branches and tags of existing checkouts again
+        #[arg(long)]
+        update: bool,
+
+        /// Discard local changes in repository checkouts
+        #[arg(long)]
+        force_checkout: bool,
+    },
+
+    /// Setup BitBake environment by syncing a repo manifest
+    Repo {
+        /// Path to the repo manifest
+        #[arg(short, long, default_value = "default.xml")]
+        manifest: PathBuf,
+
+        /// Url of the manifest repository, for relative fetch urls (default: its origin)
+        #[arg(short = 'u', long)]
+        manifest_url: Option<String>,
+
+        /// Project groups to sync, like `repo init -g` (e.g., "default,-notdefault,bsp")
+        #[arg(short, long, default_value = "default")]
+        groups: String,
+
+        /// Number of parallel fetches (default: sync-j of the manifest)
+        #[arg(short, long)]
+        jobs: Option<usize>,
+
+        /// Directory to check the projects out into
+        #[arg(short, long, default_value = ".")]
+        workspace: PathBuf,
+
+        /// Build directory
+        #[arg(short, long, default_value = "build")]
+        builddir: PathBuf,
+
+        /// Target machine for conf/local.conf
+        #[arg(long)]
+        machine: Option<String>,
+
+        /// Distribution for conf/local.conf
+        #[arg(long)]
+        distro: Option<String>,
+
+        /// Template directory with bblayers.conf.sample, relative to the oe-init-build-env project
+        /// (default: its .templateconf, or meta/conf/templates/default)
+        #[arg(long, env = "TEMPLATECONF")]
+        templateconf: Option<PathBuf>,
+
+        /// Layers for conf/bblayers.conf, relative to the workspace, instead of the template's
+        #[arg(long, value_delimiter = ',')]
+        layers: Vec<PathBuf>,
+    },
+
+    /// Convert a repo manifest (*.xml) to a KAS file, or a KAS file to a pinned repo manifest
+    Convert {
+        /// Repo manifest or KAS file to convert
+        input: PathBuf,
+
+        /// Output file (default: stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Manifest project groups to convert, like `repo init -g`
+        #[arg(short, long, default_value = "default")]
+        groups: String,
+
+        /// Url of the manifest repository, for relative fetch urls (default: its origin)
+        #[arg(short = 'u', long)]
+        manifest_url: Option<String>,
+
+        /// Synced workspace of the manifest, whose bblayers.conf.sample template lists the layers
+        #[arg(short, long)]
+        workspace: Option<PathBuf>,
     },
 
     /// Build recipes with task graph execution
@@ -47,8 +140,12 @@ pub enum Commands {
         #[arg(short, long, default_value = "build")]
         builddir: PathBuf,
 
-        /// Target recipe to build
+        /// Target recipe to build (`mc:<name>:<recipe>` selects a BBMULTICONFIG configuration)
         target: String,
+
+        /// Also write executed tasks to SSTATE_DIR in BitBake sstate layout
+        #[arg(long)]
+        export_sstate: bool,
     },
 
     /// Clean build cache
@@ -78,7 +175,7 @@ pub enum Commands {
         #[arg(short, long, default_value = "build")]
         builddir: PathBuf,
 
-        /// Query expression (e.g., "deps(busybox, 2)")
+        /// Query expression (e.g., "deps(busybox, 2)", "deps(mc:fw:*:fw-image, 2)")
         query: String,
 
         /// Output format: text, json, graph, label
@@ -105,6 +202,127 @@ pub enum Commands {
 
     /// Show task query help and examples
     TqueryHelp,
+
+    /// Open an interactive shell inside a task's sandbox
+    Devshell {
+        /// Build directory
+        #[arg(short, long, default_value = "build")]
+        builddir: PathBuf,
+
+        /// Target as <recipe>[:<task>] (e.g., "busybox:configure", default task: compile)
+        target: String,
+
+        /// Re-run the task script with `set -x` before dropping to the prompt
+        #[arg(long)]
+        rerun: bool,
+    },
+
+    /// Show a recipe's variables with their history (like `bitbake -e`)
+    Env {
+        /// Build directory
+        #[arg(short, long, default_value = "build")]
+        builddir: PathBuf,
+
+        /// Recipe name (PN)
+        recipe: String,
+
+        /// Only show this variable
+        variable: Option<String>,
+    },
+
+    /// Format .bb/.bbappend/.bbclass/.inc files following the OE style guide
+    Fmt {
+        /// Files or directories to format
+        #[arg(default_value = ".")]
+        paths: Vec<PathBuf>,
+
+        /// Only list files that need formatting; fail if there are any
+        #[arg(long)]
+        check: bool,
+
+        /// Keep the variable order of .bb/.bbappend files
+        #[arg(long)]
+        no_reorder: bool,
+    },
+
+    /// Lint .bb/.bbappend/.bbclass/.inc files (oelint-style rules)
+    Lint {
+        /// Files or directories to lint
+        #[arg(default_value = ".")]
+        paths: Vec<PathBuf>,
+
+        /// Output format: text, sarif
+        #[arg(long, default_value = "text")]
+        format: String,
+
+        /// Write the report to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum KasCommand {
+    /// Resolve every repository to a commit and write `<config>.lock.yml`
+    Lock {
+        /// Path to KAS configuration file
+        #[arg(default_value = "kas.yml")]
+        config: PathBuf,
+
+        /// Re-resolve repositories that are already locked
+        #[arg(long)]
+        update: bool,
+    },
+
+    /// Print the merged configuration, with includes resolved
+    Dump {
+        /// Path to KAS configuration file
+        #[arg(default_value = "kas.yml")]
+        config: PathBuf,
+
+        /// Output format: yaml or json
+        #[arg(long, default_value = "yaml")]
+        format: String,
+
+        /// Mark each key with the file that set it
+        #[arg(long)]
+        annotate: bool,
+
+        /// Build directory the repositories are checked out in
+        #[arg(short, long, default_value = "build")]
+        builddir: PathBuf,
+
+        /// Fetch branches and tags of existing checkouts again
+        #[arg(long)]
+        update: bool,
+
+        /// Discard local changes in repository checkouts
+        #[arg(long)]
+        force_checkout: bool,
+    },
+
+    /// Set up the build directory and open a shell in its environment
+    Shell {
+        /// Path to KAS configuration file
+        #[arg(default_value = "kas.yml")]
+        config: PathBuf,
+
+        /// Build directory
+        #[arg(short, long, default_value = "build")]
+        builddir: PathBuf,
+
+        /// Run this command instead of an interactive shell
+        #[arg(short, long)]
+        command: Option<String>,
+
+        /// Fetch branches and tags of existing checkouts again
+        #[arg(long)]
+        update: bool,
+
+        /// Discard local changes in repository checkouts
+        #[arg(long)]
+        force_checkout: bool,
+    },
 }
 
 #[derive(Subcommand)]
@@ -112,6 +330,25 @@ pub enum CacheOperation {
     /// Show cache information and statistics
     Info,
 
-    /// Garbage collect unreferenced objects
-    Gc,
+    /// Garbage collect unreferenced objects and enforce a size limit
+    Gc {
+        /// Evict least recently used objects until the CAS fits (e.g., "200G", "512M")
+        #[arg(long)]
+        max_size: Option<String>,
+
+        /// Never evict outputs of this many most recent successful builds
+        #[arg(long, default_value_t = convenient_bitbake::executor::DEFAULT_KEEP_BUILDS)]
+        keep_builds: usize,
+    },
+
+    /// Re-hash the CAS and check action cache outputs; corrupt entries are quarantined
+    Verify {
+        /// Re-fetch quarantined/missing blobs, invalidating actions that can't be restored
+        #[arg(long)]
+        repair: bool,
+
+        /// Remote cache to re-fetch blobs from (e.g., "http://localhost:9090")
+        #[arg(long)]
+        remote_cache: Option<String>,
+    },
 }
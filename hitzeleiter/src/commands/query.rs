@@ -1,11 +1,15 @@
 //! Query command for dependency exploration
 
 use convenient_bitbake::{BuildEnvironment, BuildOrchestrator, OrchestratorConfig};
+use convenient_bitbake::multiconfig::strip_query_multiconfig;
 use convenient_bitbake::query::{RecipeQueryEngine, OutputFormat};
 use std::collections::HashMap;
 use std::path::Path;
 
 /// Execute a query against the recipe graph
+///
+/// Targets may be qualified as `mc:<name>:<pattern>` to query a BBMULTICONFIG
+/// configuration instead of the default one.
 pub async fn execute(
     build_dir: &Path,
     query: &str,
@@ -18,15 +22,28 @@ pub async fn execute(
     println!("Loading build environment...");
     let env = BuildEnvironment::from_build_dir(build_dir)?;
 
+    let (mc, query) = strip_query_multiconfig(query)?;
+    let (machine, distro, mc_variables) = if mc.is_empty() {
+        (
+            env.get_machine().map(|s| s.to_string()),
+            env.get_distro().map(|s| s.to_string()),
+            HashMap::new(),
+        )
+    } else {
+        let context = env.create_multiconfig_context(&mc)?;
+        println!("  ✓ Multiconfig: {} (MACHINE: {})", mc, context.machine.as_deref().unwrap_or("unknown"));
+        (context.machine.clone(), context.distro.clone(), context.global_variables)
+    };
+
     let config = OrchestratorConfig {
         build_dir: build_dir.to_path_buf(),
-        machine: env.get_machine().map(|s| s.to_string()),
-        distro: env.get_distro().map(|s| s.to_string()),
+        machine,
+        distro,
         max_io_parallelism: 32,
         max_cpu_parallelism: num_cpus::get(),
     };
 
-    let orchestrator = BuildOrchestrator::new(config);
+    let orchestrator = BuildOrchestrator::new(config).with_multiconfig(&mc, mc_variables);
 
     // Create layer paths
     let mut layer_paths: HashMap<String, Vec<std::path::PathBuf>> = HashMap::new();
@@ -44,7 +61,7 @@ pub async fn execute(
     use convenient_bitbake::query::QueryParser;
 
     println!("Parsing query...");
-    let query_expr = QueryParser::parse(query)?;
+    let query_expr = QueryParser::parse(&query)?;
 
     println!("Executing query...");
     let engine = RecipeQueryEngine::new(&build_plan.recipe_graph);
@@ -147,4 +164,7 @@ pub fn help() {
     println!();
     println!("  # Find native dependencies");
     println!("  hitzeleiter query 'kind(\"*-native\", deps(gcc, 2))'");
+    println!();
+    println!("  # Query a BBMULTICONFIG configuration");
+    println!("  hitzeleiter query 'deps(mc:firmware:*:fw-image, 2)'");
 }
@@ -0,0 +1,128 @@
+//! Google repo manifest-based environment setup
+//!
+//! The manifest is loaded with its includes, `remove-project` and
+//! `extend-project` resolved, filtered by groups like `repo init -g`, and its
+//! projects are synced in parallel. The layers to build with are listed
+//! explicitly, or taken from the `bblayers.conf.sample` template of the
+//! `oe-init-build-env` checkout, and feed the same configuration generation
+//! and layer context as the `kas` command.
+
+use super::kas::build_context;
+use convenient_kas::{ConfigGenerator, KasConfig};
+use convenient_repo::{Manifest, RepoSync, layer_paths, oe_root, parse_groups, template_layers};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Options of [`execute`]
+pub struct RepoOptions {
+    /// Url of the manifest repository, for relative `fetch` urls
+    pub manifest_url: Option<String>,
+    /// Project groups to sync, like `repo init -g`
+    pub groups: String,
+    /// Number of parallel fetches, instead of the manifest's `sync-j`
+    pub jobs: Option<usize>,
+    /// Directory the projects are checked out into
+    pub workspace: PathBuf,
+    /// Build directory
+    pub build_dir: PathBuf,
+    /// `MACHINE` for conf/local.conf
+    pub machine: Option<String>,
+    /// `DISTRO` for conf/local.conf
+    pub distro: Option<String>,
+    /// Template directory with the `bblayers.conf.sample` to take layers from
+    pub templateconf: Option<PathBuf>,
+    /// Layers for conf/bblayers.conf, relative to the workspace; overrides the template
+    pub layers: Vec<PathBuf>,
+}
+
+/// Sync a repo manifest and set up the build directory for its layers
+///
+/// Relative `fetch` urls are resolved against `manifest_url`, or else the
+/// `origin` of the git repository holding the manifest (as in `.repo/manifests`).
+/// Without explicit `layers`, the layers are those of `bblayers.conf.sample`
+/// in `templateconf`, as `oe-init-build-env` would set up.
+pub async fn execute(
+    manifest_file: &Path,
+    options: RepoOptions,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let RepoOptions { manifest_url, groups, jobs, workspace, build_dir, machine, distro, templateconf, layers } =
+        options;
+    let groups = groups.as_str();
+    let build_dir = build_dir.as_path();
+    // ========== Step 1: Load Manifest ==========
+    println!("📦 Loading repo manifest...");
+    let mut manifest = Manifest::load(manifest_file)?;
+    let total = manifest.project.as_ref().map_or(0, Vec::len);
+    manifest.retain_groups(&parse_groups(groups));
+    let selected = manifest.project.as_ref().map_or(0, Vec::len);
+    println!("  Selected {} of {} projects (groups: {})", selected, total, groups);
+    println!();
+
+    // ========== Step 2: Sync Projects ==========
+    println!("🔄 Syncing projects...");
+    let workspace = std::path::absolute(workspace)?;
+    let mut sync = RepoSync::new(&workspace);
+    if let Some(url) = manifest_url.or_else(|| origin_url(manifest_file)) {
+        sync = sync.with_manifest_url(url);
+    }
+    if let Some(jobs) = jobs {
+        sync = sync.with_jobs(jobs);
+    }
+    let checkouts = sync.sync(&manifest).await?;
+    let layers = if layers.is_empty() {
+        let oe_root = oe_root(&checkouts)
+            .ok_or("No project has oe-init-build-env, list the layers with --layers")?;
+        template_layers(oe_root, templateconf.as_deref())?
+    } else {
+        layers.iter().map(|layer| workspace.join(layer)).collect()
+    };
+    let layer_paths = layer_paths(&checkouts, &layers)?;
+
+    let total_layers: usize = layer_paths.values().map(|v| v.len()).sum();
+    println!("  Synced {} projects with {} layers", checkouts.len(), total_layers);
+    println!();
+
+    // ========== Step 3: Generate BitBake Configuration Files ==========
+    println!("📝 Generating BitBake configuration...");
+    let config = KasConfig {
+        machine,
+        distro,
+        ..Default::default()
+    };
+    let config_gen = ConfigGenerator::new(build_dir, config.clone(), layer_paths.clone());
+    config_gen.generate_all().await?;
+    println!("  Generated: conf/local.conf");
+    println!("  Generated: conf/bblayers.conf");
+    println!();
+
+    // ========== Step 4: Build Layer Context with Priorities ==========
+    println!("🏗️  Building layer context with priorities...");
+    let build_context = build_context(config.machine.as_deref(), config.distro.as_deref(), &layer_paths);
+    println!("  Loaded {} layers with priorities", build_context.layers.len());
+    for layer in &build_context.layers {
+        println!("    • {} (priority: {})", layer.collection, layer.priority);
+    }
+    println!();
+
+    println!("Ready to build! Use:");
+    println!("  hitzeleiter build --builddir {} <target>", build_dir.display());
+    println!();
+
+    Ok(())
+}
+
+/// Url of the `origin` remote of the git repository holding `manifest_file`
+pub fn origin_url(manifest_file: &Path) -> Option<String> {
+    let dir = manifest_file.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["remote", "get-url", "origin"])
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .filter(|url| !url.is_empty())
+}
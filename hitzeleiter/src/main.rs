@@ -1,16 +1,21 @@
 //! Hitzeleiter - Hot conductor build orchestration layer for BitBake/Yocto
 //!
 //! Supports multiple modes of operation:
-//! 1. KAS mode: Build using KAS configuration files
+//! 1. KAS mode: Build using KAS configuration files; lock, dump or open a shell
+//!    (repo mode does the same setup from a Google repo manifest; convert
+//!    translates between both formats)
 //! 2. Build mode: Basic native BitBake builds
 //! 3. Ferrari mode: Full-featured builds with all optimizations
 //! 4. Clean/Cache: Cache management
 //! 5. Query: Dependency exploration
+//! 6. Devshell: Debug a task inside its sandbox
+//! 7. Env: Variable history, like `bitbake -e`
+//! 8. Fmt/Lint: Format and check recipes and classes
 
 mod commands;
 
 use clap::Parser;
-use commands::{Cli, Commands, CacheOperation};
+use commands::{Cli, Commands, CacheOperation, KasCommand};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 #[tokio::main]
@@ -29,7 +34,22 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 
     // Dispatch to appropriate command
     match cli.command {
-        Commands::Kas { config, builddir, target } => {
+        Commands::Kas { command: Some(KasCommand::Lock { config, update }), .. } => {
+            commands::kas::lock(&config, update).await?;
+        }
+        Commands::Kas {
+            command: Some(KasCommand::Dump { config, format, annotate, builddir, update, force_checkout }),
+            ..
+        } => {
+            commands::kas::dump(&config, &builddir, &format, annotate, update, force_checkout).await?;
+        }
+        Commands::Kas {
+            command: Some(KasCommand::Shell { config, builddir, command, update, force_checkout }),
+            ..
+        } => {
+            commands::kas::shell(&config, &builddir, command.as_deref(), update, force_checkout).await?;
+        }
+        Commands::Kas { command: None, config, builddir, target, update, force_checkout } => {
             println!("\n╔════════════════════════════════════════════════════════╗");
             println!("║              BITZEL ENVIRONMENT SETUP                  ║");
             println!("║  KAS-based BitBake environment initialization         ║");
@@ -37,9 +57,50 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
             println!("Mode: KAS Configuration");
             println!("Config: {:?}", config);
             println!();
-            commands::kas::execute(&config, &builddir, target).await?;
+            commands::kas::execute(&config, &builddir, target, update, force_checkout).await?;
+        }
+        Commands::Repo {
+            manifest,
+            manifest_url,
+            groups,
+            jobs,
+            workspace,
+            builddir,
+            machine,
+            distro,
+            templateconf,
+            layers,
+        } => {
+            println!("Mode: repo manifest");
+            println!("Manifest: {:?}", manifest);
+            println!();
+            commands::repo::execute(
+                &manifest,
+                commands::repo::RepoOptions {
+                    manifest_url,
+                    groups,
+                    jobs,
+                    workspace,
+                    build_dir: builddir,
+                    machine,
+                    distro,
+                    templateconf,
+                    layers,
+                },
+            )
+            .await?;
+        }
+        Commands::Convert { input, output, groups, manifest_url, workspace } => {
+            commands::convert::execute(
+                &input,
+                output.as_deref(),
+                &groups,
+                manifest_url.as_deref(),
+                workspace.as_deref(),
+            )
+            .await?;
         }
-        Commands::Build { builddir, target } => {
+        Commands::Build { builddir, target, export_sstate } => {
             println!("\n╔════════════════════════════════════════════════════════╗");
             println!("║              BITZEL BUILD ORCHESTRATOR                 ║");
             println!("║  Task Graph Execution with Dependency Resolution      ║");
@@ -47,7 +108,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
             println!("Build directory: {:?}", builddir);
             println!("Target: {}", target);
             println!();
-            commands::build::execute(&builddir, &target).await?;
+            commands::build::execute(&builddir, &target, export_sstate).await?;
         }
         Commands::Clean { builddir, all } => {
             if all {
@@ -61,8 +122,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
                 CacheOperation::Info => {
                     commands::clean::info(&builddir)?;
                 }
-                CacheOperation::Gc => {
-                    commands::clean::gc(&builddir)?;
+                CacheOperation::Gc { max_size, keep_builds } => {
+                    commands::clean::gc(&builddir, max_size.as_deref(), keep_builds)?;
+                }
+                CacheOperation::Verify { repair, remote_cache } => {
+                    commands::clean::verify(&builddir, repair, remote_cache.as_deref()).await?;
                 }
             }
         }
@@ -78,6 +142,18 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         Commands::TqueryHelp => {
             commands::tquery::help();
         }
+        Commands::Devshell { builddir, target, rerun } => {
+            commands::devshell::execute(&builddir, &target, rerun).await?;
+        }
+        Commands::Env { builddir, recipe, variable } => {
+            commands::env::execute(&builddir, &recipe, variable.as_deref()).await?;
+        }
+        Commands::Fmt { paths, check, no_reorder } => {
+            commands::fmt::execute(&paths, check, !no_reorder).await?;
+        }
+        Commands::Lint { paths, format, output } => {
+            commands::lint::execute(&paths, &format, output.as_deref()).await?;
+        }
     }
 
     Ok(())
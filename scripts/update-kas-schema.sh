@@ -0,0 +1,42 @@
+#!/bin/bash
+# Vendor the upstream kas JSON schema that convenient-kas validates against
+#
+# Usage: scripts/update-kas-schema.sh [kas-tag]
+
+set -e
+
+# kas release the schema is taken from; bump together with KAS_FORMAT_VERSION
+KAS_TAG="${1:-4.7}"
+
+PROJECT_ROOT="$(cd "$(dirname "${BASH_SOURCE[0]}")/.." && pwd)"
+DEST="${PROJECT_ROOT}/convenient-kas/tests/data/schema-kas.json"
+URL="https://raw.githubusercontent.com/siemens/kas/${KAS_TAG}/kas/schema-kas.json"
+FORMAT_VERSION="$(sed -n 's/^pub const KAS_FORMAT_VERSION: u32 = \([0-9]*\);/\1/p' \
+    "${PROJECT_ROOT}/convenient-kas/src/include_graph.rs")"
+
+echo "Fetching ${URL}"
+curl -fsSL "${URL}" -o "${DEST}.tmp"
+
+# The schema has to accept every format version we parse
+python3 - "${DEST}.tmp" "${FORMAT_VERSION}" <<'EOF'
+import json, sys
+
+schema = json.load(open(sys.argv[1]))
+version = schema["properties"]["header"]["properties"]["version"]
+maxima = [v["maximum"] for v in version.get("oneOf", version.get("anyOf", [version])) if "maximum" in v]
+if not maxima or max(maxima) < int(sys.argv[2]):
+    sys.exit(f"schema accepts header versions up to {maxima}, need {sys.argv[2]}")
+EOF
+
+# Keep the previous schema unless the schema tests pass against the new one
+mv "${DEST}" "${DEST}.orig"
+mv "${DEST}.tmp" "${DEST}"
+if ! (cd "${PROJECT_ROOT}" && cargo test -p convenient-kas --test kas_schema_tests); then
+    mv "${DEST}" "${DEST}.rejected"
+    mv "${DEST}.orig" "${DEST}"
+    echo "❌ kas_schema_tests fail against the kas ${KAS_TAG} schema, kept the previous one"
+    echo "   Fix the parser or fixtures, then move ${DEST#${PROJECT_ROOT}/}.rejected into place"
+    exit 1
+fi
+rm "${DEST}.orig"
+echo "✓ Vendored kas ${KAS_TAG} schema to ${DEST#${PROJECT_ROOT}/}"